@@ -40,7 +40,7 @@ use spectrusty::bus::{
 use spectrusty::formats::{
     tap::{TapChunkRead, TapReadInfoIter, TapChunkInfo},
     mdr::MicroCartridgeExt,
-    scr::{LoadScr, ScreenDataProvider},
+    scr::{LoadScr, LoadZxp, ScreenDataProvider},
     snapshot::{SnapshotCreator, ensure_cpu_is_safe_for_snapshot},
     z80
 };
@@ -365,7 +365,10 @@ impl<C: Cpu, U> ZxSpectrumEmu<C, U> {
             Some(s) if s.eq_ignore_ascii_case("mdr") => {
                 self.load_mdr(file)
             }
-            // Some(s) if s.eq_ignore_ascii_case("zxp") => {
+            Some(s) if s.eq_ignore_ascii_case("zxp") => {
+                self.spectrum.ula.load_zxp(file)?;
+                Ok(Some(String::new()))
+            }
             // Some(s) if s.eq_ignore_ascii_case("sna") => {
             //     let file = fs::File::open(path)?;
             //     self.load_sna(file)?;
@@ -41,7 +41,7 @@ use spectrusty::chip::{
     ula::{Ula, UlaVideoFrame, UlaNTSCVidFrame},
     ula128::{Ula128, Ula128VidFrame},
     ula3::{Ula3, Ula3VidFrame},
-    scld::Scld,
+    scld::{Scld, ScldVidFrame},
     plus::{UlaPlus, UlaPlusInner},
 };
 use spectrusty::formats::snapshot::ensure_cpu_is_safe_for_snapshot;
@@ -70,7 +70,7 @@ pub static ROM_PLUS2B: &[&[u8]] = &[include_bytes!("../../../resources/roms/plus
 pub use spectrusty::chip::ula::{UlaPAL, UlaNTSC};
 
 /// Timex TC2048 chipset.
-pub type TC2048<D, X=NoMemoryExtension> = Scld<Memory48kDock64kEx, D, X, UlaVideoFrame>;
+pub type TC2048<D, X=NoMemoryExtension> = Scld<Memory48kDock64kEx, D, X, ScldVidFrame>;
 /// ULA 128 with a AY-3-8912 sound processor + Keypad and RS232 in its I/O port A.
 pub type Ula128AyKeypad<D,
                         X=NoMemoryExtension,
@@ -131,7 +131,7 @@ pub type ZxSpectrum2B<C, D, X=NoMemoryExtension,
 // This will however result in a larger executable size.
 // Some tests also demonstrated that emulators perform slightly slower with these.
 pub type UlaPALDevice<S> = PluggableJoystickDynamicBus<S, VFrameTs<UlaVideoFrame>>;
-pub type TC2048Device<S> = PluggableJoystickDynamicBus<S, VFrameTs<UlaVideoFrame>>;
+pub type TC2048Device<S> = PluggableJoystickDynamicBus<S, VFrameTs<ScldVidFrame>>;
 pub type UlaNTSCDevice<S> = PluggableJoystickDynamicBus<S, VFrameTs<UlaNTSCVidFrame>>;
 pub type Ula128Device<S> = PluggableJoystickDynamicBus<S, VFrameTs<Ula128VidFrame>>;
 pub type Ula3Device<S> = PluggableJoystickDynamicBus<S, VFrameTs<Ula3VidFrame>>;
@@ -362,7 +362,7 @@ macro_rules! spectrum_model_ula_static_dispatch {
                     $crate::Plus3::<$crate::Ula3Device<$sdd>, $ext>::$($expr)*
                 }
                 $crate::ZxSpectrumModel::TimexTC2048(..) => {
-                    $crate::TC2048::<$crate::UlaPALDevice<$sdd>, $ext>::$($expr)*
+                    $crate::TC2048::<$crate::TC2048Device<$sdd>, $ext>::$($expr)*
                 }
             }
         }
@@ -126,6 +126,24 @@ pub struct BlepStereo<B: Blep> {
     pub blep: B,
 }
 
+/// A wrapper [Blep] implementation that distributes each channel between the two channels
+/// of a stereo [Blep] with individual amplitude weights.
+///
+/// Requires a downstream [Blep] implementation that provides at least 2 audio channels.
+/// ```text
+/// BlepStereoPan channel       Blep impl channel
+///     n ---- * pans[n].0 ----> 0
+///       \--- * pans[n].1 ----> 1
+/// ```
+/// Channels without their weights defined in `pans` are redirected to both channels unaltered.
+pub struct BlepStereoPan<B: Blep> {
+    /// `(left, right)` amplitude weights for each channel in the range [0.0, 1.0] (floats)
+    /// or [0, int::max_value()] (integers).
+    pub pans: Vec<(B::SampleDelta, B::SampleDelta)>,
+    /// A downstream [Blep] implementation.
+    pub blep: B,
+}
+
 /// A digital level to a sample amplitude conversion trait.
 pub trait AmpLevels<T: Copy> {
     /// This method should return the appropriate digital sample amplitude for the given `level`.
@@ -274,6 +292,16 @@ impl<B: Blep> BlepStereo<B> {
     }
 }
 
+impl<B: Blep> BlepStereoPan<B> {
+    pub fn build(pans: Vec<(B::SampleDelta, B::SampleDelta)>) -> impl FnOnce(B) -> Self {
+        move |blep| Self::new(pans, blep)
+    }
+
+    pub fn new(pans: Vec<(B::SampleDelta, B::SampleDelta)>, blep: B) -> Self {
+        BlepStereoPan { blep, pans }
+    }
+}
+
 impl<B: Blep> Deref for BlepAmpFilter<B> {
     type Target = B;
     fn deref(&self) -> &B {
@@ -345,6 +373,47 @@ impl<B> Blep for BlepStereo<B>
     }
 }
 
+impl<B: Blep> Deref for BlepStereoPan<B> {
+    type Target = B;
+    fn deref(&self) -> &B {
+        &self.blep
+    }
+}
+
+impl<B: Blep> DerefMut for BlepStereoPan<B> {
+    fn deref_mut(&mut self) -> &mut B {
+        &mut self.blep
+    }
+}
+
+impl<B> Blep for BlepStereoPan<B>
+    where B: Blep, B::SampleDelta: MulNorm + SampleDelta
+{
+    type SampleDelta = B::SampleDelta;
+
+    #[inline]
+    fn ensure_frame_time(&mut self, sample_rate: u32, ts_rate: f64, frame_ts: FTs, margin_ts: FTs) {
+        self.blep.ensure_frame_time(sample_rate, ts_rate, frame_ts, margin_ts)
+    }
+    #[inline]
+    fn end_frame(&mut self, timestamp: FTs) -> usize {
+        self.blep.end_frame(timestamp)
+    }
+    #[inline]
+    fn add_step(&mut self, channel: usize, timestamp: FTs, delta: B::SampleDelta) {
+        match self.pans.get(channel) {
+            Some(&(left, right)) => {
+                self.blep.add_step(0, timestamp, delta.mul_norm(left));
+                self.blep.add_step(1, timestamp, delta.mul_norm(right));
+            }
+            None => {
+                self.blep.add_step(0, timestamp, delta);
+                self.blep.add_step(1, timestamp, delta);
+            }
+        }
+    }
+}
+
 /// A helper method for rendering square-wave audio from slices containing updates of audio
 /// digital levels, sorted by time encoded in [VideoTs] time stamps.
 pub fn render_audio_frame_vts<VF,VL,L,A,T>(
@@ -407,3 +476,38 @@ pub fn render_audio_frame_ts<VL,L,A,T>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestBlep(Vec<(usize, FTs, f32)>);
+
+    impl Blep for TestBlep {
+        type SampleDelta = f32;
+        fn ensure_frame_time(&mut self, _sample_rate: u32, _ts_rate: f64, _frame_ts: FTs, _margin_ts: FTs) {}
+        fn end_frame(&mut self, _timestamp: FTs) -> usize { self.0.len() }
+        fn add_step(&mut self, channel: usize, timestamp: FTs, delta: f32) {
+            self.0.push((channel, timestamp, delta))
+        }
+    }
+
+    #[test]
+    fn blep_stereo_pan_works() {
+        let mut blep = BlepStereoPan::build(vec![(1.0, 0.0), (0.5, 0.5), (0.25, 0.75)])(TestBlep::default());
+        blep.add_step(0, 10, 0.5);
+        blep.add_step(1, 20, -0.5);
+        blep.add_step(2, 30, 1.0);
+        blep.add_step(3, 40, 0.125);
+        assert_eq!(blep.end_frame(50), 8);
+        assert_eq!(blep.0, [(0, 10, 0.5), (1, 10, 0.0),
+                            (0, 20, -0.25), (1, 20, -0.25),
+                            (0, 30, 0.25), (1, 30, 0.75),
+                            (0, 40, 0.125), (1, 40, 0.125)]);
+        blep.0.clear();
+        blep.pans.clear();
+        blep.add_step(0, 60, 0.5);
+        assert_eq!(blep.0, [(0, 60, 0.5), (1, 60, 0.5)]);
+    }
+}
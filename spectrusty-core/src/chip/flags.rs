@@ -87,6 +87,18 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Scorpion ZS-256 secondary memory control flags.
+    #[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "snapshot", serde(from = "u8", into = "u8"))]
+    #[derive(Default)]
+    pub struct ScorpionMemFlags: u8 {
+        const RAM_AT_ROM     = 0b0000_0001;
+        const SERVICE_ROM    = 0b0000_0010;
+        const RAM_BANK_HI    = 0b0001_0000;
+    }
+}
+
 #[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "snapshot", serde(try_from = "u8", into = "u8"))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -311,6 +323,47 @@ impl Ula3CtrlFlags {
     }
 }
 
+/****************************** ScorpionMemFlags ******************************/
+
+impl ScorpionMemFlags {
+    /// Returns modified flags with the RAM bank high flag set from the `bank` bit 3.
+    pub fn with_last_ram_page_bank_hi(mut self, bank: usize) -> Self {
+        self.set(ScorpionMemFlags::RAM_BANK_HI, bank & 8 != 0);
+        self
+    }
+    /// Returns a bit 3 value of a RAM bank index mapped at the last memory page.
+    ///
+    /// The complete RAM bank index can be obtained by bitwise ORing the returned value with
+    /// the result from [Ula128MemFlags::last_ram_page_bank].
+    pub fn last_ram_page_bank_hi(self) -> usize {
+        ((self & ScorpionMemFlags::RAM_BANK_HI).bits() >> 1).into()
+    }
+    /// Returns `true` if the RAM bank 0 should be mapped at the first memory page instead of a ROM bank.
+    /// Otherwise returns `false`.
+    pub fn is_ram_at_rom(self) -> bool {
+        self.intersects(ScorpionMemFlags::RAM_AT_ROM)
+    }
+    /// Returns `true` if the service ROM bank should be mapped at the first memory page.
+    /// Otherwise returns `false`.
+    pub fn is_service_rom(self) -> bool {
+        self.intersects(ScorpionMemFlags::SERVICE_ROM)
+    }
+}
+
+impl From<ScorpionMemFlags> for u8 {
+    #[inline]
+    fn from(flags: ScorpionMemFlags) -> u8 {
+        flags.bits()
+    }
+}
+
+impl From<u8> for ScorpionMemFlags {
+    #[inline]
+    fn from(flags: u8) -> ScorpionMemFlags {
+        ScorpionMemFlags::from_bits_truncate(flags)
+    }
+}
+
 /****************************** Ula3Paging ******************************/
 
 impl From<Ula3Paging> for u8 {
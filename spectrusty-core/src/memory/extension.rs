@@ -19,6 +19,11 @@ pub trait MemoryExtension: core::fmt::Debug {
     fn read_opcode<M: ZxMemory>(&mut self, pc: u16, memory: &mut M) -> u8 {
         memory.read(pc)
     }
+    /// Write `val` to the given `memory` at the given `addr` address, optionally altering provided memory.
+    #[inline]
+    fn write_mem<M: ZxMemory>(&mut self, addr: u16, val: u8, memory: &mut M) {
+        memory.write(addr, val)
+    }
     // /// Writes to the memory extension port. Should return optionally modified `data` if the extension wants
     // /// to influence some other chipset functions.
     // #[inline]
@@ -19,7 +19,7 @@ use serde::{
     de::{self, Visitor}
 };
 
-use super::{MEM8K_SIZE, MEM32K_SIZE, MEM48K_SIZE, MEM64K_SIZE, MEM128K_SIZE};
+use super::{MEM8K_SIZE, MEM16K_SIZE, MEM32K_SIZE, MEM48K_SIZE, MEM64K_SIZE, MEM128K_SIZE};
 
 pub fn serialize_mem<T, S>(mem: &T, serializer: S) -> Result<S::Ok, S::Error>
     where S: Serializer,
@@ -127,8 +127,12 @@ macro_rules! impl_box_mem_ser_de_ext {
 impl_box_mem_ser_de_ext!(MEM32K_SIZE);
 impl_box_mem_ser_de_ext!(MEM48K_SIZE);
 impl_box_mem_ser_de_ext!(MEM64K_SIZE);
+impl_box_mem_ser_de_ext!(MEM16K_SIZE + MEM64K_SIZE + MEM16K_SIZE);
 impl_box_mem_ser_de_ext!(MEM32K_SIZE + MEM128K_SIZE);
 impl_box_mem_ser_de_ext!(MEM64K_SIZE + MEM128K_SIZE);
+impl_box_mem_ser_de_ext!(MEM64K_SIZE + 2 * MEM128K_SIZE);
+impl_box_mem_ser_de_ext!(MEM32K_SIZE + 4 * MEM128K_SIZE);
+impl_box_mem_ser_de_ext!(MEM32K_SIZE + 8 * MEM128K_SIZE);
 impl_box_mem_ser_de_ext!(MEM128K_SIZE + MEM128K_SIZE);
 impl_box_mem_ser_de_ext!(MEM8K_SIZE + MEM128K_SIZE);
 impl_box_mem_ser_de_ext!(MEM48K_SIZE + MEM128K_SIZE + MEM128K_SIZE);
@@ -7,6 +7,8 @@
 */
 //! # Video API.
 pub mod pixel;
+pub mod crt;
+pub mod palette;
 
 use core::str::FromStr;
 use core::convert::TryFrom;
@@ -19,9 +21,9 @@ use serde::{Serialize, Deserialize};
 use bitflags::bitflags;
 
 use crate::clock::{Ts, FTs, VideoTs, VFrameTsCounter, MemoryContention};
-use crate::chip::UlaPortFlags;
+use crate::chip::{FrameState, UlaPortFlags};
 
-pub use pixel::{Palette, PixelBuffer};
+pub use pixel::{Palette, PixelBuffer, RgbPalette};
 
 /// A halved count of PAL `pixel lines` (low resolution).
 pub const PAL_VC: u32 = 576/2;
@@ -51,6 +53,17 @@ pub struct TryFromUIntBorderSizeError(pub u8);
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ParseBorderSizeError;
 
+/// This enum is used to select a field of an interlaced video frame when rendering video frames
+/// with [Video::render_video_frame_interlaced].
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VideoField {
+    /// The field rendered into the even lines of the interlaced image: 0, 2, 4, ...
+    Even,
+    /// The field rendered into the odd lines of the interlaced image: 1, 3, 5, ...
+    Odd
+}
+
 /// General-purpose coordinates, used by various video related methods as a return or an argument type.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct CellCoords {
@@ -118,6 +131,56 @@ pub trait Video {
         pitch: usize,
         border_size: BorderSize
     );
+    /// Renders last emulated frame's video data into the provided pixel `buffer` as a single `field`
+    /// of an interlaced image.
+    ///
+    /// The interlaced image has twice as many lines as the progressive one, see
+    /// [Video::render_size_pixels_interlaced]. Only every other line of the `buffer` is being rendered:
+    /// the even lines for [VideoField::Even] and the odd lines for [VideoField::Odd]. Thus the border
+    /// and the INK/PAPER area of the odd field appear half a scanline lower than of the even field,
+    /// as on a CRT display, while the lines of the opposite field are left intact.
+    ///
+    /// To reproduce flicker-based effects, render consecutive frames into the same `buffer` alternating
+    /// the fields, e.g. with [Video::render_video_field].
+    ///
+    /// `pitch` is the number of bytes in a single row of pixel data of the interlaced image.
+    /// The rest of the arguments and the notes are the same as for [Video::render_video_frame].
+    fn render_video_frame_interlaced<'a, B: PixelBuffer<'a>, P: Palette<Pixel=B::Pixel>>(
+        &mut self,
+        buffer: &'a mut [u8],
+        pitch: usize,
+        border_size: BorderSize,
+        field: VideoField
+    ) {
+        let offset = field.line_offset() * pitch;
+        if offset <= buffer.len() {
+            let (_, buffer) = {buffer}.split_at_mut(offset);
+            self.render_video_frame::<B, P>(buffer, 2 * pitch, border_size)
+        }
+    }
+    /// Renders last emulated frame's video data into the field of the interlaced image `buffer`
+    /// selected by [Video::current_video_field].
+    ///
+    /// The arguments and the notes are the same as for [Video::render_video_frame_interlaced].
+    fn render_video_field<'a, B: PixelBuffer<'a>, P: Palette<Pixel=B::Pixel>>(
+        &mut self,
+        buffer: &'a mut [u8],
+        pitch: usize,
+        border_size: BorderSize
+    )
+        where Self: FrameState
+    {
+        let field = self.current_video_field();
+        self.render_video_frame_interlaced::<B, P>(buffer, pitch, border_size, field)
+    }
+    /// Returns the field of the interlaced image the last emulated frame belongs to.
+    ///
+    /// The field is determined by [VideoFrame::frame_field] from the current frame counter value.
+    fn current_video_field(&self) -> VideoField
+        where Self: FrameState
+    {
+        Self::VideoFrame::frame_field(self.current_frame())
+    }
     /// Returns rendered screen pixel size (horizontal, vertical), including the border area, measured
     /// in pixels depending on [Video::PIXEL_DENSITY].
     ///
@@ -126,6 +189,14 @@ pub trait Video {
         let (width, height) = Self::VideoFrame::screen_size_pixels(border_size);
         (width * Self::pixel_density(), height)
     }
+    /// Returns rendered interlaced screen pixel size (horizontal, vertical), including the border area,
+    /// measured in pixels depending on [Video::PIXEL_DENSITY].
+    ///
+    /// The vertical size is twice the size returned by [Video::render_size_pixels].
+    fn render_size_pixels_interlaced(border_size: BorderSize) -> (u32, u32) {
+        let (width, height) = Self::render_size_pixels(border_size);
+        (width, 2 * height)
+    }
     /// Returns the horizontal pixel density.
     fn pixel_density() -> u32 {
         Self::PIXEL_DENSITY
@@ -169,6 +240,8 @@ pub trait VideoFrame: Copy + Debug {
     const HTS_RANGE: Range<Ts>;
     /// The number of horizontal T-states.
     const HTS_COUNT: Ts = Self::HTS_RANGE.end - Self::HTS_RANGE.start;
+    /// The horizontal T-state of the first video scan line when the frame interrupt is being requested.
+    const HTS_IRQ: Ts = 0;
     /// The first visible video scan line index of the top border.
     const VSL_BORDER_TOP: Ts;
     /// A range of video scan line indexes where pixel data is being drawn.
@@ -262,6 +335,14 @@ pub trait VideoFrame: Copy + Debug {
     fn snow_interference_coords(_ts: VideoTs) -> Option<CellCoords> {
         None
     }
+    /// Returns the field of the interlaced image the frame with the given `frame` counter value
+    /// belongs to.
+    ///
+    /// The default implementation alternates the fields on consecutive frames, see [VideoField::from_frame].
+    #[inline]
+    fn frame_field(frame: u64) -> VideoField {
+        VideoField::from_frame(frame)
+    }
     /// Returns `true` if the given scan line index is contended for MREQ (memory request) access.
     ///
     /// This indicates if the contention should be applied during the indicated video scan line.
@@ -417,6 +498,34 @@ impl From<BorderColor> for u8 {
     }
 }
 
+impl VideoField {
+    /// Returns the field that should be rendered for the given `frame` counter value,
+    /// so consecutive frames alternate between the fields.
+    #[inline]
+    pub fn from_frame(frame: u64) -> Self {
+        if frame & 1 == 0 { VideoField::Even } else { VideoField::Odd }
+    }
+    /// Returns the opposite field.
+    #[inline]
+    pub fn toggled(self) -> Self {
+        match self {
+            VideoField::Even => VideoField::Odd,
+            VideoField::Odd => VideoField::Even
+        }
+    }
+    /// Returns the index of the first line of the interlaced image that belongs to the field.
+    #[inline]
+    pub fn line_offset(self) -> usize {
+        self as usize
+    }
+}
+
+impl Default for VideoField {
+    fn default() -> Self {
+        VideoField::Even
+    }
+}
+
 /// Returns an offset into INK/PAPER bitmap memory of the given vertical coordinate `y` [0, 192) (0 on top).
 #[inline(always)]
 pub fn pixel_line_offset<T>(y: T) -> T
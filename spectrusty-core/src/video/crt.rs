@@ -0,0 +1,270 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! Software CRT post-processing of rendered video frames.
+
+[CrtFilter] imitates the look of a PAL television set on frames rendered with [Video::render_video_frame].
+It runs entirely on CPU, so it can be used wherever there is no GPU available, e.g. by headless capture
+tools or in web builds.
+
+The filter reads back pixels via [RgbPalette], which should be the same [Palette] implementation that
+was used to render the frame:
+
+```text
+ula.render_video_frame::<PixelBufA24, SpectrumPalRGB24>(&mut buffer, pitch, border);
+let (width, _) = UlaPAL::<Memory48k>::render_size_pixels(border);
+crt_filter.apply::<SpectrumPalRGB24>(&mut buffer, pitch, width as usize);
+```
+
+The following effects are applied, in order:
+
+* The horizontal blur of the luminance, as a result of the limited bandwidth of the video signal.
+* The PAL chroma bleed: the chrominance has a much lower bandwidth than the luminance, so the colors spread
+  horizontally, and the PAL decoder's delay line averages the chrominance of the adjacent lines.
+* The scanlines: every other pixel line is being darkened.
+* The gamma correction.
+
+[Video::render_video_frame]: crate::video::Video::render_video_frame
+[Palette]: crate::video::Palette
+!*/
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use super::pixel::RgbPalette;
+
+// Luminance weights of the RGB components.
+const LUMA_R: f32 = 0.299;
+const LUMA_G: f32 = 0.587;
+const LUMA_B: f32 = 0.114;
+// The maximum chrominance low-pass filter coefficient.
+const CHROMA_LOWPASS_MAX: f32 = 0.7;
+
+/// The parameters of the software CRT post-processing filter.
+///
+/// See the [module][self] documentation for the details.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(default, rename_all = "camelCase"))]
+pub struct CrtFilter {
+    /// The strength of the PAL chroma bleed: from `0.0` (none) to `1.0` (maximum).
+    pub chroma_bleed: f32,
+    /// The darkening of every other pixel line: from `0.0` (no scanlines) to `1.0` (black lines).
+    pub scanlines: f32,
+    /// The strength of the horizontal luminance blur: from `0.0` (none) to `1.0` (maximum).
+    pub blur: f32,
+    /// The gamma correction: each color component `v` in the range `[0.0, 1.0]` is being converted to
+    /// `v^(1/gamma)`. `1.0` leaves the colors intact. Should be a positive number.
+    pub gamma: f32
+}
+
+impl Default for CrtFilter {
+    fn default() -> Self {
+        CrtFilter {
+            chroma_bleed: 0.5,
+            scanlines: 0.25,
+            blur: 0.3,
+            gamma: 1.0
+        }
+    }
+}
+
+impl CrtFilter {
+    /// Returns a filter which doesn't alter rendered frames.
+    pub fn none() -> Self {
+        CrtFilter {
+            chroma_bleed: 0.0,
+            scanlines: 0.0,
+            blur: 0.0,
+            gamma: 1.0
+        }
+    }
+    /// Applies the filter to the rendered frame in the `buffer`.
+    ///
+    /// * `pitch` is the number of bytes of a single line of pixels in the `buffer`.
+    /// * `width` is the number of pixels in a single line to be processed.
+    ///
+    /// All lines found in the `buffer` are being processed, the line at offset `0` is the first one.
+    /// `P` should be the same [Palette][crate::video::Palette] implementation that was used to render
+    /// the frame.
+    pub fn apply<P: RgbPalette>(&self, buffer: &mut [u8], pitch: usize, width: usize) {
+        if pitch == 0 || width == 0 {
+            return
+        }
+        let gamma_table = self.gamma_table();
+        let blur = clamp_unit(self.blur) / 3.0;
+        let lowpass = clamp_unit(self.chroma_bleed) * CHROMA_LOWPASS_MAX;
+        let line_mix = clamp_unit(self.chroma_bleed) * 0.5;
+        let scanline_level = 1.0 - clamp_unit(self.scanlines);
+
+        let mut luma = vec![0.0f32; width];
+        let mut chroma = vec![(0.0f32, 0.0f32); width];
+        let mut prev_chroma: Vec<(f32, f32)> = Vec::with_capacity(width);
+        for (index, line) in buffer.chunks_mut(pitch).enumerate() {
+            let (_, pixels, _) = unsafe { line.align_to_mut::<P::Pixel>() };
+            let len = pixels.len().min(width);
+            let pixels = &mut pixels[..len];
+            let (luma, chroma) = (&mut luma[..len], &mut chroma[..len]);
+            for ((pixel, y), c) in pixels.iter().zip(luma.iter_mut()).zip(chroma.iter_mut()) {
+                let [r, g, b] = P::pixel_to_rgb(*pixel);
+                let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+                *y = LUMA_R * r + LUMA_G * g + LUMA_B * b;
+                *c = (b - *y, r - *y);
+            }
+            blur_line(luma, blur);
+            lowpass_line(chroma, lowpass);
+            if prev_chroma.len() < len {
+                prev_chroma.clear();
+                prev_chroma.extend_from_slice(chroma);
+            }
+            let level = if index & 1 == 1 { scanline_level } else { 1.0 };
+            for (((pixel, &y), c), prev_c) in pixels.iter_mut().zip(luma.iter())
+                                                     .zip(chroma.iter())
+                                                     .zip(prev_chroma.iter_mut())
+            {
+                let (cb, cr) = *c;
+                let cb_mix = cb + (prev_c.0 - cb) * line_mix;
+                let cr_mix = cr + (prev_c.1 - cr) * line_mix;
+                *prev_c = *c;
+                let r = y + cr_mix;
+                let b = y + cb_mix;
+                let g = (y - LUMA_R * r - LUMA_B * b) / LUMA_G;
+                let rgb = [r, g, b];
+                let mut out = [0u8; 3];
+                for (o, &v) in out.iter_mut().zip(rgb.iter()) {
+                    *o = gamma_table[to_u8(v * level) as usize];
+                }
+                *pixel = P::rgb_to_pixel(out);
+            }
+        }
+    }
+
+    fn gamma_table(&self) -> [u8; 256] {
+        let mut table = [0u8; 256];
+        let exp = 1.0 / self.gamma.max(0.01);
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = to_u8((i as f32 / 255.0).powf(exp) * 255.0);
+        }
+        table
+    }
+}
+
+// f32::clamp requires Rust 1.50, also max/min maps NaN to 0
+#[allow(clippy::manual_clamp)]
+#[inline]
+fn clamp_unit(v: f32) -> f32 {
+    v.max(0.0).min(1.0)
+}
+
+#[allow(clippy::manual_clamp)]
+#[inline]
+fn to_u8(v: f32) -> u8 {
+    (v.max(0.0).min(255.0) + 0.5) as u8
+}
+
+/// Applies a 3-tap horizontal blur with the side taps of the given `weight`.
+fn blur_line(line: &mut [f32], weight: f32) {
+    if weight == 0.0 || line.len() < 2 {
+        return
+    }
+    let center = 1.0 - 2.0 * weight;
+    let mut prev = line[0];
+    for i in 0..line.len() {
+        let cur = line[i];
+        let next = line.get(i + 1).copied().unwrap_or(cur);
+        line[i] = center * cur + weight * (prev + next);
+        prev = cur;
+    }
+}
+
+/// Applies a one-pole low-pass filter forward and backward, so the colors spread evenly to both sides.
+fn lowpass_line(line: &mut [(f32, f32)], k: f32) {
+    if k == 0.0 {
+        return
+    }
+    let mut acc = match line.first() {
+        Some(&c) => c,
+        None => return
+    };
+    for c in line.iter_mut() {
+        acc = (acc.0 + (c.0 - acc.0) * (1.0 - k), acc.1 + (c.1 - acc.1) * (1.0 - k));
+        *c = acc;
+    }
+    for c in line.iter_mut().rev() {
+        acc = (acc.0 + (c.0 - acc.0) * (1.0 - k), acc.1 + (c.1 - acc.1) * (1.0 - k));
+        *c = acc;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{Palette, PixelBuffer, pixel::*};
+    use super::*;
+
+    fn render_stripes(buffer: &mut [u8], pitch: usize, colors: &[u8]) {
+        for line in buffer.chunks_mut(pitch) {
+            let mut pixbuf = PixelBufA24::from_line(line);
+            for &color in colors.iter() {
+                pixbuf.put_pixels(SpectrumPalRGB24::get_pixel(color), 4);
+            }
+        }
+    }
+
+    #[test]
+    fn crt_filter_none_works() {
+        let colors: Vec<u8> = (0..16).collect();
+        let pitch = 16 * 4 * 3;
+        let mut buffer = vec![0u8; pitch * 4];
+        render_stripes(&mut buffer, pitch, &colors);
+        let source = buffer.clone();
+        CrtFilter::none().apply::<SpectrumPalRGB24>(&mut buffer, pitch, 64);
+        assert_eq!(buffer, source);
+    }
+
+    #[test]
+    fn crt_filter_works() {
+        let pitch = 8 * 3;
+        let mut buffer = vec![0u8; pitch * 2];
+        render_stripes(&mut buffer, pitch, &[15, 10]);
+        let source = buffer.clone();
+        let filter = CrtFilter { scanlines: 0.75, ..CrtFilter::none() };
+        filter.apply::<SpectrumPalRGB24>(&mut buffer, pitch, 8);
+        assert_eq!(buffer[..pitch], source[..pitch]);
+        assert_eq!(&buffer[pitch..pitch + 3], &[64, 64, 64]);
+        assert_eq!(&buffer[pitch + 21..], &[64, 0, 0]);
+
+        let mut buffer = source.clone();
+        let filter = CrtFilter { blur: 1.0, ..CrtFilter::none() };
+        filter.apply::<SpectrumPalRGB24>(&mut buffer, pitch, 8);
+        assert_eq!(buffer[..9], source[..9]);
+        assert!(buffer[9..12].iter().zip(source[9..12].iter()).all(|(a, b)| a < b));
+        assert!(buffer[13] > source[13] && buffer[14] > source[14]);
+        assert_eq!(buffer[21..24], source[21..24]);
+
+        let mut buffer = source.clone();
+        let filter = CrtFilter { chroma_bleed: 1.0, ..CrtFilter::none() };
+        filter.apply::<SpectrumPalRGB24>(&mut buffer, pitch, 8);
+        // the white pixel next to the red one is tinted red
+        assert_eq!(buffer[9], 255);
+        assert!(buffer[10] < 255 && buffer[11] < 255);
+        // and the red pixel next to the white one is less saturated
+        assert!(buffer[13] > 0 && buffer[14] > 0);
+
+        let mut buffer = source.clone();
+        let filter = CrtFilter { gamma: 2.0, ..CrtFilter::none() };
+        render_stripes(&mut buffer, pitch, &[1, 0]);
+        filter.apply::<SpectrumPalRGB24>(&mut buffer, pitch, 8);
+        assert_eq!(&buffer[..3], &[0, 0, 215]);
+        assert_eq!(&buffer[21..24], &[0, 0, 0]);
+
+        // only the given width is processed
+        let mut buffer = source.clone();
+        let filter = CrtFilter { scanlines: 1.0, ..CrtFilter::none() };
+        filter.apply::<SpectrumPalRGB24>(&mut buffer, pitch, 4);
+        assert_eq!(&buffer[pitch..pitch + 12], &[0; 12]);
+        assert_eq!(buffer[pitch + 12..], source[pitch + 12..]);
+    }
+}
@@ -0,0 +1,252 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! Run-time color palettes of ZX Spectrum colors.
+
+The [Palette] implementations in the [pixel][super::pixel] module define their colors at compile time.
+[ColorPalette] holds the 16 ZX Spectrum colors at run time instead, so the color profile can be switched,
+e.g. to one of the [PRESETS] or to a palette loaded from a file, without recompiling the emulator.
+
+Frames are being rendered with [ColorIndexPal] via [PixelBufP16] into a buffer of color indexes first,
+which is then converted to the target pixel format by a [PixelMap] created from the [ColorPalette]:
+
+```text
+let pixel_map = ColorPalette::SPECTRUSTY.pixel_map::<SpectrumPalRGB24>();
+ula.render_video_frame::<PixelBufP16, ColorIndexPal>(&mut indexes, width * 2, border);
+pixel_map.map_frame::<PixelBufA24>(&indexes, width * 2, &mut buffer, pitch, width);
+```
+
+Only the 16 standard colors are affected by the [ColorPalette]. The [ULAplus] colors are always mapped with
+the standard `G3R3B2` expansion, the same as by the compile-time palettes, so the colors programmed by
+ULAplus software look the same regardless of the selected profile.
+
+[Palette]: super::Palette
+[PixelBufP16]: super::pixel::PixelBufP16
+[ULAplus]: https://faqwiki.zxnet.co.uk/wiki/ULAplus#GRB_palette_entries
+!*/
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use super::pixel::{Palette, PixelBuffer, RgbPalette, grb_2r, grb_2g, grb_2b, grayscale};
+
+// The ranges of pixel values produced by ColorIndexPal.
+const INDEX_COLOR: u16 = 0x000;
+const INDEX_GRAY: u16 = 0x010;
+const INDEX_GRB8: u16 = 0x100;
+const INDEX_GRAY8: u16 = 0x200;
+const INDEX_END: u16 = 0x300;
+
+/// The 16 ZX Spectrum colors as `[red, green, blue]` components.
+///
+/// See [Palette::get_pixel][super::Palette::get_pixel] for the order of colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct ColorPalette {
+    /// The 8 normal colors followed by the 8 bright colors.
+    pub colors: [[u8;3];16]
+}
+
+/// A [Palette] implementation to be used with [PixelBufP16][super::pixel::PixelBufP16], producing
+/// pixels as color indexes to be converted by a [PixelMap].
+///
+/// The pixel values are:
+/// ```text
+/// 0x000 - 0x00F: ZX Spectrum colors
+/// 0x010 - 0x01F: grayscale ZX Spectrum colors
+/// 0x100 - 0x1FF: ULAplus G3R3B2 colors
+/// 0x200 - 0x2FF: grayscale intensities
+/// ```
+pub struct ColorIndexPal;
+
+/// A lookup table of pixels of the target pixel format, for converting frames rendered with [ColorIndexPal].
+#[derive(Clone, Debug)]
+pub struct PixelMap<T> {
+    pixels: Box<[T]>
+}
+
+/// The color palette presets with their names.
+///
+/// See the documentation of each [ColorPalette] constant for the source of its colors.
+pub const PRESETS: &[(&str, ColorPalette)] = &[
+    ("spectrusty", ColorPalette::SPECTRUSTY),
+    ("fuse", ColorPalette::FUSE)
+];
+
+/// Returns the 16 ZX Spectrum colors with the normal `n` and bright `b` intensities.
+const fn spectrum_colors(n: u8, b: u8) -> [[u8;3];16] {
+    [[0, 0, 0], [0, 0, n], [n, 0, 0], [n, 0, n], [0, n, 0], [0, n, n], [n, n, 0], [n, n, n],
+     [0, 0, 0], [0, 0, b], [b, 0, 0], [b, 0, b], [0, b, 0], [0, b, b], [b, b, 0], [b, b, b]]
+}
+
+impl Default for ColorPalette {
+    fn default() -> Self {
+        ColorPalette::SPECTRUSTY
+    }
+}
+
+impl ColorPalette {
+    /// The colors of the compile-time [Palette] implementations.
+    pub const SPECTRUSTY: ColorPalette = ColorPalette { colors: spectrum_colors(0xB6, 0xFF) };
+    /// The default colors of the [Fuse] emulator, with the normal intensity of `0xC0`.
+    ///
+    /// [Fuse]: http://fuse-emulator.sourceforge.net/
+    pub const FUSE: ColorPalette = ColorPalette { colors: spectrum_colors(0xC0, 0xFF) };
+
+    /// Creates a new palette from the 16 ZX Spectrum `colors`.
+    pub fn new(colors: [[u8;3];16]) -> Self {
+        ColorPalette { colors }
+    }
+    /// Returns a preset palette with the given `name` from [PRESETS], ignoring the case.
+    pub fn from_preset_name(name: &str) -> Option<Self> {
+        PRESETS.iter().find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+                      .map(|&(_, palette)| palette)
+    }
+    /// Returns the color with the given `index` in the range: [0, 15].
+    ///
+    /// Only the 4 lowest bits of the `index` are used.
+    #[inline]
+    pub fn get_color(&self, index: u8) -> [u8;3] {
+        self.colors[(index & 15) as usize]
+    }
+    /// Returns the grayscale intensity of the color with the given `index` in the range: [0, 15].
+    #[inline]
+    pub fn get_gray(&self, index: u8) -> u8 {
+        let [r, g, b] = self.get_color(index);
+        grayscale(r, g, b)
+    }
+    /// Returns a lookup table converting pixels of [ColorIndexPal] to pixels of the palette `P`.
+    pub fn pixel_map<P: RgbPalette>(&self) -> PixelMap<P::Pixel> {
+        let pixels = (0..INDEX_END).map(|index| {
+            let rgb = match index {
+                INDEX_COLOR..=0x00F => self.get_color(index as u8),
+                INDEX_GRAY..=0x01F => {
+                    let v = self.get_gray(index as u8);
+                    [v, v, v]
+                }
+                INDEX_GRB8..=0x1FF => {
+                    let grb = index as u8;
+                    [grb_2r(grb), grb_2g(grb), grb_2b(grb)]
+                }
+                INDEX_GRAY8..=0x2FF => {
+                    let v = index as u8;
+                    [v, v, v]
+                }
+                _ => [0, 0, 0]
+            };
+            P::rgb_to_pixel(rgb)
+        }).collect();
+        PixelMap { pixels }
+    }
+}
+
+impl Palette for ColorIndexPal {
+    type Pixel = u16;
+
+    #[inline(always)]
+    fn get_pixel(index: u8) -> Self::Pixel {
+        INDEX_COLOR | u16::from(index & 15)
+    }
+    #[inline(always)]
+    fn get_pixel_gray(index: u8) -> Self::Pixel {
+        INDEX_GRAY | u16::from(index & 15)
+    }
+    #[inline(always)]
+    fn get_pixel_grb8(g3r3b2: u8) -> Self::Pixel {
+        INDEX_GRB8 | u16::from(g3r3b2)
+    }
+    #[inline(always)]
+    fn get_pixel_gray8(value: u8) -> Self::Pixel {
+        INDEX_GRAY8 | u16::from(value)
+    }
+}
+
+impl<T: Copy> PixelMap<T> {
+    /// Returns a pixel for the given [ColorIndexPal] pixel value.
+    ///
+    /// Values not produced by [ColorIndexPal] are converted to the black pixel.
+    #[inline]
+    pub fn get(&self, index: u16) -> T {
+        match self.pixels.get(index as usize) {
+            Some(&pixel) => pixel,
+            None => self.pixels[INDEX_COLOR as usize]
+        }
+    }
+    /// Converts the frame rendered with [ColorIndexPal] via [PixelBufP16][super::pixel::PixelBufP16]
+    /// in the `src` buffer, to the pixels placed into the `dst` buffer via [PixelBuffer] `B`.
+    ///
+    /// `src_pitch` and `dst_pitch` are the numbers of bytes of a single line of each buffer.
+    /// `width` is the number of pixels in a single line to be converted.
+    pub fn map_frame<'a, B>(&self, src: &[u8], src_pitch: usize, dst: &'a mut [u8], dst_pitch: usize, width: usize)
+        where B: PixelBuffer<'a, Pixel=T>
+    {
+        for (src_line, dst_line) in src.chunks(src_pitch).zip(dst.chunks_mut(dst_pitch)) {
+            let indexes = src_line.chunks_exact(2).map(|c| u16::from_ne_bytes([c[0], c[1]]));
+            let mut buffer = B::from_line(dst_line);
+            for index in indexes.take(width) {
+                buffer.put_pixel(self.get(index));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::pixel::*;
+
+    #[test]
+    fn color_palette_works() {
+        for index in 0..16 {
+            assert_eq!(ColorPalette::SPECTRUSTY.get_color(index), SpectrumPalRGB24::get_pixel(index));
+            assert_eq!(ColorPalette::SPECTRUSTY.get_color(index + 16), SpectrumPalRGB24::get_pixel(index));
+            assert_eq!([ColorPalette::SPECTRUSTY.get_gray(index); 3], GrayscalePalRGB24::get_pixel_gray(index));
+        }
+        assert_eq!(ColorPalette::default(), ColorPalette::SPECTRUSTY);
+        assert_eq!(ColorPalette::from_preset_name("Fuse"), Some(ColorPalette::FUSE));
+        assert_eq!(ColorPalette::from_preset_name("issue4"), None);
+        for (name, palette) in PRESETS.iter() {
+            assert_eq!(ColorPalette::from_preset_name(name), Some(*palette));
+            assert_eq!(palette.get_color(0), [0, 0, 0]);
+            assert_eq!(palette.get_color(15), [255, 255, 255]);
+        }
+        assert_eq!(ColorPalette::FUSE.get_color(7), [0xC0, 0xC0, 0xC0]);
+    }
+
+    #[test]
+    fn color_palette_pixel_map_works() {
+        let palette = ColorPalette::new(spectrum_colors(0xD7, 0xFF));
+        let map = palette.pixel_map::<SpectrumPalRGB24>();
+        for index in 0..16 {
+            assert_eq!(map.get(ColorIndexPal::get_pixel(index)), palette.get_color(index));
+            assert_eq!(map.get(ColorIndexPal::get_pixel_gray(index)), [palette.get_gray(index); 3]);
+        }
+        // ULAplus colors are independent from the palette
+        for grb in 0..=255 {
+            assert_eq!(map.get(ColorIndexPal::get_pixel_grb8(grb)), SpectrumPalRGB24::get_pixel_grb8(grb));
+            assert_eq!(map.get(ColorIndexPal::get_pixel_gray8(grb)), SpectrumPalRGB24::get_pixel_gray8(grb));
+        }
+        assert_eq!(map.get(INDEX_END), [0, 0, 0]);
+        assert_eq!(map.get(u16::MAX), [0, 0, 0]);
+
+        let spectrusty = ColorPalette::SPECTRUSTY.pixel_map::<SpectrumPalR5G6B5>();
+        for index in 0..16 {
+            assert_eq!(spectrusty.get(ColorIndexPal::get_pixel(index)), SpectrumPalR5G6B5::get_pixel(index));
+        }
+
+        let indexes: [u16; 6] = [
+            ColorIndexPal::get_pixel(1), ColorIndexPal::get_pixel(10), 0,
+            ColorIndexPal::get_pixel_grb8(0b1110_0000), ColorIndexPal::get_pixel_gray8(0x40), 0];
+        let mut src = Vec::new();
+        for index in indexes.iter() {
+            src.extend_from_slice(&index.to_ne_bytes());
+        }
+        let mut dst = [0u8; 2 * 7];
+        map.map_frame::<PixelBufA24>(&src, 6, &mut dst, 7, 2);
+        assert_eq!(dst, [0, 0, 0xD7, 0xFF, 0, 0, 0,
+                         0, 0xFF, 0, 0x40, 0x40, 0x40, 0]);
+    }
+}
@@ -68,6 +68,18 @@ pub trait Palette {
     fn get_pixel_gray8(value: u8) -> Self::Pixel;
 }
 
+/// A trait for converting pixels of a [Palette] to and from RGB color components.
+///
+/// Used by the post-processing tools to read back pixels of the already rendered frames.
+pub trait RgbPalette: Palette {
+    /// Should return the `[red, green, blue]` color components of the given `pixel`.
+    fn pixel_to_rgb(pixel: Self::Pixel) -> [u8;3];
+    /// Should return a pixel with the given `[red, green, blue]` color components.
+    ///
+    /// Palettes with packed pixel formats may lose the lowest bits of the components.
+    fn rgb_to_pixel(rgb: [u8;3]) -> Self::Pixel;
+}
+
 /// A [PixelBuffer] tool for placing pixels into byte buffers using 3 `u8` element arrays of color channels
 /// (3 bytes per pixel).
 pub struct PixelBufA24<'a> {
@@ -116,6 +128,13 @@ pub struct SpectrumPalR5G6B5;
 /// A color ZX Spectrum [Palette] implementation to be used with [PixelBufP8].
 pub struct SpectrumPalR3G3B2;
 
+/// A color ZX Spectrum [Palette] implementation to be used with [PixelBufP8], producing pixels as indexes
+/// of [ULAplus](https://faqwiki.zxnet.co.uk/wiki/ULAplus#GRB_palette_entries) colors (`G3R3B2`),
+/// e.g. for indexed color image formats.
+///
+/// Grayscale pixels are being approximated with the closest `G3R3B2` colors.
+pub struct SpectrumPalGRB8;
+
 /// A grayscale ZX Spectrum [Palette] implementation to be used with [PixelBufA24].
 pub struct GrayscalePalRGB24;
 
@@ -248,15 +267,15 @@ macro_rules! impl_pixel_grb_gray {
 
 const ALPHA_MAX: u8 = u8::max_value();
 
-const fn grb_2r(grb: u8) -> u8 {
+pub(crate) const fn grb_2r(grb: u8) -> u8 {
     ((grb >> 3) & 3) | (grb & 0b11100) | ((grb & 0b11100) << 3)
 }
 
-const fn grb_2g(grb: u8) -> u8 {
+pub(crate) const fn grb_2g(grb: u8) -> u8 {
     ((grb >> 6) & 3) | ((grb >> 3) & 0b11100) | (grb & 0b11100000)
 }
 
-const fn grb_2b(grb: u8) -> u8 {
+pub(crate) const fn grb_2b(grb: u8) -> u8 {
     (grb & 3) | ((grb << 3) & 0b11000) | (((grb << 2) | (grb << 1)) & 0b100) |
     ((grb << 6) & 0b11000000) | (((grb << 5) | (grb << 4)) & 0b100000)
 }
@@ -276,7 +295,7 @@ const fn pack_332(a: u8, b: u8, c: u8) -> u8 {
     ((a >> 5) << 5) | ((b >> 5) << 2) | (c >> 6)
 }
 
-const fn grayscale(r: u8, g: u8, b: u8) -> u8 {
+pub(crate) const fn grayscale(r: u8, g: u8, b: u8) -> u8 {
     ((13933 * r as u32 + 46871 * g as u32 + 4732 * b as u32) >> 16) as u8
 }
 
@@ -427,6 +446,80 @@ impl_palette!(GrayscalePalR8G8B8A8, u32);
 impl_palette!(GrayscalePalR5G6B5,   u16);
 impl_palette!(GrayscalePalR3G3B2,   u8);
 
+#[inline(always)]
+const fn pack_grb(r: u8, g: u8, b: u8) -> u8 {
+    (g & 0b11100000) | ((r >> 3) & 0b00011100) | (b >> 6)
+}
+
+impl Palette for SpectrumPalGRB8 {
+    type Pixel = u8;
+
+    #[inline(always)]
+    fn get_pixel_grb8(g3r3b2: u8) -> Self::Pixel {
+        g3r3b2
+    }
+    #[inline(always)]
+    fn get_pixel_gray(index: u8) -> Self::Pixel {
+        Self::get_pixel_gray8(GRAYSCALE[index_to_grb(index) as usize])
+    }
+    #[inline(always)]
+    fn get_pixel_gray8(value: u8) -> Self::Pixel {
+        pack_grb(value, value, value)
+    }
+}
+
+macro_rules! impl_rgb_palette {
+    ($($palette:ty),* => $pixel:ty, |$p:ident| $to_rgb:expr, |$r:ident, $g:ident, $b:ident| $from_rgb:expr) => {$(
+        impl RgbPalette for $palette {
+            #[inline(always)]
+            fn pixel_to_rgb($p: $pixel) -> [u8;3] {
+                $to_rgb
+            }
+            #[inline(always)]
+            fn rgb_to_pixel([$r, $g, $b]: [u8;3]) -> $pixel {
+                $from_rgb
+            }
+        }
+    )*};
+}
+
+#[inline(always)]
+fn expand_bits(v: u8, bits: u32) -> u8 {
+    let v = v << (8 - bits);
+    let mut res = v;
+    let mut shift = bits;
+    while shift < 8 {
+        res |= v >> shift;
+        shift += bits;
+    }
+    res
+}
+
+impl_rgb_palette!(SpectrumPalRGB24, GrayscalePalRGB24 => [u8;3],
+    |p| p,
+    |r, g, b| [r, g, b]);
+impl_rgb_palette!(SpectrumPalRGBA32, GrayscalePalRGBA32 => [u8;4],
+    |p| [p[0], p[1], p[2]],
+    |r, g, b| [r, g, b, ALPHA_MAX]);
+impl_rgb_palette!(SpectrumPalARGB32, GrayscalePalARGB32 => [u8;4],
+    |p| [p[1], p[2], p[3]],
+    |r, g, b| [ALPHA_MAX, r, g, b]);
+impl_rgb_palette!(SpectrumPalA8R8G8B8, GrayscalePalA8R8G8B8 => u32,
+    |p| [(p >> 16) as u8, (p >> 8) as u8, p as u8],
+    |r, g, b| pack_8888(ALPHA_MAX, r, g, b));
+impl_rgb_palette!(SpectrumPalR8G8B8A8, GrayscalePalR8G8B8A8 => u32,
+    |p| [(p >> 24) as u8, (p >> 16) as u8, (p >> 8) as u8],
+    |r, g, b| pack_8888(r, g, b, ALPHA_MAX));
+impl_rgb_palette!(SpectrumPalR5G6B5, GrayscalePalR5G6B5 => u16,
+    |p| [expand_bits((p >> 11) as u8, 5), expand_bits((p >> 5) as u8 & 0x3f, 6), expand_bits(p as u8 & 0x1f, 5)],
+    |r, g, b| pack_565(r, g, b));
+impl_rgb_palette!(SpectrumPalGRB8 => u8,
+    |p| [grb_2r(p), grb_2g(p), grb_2b(p)],
+    |r, g, b| pack_grb(r, g, b));
+impl_rgb_palette!(SpectrumPalR3G3B2, GrayscalePalR3G3B2 => u8,
+    |p| [expand_bits(p >> 5, 3), expand_bits((p >> 2) & 7, 3), expand_bits(p & 3, 2)],
+    |r, g, b| pack_332(r, g, b));
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -531,4 +624,38 @@ mod tests {
             assert_eq!(GrayscalePalR3G3B2::get_pixel_grb8(i), grayscale_u8(v));
         }
     }
+    #[test]
+    fn pixel_palette_rgb_works() {
+        for i in 0..=255u8 {
+            let rgb = SpectrumPalRGB24::get_pixel_grb8(i);
+            assert_eq!(SpectrumPalRGB24::pixel_to_rgb(SpectrumPalRGB24::get_pixel_grb8(i)), rgb);
+            assert_eq!(SpectrumPalRGBA32::pixel_to_rgb(SpectrumPalRGBA32::get_pixel_grb8(i)), rgb);
+            assert_eq!(SpectrumPalARGB32::pixel_to_rgb(SpectrumPalARGB32::get_pixel_grb8(i)), rgb);
+            assert_eq!(SpectrumPalA8R8G8B8::pixel_to_rgb(SpectrumPalA8R8G8B8::get_pixel_grb8(i)), rgb);
+            assert_eq!(SpectrumPalR8G8B8A8::pixel_to_rgb(SpectrumPalR8G8B8A8::get_pixel_grb8(i)), rgb);
+            assert_eq!(SpectrumPalRGBA32::rgb_to_pixel(rgb), SpectrumPalRGBA32::get_pixel_grb8(i));
+            assert_eq!(SpectrumPalARGB32::rgb_to_pixel(rgb), SpectrumPalARGB32::get_pixel_grb8(i));
+            assert_eq!(SpectrumPalA8R8G8B8::rgb_to_pixel(rgb), SpectrumPalA8R8G8B8::get_pixel_grb8(i));
+            assert_eq!(SpectrumPalR8G8B8A8::rgb_to_pixel(rgb), SpectrumPalR8G8B8A8::get_pixel_grb8(i));
+            assert_eq!(SpectrumPalR5G6B5::rgb_to_pixel(rgb), SpectrumPalR5G6B5::get_pixel_grb8(i));
+            assert_eq!(SpectrumPalR3G3B2::rgb_to_pixel(rgb), SpectrumPalR3G3B2::get_pixel_grb8(i));
+            assert_eq!(GrayscalePalRGB24::pixel_to_rgb(GrayscalePalRGB24::get_pixel_gray8(i)), [i, i, i]);
+        }
+        for i in 0..=255u8 {
+            let rgb = SpectrumPalRGB24::get_pixel_grb8(i);
+            assert_eq!(SpectrumPalGRB8::get_pixel_grb8(i), i);
+            assert_eq!(SpectrumPalGRB8::pixel_to_rgb(i), rgb);
+            assert_eq!(SpectrumPalGRB8::rgb_to_pixel(rgb), i);
+        }
+        for i in 0..16 {
+            assert_eq!(SpectrumPalGRB8::pixel_to_rgb(SpectrumPalGRB8::get_pixel(i)), SpectrumPalRGB24::get_pixel(i));
+        }
+        assert_eq!(SpectrumPalGRB8::get_pixel_gray8(0), 0);
+        assert_eq!(SpectrumPalGRB8::get_pixel_gray8(255), 255);
+        assert_eq!(SpectrumPalGRB8::get_pixel_gray(7), 0b1011_0110);
+        assert_eq!(SpectrumPalR5G6B5::pixel_to_rgb(0xffff), [255, 255, 255]);
+        assert_eq!(SpectrumPalR5G6B5::pixel_to_rgb(0b1000_0100_0000_0001), [0b10000100, 0b10000010, 0b00001000]);
+        assert_eq!(SpectrumPalR3G3B2::pixel_to_rgb(0xff), [255, 255, 255]);
+        assert_eq!(SpectrumPalR3G3B2::pixel_to_rgb(0b1000_1001), [0b10010010, 0b01001001, 0b01010101]);
+    }
 }
@@ -0,0 +1,160 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **D40** and **D80** file format utilities.
+
+[Utilities][D80DiskExt] in this module provide additional methods to the [FloppyDisk] type
+with abilities to read and write disk images in the **D40** and **D80** file formats.
+
+Both formats are raw images of disks used by the Didaktik MDOS disk interfaces. The disks have
+2 sides, each track consists of 9 sectors, 512 bytes each, numbered from 1 to 9. The **D40** disks
+have 40 cylinders and the **D80** disks have 80 cylinders, otherwise the formats are identical.
+The files contain the data of all sectors ordered by tracks and then by sectors, with the sides
+interleaved: cylinder 0 side 0, cylinder 0 side 1, cylinder 1 side 0, ...
+!*/
+use std::io::{self, Read, Write};
+
+pub use spectrusty_peripherals::storage::floppy::{
+    FloppyDisk, DiskTrack, DiskSector
+};
+
+/// The number of cylinders of a **D40** disk.
+pub const D40_CYLINDERS: u8 = 40;
+/// The number of cylinders of a **D80** disk.
+pub const D80_CYLINDERS: u8 = 80;
+/// The number of sides of a **D40** or **D80** disk.
+pub const D80_SIDES: u8 = 2;
+/// The number of sectors in a single **D40** or **D80** track.
+pub const D80_SECTORS: u8 = 9;
+/// The size of a single **D40** or **D80** sector in bytes.
+pub const D80_SECTOR_SIZE: usize = 512;
+/// The size of a **D40** file in bytes.
+pub const D40_IMAGE_SIZE: usize = D40_CYLINDERS as usize * CYLINDER_SIZE;
+/// The size of a **D80** file in bytes.
+pub const D80_IMAGE_SIZE: usize = D80_CYLINDERS as usize * CYLINDER_SIZE;
+
+const TRACK_SIZE: usize = D80_SECTORS as usize * D80_SECTOR_SIZE;
+const CYLINDER_SIZE: usize = D80_SIDES as usize * TRACK_SIZE;
+
+/// Extends [FloppyDisk] with methods for reading and writing **D40** and **D80** files.
+pub trait D80DiskExt: Sized {
+    /// Creates a new, empty **D40** disk with 40 cylinders and 2 sides.
+    fn new_d40_formatted() -> Self;
+    /// Creates a new, empty **D80** disk with 80 cylinders and 2 sides.
+    fn new_d80_formatted() -> Self;
+    /// Creates a new instance of [FloppyDisk] from the **D40** or **D80** file data read from
+    /// the provided reader.
+    ///
+    /// The number of cylinders is determined from the size of the file.
+    fn from_d80<R: Read>(rd: R) -> io::Result<Self>;
+    /// Writes the disk to a **D40** or **D80** file, depending on the number of cylinders of the disk,
+    /// using a provided writer.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the disk has neither 40 nor 80 cylinders
+    /// or if any of the sectors is missing from the disk.
+    fn write_d80<W: Write>(&self, wr: W) -> io::Result<usize>;
+}
+
+fn new_formatted(cylinders: u8) -> FloppyDisk {
+    FloppyDisk::new_formatted(cylinders, D80_SIDES, D80_SECTORS, 1, 2, 0)
+}
+
+impl D80DiskExt for FloppyDisk {
+    fn new_d40_formatted() -> Self {
+        new_formatted(D40_CYLINDERS)
+    }
+
+    fn new_d80_formatted() -> Self {
+        new_formatted(D80_CYLINDERS)
+    }
+
+    fn from_d80<R: Read>(mut rd: R) -> io::Result<Self> {
+        let mut data = Vec::with_capacity(D80_IMAGE_SIZE);
+        rd.read_to_end(&mut data)?;
+        let cylinders = match data.len() {
+            D40_IMAGE_SIZE => D40_CYLINDERS,
+            D80_IMAGE_SIZE => D80_CYLINDERS,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "D80 image size is invalid"))
+        };
+        let mut disk = new_formatted(cylinders);
+        let tracks = (0..cylinders).flat_map(|cyl| (0..D80_SIDES).map(move |side| (cyl, side)));
+        for ((cyl, side), track_data) in tracks.zip(data.chunks(TRACK_SIZE)) {
+            let track = disk.track_mut(cyl, side).unwrap();
+            for (sector, sector_data) in track.sectors.iter_mut().zip(track_data.chunks(D80_SECTOR_SIZE)) {
+                sector.data.copy_from_slice(sector_data);
+            }
+        }
+        Ok(disk)
+    }
+
+    fn write_d80<W: Write>(&self, mut wr: W) -> io::Result<usize> {
+        let cylinders = match self.cylinders() {
+            D40_CYLINDERS => D40_CYLINDERS,
+            D80_CYLINDERS => D80_CYLINDERS,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "disk can't be represented in the D80 format"))
+        };
+        let mut bytes = 0;
+        for cyl in 0..cylinders {
+            for side in 0..D80_SIDES {
+                for id in 1..=D80_SECTORS {
+                    let data = self.track(cyl, side)
+                        .and_then(|track| track.sectors.iter().find(|s| s.id == id))
+                        .map(|s| &s.data[..])
+                        .filter(|data| data.len() == D80_SECTOR_SIZE)
+                        .ok_or_else(||
+                            io::Error::new(io::ErrorKind::InvalidInput, "disk can't be represented in the D80 format")
+                        )?;
+                    wr.write_all(data)?;
+                    bytes += data.len();
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn d80_works() {
+        let mut disk = FloppyDisk::new_d80_formatted();
+        assert_eq!(disk.cylinders(), 80);
+        assert_eq!(disk.sides(), 2);
+        disk.track_mut(0, 1).unwrap().sectors[0].data[0] = 1;
+        disk.track_mut(1, 0).unwrap().sectors[8].data[511] = 2;
+        disk.track_mut(79, 1).unwrap().sectors[8].data[511] = 3;
+
+        let mut d80 = Vec::new();
+        assert_eq!(disk.write_d80(&mut d80).unwrap(), D80_IMAGE_SIZE);
+        assert_eq!(d80.len(), 737280);
+        assert_eq!(d80[TRACK_SIZE], 1);
+        assert_eq!(d80[3 * TRACK_SIZE - 1], 2);
+        assert_eq!(d80[D80_IMAGE_SIZE - 1], 3);
+        assert_eq!(FloppyDisk::from_d80(Cursor::new(&d80)).unwrap(), disk);
+
+        let mut disk = FloppyDisk::new_d40_formatted();
+        assert_eq!(disk.cylinders(), 40);
+        disk.track_mut(39, 0).unwrap().sectors[0].data[0] = 4;
+        let mut d40 = Vec::new();
+        assert_eq!(disk.write_d80(&mut d40).unwrap(), D40_IMAGE_SIZE);
+        assert_eq!(d40.len(), 368640);
+        assert_eq!(d40[D40_IMAGE_SIZE - 2 * TRACK_SIZE], 4);
+        assert_eq!(FloppyDisk::from_d80(Cursor::new(&d40)).unwrap(), disk);
+
+        assert_eq!(FloppyDisk::from_d80(Cursor::new(&d40[1..])).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        disk.track_mut(20, 1).unwrap().sectors.pop();
+        assert_eq!(disk.write_d80(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let disk = FloppyDisk::new_formatted(41, 2, 9, 1, 2, 0);
+        assert_eq!(disk.write_d80(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
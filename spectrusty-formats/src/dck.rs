@@ -0,0 +1,303 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **DCK** file format utilities.
+
+**DCK** files contain images of the Timex TC2068 / TS2068 cartridges and other memory banks
+of these computers.
+
+A file consists of one or more bank images. Each image begins with a 9-byte header:
+
+* byte 0: the bank ID: `0` - DOCK, `254` - EX-ROM, `255` - HOME.
+* bytes 1 to 8: the types of eight 8kb chunks of the bank, for addresses from `0x0000` to `0xFFFF`:
+  `0` - the chunk is absent, `1` - RAM with no data in the file, `2` - ROM, `3` - RAM.
+
+The header is followed by the data of the ROM and RAM chunks that have their data stored in the file,
+8192 bytes each, in the order of their addresses.
+
+The [DckBankImage::load_into_memory] and [load_dck] functions load the chunks into the memory
+the same way the `Scld` chipset of the `spectrusty` crate expects them:
+
+* The DOCK ROM chunks are loaded into ROM banks `[0, 7]`.
+* The EX-ROM ROM chunk is attached to its memory page with [ZxMemory::map_exrom], so the ROM banks
+  `[8, ROM_BANKS_MAX - 2]` holding the EX-ROM of the computer itself are left unmodified.
+* The HOME chunks are loaded into ROM banks `[ROM_BANKS_MAX - 1, ROM_BANKS_MAX]` and RAM banks `[0, 5]`.
+* The DOCK and EX-ROM RAM chunks are loaded into RAM banks `[0, 5]`, so they stay writable.
+
+The memory must have 8kb pages and support EX-ROM attachments, e.g. `Memory48kDock64kEx`.
+
+Because only one EX-ROM can be attached at the same time, an EX-ROM bank image may contain only one
+ROM chunk. There are no separate DOCK and EX-ROM RAM banks, so their RAM chunks replace the HOME RAM
+at the same addresses and can't be placed below `0x4000`. Each chunk is also available as an [ExRom],
+so it can be attached to any memory page with [ZxMemory::map_exrom] later.
+
+[ZxMemory::map_exrom]: spectrusty_core::memory::ZxMemory::map_exrom
+!*/
+use core::convert::TryFrom;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use spectrusty_core::memory::{ZxMemory, ExRom, MEM8K_SIZE};
+
+use crate::ReadExactEx;
+
+/// The number of chunks in a single bank image.
+pub const DCK_CHUNKS: usize = 8;
+/// The size of a single chunk in bytes.
+pub const DCK_CHUNK_SIZE: usize = MEM8K_SIZE;
+
+/// The bank ID of a **DCK** image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DckBank {
+    Dock  = 0,
+    ExRom = 254,
+    Home  = 255
+}
+
+/// The type of a chunk in a **DCK** image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DckChunkKind {
+    /// RAM with no data stored in the file, its content is zeroed.
+    RamEmpty = 1,
+    /// ROM with data stored in the file.
+    Rom = 2,
+    /// RAM with data stored in the file.
+    Ram = 3
+}
+
+/// A single 8kb chunk of a **DCK** bank image.
+#[derive(Clone, Debug)]
+pub struct DckChunk {
+    /// The type of the chunk.
+    pub kind: DckChunkKind,
+    /// The content of the chunk, always 8192 bytes long.
+    pub data: ExRom
+}
+
+/// A single bank image of a **DCK** file.
+#[derive(Clone, Debug)]
+pub struct DckBankImage {
+    /// The bank ID.
+    pub bank: DckBank,
+    /// The chunks of the bank for addresses from `0x0000` to `0xFFFF`, `None` if the chunk is absent.
+    pub chunks: [Option<DckChunk>; DCK_CHUNKS]
+}
+
+impl TryFrom<u8> for DckBank {
+    type Error = io::Error;
+
+    fn try_from(bank: u8) -> io::Result<Self> {
+        Ok(match bank {
+            0   => DckBank::Dock,
+            254 => DckBank::ExRom,
+            255 => DckBank::Home,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown DCK bank ID"))
+        })
+    }
+}
+
+impl TryFrom<u8> for DckChunkKind {
+    type Error = io::Error;
+
+    fn try_from(kind: u8) -> io::Result<Self> {
+        Ok(match kind {
+            1 => DckChunkKind::RamEmpty,
+            2 => DckChunkKind::Rom,
+            3 => DckChunkKind::Ram,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown DCK chunk type"))
+        })
+    }
+}
+
+impl DckChunkKind {
+    /// Returns `true` if the chunk is a RAM chunk.
+    pub fn is_ram(self) -> bool {
+        self != DckChunkKind::Rom
+    }
+}
+
+impl DckBankImage {
+    /// Reads the next bank image from the provided reader.
+    ///
+    /// Returns `Ok(None)` if there is no more data to be read.
+    pub fn read_next<R: Read>(mut rd: R) -> io::Result<Option<Self>> {
+        let mut header = [0u8; 1 + DCK_CHUNKS];
+        if !rd.read_exact_or_none(&mut header)? {
+            return Ok(None)
+        }
+        let bank = DckBank::try_from(header[0])?;
+        let mut chunks: [Option<DckChunk>; DCK_CHUNKS] = Default::default();
+        for (chunk, &kind) in chunks.iter_mut().zip(header[1..].iter()) {
+            if kind == 0 {
+                continue
+            }
+            let kind = DckChunkKind::try_from(kind)?;
+            let mut data = Rc::new([0u8; DCK_CHUNK_SIZE]);
+            if kind != DckChunkKind::RamEmpty {
+                rd.read_exact(Rc::get_mut(&mut data).unwrap())?;
+            }
+            *chunk = Some(DckChunk { kind, data });
+        }
+        Ok(Some(DckBankImage { bank, chunks }))
+    }
+    /// Loads the present chunks into the appropriate memory banks or attaches them as EX-ROM.
+    ///
+    /// See the [module][self] documentation for the details.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the memory doesn't have 8kb pages,
+    /// if the memory lacks banks required by the image, if the EX-ROM can't be attached or if
+    /// the chunks can't be placed in the memory.
+    pub fn load_into_memory<M: ZxMemory>(&self, memory: &mut M) -> io::Result<()> {
+        if M::PAGE_SIZE != DCK_CHUNK_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "memory with 8kb pages is required for DCK images"))
+        }
+        if self.bank == DckBank::ExRom &&
+                self.chunks.iter().flatten().filter(|chunk| !chunk.kind.is_ram()).count() > 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "only one EX-ROM chunk can be attached at the same time"))
+        }
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => continue
+            };
+            let bank_mem = match self.bank {
+                DckBank::Home if index < 2 => memory.rom_bank_mut(M::ROM_BANKS_MAX - 1 + index)?,
+                DckBank::Home => memory.ram_bank_mut(index - 2)?,
+                _ if chunk.kind.is_ram() => {
+                    if index < 2 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "RAM chunks can't be loaded below 0x4000"))
+                    }
+                    memory.ram_bank_mut(index - 2)?
+                }
+                DckBank::Dock => memory.rom_bank_mut(index)?,
+                DckBank::ExRom => {
+                    memory.map_exrom(Rc::clone(&chunk.data), index as u8)?;
+                    continue
+                }
+            };
+            bank_mem.copy_from_slice(&chunk.data);
+        }
+        Ok(())
+    }
+}
+
+/// Reads all bank images from the **DCK** file data provided by the reader.
+pub fn read_dck<R: Read>(mut rd: R) -> io::Result<Vec<DckBankImage>> {
+    let mut banks = Vec::new();
+    while let Some(image) = DckBankImage::read_next(&mut rd)? {
+        banks.push(image);
+    }
+    if banks.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no DCK bank images found"))
+    }
+    Ok(banks)
+}
+
+/// Reads all bank images from the **DCK** file data provided by the reader and loads them
+/// into the `memory` with [DckBankImage::load_into_memory].
+///
+/// On success returns the read bank images.
+pub fn load_dck<R: Read, M: ZxMemory>(rd: R, memory: &mut M) -> io::Result<Vec<DckBankImage>> {
+    let banks = read_dck(rd)?;
+    for image in banks.iter() {
+        image.load_into_memory(memory)?;
+    }
+    Ok(banks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use spectrusty::memory::{Memory48kDock64kEx, Memory48k};
+    use super::*;
+
+    #[test]
+    fn dck_works() {
+        let mut dck = vec![0, 2, 0, 0, 0, 0, 0, 1, 3];
+        dck.extend_from_slice(&[0xA0; DCK_CHUNK_SIZE]);
+        dck.extend_from_slice(&[0xA7; DCK_CHUNK_SIZE]);
+        dck.extend_from_slice(&[254, 0, 0, 0, 0, 2, 0, 0, 0]);
+        dck.extend_from_slice(&[0xE7; DCK_CHUNK_SIZE]);
+        dck.extend_from_slice(&[255, 0, 0, 3, 0, 0, 0, 0, 0]);
+        dck.extend_from_slice(&[0x53; DCK_CHUNK_SIZE]);
+
+        let banks = read_dck(Cursor::new(&dck)).unwrap();
+        assert_eq!(banks.len(), 3);
+        assert_eq!(banks[0].bank, DckBank::Dock);
+        assert_eq!(banks[0].chunks[0].as_ref().unwrap().kind, DckChunkKind::Rom);
+        assert_eq!(banks[0].chunks[6].as_ref().unwrap().kind, DckChunkKind::RamEmpty);
+        assert!(banks[0].chunks[6].as_ref().unwrap().data.iter().all(|&b| b == 0));
+        assert!(banks[0].chunks[2].is_none());
+        assert!(banks[0].chunks[7].as_ref().unwrap().kind.is_ram());
+        assert_eq!(banks[1].bank, DckBank::ExRom);
+        assert_eq!(banks[2].bank, DckBank::Home);
+
+        let mut mem = Memory48kDock64kEx::default();
+        mem.ram_bank_mut(4).unwrap().iter_mut().for_each(|b| *b = 0xFF);
+        let banks = load_dck(Cursor::new(&dck), &mut mem).unwrap();
+        assert!(mem.rom_bank_ref(0).unwrap().iter().all(|&b| b == 0xA0));
+        assert!(mem.ram_bank_ref(4).unwrap().iter().all(|&b| b == 0));
+        assert!(mem.ram_bank_ref(5).unwrap().iter().all(|&b| b == 0xA7));
+        assert!(mem.ram_bank_ref(0).unwrap().iter().all(|&b| b == 0x53));
+        assert_eq!(mem.read(0x4000), 0x53);
+        assert_eq!(mem.read(0xE000), 0xA7);
+        mem.write(0xE000, 0x11);
+        assert_eq!(mem.read(0xE000), 0x11);
+        assert!(mem.has_mapped_exrom(&banks[1].chunks[4].as_ref().unwrap().data));
+        assert_eq!(mem.read(0x8000), 0xE7);
+        let exrom = &banks[0].chunks[0].as_ref().unwrap().data;
+        mem.map_exrom(Rc::clone(exrom), 4).unwrap();
+        assert_eq!(mem.read(0x8000), 0xA0);
+
+        assert_eq!(load_dck(Cursor::new(&dck), &mut Memory48k::default()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(read_dck(Cursor::new(&dck[..dck.len() - 1])).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_dck(Cursor::new(&dck[..5])).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_dck(Cursor::new(&[])).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+        dck[0] = 1;
+        assert_eq!(read_dck(Cursor::new(&dck)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        dck[0] = 0;
+        dck[3] = 4;
+        assert_eq!(read_dck(Cursor::new(&dck)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_dck(Cursor::new(&[0, 0, 1, 0, 0, 0, 0, 0, 0]), &mut Memory48kDock64kEx::default())
+                   .unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn dck_exrom_works() {
+        let mut dck = vec![254, 0, 0, 0, 0, 2, 0, 0, 3];
+        dck.extend_from_slice(&[0xE7; DCK_CHUNK_SIZE]);
+        dck.extend_from_slice(&[0x3E; DCK_CHUNK_SIZE]);
+
+        let mut mem = Memory48kDock64kEx::default();
+        mem.rom_bank_mut(8).unwrap().iter_mut().for_each(|b| *b = 0x88);
+        let banks = load_dck(Cursor::new(&dck), &mut mem).unwrap();
+        assert_eq!(banks.len(), 1);
+        assert!(mem.rom_bank_ref(8).unwrap().iter().all(|&b| b == 0x88));
+        assert!(mem.has_mapped_exrom(&banks[0].chunks[4].as_ref().unwrap().data));
+        assert!(mem.is_exrom_at(4));
+        assert_eq!(mem.read(0x8000), 0xE7);
+        mem.write(0x8000, 0);
+        assert_eq!(mem.read(0x8000), 0xE7);
+        assert_eq!(mem.read(0xE000), 0x3E);
+        mem.write(0xE000, 0);
+        assert_eq!(mem.read(0xE000), 0);
+
+        dck[6] = 2;
+        dck.extend_from_slice(&[0x55; DCK_CHUNK_SIZE]);
+        assert_eq!(load_dck(Cursor::new(&dck), &mut Memory48kDock64kEx::default()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,325 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **DSK** file format utilities.
+
+[Utilities][FloppyDiskExt] in this module provide additional methods to the [FloppyDisk] type
+with abilities to read and write disk images in the standard and extended **DSK** file formats.
+
+**DSK** files are images of floppy disks used by the +3 (and Amstrad CPC) disk drives.
+There are two flavours of this format:
+
+* the standard format, beginning with `"MV - CPC"`, where all tracks have the same size and all sectors
+  of a track have the same size, determined by the track's size code;
+* the extended format, beginning with `"EXTENDED"`, where each track can have a different size, tracks
+  can be unformatted and each sector has its own data length.
+
+The disk information block (256 bytes):
+
+| offset | size | description                                                                   |
+|--------|------|-------------------------------------------------------------------------------|
+|      0 |   34 | `"MV - CPCEMU Disk-File\r\nDisk-Info\r\n"` or `"EXTENDED CPC DSK File\r\nDisk-Info\r\n"`. |
+|     34 |   14 | The name of the creator.                                                      |
+|     48 |    1 | The number of cylinders.                                                      |
+|     49 |    1 | The number of sides.                                                          |
+|     50 |    2 | Standard: the size of each track including the track information block (LSB first). |
+|     52 |  204 | Extended: the MSB of each track size including the track information block, ordered by cylinders, then sides. `0` indicates an unformatted track. |
+
+Each track begins with the track information block (256 bytes) followed by the sector data:
+
+| offset | size | description                                                                   |
+|--------|------|-------------------------------------------------------------------------------|
+|      0 |   12 | `"Track-Info\r\n"`.                                                           |
+|     16 |    1 | The cylinder number.                                                          |
+|     17 |    1 | The side number.                                                              |
+|     20 |    1 | The sector size code.                                                         |
+|     21 |    1 | The number of sectors.                                                        |
+|     22 |    1 | The length of the gap #3.                                                     |
+|     23 |    1 | The filler byte.                                                              |
+|     24 |  8*n | The sector information list: C, H, R, N, ST1, ST2 and the data length (LSB first, extended only). |
+!*/
+use std::io::{self, Read, Write};
+
+pub use spectrusty_peripherals::storage::floppy::{
+    FloppyDisk, DiskTrack, DiskSector, sector_size
+};
+
+const DSK_SIG: &[u8;34] = b"MV - CPCEMU Disk-File\r\nDisk-Info\r\n";
+const EDSK_SIG: &[u8;34] = b"EXTENDED CPC DSK File\r\nDisk-Info\r\n";
+const TRACK_SIG: &[u8;12] = b"Track-Info\r\n";
+const CREATOR: &[u8;14] = b"SPECTRUSTY\0\0\0\0";
+
+const INFO_SIZE: usize = 0x100;
+const SECTOR_INFO_OFFSET: usize = 0x18;
+const SECTOR_INFO_SIZE: usize = 8;
+/// The maximum number of sectors that can be described in a track information block.
+pub const MAX_DSK_SECTORS: usize = (INFO_SIZE - SECTOR_INFO_OFFSET) / SECTOR_INFO_SIZE;
+/// The maximum number of tracks that can be stored in an extended DSK file.
+pub const MAX_EDSK_TRACKS: usize = INFO_SIZE - 0x34;
+
+/// Extends [FloppyDisk] with methods for reading and writing **DSK** files.
+pub trait FloppyDiskExt: Sized {
+    /// Creates a new instance of [FloppyDisk] from the standard or extended **DSK** file data
+    /// read from the provided reader.
+    fn from_dsk<R: Read>(rd: R) -> io::Result<Self>;
+    /// Writes the disk to a standard **DSK** file using a provided writer.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the disk can't be represented in the
+    /// standard format, e.g. when any of its sectors has the data length different from the nominal
+    /// sector size of its track. In this instance use [FloppyDiskExt::write_edsk] instead.
+    fn write_dsk<W: Write>(&self, wr: W) -> io::Result<usize>;
+    /// Writes the disk to an extended **DSK** file using a provided writer.
+    ///
+    /// Returns the number of bytes written.
+    fn write_edsk<W: Write>(&self, wr: W) -> io::Result<usize>;
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn parse_track(data: &[u8], extended: bool) -> io::Result<DiskTrack> {
+    if data[0..10] != TRACK_SIG[0..10] {
+        return Err(invalid_data("DSK track information block not found"))
+    }
+    let size_code = data[0x14];
+    let count = data[0x15] as usize;
+    if count > MAX_DSK_SECTORS {
+        return Err(invalid_data("too many sectors in a DSK track"))
+    }
+    let mut track = DiskTrack { gap3: data[0x16], filler: data[0x17], sectors: Vec::with_capacity(count) };
+    let mut offset = INFO_SIZE;
+    for info in data[SECTOR_INFO_OFFSET..].chunks_exact(SECTOR_INFO_SIZE).take(count) {
+        let len = match u16::from_le_bytes([info[6], info[7]]) {
+            len if extended && len != 0 => len as usize,
+            _ if extended => sector_size(info[3]),
+            _ => sector_size(size_code)
+        };
+        let sector_data = data.get(offset..offset + len)
+                          .ok_or_else(|| invalid_data("DSK sector data exceeds the track size"))?;
+        offset += len;
+        track.sectors.push(DiskSector {
+            cylinder: info[0],
+            head: info[1],
+            id: info[2],
+            size_code: info[3],
+            st1: info[4],
+            st2: info[5],
+            data: sector_data.into()
+        });
+    }
+    Ok(track)
+}
+
+fn track_info(track: &DiskTrack, cylinder: u8, side: u8, extended: bool) -> io::Result<[u8;INFO_SIZE]> {
+    if track.sectors.len() > MAX_DSK_SECTORS {
+        return Err(invalid_input("too many sectors in a track to be stored in a DSK file"))
+    }
+    let mut info = [0u8;INFO_SIZE];
+    info[0..TRACK_SIG.len()].copy_from_slice(TRACK_SIG);
+    info[0x10] = cylinder;
+    info[0x11] = side;
+    info[0x14] = track.sectors.first().map(|sector| sector.size_code).unwrap_or(2);
+    info[0x15] = track.sectors.len() as u8;
+    info[0x16] = track.gap3;
+    info[0x17] = track.filler;
+    for (sector, chunk) in track.sectors.iter()
+                           .zip(info[SECTOR_INFO_OFFSET..].chunks_exact_mut(SECTOR_INFO_SIZE))
+    {
+        chunk[0..6].copy_from_slice(&[sector.cylinder, sector.head, sector.id, sector.size_code,
+                                      sector.st1, sector.st2]);
+        if extended {
+            chunk[6..8].copy_from_slice(&(sector.data.len() as u16).to_le_bytes());
+        }
+    }
+    Ok(info)
+}
+
+fn track_data_size(track: &DiskTrack) -> usize {
+    track.sectors.iter().map(|sector| sector.data.len()).sum()
+}
+
+fn write_track<W: Write>(mut wr: W, info: &[u8;INFO_SIZE], track: &DiskTrack, track_size: usize) -> io::Result<()> {
+    wr.write_all(info)?;
+    let mut size = INFO_SIZE;
+    for sector in track.sectors.iter() {
+        wr.write_all(&sector.data)?;
+        size += sector.data.len();
+    }
+    let padding = vec![0u8;track_size - size];
+    wr.write_all(&padding)
+}
+
+fn disk_info(signature: &[u8;34], disk: &FloppyDisk) -> [u8;INFO_SIZE] {
+    let mut info = [0u8;INFO_SIZE];
+    info[0..34].copy_from_slice(signature);
+    info[34..48].copy_from_slice(CREATOR);
+    info[0x30] = disk.cylinders();
+    info[0x31] = disk.sides();
+    info
+}
+
+fn tracks_with_position(disk: &FloppyDisk) -> impl Iterator<Item=(u8, u8, &DiskTrack)> {
+    let sides = disk.sides();
+    disk.tracks().iter().enumerate().map(move |(index, track)| {
+        ((index / sides as usize) as u8, (index % sides as usize) as u8, track)
+    })
+}
+
+impl FloppyDiskExt for FloppyDisk {
+    fn from_dsk<R: Read>(mut rd: R) -> io::Result<Self> {
+        let mut info = [0u8;INFO_SIZE];
+        rd.read_exact(&mut info)?;
+        let extended = if info.starts_with(&DSK_SIG[0..8]) {
+            false
+        }
+        else if info.starts_with(&EDSK_SIG[0..8]) {
+            true
+        }
+        else {
+            return Err(invalid_data("DSK format not recognized"))
+        };
+        let cylinders = info[0x30] as usize;
+        let sides = info[0x31];
+        if !(sides == 1 || sides == 2) {
+            return Err(invalid_data("unsupported number of DSK disk sides"))
+        }
+        let num_tracks = cylinders * sides as usize;
+        if extended && num_tracks > MAX_EDSK_TRACKS {
+            return Err(invalid_data("too many tracks in an extended DSK file"))
+        }
+        let mut tracks = Vec::with_capacity(num_tracks);
+        let mut buf = Vec::new();
+        for index in 0..num_tracks {
+            let track_size = if extended {
+                info[0x34 + index] as usize * 256
+            }
+            else {
+                u16::from_le_bytes([info[0x32], info[0x33]]) as usize
+            };
+            if track_size == 0 {
+                tracks.push(DiskTrack::default());
+                continue
+            }
+            if track_size < INFO_SIZE {
+                return Err(invalid_data("DSK track size is too small"))
+            }
+            buf.resize(track_size, 0);
+            rd.read_exact(&mut buf)?;
+            tracks.push(parse_track(&buf, extended)?);
+        }
+        Ok(FloppyDisk::from_tracks(sides, tracks))
+    }
+
+    fn write_dsk<W: Write>(&self, mut wr: W) -> io::Result<usize> {
+        for track in self.tracks() {
+            if let Some(size_code) = track.sectors.first().map(|sector| sector.size_code) {
+                if track.sectors.iter().any(|sector| sector.data.len() != sector_size(size_code)) {
+                    return Err(invalid_input("disk can't be represented in the standard DSK format"))
+                }
+            }
+        }
+        let track_size = self.tracks().iter().map(track_data_size).max().unwrap_or(0) + INFO_SIZE;
+        if track_size > u16::MAX as usize {
+            return Err(invalid_input("track size exceeds the standard DSK format limit"))
+        }
+        let mut info = disk_info(DSK_SIG, self);
+        info[0x32..0x34].copy_from_slice(&(track_size as u16).to_le_bytes());
+        wr.write_all(&info)?;
+        for (cylinder, side, track) in tracks_with_position(self) {
+            let info = track_info(track, cylinder, side, false)?;
+            write_track(&mut wr, &info, track, track_size)?;
+        }
+        Ok(INFO_SIZE + track_size * self.tracks().len())
+    }
+
+    fn write_edsk<W: Write>(&self, mut wr: W) -> io::Result<usize> {
+        if self.tracks().len() > MAX_EDSK_TRACKS {
+            return Err(invalid_input("too many tracks to be stored in an extended DSK file"))
+        }
+        let mut info = disk_info(EDSK_SIG, self);
+        for (track, size) in self.tracks().iter().zip(info[0x34..].iter_mut()) {
+            if !track.sectors.is_empty() {
+                // the high byte of the track size, rounded up
+                let track_size = (track_data_size(track) + INFO_SIZE + 0xFF) >> 8;
+                if track_size > u8::MAX as usize {
+                    return Err(invalid_input("track size exceeds the extended DSK format limit"))
+                }
+                *size = track_size as u8;
+            }
+        }
+        wr.write_all(&info)?;
+        let mut bytes = INFO_SIZE;
+        for ((cylinder, side, track), &size) in tracks_with_position(self).zip(info[0x34..].iter()) {
+            if size != 0 {
+                let track_size = size as usize * 256;
+                let info = track_info(track, cylinder, side, true)?;
+                write_track(&mut wr, &info, track, track_size)?;
+                bytes += track_size;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn dsk_works() {
+        let mut disk = FloppyDisk::new_formatted(40, 1, 9, 1, 2, 0xE5);
+        disk.track_mut(2, 0).unwrap().sectors[3].data[..4].copy_from_slice(b"+3DS");
+        let mut buf = Vec::new();
+        assert_eq!(disk.write_dsk(&mut buf).unwrap(), 256 + 40 * (256 + 9 * 512));
+        assert_eq!(buf.len(), 256 + 40 * (256 + 9 * 512));
+        assert_eq!(&buf[0..34], DSK_SIG);
+        assert_eq!(&buf[0x30..0x34], [40, 1, 0x00, 0x13]);
+        let disk2 = FloppyDisk::from_dsk(Cursor::new(&buf)).unwrap();
+        assert_eq!(disk2, disk);
+        assert_eq!(&disk2.track(2, 0).unwrap().sectors[3].data[..5], b"+3DS\xE5");
+
+        let mut buf = Vec::new();
+        assert_eq!(disk.write_edsk(&mut buf).unwrap(), 256 + 40 * (256 + 9 * 512));
+        assert_eq!(&buf[0..34], EDSK_SIG);
+        assert_eq!(&buf[0x30..0x37], [40, 1, 0, 0, 0x13, 0x13, 0x13]);
+        let disk2 = FloppyDisk::from_dsk(Cursor::new(&buf)).unwrap();
+        assert_eq!(disk2, disk);
+    }
+
+    #[test]
+    fn edsk_works() {
+        let mut disk = FloppyDisk::new_formatted(3, 2, 9, 0xC1, 2, 0xE5);
+        *disk.track_mut(1, 1).unwrap() = DiskTrack::default();
+        let track = disk.track_mut(2, 0).unwrap();
+        track.sectors.truncate(2);
+        track.sectors[0].data = vec![1u8;100].into_boxed_slice();
+        track.sectors[1].st1 = 0x20;
+        track.sectors[1].st2 = 0x20;
+        assert_eq!(disk.write_dsk(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let mut buf = Vec::new();
+        let size = disk.write_edsk(&mut buf).unwrap();
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[0x30..0x3A], [3, 2, 0, 0, 0x13, 0x13, 0x13, 0x00, 0x04, 0x13]);
+        assert_eq!(size, 256 + 4 * 0x1300 + 0x400);
+        let disk2 = FloppyDisk::from_dsk(Cursor::new(&buf)).unwrap();
+        assert_eq!(disk2, disk);
+        assert!(disk2.track(1, 1).unwrap().sectors.is_empty());
+        assert!(disk2.track(2, 0).unwrap().sectors[1].has_data_error());
+
+        buf[0] = b'X';
+        assert_eq!(FloppyDisk::from_dsk(Cursor::new(&buf)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(FloppyDisk::from_dsk(Cursor::new(&buf[..100])).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
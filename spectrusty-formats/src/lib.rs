@@ -21,14 +21,117 @@
 //! ZX Spectrum related file format utilities.
 use std::io::{self, Read, Write};
 
+/// Implements the [Cpu][spectrusty_core::z80emu::Cpu] methods that access the registers and
+/// the state of the CPU, by delegating them to the wrapped CPU in the given field.
+///
+/// The remaining methods: `inc_r`, `add_r` and the execution methods must be implemented separately.
+macro_rules! delegate_cpu_state {
+    ($cpu:ident) => {
+        #[inline]
+        fn reset(&mut self) { self.$cpu.reset() }
+        #[inline]
+        fn get_pc(&self) -> u16 { self.$cpu.get_pc() }
+        #[inline]
+        fn set_pc(&mut self, pc: u16) { self.$cpu.set_pc(pc) }
+        #[inline]
+        fn get_sp(&self) -> u16 { self.$cpu.get_sp() }
+        #[inline]
+        fn set_sp(&mut self, sp: u16) { self.$cpu.set_sp(sp) }
+        #[inline]
+        fn get_acc(&self) -> u8 { self.$cpu.get_acc() }
+        #[inline]
+        fn set_acc(&mut self, val: u8) { self.$cpu.set_acc(val) }
+        #[inline]
+        fn get_flags(&self) -> CpuFlags { self.$cpu.get_flags() }
+        #[inline]
+        fn set_flags(&mut self, flags: CpuFlags) { self.$cpu.set_flags(flags) }
+        #[inline]
+        fn get_r(&self) -> u8 { self.$cpu.get_r() }
+        #[inline]
+        fn set_r(&mut self, r: u8) { self.$cpu.set_r(r) }
+        #[inline]
+        fn get_i(&self) -> u8 { self.$cpu.get_i() }
+        #[inline]
+        fn set_i(&mut self, i: u8) { self.$cpu.set_i(i) }
+        #[inline]
+        fn get_ir(&self) -> u16 { self.$cpu.get_ir() }
+        #[inline]
+        fn get_iffs(&self) -> (bool, bool) { self.$cpu.get_iffs() }
+        #[inline]
+        fn set_iffs(&mut self, iff1: bool, iff2: bool) { self.$cpu.set_iffs(iff1, iff2) }
+        #[inline]
+        fn halt(&mut self) { self.$cpu.halt() }
+        #[inline]
+        fn is_halt(&self) -> bool { self.$cpu.is_halt() }
+        #[inline]
+        fn get_im(&self) -> InterruptMode { self.$cpu.get_im() }
+        #[inline]
+        fn set_im(&mut self, im: InterruptMode) { self.$cpu.set_im(im) }
+        #[inline]
+        fn ex_af_af(&mut self) { self.$cpu.ex_af_af() }
+        #[inline]
+        fn exx(&mut self) { self.$cpu.exx() }
+        #[inline]
+        fn get_reg(&self, reg: Reg8, prefix: Option<Prefix>) -> u8 { self.$cpu.get_reg(reg, prefix) }
+        #[inline]
+        fn set_reg(&mut self, dst: Reg8, prefix: Option<Prefix>, val: u8) { self.$cpu.set_reg(dst, prefix, val) }
+        #[inline]
+        fn get_reg2(&self, src: StkReg16) -> (u8, u8) { self.$cpu.get_reg2(src) }
+        #[inline]
+        fn get_alt_reg2(&self, src: StkReg16) -> (u8, u8) { self.$cpu.get_alt_reg2(src) }
+        #[inline]
+        fn get_reg16(&self, src: StkReg16) -> u16 { self.$cpu.get_reg16(src) }
+        #[inline]
+        fn get_alt_reg16(&self, src: StkReg16) -> u16 { self.$cpu.get_alt_reg16(src) }
+        #[inline]
+        fn set_reg2(&mut self, src: StkReg16, hi: u8, lo: u8) { self.$cpu.set_reg2(src, hi, lo) }
+        #[inline]
+        fn set_reg16(&mut self, src: StkReg16, val: u16) { self.$cpu.set_reg16(src, val) }
+        #[inline]
+        fn get_index2(&self, prefix: Prefix) -> (u8, u8) { self.$cpu.get_index2(prefix) }
+        #[inline]
+        fn get_index16(&self, prefix: Prefix) -> u16 { self.$cpu.get_index16(prefix) }
+        #[inline]
+        fn set_index2(&mut self, prefix: Prefix, hi: u8, lo: u8) { self.$cpu.set_index2(prefix, hi, lo) }
+        #[inline]
+        fn set_index16(&mut self, prefix: Prefix, val: u16) { self.$cpu.set_index16(prefix, val) }
+        #[inline]
+        fn is_irq_allowed(&self) -> bool { self.$cpu.is_irq_allowed() }
+        #[inline]
+        fn is_nmi_allowed(&self) -> bool { self.$cpu.is_nmi_allowed() }
+        #[inline]
+        fn restore_iff1(&mut self) { self.$cpu.restore_iff1() }
+        #[inline]
+        fn disable_interrupts(&mut self) { self.$cpu.disable_interrupts() }
+        #[inline]
+        fn enable_interrupts(&mut self) { self.$cpu.enable_interrupts() }
+        #[inline]
+        fn is_after_ei(&self) -> bool { self.$cpu.is_after_ei() }
+        #[inline]
+        fn is_after_prefix(&self) -> bool { self.$cpu.is_after_prefix() }
+        #[inline]
+        fn get_prefix(&self) -> Option<Prefix> { self.$cpu.get_prefix() }
+    };
+}
+
 pub mod ay;
+pub mod d80;
+pub mod dck;
+pub mod dsk;
+pub mod mgt;
+pub mod trd;
+pub mod scl;
 pub mod mdr;
 pub mod sna;
 pub mod tap;
 pub mod snapshot;
 pub mod scr;
+pub mod palette;
 pub mod z80;
-// pub mod tzx;
+pub mod tzx;
+pub mod pzx;
+pub mod szx;
+pub mod rzx;
 
 /// A trait that extends [Read] with methods that ease reading from chunked files.
 pub trait ReadExactEx: Read {
@@ -0,0 +1,169 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **MGT** and **IMG** file format utilities.
+
+[Utilities][MgtDiskExt] in this module provide additional methods to the [FloppyDisk] type
+with abilities to read and write disk images in the **MGT** and **IMG** file formats.
+
+Both formats are raw images of disks used by the MGT +D and DISCiPLE interfaces. The disks have
+80 cylinders and 2 sides, each track consists of 10 sectors, 512 bytes each, numbered from 1 to 10.
+The files contain the data of all sectors ordered by tracks and then by sectors, the only difference
+between the formats is the order of tracks:
+
+* **MGT**: cylinder 0 side 0, cylinder 0 side 1, cylinder 1 side 0, ... - the sides are interleaved,
+* **IMG**: cylinder 0 side 0, cylinder 1 side 0, ... cylinder 79 side 0, cylinder 0 side 1, ... - the whole
+  first side precedes the second side.
+
+!*/
+use std::io::{self, Read, Write};
+
+pub use spectrusty_peripherals::storage::floppy::{
+    FloppyDisk, DiskTrack, DiskSector
+};
+
+/// The number of cylinders of an **MGT** disk.
+pub const MGT_CYLINDERS: u8 = 80;
+/// The number of sides of an **MGT** disk.
+pub const MGT_SIDES: u8 = 2;
+/// The number of sectors in a single **MGT** track.
+pub const MGT_SECTORS: u8 = 10;
+/// The size of a single **MGT** sector in bytes.
+pub const MGT_SECTOR_SIZE: usize = 512;
+/// The size of an **MGT** or **IMG** file in bytes.
+pub const MGT_IMAGE_SIZE: usize = MGT_CYLINDERS as usize * MGT_SIDES as usize * TRACK_SIZE;
+
+const TRACK_SIZE: usize = MGT_SECTORS as usize * MGT_SECTOR_SIZE;
+
+/// Extends [FloppyDisk] with methods for reading and writing **MGT** and **IMG** files.
+pub trait MgtDiskExt: Sized {
+    /// Creates a new, empty **MGT** disk with 80 cylinders and 2 sides.
+    fn new_mgt_formatted() -> Self;
+    /// Creates a new instance of [FloppyDisk] from the **MGT** file data read from the provided reader.
+    fn from_mgt<R: Read>(rd: R) -> io::Result<Self>;
+    /// Creates a new instance of [FloppyDisk] from the **IMG** file data read from the provided reader.
+    fn from_img<R: Read>(rd: R) -> io::Result<Self>;
+    /// Writes the disk to an **MGT** file using a provided writer.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if any of the **MGT** sectors is
+    /// missing from the disk.
+    fn write_mgt<W: Write>(&self, wr: W) -> io::Result<usize>;
+    /// Writes the disk to an **IMG** file using a provided writer.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if any of the **MGT** sectors is
+    /// missing from the disk.
+    fn write_img<W: Write>(&self, wr: W) -> io::Result<usize>;
+}
+
+fn mgt_tracks() -> impl Iterator<Item=(u8, u8)> {
+    (0..MGT_CYLINDERS).flat_map(|cyl| (0..MGT_SIDES).map(move |side| (cyl, side)))
+}
+
+fn img_tracks() -> impl Iterator<Item=(u8, u8)> {
+    (0..MGT_SIDES).flat_map(|side| (0..MGT_CYLINDERS).map(move |cyl| (cyl, side)))
+}
+
+fn read_image<R: Read, I: Iterator<Item=(u8, u8)>>(mut rd: R, tracks: I) -> io::Result<FloppyDisk> {
+    let mut data = Vec::with_capacity(MGT_IMAGE_SIZE);
+    rd.read_to_end(&mut data)?;
+    if data.len() != MGT_IMAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "MGT image size is invalid"))
+    }
+    let mut disk = FloppyDisk::new_mgt_formatted();
+    for ((cyl, side), track_data) in tracks.zip(data.chunks(TRACK_SIZE)) {
+        let track = disk.track_mut(cyl, side).unwrap();
+        for (sector, sector_data) in track.sectors.iter_mut().zip(track_data.chunks(MGT_SECTOR_SIZE)) {
+            sector.data.copy_from_slice(sector_data);
+        }
+    }
+    Ok(disk)
+}
+
+fn write_image<W: Write, I: Iterator<Item=(u8, u8)>>(
+        disk: &FloppyDisk,
+        mut wr: W,
+        tracks: I
+    ) -> io::Result<usize>
+{
+    let mut bytes = 0;
+    for (cyl, side) in tracks {
+        for id in 1..=MGT_SECTORS {
+            let data = disk.track(cyl, side)
+                .and_then(|track| track.sectors.iter().find(|s| s.id == id))
+                .map(|s| &s.data[..])
+                .filter(|data| data.len() == MGT_SECTOR_SIZE)
+                .ok_or_else(||
+                    io::Error::new(io::ErrorKind::InvalidInput, "disk can't be represented in the MGT format")
+                )?;
+            wr.write_all(data)?;
+            bytes += data.len();
+        }
+    }
+    Ok(bytes)
+}
+
+impl MgtDiskExt for FloppyDisk {
+    fn new_mgt_formatted() -> Self {
+        FloppyDisk::new_formatted(MGT_CYLINDERS, MGT_SIDES, MGT_SECTORS, 1, 2, 0)
+    }
+
+    fn from_mgt<R: Read>(rd: R) -> io::Result<Self> {
+        read_image(rd, mgt_tracks())
+    }
+
+    fn from_img<R: Read>(rd: R) -> io::Result<Self> {
+        read_image(rd, img_tracks())
+    }
+
+    fn write_mgt<W: Write>(&self, wr: W) -> io::Result<usize> {
+        write_image(self, wr, mgt_tracks())
+    }
+
+    fn write_img<W: Write>(&self, wr: W) -> io::Result<usize> {
+        write_image(self, wr, img_tracks())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn mgt_works() {
+        let mut disk = FloppyDisk::new_mgt_formatted();
+        assert_eq!(disk.cylinders(), 80);
+        assert_eq!(disk.sides(), 2);
+        disk.track_mut(0, 1).unwrap().sectors[0].data[0] = 1;
+        disk.track_mut(1, 0).unwrap().sectors[9].data[511] = 2;
+        disk.track_mut(79, 1).unwrap().sectors[9].data[511] = 3;
+
+        let mut mgt = Vec::new();
+        assert_eq!(disk.write_mgt(&mut mgt).unwrap(), MGT_IMAGE_SIZE);
+        assert_eq!(mgt.len(), 819200);
+        assert_eq!(mgt[TRACK_SIZE], 1);
+        assert_eq!(mgt[3 * TRACK_SIZE - 1], 2);
+        assert_eq!(mgt[MGT_IMAGE_SIZE - 1], 3);
+        assert_eq!(FloppyDisk::from_mgt(Cursor::new(&mgt)).unwrap(), disk);
+
+        let mut img = Vec::new();
+        assert_eq!(disk.write_img(&mut img).unwrap(), MGT_IMAGE_SIZE);
+        assert_eq!(img[80 * TRACK_SIZE], 1);
+        assert_eq!(img[2 * TRACK_SIZE - 1], 2);
+        assert_eq!(img[MGT_IMAGE_SIZE - 1], 3);
+        assert_eq!(FloppyDisk::from_img(Cursor::new(&img)).unwrap(), disk);
+
+        assert_eq!(FloppyDisk::from_mgt(Cursor::new(&mgt[1..])).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        disk.track_mut(40, 0).unwrap().sectors.pop();
+        assert_eq!(disk.write_mgt(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(disk.write_img(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
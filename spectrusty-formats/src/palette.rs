@@ -0,0 +1,326 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! Color palette file utilities.
+
+[ColorPalette]s can be loaded from and saved to the following text formats:
+
+* **GIMP** palettes (`.gpl`): a `GIMP Palette` line, optional `Name:` and `Columns:` lines and comments,
+  followed by lines of colors, each with 3 decimal components and an optional color name.
+* **JASC** palettes (`.pal`): `JASC-PAL` and `0100` lines, the number of colors line, followed by lines
+  of colors, each with 3 decimal components.
+* **JSON** lists: an array of colors, each color being either a `"#RRGGBB"` string or an array
+  of 3 decimal components, e.g. `["#000000", [0, 0, 215], ...]`.
+
+The colors are in the order of ZX Spectrum color indexes: 8 normal colors followed by 8 bright colors.
+Only the first 16 colors of a file are used.
+*/
+use core::fmt::Write as _;
+use std::io::{self, Read, Write};
+
+pub use spectrusty_core::video::palette::ColorPalette;
+
+const GPL_SIGNATURE: &str = "GIMP Palette";
+const JASC_SIGNATURE: &str = "JASC-PAL";
+const JASC_VERSION: &str = "0100";
+const NUM_COLORS: usize = 16;
+const COLOR_NAMES: [&str; 8] = ["black", "blue", "red", "magenta", "green", "cyan", "yellow", "white"];
+
+/// Attempts to read a palette file in any of the supported formats from the `src`.
+///
+/// The format is recognized by the file content.
+///
+/// # Errors
+/// This function will return an error if the file is not recognized as a supported palette file
+/// or if it has less than 16 colors. Other errors may also be returned from attempts to read the file.
+pub fn load_palette<R: Read>(src: R) -> io::Result<ColorPalette> {
+    let text = read_text(src)?;
+    let start = text.trim_start();
+    if start.starts_with(GPL_SIGNATURE) {
+        parse_gpl(&text)
+    }
+    else if start.starts_with(JASC_SIGNATURE) {
+        parse_jasc_pal(&text)
+    }
+    else if start.starts_with('[') {
+        parse_json(&text)
+    }
+    else {
+        invalid_data("Unrecognized palette file format")
+    }
+}
+
+/// Attempts to read the **GIMP** palette file from the `src`.
+///
+/// # Errors
+/// This function will return an error if the file is not recognized as a **GIMP** palette file
+/// or if it has less than 16 colors. Other errors may also be returned from attempts to read the file.
+pub fn load_gpl<R: Read>(src: R) -> io::Result<ColorPalette> {
+    parse_gpl(&read_text(src)?)
+}
+
+/// Attempts to read the **JASC** palette file from the `src`.
+///
+/// # Errors
+/// This function will return an error if the file is not recognized as a **JASC** palette file
+/// or if it has less than 16 colors. Other errors may also be returned from attempts to read the file.
+pub fn load_jasc_pal<R: Read>(src: R) -> io::Result<ColorPalette> {
+    parse_jasc_pal(&read_text(src)?)
+}
+
+/// Attempts to read the **JSON** list of colors from the `src`.
+///
+/// # Errors
+/// This function will return an error if the file is not recognized as a **JSON** list of colors
+/// or if it has less than 16 colors. Other errors may also be returned from attempts to read the file.
+pub fn load_json<R: Read>(src: R) -> io::Result<ColorPalette> {
+    parse_json(&read_text(src)?)
+}
+
+/// Writes the `palette` as the **GIMP** palette file with the given `name` into the `dst`.
+///
+/// # Errors
+/// This function may return an error from attempts to write the file.
+pub fn save_gpl<W: Write>(palette: &ColorPalette, name: &str, mut dst: W) -> io::Result<()> {
+    let mut text = String::new();
+    writeln!(text, "{}", GPL_SIGNATURE).unwrap();
+    writeln!(text, "Name: {}", name.lines().next().unwrap_or("")).unwrap();
+    writeln!(text, "Columns: 8").unwrap();
+    writeln!(text, "#").unwrap();
+    for (index, [r, g, b]) in palette.colors.iter().enumerate() {
+        let bright = if index < 8 { "" } else { "bright " };
+        writeln!(text, "{:3} {:3} {:3}\t{}{}", r, g, b, bright, COLOR_NAMES[index & 7]).unwrap();
+    }
+    dst.write_all(text.as_bytes())
+}
+
+/// Writes the `palette` as the **JASC** palette file into the `dst`.
+///
+/// # Errors
+/// This function may return an error from attempts to write the file.
+pub fn save_jasc_pal<W: Write>(palette: &ColorPalette, mut dst: W) -> io::Result<()> {
+    let mut text = String::new();
+    // the format requires CRLF line endings
+    write!(text, "{}\r\n{}\r\n{}\r\n", JASC_SIGNATURE, JASC_VERSION, NUM_COLORS).unwrap();
+    for [r, g, b] in palette.colors.iter() {
+        write!(text, "{} {} {}\r\n", r, g, b).unwrap();
+    }
+    dst.write_all(text.as_bytes())
+}
+
+/// Writes the `palette` as the **JSON** list of `"#RRGGBB"` colors into the `dst`.
+///
+/// # Errors
+/// This function may return an error from attempts to write the file.
+pub fn save_json<W: Write>(palette: &ColorPalette, mut dst: W) -> io::Result<()> {
+    let mut text = String::from("[");
+    for (index, [r, g, b]) in palette.colors.iter().enumerate() {
+        let sep = if index == 0 { "" } else { "," };
+        write!(text, "{}\n  \"#{:02X}{:02X}{:02X}\"", sep, r, g, b).unwrap();
+    }
+    text.push_str("\n]\n");
+    dst.write_all(text.as_bytes())
+}
+
+fn invalid_data<T>(msg: &'static str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn read_text<R: Read>(mut src: R) -> io::Result<String> {
+    let mut text = String::new();
+    src.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn palette_from_colors<I: IntoIterator<Item=io::Result<[u8;3]>>>(colors: I) -> io::Result<ColorPalette> {
+    let mut palette = ColorPalette::default();
+    let mut count = 0;
+    for (target, color) in palette.colors.iter_mut().zip(colors) {
+        *target = color?;
+        count += 1;
+    }
+    if count != NUM_COLORS {
+        return invalid_data("Too few colors in the palette")
+    }
+    Ok(palette)
+}
+
+fn parse_component(value: Option<&str>) -> io::Result<u8> {
+    match value.map(str::parse) {
+        Some(Ok(value)) => Ok(value),
+        _ => invalid_data("Invalid palette color component")
+    }
+}
+
+/// Parses 3 decimal color components separated by whitespace, ignoring the rest of the `line`.
+fn parse_color_line(line: &str) -> io::Result<[u8;3]> {
+    let mut values = line.split_whitespace();
+    Ok([parse_component(values.next())?,
+        parse_component(values.next())?,
+        parse_component(values.next())?])
+}
+
+fn parse_gpl(text: &str) -> io::Result<ColorPalette> {
+    let mut lines = text.trim_start().lines();
+    if lines.next().map(str::trim_end) != Some(GPL_SIGNATURE) {
+        return invalid_data("Not a GIMP palette file")
+    }
+    palette_from_colors(lines.map(str::trim)
+                             .filter(|line| !(line.is_empty() || line.starts_with('#') ||
+                                              line.starts_with("Name:") || line.starts_with("Columns:")))
+                             .map(parse_color_line))
+}
+
+fn parse_jasc_pal(text: &str) -> io::Result<ColorPalette> {
+    let mut lines = text.trim_start().lines().map(str::trim);
+    if lines.next() != Some(JASC_SIGNATURE) || lines.next() != Some(JASC_VERSION) {
+        return invalid_data("Not a JASC palette file")
+    }
+    let count: usize = match lines.next().map(str::parse) {
+        Some(Ok(count)) => count,
+        _ => return invalid_data("Invalid JASC palette number of colors")
+    };
+    palette_from_colors(lines.take(count).map(parse_color_line))
+}
+
+fn parse_json(text: &str) -> io::Result<ColorPalette> {
+    let mut parser = JsonColors { text: text.trim() };
+    if !parser.consume('[') {
+        return invalid_data("Not a JSON list of colors")
+    }
+    let mut colors = Vec::with_capacity(NUM_COLORS);
+    if !parser.consume(']') {
+        loop {
+            colors.push(parser.parse_color()?);
+            if parser.consume(']') {
+                break
+            }
+            if !parser.consume(',') {
+                return invalid_data("Invalid JSON list of colors")
+            }
+        }
+    }
+    if !parser.text.is_empty() {
+        return invalid_data("Invalid JSON list of colors")
+    }
+    palette_from_colors(colors.into_iter().map(Ok))
+}
+
+struct JsonColors<'a> {
+    text: &'a str
+}
+
+impl<'a> JsonColors<'a> {
+    /// Consumes the `token` preceded by whitespace, returns `true` if the `token` was found.
+    fn consume(&mut self, token: char) -> bool {
+        let text = self.text.trim_start();
+        if text.starts_with(token) {
+            self.text = &text[token.len_utf8()..];
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn parse_color(&mut self) -> io::Result<[u8;3]> {
+        if self.consume('"') {
+            let end = match self.text.find('"') {
+                Some(end) => end,
+                None => return invalid_data("Invalid JSON string")
+            };
+            let (color, rest) = self.text.split_at(end);
+            self.text = &rest[1..];
+            parse_hex_color(color)
+        }
+        else if self.consume('[') {
+            let mut color = [0u8; 3];
+            for (index, component) in color.iter_mut().enumerate() {
+                if index != 0 && !self.consume(',') {
+                    return invalid_data("Invalid JSON color components")
+                }
+                let text = self.text.trim_start();
+                let end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+                *component = parse_component(Some(&text[..end]))?;
+                self.text = &text[end..];
+            }
+            if !self.consume(']') {
+                return invalid_data("Invalid JSON color components")
+            }
+            Ok(color)
+        }
+        else {
+            invalid_data("Invalid JSON color")
+        }
+    }
+}
+
+/// Parses a `#RRGGBB` color.
+fn parse_hex_color(color: &str) -> io::Result<[u8;3]> {
+    let hex = color.trim();
+    if hex.len() != 7 || !hex.starts_with('#') || !hex[1..].bytes().all(|c| c.is_ascii_hexdigit()) {
+        return invalid_data("Invalid hexadecimal color")
+    }
+    let component = |n: usize| u8::from_str_radix(&hex[n..n + 2], 16).unwrap();
+    Ok([component(1), component(3), component(5)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_files_work() {
+        let mut colors = ColorPalette::SPECTRUSTY.colors;
+        for color in colors[..8].iter_mut().flatten().filter(|c| **c != 0) {
+            *color = 0xCD;
+        }
+        let palette = ColorPalette::new(colors);
+        let mut gpl = Vec::new();
+        save_gpl(&palette, "Issue 3", &mut gpl).unwrap();
+        assert!(gpl.starts_with(b"GIMP Palette\nName: Issue 3\nColumns: 8\n#\n  0   0   0\tblack\n"));
+        assert_eq!(load_gpl(&gpl[..]).unwrap(), palette);
+        assert_eq!(load_palette(&gpl[..]).unwrap(), palette);
+        let mut pal = Vec::new();
+        save_jasc_pal(&palette, &mut pal).unwrap();
+        assert!(pal.starts_with(b"JASC-PAL\r\n0100\r\n16\r\n0 0 0\r\n0 0 205\r\n"));
+        assert_eq!(load_jasc_pal(&pal[..]).unwrap(), palette);
+        assert_eq!(load_palette(&pal[..]).unwrap(), palette);
+        let mut json = Vec::new();
+        save_json(&palette, &mut json).unwrap();
+        assert!(json.starts_with(b"[\n  \"#000000\",\n  \"#0000CD\","));
+        assert_eq!(load_json(&json[..]).unwrap(), palette);
+        assert_eq!(load_palette(&json[..]).unwrap(), palette);
+        // mixed color notations
+        let json = r##"[ "#000000", [0,0,205], [ 205 , 0, 0 ], "#cd00cd", "#00CD00", "#00CDCD", "#CDCD00", "#CDCDCD",
+                         "#000000", "#0000FF", "#FF0000", "#FF00FF", "#00FF00", "#00FFFF", "#FFFF00", "#FFFFFF",
+                         "#123456" ]"##;
+        assert_eq!(load_json(json.as_bytes()).unwrap(), palette);
+        // more colors, comments and names in a GIMP palette
+        let mut gpl = String::from("GIMP Palette\r\nName: Test\r\n# comment\r\n\r\n");
+        for [r, g, b] in palette.colors.iter().chain(&[[1, 2, 3]]) {
+            gpl.push_str(&format!("{} {} {} Untitled\r\n", r, g, b));
+        }
+        assert_eq!(load_gpl(gpl.as_bytes()).unwrap(), palette);
+    }
+
+    #[test]
+    fn palette_files_errors_work() {
+        assert_eq!(load_palette(&b"ZX Palette"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_gpl(&b"GIMP Palette\n0 0 0\n"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_gpl(&b"GIMP Palette\n0 0\n"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_gpl(&b"GIMP Palette\n0 0 256\n"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_jasc_pal(&b"JASC-PAL\n0100\n1\n0 0 0\n"[..]).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(load_jasc_pal(&b"JASC-PAL\n0200\n"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_json(&b"[]"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_json(&b"[\"#00000\"]"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_json(&b"[[0,0]]"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_json(&b"[\"#000000\",]"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(load_json(&b"[\"#000000\"] x"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
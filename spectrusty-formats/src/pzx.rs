@@ -0,0 +1,361 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **PZX** file format utilities.
+
+# PZX format
+
+A **PZX** file consists of blocks, each starting with an 8 byte header:
+
+| offset | size | description                              |
+|--------|------|------------------------------------------|
+|    0   |    4 | block tag (4 ASCII characters)           |
+|    4   |    4 | size of the block data (LSB first)       |
+|    8   |  ... | block data                               |
+
+The file must begin with the `PZXT` header block. The signal is described by only 3 kinds of blocks:
+
+* `PULS` - a sequence of pulses of arbitrary lengths,
+* `DATA` - data bits, each bit encoded with its own sequence of pulses,
+* `PAUS` - a pause with the given signal level.
+
+Additionally `BRWS` blocks mark the browse points and `STOP` blocks stop the tape.
+Blocks with unknown tags are preserved but otherwise ignored.
+
+For the detailed description of each block, see the
+[PZX format specification](http://zxds.raxoft.cz/docs/pzx.txt).
+
+## Interpreting *PZX* files
+
+[PzxBlock] represents a single parsed *PZX* block. [PzxReadIter] parses blocks directly from
+any [reader][Read]. To read the whole file at once use [PzxTape::read_from] or [read_pzx].
+
+```no_run
+use spectrusty_formats::pzx::*;
+
+let pzxfile = std::fs::File::open("some.pzx")?;
+let tape = read_pzx(pzxfile)?;
+for (index, block) in tape.blocks.iter().enumerate() {
+    println!("{:3}: {}", index, block);
+}
+# Ok::<(), std::io::Error>(())
+```
+
+### *TAPE* pulses
+
+[PzxPulseIter] encodes the blocks as *TAPE* pulse intervals. The pulses can be fed directly into
+the emulator via [EarIn::feed_ear_in].
+
+```no_run
+use spectrusty::{memory::Memory48k, chip::{ula::UlaPAL, EarIn}};
+
+let mut ula = UlaPAL::<Memory48k>::default();
+//...
+use spectrusty_formats::pzx::*;
+
+let pzxfile = std::fs::File::open("some.pzx")?;
+let mut pulse_iter = read_pzx_pulse_iter(pzxfile)?;
+
+// feed the buffer fragmentarily before each emulated frame
+ula.feed_ear_in(&mut pulse_iter, Some(1));
+
+// the tape may be stopped by a block
+if pulse_iter.is_stopped() {
+    // ... and resumed later
+    pulse_iter.resume();
+}
+# Ok::<(), std::io::Error>(())
+```
+
+### Writing *PZX* files
+
+[PzxWriter] writes blocks and encodes *TAPE* pulse intervals, e.g. from [MicOut::mic_out_pulse_iter],
+as *PZX* blocks.
+
+[EarIn::feed_ear_in]: spectrusty_core::chip::EarIn::feed_ear_in
+[MicOut::mic_out_pulse_iter]: spectrusty_core::chip::MicOut::mic_out_pulse_iter
+*/
+use core::fmt;
+use std::borrow::Cow;
+use std::io::{Read, Result};
+
+use crate::tap::TapChunk;
+
+mod pulse;
+mod read;
+mod write;
+pub use pulse::*;
+pub use read::*;
+pub use write::*;
+
+/// The tag of the *PZX* header block.
+pub const PZX_HEADER_TAG: &[u8;4] = b"PZXT";
+/// The tag of the *PZX* pulse sequence block.
+pub const PZX_PULSES_TAG: &[u8;4] = b"PULS";
+/// The tag of the *PZX* data block.
+pub const PZX_DATA_TAG: &[u8;4] = b"DATA";
+/// The tag of the *PZX* pause block.
+pub const PZX_PAUSE_TAG: &[u8;4] = b"PAUS";
+/// The tag of the *PZX* browse point block.
+pub const PZX_BROWSE_TAG: &[u8;4] = b"BRWS";
+/// The tag of the *PZX* stop tape block.
+pub const PZX_STOP_TAG: &[u8;4] = b"STOP";
+/// The major version number of the supported *PZX* format.
+pub const PZX_MAJOR_VERSION: u8 = 1;
+/// The minor version number of the supported *PZX* format.
+pub const PZX_MINOR_VERSION: u8 = 0;
+/// The maximum duration of a single pulse or a pause in T-states.
+pub const PZX_MAX_DURATION: u32 = 0x7FFF_FFFF;
+/// The maximum repetition count of a single pulse.
+pub const PZX_MAX_REPEAT: u16 = 0x7FFF;
+
+/// Represents a parsed *PZX* block.
+///
+/// Text fields are stored as they appear in the file (usually as UTF-8 characters).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PzxBlock {
+    /// The header block.
+    Header {
+        /// The major version number of the format.
+        major: u8,
+        /// The minor version number of the format.
+        minor: u8,
+        /// The tape title followed by pairs of: info type and info text, e.g. `"Author"`, `"Joe Bloggs"`.
+        info: Box<[Box<[u8]>]>
+    },
+    /// A sequence of pulses of: `(repeat count, duration in T-states)`.
+    ///
+    /// The signal level is low at the start of the block. A pulse of zero duration may be used
+    /// to change the level without any delay.
+    Pulses(Box<[(u16, u32)]>),
+    /// Data block, each bit is encoded with the sequence of pulses.
+    Data {
+        /// The initial signal level: `true` for high, `false` for low.
+        level: bool,
+        /// The number of data bits (31-bit).
+        bits: u32,
+        /// The duration of the tail pulse after the last bit in T-states or 0 if there is none.
+        tail: u16,
+        /// The pulse sequence of the ZERO bit in T-states.
+        zero: Box<[u16]>,
+        /// The pulse sequence of the ONE bit in T-states.
+        one: Box<[u16]>,
+        /// Data bits, most significant bit first.
+        data: Box<[u8]>
+    },
+    /// A pause with the constant signal level.
+    Pause {
+        /// The signal level: `true` for high, `false` for low.
+        level: bool,
+        /// The duration of the pause in T-states (31-bit).
+        duration: u32
+    },
+    /// The browse point with its description.
+    Browse(Box<[u8]>),
+    /// Stop the tape.
+    Stop {
+        /// `true` if the tape should be stopped only in 48k mode.
+        only48k: bool
+    },
+    /// A block with an unknown tag.
+    Unknown {
+        /// The block tag.
+        tag: [u8;4],
+        /// Raw block data.
+        data: Box<[u8]>
+    }
+}
+
+/// Represents the whole content of a *PZX* file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PzxTape {
+    /// The tape blocks, beginning with the [PzxBlock::Header].
+    pub blocks: Vec<PzxBlock>
+}
+
+/// Reads the whole *PZX* file from the given reader.
+pub fn read_pzx<R: Read>(rd: R) -> Result<PzxTape> {
+    PzxTape::read_from(rd)
+}
+
+/// Reads the whole *PZX* file from the given reader and creates a [PzxPulseIter] from its blocks.
+pub fn read_pzx_pulse_iter<R: Read>(rd: R) -> Result<PzxPulseIter<Vec<PzxBlock>>> {
+    read_pzx(rd).map(|tape| PzxPulseIter::new(tape.blocks))
+}
+
+impl Default for PzxBlock {
+    /// Returns the header block of the supported version without any info.
+    fn default() -> Self {
+        PzxBlock::Header { major: PZX_MAJOR_VERSION, minor: PZX_MINOR_VERSION, info: Box::new([]) }
+    }
+}
+
+impl PzxBlock {
+    /// Returns the tag of this block.
+    pub fn tag(&self) -> &[u8;4] {
+        match self {
+            PzxBlock::Header {..}  => PZX_HEADER_TAG,
+            PzxBlock::Pulses(..)   => PZX_PULSES_TAG,
+            PzxBlock::Data {..}    => PZX_DATA_TAG,
+            PzxBlock::Pause {..}   => PZX_PAUSE_TAG,
+            PzxBlock::Browse(..)   => PZX_BROWSE_TAG,
+            PzxBlock::Stop {..}    => PZX_STOP_TAG,
+            PzxBlock::Unknown { tag, .. } => tag
+        }
+    }
+    /// Returns `true` if this block produces any *TAPE* signal.
+    pub fn is_signal(&self) -> bool {
+        matches!(self, PzxBlock::Pulses(..)|PzxBlock::Data {..}|PzxBlock::Pause {..})
+    }
+    /// Returns the content of the data block as a [TapChunk] if it consists of whole bytes.
+    ///
+    /// Returns `None` for any other block type.
+    pub fn as_tap_chunk(&self) -> Option<TapChunk<&[u8]>> {
+        match self {
+            PzxBlock::Data { bits, data, .. } if bits & 7 == 0 => {
+                Some(TapChunk::from(&data[..(bits >> 3) as usize]))
+            }
+            _ => None
+        }
+    }
+}
+
+fn text(text: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(text)
+}
+
+impl fmt::Display for PzxBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PzxBlock::Header { major, minor, info } => {
+                write!(f, "PZX {}.{}", major, minor)?;
+                match info.first() {
+                    Some(title) if !title.is_empty() => write!(f, ": {}", text(title)),
+                    _ => Ok(())
+                }
+            }
+            PzxBlock::Pulses(pulses) => {
+                let count: u32 = pulses.iter().map(|&(count, _)| u32::from(count)).sum();
+                write!(f, "Pulses: {}", count)
+            }
+            PzxBlock::Data { bits, .. } => {
+                match self.as_tap_chunk() {
+                    Some(chunk) => write!(f, "Data: {}", chunk),
+                    None => write!(f, "Data: {} bits", bits)
+                }
+            }
+            PzxBlock::Pause { level, duration } => {
+                write!(f, "Pause: {} T {}", duration, if *level { "high" } else { "low" })
+            }
+            PzxBlock::Browse(txt) => write!(f, "Browse: {}", text(txt)),
+            PzxBlock::Stop { only48k: false } => write!(f, "Stop the tape"),
+            PzxBlock::Stop { only48k: true } => write!(f, "Stop the tape in 48k mode"),
+            PzxBlock::Unknown { tag, data } => {
+                write!(f, "(unknown {}: {} bytes)", text(tag), data.len())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use crate::tap::{*, pulse::consts::*};
+    use super::*;
+
+    fn put_block(pzx: &mut Vec<u8>, tag: &[u8;4], body: &[u8]) {
+        pzx.extend_from_slice(tag);
+        pzx.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        pzx.extend_from_slice(body);
+    }
+
+    /// Converts the content of a TAP file to the PZX file with the ROM timings.
+    pub(super) fn tap_to_pzx(tap: &[u8]) -> Vec<u8> {
+        let mut pzx = Vec::new();
+        put_block(&mut pzx, PZX_HEADER_TAG, &[PZX_MAJOR_VERSION, PZX_MINOR_VERSION]);
+        for chunk in TapChunkIter::from(&tap) {
+            let data = chunk.as_ref();
+            let pilot = if data[0] & 0x80 == 0 { LEAD_PULSES_HEAD } else { LEAD_PULSES_DATA };
+            let mut body = Vec::new();
+            body.extend_from_slice(&(0x8000 | pilot).to_le_bytes());
+            body.extend_from_slice(&(LEAD_PULSE_LENGTH.get() as u16).to_le_bytes());
+            body.extend_from_slice(&(SYNC_PULSE1_LENGTH.get() as u16).to_le_bytes());
+            body.extend_from_slice(&(SYNC_PULSE2_LENGTH.get() as u16).to_le_bytes());
+            put_block(&mut pzx, PZX_PULSES_TAG, &body);
+            let mut body = Vec::new();
+            // the odd number of pulses leaves the signal high
+            body.extend_from_slice(&(0x8000_0000 | (data.len() as u32 * 8)).to_le_bytes());
+            body.extend_from_slice(&945u16.to_le_bytes());
+            body.extend_from_slice(&[2, 2]);
+            for pulse in [ZERO_PULSE_LENGTH, ZERO_PULSE_LENGTH, ONE_PULSE_LENGTH, ONE_PULSE_LENGTH].iter() {
+                body.extend_from_slice(&(pulse.get() as u16).to_le_bytes());
+            }
+            body.extend_from_slice(data);
+            put_block(&mut pzx, PZX_DATA_TAG, &body);
+            put_block(&mut pzx, PZX_PAUSE_TAG, &3_500_000u32.to_le_bytes());
+        }
+        pzx
+    }
+
+    #[test]
+    fn read_pzx_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let mut pzx = tap_to_pzx(&tap);
+        put_block(&mut pzx, PZX_HEADER_TAG, b"\x01\x00Title\0Author\0Joe Bloggs");
+        put_block(&mut pzx, PZX_BROWSE_TAG, b"Level 1");
+        put_block(&mut pzx, PZX_PULSES_TAG, &[0x02, 0x80, 0x9b, 0x02, 0x01, 0x80, 0x00, 0x00,
+                                               0x01, 0x80, 0x01, 0x80, 0x00, 0x00]);
+        put_block(&mut pzx, PZX_STOP_TAG, &[1, 0]);
+        put_block(&mut pzx, PZX_STOP_TAG, &[0, 0]);
+        put_block(&mut pzx, b"XTRA", &[0xaa, 0x55]);
+        let tape = read_pzx(Cursor::new(pzx))?;
+        let res: Vec<_> = tape.blocks.iter().map(|block| format!("{}", block)).collect();
+        assert_eq!(res, [
+            "PZX 1.0",
+            "Pulses: 8065",
+            "Data: Program: \"HelloWorld\" LINE 10",
+            "Pause: 3500000 T low",
+            "Pulses: 3225",
+            "Data: (data 6)",
+            "Pause: 3500000 T low",
+            "Pulses: 8065",
+            "Data: Number array: \"a(10)\" DATA A()",
+            "Pause: 3500000 T low",
+            "Pulses: 3225",
+            "Data: (data 53)",
+            "Pause: 3500000 T low",
+            "Pulses: 8065",
+            "Data: Character array: \"weekdays\" DATA W$()",
+            "Pause: 3500000 T low",
+            "Pulses: 3225",
+            "Data: (data 26)",
+            "Pause: 3500000 T low",
+            "PZX 1.0: Title",
+            "Browse: Level 1",
+            "Pulses: 4",
+            "Stop the tape in 48k mode",
+            "Stop the tape",
+            "(unknown XTRA: 2 bytes)"]);
+        assert_eq!(PzxBlock::Pulses(vec![(2, 667), (1, 0), (1, 0x1_0000)].into()), tape.blocks[21]);
+        assert_eq!(Some(&tap[2..21]), tape.blocks[2].as_tap_chunk().map(TapChunk::into_inner));
+        assert_eq!(b"XTRA", tape.blocks[24].tag());
+        match &tape.blocks[19] {
+            PzxBlock::Header { info, .. } => assert_eq!(info.len(), 3),
+            _ => panic!("expected a header block")
+        }
+        // the header block must be first
+        let mut pzx = Vec::new();
+        put_block(&mut pzx, PZX_BROWSE_TAG, b"");
+        assert!(read_pzx(Cursor::new(pzx)).is_err());
+        let mut pzx = Vec::new();
+        put_block(&mut pzx, PZX_HEADER_TAG, &[2, 0]);
+        assert!(read_pzx(Cursor::new(pzx)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::mem;
+use core::num::NonZeroU32;
+
+use super::*;
+
+/// Implements an iterator of T-state pulse intervals over the slice of [PzxBlock]s.
+///
+/// Anything that implements `AsRef<[PzxBlock]>` can be used as `T` (e.g. `&[PzxBlock]` or `Vec<PzxBlock>`).
+///
+/// Adjacent intervals of the same signal level are merged into a single pulse. The tape stops after
+/// the *Stop* block or, only if [PzxPulseIter::mode48k] is `true`, after the *Stop* block with the 48k
+/// flag. While the tape is stopped, [Iterator::next] returns `None`. Use [PzxPulseIter::resume] to continue.
+#[derive(Clone, Debug)]
+pub struct PzxPulseIter<T> {
+    /// Determines if the *Stop* blocks with the 48k flag should stop the tape.
+    pub mode48k: bool,
+    index: usize,
+    state: PulseState,
+    /// the signal level of the pending interval
+    level: bool,
+    /// the signal level after the last interval
+    signal: bool,
+    stopped: bool,
+    pending: u32,
+    blocks: T
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PulseState {
+    Enter,
+    Pulses { offset: usize, repeat: u16, level: bool },
+    Data { bit: u32, step: usize, level: bool },
+    Leave,
+    Done
+}
+
+impl<T> PzxPulseIter<T> {
+    /// Returns the wrapped blocks container.
+    pub fn into_inner(self) -> T {
+        self.blocks
+    }
+    /// Returns a reference to the wrapped blocks container.
+    pub fn get_ref(&self) -> &T {
+        &self.blocks
+    }
+    /// Returns the index of the currently processed block.
+    ///
+    /// The returned value may be equal to the number of blocks if the tape has ended.
+    pub fn block_index(&self) -> usize {
+        self.index
+    }
+    /// Returns the current signal level: `true` is high, `false` is low.
+    pub fn level(&self) -> bool {
+        self.level
+    }
+    /// Returns `true` if the tape has been stopped by one of the blocks.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+    /// Returns `true` if there are no more pulses to emit.
+    pub fn is_done(&self) -> bool {
+        self.state == PulseState::Done && self.pending == 0
+    }
+    /// Resumes the tape stopped by one of the blocks.
+    pub fn resume(&mut self) {
+        self.stopped = false;
+    }
+}
+
+impl<T: AsRef<[PzxBlock]>> PzxPulseIter<T> {
+    /// Creates a new iterator from the given blocks container.
+    pub fn new(blocks: T) -> Self {
+        let mut iter = PzxPulseIter {
+            mode48k: false,
+            index: 0,
+            state: PulseState::Enter,
+            level: false,
+            signal: false,
+            stopped: false,
+            pending: 0,
+            blocks
+        };
+        iter.rewind();
+        iter
+    }
+    /// Rewinds the tape to the first block.
+    pub fn rewind(&mut self) {
+        self.jump_to_block(0);
+    }
+    /// Rewinds or forwards the tape to the beginning of the block at the given `index`.
+    ///
+    /// Resumes the tape and resets the signal level to low.
+    pub fn jump_to_block(&mut self, index: usize) {
+        self.index = index;
+        self.state = if index < self.blocks.as_ref().len() {
+            PulseState::Enter
+        }
+        else {
+            PulseState::Done
+        };
+        self.stopped = false;
+        self.level = false;
+        self.signal = false;
+        self.pending = 0;
+    }
+    /// Returns the next interval of the constant signal: `(duration, level)`.
+    fn next_interval(&mut self) -> Option<(u32, bool)> {
+        loop {
+            if self.stopped {
+                return None
+            }
+            let block = match self.blocks.as_ref().get(self.index) {
+                Some(block) => block,
+                None => {
+                    self.state = PulseState::Done;
+                    return None
+                }
+            };
+            match (self.state, block) {
+                (PulseState::Done, _) => return None,
+                (PulseState::Enter, PzxBlock::Pulses(..)) => {
+                    self.state = PulseState::Pulses { offset: 0, repeat: 0, level: false };
+                }
+                (PulseState::Enter, &PzxBlock::Data { level, .. }) => {
+                    self.state = PulseState::Data { bit: 0, step: 0, level };
+                }
+                (PulseState::Enter, &PzxBlock::Pause { level, duration }) => {
+                    self.state = PulseState::Leave;
+                    self.signal = level;
+                    return Some((duration, level))
+                }
+                (PulseState::Enter, &PzxBlock::Stop { only48k }) => {
+                    self.state = PulseState::Leave;
+                    self.stopped = !only48k || self.mode48k;
+                }
+                (PulseState::Enter, _)|(PulseState::Leave, _) => {
+                    self.index += 1;
+                    self.state = if self.index < self.blocks.as_ref().len() {
+                        PulseState::Enter
+                    }
+                    else {
+                        PulseState::Done
+                    };
+                }
+                (PulseState::Pulses { offset, repeat, level }, PzxBlock::Pulses(pulses)) => {
+                    match pulses.get(offset) {
+                        Some(&(count, duration)) if repeat < count => {
+                            self.state = PulseState::Pulses { offset, repeat: repeat + 1, level: !level };
+                            self.signal = !level;
+                            return Some((duration, level))
+                        }
+                        Some(..) => {
+                            self.state = PulseState::Pulses { offset: offset + 1, repeat: 0, level };
+                        }
+                        None => self.state = PulseState::Leave
+                    }
+                }
+                (PulseState::Data { bit, step, level }, PzxBlock::Data { bits, tail, zero, one, data, .. }) => {
+                    if bit < *bits {
+                        let byte = data.get((bit >> 3) as usize).copied().unwrap_or_default();
+                        let sequence = if byte & (0x80 >> (bit & 7)) != 0 { one } else { zero };
+                        match sequence.get(step) {
+                            Some(&pulse) => {
+                                self.state = PulseState::Data { bit, step: step + 1, level: !level };
+                                self.signal = !level;
+                                return Some((pulse.into(), level))
+                            }
+                            None => {
+                                self.state = PulseState::Data { bit: bit + 1, step: 0, level };
+                            }
+                        }
+                    }
+                    else {
+                        self.state = PulseState::Leave;
+                        if *tail != 0 {
+                            self.signal = !level;
+                            return Some(((*tail).into(), level))
+                        }
+                    }
+                }
+                _ => self.state = PulseState::Leave
+            }
+        }
+    }
+}
+
+impl<T: AsRef<[PzxBlock]>> Iterator for PzxPulseIter<T> {
+    type Item = NonZeroU32;
+
+    fn next(&mut self) -> Option<NonZeroU32> {
+        loop {
+            match self.next_interval() {
+                Some((0, _)) => {}
+                Some((duration, level)) => {
+                    if level == self.level || self.pending == 0 {
+                        self.level = level;
+                        self.pending = self.pending.saturating_add(duration);
+                    }
+                    else {
+                        let pulse = mem::replace(&mut self.pending, duration);
+                        self.level = level;
+                        return NonZeroU32::new(pulse)
+                    }
+                }
+                None => {
+                    // the edge after the last pulse
+                    if self.level != self.signal {
+                        self.level = self.signal;
+                        if let Some(pulse) = NonZeroU32::new(mem::take(&mut self.pending)) {
+                            return Some(pulse)
+                        }
+                    }
+                    if self.state == PulseState::Done {
+                        self.pending = 0;
+                    }
+                    return None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use crate::tap::write_tap;
+    use super::*;
+    use super::super::tests::tap_to_pzx;
+
+    fn pulses<T: AsRef<[PzxBlock]>>(iter: &mut PzxPulseIter<T>) -> Vec<u32> {
+        iter.by_ref().map(NonZeroU32::get).collect()
+    }
+
+    #[test]
+    fn pzx_pulse_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let mut pulse_iter = read_pzx_pulse_iter(Cursor::new(tap_to_pzx(&tap)))?;
+        let mut tap_writer = write_tap(Cursor::new(Vec::new()))?;
+        assert_eq!(5, tap_writer.write_pulses_as_tap_chunks(&mut pulse_iter)?);
+        assert_eq!(1, tap_writer.end_pulse_chunk()?);
+        assert!(pulse_iter.is_done());
+        assert_eq!(19, pulse_iter.block_index());
+        let tgt: Vec<u8> = tap_writer.into_inner().into_inner().into_inner();
+        assert_eq!(tap, tgt);
+        Ok(())
+    }
+
+    #[test]
+    fn pzx_signal_blocks_work() {
+        let blocks = vec![
+            PzxBlock::default(),
+            PzxBlock::Pulses(vec![(2, 100), (1, 0), (1, 200), (1, 0), (1, 0), (1, 300)].into()),
+            PzxBlock::Data { level: true, bits: 3, tail: 50, zero: Box::new([10, 10]), one: Box::new([20]),
+                             data: Box::new([0b0100_0000]) },
+            PzxBlock::Pause { level: true, duration: 1000 },
+            PzxBlock::Browse(Box::new(*b"Browse")),
+            PzxBlock::Stop { only48k: true },
+            PzxBlock::Pause { level: false, duration: 2000 },
+            PzxBlock::Stop { only48k: false },
+            PzxBlock::Pulses(vec![(1, 0), (1, 400), (1, 500)].into()),
+        ];
+        let mut iter = PzxPulseIter::new(&blocks[..]);
+        assert_eq!(pulses(&mut iter), [
+            // the zero pulse changes the level without delay, so the pulses are merged
+            100, 100 + 200,
+            // the data block starts high
+            300, 10, 10, 20, 10, 10,
+            // the tail pulse is low, then the high pause
+            50, 1000]);
+        assert!(iter.is_stopped());
+        assert!(!iter.is_done());
+        assert_eq!(7, iter.block_index());
+        iter.resume();
+        // the pulse block starts high, the edge after the last pulse ends the tape
+        assert_eq!(pulses(&mut iter), [2000, 400, 500]);
+        assert!(iter.is_done());
+        assert_eq!(blocks.len(), iter.block_index());
+
+        iter.mode48k = true;
+        iter.jump_to_block(3);
+        assert_eq!(pulses(&mut iter), []);
+        assert!(iter.is_stopped());
+        assert_eq!(5, iter.block_index());
+        iter.resume();
+        assert_eq!(pulses(&mut iter), [1000]);
+        assert!(iter.is_stopped());
+    }
+}
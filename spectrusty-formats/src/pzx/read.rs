@@ -0,0 +1,242 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryInto;
+use std::io::{ErrorKind, Error, Read, Result};
+
+use crate::ReadExactEx;
+use super::*;
+
+/// Implements an iterator of [PzxBlock]s parsed from the underlying reader.
+///
+/// Created by [PzxReadIter::try_new] which reads and validates the *PZX* header block first.
+/// The header block is then yielded as the first item.
+#[derive(Debug)]
+pub struct PzxReadIter<R> {
+    major: u8,
+    minor: u8,
+    header: Option<PzxBlock>,
+    done: bool,
+    rd: R
+}
+
+fn invalid_data<T>(msg: &'static str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, msg))
+}
+
+fn unexpected_eof<T>() -> Result<T> {
+    Err(Error::new(ErrorKind::UnexpectedEof, "PZX: unexpected end of block data"))
+}
+
+fn read_data<R: Read>(rd: &mut R, len: u32) -> Result<Box<[u8]>> {
+    let mut data = Vec::new();
+    rd.take(len.into()).read_to_end(&mut data)?;
+    if data.len() != len as usize {
+        return unexpected_eof()
+    }
+    Ok(data.into_boxed_slice())
+}
+
+fn take_u16(rd: &mut &[u8]) -> Result<u16> {
+    if rd.len() < 2 {
+        return unexpected_eof()
+    }
+    let (word, rest) = rd.split_at(2);
+    *rd = rest;
+    Ok(u16::from_le_bytes([word[0], word[1]]))
+}
+
+fn take_u32(rd: &mut &[u8]) -> Result<u32> {
+    if rd.len() < 4 {
+        return unexpected_eof()
+    }
+    let (dword, rest) = rd.split_at(4);
+    *rd = rest;
+    Ok(u32::from_le_bytes(dword.try_into().unwrap()))
+}
+
+fn take_pulses(rd: &mut &[u8], count: u8) -> Result<Box<[u16]>> {
+    (0..count).map(|_| take_u16(rd)).collect()
+}
+
+fn parse_header(data: &[u8]) -> Result<PzxBlock> {
+    if data.len() < 2 {
+        return unexpected_eof()
+    }
+    let (major, minor) = (data[0], data[1]);
+    let rest = &data[2..];
+    let mut info: Vec<Box<[u8]>> = if rest.is_empty() {
+        Vec::new()
+    }
+    else {
+        rest.split(|&b| b == 0).map(Box::from).collect()
+    };
+    // the last string may or may not be terminated
+    if rest.last() == Some(&0) {
+        info.pop();
+    }
+    Ok(PzxBlock::Header { major, minor, info: info.into_boxed_slice() })
+}
+
+fn parse_pulses(mut rd: &[u8]) -> Result<PzxBlock> {
+    let rd = &mut rd;
+    let mut pulses = Vec::new();
+    while !rd.is_empty() {
+        let mut count = 1;
+        let mut duration = u32::from(take_u16(rd)?);
+        if duration > 0x8000 {
+            count = (duration & 0x7FFF) as u16;
+            duration = take_u16(rd)?.into();
+        }
+        if duration >= 0x8000 {
+            duration = (duration & 0x7FFF) << 16 | u32::from(take_u16(rd)?);
+        }
+        pulses.push((count, duration));
+    }
+    Ok(PzxBlock::Pulses(pulses.into_boxed_slice()))
+}
+
+fn parse_data(mut rd: &[u8]) -> Result<PzxBlock> {
+    let rd = &mut rd;
+    let count = take_u32(rd)?;
+    let tail = take_u16(rd)?;
+    if rd.len() < 2 {
+        return unexpected_eof()
+    }
+    let (p0, p1) = (rd[0], rd[1]);
+    *rd = &rd[2..];
+    let zero = take_pulses(rd, p0)?;
+    let one = take_pulses(rd, p1)?;
+    let level = count & 0x8000_0000 != 0;
+    let bits = count & 0x7FFF_FFFF;
+    let len = (bits / 8 + u32::from(bits & 7 != 0)) as usize;
+    if rd.len() < len {
+        return unexpected_eof()
+    }
+    Ok(PzxBlock::Data { level, bits, tail, zero, one, data: rd[..len].into() })
+}
+
+fn parse_pause(mut rd: &[u8]) -> Result<PzxBlock> {
+    let duration = take_u32(&mut rd)?;
+    Ok(PzxBlock::Pause { level: duration & 0x8000_0000 != 0, duration: duration & 0x7FFF_FFFF })
+}
+
+fn parse_stop(mut rd: &[u8]) -> Result<PzxBlock> {
+    let flags = take_u16(&mut rd)?;
+    Ok(PzxBlock::Stop { only48k: flags == 1 })
+}
+
+impl PzxBlock {
+    /// Reads the next block, including its tag and size, from the given reader.
+    ///
+    /// Returns `Ok(None)` if there are no more blocks to be read.
+    pub fn read_block<R: Read>(mut rd: R) -> Result<Option<PzxBlock>> {
+        let rd = &mut rd;
+        let mut head = [0u8;8];
+        if !rd.read_exact_or_none(&mut head)? {
+            return Ok(None)
+        }
+        let tag: [u8;4] = head[0..4].try_into().unwrap();
+        let size = u32::from_le_bytes(head[4..8].try_into().unwrap());
+        let data = read_data(rd, size)?;
+        let block = match &tag {
+            PZX_HEADER_TAG => parse_header(&data)?,
+            PZX_PULSES_TAG => parse_pulses(&data)?,
+            PZX_DATA_TAG => parse_data(&data)?,
+            PZX_PAUSE_TAG => parse_pause(&data)?,
+            PZX_BROWSE_TAG => PzxBlock::Browse(data),
+            PZX_STOP_TAG => parse_stop(&data)?,
+            _ => PzxBlock::Unknown { tag, data }
+        };
+        Ok(Some(block))
+    }
+}
+
+fn check_version(major: u8) -> Result<()> {
+    if major != PZX_MAJOR_VERSION {
+        return invalid_data("PZX: unsupported major version")
+    }
+    Ok(())
+}
+
+impl<R: Read> PzxReadIter<R> {
+    /// Reads and validates the *PZX* header block from the given reader.
+    ///
+    /// On success returns an iterator of blocks, including the header block.
+    pub fn try_new(mut rd: R) -> Result<Self> {
+        match PzxBlock::read_block(rd.by_ref())? {
+            Some(PzxBlock::Header { major, minor, info }) => {
+                check_version(major)?;
+                let header = Some(PzxBlock::Header { major, minor, info });
+                Ok(PzxReadIter { major, minor, header, done: false, rd })
+            }
+            _ => invalid_data("PZX: missing header block")
+        }
+    }
+}
+
+impl<R> PzxReadIter<R> {
+    /// Returns the major and the minor version numbers of the last read header block.
+    pub fn version(&self) -> (u8, u8) {
+        (self.major, self.minor)
+    }
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.rd
+    }
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rd
+    }
+    /// Returns a mutable reference to the wrapped reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.rd
+    }
+}
+
+impl<R: Read> Iterator for PzxReadIter<R> {
+    type Item = Result<PzxBlock>;
+
+    /// Returns `None` after the end of the stream or after the first error.
+    ///
+    /// Header blocks of the unsupported major version, e.g. from concatenated files, produce an error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(header) = self.header.take() {
+            return Some(Ok(header))
+        }
+        if self.done {
+            return None
+        }
+        let res = match PzxBlock::read_block(self.rd.by_ref()) {
+            Ok(Some(PzxBlock::Header { major, minor, info })) => {
+                check_version(major).map(|_| {
+                    self.major = major;
+                    self.minor = minor;
+                    PzxBlock::Header { major, minor, info }
+                })
+            }
+            Ok(Some(block)) => Ok(block),
+            Ok(None) => {
+                self.done = true;
+                return None
+            }
+            Err(e) => Err(e)
+        };
+        if res.is_err() {
+            self.done = true;
+        }
+        Some(res)
+    }
+}
+
+impl PzxTape {
+    /// Reads the whole *PZX* file from the given reader.
+    pub fn read_from<R: Read>(rd: R) -> Result<Self> {
+        let blocks = PzxReadIter::try_new(rd)?.collect::<Result<Vec<_>>>()?;
+        Ok(PzxTape { blocks })
+    }
+}
@@ -0,0 +1,363 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryFrom;
+use core::mem;
+use core::num::NonZeroU32;
+use std::io::{ErrorKind, Error, Write, Result};
+
+use super::*;
+
+/// A tool for writing *PZX* files.
+///
+/// Data can be written in one of 2 ways:
+///
+/// * Writing [PzxBlock]s with [PzxWriter::write_block].
+/// * Writing *TAPE* pulse intervals, e.g. from [MicOut::mic_out_pulse_iter], with
+///   [PzxWriter::write_pulses_as_pzx_blocks].
+///
+/// The pulses are grouped into segments separated by pauses. Each segment is stored as is in
+/// a pulse sequence block, so the signal is preserved exactly. The pauses between segments are
+/// stored as pause blocks.
+///
+/// [MicOut::mic_out_pulse_iter]: spectrusty_core::chip::MicOut::mic_out_pulse_iter
+#[derive(Debug)]
+pub struct PzxWriter<W> {
+    /// buffered pulses of: (repeat count, duration)
+    pulses: Vec<(u16, u32)>,
+    /// `true` if the next pulse is the pause between segments
+    ended: bool,
+    /// the current level of the signal
+    level: bool,
+    written: bool,
+    wr: W
+}
+
+fn invalid_input<T>(msg: &'static str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidInput, msg))
+}
+
+fn check_duration(duration: u32) -> Result<()> {
+    if duration > PZX_MAX_DURATION {
+        return invalid_input("PZX: duration too long")
+    }
+    Ok(())
+}
+
+fn put_u16s(body: &mut Vec<u8>, words: &[u16]) {
+    for word in words.iter() {
+        body.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn data_body(level: bool, bits: u32, tail: u16, zero: &[u16], one: &[u16], data: &[u8]) -> Result<Vec<u8>> {
+    check_duration(bits)?;
+    let p0 = u8::try_from(zero.len()).or_else(|_| invalid_input("PZX: too many pulses in a bit"))?;
+    let p1 = u8::try_from(one.len()).or_else(|_| invalid_input("PZX: too many pulses in a bit"))?;
+    let len = (bits / 8 + u32::from(bits & 7 != 0)) as usize;
+    if data.len() < len {
+        return invalid_input("PZX: not enough data bits")
+    }
+    let mut body = Vec::with_capacity(8 + 2 * (zero.len() + one.len()) + len);
+    body.extend_from_slice(&(bits | (level as u32) << 31).to_le_bytes());
+    body.extend_from_slice(&tail.to_le_bytes());
+    body.extend_from_slice(&[p0, p1]);
+    put_u16s(&mut body, zero);
+    put_u16s(&mut body, one);
+    body.extend_from_slice(&data[..len]);
+    Ok(body)
+}
+
+fn pulses_body(pulses: &[(u16, u32)]) -> Result<Vec<u8>> {
+    let mut body = Vec::with_capacity(pulses.len() * 2);
+    for &(count, duration) in pulses.iter() {
+        if count == 0 || count > PZX_MAX_REPEAT {
+            return invalid_input("PZX: invalid pulse repeat count")
+        }
+        check_duration(duration)?;
+        if count > 1 || duration > 0xFFFF {
+            put_u16s(&mut body, &[0x8000 | count]);
+        }
+        if duration < 0x8000 {
+            put_u16s(&mut body, &[duration as u16]);
+        }
+        else {
+            put_u16s(&mut body, &[0x8000 | (duration >> 16) as u16, duration as u16]);
+        }
+    }
+    Ok(body)
+}
+
+impl PzxBlock {
+    /// Writes this block, including its tag and size, to the given writer.
+    pub fn write_block<W: Write>(&self, mut wr: W) -> Result<()> {
+        let body: Cow<'_, [u8]> = match self {
+            PzxBlock::Header { major, minor, info } => {
+                let mut body = vec![*major, *minor];
+                for text in info.iter() {
+                    body.extend_from_slice(text);
+                    body.push(0);
+                }
+                body.into()
+            }
+            PzxBlock::Pulses(pulses) => pulses_body(pulses)?.into(),
+            PzxBlock::Data { level, bits, tail, zero, one, data } => {
+                data_body(*level, *bits, *tail, zero, one, data)?.into()
+            }
+            &PzxBlock::Pause { level, duration } => {
+                check_duration(duration)?;
+                (duration | (level as u32) << 31).to_le_bytes().to_vec().into()
+            }
+            PzxBlock::Browse(text) => Cow::Borrowed(&text[..]),
+            &PzxBlock::Stop { only48k } => (only48k as u16).to_le_bytes().to_vec().into(),
+            PzxBlock::Unknown { data, .. } => Cow::Borrowed(&data[..])
+        };
+        let size = u32::try_from(body.len()).or_else(|_| invalid_input("PZX: block data too large"))?;
+        wr.write_all(self.tag())?;
+        wr.write_all(&size.to_le_bytes())?;
+        wr.write_all(&body)
+    }
+}
+
+impl PzxTape {
+    /// Writes all the blocks to the given writer.
+    ///
+    /// If the first block is not a header block, the default [PzxBlock::Header] is written first.
+    pub fn write_to<W: Write>(&self, mut wr: W) -> Result<()> {
+        match self.blocks.first() {
+            Some(PzxBlock::Header {..}) => {}
+            _ => PzxBlock::default().write_block(wr.by_ref())?
+        }
+        for block in self.blocks.iter() {
+            block.write_block(wr.by_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> PzxWriter<W> {
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+    /// Returns a mutable reference to the inner writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.wr
+    }
+    /// Returns a shared reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.wr
+    }
+}
+
+impl<W: Write> PzxWriter<W> {
+    /// Writes the default *PZX* header block to the given writer and on success returns a new
+    /// instance of `PzxWriter`.
+    pub fn try_new(wr: W) -> Result<Self> {
+        Self::try_new_with_header(wr, &PzxBlock::default())
+    }
+    /// Writes the given `header` block to the given writer and on success returns a new
+    /// instance of `PzxWriter`.
+    ///
+    /// Returns an error if `header` is not a [PzxBlock::Header].
+    pub fn try_new_with_header(mut wr: W, header: &PzxBlock) -> Result<Self> {
+        if !matches!(header, PzxBlock::Header {..}) {
+            return invalid_input("PZX: the first block must be a header block")
+        }
+        header.write_block(wr.by_ref())?;
+        Ok(PzxWriter {
+            pulses: Vec::new(),
+            ended: true,
+            level: false,
+            written: false,
+            wr
+        })
+    }
+    /// Flushes the underlying writer, ensuring that all intermediately buffered
+    /// contents reach their destination (invokes [Write::flush]).
+    pub fn flush(&mut self) -> Result<()> {
+        self.wr.flush()
+    }
+    /// Writes the given block.
+    ///
+    /// Writes the buffered pulses with [PzxWriter::end_pulse_block] before proceeding with writing the block.
+    ///
+    /// Returns the number of *PZX* blocks written.
+    pub fn write_block(&mut self, block: &PzxBlock) -> Result<usize> {
+        let nblocks = self.end_pulse_block()?;
+        block.write_block(self.wr.by_ref())?;
+        Ok(nblocks + 1)
+    }
+    /// Forces the buffered pulses to be written as a *PZX* pulse sequence block.
+    ///
+    /// The next pulse will be interpreted as the pause after the last written block.
+    ///
+    /// Returns the number of *PZX* blocks written.
+    pub fn end_pulse_block(&mut self) -> Result<usize> {
+        self.ended = true;
+        if self.pulses.is_empty() {
+            return Ok(0)
+        }
+        let pulses = mem::take(&mut self.pulses);
+        PzxBlock::Pulses(pulses.into_boxed_slice()).write_block(self.wr.by_ref())?;
+        self.written = true;
+        Ok(1)
+    }
+    /// Interprets pulse intervals from the provided iterator as *PZX* blocks and writes them
+    /// to the underlying writer.
+    ///
+    /// The pulse iterator is expected to provide only a fragment of pulses, such as an iterator
+    /// returned from [MicOut::mic_out_pulse_iter]. Providing an empty iterator is equivalent
+    /// to calling [PzxWriter::end_pulse_block].
+    ///
+    /// Returns the number of *PZX* blocks written.
+    ///
+    /// [MicOut::mic_out_pulse_iter]: spectrusty_core::chip::MicOut::mic_out_pulse_iter
+    pub fn write_pulses_as_pzx_blocks<I>(&mut self, iter: I) -> Result<usize>
+        where I: Iterator<Item=NonZeroU32>
+    {
+        let mut iter = iter.peekable();
+        if iter.peek().is_none() {
+            return self.end_pulse_block()
+        }
+        let mut nblocks = 0;
+        for pulse in iter {
+            let pulse = pulse.get();
+            if pulse > PZX_MAX_DURATION || self.ended {
+                nblocks += self.end_pulse_block()?;
+                nblocks += self.write_pause(pulse)?;
+                self.ended = false;
+            }
+            else {
+                self.push_pulse(pulse);
+            }
+            self.level = !self.level;
+        }
+        Ok(nblocks)
+    }
+
+    fn push_pulse(&mut self, pulse: u32) {
+        match self.pulses.last_mut() {
+            Some((count, duration)) if *duration == pulse && *count < PZX_MAX_REPEAT => {
+                *count += 1;
+            }
+            last => {
+                // pulse sequences always begin with the low level
+                if last.is_none() && self.level {
+                    self.pulses.push((1, 0));
+                }
+                self.pulses.push((1, pulse));
+            }
+        }
+    }
+    /// Writes the pause after the last written block. Returns the number of blocks written.
+    fn write_pause(&mut self, mut pulse: u32) -> Result<usize> {
+        if !self.written {
+            return Ok(0)
+        }
+        let mut nblocks = 0;
+        while pulse != 0 {
+            let duration = pulse.min(PZX_MAX_DURATION);
+            PzxBlock::Pause { level: self.level, duration }.write_block(self.wr.by_ref())?;
+            pulse -= duration;
+            nblocks += 1;
+        }
+        Ok(nblocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use crate::tap::{*, pulse::{*, consts::*}};
+    use super::*;
+    use super::super::tests::tap_to_pzx;
+
+    fn pulses(slice: &[u32]) -> impl Iterator<Item=NonZeroU32> + '_ {
+        slice.iter().map(|&p| NonZeroU32::new(p).unwrap())
+    }
+
+    #[test]
+    fn write_pzx_blocks_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let mut pzx = tap_to_pzx(&tap);
+        let tape = read_pzx(Cursor::new(&pzx))?;
+        let mut tape = PzxTape { blocks: tape.blocks.into_iter().skip(1).collect() };
+        tape.blocks.extend_from_slice(&[
+            PzxBlock::Header { major: 1, minor: 0, info: vec![Box::from(&b"Title"[..]),
+                                                              Box::from(&b"Author"[..]),
+                                                              Box::from(&b"Joe Bloggs"[..])].into() },
+            PzxBlock::Browse(Box::from(&b"Level 1"[..])),
+            PzxBlock::Pulses(vec![(2, 667), (1, 0), (1, 0x8000), (1, 0x1_0000), (0x7FFF, PZX_MAX_DURATION)].into()),
+            PzxBlock::Pause { level: true, duration: 1000 },
+            PzxBlock::Data { level: false, bits: 11, tail: 0, zero: Box::new([1]), one: Box::new([]),
+                             data: Box::new([0xAA, 0xE0]) },
+            PzxBlock::Stop { only48k: true },
+            PzxBlock::Stop { only48k: false },
+            PzxBlock::Unknown { tag: *b"XTRA", data: Box::new([0xaa, 0x55]) }
+        ]);
+        let mut written = Vec::new();
+        tape.write_to(&mut written)?;
+        assert_eq!(&written[..pzx.len()], &pzx[..]);
+        let tape_read = read_pzx(Cursor::new(&written))?;
+        assert_eq!(&tape_read.blocks[1..], &tape.blocks[..]);
+        pzx.clear();
+        tape_read.write_to(&mut pzx)?;
+        assert_eq!(written, pzx);
+
+        let block = PzxBlock::Pulses(vec![(1, PZX_MAX_DURATION + 1)].into());
+        assert!(block.write_block(&mut Vec::new()).is_err());
+        let block = PzxBlock::Data { level: false, bits: 9, tail: 0, zero: Box::new([]), one: Box::new([]),
+                                     data: Box::new([0]) };
+        assert!(block.write_block(&mut Vec::new()).is_err());
+        assert!(PzxWriter::try_new_with_header(Vec::new(), &PzxBlock::Browse(Box::new([]))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn write_pulses_works() -> Result<()> {
+        let mut writer = PzxWriter::try_new(Vec::new())?;
+        // the initial pause is not written
+        assert_eq!(0, writer.write_pulses_as_pzx_blocks(pulses(&[3_500_000, 100, 100, 100, 200]))?);
+        assert_eq!(1, writer.write_pulses_as_pzx_blocks(pulses(&[]))?);
+        assert_eq!(4, writer.write_pulses_as_pzx_blocks(pulses(&[7000, 300, 300, 0x8000_0000]))?);
+        assert_eq!(0, writer.end_pulse_block()?);
+        let tape = read_pzx(Cursor::new(writer.into_inner()))?;
+        assert_eq!(tape.blocks, [
+            PzxBlock::default(),
+            PzxBlock::Pulses(vec![(1, 0), (3, 100), (1, 200)].into()),
+            PzxBlock::Pause { level: true, duration: 7000 },
+            PzxBlock::Pulses(vec![(2, 300)].into()),
+            PzxBlock::Pause { level: false, duration: PZX_MAX_DURATION },
+            PzxBlock::Pause { level: false, duration: 1 },
+        ]);
+        let replay: Vec<u32> = PzxPulseIter::new(tape.blocks).map(NonZeroU32::get).collect();
+        assert_eq!(replay, [100, 100, 100, 200, 7000, 300, 300]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_tap_pulses_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let mut writer = PzxWriter::try_new(Vec::new())?;
+        for chunk in TapChunkIter::from(&tap) {
+            let iter = ReadEncPulseIter::new(chunk.as_ref());
+            writer.write_pulses_as_pzx_blocks(Some(PAUSE_PULSE_LENGTH).into_iter().chain(iter))?;
+            writer.write_pulses_as_pzx_blocks(pulses(&[]))?;
+        }
+        let mut pulse_iter = read_pzx_pulse_iter(Cursor::new(writer.into_inner()))?;
+        let mut tap_writer = write_tap(Cursor::new(Vec::new()))?;
+        tap_writer.write_pulses_as_tap_chunks(&mut pulse_iter)?;
+        tap_writer.end_pulse_chunk()?;
+        let tgt: Vec<u8> = tap_writer.into_inner().into_inner().into_inner();
+        assert_eq!(tap, tgt);
+        Ok(())
+    }
+}
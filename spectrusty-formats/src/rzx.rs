@@ -0,0 +1,410 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **RZX** input recording file format utilities.
+
+# RZX format
+
+An **RZX** file starts with a 10 byte header: `RZX!` signature, the major and minor version numbers
+and 32-bit flags, followed by blocks, each starting with a 5 byte header:
+
+| offset | size | description                                         |
+|--------|------|-----------------------------------------------------|
+|    0   |    1 | block id                                            |
+|    1   |    4 | the total length of the block, including the header |
+|    5   |  ... | block data                                          |
+
+The recognized blocks are:
+
+* `0x10` - creator information,
+* `0x30` - an embedded snapshot which sets up the emulator before the input recording starts,
+* `0x80` - an input recording: a sequence of frames, each consisting of the number of opcode
+  fetches (`R` register increments) until the next interrupt and the values read by `IN`
+  instructions during that frame.
+
+Blocks with unknown ids, including the security information and signature blocks, are preserved but
+otherwise ignored. External snapshot references and encrypted input recordings are not supported.
+
+For the detailed description of each block, see the
+[RZX format specification](https://worldofspectrum.net/RZXformat.html).
+
+## Playing back *RZX* files
+
+```no_run
+use spectrusty::z80emu::Z80NMOS;
+use spectrusty::chip::ula::UlaPAL;
+use spectrusty::memory::Memory48k;
+use spectrusty_formats::{rzx::*, snapshot::*};
+
+struct Emulator {
+    ula: UlaPAL<Memory48k>,
+    cpu: Z80NMOS,
+    //...
+}
+
+impl SnapshotLoader for Emulator {
+    //...
+#    type Error = &'static str;
+#    fn select_model(&mut self, _model: ComputerModel, _extensions: Extensions,
+#        _border: spectrusty::video::BorderColor, _issue: spectrusty::chip::ReadEarMode
+#    ) -> Result<(), Self::Error> { unimplemented!() }
+#    fn read_into_memory<R: std::io::Read>(&mut self, _range: MemoryRange, _reader: R
+#    ) -> Result<(), spectrusty::memory::ZxMemoryError> { unimplemented!() }
+#    fn assign_cpu(&mut self, _cpu: CpuModel) { unimplemented!() }
+#    fn set_clock(&mut self, _tstates: spectrusty::clock::FTs) { unimplemented!() }
+#    fn write_port(&mut self, _port: u16, _data: u8) { unimplemented!() }
+}
+
+# fn main() -> std::io::Result<()> {
+# let mut emulator = Emulator { ula: Default::default(), cpu: Default::default() };
+let rzx = read_rzx(std::fs::File::open("replay.rzx")?)?;
+// set up the emulator from the embedded snapshot
+rzx.snapshots().next().expect("no snapshot").load_into(&mut emulator)?;
+for recording in rzx.input_recordings() {
+    let mut player = RzxPlayer::new(recording.clone());
+    while player.execute_next_frame(&mut emulator.ula, &mut emulator.cpu)? {
+        // render video and audio here
+    }
+}
+# Ok(())
+# }
+```
+
+## Recording
+
+[RzxRecorder] captures the input recording from a live [ControlUnit] run. Together with a snapshot
+of the emulator's state at the start of the recording, it can be written as an **RZX** file:
+
+```no_run
+# use spectrusty::{z80emu::Z80NMOS, chip::ula::UlaPAL, memory::Memory48k};
+# use spectrusty_formats::{rzx::*, snapshot::SnapshotCreator};
+# fn record<E: SnapshotCreator>(emulator: &E, ula: &mut UlaPAL<Memory48k>, cpu: &mut Z80NMOS)
+# -> std::io::Result<()> {
+let (snapshot, _result) = RzxSnapshot::from_szx_snapshot(emulator)?;
+let mut recorder = RzxRecorder::new(ula);
+for _ in 0..500 {
+    recorder.execute_next_frame(ula, cpu);
+}
+let rzx = Rzx::new(snapshot, recorder.into_recording());
+rzx.write_to(std::fs::File::create("replay.rzx")?)?;
+# Ok(())
+# }
+```
+
+[ControlUnit]: spectrusty_core::chip::ControlUnit
+*/
+use std::io::{Cursor, Error, ErrorKind, Read, Result};
+
+use crate::snapshot::{SnapshotCreator, SnapshotLoader, SnapshotResult};
+use crate::{sna, szx, z80};
+
+mod cpu;
+mod playback;
+mod read;
+mod write;
+pub use playback::*;
+
+/// The signature of the *RZX* file.
+pub const RZX_SIGNATURE: &[u8;4] = b"RZX!";
+/// The major version number of the supported *RZX* format.
+pub const RZX_MAJOR_VERSION: u8 = 0;
+/// The minor version number of the supported *RZX* format.
+pub const RZX_MINOR_VERSION: u8 = 13;
+/// The id of the *RZX* creator information block.
+pub const RZX_CREATOR_ID: u8 = 0x10;
+/// The id of the *RZX* snapshot block.
+pub const RZX_SNAPSHOT_ID: u8 = 0x30;
+/// The id of the *RZX* input recording block.
+pub const RZX_INPUT_ID: u8 = 0x80;
+/// The value of the `IN` counter of a frame indicating that the values of the previous frame are repeated.
+pub const RZX_REPEAT_INPUTS: u16 = 0xFFFF;
+
+/// The snapshot data is an external snapshot descriptor.
+const SNAPSHOT_EXTERNAL: u32 = 0x01;
+/// The snapshot or frames data is compressed.
+const DATA_COMPRESSED: u32 = 0x02;
+/// The input recording frames are encrypted.
+const INPUT_PROTECTED: u32 = 0x01;
+
+/// A single frame of the *RZX* input recording.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RzxFrame {
+    /// The number of opcode fetches (`R` register increments) executed until the next interrupt.
+    pub fetch_count: u16,
+    /// The values returned by `IN` instructions during this frame in the order of execution.
+    ///
+    /// Repeated values are resolved when the file is being read.
+    pub inputs: Box<[u8]>
+}
+
+/// The *RZX* input recording.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RzxInputRecording {
+    /// The value of the frame T-states counter at the beginning of the recording.
+    pub tstates: u32,
+    /// The recorded frames.
+    pub frames: Vec<RzxFrame>
+}
+
+/// The snapshot embedded in the *RZX* file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RzxSnapshot {
+    /// The snapshot file extension in ASCII, padded with zeroes, e.g. `b"z80\0"`.
+    pub ext: [u8;4],
+    /// `true` if `data` contains the external snapshot descriptor instead of the snapshot itself.
+    pub external: bool,
+    /// The uncompressed snapshot data.
+    pub data: Box<[u8]>
+}
+
+/// Represents a parsed *RZX* block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RzxBlock {
+    /// The creator information block.
+    Creator {
+        /// The name of the creator program without the trailing zeroes.
+        name: Box<[u8]>,
+        /// The major version number of the creator program.
+        major: u16,
+        /// The minor version number of the creator program.
+        minor: u16,
+        /// Custom data.
+        custom: Box<[u8]>
+    },
+    /// The snapshot block.
+    Snapshot(RzxSnapshot),
+    /// The input recording block.
+    Input(RzxInputRecording),
+    /// A block with an unknown id.
+    Unknown {
+        /// The block id.
+        id: u8,
+        /// Raw block data.
+        data: Box<[u8]>
+    }
+}
+
+/// Represents the whole content of an *RZX* file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rzx {
+    /// The major version number of the file format.
+    pub major: u8,
+    /// The minor version number of the file format.
+    pub minor: u8,
+    /// The file header flags.
+    pub flags: u32,
+    /// The blocks of the file.
+    pub blocks: Vec<RzxBlock>
+}
+
+/// Reads the whole *RZX* file from the given reader.
+pub fn read_rzx<R: Read>(rd: R) -> Result<Rzx> {
+    Rzx::read_from(rd)
+}
+
+impl RzxBlock {
+    /// Returns the id of this block.
+    pub fn id(&self) -> u8 {
+        match self {
+            RzxBlock::Creator {..} => RZX_CREATOR_ID,
+            RzxBlock::Snapshot(..) => RZX_SNAPSHOT_ID,
+            RzxBlock::Input(..)    => RZX_INPUT_ID,
+            RzxBlock::Unknown { id, .. } => *id
+        }
+    }
+}
+
+impl Rzx {
+    /// Creates the *RZX* content of the supported version with the creator information,
+    /// the given `snapshot` and the input `recording`.
+    pub fn new(snapshot: RzxSnapshot, recording: RzxInputRecording) -> Self {
+        let creator = RzxBlock::Creator {
+            name: Box::from(&b"SPECTRUSTY"[..]),
+            major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
+            minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
+            custom: Box::new([])
+        };
+        Rzx {
+            major: RZX_MAJOR_VERSION,
+            minor: RZX_MINOR_VERSION,
+            flags: 0,
+            blocks: vec![creator, RzxBlock::Snapshot(snapshot), RzxBlock::Input(recording)]
+        }
+    }
+    /// Returns an iterator of the snapshots found in the file.
+    pub fn snapshots(&self) -> impl Iterator<Item=&RzxSnapshot> {
+        self.blocks.iter().filter_map(|block| match block {
+            RzxBlock::Snapshot(snapshot) => Some(snapshot),
+            _ => None
+        })
+    }
+    /// Returns an iterator of the input recordings found in the file.
+    pub fn input_recordings(&self) -> impl Iterator<Item=&RzxInputRecording> {
+        self.blocks.iter().filter_map(|block| match block {
+            RzxBlock::Input(recording) => Some(recording),
+            _ => None
+        })
+    }
+}
+
+impl RzxSnapshot {
+    /// Creates the snapshot block content from the given `snapshot` in the **SZX** format.
+    ///
+    /// Returns the snapshot and the result of [szx::save_szx].
+    pub fn from_szx_snapshot<C: SnapshotCreator>(snapshot: &C) -> Result<(Self, SnapshotResult)> {
+        let mut data = Vec::new();
+        let result = szx::save_szx(snapshot, &mut data)?;
+        let snapshot = RzxSnapshot { ext: *b"szx\0", external: false, data: data.into_boxed_slice() };
+        Ok((snapshot, result))
+    }
+    /// Returns the snapshot file extension in lower case, without the trailing zeroes.
+    pub fn extension(&self) -> String {
+        self.ext.iter().take_while(|&&b| b != 0)
+                .map(|&b| char::from(b.to_ascii_lowercase()))
+                .collect()
+    }
+    /// Loads the embedded snapshot into the provided snapshot `loader` implementing [SnapshotLoader].
+    ///
+    /// Supported snapshot formats are: **Z80**, **SNA** and **SZX**.
+    ///
+    /// # Errors
+    /// This function will return an error if the snapshot is external or its format is not supported.
+    /// Other errors may also be returned from the snapshot loaders.
+    pub fn load_into<S: SnapshotLoader>(&self, loader: &mut S) -> Result<()> {
+        if self.external {
+            return Err(Error::new(ErrorKind::InvalidData, "RZX: external snapshots are not supported"))
+        }
+        match self.extension().as_str() {
+            "z80" => z80::load_z80(&self.data[..], loader),
+            "sna" => sna::load_sna(Cursor::new(&self.data[..]), loader),
+            "szx" => szx::load_szx(&self.data[..], loader),
+            _ => Err(Error::new(ErrorKind::InvalidData, "RZX: unsupported snapshot format"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::z80emu::{Cpu, Z80NMOS};
+    use spectrusty::clock::FTs;
+    use spectrusty::chip::{FrameState, MemoryAccess, ula::UlaPAL};
+    use spectrusty::memory::{Memory48k, ZxMemory};
+    use spectrusty::peripherals::{ZXKeyboardMap, KeyboardInterface};
+    use super::*;
+
+    fn test_rzx() -> Rzx {
+        let frames = vec![
+            RzxFrame { fetch_count: 100, inputs: Box::new([]) },
+            RzxFrame { fetch_count: 2000, inputs: Box::new([0xbf, 0xff, 0x1f]) },
+            RzxFrame { fetch_count: 3000, inputs: Box::new([0xbf, 0xff, 0x1f]) },
+            RzxFrame { fetch_count: 4000, inputs: Box::new([0xff]) },
+        ];
+        let snapshot = RzxSnapshot { ext: *b"Z80\0", external: false, data: (0..200u8).collect() };
+        let mut rzx = Rzx::new(snapshot, RzxInputRecording { tstates: 12345, frames });
+        rzx.blocks.push(RzxBlock::Unknown { id: 0x21, data: Box::new([1, 2, 3]) });
+        rzx
+    }
+
+    #[test]
+    fn rzx_read_write_works() -> Result<()> {
+        let rzx = test_rzx();
+        let mut buf = Vec::new();
+        rzx.write_to(&mut buf)?;
+        assert_eq!(&buf[0..10], b"RZX!\x00\x0d\x00\x00\x00\x00");
+        assert_eq!(&buf[10..15], b"\x10\x1d\x00\x00\x00");
+        assert_eq!(&buf[15..25], b"SPECTRUSTY");
+        let read = read_rzx(&buf[..])?;
+        assert_eq!(read, rzx);
+        assert_eq!(read.blocks.iter().map(RzxBlock::id).collect::<Vec<_>>(), [0x10, 0x30, 0x80, 0x21]);
+        assert_eq!(read.snapshots().next().unwrap().extension(), "z80");
+        assert_eq!(read.input_recordings().count(), 1);
+        // corrupted files
+        assert_eq!(read_rzx(&buf[..9]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(read_rzx(&buf[..buf.len() - 1]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        buf[3] = b'?';
+        assert_eq!(read_rzx(&buf[..]).unwrap_err().to_string(), "RZX: not an RZX file");
+        Ok(())
+    }
+
+    fn test_program(ula: &mut UlaPAL<Memory48k>, cpu: &mut Z80NMOS) {
+        // the program repeatedly reads a keyboard row into the screen memory and halts,
+        // the interrupt routine modifies the memory at HL
+        let program = [
+            0xED, 0x56,       // 0x8000 IM 1
+            0xFB,             // 0x8002 EI
+            0x21, 0x00, 0x40, // 0x8003 LD HL, 0x4000
+            0x01, 0xFE, 0xBF, // 0x8006 LD BC, 0xBFFE
+            0xED, 0x78,       // 0x8009 IN A, (C)
+            0x77,             // 0x800B LD (HL), A
+            0x23,             // 0x800C INC HL
+            0xCB, 0x5C,       // 0x800D BIT 3, H
+            0x28, 0xF8,       // 0x800F JR Z, 0x8009
+            0x76,             // 0x8011 HALT
+            0x18, 0xEF,       // 0x8012 JR 0x8003
+        ];
+        let isr = [
+            0x34,             // INC (HL)
+            0xFB,             // EI
+            0xC9,             // RET
+        ];
+        let mem = ula.memory_mut();
+        mem.mem_mut()[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+        mem.mem_mut()[0x0038..0x0038 + isr.len()].copy_from_slice(&isr);
+        cpu.reset();
+        cpu.set_pc(0x8000);
+        cpu.set_sp(0xC000);
+    }
+
+    #[test]
+    fn rzx_record_playback_works() -> Result<()> {
+        let mut ula = UlaPAL::<Memory48k>::default();
+        let mut cpu = Z80NMOS::default();
+        test_program(&mut ula, &mut cpu);
+        ula.set_frame_tstate(1000 as FTs);
+        let (mut ula_play, mut cpu_play) = (ula.clone(), cpu.clone());
+
+        let mut recorder = RzxRecorder::new(&ula);
+        for n in 0..10 {
+            ula.set_key_state(if n & 1 == 0 { ZXKeyboardMap::L } else { ZXKeyboardMap::empty() });
+            recorder.execute_next_frame(&mut ula, &mut cpu);
+        }
+        let recording = recorder.into_recording();
+        assert_eq!(recording.tstates, 1000);
+        assert!(recording.frames.len() >= 10);
+        assert!(recording.frames.iter().flat_map(|frame| frame.inputs.iter()).any(|&data| data & 2 == 0));
+
+        let mut buf = Vec::new();
+        Rzx::new(RzxSnapshot::default(), recording).write_to(&mut buf)?;
+        let recording = read_rzx(&buf[..])?.input_recordings().next().unwrap().clone();
+        let frames = recording.frames.len();
+
+        let mut player = RzxPlayer::new(recording);
+        let mut count = 0;
+        // the keyboard state is ignored during playback
+        ula_play.set_key_state(ZXKeyboardMap::L|ZXKeyboardMap::EN);
+        while player.execute_next_frame(&mut ula_play, &mut cpu_play)? {
+            count += 1;
+        }
+        assert!(player.is_done());
+        assert_eq!(count, frames);
+        assert_eq!(cpu_play, cpu);
+        assert!(ula_play.memory_ref().mem_ref() == ula.memory_ref().mem_ref());
+        Ok(())
+    }
+
+    #[test]
+    fn rzx_playback_detects_desync() {
+        let mut ula = UlaPAL::<Memory48k>::default();
+        let mut cpu = Z80NMOS::default();
+        test_program(&mut ula, &mut cpu);
+        let frames = vec![RzxFrame { fetch_count: 100, inputs: Box::new([]) }];
+        let mut player = RzxPlayer::new(RzxInputRecording { tstates: 0, frames });
+        let err = player.execute_next_frame(&mut ula, &mut cpu).unwrap_err();
+        assert_eq!(err.to_string(), "RZX: playback out of sync");
+        assert_eq!(cpu.get_sp(), 0xC000);
+    }
+}
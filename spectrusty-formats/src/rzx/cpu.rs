@@ -0,0 +1,373 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A [Cpu] wrapper intercepting the input and the opcode fetches for the input recordings.
+use core::cell::Cell;
+use core::num::{NonZeroU8, NonZeroU16};
+use core::mem;
+
+use spectrusty_core::z80emu::{*, host::Result};
+
+use super::RzxFrame;
+
+/// The state of the input recording or playback.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct InputState {
+    /// `true` when recording, `false` when playing back.
+    pub recording: bool,
+    /// Recording: the inputs of the current frame.
+    /// Playback: the inputs of the frame being played.
+    pub inputs: Vec<u8>,
+    /// Playback: the index of the next input to be returned.
+    pub input_index: usize,
+    /// Recording: `true` if an interrupt has been accepted.
+    /// Playback: `true` if an interrupt should be requested.
+    pub irq: bool,
+    /// Recording: frames closed by the accepted interrupts.
+    pub frames: Vec<RzxFrame>,
+    /// Playback: `true` if more inputs were requested than recorded.
+    pub overrun: bool
+}
+
+/// Wraps the [Cpu] counting opcode fetches and intercepting the [Io] of the executed instructions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct RzxCpu<C> {
+    pub cpu: C,
+    pub state: InputState,
+    pub fetches: Cell<u32>
+}
+
+struct RzxIo<'a, M> {
+    control: &'a mut M,
+    state: &'a mut InputState,
+    fetches: &'a Cell<u32>
+}
+
+struct RzxClock<'a, T> {
+    tsc: &'a mut T,
+    fetches: &'a Cell<u32>
+}
+
+fn add_fetches(fetches: &Cell<u32>, count: u32) {
+    fetches.set(fetches.get().saturating_add(count));
+}
+
+impl InputState {
+    pub fn recording(inputs: Vec<u8>) -> Self {
+        InputState { recording: true, inputs, ..InputState::default() }
+    }
+
+    pub fn playback(inputs: Vec<u8>) -> Self {
+        InputState { recording: false, inputs, ..InputState::default() }
+    }
+}
+
+impl<M: Memory> Memory for RzxIo<'_, M> {
+    type Timestamp = M::Timestamp;
+
+    #[inline]
+    fn read_mem(&self, address: u16, ts: Self::Timestamp) -> u8 {
+        self.control.read_mem(address, ts)
+    }
+    #[inline]
+    fn read_mem16(&self, address: u16, ts: Self::Timestamp) -> u16 {
+        self.control.read_mem16(address, ts)
+    }
+    #[inline]
+    fn read_opcode(&mut self, pc: u16, ir: u16, ts: Self::Timestamp) -> u8 {
+        self.control.read_opcode(pc, ir, ts)
+    }
+    #[inline]
+    fn write_mem(&mut self, address: u16, value: u8, ts: Self::Timestamp) {
+        self.control.write_mem(address, value, ts)
+    }
+    #[inline]
+    fn read_debug(&self, address: u16) -> u8 {
+        self.control.read_debug(address)
+    }
+}
+
+impl<M: Io> Io for RzxIo<'_, M> {
+    type Timestamp = M::Timestamp;
+    type WrIoBreak = M::WrIoBreak;
+    type RetiBreak = M::RetiBreak;
+
+    /// The data is always read from the underlying implementation, so all side effects are preserved.
+    /// When playing back, the data is replaced with the recorded value.
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> (u8, Option<NonZeroU16>) {
+        let (data, wait_states) = self.control.read_io(port, timestamp);
+        let state = &mut *self.state;
+        if state.recording {
+            state.inputs.push(data);
+            return (data, wait_states)
+        }
+        match state.inputs.get(state.input_index) {
+            Some(&data) => {
+                state.input_index += 1;
+                (data, wait_states)
+            }
+            None => {
+                state.overrun = true;
+                (data, wait_states)
+            }
+        }
+    }
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> (Option<Self::WrIoBreak>, Option<NonZeroU16>) {
+        self.control.write_io(port, data, timestamp)
+    }
+    fn is_irq(&mut self, timestamp: Self::Timestamp) -> bool {
+        if self.state.recording {
+            self.control.is_irq(timestamp)
+        }
+        else {
+            self.state.irq
+        }
+    }
+    /// When recording, the accepted interrupt closes the current frame.
+    fn irq_data(&mut self, pc: u16, timestamp: Self::Timestamp) -> (u8, Option<NonZeroU16>) {
+        let state = &mut *self.state;
+        if state.recording {
+            let fetch_count = self.fetches.replace(0).min(u16::MAX.into()) as u16;
+            let inputs = mem::take(&mut state.inputs).into_boxed_slice();
+            state.frames.push(RzxFrame { fetch_count, inputs });
+            state.irq = true;
+        }
+        else {
+            state.irq = false;
+        }
+        self.control.irq_data(pc, timestamp)
+    }
+    #[inline]
+    fn reti(&mut self, address: u16, timestamp: Self::Timestamp) -> Option<Self::RetiBreak> {
+        self.control.reti(address, timestamp)
+    }
+}
+
+impl<T: Clock> Clock for RzxClock<'_, T> {
+    type Limit = T::Limit;
+    type Timestamp = T::Timestamp;
+
+    #[inline]
+    fn is_past_limit(&self, limit: Self::Limit) -> bool {
+        self.tsc.is_past_limit(limit)
+    }
+    #[inline]
+    fn add_irq(&mut self, pc: u16) -> Self::Timestamp {
+        self.tsc.add_irq(pc)
+    }
+    #[inline]
+    fn add_no_mreq(&mut self, address: u16, add_ts: NonZeroU8) {
+        self.tsc.add_no_mreq(address, add_ts)
+    }
+    #[inline]
+    fn add_m1(&mut self, address: u16) -> Self::Timestamp {
+        add_fetches(self.fetches, 1);
+        self.tsc.add_m1(address)
+    }
+    #[inline]
+    fn add_mreq(&mut self, address: u16) -> Self::Timestamp {
+        self.tsc.add_mreq(address)
+    }
+    #[inline]
+    fn add_io(&mut self, port: u16) -> Self::Timestamp {
+        self.tsc.add_io(port)
+    }
+    #[inline]
+    fn add_wait_states(&mut self, bus: u16, wait_states: NonZeroU16) {
+        self.tsc.add_wait_states(bus, wait_states)
+    }
+    #[inline]
+    fn as_timestamp(&self) -> Self::Timestamp {
+        self.tsc.as_timestamp()
+    }
+}
+
+impl<C: Cpu> RzxCpu<C> {
+    pub fn new(cpu: C, state: InputState, fetches: u32) -> Self {
+        RzxCpu { cpu, state, fetches: Cell::new(fetches) }
+    }
+
+    pub fn fetches(&self) -> u32 {
+        self.fetches.get()
+    }
+
+    pub fn into_parts(self) -> (C, InputState, u32) {
+        (self.cpu, self.state, self.fetches.into_inner())
+    }
+
+    #[inline]
+    fn wrap<'a, M, T>(
+            &'a mut self,
+            control: &'a mut M,
+            tsc: &'a mut T
+        ) -> (&'a mut C, RzxIo<'a, M>, RzxClock<'a, T>)
+    {
+        let RzxCpu { cpu, state, fetches } = self;
+        let fetches = &*fetches;
+        (cpu, RzxIo { control, state, fetches }, RzxClock { tsc, fetches })
+    }
+}
+
+impl<C: Cpu> Cpu for RzxCpu<C> {
+    delegate_cpu_state!(cpu);
+
+    /// Counts as an opcode fetch.
+    #[inline]
+    fn inc_r(&mut self) {
+        add_fetches(&self.fetches, 1);
+        self.cpu.inc_r()
+    }
+    /// Counts as `delta` opcode fetches, e.g. when the halted state is being fast-forwarded.
+    #[inline]
+    fn add_r(&mut self, delta: i32) {
+        if delta > 0 {
+            add_fetches(&self.fetches, delta as u32);
+        }
+        self.cpu.add_r(delta)
+    }
+
+    fn irq<M, T, F>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            debug: Option<F>
+        ) -> Option<Result<M::WrIoBreak, M::RetiBreak>>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(CpuDebug)
+    {
+        let (cpu, mut control, mut tsc) = self.wrap(control, tsc);
+        cpu.irq(&mut control, &mut tsc, debug)
+    }
+
+    fn nmi<M, T>(&mut self, control: &mut M, tsc: &mut T) -> bool
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock
+    {
+        let (cpu, mut control, mut tsc) = self.wrap(control, tsc);
+        cpu.nmi(&mut control, &mut tsc)
+    }
+
+    fn execute_instruction<M, T, F>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            debug: Option<F>,
+            code: u8
+        ) -> Result<M::WrIoBreak, M::RetiBreak>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(CpuDebug)
+    {
+        let (cpu, mut control, mut tsc) = self.wrap(control, tsc);
+        cpu.execute_instruction(&mut control, &mut tsc, debug, code)
+    }
+
+    fn execute_next<M, T, F>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            debug: Option<F>
+        ) -> Result<M::WrIoBreak, M::RetiBreak>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(CpuDebug)
+    {
+        let (cpu, mut control, mut tsc) = self.wrap(control, tsc);
+        cpu.execute_next(&mut control, &mut tsc, debug)
+    }
+
+    fn execute_with_limit<M, T>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            limit: T::Limit
+        ) -> Result<M::WrIoBreak, M::RetiBreak>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock
+    {
+        let (cpu, mut control, mut tsc) = self.wrap(control, tsc);
+        cpu.execute_with_limit(&mut control, &mut tsc, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::chip::{ControlUnit, MemoryAccess, ula::UlaPAL};
+    use spectrusty::memory::{Memory48k, ZxMemory};
+    use super::*;
+
+    type NoDebug = fn(CpuDebug);
+
+    fn test_ula() -> UlaPAL<Memory48k> {
+        let mut ula = UlaPAL::<Memory48k>::default();
+        let program = [
+            0x00,                   // 0x8000 NOP
+            0xDD, 0x21, 0x34, 0x12, // 0x8001 LD IX, 0x1234
+            0xDB, 0xFE,             // 0x8005 IN A, (0xFE)
+            0xED, 0x78,             // 0x8007 IN A, (C)
+        ];
+        ula.memory_mut().mem_mut()[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+        ula
+    }
+
+    fn test_cpu(state: InputState) -> RzxCpu<Z80NMOS> {
+        let mut cpu = RzxCpu::new(Z80NMOS::default(), state, 0);
+        cpu.reset();
+        cpu.set_pc(0x8000);
+        cpu.set_reg16(StkReg16::BC, 0xFEFE);
+        cpu.set_r(0);
+        cpu
+    }
+
+    #[test]
+    fn rzx_cpu_playback_works() {
+        let mut ula = test_ula();
+        let mut cpu = test_cpu(InputState::playback(vec![0x12, 0x34]));
+        // the state is delegated to the wrapped cpu
+        assert_eq!(cpu.cpu.get_pc(), 0x8000);
+        assert_eq!(cpu.cpu.get_reg16(StkReg16::BC), 0xFEFE);
+        assert_eq!(cpu.get_iffs(), (false, false));
+        // the prefix is executed in a separate step
+        for _ in 0..4 {
+            ula.execute_single_step(&mut cpu, None::<NoDebug>).unwrap();
+        }
+        assert_eq!(cpu.get_pc(), 0x8007);
+        assert_eq!(cpu.get_index16(Prefix::Xdd), 0x1234);
+        assert_eq!(cpu.get_acc(), 0x12);
+        ula.execute_single_step(&mut cpu, None::<NoDebug>).unwrap();
+        assert_eq!(cpu.get_acc(), 0x34);
+        // the prefixes are counted as opcode fetches
+        assert_eq!(cpu.fetches(), 6);
+        assert_eq!(cpu.get_r(), 6);
+        cpu.inc_r();
+        cpu.add_r(3);
+        assert_eq!(cpu.fetches(), 10);
+        cpu.add_r(-1);
+        assert_eq!(cpu.fetches(), 10);
+        assert_eq!(cpu.get_r(), 9);
+        let (inner, state, fetches) = cpu.into_parts();
+        assert_eq!(inner.get_r(), 9);
+        assert_eq!(fetches, 10);
+        assert_eq!(state.input_index, 2);
+        assert!(!state.overrun);
+    }
+
+    #[test]
+    fn rzx_cpu_recording_works() {
+        let mut ula = test_ula();
+        let mut cpu = test_cpu(InputState::recording(Vec::new()));
+        cpu.set_pc(0x8005);
+        ula.execute_single_step(&mut cpu, None::<NoDebug>).unwrap();
+        let data = cpu.get_acc();
+        ula.execute_single_step(&mut cpu, None::<NoDebug>).unwrap();
+        assert_eq!(cpu.state.inputs, [data, cpu.get_acc()]);
+        assert_eq!(cpu.fetches(), 3);
+        assert!(cpu.state.frames.is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::mem;
+use std::io::{Error, ErrorKind, Result};
+
+use spectrusty_core::z80emu::{Cpu, CpuDebug};
+use spectrusty_core::chip::{ControlUnit, FrameState};
+use spectrusty_core::clock::FTs;
+
+use super::cpu::{InputState, RzxCpu};
+use super::{RzxFrame, RzxInputRecording};
+
+/// Frames are closed at the end of [RzxRecorder::execute_next_frame] when the number of pending
+/// opcode fetches exceeds this value, even if the interrupts are enabled.
+const MAX_PENDING_FETCHES: u32 = 0x8000;
+
+type NoDebug = fn(CpuDebug);
+
+/// Plays back the *RZX* input recording.
+///
+/// Each recorded frame is executed instruction by instruction until the recorded number of opcode
+/// fetches is reached. Every value read by the `IN` family instructions is being replaced with the
+/// next recorded value. The interrupts are requested only at the frame boundaries, regardless of
+/// the T-state counter of the chipset.
+///
+/// The emulator should be initialized from the snapshot preceding the input recording before the
+/// playback starts, e.g. with [RzxSnapshot::load_into][super::RzxSnapshot::load_into].
+///
+/// # Note
+/// The T-state counter of the chipset is not being forced to match the frame boundaries of the
+/// recording. This is not an issue with recordings made with [RzxRecorder], but recordings made by
+/// other emulators may drift slightly against the video frames.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RzxPlayer {
+    recording: RzxInputRecording,
+    frame_index: usize
+}
+
+/// Records the *RZX* input recording from a live [ControlUnit] run.
+///
+/// The values read by the `IN` family instructions are being recorded and the frames are closed
+/// each time the interrupt is accepted. When the interrupts are disabled, the frames are closed
+/// at the end of [RzxRecorder::execute_next_frame] instead.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RzxRecorder {
+    tstates: u32,
+    frames: Vec<RzxFrame>,
+    inputs: Vec<u8>,
+    fetches: u32
+}
+
+impl RzxPlayer {
+    /// Creates a new player of the given input `recording`.
+    pub fn new(recording: RzxInputRecording) -> Self {
+        RzxPlayer { recording, frame_index: 0 }
+    }
+    /// Returns the index of the next frame to be played.
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+    /// Returns `true` if all the frames have been played.
+    pub fn is_done(&self) -> bool {
+        self.frame_index >= self.recording.frames.len()
+    }
+    /// Returns a reference to the input recording.
+    pub fn recording(&self) -> &RzxInputRecording {
+        &self.recording
+    }
+    /// Returns the input recording.
+    pub fn into_recording(self) -> RzxInputRecording {
+        self.recording
+    }
+    /// Executes the next recorded frame on the `cpu` using the `chip`.
+    ///
+    /// Before the first frame is being played, sets the T-state counter of the `chip` to the value
+    /// stored in the recording.
+    ///
+    /// Returns `Ok(true)` if the frame has been played or `Ok(false)` if there are no more frames.
+    ///
+    /// # Errors
+    /// Returns an error with [ErrorKind::InvalidData] if the number of executed `IN` instructions
+    /// doesn't match the recorded values. In this instance the frame is still played to the end and
+    /// the next call will play the next frame.
+    pub fn execute_next_frame<U, C>(&mut self, chip: &mut U, cpu: &mut C) -> Result<bool>
+        where U: ControlUnit + FrameState,
+              C: Cpu
+    {
+        let frame = match self.recording.frames.get(self.frame_index) {
+            Some(frame) => frame,
+            None => return Ok(false)
+        };
+        if self.frame_index == 0 {
+            chip.set_frame_tstate(self.recording.tstates as FTs);
+        }
+        let state = InputState::playback(frame.inputs.to_vec());
+        let mut rzx_cpu = RzxCpu::new(mem::take(cpu), state, 0);
+        if self.frame_index != 0 && rzx_cpu.is_irq_allowed() {
+            rzx_cpu.state.irq = true;
+            let _ = chip.execute_single_step(&mut rzx_cpu, None::<NoDebug>);
+            rzx_cpu.state.irq = false;
+            rzx_cpu.fetches.set(0);
+        }
+        while rzx_cpu.fetches() < frame.fetch_count.into() {
+            let _ = chip.execute_single_step(&mut rzx_cpu, None::<NoDebug>);
+        }
+        let (inner, state, _) = rzx_cpu.into_parts();
+        *cpu = inner;
+        self.frame_index += 1;
+        if state.overrun || state.input_index != state.inputs.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "RZX: playback out of sync"))
+        }
+        Ok(true)
+    }
+}
+
+impl RzxRecorder {
+    /// Creates a new recorder, capturing the current T-state counter of the `chip`.
+    pub fn new<U: FrameState>(chip: &U) -> Self {
+        let (_, tstates) = chip.frame_tstate();
+        RzxRecorder { tstates: tstates as u32, ..RzxRecorder::default() }
+    }
+    /// Returns the number of frames recorded so far, not including the pending frame.
+    pub fn frames_count(&self) -> usize {
+        self.frames.len()
+    }
+    /// Executes instructions on the `cpu` using the `chip` via [ControlUnit::execute_next_frame]
+    /// while recording the inputs.
+    pub fn execute_next_frame<U: ControlUnit, C: Cpu>(&mut self, chip: &mut U, cpu: &mut C) {
+        let state = InputState::recording(mem::take(&mut self.inputs));
+        let mut rzx_cpu = RzxCpu::new(mem::take(cpu), state, self.fetches);
+        chip.execute_next_frame(&mut rzx_cpu);
+        let (inner, state, fetches) = rzx_cpu.into_parts();
+        *cpu = inner;
+        self.frames.extend(state.frames);
+        self.inputs = state.inputs;
+        self.fetches = fetches;
+        // when interrupts are disabled the frame is closed here, so the fetch counter can't overflow
+        let (iff1, _) = cpu.get_iffs();
+        if (!state.irq && !iff1) || fetches > MAX_PENDING_FETCHES {
+            self.close_frame();
+        }
+    }
+    /// Closes the pending frame and returns the input recording.
+    pub fn into_recording(mut self) -> RzxInputRecording {
+        if self.fetches != 0 || !self.inputs.is_empty() {
+            self.close_frame();
+        }
+        RzxInputRecording { tstates: self.tstates, frames: self.frames }
+    }
+
+    fn close_frame(&mut self) {
+        let fetch_count = self.fetches.min(u16::MAX.into()) as u16;
+        let inputs = mem::take(&mut self.inputs).into_boxed_slice();
+        self.frames.push(RzxFrame { fetch_count, inputs });
+        self.fetches = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::z80emu::Z80NMOS;
+    use spectrusty::chip::{MemoryAccess, ula::UlaPAL};
+    use spectrusty::memory::{Memory48k, ZxMemory};
+    use super::*;
+
+    fn test_machine() -> (UlaPAL<Memory48k>, Z80NMOS) {
+        let mut ula = UlaPAL::<Memory48k>::default();
+        let program = [
+            0xDB, 0xFE, // 0x8000 IN A, (0xFE)
+            0x18, 0xFC, // 0x8002 JR 0x8000
+        ];
+        ula.memory_mut().mem_mut()[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+        let mut cpu = Z80NMOS::default();
+        cpu.reset();
+        cpu.set_pc(0x8000);
+        (ula, cpu)
+    }
+
+    #[test]
+    fn rzx_playback_in_count_mismatch_works() {
+        let (mut ula, mut cpu) = test_machine();
+        let frames = vec![
+            // 2 IN instructions are executed in each frame
+            RzxFrame { fetch_count: 4, inputs: Box::new([1, 2, 3]) },
+            RzxFrame { fetch_count: 4, inputs: Box::new([4, 5]) },
+            RzxFrame { fetch_count: 4, inputs: Box::new([6]) },
+        ];
+        let mut player = RzxPlayer::new(RzxInputRecording { tstates: 100, frames });
+        let err = player.execute_next_frame(&mut ula, &mut cpu).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(ula.frame_tstate().1, 100 + 2 * (11 + 12));
+        assert_eq!(player.frame_index(), 1);
+        assert_eq!(cpu.get_pc(), 0x8000);
+        assert_eq!(cpu.get_acc(), 2);
+        assert!(player.execute_next_frame(&mut ula, &mut cpu).unwrap());
+        assert_eq!(cpu.get_acc(), 5);
+        // the missing input is read from the chipset
+        let err = player.execute_next_frame(&mut ula, &mut cpu).unwrap_err();
+        assert_eq!(err.to_string(), "RZX: playback out of sync");
+        assert_eq!(cpu.get_pc(), 0x8000);
+        assert!(player.is_done());
+        assert!(!player.execute_next_frame(&mut ula, &mut cpu).unwrap());
+    }
+
+    #[test]
+    fn rzx_recorder_with_disabled_interrupts_works() {
+        let (mut ula, mut cpu) = test_machine();
+        let mut recorder = RzxRecorder::new(&ula);
+        for _ in 0..2 {
+            recorder.execute_next_frame(&mut ula, &mut cpu);
+        }
+        assert_eq!(recorder.frames_count(), 2);
+        let recording = recorder.into_recording();
+        assert_eq!(recording.tstates, 0);
+        // a frame may end between the IN and JR instructions
+        for frame in recording.frames.iter() {
+            let fetches = usize::from(frame.fetch_count);
+            assert!(fetches > 0);
+            assert!(fetches / 2 <= frame.inputs.len() && frame.inputs.len() <= fetches - fetches / 2);
+        }
+    }
+}
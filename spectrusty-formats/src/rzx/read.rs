@@ -0,0 +1,249 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryInto;
+use std::borrow::Cow;
+use std::io::{ErrorKind, Error, Read, Result};
+
+#[cfg(feature = "compression")]
+use compression::prelude::*;
+
+use crate::ReadExactEx;
+use super::*;
+
+fn invalid_data<T>(msg: &'static str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, msg))
+}
+
+fn unexpected_eof<T>() -> Result<T> {
+    Err(Error::new(ErrorKind::UnexpectedEof, "RZX: unexpected end of block data"))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(data: &[u8], is_compressed: bool) -> Result<Cow<'_, [u8]>> {
+    if is_compressed {
+        data.iter().copied().decode(&mut ZlibDecoder::new())
+            .collect::<core::result::Result<Vec<_>, _>>()
+            .map(Cow::Owned)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+    else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(data: &[u8], is_compressed: bool) -> Result<Cow<'_, [u8]>> {
+    if is_compressed {
+        invalid_data("RZX: zlib compression is not supported")
+    }
+    else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+fn take_bytes<'a>(rd: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if rd.len() < len {
+        return unexpected_eof()
+    }
+    let (bytes, rest) = rd.split_at(len);
+    *rd = rest;
+    Ok(bytes)
+}
+
+fn take_u16(rd: &mut &[u8]) -> Result<u16> {
+    take_bytes(rd, 2).map(|word| u16::from_le_bytes(word.try_into().unwrap()))
+}
+
+fn take_u32(rd: &mut &[u8]) -> Result<u32> {
+    take_bytes(rd, 4).map(|dword| u32::from_le_bytes(dword.try_into().unwrap()))
+}
+
+fn parse_creator(mut rd: &[u8]) -> Result<RzxBlock> {
+    let rd = &mut rd;
+    let name = take_bytes(rd, 20)?;
+    let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    let major = take_u16(rd)?;
+    let minor = take_u16(rd)?;
+    Ok(RzxBlock::Creator { name: name[..name_len].into(), major, minor, custom: (*rd).into() })
+}
+
+fn parse_snapshot(mut rd: &[u8]) -> Result<RzxBlock> {
+    let rd = &mut rd;
+    let flags = take_u32(rd)?;
+    let ext = take_bytes(rd, 4)?.try_into().unwrap();
+    let length = take_u32(rd)?;
+    let external = flags & SNAPSHOT_EXTERNAL != 0;
+    let data = decompress(rd, flags & DATA_COMPRESSED != 0)?;
+    if !external && data.len() != length as usize {
+        return invalid_data("RZX: invalid snapshot length")
+    }
+    Ok(RzxBlock::Snapshot(RzxSnapshot { ext, external, data: data.into() }))
+}
+
+fn parse_frames(mut rd: &[u8], count: u32) -> Result<Vec<RzxFrame>> {
+    let rd = &mut rd;
+    let mut frames: Vec<RzxFrame> = Vec::with_capacity(count.min(0x10000) as usize);
+    for _ in 0..count {
+        let fetch_count = take_u16(rd)?;
+        let inputs = match take_u16(rd)? {
+            RZX_REPEAT_INPUTS => frames.last().map(|frame| frame.inputs.clone()).unwrap_or_default(),
+            in_count => take_bytes(rd, in_count.into())?.into()
+        };
+        frames.push(RzxFrame { fetch_count, inputs });
+    }
+    Ok(frames)
+}
+
+fn parse_input(mut rd: &[u8]) -> Result<RzxBlock> {
+    let rd = &mut rd;
+    let count = take_u32(rd)?;
+    let _reserved = take_bytes(rd, 1)?;
+    let tstates = take_u32(rd)?;
+    let flags = take_u32(rd)?;
+    if flags & INPUT_PROTECTED != 0 {
+        return invalid_data("RZX: encrypted input recordings are not supported")
+    }
+    let data = decompress(rd, flags & DATA_COMPRESSED != 0)?;
+    let frames = parse_frames(&data, count)?;
+    Ok(RzxBlock::Input(RzxInputRecording { tstates, frames }))
+}
+
+impl RzxBlock {
+    /// Reads the next block, including its id and length, from the given reader.
+    ///
+    /// Returns `Ok(None)` if there are no more blocks to be read.
+    pub fn read_block<R: Read>(mut rd: R) -> Result<Option<RzxBlock>> {
+        let rd = &mut rd;
+        let mut head = [0u8;5];
+        if !rd.read_exact_or_none(&mut head)? {
+            return Ok(None)
+        }
+        let id = head[0];
+        let length = u32::from_le_bytes(head[1..5].try_into().unwrap());
+        let length = match length.checked_sub(head.len() as u32) {
+            Some(length) => length,
+            None => return invalid_data("RZX: invalid block length")
+        };
+        let mut data = Vec::new();
+        rd.take(length.into()).read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return unexpected_eof()
+        }
+        let block = match id {
+            RZX_CREATOR_ID => parse_creator(&data)?,
+            RZX_SNAPSHOT_ID => parse_snapshot(&data)?,
+            RZX_INPUT_ID => parse_input(&data)?,
+            _ => RzxBlock::Unknown { id, data: data.into_boxed_slice() }
+        };
+        Ok(Some(block))
+    }
+}
+
+impl Rzx {
+    /// Reads the whole *RZX* file from the given reader.
+    pub fn read_from<R: Read>(mut rd: R) -> Result<Self> {
+        let mut header = [0u8;10];
+        rd.read_exact(&mut header)?;
+        if &header[0..4] != RZX_SIGNATURE {
+            return invalid_data("RZX: not an RZX file")
+        }
+        let (major, minor) = (header[4], header[5]);
+        if major != RZX_MAJOR_VERSION {
+            return invalid_data("RZX: unsupported major version")
+        }
+        let flags = u32::from_le_bytes(header[6..10].try_into().unwrap());
+        let mut blocks = Vec::new();
+        while let Some(block) = RzxBlock::read_block(rd.by_ref())? {
+            blocks.push(block);
+        }
+        Ok(Rzx { major, minor, flags, blocks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_block(flags: u32, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![RZX_INPUT_ID];
+        block.extend_from_slice(&(18 + data.len() as u32).to_le_bytes());
+        block.extend_from_slice(&3u32.to_le_bytes());
+        block.push(0);
+        block.extend_from_slice(&1234u32.to_le_bytes());
+        block.extend_from_slice(&flags.to_le_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    const FRAMES: [u8;14] = [
+        10, 0, 2, 0, 0xbf, 0x1f,
+        20, 0, 0xff, 0xff,
+        30, 0, 0, 0
+    ];
+
+    fn expected_recording() -> RzxBlock {
+        RzxBlock::Input(RzxInputRecording { tstates: 1234, frames: vec![
+            RzxFrame { fetch_count: 10, inputs: Box::new([0xbf, 0x1f]) },
+            RzxFrame { fetch_count: 20, inputs: Box::new([0xbf, 0x1f]) },
+            RzxFrame { fetch_count: 30, inputs: Box::new([]) }
+        ]})
+    }
+
+    #[test]
+    fn rzx_read_uncompressed_input_works() -> Result<()> {
+        let block = input_block(0, &FRAMES);
+        assert_eq!(RzxBlock::read_block(&block[..])?, Some(expected_recording()));
+        assert_eq!(RzxBlock::read_block(&[][..])?, None);
+        // the frames are missing their last byte
+        let block = input_block(0, &FRAMES[..13]);
+        let err = RzxBlock::read_block(&block[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        let block = input_block(INPUT_PROTECTED, &FRAMES);
+        let err = RzxBlock::read_block(&block[..]).unwrap_err();
+        assert_eq!(err.to_string(), "RZX: encrypted input recordings are not supported");
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn rzx_read_compressed_blocks_works() -> Result<()> {
+        let compress = |data: &[u8]| -> Vec<u8> {
+            data.iter().copied().encode(&mut ZlibEncoder::new(), Action::Finish)
+                .collect::<core::result::Result<_,_>>().unwrap()
+        };
+        let block = input_block(DATA_COMPRESSED, &compress(&FRAMES));
+        assert_eq!(RzxBlock::read_block(&block[..])?, Some(expected_recording()));
+        let snapshot: Vec<u8> = (0..100).collect();
+        let mut block = vec![RZX_SNAPSHOT_ID, 0, 0, 0, 0];
+        block.extend_from_slice(&DATA_COMPRESSED.to_le_bytes());
+        block.extend_from_slice(b"SNA\0");
+        block.extend_from_slice(&100u32.to_le_bytes());
+        block.extend_from_slice(&compress(&snapshot));
+        let len = block.len() as u32;
+        block[1..5].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(RzxBlock::read_block(&block[..])?, Some(RzxBlock::Snapshot(RzxSnapshot {
+            ext: *b"SNA\0", external: false, data: snapshot.into()
+        })));
+        // the uncompressed length doesn't match
+        block[13] = 99;
+        let err = RzxBlock::read_block(&block[..]).unwrap_err();
+        assert_eq!(err.to_string(), "RZX: invalid snapshot length");
+        // not a zlib stream
+        let block = input_block(DATA_COMPRESSED, &FRAMES);
+        assert_eq!(RzxBlock::read_block(&block[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn rzx_read_compressed_blocks_works() {
+        let block = input_block(DATA_COMPRESSED, &FRAMES);
+        let err = RzxBlock::read_block(&block[..]).unwrap_err();
+        assert_eq!(err.to_string(), "RZX: zlib compression is not supported");
+    }
+}
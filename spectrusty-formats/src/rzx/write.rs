@@ -0,0 +1,194 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryFrom;
+use std::borrow::Cow;
+use std::io::{ErrorKind, Error, Write, Result};
+
+#[cfg(feature = "compression")]
+use compression::prelude::*;
+
+use super::*;
+
+fn invalid_input<T>(msg: &'static str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidInput, msg))
+}
+
+#[cfg(feature = "compression")]
+fn compress(data: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    match data.iter().copied().encode(&mut ZlibEncoder::new(), Action::Finish)
+                     .collect::<core::result::Result<Vec<_>, _>>() {
+        Ok(buf) if buf.len() < data.len() => (Cow::Owned(buf), true),
+        _ => (Cow::Borrowed(data), false)
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(data: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    (Cow::Borrowed(data), false)
+}
+
+fn len_u32(len: usize) -> Result<u32> {
+    u32::try_from(len).or_else(|_| invalid_input("RZX: block data too large"))
+}
+
+fn creator_body(name: &[u8], major: u16, minor: u16, custom: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(24 + custom.len());
+    let mut name_buf = [0u8;20];
+    let len = name.len().min(name_buf.len() - 1);
+    name_buf[..len].copy_from_slice(&name[..len]);
+    body.extend_from_slice(&name_buf);
+    body.extend_from_slice(&major.to_le_bytes());
+    body.extend_from_slice(&minor.to_le_bytes());
+    body.extend_from_slice(custom);
+    body
+}
+
+fn snapshot_body(snapshot: &RzxSnapshot) -> Result<Vec<u8>> {
+    let (data, is_compressed) = if snapshot.external {
+        (Cow::Borrowed(&snapshot.data[..]), false)
+    }
+    else {
+        compress(&snapshot.data)
+    };
+    let mut flags = 0;
+    if snapshot.external {
+        flags |= SNAPSHOT_EXTERNAL;
+    }
+    if is_compressed {
+        flags |= DATA_COMPRESSED;
+    }
+    let mut body = Vec::with_capacity(12 + data.len());
+    body.extend_from_slice(&flags.to_le_bytes());
+    body.extend_from_slice(&snapshot.ext);
+    body.extend_from_slice(&len_u32(snapshot.data.len())?.to_le_bytes());
+    body.extend_from_slice(&data);
+    Ok(body)
+}
+
+fn frames_data(frames: &[RzxFrame]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut prev: Option<&[u8]> = None;
+    for frame in frames.iter() {
+        data.extend_from_slice(&frame.fetch_count.to_le_bytes());
+        let inputs = &frame.inputs[..];
+        if !inputs.is_empty() && prev == Some(inputs) {
+            data.extend_from_slice(&RZX_REPEAT_INPUTS.to_le_bytes());
+        }
+        else {
+            let in_count = match u16::try_from(inputs.len()) {
+                Ok(count) if count != RZX_REPEAT_INPUTS => count,
+                _ => return invalid_input("RZX: too many inputs in a frame")
+            };
+            data.extend_from_slice(&in_count.to_le_bytes());
+            data.extend_from_slice(inputs);
+        }
+        prev = Some(inputs);
+    }
+    Ok(data)
+}
+
+fn input_body(recording: &RzxInputRecording) -> Result<Vec<u8>> {
+    let frames = frames_data(&recording.frames)?;
+    let (data, is_compressed) = compress(&frames);
+    let flags = if is_compressed { DATA_COMPRESSED } else { 0 };
+    let mut body = Vec::with_capacity(13 + data.len());
+    body.extend_from_slice(&len_u32(recording.frames.len())?.to_le_bytes());
+    body.push(0);
+    body.extend_from_slice(&recording.tstates.to_le_bytes());
+    body.extend_from_slice(&flags.to_le_bytes());
+    body.extend_from_slice(&data);
+    Ok(body)
+}
+
+impl RzxBlock {
+    /// Writes this block, including its id and length, to the given writer.
+    ///
+    /// Snapshots and input recordings are being compressed with zlib only if the `compression`
+    /// feature is enabled.
+    pub fn write_block<W: Write>(&self, mut wr: W) -> Result<()> {
+        let body: Cow<'_, [u8]> = match self {
+            RzxBlock::Creator { name, major, minor, custom } => {
+                creator_body(name, *major, *minor, custom).into()
+            }
+            RzxBlock::Snapshot(snapshot) => snapshot_body(snapshot)?.into(),
+            RzxBlock::Input(recording) => input_body(recording)?.into(),
+            RzxBlock::Unknown { data, .. } => Cow::Borrowed(&data[..])
+        };
+        let length = len_u32(body.len() + 5)?;
+        wr.write_all(&[self.id()])?;
+        wr.write_all(&length.to_le_bytes())?;
+        wr.write_all(&body)
+    }
+}
+
+impl Rzx {
+    /// Writes the *RZX* header and all the blocks to the given writer.
+    pub fn write_to<W: Write>(&self, mut wr: W) -> Result<()> {
+        wr.write_all(RZX_SIGNATURE)?;
+        wr.write_all(&[self.major, self.minor])?;
+        wr.write_all(&self.flags.to_le_bytes())?;
+        for block in self.blocks.iter() {
+            block.write_block(wr.by_ref())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rzx_frames_data_works() -> Result<()> {
+        let frames = [
+            RzxFrame { fetch_count: 10, inputs: Box::new([1, 2]) },
+            RzxFrame { fetch_count: 20, inputs: Box::new([1, 2]) },
+            RzxFrame { fetch_count: 30, inputs: Box::new([]) },
+            RzxFrame { fetch_count: 40, inputs: Box::new([]) },
+        ];
+        assert_eq!(frames_data(&frames)?, [
+            10, 0, 2, 0, 1, 2,
+            20, 0, 0xff, 0xff,
+            30, 0, 0, 0,
+            40, 0, 0, 0
+        ]);
+        let frames = [RzxFrame { fetch_count: 1, inputs: vec![0; 0xFFFF].into() }];
+        let err = frames_data(&frames).unwrap_err();
+        assert_eq!(err.to_string(), "RZX: too many inputs in a frame");
+        Ok(())
+    }
+
+    #[test]
+    fn rzx_write_blocks_works() -> Result<()> {
+        let snapshot = RzxSnapshot { ext: *b"z80\0", external: true, data: vec![0; 300].into() };
+        let mut buf = Vec::new();
+        RzxBlock::Snapshot(snapshot).write_block(&mut buf)?;
+        // external snapshot descriptors are never compressed
+        assert_eq!(&buf[..17], b"\x30\x3d\x01\x00\x00\x01\x00\x00\x00z80\0\x2c\x01\x00\x00");
+        assert_eq!(buf.len(), 17 + 300);
+
+        let snapshot = RzxSnapshot { ext: *b"z80\0", external: false, data: vec![0; 300].into() };
+        let mut buf = Vec::new();
+        RzxBlock::Snapshot(snapshot.clone()).write_block(&mut buf)?;
+        let flags = u32::from_le_bytes([buf[5], buf[6], buf[7], buf[8]]);
+        if cfg!(feature = "compression") {
+            assert_eq!(flags, DATA_COMPRESSED);
+            assert!(buf.len() < 17 + 300);
+        }
+        else {
+            assert_eq!(flags, 0);
+            assert_eq!(buf.len(), 17 + 300);
+        }
+        assert_eq!(RzxBlock::read_block(&buf[..])?, Some(RzxBlock::Snapshot(snapshot)));
+
+        let mut buf = Vec::new();
+        RzxBlock::Unknown { id: 0x21, data: Box::new([1, 2, 3]) }.write_block(&mut buf)?;
+        assert_eq!(buf, [0x21, 8, 0, 0, 0, 1, 2, 3]);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **SCL** file format utilities.
+
+[Utilities][SclDiskExt] in this module provide additional methods to the [FloppyDisk] type
+with abilities to read and write **TR-DOS** disks from and to the **SCL** file format.
+
+**SCL** files are archives of **TR-DOS** files. Unlike [**TRD**][super::trd] files they contain only
+the catalogue entries and the data of the files that are not deleted:
+
+| offset  | size     | description                                                                   |
+|---------|----------|-------------------------------------------------------------------------------|
+|       0 |        8 | `"SINCLAIR"`.                                                                 |
+|       8 |        1 | The number of files: `n`.                                                     |
+|       9 |   14 * n | The catalogue entries: name (8), type (1), start (2), length (2), sectors (1). |
+| 9+14\*n | 256 \* s | The data of the files, `s` is the total number of sectors of all files.        |
+|       - |        4 | The checksum: a sum of all the previous bytes (LSB first).                    |
+
+When an **SCL** file is being read, the files are being stored one after another on a newly created
+80 cylinders, double sided **TR-DOS** disk.
+!*/
+use std::io::{self, Read, Write};
+
+use super::trd::{
+    TrdDiskExt, TRDOS_SECTORS, TRDOS_SECTOR_SIZE, TRDOS_MAX_FILES, TRDOS_CAT_ENTRY_SIZE,
+    TRDOS_SYSTEM_SECTOR
+};
+pub use super::trd::FloppyDisk;
+
+const SCL_SIG: &[u8;8] = b"SINCLAIR";
+const SCL_HEADER_SIZE: usize = 14;
+
+/// Extends [FloppyDisk] with methods for reading and writing **SCL** files.
+pub trait SclDiskExt: Sized {
+    /// Creates a new **TR-DOS** disk with the files read from the provided **SCL** file reader.
+    fn from_scl<R: Read>(rd: R) -> io::Result<Self>;
+    /// Writes the files found in the **TR-DOS** catalogue of the disk to an **SCL** file using a provided
+    /// writer. Deleted files are being omitted.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the disk is not a valid **TR-DOS** disk.
+    fn write_scl<W: Write>(&self, wr: W) -> io::Result<usize>;
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |sum, &b| sum.wrapping_add(u32::from(b)))
+}
+
+fn next_sector(track: &mut u8, sector: &mut u8) {
+    *sector += 1;
+    if *sector == TRDOS_SECTORS {
+        *sector = 0;
+        *track = track.wrapping_add(1);
+    }
+}
+
+fn catalog_entry_position(index: usize) -> (u8, usize) {
+    let sector = index / (TRDOS_SECTOR_SIZE / TRDOS_CAT_ENTRY_SIZE);
+    let offset = index % (TRDOS_SECTOR_SIZE / TRDOS_CAT_ENTRY_SIZE) * TRDOS_CAT_ENTRY_SIZE;
+    (sector as u8, offset)
+}
+
+fn invalid_disk() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "not a valid TR-DOS disk")
+}
+
+impl SclDiskExt for FloppyDisk {
+    fn from_scl<R: Read>(mut rd: R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        rd.read_to_end(&mut data)?;
+        if data.len() < SCL_SIG.len() + 1 || &data[..SCL_SIG.len()] != SCL_SIG {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "SCL format not recognized"))
+        }
+        let count = data[SCL_SIG.len()] as usize;
+        if count > TRDOS_MAX_FILES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many files in the SCL file"))
+        }
+        let headers_start = SCL_SIG.len() + 1;
+        let data_start = headers_start + count * SCL_HEADER_SIZE;
+        let headers = data.get(headers_start..data_start).ok_or_else(||
+            io::Error::new(io::ErrorKind::UnexpectedEof, "SCL catalogue is incomplete")
+        )?;
+        let total_sectors: usize = headers.chunks(SCL_HEADER_SIZE).map(|h| h[13] as usize).sum();
+        let data_end = data_start + total_sectors * TRDOS_SECTOR_SIZE;
+        if data.len() < data_end {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "SCL file data is incomplete"))
+        }
+        if let Some(sum) = data.get(data_end..data_end + 4) {
+            if checksum(&data[..data_end]).to_le_bytes() != sum {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "SCL checksum mismatch"))
+            }
+        }
+
+        let mut disk = FloppyDisk::new_trdos_formatted(b"");
+        let sys = disk.trdos_sector_ref(0, TRDOS_SYSTEM_SECTOR).unwrap();
+        let free_sectors = u16::from_le_bytes([sys[0xE5], sys[0xE6]]) as usize;
+        if total_sectors > free_sectors {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "SCL files don't fit on a TR-DOS disk"))
+        }
+        let (mut track, mut sector) = (1u8, 0u8);
+        let mut file_data = data[data_start..data_end].chunks(TRDOS_SECTOR_SIZE);
+        for (index, header) in headers.chunks(SCL_HEADER_SIZE).enumerate() {
+            let (cat_sector, offset) = catalog_entry_position(index);
+            let entry = &mut disk.trdos_sector_mut(0, cat_sector).unwrap()
+                                 [offset..offset + TRDOS_CAT_ENTRY_SIZE];
+            entry[..SCL_HEADER_SIZE].copy_from_slice(header);
+            entry[SCL_HEADER_SIZE] = sector;
+            entry[SCL_HEADER_SIZE + 1] = track;
+            for _ in 0..header[13] {
+                disk.trdos_sector_mut(track, sector).unwrap()
+                    .copy_from_slice(file_data.next().unwrap());
+                next_sector(&mut track, &mut sector);
+            }
+        }
+        let sys = disk.trdos_sector_mut(0, TRDOS_SYSTEM_SECTOR).unwrap();
+        sys[0xE1] = sector;
+        sys[0xE2] = track;
+        sys[0xE4] = count as u8;
+        sys[0xE5..0xE7].copy_from_slice(&((free_sectors - total_sectors) as u16).to_le_bytes());
+        Ok(disk)
+    }
+
+    fn write_scl<W: Write>(&self, mut wr: W) -> io::Result<usize> {
+        let mut entries = Vec::new();
+        for index in 0..TRDOS_MAX_FILES {
+            let (cat_sector, offset) = catalog_entry_position(index);
+            let entry = self.trdos_sector_ref(0, cat_sector).ok_or_else(invalid_disk)?
+                        .get(offset..offset + TRDOS_CAT_ENTRY_SIZE).unwrap();
+            match entry[0] {
+                0 => break,
+                1 => continue,
+                _ => entries.push(entry)
+            }
+        }
+        let mut head = Vec::with_capacity(SCL_SIG.len() + 1 + entries.len() * SCL_HEADER_SIZE);
+        head.extend_from_slice(SCL_SIG);
+        head.push(entries.len() as u8);
+        for entry in entries.iter() {
+            head.extend_from_slice(&entry[..SCL_HEADER_SIZE]);
+        }
+        wr.write_all(&head)?;
+        let mut sum = checksum(&head);
+        let mut bytes = head.len();
+        for entry in entries.iter() {
+            let (mut sector, mut track) = (entry[SCL_HEADER_SIZE], entry[SCL_HEADER_SIZE + 1]);
+            for _ in 0..entry[13] {
+                let data = self.trdos_sector_ref(track, sector).ok_or_else(invalid_disk)?;
+                wr.write_all(data)?;
+                sum = sum.wrapping_add(checksum(data));
+                bytes += data.len();
+                next_sector(&mut track, &mut sector);
+            }
+        }
+        wr.write_all(&sum.to_le_bytes())?;
+        Ok(bytes + 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn scl_file() -> Vec<u8> {
+        let mut scl = Vec::new();
+        scl.extend_from_slice(b"SINCLAIR\x02");
+        scl.extend_from_slice(b"boot    B\x10\x00\x10\x00\x01");
+        scl.extend_from_slice(b"data    C\x00\x80\x00\x44\x45");
+        scl.extend_from_slice(&[0xAA;256]);
+        for i in 0..0x45 {
+            scl.extend_from_slice(&[i;256]);
+        }
+        let sum = checksum(&scl);
+        scl.extend_from_slice(&sum.to_le_bytes());
+        scl
+    }
+
+    #[test]
+    fn scl_works() {
+        let scl = scl_file();
+        let disk = FloppyDisk::from_scl(Cursor::new(&scl)).unwrap();
+        let cat = disk.trdos_sector_ref(0, 0).unwrap();
+        assert_eq!(&cat[..16], b"boot    B\x10\x00\x10\x00\x01\x00\x01");
+        assert_eq!(&cat[16..32], b"data    C\x00\x80\x00\x44\x45\x01\x01");
+        assert_eq!(cat[32], 0);
+        let sys = disk.trdos_sector_ref(0, 8).unwrap();
+        assert_eq!(sys[0xE1..0xE8], [6, 5, 0x16, 2, 0xAA, 0x09, 0x10]);
+        assert_eq!(disk.trdos_sector_ref(1, 0).unwrap(), &[0xAA;256][..]);
+        assert_eq!(disk.trdos_sector_ref(1, 1).unwrap(), &[0;256][..]);
+        assert_eq!(disk.trdos_sector_ref(5, 5).unwrap(), &[0x44;256][..]);
+        assert_eq!(disk.trdos_sector_ref(5, 6).unwrap(), &[0;256][..]);
+
+        let mut buf = Vec::new();
+        assert_eq!(disk.write_scl(&mut buf).unwrap(), scl.len());
+        assert_eq!(buf, scl);
+
+        let mut disk = disk;
+        disk.trdos_sector_mut(0, 0).unwrap()[0] = 1;
+        let mut buf = Vec::new();
+        assert_eq!(disk.write_scl(&mut buf).unwrap(), 9 + 14 + 0x45 * 256 + 4);
+        assert_eq!(&buf[..23], b"SINCLAIR\x01data    C\x00\x80\x00\x44\x45");
+
+        let mut scl = scl;
+        let len = scl.len();
+        scl[len - 1] ^= 1;
+        assert_eq!(FloppyDisk::from_scl(Cursor::new(&scl)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(FloppyDisk::from_scl(Cursor::new(&scl[..len - 5])).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+        assert!(FloppyDisk::from_scl(Cursor::new(&scl[..len - 4])).is_ok());
+        scl[0] = b'Z';
+        assert_eq!(FloppyDisk::from_scl(Cursor::new(&scl)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -20,12 +20,17 @@ The type of the file is recognized by its total byte size:
 | 12289 | Hi-res screen data (primary + secondary) followed by the hi-res color information byte. |
 | 12353 | Hi-res screen data (primary + secondary + hi-res color) + 64 palette registers.         |
 
+
+The textual **ZXP** format is supported by [LoadZxp] and [SaveZxp].
 */
 use core::slice;
 use std::io::{self, Read, Write, Seek, SeekFrom};
 
 pub use spectrusty_core::memory::ScreenArray;
 
+mod zxp;
+pub use zxp::*;
+
 const PIXELS_SIZE: usize = 6144;
 const SCR_SIZE: u64 = 6912;
 const PALETTE_SIZE: u64 = 64;
@@ -0,0 +1,336 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::fmt::Write as _;
+use std::io::{self, Read, Write};
+
+use spectrusty_core::video::pixel_line_offset;
+
+use super::*;
+
+const ZXP_SIGNATURE: &str = "ZX-Paintbrush extended image";
+const PIXEL_LINES: usize = 192;
+const ATTR_ROWS: usize = 24;
+const COLUMNS: usize = 32;
+
+/// Utilities for loading **ZXP** files.
+///
+/// **ZXP** is a text format of the ZX-Paintbrush editor. The file starts with the
+/// `ZX-Paintbrush extended image` line followed by the sections separated by empty lines:
+///
+/// * 192 lines of pixels, each having 256 characters: `1` for INK or `0` for PAPER.
+/// * Lines of attributes as 2-digit hexadecimal numbers separated by spaces, 32 in each line:
+///   24 lines for the standard screen or 192 lines for the hi-color 8x1 attributes.
+///
+/// The following extensions are recognized to support all [ScrMode]s:
+///
+/// * Hi-res screens have 512 characters in each pixel line, and the attribute section is replaced
+///   with a single hexadecimal number of the hi-res color information byte.
+/// * An optional last section of 64 hexadecimal numbers contains ULAplus palette registers.
+///
+/// Methods of this trait are implemented automatically for types that implement [ScreenDataProvider].
+pub trait LoadZxp: ScreenDataProvider {
+    /// Attempts to read the `ZXP` file from the `src` and load it into the underlying implementation.
+    ///
+    /// # Errors
+    /// This function will return an error if a file is not recognized as a `ZXP` file
+    /// or the requested screen mode exceeds the capacity of the underlying implementation.
+    /// Other errors may also be returned from attempts to read the file.
+    fn load_zxp<R: Read>(&mut self, src: R) -> io::Result<()>;
+}
+
+/// Utilities for saving **ZXP** files.
+///
+/// See [LoadZxp] for the description of the format.
+///
+/// Methods of this trait are implemented automatically for types that implement [ScreenDataProvider].
+pub trait SaveZxp: ScreenDataProvider {
+    /// Attempts to save the screen from the underlying implementation and write as the `ZXP`
+    /// file into the `dst`.
+    ///
+    /// # Errors
+    /// This function may return an error from attempts to write the file.
+    fn save_zxp<W: Write>(&self, dst: W) -> io::Result<()>;
+}
+
+fn invalid_data<T>(msg: &'static str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn parse_pixels(line: &str, bytes: &mut [u8]) -> io::Result<()> {
+    let line = line.trim_end();
+    if line.len() != bytes.len() * 8 {
+        return invalid_data("Invalid ZXP pixel line length")
+    }
+    for (byte, chunk) in bytes.iter_mut().zip(line.as_bytes().chunks(8)) {
+        *byte = 0;
+        for ch in chunk.iter() {
+            *byte = match ch {
+                b'0' => *byte << 1,
+                b'1' => *byte << 1 | 1,
+                _ => return invalid_data("Invalid ZXP pixel character")
+            };
+        }
+    }
+    Ok(())
+}
+
+fn parse_hex<'a, I: IntoIterator<Item=&'a str>>(lines: I) -> io::Result<Vec<u8>> {
+    lines.into_iter().flat_map(str::split_whitespace)
+         .map(|s| u8::from_str_radix(s, 16).or_else(|_| invalid_data("Invalid ZXP hexadecimal number")))
+         .collect()
+}
+
+fn format_pixels(text: &mut String, bytes: &[u8]) {
+    for byte in bytes.iter() {
+        write!(text, "{:08b}", byte).unwrap();
+    }
+    text.push('\n');
+}
+
+fn format_hex(text: &mut String, bytes: &[u8]) {
+    for (i, byte) in bytes.iter().enumerate() {
+        if i != 0 {
+            text.push(' ');
+        }
+        write!(text, "{:02X}", byte).unwrap();
+    }
+    text.push('\n');
+}
+
+/// Returns the bytes of the pixel line `y` in hi-res mode, interleaved from both screens.
+fn hires_line(primary: &ScreenArray, secondary: &ScreenArray, y: usize) -> [u8;COLUMNS * 2] {
+    let offset = pixel_line_offset(y);
+    let mut line = [0u8;COLUMNS * 2];
+    for (pair, (&even, &odd)) in line.chunks_mut(2).zip(primary[offset..offset + COLUMNS].iter()
+                                                     .zip(secondary[offset..offset + COLUMNS].iter())) {
+        pair.copy_from_slice(&[even, odd]);
+    }
+    line
+}
+
+impl<T> LoadZxp for T where T: ScreenDataProvider {
+    fn load_zxp<R: Read>(&mut self, mut src: R) -> io::Result<()> {
+        let mut text = String::new();
+        src.read_to_string(&mut text)?;
+        let mut lines = text.lines().skip_while(|line| line.trim().is_empty());
+        match lines.next() {
+            Some(line) if line.trim() == ZXP_SIGNATURE => {}
+            _ => return invalid_data("ZXP screen format not recognized")
+        }
+        let mut sections: Vec<Vec<&str>> = Vec::new();
+        let mut is_new_section = true;
+        for line in lines {
+            if line.trim().is_empty() {
+                is_new_section = true;
+            }
+            else if is_new_section {
+                sections.push(vec![line]);
+                is_new_section = false;
+            }
+            else {
+                sections.last_mut().unwrap().push(line);
+            }
+        }
+        let (pixels, colors, palette) = match &sections[..] {
+            [pixels, colors] => (pixels, colors, None),
+            [pixels, colors, palette] => (pixels, colors, Some(parse_hex(palette.iter().copied())?)),
+            _ => return invalid_data("ZXP screen format not recognized")
+        };
+        if pixels.len() != PIXEL_LINES {
+            return invalid_data("Invalid number of ZXP pixel lines")
+        }
+        let is_hires = pixels[0].trim_end().len() == COLUMNS * 16;
+        let colors = parse_hex(colors.iter().copied())?;
+        let with_palette = palette.is_some();
+        let mode = match colors.len() {
+            _ if is_hires => match colors[..] {
+                [color] => ScrMode::HighRes(color, with_palette),
+                _ => return invalid_data("Invalid ZXP hi-res color information")
+            }
+            n if n == ATTR_ROWS * COLUMNS => ScrMode::Classic(with_palette),
+            n if n == PIXEL_LINES * COLUMNS => ScrMode::HighColor(with_palette),
+            _ => return invalid_data("Invalid number of ZXP attributes")
+        };
+        if let Some(palette) = palette.as_ref() {
+            if palette.len() != PALETTE_SIZE as usize {
+                return invalid_data("Invalid number of ZXP palette registers")
+            }
+        }
+        if !self.set_screen_mode(mode) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Screen format not supported"));
+        }
+        let mut line = [0u8;COLUMNS * 2];
+        for (y, pixels) in pixels.iter().enumerate() {
+            let offset = pixel_line_offset(y);
+            if is_hires {
+                parse_pixels(pixels, &mut line)?;
+                let primary = &mut self.screen_primary_mut()[offset..offset + COLUMNS];
+                for (tgt, pair) in primary.iter_mut().zip(line.chunks(2)) {
+                    *tgt = pair[0];
+                }
+                let secondary = &mut self.screen_secondary_mut()[offset..offset + COLUMNS];
+                for (tgt, pair) in secondary.iter_mut().zip(line.chunks(2)) {
+                    *tgt = pair[1];
+                }
+            }
+            else {
+                parse_pixels(pixels, &mut self.screen_primary_mut()[offset..offset + COLUMNS])?;
+            }
+        }
+        match mode {
+            ScrMode::Classic(..) => {
+                self.screen_primary_mut()[PIXELS_SIZE..].copy_from_slice(&colors);
+            }
+            ScrMode::HighColor(..) => {
+                let secondary = self.screen_secondary_mut();
+                for (y, attrs) in colors.chunks(COLUMNS).enumerate() {
+                    let offset = pixel_line_offset(y);
+                    secondary[offset..offset + COLUMNS].copy_from_slice(attrs);
+                }
+            }
+            ScrMode::HighRes(..) => {}
+        }
+        if let Some(palette) = palette {
+            self.screen_palette_mut().copy_from_slice(&palette);
+        }
+        Ok(())
+    }
+}
+
+impl<T> SaveZxp for T where T: ScreenDataProvider {
+    fn save_zxp<W: Write>(&self, mut dst: W) -> io::Result<()> {
+        let mode = self.get_screen_mode();
+        let mut text = String::with_capacity(64 * 1024);
+        text.push_str(ZXP_SIGNATURE);
+        text.push_str("\n\n");
+        let primary = self.screen_primary_ref();
+        for y in 0..PIXEL_LINES {
+            let offset = pixel_line_offset(y);
+            if let ScrMode::HighRes(..) = mode {
+                format_pixels(&mut text, &hires_line(primary, self.screen_secondary_ref(), y));
+            }
+            else {
+                format_pixels(&mut text, &primary[offset..offset + COLUMNS]);
+            }
+        }
+        text.push('\n');
+        match mode {
+            ScrMode::Classic(..) => {
+                for attrs in primary[PIXELS_SIZE..].chunks(COLUMNS) {
+                    format_hex(&mut text, attrs);
+                }
+            }
+            ScrMode::HighColor(..) => {
+                let secondary = self.screen_secondary_ref();
+                for y in 0..PIXEL_LINES {
+                    let offset = pixel_line_offset(y);
+                    format_hex(&mut text, &secondary[offset..offset + COLUMNS]);
+                }
+            }
+            ScrMode::HighRes(color, ..) => format_hex(&mut text, &[color])
+        }
+        if let ScrMode::Classic(true)|
+               ScrMode::HighColor(true)|
+               ScrMode::HighRes(.., true) = mode {
+            text.push('\n');
+            for regs in self.screen_palette_ref().chunks(8) {
+                format_hex(&mut text, regs);
+            }
+        }
+        dst.write_all(text.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestScreen {
+        mode: ScrMode,
+        primary: ScreenArray,
+        secondary: ScreenArray,
+        palette: [u8;PALETTE_SIZE as usize]
+    }
+
+    impl TestScreen {
+        fn new(mode: ScrMode, seed: usize) -> Self {
+            let mut primary = [0;6912];
+            let mut secondary = [0;6912];
+            for (i, (p, s)) in primary.iter_mut().zip(secondary.iter_mut()).enumerate() {
+                *p = (i * seed) as u8;
+                *s = ((i * seed) >> 3) as u8;
+            }
+            let mut palette = [0;PALETTE_SIZE as usize];
+            for (i, p) in palette.iter_mut().enumerate() {
+                *p = (i * seed) as u8;
+            }
+            TestScreen { mode, primary, secondary, palette }
+        }
+    }
+
+    impl ScreenDataProvider for TestScreen {
+        fn get_screen_mode(&self) -> ScrMode { self.mode }
+        fn set_screen_mode(&mut self, mode: ScrMode) -> bool { self.mode = mode; true }
+        fn screen_primary_ref(&self) -> &ScreenArray { &self.primary }
+        fn screen_primary_mut(&mut self) -> &mut ScreenArray { &mut self.primary }
+        fn screen_secondary_ref(&self) -> &ScreenArray { &self.secondary }
+        fn screen_secondary_mut(&mut self) -> &mut ScreenArray { &mut self.secondary }
+        fn screen_palette_ref(&self) -> &[u8;PALETTE_SIZE as usize] { &self.palette }
+        fn screen_palette_mut(&mut self) -> &mut [u8;PALETTE_SIZE as usize] { &mut self.palette }
+    }
+
+    #[test]
+    fn zxp_works() -> io::Result<()> {
+        for &mode in &[ScrMode::Classic(false), ScrMode::Classic(true),
+                       ScrMode::HighColor(false), ScrMode::HighColor(true),
+                       ScrMode::HighRes(0x18, false), ScrMode::HighRes(0x30, true)] {
+            let screen = TestScreen::new(mode, 7);
+            let mut buf = Vec::new();
+            screen.save_zxp(&mut buf)?;
+            assert!(buf.starts_with(b"ZX-Paintbrush extended image\n\n"));
+            let mut loaded = TestScreen::new(ScrMode::Classic(false), 0);
+            loaded.load_zxp(&buf[..])?;
+            assert_eq!(loaded.mode, mode);
+            let mut scr = Vec::new();
+            let mut scr_loaded = Vec::new();
+            screen.save_scr(&mut scr)?;
+            loaded.save_scr(&mut scr_loaded)?;
+            assert!(scr == scr_loaded);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn zxp_parse_works() -> io::Result<()> {
+        let mut text = String::from("ZX-Paintbrush extended image\r\n\r\n");
+        text.push_str("10000001");
+        text.push_str(&"0".repeat(248));
+        text.push_str("\r\n");
+        for _ in 1..192 {
+            text.push_str(&"0".repeat(256));
+            text.push_str("\r\n");
+        }
+        text.push_str("\r\n");
+        for _ in 0..24 {
+            text.push_str(&"38 ".repeat(32));
+            text.push_str("\r\n");
+        }
+        let mut screen = TestScreen::new(ScrMode::HighColor(true), 3);
+        screen.load_zxp(text.as_bytes())?;
+        assert_eq!(screen.mode, ScrMode::Classic(false));
+        assert_eq!(screen.primary[0], 0x81);
+        assert!(screen.primary[1..PIXELS_SIZE].iter().all(|&b| b == 0));
+        assert!(screen.primary[PIXELS_SIZE..].iter().all(|&b| b == 0x38));
+
+        let err = screen.load_zxp(&text.as_bytes()[..text.len() - 10]).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid number of ZXP attributes");
+        let err = screen.load_zxp(&b"ZX-Paintbrush image\n"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "ZXP screen format not recognized");
+        Ok(())
+    }
+}
@@ -311,10 +311,14 @@ pub fn save_sna<C: SnapshotCreator, W: Write>(
     let is_128 = match model {
         Spectrum48 => false,
         Spectrum128 => true,
-        SpectrumPlus2|SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|SpectrumSE => {
+        SpectrumPlus2|SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|SpectrumSE|Pentagon128 => {
             result.insert(SnapshotResult::MODEL_NSUP);
             true
         }
+        Pentagon512|Pentagon1024 => {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "SNA: can't create a snapshot of a computer with more than 128k of RAM"))
+        }
         Spectrum16|SpectrumNTSC|TimexTC2048|TimexTC2068|TimexTS2068 => {
             result.insert(SnapshotResult::MODEL_NSUP);
             false
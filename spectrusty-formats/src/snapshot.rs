@@ -15,7 +15,8 @@ use spectrusty_core::z80emu::{*, z80::*};
 use spectrusty_core::clock::{VideoTs, FTs};
 use spectrusty_core::chip::{
     ControlUnit, MemoryAccess, ReadEarMode,
-    Ula128MemFlags, Ula3CtrlFlags, ScldCtrlFlags
+    Ula128MemFlags, Ula3CtrlFlags, ScldCtrlFlags,
+    UlaPlusRegFlags, ColorMode
 };
 use spectrusty_core::video::{Video, BorderColor};
 use spectrusty_core::memory::{ZxMemory, ZxMemoryError};
@@ -36,6 +37,9 @@ pub enum ComputerModel {
     TimexTC2048,
     TimexTC2068,
     TimexTS2068,
+    Pentagon128,
+    Pentagon512,
+    Pentagon1024,
 }
 
 bitflags! {
@@ -127,9 +131,9 @@ pub enum MemoryRange {
     Ram(Range<usize>),
     /// Load into the Interface1 ROM.
     Interface1Rom,
-    /// Load into the MGT +D ROM.
+    /// Load into the MGT +D memory: 16kb of ROM and RAM as they appear at `0x0000` when paged in.
     PlusDRom,
-    /// Load into the MGT DISCiPLE ROM.
+    /// Load into the MGT DISCiPLE memory: 16kb of ROM and RAM as they appear at `0x0000` when paged in.
     DiscipleRom,
     /// Load into the Multiface ROM.
     MultifaceRom,
@@ -150,10 +154,14 @@ pub trait SnapshotCreator {
     fn joystick(&self) -> Option<JoystickModel> { None }
     fn ay_state(&self, _choice: Ay3_891xDevice) -> Option<(AyRegister, &[u8;16])> { None }
     fn ula128_flags(&self) -> Ula128MemFlags { unimplemented!() }
+    fn pentagon_mem_port(&self) -> u8 { 0 }
     fn ula3_flags(&self) -> Ula3CtrlFlags { unimplemented!() }
     fn timex_flags(&self) -> ScldCtrlFlags { unimplemented!() }
     fn timex_memory_banks(&self) -> u8 { unimplemented!() }
-    // fn ulaplus_flags(&self) -> UlaPlusRegFlags;
+    fn ulaplus_flags(&self) -> UlaPlusRegFlags { UlaPlusRegFlags::default() }
+    fn ulaplus_color_mode(&self) -> ColorMode { ColorMode::default() }
+    fn ulaplus_palette(&self) -> Option<&[u8;64]> { None }
+    fn plus3_disk_drives(&self) -> Option<u8> { None }
     fn is_interface1_rom_paged_in(&self) -> bool { unimplemented!() }
     fn is_plus_d_rom_paged_in(&self) -> bool { unimplemented!() }
     fn is_disciple_rom_paged_in(&self) -> bool { unimplemented!() }
@@ -238,6 +246,13 @@ pub trait SnapshotLoader {
     /// # Panics
     /// The default implementation always panics.
     fn plus_d_rom_paged_in(&mut self) { unimplemented!() }
+    /// Should page in the MGT DISCiPLE ROM if one is available.
+    ///
+    /// This method should not fail. If an MGT DISCiPLE is not supported [SnapshotLoader::select_model]
+    /// may return with an error if [Extensions] would indicate such support is being requested.
+    ///
+    /// The default implementation does nothing.
+    fn disciple_rom_paged_in(&mut self) {}
     /// Should page in the TR-DOS ROM if one is available.
     ///
     /// This method should not fail. If an TR-DOS is not supported [SnapshotLoader::select_model]
@@ -246,6 +261,17 @@ pub trait SnapshotLoader {
     /// # Panics
     /// The default implementation always panics.
     fn tr_dos_rom_paged_in(&mut self) { unimplemented!() }
+    /// Should attach the given number of `drives` to the +3 disk controller if one is available
+    /// and set the state of the disk motor.
+    ///
+    /// This method should not fail. Default implementation does nothing.
+    fn setup_plus3_disk_drives(&mut self, _drives: u8, _motor_on: bool) {}
+    /// Should store the decompressed `data` of the multi-load game `level` from the **SLT** extension
+    /// of the snapshot. The level data can be served with [SltLevels][crate::z80::SltLevels].
+    ///
+    /// This method should not fail. Default implementation ignores the data.
+    #[allow(clippy::boxed_local)]
+    fn setup_slt_level(&mut self, _level: u16, _data: Box<[u8]>) {}
 }
 
 /// Returns `true` if a `cpu` is safe for a snapshot using lossy formats.
@@ -299,15 +325,20 @@ impl ComputerModel {
             SpectrumNTSC => {
                 59136
             }
-            Spectrum16|Spectrum48|
-            TimexTC2048|TimexTC2068|TimexTS2068 => {
+            TimexTS2068 => {
+                59736
+            }
+            Spectrum16|Spectrum48|SpectrumSE|
+            TimexTC2048|TimexTC2068 => {
                 69888
             }
             Spectrum128|SpectrumPlus2|
-            SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
-            SpectrumSE=> {
+            SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e => {
                 70908
             }
+            Pentagon128|Pentagon512|Pentagon1024 => {
+                71680
+            }
         }
     }
     /// Returns `issue` or [ReadEarMode::Clear] when it does not apply to the model.
@@ -330,6 +361,7 @@ impl ComputerModel {
                     Err(ext & (Extensions::SAM_RAM|Extensions::IF1|Extensions::PLUS_D|Extensions::DISCIPLE))
             }
             TimexTC2068|TimexTS2068 if ext.intersects(Extensions::SAM_RAM) => Err(Extensions::SAM_RAM),
+            Pentagon128|Pentagon512|Pentagon1024 if ext.intersects(Extensions::SAM_RAM) => Err(Extensions::SAM_RAM),
             _ => Ok(())
         }
     }
@@ -351,6 +383,9 @@ impl From<ComputerModel> for &str {
             TimexTC2048    => "Timex TC2048",
             TimexTC2068    => "Timex TC2068",
             TimexTS2068    => "Timex TS2068",
+            Pentagon128    => "Pentagon 128k",
+            Pentagon512    => "Pentagon 512k",
+            Pentagon1024   => "Pentagon 1024k",
         }
     }
 }
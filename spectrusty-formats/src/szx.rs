@@ -0,0 +1,233 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! **SZX** (ZX-State) snapshot format utilities.
+//!
+//! See the specification reference on [Spectaculator](https://www.spectaculator.com/docs/zx-state/intro.shtml).
+//!
+//! ## Implementation specifics
+//!
+//! When reading from the **SZX** file:
+//!
+//! * Supported blocks are: `CRTR`, `Z80R`, `SPCR`, `RAMP`, `AY`, `KEYB`, `JOY`, `IF1`, `PLTT`,
+//!   `SCLD`, `B128` and `+3`. Other blocks are being ignored, including the disk and microdrive
+//!   image blocks.
+//! * The joystick of the first player from the `JOY` block takes precedence over the keyboard
+//!   joystick from the `KEYB` block.
+//! * Pentagon, Scorpion and Spectrum 128Ke machines are not supported.
+//!
+//! When writing to the **SZX** file:
+//!
+//! * ROMs are not being saved.
+//! * Memory pages are being compressed with zlib only if the `compression` feature is enabled.
+//! * Handling of MGT +D, DISCiPLE, or SamRam is currently not implemented.
+mod common;
+mod loader;
+mod saver;
+
+pub use loader::*;
+pub use saver::*;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use spectrusty_core::z80emu::{Cpu, StkReg16, Prefix, InterruptMode, Z80NMOS};
+    use spectrusty_core::clock::FTs;
+    use spectrusty_core::chip::{ReadEarMode, Ula128MemFlags, Ula3CtrlFlags, UlaPlusRegFlags, ColorMode};
+    use spectrusty_core::memory::ZxMemoryError;
+    use spectrusty_core::video::BorderColor;
+    use spectrusty_peripherals::ay::AyRegister;
+    use crate::snapshot::*;
+    use super::*;
+
+    #[derive(Default)]
+    struct TestSnapshot {
+        model: Option<ComputerModel>,
+        extensions: Extensions,
+        border: Option<BorderColor>,
+        issue: Option<ReadEarMode>,
+        cpu: Option<CpuModel>,
+        clock: FTs,
+        ram: Vec<u8>,
+        joystick: Option<JoystickModel>,
+        ay: Option<(Ay3_891xDevice, AyRegister, [u8;16])>,
+        ports: Vec<(u16, u8)>,
+        palette: Vec<u8>,
+        plus3_drives: Option<(u8, bool)>,
+    }
+
+    impl SnapshotCreator for TestSnapshot {
+        fn model(&self) -> ComputerModel { self.model.unwrap() }
+        fn extensions(&self) -> Extensions { self.extensions }
+        fn cpu(&self) -> CpuModel { self.cpu.clone().unwrap() }
+        fn current_clock(&self) -> FTs { self.clock }
+        fn border_color(&self) -> BorderColor { self.border.unwrap() }
+        fn issue(&self) -> ReadEarMode { self.issue.unwrap() }
+        fn memory_ref(&self, range: MemoryRange) -> Result<&[u8], ZxMemoryError> {
+            match range {
+                MemoryRange::Ram(range) => Ok(&self.ram[range]),
+                _ => Err(ZxMemoryError::UnsupportedExRomPaging)
+            }
+        }
+        fn joystick(&self) -> Option<JoystickModel> { self.joystick }
+        fn ay_state(&self, choice: Ay3_891xDevice) -> Option<(AyRegister, &[u8;16])> {
+            match &self.ay {
+                Some((dev, reg, regs)) if *dev == choice => Some((*reg, regs)),
+                _ => None
+            }
+        }
+        fn ula128_flags(&self) -> Ula128MemFlags { Ula128MemFlags::from_bits_truncate(0x13) }
+        fn ula3_flags(&self) -> Ula3CtrlFlags { Ula3CtrlFlags::from_bits_truncate(0x0c) }
+        fn ulaplus_flags(&self) -> UlaPlusRegFlags { UlaPlusRegFlags::from_bits_truncate(0x2a) }
+        fn ulaplus_color_mode(&self) -> ColorMode { ColorMode::PALETTE }
+        fn ulaplus_palette(&self) -> Option<&[u8;64]> {
+            use core::convert::TryInto;
+            self.palette[..].try_into().ok()
+        }
+        fn plus3_disk_drives(&self) -> Option<u8> { self.plus3_drives.map(|(drives, _)| drives) }
+        fn is_interface1_rom_paged_in(&self) -> bool { false }
+    }
+
+    impl SnapshotLoader for TestSnapshot {
+        type Error = &'static str;
+        fn select_model(
+                &mut self,
+                model: ComputerModel,
+                extensions: Extensions,
+                border: BorderColor,
+                issue: ReadEarMode
+            ) -> Result<(), Self::Error>
+        {
+            self.model = Some(model);
+            self.extensions = extensions;
+            self.border = Some(border);
+            self.issue = Some(issue);
+            Ok(())
+        }
+        fn read_into_memory<R: Read>(&mut self, range: MemoryRange, mut reader: R) -> Result<(), ZxMemoryError> {
+            match range {
+                MemoryRange::Ram(range) => {
+                    if self.ram.len() < range.end {
+                        self.ram.resize(range.end, 0);
+                    }
+                    reader.read_exact(&mut self.ram[range]).map_err(ZxMemoryError::Io)
+                }
+                _ => Err(ZxMemoryError::UnsupportedExRomPaging)
+            }
+        }
+        fn assign_cpu(&mut self, cpu: CpuModel) { self.cpu = Some(cpu) }
+        fn set_clock(&mut self, tstates: FTs) { self.clock = tstates }
+        fn write_port(&mut self, port: u16, data: u8) { self.ports.push((port, data)) }
+        fn select_joystick(&mut self, joystick: JoystickModel) { self.joystick = Some(joystick) }
+        fn setup_ay(&mut self, choice: Ay3_891xDevice, reg_selected: AyRegister, reg_values: &[u8;16]) {
+            self.ay = Some((choice, reg_selected, *reg_values));
+        }
+        fn setup_plus3_disk_drives(&mut self, drives: u8, motor_on: bool) {
+            self.plus3_drives = Some((drives, motor_on));
+        }
+    }
+
+    fn test_cpu() -> Z80NMOS {
+        let mut cpu = Z80NMOS::default();
+        cpu.reset();
+        cpu.set_reg16(StkReg16::BC, 0x1234);
+        cpu.set_reg16(StkReg16::DE, 0x5678);
+        cpu.set_reg16(StkReg16::HL, 0x9abc);
+        cpu.set_index16(Prefix::Xdd, 0xdead);
+        cpu.set_index16(Prefix::Yfd, 0x5c3a);
+        cpu.set_sp(0xfffe);
+        cpu.set_pc(0x8000);
+        cpu.set_i(0x3f);
+        cpu.set_r(0x85);
+        cpu.set_im(InterruptMode::Mode2);
+        cpu.enable_interrupts();
+        cpu.set_memptr(0x4321);
+        cpu
+    }
+
+    #[test]
+    fn szx_round_trip_works() -> std::io::Result<()> {
+        let snapshot = TestSnapshot {
+            model: Some(ComputerModel::SpectrumPlus3),
+            extensions: Extensions::ULA_PLUS,
+            border: Some(BorderColor::CYAN),
+            issue: Some(ReadEarMode::Clear),
+            cpu: Some(CpuModel::NMOS(test_cpu())),
+            clock: 12345,
+            ram: (0..8*0x4000).map(|n| (n / 0x4000 * 17 + n % 3) as u8).collect(),
+            joystick: Some(JoystickModel::Sinclair2),
+            ay: Some((Ay3_891xDevice::Ay128k, AyRegister::from(7), [3;16])),
+            palette: (0..64).collect(),
+            plus3_drives: Some((2, false)),
+            ..TestSnapshot::default()
+        };
+        let mut buf = Vec::new();
+        assert_eq!(save_szx(&snapshot, &mut buf)?, SnapshotResult::OK);
+        assert_eq!(&buf[0..8], b"ZXST\x01\x04\x05\x00");
+
+        let mut loaded = TestSnapshot::default();
+        load_szx(Cursor::new(buf), &mut loaded)?;
+        assert_eq!(loaded.model, snapshot.model);
+        assert_eq!(loaded.extensions, snapshot.extensions);
+        assert_eq!(loaded.border, snapshot.border);
+        assert_eq!(loaded.issue, snapshot.issue);
+        assert_eq!(loaded.cpu, snapshot.cpu);
+        assert_eq!(loaded.clock, 12345);
+        assert!(loaded.ram == snapshot.ram);
+        assert_eq!(loaded.joystick, snapshot.joystick);
+        assert_eq!(loaded.ay, snapshot.ay);
+        assert_eq!(loaded.plus3_drives, Some((2, true)));
+        assert_eq!(&loaded.ports[0..2], [(0x1ffd, 0x0c), (0x7ffd, 0x13)]);
+        let palette: Vec<_> = loaded.ports[2..130].chunks(2).map(|ports| {
+            assert_eq!(ports[0].0, 0xbf3b);
+            assert_eq!(ports[1].0, 0xff3b);
+            assert_eq!(ports[0].1 & 0xc0, 0);
+            ports[1].1
+        }).collect();
+        assert_eq!(palette, snapshot.palette);
+        assert_eq!(&loaded.ports[130..], [(0xbf3b, 0x40), (0xff3b, 0x01), (0xbf3b, 0x2a)]);
+        Ok(())
+    }
+
+    #[test]
+    fn szx_skips_missing_ulaplus_palette() -> std::io::Result<()> {
+        let snapshot = TestSnapshot {
+            model: Some(ComputerModel::Spectrum48),
+            extensions: Extensions::ULA_PLUS,
+            border: Some(BorderColor::BLUE),
+            issue: Some(ReadEarMode::Issue3),
+            cpu: Some(CpuModel::NMOS(test_cpu())),
+            ram: vec![0;3*0x4000],
+            ..TestSnapshot::default()
+        };
+        let mut buf = Vec::new();
+        assert_eq!(save_szx(&snapshot, &mut buf)?, SnapshotResult::OK);
+        assert!(!buf.windows(4).any(|id| id == b"PLTT"));
+
+        let mut loaded = TestSnapshot::default();
+        load_szx(Cursor::new(buf), &mut loaded)?;
+        assert_eq!(loaded.model, snapshot.model);
+        assert_eq!(loaded.extensions, Extensions::NONE);
+        assert!(loaded.ports.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn szx_load_errors_work() {
+        let mut loader = TestSnapshot::default();
+        let err = load_szx(&b"ZXST\x02\x00\x01\x00"[..], &mut loader).unwrap_err();
+        assert_eq!(err.to_string(), "SZX: unsupported version");
+        let err = load_szx(&b"ZXSX\x01\x04\x01\x00"[..], &mut loader).unwrap_err();
+        assert_eq!(err.to_string(), "SZX: not a ZX-State file");
+        let err = load_szx(&b"ZXST\x01\x04\x10\x00"[..], &mut loader).unwrap_err();
+        assert_eq!(err.to_string(), "SZX: unsupported machine");
+        let err = load_szx(&b"ZXST\x01\x04\x01\x00"[..], &mut loader).unwrap_err();
+        assert_eq!(err.to_string(), "SZX: missing Z80R block");
+        let err = load_szx(&b"ZXST\x01\x04\x01\x00Z80R\x25\x00\x00\x00"[..], &mut loader).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,459 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryFrom;
+use core::mem::size_of;
+use core::ops::Range;
+use std::io::{self, Read, Write, Result};
+
+use bitflags::bitflags;
+
+use crate::{StructRead, StructWrite};
+use crate::snapshot::*;
+
+pub const PAGE_SIZE: usize = 0x4000;
+
+pub const SZX_MAGIC: &[u8;4] = b"ZXST";
+pub const SZX_MAJOR_VERSION: u8 = 1;
+pub const SZX_MINOR_VERSION: u8 = 4;
+
+pub const CREATOR_ID: &[u8;4] = b"CRTR";
+pub const Z80_REGS_ID: &[u8;4] = b"Z80R";
+pub const SPEC_REGS_ID: &[u8;4] = b"SPCR";
+pub const RAM_PAGE_ID: &[u8;4] = b"RAMP";
+pub const AY_ID: &[u8;4] = b"AY\0\0";
+pub const KEYBOARD_ID: &[u8;4] = b"KEYB";
+pub const JOYSTICK_ID: &[u8;4] = b"JOY\0";
+pub const IF1_ID: &[u8;4] = b"IF1\0";
+pub const PALETTE_ID: &[u8;4] = b"PLTT";
+pub const SCLD_ID: &[u8;4] = b"SCLD";
+pub const BETA128_ID: &[u8;4] = b"B128";
+pub const PLUS3_ID: &[u8;4] = b"+3\0\0";
+
+/// The joystick type indicating no joystick.
+pub const JOYSTICK_NONE: u8 = 8;
+/// The number of microdrives reported in the `IF1` block.
+pub const IF1_MICRODRIVES: u8 = 8;
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Header {
+    pub magic: [u8;4],
+    pub major: u8,
+    pub minor: u8,
+    pub machine_id: u8,
+    pub flags: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct BlockHeader {
+    pub id: [u8;4],
+    pub size: [u8;4]
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Creator {
+    pub name: [u8;32],
+    pub major: [u8;2],
+    pub minor: [u8;2]
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Z80Regs {
+    pub af: [u8;2],
+    pub bc: [u8;2],
+    pub de: [u8;2],
+    pub hl: [u8;2],
+    pub af_alt: [u8;2],
+    pub bc_alt: [u8;2],
+    pub de_alt: [u8;2],
+    pub hl_alt: [u8;2],
+    pub ix: [u8;2],
+    pub iy: [u8;2],
+    pub sp: [u8;2],
+    pub pc: [u8;2],
+    pub i: u8,
+    pub r: u8,
+    pub iff1: u8,
+    pub iff2: u8,
+    pub im: u8,
+    pub cycles_start: [u8;4],
+    pub hold_int_req_cycles: u8,
+    pub flags: u8,
+    pub memptr: [u8;2]
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct SpecRegs {
+    pub border: u8,
+    pub port_7ffd: u8,
+    pub port_1ffd: u8,
+    pub port_fe: u8,
+    pub reserved: [u8;4]
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct RamPageHeader {
+    pub flags: [u8;2],
+    pub page: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct AyState {
+    pub flags: u8,
+    pub current_reg: u8,
+    pub regs: [u8;16]
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Keyboard {
+    pub flags: [u8;4],
+    pub joystick: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Joystick {
+    pub flags: [u8;4],
+    pub player1: u8,
+    pub player2: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct If1Header {
+    pub flags: [u8;2],
+    pub microdrives: u8,
+    pub reserved: [u8;3],
+    pub reserved_dw: [u8;32],
+    pub rom_size: [u8;2]
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Palette {
+    pub flags: u8,
+    pub current_reg: u8,
+    pub regs: [u8;64],
+    pub mode: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct ScldRegs {
+    pub port_ff: u8,
+    pub port_f4: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Beta128 {
+    pub flags: [u8;4],
+    pub drives: u8,
+    pub sys_reg: u8,
+    pub track: u8,
+    pub sector: u8,
+    pub data: u8,
+    pub status: u8
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct Plus3 {
+    pub drives: u8,
+    pub motor_on: u8
+}
+
+// Structs must be packed and consist of `u8` or/and arrays of `u8` primitives only.
+unsafe impl StructRead for Header {}
+unsafe impl StructRead for BlockHeader {}
+unsafe impl StructRead for Creator {}
+unsafe impl StructRead for Z80Regs {}
+unsafe impl StructRead for SpecRegs {}
+unsafe impl StructRead for RamPageHeader {}
+unsafe impl StructRead for AyState {}
+unsafe impl StructRead for Keyboard {}
+unsafe impl StructRead for Joystick {}
+unsafe impl StructRead for If1Header {}
+unsafe impl StructRead for Palette {}
+unsafe impl StructRead for ScldRegs {}
+unsafe impl StructRead for Beta128 {}
+unsafe impl StructRead for Plus3 {}
+unsafe impl StructWrite for Header {}
+unsafe impl StructWrite for BlockHeader {}
+unsafe impl StructWrite for Creator {}
+unsafe impl StructWrite for Z80Regs {}
+unsafe impl StructWrite for SpecRegs {}
+unsafe impl StructWrite for RamPageHeader {}
+unsafe impl StructWrite for AyState {}
+unsafe impl StructWrite for Keyboard {}
+unsafe impl StructWrite for Joystick {}
+unsafe impl StructWrite for If1Header {}
+unsafe impl StructWrite for Palette {}
+unsafe impl StructWrite for ScldRegs {}
+unsafe impl StructWrite for Beta128 {}
+unsafe impl StructWrite for Plus3 {}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette { flags: 0, current_reg: 0, regs: [0;64], mode: 0 }
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Z80Flags: u8 {
+        const EILAST = 0b0000_0001;
+        const HALTED = 0b0000_0010;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct RamPageFlags: u16 {
+        const COMPRESSED = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct AyFlags: u8 {
+        const FULLER_BOX = 0b0000_0001;
+        const AY_128K    = 0b0000_0010;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct KeyboardFlags: u32 {
+        const ISSUE2 = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct If1Flags: u16 {
+        const ENABLED    = 0b0000_0001;
+        const COMPRESSED = 0b0000_0010;
+        const PAGED      = 0b0000_0100;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct PaletteFlags: u8 {
+        const ENABLED = 0b0000_0001;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Beta128Flags: u32 {
+        const CONNECTED  = 0b0000_0001;
+        const CUSTOM_ROM = 0b0000_0010;
+        const PAGED      = 0b0000_0100;
+    }
+}
+
+/// A single **SZX** block with its data.
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub id: [u8;4],
+    pub data: Vec<u8>
+}
+
+impl Block {
+    /// Reads the data of the block as a struct `T`.
+    ///
+    /// If the block is shorter than the struct (e.g. written by an older version of the format)
+    /// the remaining fields are left with their default values.
+    pub fn read_struct<T: StructRead + Default>(&self) -> Result<T> {
+        let mut obj = T::default();
+        let limit = self.data.len().min(size_of::<T>());
+        obj.read_struct_with_limit(&self.data[..], limit)?;
+        Ok(obj)
+    }
+    /// Returns the data following the struct `T` at the beginning of the block.
+    pub fn data_after<T>(&self) -> &[u8] {
+        self.data.get(size_of::<T>()..).unwrap_or(&[])
+    }
+}
+
+/// Reads the next block from `rd`. Returns `Ok(None)` if there are no more blocks.
+pub fn read_block<R: Read>(mut rd: R) -> Result<Option<Block>> {
+    let mut head = BlockHeader::default();
+    if !head.read_struct_or_nothing(rd.by_ref())? {
+        return Ok(None)
+    }
+    let size = u32::from_le_bytes(head.size);
+    let mut data = Vec::new();
+    rd.take(size.into()).read_to_end(&mut data)?;
+    if data.len() != size as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "SZX: unexpected end of block data"))
+    }
+    Ok(Some(Block { id: head.id, data }))
+}
+
+/// Writes a block header followed by the `parts` of the block data to `wr`.
+pub fn write_block<W: Write>(mut wr: W, id: &[u8;4], parts: &[&[u8]]) -> Result<()> {
+    let size = parts.iter().map(|part| part.len()).sum::<usize>();
+    let size = u32::try_from(size).map_err(|_|
+        io::Error::new(io::ErrorKind::InvalidInput, "SZX: block data too large")
+    )?;
+    BlockHeader { id: *id, size: size.to_le_bytes() }.write_struct(wr.by_ref())?;
+    for part in parts {
+        wr.write_all(part)?;
+    }
+    Ok(())
+}
+
+/// Returns a struct as a slice of bytes.
+pub fn struct_bytes<T: StructWrite>(obj: &T) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(size_of::<T>());
+    obj.write_struct(&mut buf).unwrap();
+    buf
+}
+
+pub fn model_to_machine_id(model: ComputerModel) -> u8 {
+    use ComputerModel::*;
+    match model {
+        Spectrum16     => 0,
+        Spectrum48     => 1,
+        Spectrum128    => 2,
+        SpectrumPlus2  => 3,
+        SpectrumPlus2A => 4,
+        SpectrumPlus3  => 5,
+        SpectrumPlus3e => 6,
+        TimexTC2048    => 8,
+        TimexTC2068    => 9,
+        SpectrumSE     => 11,
+        TimexTS2068    => 12,
+        Pentagon128    => 7,
+        Pentagon512    => 13,
+        Pentagon1024   => 14,
+        SpectrumNTSC   => 15,
+    }
+}
+
+pub fn machine_id_to_model(machine_id: u8) -> Option<ComputerModel> {
+    use ComputerModel::*;
+    Some(match machine_id {
+         0 => Spectrum16,
+         1 => Spectrum48,
+         2 => Spectrum128,
+         3 => SpectrumPlus2,
+         4 => SpectrumPlus2A,
+         5 => SpectrumPlus3,
+         6 => SpectrumPlus3e,
+         7 => Pentagon128,
+         8 => TimexTC2048,
+         9 => TimexTC2068,
+         // 10 => Scorpion256,
+        11 => SpectrumSE,
+        12 => TimexTS2068,
+        13 => Pentagon512,
+        14 => Pentagon1024,
+        15 => SpectrumNTSC,
+         // 16 => Spectrum128Ke,
+         _ => return None
+    })
+}
+
+/// Returns the **SZX** RAM page numbers of the given `model` with their memory ranges.
+pub fn ram_pages(model: ComputerModel) -> impl Iterator<Item=(u8, Range<usize>)> {
+    use ComputerModel::*;
+    // pages of 48k models are mapped in the order of their memory addresses,
+    // otherwise page numbers are the same as bank numbers
+    let (pages, banks): (&[u8], u8) = match model {
+        Spectrum16 => (&[5], 0),
+        Spectrum48|SpectrumNTSC|
+        TimexTC2048|TimexTC2068|TimexTS2068 => (&[5, 2, 0], 0),
+        Spectrum128|SpectrumPlus2|
+        SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
+        Pentagon128 => (&[], 8),
+        SpectrumSE => (&[], 17),
+        Pentagon512 => (&[], 32),
+        Pentagon1024 => (&[], 64)
+    };
+    let page_range = |bank: usize| bank * PAGE_SIZE..(bank + 1) * PAGE_SIZE;
+    pages.iter().enumerate().map(move |(index, &page)| (page, page_range(index)))
+    .chain((0..banks).map(move |page| (page, page_range(page as usize))))
+}
+
+/// Returns the memory range of the **SZX** RAM `page` of the given `model`.
+pub fn ram_page_range(page: u8, model: ComputerModel) -> Option<Range<usize>> {
+    ram_pages(model).find(|(p, _)| *p == page).map(|(_, range)| range)
+}
+
+pub fn joystick_to_type(joystick: JoystickModel) -> u8 {
+    match joystick {
+        JoystickModel::Kempston  => 0,
+        JoystickModel::Fuller    => 1,
+        JoystickModel::Cursor    => 2,
+        JoystickModel::Sinclair1 => 3,
+        JoystickModel::Sinclair2 => 4,
+    }
+}
+
+pub fn type_to_joystick(joy_type: u8) -> Option<JoystickModel> {
+    Some(match joy_type {
+        0 => JoystickModel::Kempston,
+        1 => JoystickModel::Fuller,
+        2 => JoystickModel::Cursor,
+        3 => JoystickModel::Sinclair1,
+        4 => JoystickModel::Sinclair2,
+        _ => return None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn szx_ram_pages_works() {
+        use ComputerModel::*;
+        assert_eq!(ram_pages(Spectrum16).collect::<Vec<_>>(), [(5, 0..0x4000)]);
+        assert_eq!(ram_pages(Spectrum48).collect::<Vec<_>>(),
+                   [(5, 0..0x4000), (2, 0x4000..0x8000), (0, 0x8000..0xC000)]);
+        assert_eq!(ram_page_range(7, Spectrum128), Some(0x1C000..0x20000));
+        assert_eq!(ram_page_range(7, Spectrum48), None);
+        assert_eq!(ram_page_range(15, SpectrumSE), Some(0x3C000..0x40000));
+        assert_eq!(ram_page_range(16, SpectrumSE), Some(0x40000..0x44000));
+        assert_eq!(ram_page_range(17, SpectrumSE), None);
+        assert_eq!(ram_pages(Pentagon128).count(), 8);
+        assert_eq!(ram_page_range(31, Pentagon512), Some(0x7C000..0x80000));
+        assert_eq!(ram_page_range(32, Pentagon512), None);
+        assert_eq!(ram_page_range(63, Pentagon1024), Some(0xFC000..0x100000));
+        for id in 0..=16 {
+            if let Some(model) = machine_id_to_model(id) {
+                assert_eq!(model_to_machine_id(model), id);
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryFrom;
+use std::borrow::Cow;
+use std::io::{self, Read, Result};
+
+#[cfg(feature = "compression")]
+use compression::prelude::*;
+
+use spectrusty_core::z80emu::{Cpu, CpuFlags, InterruptMode, Prefix, StkReg16, Z80NMOS};
+use spectrusty_core::chip::{ReadEarMode, UlaPlusRegFlags};
+use spectrusty_core::clock::FTs;
+use spectrusty_core::video::BorderColor;
+
+use crate::StructRead;
+use crate::snapshot::*;
+use super::common::*;
+
+fn invalid_data<T>(msg: &'static str) -> Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(data: &[u8], is_compressed: bool) -> Result<Cow<'_, [u8]>> {
+    if is_compressed {
+        data.iter().copied().decode(&mut ZlibDecoder::new())
+            .collect::<core::result::Result<Vec<_>, _>>()
+            .map(Cow::Owned)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(data: &[u8], is_compressed: bool) -> Result<Cow<'_, [u8]>> {
+    if is_compressed {
+        invalid_data("SZX: zlib compression is not supported")
+    }
+    else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+fn create_cpu(regs: &Z80Regs) -> Result<Z80NMOS> {
+    let mut cpu = Z80NMOS::default();
+    cpu.reset();
+    cpu.set_reg16(StkReg16::HL, u16::from_le_bytes(regs.hl_alt));
+    cpu.set_reg16(StkReg16::DE, u16::from_le_bytes(regs.de_alt));
+    cpu.set_reg16(StkReg16::BC, u16::from_le_bytes(regs.bc_alt));
+    cpu.exx();
+    let [f_alt, a_alt] = regs.af_alt;
+    cpu.set_acc(a_alt);
+    cpu.set_flags(CpuFlags::from_bits_truncate(f_alt));
+    cpu.ex_af_af();
+    let [f, a] = regs.af;
+    cpu.set_acc(a);
+    cpu.set_flags(CpuFlags::from_bits_truncate(f));
+    cpu.set_reg16(StkReg16::HL, u16::from_le_bytes(regs.hl));
+    cpu.set_reg16(StkReg16::DE, u16::from_le_bytes(regs.de));
+    cpu.set_reg16(StkReg16::BC, u16::from_le_bytes(regs.bc));
+    cpu.set_index16(Prefix::Yfd, u16::from_le_bytes(regs.iy));
+    cpu.set_index16(Prefix::Xdd, u16::from_le_bytes(regs.ix));
+    cpu.set_sp(u16::from_le_bytes(regs.sp));
+    cpu.set_pc(u16::from_le_bytes(regs.pc));
+    cpu.set_i(regs.i);
+    cpu.set_r(regs.r);
+    cpu.set_im(InterruptMode::try_from(regs.im).or_else(|_|
+        invalid_data("SZX: invalid interrupt mode")
+    )?);
+    let flags = Z80Flags::from_bits_truncate(regs.flags);
+    if flags.intersects(Z80Flags::EILAST) {
+        cpu.enable_interrupts();
+    }
+    cpu.set_iffs(regs.iff1 != 0, regs.iff2 != 0);
+    if flags.intersects(Z80Flags::HALTED) {
+        cpu.halt();
+    }
+    cpu.set_memptr(u16::from_le_bytes(regs.memptr));
+    Ok(cpu)
+}
+
+fn select_ay_model(model: ComputerModel, flags: AyFlags) -> Ay3_891xDevice {
+    use ComputerModel::*;
+    match model {
+        Spectrum128|SpectrumPlus2|
+        SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
+        SpectrumSE|
+        Pentagon128|Pentagon512|Pentagon1024 => Ay3_891xDevice::Ay128k,
+        TimexTC2068|TimexTS2068 => Ay3_891xDevice::Timex,
+        _ if flags.intersects(AyFlags::FULLER_BOX) => Ay3_891xDevice::FullerBox,
+        _ => Ay3_891xDevice::Melodik
+    }
+}
+
+fn select_extensions(blocks: &[Block]) -> Result<Extensions> {
+    let mut extensions = Extensions::NONE;
+    for block in blocks {
+        match &block.id {
+            IF1_ID => {
+                let if1: If1Header = block.read_struct()?;
+                if If1Flags::from_bits_truncate(u16::from_le_bytes(if1.flags)).intersects(If1Flags::ENABLED) {
+                    extensions.insert(Extensions::IF1);
+                }
+            }
+            PALETTE_ID => extensions.insert(Extensions::ULA_PLUS),
+            BETA128_ID => {
+                let beta: Beta128 = block.read_struct()?;
+                if Beta128Flags::from_bits_truncate(u32::from_le_bytes(beta.flags))
+                                .intersects(Beta128Flags::CONNECTED) {
+                    extensions.insert(Extensions::TR_DOS);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(extensions)
+}
+
+/// Loads an **SZX** file from `rd` into the provided snapshot `loader` implementing [SnapshotLoader].
+///
+/// # Errors
+/// This function will return an error if the file is not an **SZX** file, the computer model
+/// is not supported or there is something wrong with the format.
+/// Other errors may also be returned from attempts to read the file.
+pub fn load_szx<R: Read, S: SnapshotLoader>(
+        mut rd: R,
+        loader: &mut S
+    ) -> Result<()>
+{
+    use ComputerModel::*;
+
+    let header = Header::read_new_struct(rd.by_ref())?;
+    if &header.magic != SZX_MAGIC {
+        return invalid_data("SZX: not a ZX-State file")
+    }
+    if header.major != SZX_MAJOR_VERSION {
+        return invalid_data("SZX: unsupported version")
+    }
+    let model = machine_id_to_model(header.machine_id).ok_or_else(||
+        io::Error::new(io::ErrorKind::InvalidData, "SZX: unsupported machine")
+    )?;
+
+    let mut blocks = Vec::new();
+    while let Some(block) = read_block(rd.by_ref())? {
+        blocks.push(block);
+    }
+    let find_block = |id: &[u8;4]| blocks.iter().find(|block| &block.id == id);
+
+    let cpu = match find_block(Z80_REGS_ID) {
+        Some(block) => block.read_struct::<Z80Regs>()?,
+        None => return invalid_data("SZX: missing Z80R block")
+    };
+    let spec_regs: SpecRegs = find_block(SPEC_REGS_ID).map(Block::read_struct)
+                                                      .transpose()?.unwrap_or_default();
+    let keyboard: Option<Keyboard> = find_block(KEYBOARD_ID).map(Block::read_struct).transpose()?;
+
+    let extensions = select_extensions(&blocks)?;
+    let border = BorderColor::try_from(spec_regs.border & 7).unwrap();
+    let issue = model.applicable_issue(
+        match keyboard {
+            Some(keyb) if KeyboardFlags::from_bits_truncate(u32::from_le_bytes(keyb.flags))
+                                        .intersects(KeyboardFlags::ISSUE2) => ReadEarMode::Issue2,
+            _ => ReadEarMode::Issue3
+        }
+    );
+
+    loader.select_model(model, extensions, border, issue)
+          .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let joystick = find_block(JOYSTICK_ID).map(Block::read_struct::<Joystick>).transpose()?
+                   .and_then(|joy| type_to_joystick(joy.player1))
+                   .or_else(|| keyboard.and_then(|keyb| type_to_joystick(keyb.joystick)));
+    if let Some(joystick) = joystick {
+        loader.select_joystick(joystick);
+    }
+
+    loader.assign_cpu(CpuModel::NMOS(create_cpu(&cpu)?));
+
+    for block in blocks.iter().filter(|block| &block.id == RAM_PAGE_ID) {
+        let page: RamPageHeader = block.read_struct()?;
+        let range = ram_page_range(page.page, model).ok_or_else(||
+            io::Error::new(io::ErrorKind::InvalidData, "SZX: unsupported memory page")
+        )?;
+        let is_compressed = RamPageFlags::from_bits_truncate(u16::from_le_bytes(page.flags))
+                                         .intersects(RamPageFlags::COMPRESSED);
+        let data = decompress(block.data_after::<RamPageHeader>(), is_compressed)?;
+        if data.len() != PAGE_SIZE {
+            return invalid_data("SZX: invalid memory page size")
+        }
+        loader.read_into_memory(MemoryRange::Ram(range), &data[..])?;
+    }
+
+    if let Some(block) = find_block(AY_ID) {
+        let ay: AyState = block.read_struct()?;
+        let choice = select_ay_model(model, AyFlags::from_bits_truncate(ay.flags));
+        loader.setup_ay(choice, ay.current_reg.into(), &ay.regs);
+    }
+
+    match model {
+        SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
+        SpectrumSE => {
+            loader.write_port(0x1ffd, spec_regs.port_1ffd);
+        }
+        _ => {}
+    }
+    match model {
+        Spectrum128|SpectrumPlus2|
+        SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
+        SpectrumSE|
+        Pentagon128|Pentagon512|Pentagon1024 => {
+            loader.write_port(0x7ffd, spec_regs.port_7ffd);
+        }
+        _ => {}
+    }
+    if let Some(block) = find_block(SCLD_ID) {
+        match model {
+            TimexTC2048|TimexTC2068|TimexTS2068|
+            SpectrumSE => {
+                let scld: ScldRegs = block.read_struct()?;
+                loader.write_port(0xf4, scld.port_f4);
+                loader.write_port(0xff, scld.port_ff);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(block) = find_block(PALETTE_ID) {
+        let palette: Palette = block.read_struct()?;
+        for (index, &color) in palette.regs.iter().enumerate() {
+            loader.write_port(0xbf3b, UlaPlusRegFlags::PALETTE_GROUP.bits() | index as u8);
+            loader.write_port(0xff3b, color);
+        }
+        let mut mode = palette.mode;
+        if PaletteFlags::from_bits_truncate(palette.flags).intersects(PaletteFlags::ENABLED) {
+            mode |= 1;
+        }
+        loader.write_port(0xbf3b, UlaPlusRegFlags::MODE_GROUP.bits());
+        loader.write_port(0xff3b, mode);
+        loader.write_port(0xbf3b, palette.current_reg);
+    }
+
+    if let Some(block) = find_block(IF1_ID) {
+        let if1: If1Header = block.read_struct()?;
+        let flags = If1Flags::from_bits_truncate(u16::from_le_bytes(if1.flags));
+        if flags.intersects(If1Flags::ENABLED) {
+            let rom_size = u16::from_le_bytes(if1.rom_size);
+            if rom_size != 0 {
+                let data = decompress(block.data_after::<If1Header>(),
+                                      flags.intersects(If1Flags::COMPRESSED))?;
+                if data.len() != rom_size as usize {
+                    return invalid_data("SZX: invalid Interface 1 ROM size")
+                }
+                loader.read_into_memory(MemoryRange::Interface1Rom, &data[..])?;
+            }
+            if flags.intersects(If1Flags::PAGED) {
+                loader.interface1_rom_paged_in();
+            }
+        }
+    }
+
+    if let Some(block) = find_block(BETA128_ID) {
+        let beta: Beta128 = block.read_struct()?;
+        if Beta128Flags::from_bits_truncate(u32::from_le_bytes(beta.flags))
+                        .contains(Beta128Flags::CONNECTED|Beta128Flags::PAGED) {
+            loader.tr_dos_rom_paged_in();
+        }
+    }
+
+    if let Some(block) = find_block(PLUS3_ID) {
+        match model {
+            SpectrumPlus3|SpectrumPlus3e => {
+                let plus3: Plus3 = block.read_struct()?;
+                loader.setup_plus3_disk_drives(plus3.drives, plus3.motor_on != 0);
+            }
+            _ => {}
+        }
+    }
+
+    let cycles = u32::from_le_bytes(cpu.cycles_start);
+    loader.set_clock((cycles % model.frame_tstates() as u32) as FTs);
+    Ok(())
+}
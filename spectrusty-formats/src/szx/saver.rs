@@ -0,0 +1,288 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use std::borrow::Cow;
+use std::io::{self, Write, Result};
+
+#[cfg(feature = "compression")]
+use compression::prelude::*;
+
+use spectrusty_core::z80emu::{Cpu, StkReg16, Prefix, InterruptMode, Z80NMOS};
+use spectrusty_core::chip::{ReadEarMode, Ula3CtrlFlags};
+
+use crate::StructWrite;
+use crate::snapshot::*;
+use super::common::*;
+
+const CREATOR_NAME: &[u8] = b"SPECTRUSTY";
+
+/// Returns the compressed `data` if compression is available and it makes the data smaller.
+#[cfg(feature = "compression")]
+fn compress(data: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    match data.iter().copied().encode(&mut ZlibEncoder::new(), Action::Finish)
+                     .collect::<core::result::Result<Vec<_>, _>>() {
+        Ok(buf) if buf.len() < data.len() => (Cow::Owned(buf), true),
+        _ => (Cow::Borrowed(data), false)
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(data: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    (Cow::Borrowed(data), false)
+}
+
+fn get_nmos_cpu(cpu: CpuModel, result: &mut SnapshotResult) -> Z80NMOS {
+    match cpu {
+        CpuModel::NMOS(cpu) => cpu,
+        CpuModel::CMOS(cpu) => {
+            result.insert(SnapshotResult::CPU_MODEL_NSUP);
+            cpu.into_flavour()
+        },
+        CpuModel::BM1(cpu) => {
+            result.insert(SnapshotResult::CPU_MODEL_NSUP);
+            cpu.into_flavour()
+        }
+    }
+}
+
+fn make_z80_regs(cpu: &Z80NMOS, cycles: u32) -> Z80Regs {
+    let (a, f) = cpu.get_reg2(StkReg16::AF);
+    let (a_alt, f_alt) = cpu.get_alt_reg2(StkReg16::AF);
+    let (iff1, iff2) = cpu.get_iffs();
+    let mut flags = Z80Flags::empty();
+    flags.set(Z80Flags::EILAST, cpu.is_after_ei());
+    flags.set(Z80Flags::HALTED, cpu.is_halt());
+    Z80Regs {
+        af: [f, a],
+        bc: cpu.get_reg16(StkReg16::BC).to_le_bytes(),
+        de: cpu.get_reg16(StkReg16::DE).to_le_bytes(),
+        hl: cpu.get_reg16(StkReg16::HL).to_le_bytes(),
+        af_alt: [f_alt, a_alt],
+        bc_alt: cpu.get_alt_reg16(StkReg16::BC).to_le_bytes(),
+        de_alt: cpu.get_alt_reg16(StkReg16::DE).to_le_bytes(),
+        hl_alt: cpu.get_alt_reg16(StkReg16::HL).to_le_bytes(),
+        ix: cpu.get_index16(Prefix::Xdd).to_le_bytes(),
+        iy: cpu.get_index16(Prefix::Yfd).to_le_bytes(),
+        sp: cpu.get_sp().to_le_bytes(),
+        pc: cpu.get_pc().to_le_bytes(),
+        i: cpu.get_i(),
+        r: cpu.get_r(),
+        iff1: iff1.into(),
+        iff2: iff2.into(),
+        im: match cpu.get_im() {
+            InterruptMode::Mode0 => 0,
+            InterruptMode::Mode1 => 1,
+            InterruptMode::Mode2 => 2,
+        },
+        cycles_start: cycles.to_le_bytes(),
+        hold_int_req_cycles: 0,
+        flags: flags.bits(),
+        memptr: cpu.get_memptr().to_le_bytes()
+    }
+}
+
+fn select_ay_state<S: SnapshotCreator>(
+        snapshot: &S,
+        result: &mut SnapshotResult
+    ) -> Option<AyState>
+{
+    const DEVICES: [(Ay3_891xDevice, AyFlags);4] = [
+        (Ay3_891xDevice::Ay128k, AyFlags::empty()),
+        (Ay3_891xDevice::Timex, AyFlags::empty()),
+        (Ay3_891xDevice::FullerBox, AyFlags::FULLER_BOX),
+        (Ay3_891xDevice::Melodik, AyFlags::AY_128K)
+    ];
+    let mut states = DEVICES.iter().filter_map(|&(choice, flags)| {
+        snapshot.ay_state(choice).map(|(reg, regs)| AyState {
+            flags: flags.bits(),
+            current_reg: reg.into(),
+            regs: *regs
+        })
+    });
+    let res = states.next();
+    if states.next().is_some() {
+        result.insert(SnapshotResult::SOUND_CHIP_NSUP);
+    }
+    res
+}
+
+fn save_ram_pages<W: Write, S: SnapshotCreator>(
+        mut wr: W,
+        snapshot: &S,
+        model: ComputerModel
+    ) -> Result<()>
+{
+    for (page, range) in ram_pages(model) {
+        let mem_slice = snapshot.memory_ref(MemoryRange::Ram(range))?;
+        let (data, is_compressed) = compress(mem_slice);
+        let mut flags = RamPageFlags::empty();
+        flags.set(RamPageFlags::COMPRESSED, is_compressed);
+        let page_head = RamPageHeader { flags: flags.bits().to_le_bytes(), page };
+        write_block(wr.by_ref(), RAM_PAGE_ID, &[&struct_bytes(&page_head), &data])?;
+    }
+    Ok(())
+}
+
+fn make_creator() -> Creator {
+    let mut creator = Creator::default();
+    creator.name[..CREATOR_NAME.len()].copy_from_slice(CREATOR_NAME);
+    let major: u16 = env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or_default();
+    let minor: u16 = env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or_default();
+    creator.major = major.to_le_bytes();
+    creator.minor = minor.to_le_bytes();
+    creator
+}
+
+/// Saves an **SZX** file into `wr` from the provided reference to a `snapshot` struct
+/// implementing [SnapshotCreator].
+///
+/// # Errors
+/// This function may return an error from attempts to write the file or if for some reason
+/// a snapshot could not be created.
+pub fn save_szx<C: SnapshotCreator, W: Write>(
+        snapshot: &C,
+        mut wr: W
+    ) -> Result<SnapshotResult>
+{
+    use ComputerModel::*;
+    let mut result = SnapshotResult::OK;
+    let model = snapshot.model();
+    let ext = snapshot.extensions();
+    if let Err(bad_ext) = model.validate_extensions(ext) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("SZX: the model {} can't be saved with {}", model, bad_ext)))
+    }
+    if ext.intersects(Extensions::PLUS_D) && snapshot.is_plus_d_rom_paged_in()
+       || ext.intersects(Extensions::DISCIPLE) && snapshot.is_disciple_rom_paged_in()
+    {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "SZX: can't create a snapshot with the MGT ROM paged in"))
+    }
+    if ext.intersects(Extensions::PLUS_D|Extensions::DISCIPLE|Extensions::SAM_RAM) {
+        result.insert(SnapshotResult::EXTENSTION_NSUP);
+    }
+
+    let cpu = get_nmos_cpu(snapshot.cpu(), &mut result);
+    if !is_cpu_safe_for_snapshot(&cpu) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "SZX: can't safely snapshot the CPU state"))
+    }
+
+    let header = Header {
+        magic: *SZX_MAGIC,
+        major: SZX_MAJOR_VERSION,
+        minor: SZX_MINOR_VERSION,
+        machine_id: model_to_machine_id(model),
+        flags: 0
+    };
+    header.write_struct(wr.by_ref())?;
+
+    write_block(wr.by_ref(), CREATOR_ID, &[&struct_bytes(&make_creator())])?;
+
+    let cycles = snapshot.current_clock().rem_euclid(model.frame_tstates()) as u32;
+    write_block(wr.by_ref(), Z80_REGS_ID, &[&struct_bytes(&make_z80_regs(&cpu, cycles))])?;
+
+    let border: u8 = snapshot.border_color().into();
+    let mut spec_regs = SpecRegs { border, port_fe: border, ..SpecRegs::default() };
+    match model {
+        Spectrum128|SpectrumPlus2|SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
+        SpectrumSE => {
+            spec_regs.port_7ffd = snapshot.ula128_flags().bits();
+        }
+        Pentagon128|Pentagon512|Pentagon1024 => {
+            spec_regs.port_7ffd = snapshot.pentagon_mem_port();
+        }
+        _ => {}
+    }
+    match model {
+        SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e => {
+            spec_regs.port_1ffd = snapshot.ula3_flags().bits();
+        }
+        _ => {}
+    }
+    write_block(wr.by_ref(), SPEC_REGS_ID, &[&struct_bytes(&spec_regs)])?;
+
+    let joystick = snapshot.joystick();
+    if let Some(joy) = joystick {
+        let joy = Joystick { flags: [0;4], player1: joystick_to_type(joy), player2: JOYSTICK_NONE };
+        write_block(wr.by_ref(), JOYSTICK_ID, &[&struct_bytes(&joy)])?;
+    }
+
+    let mut keyb_flags = KeyboardFlags::empty();
+    keyb_flags.set(KeyboardFlags::ISSUE2, snapshot.issue() == ReadEarMode::Issue2);
+    let keyb_joystick = match joystick {
+        Some(joy@JoystickModel::Cursor)|
+        Some(joy@JoystickModel::Sinclair1)|
+        Some(joy@JoystickModel::Sinclair2) => joystick_to_type(joy),
+        _ => JOYSTICK_NONE
+    };
+    let keyb = Keyboard { flags: keyb_flags.bits().to_le_bytes(), joystick: keyb_joystick };
+    write_block(wr.by_ref(), KEYBOARD_ID, &[&struct_bytes(&keyb)])?;
+
+    if let Some(ay) = select_ay_state(snapshot, &mut result) {
+        write_block(wr.by_ref(), AY_ID, &[&struct_bytes(&ay)])?;
+    }
+
+    match model {
+        TimexTC2048|TimexTC2068|TimexTS2068|
+        SpectrumSE => {
+            let scld = ScldRegs {
+                port_ff: snapshot.timex_flags().bits(),
+                port_f4: snapshot.timex_memory_banks()
+            };
+            write_block(wr.by_ref(), SCLD_ID, &[&struct_bytes(&scld)])?;
+        }
+        _ => {}
+    }
+
+    if ext.intersects(Extensions::ULA_PLUS) {
+        if let Some(regs) = snapshot.ulaplus_palette() {
+            let mode = snapshot.ulaplus_color_mode().bits();
+            let mut flags = PaletteFlags::empty();
+            flags.set(PaletteFlags::ENABLED, mode & 1 != 0);
+            let palette = Palette {
+                flags: flags.bits(),
+                current_reg: snapshot.ulaplus_flags().bits(),
+                regs: *regs,
+                mode
+            };
+            write_block(wr.by_ref(), PALETTE_ID, &[&struct_bytes(&palette)])?;
+        }
+    }
+
+    if ext.intersects(Extensions::IF1) {
+        let mut flags = If1Flags::ENABLED;
+        flags.set(If1Flags::PAGED, snapshot.is_interface1_rom_paged_in());
+        let if1 = If1Header {
+            flags: flags.bits().to_le_bytes(),
+            microdrives: IF1_MICRODRIVES,
+            ..If1Header::default()
+        };
+        write_block(wr.by_ref(), IF1_ID, &[&struct_bytes(&if1)])?;
+    }
+
+    if ext.intersects(Extensions::TR_DOS) {
+        let mut flags = Beta128Flags::CONNECTED;
+        flags.set(Beta128Flags::PAGED, snapshot.is_tr_dos_rom_paged_in());
+        let beta = Beta128 { flags: flags.bits().to_le_bytes(), ..Beta128::default() };
+        write_block(wr.by_ref(), BETA128_ID, &[&struct_bytes(&beta)])?;
+    }
+
+    match model {
+        SpectrumPlus3|SpectrumPlus3e => {
+            if let Some(drives) = snapshot.plus3_disk_drives() {
+                let motor_on = snapshot.ula3_flags().intersects(Ula3CtrlFlags::DISC_MOTOR);
+                let plus3 = Plus3 { drives, motor_on: motor_on.into() };
+                write_block(wr.by_ref(), PLUS3_ID, &[&struct_bytes(&plus3)])?;
+            }
+        }
+        _ => {}
+    }
+
+    save_ram_pages(wr.by_ref(), snapshot, model)?;
+    wr.flush()?;
+    Ok(result)
+}
@@ -0,0 +1,223 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! **TRD** file format utilities.
+
+[Utilities][TrdDiskExt] in this module provide additional methods to the [FloppyDisk] type
+with abilities to create **TR-DOS** disks, to access their logical sectors and to read and write
+disk images in the **TRD** file format.
+
+**TRD** files are raw images of **TR-DOS** disks used by the Beta 128 disk interface. Each track of
+a **TR-DOS** disk consists of 16 sectors, 256 bytes each, numbered from 1 to 16. A file contains the data
+of all sectors ordered by logical tracks and then by sectors. The logical track number is `cylinder * sides + side`.
+
+The first logical track contains the catalogue in sectors `0` to `7` (logical sector numbers start from 0)
+and the system sector `8`, which stores the disk geometry:
+
+| offset | size | description                                            |
+|--------|------|--------------------------------------------------------|
+|   0xE1 |    1 | The first free sector.                                 |
+|   0xE2 |    1 | The first free logical track.                          |
+|   0xE3 |    1 | The disk type, see below.                              |
+|   0xE4 |    1 | The number of files, including the deleted ones.       |
+|   0xE5 |    2 | The number of free sectors (LSB first).                |
+|   0xE7 |    1 | The **TR-DOS** identifier: `0x10`.                     |
+|   0xF4 |    1 | The number of deleted files.                           |
+|   0xF5 |    8 | The disk label.                                        |
+
+Disk types:
+
+* `0x16` - 80 cylinders, 2 sides,
+* `0x17` - 40 cylinders, 2 sides,
+* `0x18` - 80 cylinders, 1 side,
+* `0x19` - 40 cylinders, 1 side.
+
+**TRD** files are often truncated after the last used track, in this instance the missing sectors are being
+filled with zeroes up to the number of cylinders determined by the disk type.
+!*/
+use std::io::{self, Read, Write};
+
+pub use spectrusty_peripherals::storage::floppy::{
+    FloppyDisk, DiskTrack, DiskSector, MAX_CYLINDERS
+};
+
+/// The number of sectors in a single **TR-DOS** track.
+pub const TRDOS_SECTORS: u8 = 16;
+/// The size of a single **TR-DOS** sector in bytes.
+pub const TRDOS_SECTOR_SIZE: usize = 256;
+/// The maximum number of files in the **TR-DOS** catalogue.
+pub const TRDOS_MAX_FILES: usize = 128;
+/// The size of a single catalogue entry in bytes.
+pub const TRDOS_CAT_ENTRY_SIZE: usize = 16;
+/// The logical number of the system sector in the first logical track.
+pub const TRDOS_SYSTEM_SECTOR: u8 = 8;
+/// The disk type of an 80 cylinders double sided disk.
+pub const TRDOS_DISK_80_2: u8 = 0x16;
+
+const TRACK_SIZE: usize = TRDOS_SECTORS as usize * TRDOS_SECTOR_SIZE;
+
+const SYS_FREE_SECTOR: usize = 0xE1;
+const SYS_FREE_TRACK:  usize = 0xE2;
+const SYS_DISK_TYPE:   usize = 0xE3;
+const SYS_FILES:       usize = 0xE4;
+const SYS_FREE_COUNT:  usize = 0xE5;
+const SYS_TRDOS_ID:    usize = 0xE7;
+const SYS_LABEL:       usize = 0xF5;
+
+/// Extends [FloppyDisk] with methods for **TR-DOS** disks and reading and writing **TRD** files.
+pub trait TrdDiskExt: Sized {
+    /// Creates a new, empty **TR-DOS** disk with 80 cylinders and 2 sides, with the given disk `label`.
+    ///
+    /// Only the first 8 bytes of `label` are being used.
+    fn new_trdos_formatted<S: AsRef<[u8]>>(label: S) -> Self;
+    /// Creates a new instance of [FloppyDisk] from the **TRD** file data read from the provided reader.
+    fn from_trd<R: Read>(rd: R) -> io::Result<Self>;
+    /// Writes the disk to a **TRD** file using a provided writer.
+    ///
+    /// Returns the number of bytes written.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if any of the **TR-DOS** sectors is
+    /// missing from the disk.
+    fn write_trd<W: Write>(&self, wr: W) -> io::Result<usize>;
+    /// Returns a reference to the data of the **TR-DOS** logical `sector` (0 - 15) of the logical `track`.
+    ///
+    /// Returns `None` if the sector can't be found or if its size is not 256 bytes.
+    fn trdos_sector_ref(&self, track: u8, sector: u8) -> Option<&[u8]>;
+    /// Returns a mutable reference to the data of the **TR-DOS** logical `sector` (0 - 15) of the logical `track`.
+    ///
+    /// Returns `None` if the sector can't be found or if its size is not 256 bytes.
+    ///
+    /// The disk is not being marked as modified by this method.
+    fn trdos_sector_mut(&mut self, track: u8, sector: u8) -> Option<&mut [u8]>;
+}
+
+fn disk_geometry(disk_type: u8) -> Option<(u8, u8)> {
+    match disk_type {
+        0x16 => Some((80, 2)),
+        0x17 => Some((40, 2)),
+        0x18 => Some((80, 1)),
+        0x19 => Some((40, 1)),
+        _ => None
+    }
+}
+
+impl TrdDiskExt for FloppyDisk {
+    fn new_trdos_formatted<S: AsRef<[u8]>>(label: S) -> Self {
+        let mut disk = FloppyDisk::new_formatted(80, 2, TRDOS_SECTORS, 1, 1, 0);
+        let free_sectors = (disk.tracks().len() as u16 - 1) * TRDOS_SECTORS as u16;
+        let sys = disk.trdos_sector_mut(0, TRDOS_SYSTEM_SECTOR).unwrap();
+        sys[SYS_FREE_SECTOR] = 0;
+        sys[SYS_FREE_TRACK] = 1;
+        sys[SYS_DISK_TYPE] = TRDOS_DISK_80_2;
+        sys[SYS_FILES] = 0;
+        sys[SYS_FREE_COUNT..SYS_FREE_COUNT + 2].copy_from_slice(&free_sectors.to_le_bytes());
+        sys[SYS_TRDOS_ID] = 0x10;
+        sys[0xEA..0xF3].copy_from_slice(&[b' ';9]);
+        let sys_label = &mut sys[SYS_LABEL..SYS_LABEL + 8];
+        sys_label.copy_from_slice(&[b' ';8]);
+        let label = label.as_ref();
+        let len = label.len().min(8);
+        sys_label[..len].copy_from_slice(&label[..len]);
+        disk
+    }
+
+    fn from_trd<R: Read>(mut rd: R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        rd.read_to_end(&mut data)?;
+        if data.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "TRD image is empty"))
+        }
+        // the last track may be incomplete
+        let padding = (TRACK_SIZE - data.len() % TRACK_SIZE) % TRACK_SIZE;
+        data.resize(data.len() + padding, 0);
+        let tracks = data.len() / TRACK_SIZE;
+        let sys_offset = TRDOS_SYSTEM_SECTOR as usize * TRDOS_SECTOR_SIZE;
+        let (cylinders, sides) = disk_geometry(data[sys_offset + SYS_DISK_TYPE]).unwrap_or((80, 2));
+        // with 1 or 2 sides the remainder is the number of tracks of the incomplete cylinder
+        let cylinders = (cylinders as usize).max(tracks / sides as usize + tracks % sides as usize);
+        if cylinders > MAX_CYLINDERS as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "TRD image has too many tracks"))
+        }
+        let mut disk = FloppyDisk::new_formatted(cylinders as u8, sides, TRDOS_SECTORS, 1, 1, 0);
+        for (track, track_data) in data.chunks(TRACK_SIZE).enumerate() {
+            for (sector, sector_data) in track_data.chunks(TRDOS_SECTOR_SIZE).enumerate() {
+                disk.trdos_sector_mut(track as u8, sector as u8).unwrap()
+                    .copy_from_slice(sector_data);
+            }
+        }
+        Ok(disk)
+    }
+
+    fn write_trd<W: Write>(&self, mut wr: W) -> io::Result<usize> {
+        let mut bytes = 0;
+        for track in 0..self.tracks().len() {
+            for sector in 0..TRDOS_SECTORS {
+                let data = self.trdos_sector_ref(track as u8, sector).ok_or_else(||
+                    io::Error::new(io::ErrorKind::InvalidInput, "disk can't be represented in the TRD format")
+                )?;
+                wr.write_all(data)?;
+                bytes += data.len();
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn trdos_sector_ref(&self, track: u8, sector: u8) -> Option<&[u8]> {
+        let sides = self.sides();
+        self.track(track / sides, track % sides)?.sectors.iter()
+            .find(|s| s.id == sector.wrapping_add(1) && s.data.len() == TRDOS_SECTOR_SIZE)
+            .map(|s| &s.data[..])
+    }
+
+    fn trdos_sector_mut(&mut self, track: u8, sector: u8) -> Option<&mut [u8]> {
+        let sides = self.sides();
+        self.track_mut(track / sides, track % sides)?.sectors.iter_mut()
+            .find(|s| s.id == sector.wrapping_add(1) && s.data.len() == TRDOS_SECTOR_SIZE)
+            .map(|s| &mut s.data[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn trd_works() {
+        let mut disk = FloppyDisk::new_trdos_formatted("SPECTRUSTY DISK");
+        assert_eq!(disk.cylinders(), 80);
+        assert_eq!(disk.sides(), 2);
+        let sys = disk.trdos_sector_ref(0, 8).unwrap();
+        assert_eq!(sys[0xE1..0xE8], [0, 1, 0x16, 0, 0xF0, 0x09, 0x10]);
+        assert_eq!(&sys[0xF5..0xFD], b"SPECTRUS");
+        disk.trdos_sector_mut(159, 15).unwrap()[255] = 42;
+        assert_eq!(disk.track(79, 1).unwrap().sectors[15].data[255], 42);
+        assert_eq!(disk.trdos_sector_ref(160, 0), None);
+        assert_eq!(disk.trdos_sector_ref(0, 16), None);
+
+        let mut buf = Vec::new();
+        assert_eq!(disk.write_trd(&mut buf).unwrap(), 655360);
+        assert_eq!(buf.len(), 655360);
+        assert_eq!(buf[0x8E3], 0x16);
+        assert_eq!(buf[655359], 42);
+        let disk2 = FloppyDisk::from_trd(Cursor::new(&buf)).unwrap();
+        assert_eq!(disk2, disk);
+
+        buf.truncate(3 * 4096 + 1);
+        buf[0x8E3] = 0x19;
+        let disk3 = FloppyDisk::from_trd(Cursor::new(&buf)).unwrap();
+        assert_eq!(disk3.cylinders(), 40);
+        assert_eq!(disk3.sides(), 1);
+        assert_eq!(disk3.trdos_sector_ref(3, 0).unwrap()[..2], [0, 0]);
+        assert_eq!(disk3.trdos_sector_ref(0, 8).unwrap()[0xE3], 0x19);
+
+        let mut disk4 = disk3.clone();
+        disk4.track_mut(39, 0).unwrap().sectors.pop();
+        assert_eq!(disk4.write_trd(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(FloppyDisk::from_trd(Cursor::new(&[])).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -5,32 +5,108 @@
 
     For the full copyright notice, see the lib.rs file.
 */
-use core::num::NonZeroU32;
-use core::convert::TryFrom;
-use std::io::{self, Read, Write, Seek};
+/*! **TZX** file format utilities.
+
+# TZX format
+
+A **TZX** file is a structured container of *TAPE* signal descriptions. It begins with a 10 byte
+header followed by any number of blocks, each starting with a one byte block ID.
+
+| offset | size | description                              |
+|--------|------|------------------------------------------|
+|    0   |    7 | signature "ZXTape!"                      |
+|    7   |    1 | end of text marker 0x1A                  |
+|    8   |    1 | TZX major revision number                |
+|    9   |    1 | TZX minor revision number                |
+
+Blocks describe the signal in many different ways: from the standard ROM encoded data blocks
+(`0x10`), through the blocks with custom pulse timings (`0x11`, `0x12`, `0x13`, `0x14`, `0x19`),
+to the sampled recordings (`0x15`, `0x18`). Other blocks control the flow of the tape (loops,
+jumps and calls), pause or stop the tape or just provide some information about it.
+
+For the detailed description of each block, see the
+[TZX format specification](https://worldofspectrum.net/TZXformat.html).
+
+## Interpreting *TZX* files
+
+[TzxBlock] represents a single parsed *TZX* block. All the blocks of the 1.20 revision of the format
+are recognized. The deprecated and unknown blocks are preserved as [TzxBlock::Unsupported].
+
+[TzxReadIter] parses blocks directly from any [reader][Read]. To read the whole file at once
+use [TzxTape::read_from] or [read_tzx].
+
+```no_run
+use spectrusty_formats::tzx::*;
+
+let tzxfile = std::fs::File::open("some.tzx")?;
+let tape = read_tzx(tzxfile)?;
+for (index, block) in tape.blocks.iter().enumerate() {
+    println!("{:3}: {}", index, block);
+}
+# Ok::<(), std::io::Error>(())
+```
+
+### *TAPE* pulses
 
-use super::tap::TapChunkWriter;
+[TzxPulseIter] encodes the blocks as *TAPE* pulse intervals, following the tape flow control blocks
+(loops, jumps and calls). The pulses can be fed directly into the emulator via [EarIn::feed_ear_in].
 
-pub trait TzxChunk {
-    type PulseIter: Iterator<Item=NonZeroU32>;
-    fn id(&self) -> TzxId;
-    fn len(&self) -> usize;
-    fn pulse_iter(&self) -> Self::PulseIter;
-    fn as_slice(&self) -> &[u8];
-    fn write_to_tap<W: Write + Seek>(&self, rd: &mut TapChunkWriter<W>) -> io::Result<Option<usize>>;
+```no_run
+use spectrusty::{memory::Memory48k, chip::{ula::UlaPAL, EarIn}};
+
+let mut ula = UlaPAL::<Memory48k>::default();
+//...
+use spectrusty_formats::tzx::*;
+
+let tzxfile = std::fs::File::open("some.tzx")?;
+let mut pulse_iter = read_tzx_pulse_iter(tzxfile)?;
+
+// feed the buffer fragmentarily before each emulated frame
+ula.feed_ear_in(&mut pulse_iter, Some(1));
+
+// the tape may be stopped by a block
+if pulse_iter.is_stopped() {
+    // ... and resumed later
+    pulse_iter.resume();
 }
+# Ok::<(), std::io::Error>(())
+```
+
+[EarIn::feed_ear_in]: spectrusty_core::chip::EarIn::feed_ear_in
+*/
+use core::fmt;
+use core::convert::TryFrom;
+use std::borrow::Cow;
+use std::io::{Read, Result};
+
+use crate::tap::TapChunk;
+
+mod pulse;
+mod read;
+mod write;
+pub use pulse::*;
+pub use read::*;
+pub use write::*;
+
+/// The signature of *TZX* files.
+pub const TZX_SIGNATURE: &[u8;8] = b"ZXTape!\x1A";
+/// The major revision number of the supported *TZX* format.
+pub const TZX_MAJOR_VERSION: u8 = 1;
+/// The minor revision number of the supported *TZX* format.
+pub const TZX_MINOR_VERSION: u8 = 20;
 
 macro_rules! tzx_id {
-    ($($id:ident = $n:literal),*) => {
+    ($($(#[$meta:meta])* $id:ident = $n:literal),*) => {
+        /// *TZX* block IDs.
         #[repr(u8)]
         #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
         pub enum TzxId {
-            $($id = $n),*
+            $($(#[$meta])* $id = $n),*
         }
 
         impl TryFrom<u8> for TzxId {
             type Error = &'static str;
-            fn try_from(id: u8) -> Result<Self, Self::Error> {
+            fn try_from(id: u8) -> core::result::Result<Self, Self::Error> {
                 match id {
                     $($n => Ok(TzxId::$id),)*
                     _ => Err("Unknown TZX ID")
@@ -48,6 +124,10 @@ tzx_id! {
     SeqOfPulses      = 0x13,
     PureData         = 0x14,
     DirectRec        = 0x15,
+    /// Deprecated.
+    C64RomType       = 0x16,
+    /// Deprecated.
+    C64TurboData     = 0x17,
     CswRecording     = 0x18,
     Generalized      = 0x19,
     Pause            = 0x20,
@@ -65,12 +145,525 @@ tzx_id! {
     Message          = 0x31,
     Archive          = 0x32,
     Hardware         = 0x33,
+    /// Deprecated.
+    EmulationInfo    = 0x34,
     Custom           = 0x35,
+    /// Deprecated.
+    Snapshot         = 0x40,
     Glue             = 0x5A
 }
 
+/// Pulse timings of the turbo speed data blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TurboTimings {
+    /// Length of the PILOT pulse in T-states.
+    pub pilot: u16,
+    /// Length of the 1st SYNC pulse in T-states.
+    pub sync1: u16,
+    /// Length of the 2nd SYNC pulse in T-states.
+    pub sync2: u16,
+    /// Length of the ZERO bit pulse in T-states.
+    pub zero: u16,
+    /// Length of the ONE bit pulse in T-states.
+    pub one: u16,
+    /// The number of PILOT pulses.
+    pub pilot_count: u16
+}
+
+/// The compression type of the *CSW* recording block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum CswCompression {
+    /// Run-length encoding.
+    Rle  = 1,
+    /// Zlib compressed run-length encoding.
+    ZRle = 2
+}
+
+/// The polarity of the first pulse of the generalized data symbol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SymbolPolarity {
+    /// The level is toggled, an edge is made as usual.
+    Edge   = 0,
+    /// The level remains unchanged, prolonging the previous pulse.
+    NoEdge = 1,
+    /// The level is forced low.
+    Low    = 2,
+    /// The level is forced high.
+    High   = 3
+}
+
+/// A symbol definition of the generalized data block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TzxSymbol {
+    /// The polarity of the first pulse of this symbol.
+    pub polarity: SymbolPolarity,
+    /// Pulse lengths in T-states. May be empty.
+    pub pulses: Box<[u16]>
+}
+
+/// The content of the generalized data block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GeneralizedData {
+    /// Pause after this block in milliseconds.
+    pub pause: u16,
+    /// The pilot and sync symbols alphabet.
+    pub pilot_symbols: Box<[TzxSymbol]>,
+    /// The pilot and sync stream of pairs: `(symbol index, number of repetitions)`.
+    pub pilot_stream: Box<[(u8, u16)]>,
+    /// The data symbols alphabet.
+    pub data_symbols: Box<[TzxSymbol]>,
+    /// The number of symbols in the data stream.
+    pub data_count: u32,
+    /// The data stream. Each symbol takes [GeneralizedData::symbol_bits] bits, most significant bit first.
+    pub data: Box<[u8]>
+}
+
+/// An entry of the hardware type block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HardwareInfo {
+    /// The hardware type.
+    pub hw_type: u8,
+    /// The hardware ID.
+    pub hw_id: u8,
+    /// The hardware compatibility information.
+    pub info: u8
+}
+
+/// Represents a parsed *TZX* block.
+///
+/// Text fields are stored as they appear in the file (usually as ASCII characters).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TzxBlock {
+    /// Standard speed data block, encoded with the ROM timings.
+    StandardSpeed {
+        /// Pause after this block in milliseconds.
+        pause: u16,
+        /// Data as in the *TAP* chunks, including the flag and the checksum byte.
+        data: Box<[u8]>
+    },
+    /// Turbo speed data block.
+    TurboSpeed {
+        timings: TurboTimings,
+        /// Used bits in the last byte, other bits should be 0 (1 - 8).
+        used_bits: u8,
+        /// Pause after this block in milliseconds.
+        pause: u16,
+        data: Box<[u8]>
+    },
+    /// A sequence of pulses of the same length.
+    PureTone {
+        /// Length of one pulse in T-states.
+        pulse: u16,
+        /// The number of pulses.
+        count: u16
+    },
+    /// A sequence of pulses of different lengths in T-states.
+    SeqOfPulses(Box<[u16]>),
+    /// Data block without the PILOT and SYNC pulses.
+    PureData {
+        /// Length of the ZERO bit pulse in T-states.
+        zero: u16,
+        /// Length of the ONE bit pulse in T-states.
+        one: u16,
+        /// Used bits in the last byte, other bits should be 0 (1 - 8).
+        used_bits: u8,
+        /// Pause after this block in milliseconds.
+        pause: u16,
+        data: Box<[u8]>
+    },
+    /// The direct recording block. Each bit represents a sample of the signal level.
+    DirectRec {
+        /// The number of T-states per sample.
+        tstates_per_sample: u16,
+        /// Pause after this block in milliseconds.
+        pause: u16,
+        /// Used bits in the last byte, other bits should be 0 (1 - 8).
+        used_bits: u8,
+        /// Samples, most significant bit first.
+        data: Box<[u8]>
+    },
+    /// The *CSW* recording block.
+    CswRecording {
+        /// Pause after this block in milliseconds.
+        pause: u16,
+        /// The sampling rate (24-bit).
+        sample_rate: u32,
+        compression: CswCompression,
+        /// The number of stored pulses after decompression.
+        pulses: u32,
+        /// The *CSW* data.
+        data: Box<[u8]>
+    },
+    /// The generalized data block.
+    Generalized(Box<GeneralizedData>),
+    /// Pause in milliseconds or "Stop the tape" if 0.
+    Pause(u16),
+    /// The group start with the group name.
+    GroupStart(Box<[u8]>),
+    GroupEnd,
+    /// Jump to the block relative to this one.
+    Jump(i16),
+    /// The loop start with the number of repetitions.
+    LoopStart(u16),
+    LoopEnd,
+    /// The call sequence of blocks relative to this one.
+    CallSeq(Box<[i16]>),
+    Return,
+    /// Select block entries of: `(block offset relative to this one, description)`.
+    Select(Box<[(i16, Box<[u8]>)]>),
+    StopIn48k,
+    /// Set the signal level: `true` for high, `false` for low.
+    SetLevel(bool),
+    /// Text description.
+    Text(Box<[u8]>),
+    /// Message to be displayed.
+    Message {
+        /// The time in seconds for which the message should be displayed.
+        time: u8,
+        message: Box<[u8]>
+    },
+    /// Archive info entries of: `(text ID, text)`.
+    Archive(Box<[(u8, Box<[u8]>)]>),
+    /// Hardware type entries.
+    Hardware(Box<[HardwareInfo]>),
+    /// Custom info block.
+    Custom {
+        /// Identification string.
+        ident: [u8;16],
+        data: Box<[u8]>
+    },
+    /// The "glue" block, appearing where two *TZX* files have been merged together.
+    Glue {
+        major: u8,
+        minor: u8
+    },
+    /// A deprecated or an unknown block.
+    Unsupported {
+        /// The block ID.
+        id: u8,
+        /// Raw block data following the ID byte.
+        data: Box<[u8]>
+    }
+}
+
+/// Represents the whole content of a *TZX* file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TzxTape {
+    /// The major revision number of the file.
+    pub major: u8,
+    /// The minor revision number of the file.
+    pub minor: u8,
+    /// The tape blocks.
+    pub blocks: Vec<TzxBlock>
+}
+
+/// Reads the whole *TZX* file from the given reader.
+pub fn read_tzx<R: Read>(rd: R) -> Result<TzxTape> {
+    TzxTape::read_from(rd)
+}
+
+/// Reads the whole *TZX* file from the given reader and creates a [TzxPulseIter] from its blocks.
+pub fn read_tzx_pulse_iter<R: Read>(rd: R) -> Result<TzxPulseIter<Vec<TzxBlock>>> {
+    read_tzx(rd).map(|tape| TzxPulseIter::new(tape.blocks))
+}
+
 impl From<TzxId> for u8 {
     fn from(id: TzxId) -> u8 {
         id as u8
     }
 }
+
+impl TryFrom<u8> for CswCompression {
+    type Error = &'static str;
+    fn try_from(compression: u8) -> core::result::Result<Self, Self::Error> {
+        match compression {
+            1 => Ok(CswCompression::Rle),
+            2 => Ok(CswCompression::ZRle),
+            _ => Err("Unknown CSW compression type")
+        }
+    }
+}
+
+impl From<u8> for SymbolPolarity {
+    /// Converts the lowest 2 bits of the symbol flags to the polarity.
+    fn from(flags: u8) -> Self {
+        match flags & 3 {
+            0 => SymbolPolarity::Edge,
+            1 => SymbolPolarity::NoEdge,
+            2 => SymbolPolarity::Low,
+            _ => SymbolPolarity::High
+        }
+    }
+}
+
+impl Default for TurboTimings {
+    /// Returns the standard ROM timings with the number of PILOT pulses of the header block.
+    fn default() -> Self {
+        use crate::tap::pulse::consts::*;
+        TurboTimings {
+            pilot: LEAD_PULSE_LENGTH.get() as u16,
+            sync1: SYNC_PULSE1_LENGTH.get() as u16,
+            sync2: SYNC_PULSE2_LENGTH.get() as u16,
+            zero: ZERO_PULSE_LENGTH.get() as u16,
+            one: ONE_PULSE_LENGTH.get() as u16,
+            pilot_count: LEAD_PULSES_HEAD
+        }
+    }
+}
+
+impl TurboTimings {
+    /// Returns the standard ROM timings for the data block beginning with the given `flag` byte.
+    pub fn standard(flag: u8) -> Self {
+        use crate::tap::pulse::consts::*;
+        let pilot_count = if flag & 0x80 == 0 { LEAD_PULSES_HEAD } else { LEAD_PULSES_DATA };
+        TurboTimings { pilot_count, ..Default::default() }
+    }
+}
+
+impl GeneralizedData {
+    /// Returns the number of bits of each symbol in the data stream.
+    pub fn symbol_bits(&self) -> u32 {
+        symbol_bits(self.data_symbols.len())
+    }
+    /// Returns the data symbol index at the given position in the data stream.
+    pub fn data_symbol(&self, index: u32) -> Option<usize> {
+        if index >= self.data_count {
+            return None
+        }
+        let bits = self.symbol_bits();
+        let mut symbol = 0;
+        let start = index as usize * bits as usize;
+        for bit in start..start + bits as usize {
+            let byte = *self.data.get(bit >> 3)?;
+            symbol = (symbol << 1) | ((byte >> (7 - (bit & 7))) & 1) as usize;
+        }
+        Some(symbol)
+    }
+}
+
+/// Returns the number of bits needed to encode the symbol index of the alphabet of the given size.
+pub(crate) fn symbol_bits(size: usize) -> u32 {
+    size.next_power_of_two().trailing_zeros()
+}
+
+impl TzxBlock {
+    /// Returns the ID of this block.
+    pub fn id(&self) -> u8 {
+        let id = match self {
+            TzxBlock::StandardSpeed {..} => TzxId::StandardSpeed,
+            TzxBlock::TurboSpeed {..}    => TzxId::TurboSpeed,
+            TzxBlock::PureTone {..}      => TzxId::PureTone,
+            TzxBlock::SeqOfPulses(..)    => TzxId::SeqOfPulses,
+            TzxBlock::PureData {..}      => TzxId::PureData,
+            TzxBlock::DirectRec {..}     => TzxId::DirectRec,
+            TzxBlock::CswRecording {..}  => TzxId::CswRecording,
+            TzxBlock::Generalized(..)    => TzxId::Generalized,
+            TzxBlock::Pause(..)          => TzxId::Pause,
+            TzxBlock::GroupStart(..)     => TzxId::GroupStart,
+            TzxBlock::GroupEnd           => TzxId::GroupEnd,
+            TzxBlock::Jump(..)           => TzxId::Jump,
+            TzxBlock::LoopStart(..)      => TzxId::LoopStart,
+            TzxBlock::LoopEnd            => TzxId::LoopEnd,
+            TzxBlock::CallSeq(..)        => TzxId::CallSeq,
+            TzxBlock::Return             => TzxId::Return,
+            TzxBlock::Select(..)         => TzxId::Select,
+            TzxBlock::StopIn48k          => TzxId::StopIn48k,
+            TzxBlock::SetLevel(..)       => TzxId::SetLevel,
+            TzxBlock::Text(..)           => TzxId::Text,
+            TzxBlock::Message {..}       => TzxId::Message,
+            TzxBlock::Archive(..)        => TzxId::Archive,
+            TzxBlock::Hardware(..)       => TzxId::Hardware,
+            TzxBlock::Custom {..}        => TzxId::Custom,
+            TzxBlock::Glue {..}          => TzxId::Glue,
+            TzxBlock::Unsupported { id, .. } => return *id
+        };
+        id.into()
+    }
+    /// Returns `true` if this block produces any *TAPE* signal.
+    pub fn is_signal(&self) -> bool {
+        matches!(self,
+            TzxBlock::StandardSpeed {..}|
+            TzxBlock::TurboSpeed {..}|
+            TzxBlock::PureTone {..}|
+            TzxBlock::SeqOfPulses(..)|
+            TzxBlock::PureData {..}|
+            TzxBlock::DirectRec {..}|
+            TzxBlock::CswRecording {..}|
+            TzxBlock::Generalized(..)|
+            TzxBlock::Pause(..))
+    }
+    /// Returns the content of the standard speed data block as a [TapChunk].
+    ///
+    /// Returns `None` for any other block type.
+    pub fn as_tap_chunk(&self) -> Option<TapChunk<&[u8]>> {
+        match self {
+            TzxBlock::StandardSpeed { data, .. } => Some(TapChunk::from(&data[..])),
+            _ => None
+        }
+    }
+}
+
+fn text(text: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(text)
+}
+
+impl fmt::Display for TzxBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TzxBlock::StandardSpeed { data, .. } => {
+                write!(f, "Standard speed data: {}", TapChunk::from(data))
+            }
+            TzxBlock::TurboSpeed { data, .. } => {
+                write!(f, "Turbo speed data: {} bytes", data.len())
+            }
+            TzxBlock::PureTone { pulse, count } => {
+                write!(f, "Pure tone: {} x {} T", count, pulse)
+            }
+            TzxBlock::SeqOfPulses(pulses) => {
+                write!(f, "Sequence of pulses: {}", pulses.len())
+            }
+            TzxBlock::PureData { data, .. } => {
+                write!(f, "Pure data: {} bytes", data.len())
+            }
+            TzxBlock::DirectRec { tstates_per_sample, data, .. } => {
+                write!(f, "Direct recording: {} bytes, {} T per sample", data.len(), tstates_per_sample)
+            }
+            TzxBlock::CswRecording { sample_rate, pulses, .. } => {
+                write!(f, "CSW recording: {} pulses at {} Hz", pulses, sample_rate)
+            }
+            TzxBlock::Generalized(gen) => {
+                write!(f, "Generalized data: {} symbols", gen.data_count)
+            }
+            TzxBlock::Pause(0) => write!(f, "Stop the tape"),
+            TzxBlock::Pause(ms) => write!(f, "Pause: {} ms", ms),
+            TzxBlock::GroupStart(name) => write!(f, "Group: {}", text(name)),
+            TzxBlock::GroupEnd => write!(f, "Group end"),
+            TzxBlock::Jump(offset) => write!(f, "Jump: {:+}", offset),
+            TzxBlock::LoopStart(count) => write!(f, "Loop: {} times", count),
+            TzxBlock::LoopEnd => write!(f, "Loop end"),
+            TzxBlock::CallSeq(calls) => write!(f, "Call sequence: {} calls", calls.len()),
+            TzxBlock::Return => write!(f, "Return"),
+            TzxBlock::Select(entries) => write!(f, "Select: {} options", entries.len()),
+            TzxBlock::StopIn48k => write!(f, "Stop the tape in 48k mode"),
+            TzxBlock::SetLevel(level) => {
+                write!(f, "Set signal level: {}", if *level { "high" } else { "low" })
+            }
+            TzxBlock::Text(txt) => write!(f, "Text: {}", text(txt)),
+            TzxBlock::Message { message, .. } => write!(f, "Message: {}", text(message)),
+            TzxBlock::Archive(entries) => {
+                match entries.iter().find(|(id, _)| *id == 0) {
+                    Some((_, title)) => write!(f, "Archive info: {}", text(title)),
+                    None => write!(f, "Archive info: {} entries", entries.len())
+                }
+            }
+            TzxBlock::Hardware(entries) => write!(f, "Hardware info: {} entries", entries.len()),
+            TzxBlock::Custom { ident, .. } => write!(f, "Custom info: {}", text(ident).trim_end()),
+            TzxBlock::Glue {..} => write!(f, "Glue"),
+            TzxBlock::Unsupported { id, data } => {
+                write!(f, "(unsupported 0x{:02X}: {} bytes)", id, data.len())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use crate::tap::*;
+    use super::*;
+
+    /// Converts the content of a TAP file to the TZX file with standard speed data blocks.
+    pub(super) fn tap_to_tzx(tap: &[u8]) -> Vec<u8> {
+        let mut tzx = TZX_SIGNATURE.to_vec();
+        tzx.extend_from_slice(&[TZX_MAJOR_VERSION, TZX_MINOR_VERSION]);
+        for chunk in TapChunkIter::from(&tap) {
+            tzx.push(TzxId::StandardSpeed.into());
+            tzx.extend_from_slice(&1000u16.to_le_bytes());
+            tzx.extend_from_slice(&(chunk.as_ref().len() as u16).to_le_bytes());
+            tzx.extend_from_slice(chunk.as_ref());
+        }
+        tzx
+    }
+
+    #[test]
+    fn read_tzx_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let mut tzx = tap_to_tzx(&tap);
+        tzx.extend_from_slice(&[
+            0x21, 5, b'G', b'r', b'o', b'u', b'p',
+            0x12, 0x78, 0x08, 0x10, 0x00,
+            0x13, 2, 0x9b, 0x02, 0xdf, 0x02,
+            0x24, 3, 0,
+            0x20, 0xe8, 0x03,
+            0x25,
+            0x22,
+            0x2B, 1, 0, 0, 0, 1,
+            0x30, 4, b'T', b'e', b'x', b't',
+            0x32, 8, 0, 1, 0x00, 5, b'T', b'i', b't', b'l', b'e',
+            0x33, 1, 0, 0x1a, 1,
+            0x2A, 0, 0, 0, 0,
+            0x20, 0, 0,
+            0x16, 2, 0, 0, 0, 0xaa, 0x55
+        ]);
+        let tape = read_tzx(Cursor::new(tzx))?;
+        assert_eq!(TZX_MAJOR_VERSION, tape.major);
+        assert_eq!(TZX_MINOR_VERSION, tape.minor);
+        let res: Vec<_> = tape.blocks.iter().map(|block| format!("{}", block)).collect();
+        assert_eq!(res, [
+            "Standard speed data: Program: \"HelloWorld\" LINE 10",
+            "Standard speed data: (data 6)",
+            "Standard speed data: Number array: \"a(10)\" DATA A()",
+            "Standard speed data: (data 53)",
+            "Standard speed data: Character array: \"weekdays\" DATA W$()",
+            "Standard speed data: (data 26)",
+            "Group: Group",
+            "Pure tone: 16 x 2168 T",
+            "Sequence of pulses: 2",
+            "Loop: 3 times",
+            "Pause: 1000 ms",
+            "Loop end",
+            "Group end",
+            "Set signal level: high",
+            "Text: Text",
+            "Archive info: Title",
+            "Hardware info: 1 entries",
+            "Stop the tape in 48k mode",
+            "Stop the tape",
+            "(unsupported 0x16: 6 bytes)"]);
+        assert_eq!(TzxBlock::SeqOfPulses(vec![667, 735].into()), tape.blocks[8]);
+        assert_eq!(Some(&tap[2..21]), tape.blocks[0].as_tap_chunk().map(TapChunk::into_inner));
+        assert_eq!(0x16, tape.blocks[19].id());
+        assert!(TzxId::try_from(0x16).is_ok());
+        assert!(TzxId::try_from(0x17).is_ok());
+        assert!(TzxId::try_from(0x1A).is_err());
+
+        let mut tzx = TZX_SIGNATURE.to_vec();
+        tzx.extend_from_slice(&[2, 0]);
+        assert!(read_tzx(&tzx[..]).is_err());
+        tzx[1] = b'Z';
+        assert!(read_tzx(&tzx[..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn generalized_data_works() {
+        let gen = GeneralizedData {
+            pause: 0,
+            pilot_symbols: Box::new([]),
+            pilot_stream: Box::new([]),
+            data_symbols: vec![TzxSymbol { polarity: SymbolPolarity::Edge, pulses: Box::new([100]) }; 3].into(),
+            data_count: 5,
+            data: Box::new([0b00_01_10_11, 0b01_000000])
+        };
+        assert_eq!(2, gen.symbol_bits());
+        let symbols: Vec<_> = (0..6).map(|n| gen.data_symbol(n)).collect();
+        assert_eq!(symbols, [Some(0), Some(1), Some(2), Some(3), Some(1), None]);
+        assert_eq!(0, symbol_bits(1));
+        assert_eq!(1, symbol_bits(2));
+        assert_eq!(8, symbol_bits(256));
+    }
+}
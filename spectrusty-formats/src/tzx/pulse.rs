@@ -0,0 +1,697 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::num::NonZeroU32;
+use std::io::{ErrorKind, Error, Result};
+
+#[cfg(feature = "compression")]
+use compression::prelude::*;
+use log::warn;
+
+use super::*;
+
+/// The number of T-states in a millisecond.
+const TSTATES_PER_MS: u32 = 3_500;
+/// The clock frequency used for converting the sampling rate of *CSW* recordings.
+const CPU_HZ: u64 = 3_500_000;
+/// The maximum number of blocks entered and signal operations not producing any pulse by a single
+/// call to [Iterator::next], so a malformed tape can not hang the iterator.
+const MAX_SILENT_STEPS: u32 = 0x4_0000;
+
+/// Implements an iterator of T-state pulse intervals over the slice of [TzxBlock]s.
+///
+/// Anything that implements `AsRef<[TzxBlock]>` can be used as `T` (e.g. `&[TzxBlock]` or `Vec<TzxBlock>`).
+///
+/// The iterator follows the flow control blocks (loops, jumps, calls and returns). The select blocks
+/// are ignored. The tape stops after the *Pause* block with a zero duration and, if
+/// [TzxPulseIter::mode48k] is `true`, after the *Stop the tape if in 48k mode* block. While the tape
+/// is stopped, [Iterator::next] returns `None`. Use [TzxPulseIter::resume] to continue.
+#[derive(Clone, Debug)]
+pub struct TzxPulseIter<T> {
+    /// Determines if the *Stop the tape if in 48k mode* blocks should stop the tape.
+    pub mode48k: bool,
+    index: usize,
+    state: PulseState,
+    level: bool,
+    stopped: bool,
+    pending: u32,
+    /// (the index of the first block of the loop, the number of remaining repetitions)
+    repeat: Option<(usize, u16)>,
+    /// (the index of the call sequence block, the index of the current call)
+    call: Option<(usize, usize)>,
+    csw: Vec<u32>,
+    blocks: T
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    /// Hold the level then toggle it.
+    Pulse(u32),
+    /// Toggle the level unless there is nothing pending.
+    Edge,
+    /// Hold the level.
+    Hold(u32),
+    /// Force the level.
+    Level(bool)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PulseState {
+    Enter,
+    Pilot { countdown: u16 },
+    Sync2,
+    Data { offset: usize, bit: u8, second: bool },
+    Tone { countdown: u16 },
+    Pulses { offset: usize },
+    DirectRec { offset: usize, bit: u8, hold: bool },
+    Csw { offset: usize },
+    GenPilot { entry: usize, repeat: u16, step: usize },
+    GenData { index: u32, step: usize },
+    GenClose,
+    Pause { ms: u16, stage: u8 },
+    Leave,
+    Done
+}
+
+impl<T> TzxPulseIter<T> {
+    /// Returns the wrapped blocks container.
+    pub fn into_inner(self) -> T {
+        self.blocks
+    }
+    /// Returns a reference to the wrapped blocks container.
+    pub fn get_ref(&self) -> &T {
+        &self.blocks
+    }
+    /// Returns the index of the currently processed block.
+    ///
+    /// The returned value may be equal to the number of blocks if the tape has ended.
+    pub fn block_index(&self) -> usize {
+        self.index
+    }
+    /// Returns the current signal level: `true` is high, `false` is low.
+    pub fn level(&self) -> bool {
+        self.level
+    }
+    /// Returns `true` if the tape has been stopped by one of the blocks.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+    /// Returns `true` if there are no more pulses to emit.
+    pub fn is_done(&self) -> bool {
+        self.state == PulseState::Done
+    }
+    /// Resumes the tape stopped by one of the blocks.
+    pub fn resume(&mut self) {
+        self.stopped = false;
+    }
+}
+
+impl<T: AsRef<[TzxBlock]>> TzxPulseIter<T> {
+    /// Creates a new iterator from the given blocks container.
+    pub fn new(blocks: T) -> Self {
+        let mut iter = TzxPulseIter {
+            mode48k: false,
+            index: 0,
+            state: PulseState::Enter,
+            level: false,
+            stopped: false,
+            pending: 0,
+            repeat: None,
+            call: None,
+            csw: Vec::new(),
+            blocks
+        };
+        iter.rewind();
+        iter
+    }
+    /// Rewinds the tape to the first block.
+    pub fn rewind(&mut self) {
+        self.jump_to_block(0);
+    }
+    /// Rewinds or forwards the tape to the beginning of the block at the given `index`.
+    ///
+    /// Resumes the tape and resets the state of loops and calls.
+    pub fn jump_to_block(&mut self, index: usize) {
+        self.index = index;
+        self.state = if index < self.blocks.as_ref().len() {
+            PulseState::Enter
+        }
+        else {
+            PulseState::Done
+        };
+        self.stopped = false;
+        self.pending = 0;
+        self.repeat = None;
+        self.call = None;
+    }
+
+    fn goto_block(&mut self, index: Option<usize>) {
+        let len = self.blocks.as_ref().len();
+        match index {
+            Some(index) if index < len => {
+                self.index = index;
+                self.state = PulseState::Enter;
+            }
+            _ => {
+                self.index = len;
+                self.state = PulseState::Done;
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.stopped = true;
+        self.pending = 0;
+        self.goto_block(self.index.checked_add(1));
+    }
+
+    /// `steps` counts the blocks entered and the operations not producing any pulse since the last pulse.
+    fn next_op(&mut self, steps: &mut u32) -> Option<Op> {
+        loop {
+            if self.stopped {
+                return None
+            }
+            let block = match self.blocks.as_ref().get(self.index) {
+                Some(block) => block,
+                None => {
+                    self.state = PulseState::Done;
+                    return None
+                }
+            };
+            match self.state {
+                PulseState::Done => return None,
+                PulseState::Enter => {
+                    *steps += 1;
+                    if *steps > MAX_SILENT_STEPS {
+                        warn!("TZX: too many blocks without any signal");
+                        self.goto_block(None);
+                        return None
+                    }
+                    if let Some(op) = self.enter_block() {
+                        return Some(op)
+                    }
+                }
+                PulseState::Leave => {
+                    self.goto_block(self.index.checked_add(1));
+                }
+                state => {
+                    let (op, state) = next_block_op(block, state, &self.csw);
+                    self.state = state;
+                    if let Some(op) = op {
+                        return Some(op)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets the initial state of the current block or executes the flow control blocks.
+    fn enter_block(&mut self) -> Option<Op> {
+        let index = self.index;
+        let next = index.checked_add(1);
+        let blocks = self.blocks.as_ref();
+        self.state = match &blocks[index] {
+            TzxBlock::StandardSpeed { data, pause } => {
+                match data.first() {
+                    Some(&flag) => PulseState::Pilot { countdown: TurboTimings::standard(flag).pilot_count },
+                    None => PulseState::Pause { ms: *pause, stage: 0 }
+                }
+            }
+            TzxBlock::TurboSpeed { timings, .. } => {
+                PulseState::Pilot { countdown: timings.pilot_count }
+            }
+            TzxBlock::PureTone { count, .. } => PulseState::Tone { countdown: *count },
+            TzxBlock::SeqOfPulses(..) => PulseState::Pulses { offset: 0 },
+            TzxBlock::PureData {..} => PulseState::Data { offset: 0, bit: 0, second: false },
+            TzxBlock::DirectRec {..} => PulseState::DirectRec { offset: 0, bit: 0, hold: false },
+            TzxBlock::CswRecording { pause, sample_rate, compression, data, .. } => {
+                match decode_csw(*sample_rate, *compression, data, &mut self.csw) {
+                    Ok(()) => PulseState::Csw { offset: 0 },
+                    Err(e) => {
+                        warn!("TZX: CSW recording block {}: {}", index, e);
+                        PulseState::Pause { ms: *pause, stage: 0 }
+                    }
+                }
+            }
+            TzxBlock::Generalized(..) => PulseState::GenPilot { entry: 0, repeat: 0, step: 0 },
+            TzxBlock::Pause(0) => {
+                self.stop();
+                return None
+            }
+            &TzxBlock::Pause(ms) => PulseState::Pause { ms, stage: 0 },
+            &TzxBlock::Jump(offset) => {
+                let target = if offset == 0 { next } else { rel_index(index, offset) };
+                self.goto_block(target);
+                return None
+            }
+            &TzxBlock::LoopStart(count) => {
+                self.repeat = next.map(|start| (start, count));
+                PulseState::Leave
+            }
+            TzxBlock::LoopEnd => {
+                if let Some((start, count)) = self.repeat {
+                    if count > 1 {
+                        self.repeat = Some((start, count - 1));
+                        self.goto_block(Some(start));
+                        return None
+                    }
+                    self.repeat = None;
+                }
+                PulseState::Leave
+            }
+            TzxBlock::CallSeq(calls) => {
+                if let Some(&offset) = calls.first() {
+                    self.call = Some((index, 0));
+                    self.goto_block(rel_index(index, offset));
+                    return None
+                }
+                PulseState::Leave
+            }
+            TzxBlock::Return => {
+                if let Some((call_index, call_no)) = self.call.take() {
+                    let offset = match &blocks[call_index] {
+                        TzxBlock::CallSeq(calls) => calls.get(call_no + 1),
+                        _ => None
+                    };
+                    match offset {
+                        Some(&offset) => {
+                            self.call = Some((call_index, call_no + 1));
+                            self.goto_block(rel_index(call_index, offset));
+                        }
+                        None => self.goto_block(call_index.checked_add(1))
+                    }
+                    return None
+                }
+                PulseState::Leave
+            }
+            TzxBlock::StopIn48k if self.mode48k => {
+                self.stop();
+                return None
+            }
+            &TzxBlock::SetLevel(level) => {
+                self.state = PulseState::Leave;
+                return Some(Op::Level(level))
+            }
+            _ => PulseState::Leave
+        };
+        None
+    }
+}
+
+// i16::unsigned_abs requires Rust 1.51
+#[allow(clippy::cast_abs_to_unsigned)]
+fn rel_index(index: usize, offset: i16) -> Option<usize> {
+    if offset < 0 {
+        index.checked_sub((offset as i32).abs() as usize)
+    }
+    else {
+        index.checked_add(offset as usize)
+    }
+}
+
+fn used_bits_of(data: &[u8], offset: usize, used_bits: u8) -> u8 {
+    if offset + 1 == data.len() && (1..=8).contains(&used_bits) {
+        used_bits
+    }
+    else {
+        8
+    }
+}
+
+/// Returns the next operation and the next state of the signal producing block.
+fn next_block_op(block: &TzxBlock, state: PulseState, csw: &[u32]) -> (Option<Op>, PulseState) {
+    use PulseState::*;
+    let (timings, used_bits, pause, data) = match block {
+        TzxBlock::StandardSpeed { pause, data } => {
+            let flag = data.first().copied().unwrap_or_default();
+            (TurboTimings::standard(flag), 8, *pause, &data[..])
+        }
+        TzxBlock::TurboSpeed { timings, used_bits, pause, data } => (*timings, *used_bits, *pause, &data[..]),
+        &TzxBlock::PureData { zero, one, used_bits, pause, ref data } => {
+            (TurboTimings { zero, one, ..Default::default() }, used_bits, pause, &data[..])
+        }
+        TzxBlock::PureTone { pulse, .. } => {
+            return match state {
+                Tone { countdown } if countdown != 0 => {
+                    (Some(Op::Pulse((*pulse).into())), Tone { countdown: countdown - 1 })
+                }
+                _ => (None, Leave)
+            }
+        }
+        TzxBlock::SeqOfPulses(pulses) => {
+            return match state {
+                Pulses { offset } if offset < pulses.len() => {
+                    (Some(Op::Pulse(pulses[offset].into())), Pulses { offset: offset + 1 })
+                }
+                _ => (None, Leave)
+            }
+        }
+        &TzxBlock::DirectRec { tstates_per_sample, pause, used_bits, ref data } => {
+            return match state {
+                DirectRec { offset, bit, hold: false } if offset < data.len() => {
+                    let level = data[offset] & (0x80 >> bit) != 0;
+                    (Some(Op::Level(level)), DirectRec { offset, bit, hold: true })
+                }
+                DirectRec { offset, bit, hold: true } => {
+                    let state = if bit + 1 < used_bits_of(data, offset, used_bits) {
+                        DirectRec { offset, bit: bit + 1, hold: false }
+                    }
+                    else {
+                        DirectRec { offset: offset + 1, bit: 0, hold: false }
+                    };
+                    (Some(Op::Hold(tstates_per_sample.into())), state)
+                }
+                Pause { stage, .. } => pause_op(pause, stage),
+                _ => pause_op(pause, 0)
+            }
+        }
+        &TzxBlock::CswRecording { pause, .. } => {
+            return match state {
+                Csw { offset } if offset < csw.len() => {
+                    (Some(Op::Pulse(csw[offset])), Csw { offset: offset + 1 })
+                }
+                Pause { stage, .. } => pause_op(pause, stage),
+                _ => pause_op(pause, 0)
+            }
+        }
+        TzxBlock::Generalized(gen) => return next_generalized_op(gen, state),
+        &TzxBlock::Pause(ms) => {
+            return match state {
+                Pause { stage, .. } => pause_op(ms, stage),
+                _ => (None, Leave)
+            }
+        }
+        _ => return (None, Leave)
+    };
+    match state {
+        Pilot { countdown } if countdown != 0 => {
+            (Some(Op::Pulse(timings.pilot.into())), Pilot { countdown: countdown - 1 })
+        }
+        Pilot {..} => (Some(Op::Pulse(timings.sync1.into())), Sync2),
+        Sync2 => (Some(Op::Pulse(timings.sync2.into())), Data { offset: 0, bit: 0, second: false }),
+        Data { offset, bit, second } if offset < data.len() => {
+            let pulse = if data[offset] & (0x80 >> bit) != 0 { timings.one } else { timings.zero };
+            let state = if !second {
+                Data { offset, bit, second: true }
+            }
+            else if bit + 1 < used_bits_of(data, offset, used_bits) {
+                Data { offset, bit: bit + 1, second: false }
+            }
+            else {
+                Data { offset: offset + 1, bit: 0, second: false }
+            };
+            (Some(Op::Pulse(pulse.into())), state)
+        }
+        Pause { stage, .. } => pause_op(pause, stage),
+        _ => pause_op(pause, 0)
+    }
+}
+
+/// The operations of the pause following the block: hold the last level for 1 ms,
+/// then force the level low for the rest of the pause.
+fn pause_op(ms: u16, stage: u8) -> (Option<Op>, PulseState) {
+    match (ms, stage) {
+        (0, _) => (None, PulseState::Leave),
+        (_, 0) => (Some(Op::Hold(TSTATES_PER_MS)), PulseState::Pause { ms, stage: 1 }),
+        (_, 1) => (Some(Op::Level(false)), PulseState::Pause { ms, stage: 2 }),
+        (ms, 2) if ms > 1 => {
+            (Some(Op::Hold(u32::from(ms - 1) * TSTATES_PER_MS)), PulseState::Pause { ms, stage: 3 })
+        }
+        _ => (None, PulseState::Leave)
+    }
+}
+
+/// Returns the operation of the given step of the symbol: the first step sets the polarity,
+/// each next step holds the level for the duration of the pulse.
+fn symbol_op(symbol: &TzxSymbol, step: usize) -> Option<Op> {
+    if step == 0 {
+        return Some(match symbol.polarity {
+            SymbolPolarity::Edge => Op::Edge,
+            SymbolPolarity::NoEdge => Op::Hold(0),
+            SymbolPolarity::Low => Op::Level(false),
+            SymbolPolarity::High => Op::Level(true)
+        })
+    }
+    let pulses = &symbol.pulses;
+    match pulses.get(step - 1) {
+        Some(&pulse) if step < pulses.len() => Some(Op::Pulse(pulse.into())),
+        Some(&pulse) => Some(Op::Hold(pulse.into())),
+        None => None
+    }
+}
+
+fn next_generalized_op(gen: &GeneralizedData, state: PulseState) -> (Option<Op>, PulseState) {
+    use PulseState::*;
+    match state {
+        GenPilot { entry, repeat, step } => {
+            match gen.pilot_stream.get(entry) {
+                Some(&(symbol, count)) if repeat < count => {
+                    match symbol_op(&gen.pilot_symbols[symbol as usize], step) {
+                        Some(op) => (Some(op), GenPilot { entry, repeat, step: step + 1 }),
+                        None => (None, GenPilot { entry, repeat: repeat + 1, step: 0 })
+                    }
+                }
+                Some(..) => (None, GenPilot { entry: entry + 1, repeat: 0, step: 0 }),
+                None => (None, GenData { index: 0, step: 0 })
+            }
+        }
+        GenData { index, step } => {
+            match gen.data_symbol(index) {
+                Some(symbol) => {
+                    match symbol_op(&gen.data_symbols[symbol], step) {
+                        Some(op) => (Some(op), GenData { index, step: step + 1 }),
+                        None => (None, GenData { index: index + 1, step: 0 })
+                    }
+                }
+                None => (None, GenClose)
+            }
+        }
+        GenClose => (Some(Op::Edge), Pause { ms: gen.pause, stage: 0 }),
+        Pause { stage, .. } => pause_op(gen.pause, stage),
+        _ => (None, Leave)
+    }
+}
+
+/// Decodes the *CSW* recording data into pulse lengths in T-states.
+fn decode_csw(sample_rate: u32, compression: CswCompression, data: &[u8], pulses: &mut Vec<u32>) -> Result<()> {
+    if sample_rate == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid sample rate"))
+    }
+    let data = match compression {
+        CswCompression::Rle => Cow::Borrowed(data),
+        #[cfg(feature = "compression")]
+        CswCompression::ZRle => {
+            Cow::Owned(data.iter().copied().decode(&mut ZlibDecoder::new())
+                           .collect::<core::result::Result<Vec<_>, _>>()
+                           .map_err(|e| Error::new(ErrorKind::InvalidData, e))?)
+        }
+        #[cfg(not(feature = "compression"))]
+        CswCompression::ZRle => {
+            return Err(Error::new(ErrorKind::InvalidData, "Z-RLE compression is not supported"))
+        }
+    };
+    pulses.clear();
+    let mut rd = &data[..];
+    let mut samples: u64 = 0;
+    let mut tstates: u64 = 0;
+    while let Some((&count, rest)) = rd.split_first() {
+        rd = rest;
+        let count = match count {
+            0 if rd.len() >= 4 => {
+                let (count, rest) = rd.split_at(4);
+                rd = rest;
+                u32::from_le_bytes([count[0], count[1], count[2], count[3]])
+            }
+            0 => return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected end of CSW data")),
+            count => count.into()
+        };
+        // the pulses are rounded to the nearest T-state without accumulating an error
+        samples += u64::from(count);
+        let end = (samples * CPU_HZ + u64::from(sample_rate) / 2) / u64::from(sample_rate);
+        let pulse = u32::try_from(end - tstates).unwrap_or(u32::MAX);
+        tstates = end;
+        pulses.push(pulse);
+    }
+    Ok(())
+}
+
+impl<T: AsRef<[TzxBlock]>> Iterator for TzxPulseIter<T> {
+    type Item = NonZeroU32;
+
+    fn next(&mut self) -> Option<NonZeroU32> {
+        let mut steps = 0;
+        loop {
+            match self.next_op(&mut steps)? {
+                Op::Pulse(pulse) => {
+                    let pending = self.pending.saturating_add(pulse);
+                    self.pending = 0;
+                    if let Some(pulse) = NonZeroU32::new(pending) {
+                        self.level = !self.level;
+                        return Some(pulse)
+                    }
+                }
+                Op::Edge => {
+                    if let Some(pulse) = NonZeroU32::new(self.pending) {
+                        self.pending = 0;
+                        self.level = !self.level;
+                        return Some(pulse)
+                    }
+                }
+                Op::Hold(tstates) => {
+                    self.pending = self.pending.saturating_add(tstates);
+                }
+                Op::Level(level) => {
+                    if level != self.level {
+                        if let Some(pulse) = NonZeroU32::new(self.pending) {
+                            self.pending = 0;
+                            self.level = level;
+                            return Some(pulse)
+                        }
+                    }
+                }
+            }
+            // the operations not producing any pulse
+            steps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use crate::tap::write_tap;
+    use super::*;
+    use super::super::tests::tap_to_tzx;
+
+    fn pulses<T: AsRef<[TzxBlock]>>(iter: &mut TzxPulseIter<T>) -> Vec<u32> {
+        iter.by_ref().map(NonZeroU32::get).collect()
+    }
+
+    #[test]
+    fn tzx_pulse_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let mut pulse_iter = read_tzx_pulse_iter(Cursor::new(tap_to_tzx(&tap)))?;
+        let mut tap_writer = write_tap(Cursor::new(Vec::new()))?;
+        // the pause after the last block ends the last chunk
+        assert_eq!(6, tap_writer.write_pulses_as_tap_chunks(&mut pulse_iter)?);
+        assert_eq!(0, tap_writer.end_pulse_chunk()?);
+        assert!(pulse_iter.is_done());
+        assert_eq!(6, pulse_iter.block_index());
+        let tgt: Vec<u8> = tap_writer.into_inner().into_inner().into_inner();
+        assert_eq!(tap, tgt);
+        Ok(())
+    }
+
+    #[test]
+    fn tzx_flow_control_works() {
+        let tone = |pulse| TzxBlock::PureTone { pulse, count: 2 };
+        let blocks = vec![
+            TzxBlock::LoopStart(2),
+            tone(100),
+            TzxBlock::LoopEnd,
+            TzxBlock::Jump(2),
+            tone(200),
+            TzxBlock::CallSeq(vec![3, 3].into()),
+            TzxBlock::Pause(0),
+            TzxBlock::Jump(3),
+            tone(300),
+            TzxBlock::Return,
+            TzxBlock::StopIn48k,
+            TzxBlock::SeqOfPulses(vec![400, 0, 500].into()),
+        ];
+        let mut iter = TzxPulseIter::new(&blocks[..]);
+        assert_eq!(pulses(&mut iter), [100, 100, 100, 100, 300, 300, 300, 300]);
+        assert!(iter.is_stopped());
+        assert!(!iter.is_done());
+        assert_eq!(None, iter.next());
+        iter.resume();
+        assert_eq!(pulses(&mut iter), [400, 500]);
+        assert!(iter.is_done());
+        assert_eq!(blocks.len(), iter.block_index());
+
+        iter.mode48k = true;
+        iter.rewind();
+        assert_eq!(pulses(&mut iter), [100, 100, 100, 100, 300, 300, 300, 300]);
+        iter.resume();
+        assert_eq!(pulses(&mut iter), []);
+        assert!(iter.is_stopped());
+        iter.resume();
+        assert_eq!(pulses(&mut iter), [400, 500]);
+        iter.jump_to_block(4);
+        assert_eq!(pulses(&mut iter), [200, 200, 300, 300, 300, 300]);
+        assert!(iter.is_stopped());
+    }
+
+    #[test]
+    fn tzx_silent_loop_ends() {
+        let blocks = [
+            TzxBlock::PureTone { pulse: 100, count: 2 },
+            TzxBlock::SetLevel(false),
+            TzxBlock::Jump(-1)
+        ];
+        let mut iter = TzxPulseIter::new(&blocks);
+        assert_eq!(pulses(&mut iter), [100, 100]);
+        assert!(iter.is_done());
+        let blocks = [TzxBlock::LoopEnd, TzxBlock::Jump(-1)];
+        let mut iter = TzxPulseIter::new(&blocks);
+        assert_eq!(None, iter.next());
+        assert!(iter.is_done());
+    }
+
+    #[test]
+    fn tzx_signal_blocks_work() {
+        let symbol = |polarity, pulses: &[u16]| TzxSymbol { polarity, pulses: pulses.into() };
+        let blocks = vec![
+            TzxBlock::PureData { zero: 10, one: 20, used_bits: 2, pause: 0, data: Box::new([0x40]) },
+            TzxBlock::SetLevel(false),
+            TzxBlock::DirectRec { tstates_per_sample: 5, pause: 0, used_bits: 6, data: Box::new([0b11001100]) },
+            TzxBlock::Pause(2),
+            TzxBlock::CswRecording { pause: 0, sample_rate: 1_750_000, compression: CswCompression::Rle,
+                                     pulses: 2, data: Box::new([3, 0, 4, 0, 0, 0]) },
+            TzxBlock::Generalized(Box::new(GeneralizedData {
+                pause: 1,
+                pilot_symbols: vec![symbol(SymbolPolarity::Edge, &[30, 40])].into(),
+                pilot_stream: Box::new([(0, 2)]),
+                data_symbols: vec![symbol(SymbolPolarity::Edge, &[50]),
+                                   symbol(SymbolPolarity::NoEdge, &[60])].into(),
+                data_count: 3,
+                data: Box::new([0b0110_0000])
+            }))
+        ];
+        let mut iter = TzxPulseIter::new(blocks);
+        assert_eq!(pulses(&mut iter), [
+            // pure data: bits 0 and 1
+            10, 10, 20, 20,
+            // direct recording: the level is already low, so the first sample is prolonged
+            5, 5, 10,
+            // the last 2 samples and 1 ms of a pause, then a low level for the rest
+            3510,
+            // CSW recording: 3 and 4 samples at a half of the CPU frequency
+            3500 + 6, 8,
+            // generalized: pilot symbols
+            30, 40, 30, 40,
+            // data: symbol 0 followed by 2 prolonged symbols 1
+            50 + 60 + 60,
+            // the pause after the generalized data block
+            3500]);
+        assert!(iter.is_done());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn tzx_csw_zrle_works() {
+        let rle = [3u8, 0, 4, 0, 0, 0, 7];
+        let data: Vec<u8> = rle.iter().copied().encode(&mut ZlibEncoder::new(), Action::Finish)
+                               .collect::<core::result::Result<_,_>>().unwrap();
+        let blocks = [TzxBlock::CswRecording { pause: 0, sample_rate: 44100, compression: CswCompression::ZRle,
+                                               pulses: 3, data: data.into() }];
+        let mut iter = TzxPulseIter::new(&blocks);
+        assert_eq!(pulses(&mut iter), [238, 318, 555]);
+    }
+}
@@ -0,0 +1,386 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryFrom;
+use core::slice;
+use std::io::{ErrorKind, Error, Read, Result};
+
+use crate::ReadExactEx;
+use super::*;
+
+/// Implements an iterator of [TzxBlock]s parsed from the underlying reader.
+///
+/// Created by [TzxReadIter::try_new] which reads and validates the *TZX* header first.
+#[derive(Debug)]
+pub struct TzxReadIter<R> {
+    major: u8,
+    minor: u8,
+    done: bool,
+    rd: R
+}
+
+fn invalid_data<T>(msg: &'static str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, msg))
+}
+
+fn read_u8<R: Read>(rd: &mut R) -> Result<u8> {
+    let mut byte = 0u8;
+    rd.read_exact(slice::from_mut(&mut byte))?;
+    Ok(byte)
+}
+
+fn read_u16<R: Read>(rd: &mut R) -> Result<u16> {
+    let mut buf = [0u8;2];
+    rd.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u24<R: Read>(rd: &mut R) -> Result<u32> {
+    let mut buf = [0u8;4];
+    rd.read_exact(&mut buf[0..3])?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(rd: &mut R) -> Result<u32> {
+    let mut buf = [0u8;4];
+    rd.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_data<R: Read>(rd: &mut R, len: u32) -> Result<Box<[u8]>> {
+    let mut data = Vec::new();
+    rd.take(len.into()).read_to_end(&mut data)?;
+    if data.len() != len as usize {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "TZX: unexpected end of block data"))
+    }
+    Ok(data.into_boxed_slice())
+}
+
+fn read_symbols<R: Read>(rd: &mut R, count: usize, max_pulses: u8) -> Result<Box<[TzxSymbol]>> {
+    (0..count).map(|_| {
+        let polarity = SymbolPolarity::from(read_u8(rd)?);
+        let mut pulses = Vec::with_capacity(max_pulses.into());
+        for _ in 0..max_pulses {
+            pulses.push(read_u16(rd)?);
+        }
+        // a zero pulse terminates the symbol definition
+        if let Some(end) = pulses.iter().position(|&p| p == 0) {
+            pulses.truncate(end);
+        }
+        Ok(TzxSymbol { polarity, pulses: pulses.into_boxed_slice() })
+    }).collect()
+}
+
+fn read_generalized(mut rd: &[u8]) -> Result<GeneralizedData> {
+    let rd = &mut rd;
+    let pause = read_u16(rd)?;
+    let pilot_count = read_u32(rd)?;
+    let pilot_max_pulses = read_u8(rd)?;
+    let pilot_size = match read_u8(rd)? { 0 => 256, n => n as usize };
+    let data_count = read_u32(rd)?;
+    let data_max_pulses = read_u8(rd)?;
+    let data_size = match read_u8(rd)? { 0 => 256, n => n as usize };
+    let (pilot_symbols, pilot_stream) = if pilot_count != 0 {
+        let symbols = read_symbols(rd, pilot_size, pilot_max_pulses)?;
+        let stream = (0..pilot_count).map(|_| {
+            let symbol = read_u8(rd)?;
+            let repeat = read_u16(rd)?;
+            if symbol as usize >= symbols.len() {
+                return invalid_data("TZX: invalid pilot symbol in generalized data")
+            }
+            Ok((symbol, repeat))
+        }).collect::<Result<_>>()?;
+        (symbols, stream)
+    }
+    else {
+        Default::default()
+    };
+    let (data_symbols, data) = if data_count != 0 {
+        let symbols = read_symbols(rd, data_size, data_max_pulses)?;
+        let bits = u64::from(data_count) * u64::from(symbol_bits(data_size));
+        let len = bits / 8 + u64::from(bits % 8 != 0);
+        if len > rd.len() as u64 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "TZX: unexpected end of generalized data"))
+        }
+        (symbols, rd[..len as usize].into())
+    }
+    else {
+        Default::default()
+    };
+    let gen = GeneralizedData { pause, pilot_symbols, pilot_stream, data_symbols, data_count, data };
+    if (0..gen.data_count).any(|n| gen.data_symbol(n).unwrap() >= gen.data_symbols.len()) {
+        return invalid_data("TZX: invalid data symbol in generalized data")
+    }
+    Ok(gen)
+}
+
+fn read_entries<R: Read, F, T>(rd: &mut R, mut entry: F) -> Result<Box<[T]>>
+    where F: FnMut(&mut &[u8]) -> Result<T>
+{
+    let len = read_u16(rd)?;
+    let data = read_data(rd, len.into())?;
+    let mut rd = &data[..];
+    let count = read_u8(&mut rd)?;
+    let entries = (0..count).map(|_| entry(&mut rd)).collect::<Result<_>>()?;
+    if !rd.is_empty() {
+        return invalid_data("TZX: invalid length of the block")
+    }
+    Ok(entries)
+}
+
+fn read_text<R: Read>(rd: &mut R) -> Result<Box<[u8]>> {
+    let len = read_u8(rd)?;
+    read_data(rd, len.into())
+}
+
+impl TzxBlock {
+    /// Reads the next block from the given reader.
+    ///
+    /// Returns `Ok(None)` if the end of the stream has been reached before the block ID.
+    pub fn read_block<R: Read>(mut rd: R) -> Result<Option<TzxBlock>> {
+        let rd = &mut rd;
+        let mut id = 0u8;
+        if !rd.read_exact_or_none(slice::from_mut(&mut id))? {
+            return Ok(None)
+        }
+        let id = match TzxId::try_from(id) {
+            Ok(id) => id,
+            Err(_) => {
+                // the general extension rule: each unknown block begins with its length
+                let len = read_u32(rd)?;
+                let mut data = len.to_le_bytes().to_vec();
+                data.extend_from_slice(&read_data(rd, len)?);
+                return Ok(Some(TzxBlock::Unsupported { id, data: data.into_boxed_slice() }))
+            }
+        };
+        let block = match id {
+            TzxId::StandardSpeed => {
+                let pause = read_u16(rd)?;
+                let len = read_u16(rd)?;
+                let data = read_data(rd, len.into())?;
+                TzxBlock::StandardSpeed { pause, data }
+            }
+            TzxId::TurboSpeed => {
+                let timings = TurboTimings {
+                    pilot: read_u16(rd)?,
+                    sync1: read_u16(rd)?,
+                    sync2: read_u16(rd)?,
+                    zero: read_u16(rd)?,
+                    one: read_u16(rd)?,
+                    pilot_count: read_u16(rd)?
+                };
+                let used_bits = read_u8(rd)?;
+                let pause = read_u16(rd)?;
+                let len = read_u24(rd)?;
+                let data = read_data(rd, len)?;
+                TzxBlock::TurboSpeed { timings, used_bits, pause, data }
+            }
+            TzxId::PureTone => {
+                let pulse = read_u16(rd)?;
+                let count = read_u16(rd)?;
+                TzxBlock::PureTone { pulse, count }
+            }
+            TzxId::SeqOfPulses => {
+                let count = read_u8(rd)?;
+                let pulses = (0..count).map(|_| read_u16(rd)).collect::<Result<_>>()?;
+                TzxBlock::SeqOfPulses(pulses)
+            }
+            TzxId::PureData => {
+                let zero = read_u16(rd)?;
+                let one = read_u16(rd)?;
+                let used_bits = read_u8(rd)?;
+                let pause = read_u16(rd)?;
+                let len = read_u24(rd)?;
+                let data = read_data(rd, len)?;
+                TzxBlock::PureData { zero, one, used_bits, pause, data }
+            }
+            TzxId::DirectRec => {
+                let tstates_per_sample = read_u16(rd)?;
+                let pause = read_u16(rd)?;
+                let used_bits = read_u8(rd)?;
+                let len = read_u24(rd)?;
+                let data = read_data(rd, len)?;
+                TzxBlock::DirectRec { tstates_per_sample, pause, used_bits, data }
+            }
+            TzxId::CswRecording => {
+                let len = read_u32(rd)?;
+                let len = match len.checked_sub(10) {
+                    Some(len) => len,
+                    None => return invalid_data("TZX: invalid length of the CSW block")
+                };
+                let pause = read_u16(rd)?;
+                let sample_rate = read_u24(rd)?;
+                let compression = CswCompression::try_from(read_u8(rd)?)
+                                  .or_else(invalid_data)?;
+                let pulses = read_u32(rd)?;
+                let data = read_data(rd, len)?;
+                TzxBlock::CswRecording { pause, sample_rate, compression, pulses, data }
+            }
+            TzxId::Generalized => {
+                let len = read_u32(rd)?;
+                let data = read_data(rd, len)?;
+                TzxBlock::Generalized(Box::new(read_generalized(&data)?))
+            }
+            TzxId::Pause => TzxBlock::Pause(read_u16(rd)?),
+            TzxId::GroupStart => TzxBlock::GroupStart(read_text(rd)?),
+            TzxId::GroupEnd => TzxBlock::GroupEnd,
+            TzxId::Jump => TzxBlock::Jump(read_u16(rd)? as i16),
+            TzxId::LoopStart => TzxBlock::LoopStart(read_u16(rd)?),
+            TzxId::LoopEnd => TzxBlock::LoopEnd,
+            TzxId::CallSeq => {
+                let count = read_u16(rd)?;
+                let calls = (0..count).map(|_| read_u16(rd).map(|n| n as i16))
+                            .collect::<Result<_>>()?;
+                TzxBlock::CallSeq(calls)
+            }
+            TzxId::Return => TzxBlock::Return,
+            TzxId::Select => {
+                TzxBlock::Select(read_entries(rd, |rd| {
+                    let offset = read_u16(rd)? as i16;
+                    Ok((offset, read_text(rd)?))
+                })?)
+            }
+            TzxId::StopIn48k => {
+                if read_u32(rd)? != 0 {
+                    return invalid_data("TZX: invalid length of the stop the tape block")
+                }
+                TzxBlock::StopIn48k
+            }
+            TzxId::SetLevel => {
+                if read_u32(rd)? != 1 {
+                    return invalid_data("TZX: invalid length of the set signal level block")
+                }
+                TzxBlock::SetLevel(read_u8(rd)? != 0)
+            }
+            TzxId::Text => TzxBlock::Text(read_text(rd)?),
+            TzxId::Message => {
+                let time = read_u8(rd)?;
+                let message = read_text(rd)?;
+                TzxBlock::Message { time, message }
+            }
+            TzxId::Archive => {
+                TzxBlock::Archive(read_entries(rd, |rd| {
+                    let text_id = read_u8(rd)?;
+                    Ok((text_id, read_text(rd)?))
+                })?)
+            }
+            TzxId::Hardware => {
+                let count = read_u8(rd)?;
+                let entries = (0..count).map(|_| {
+                    Ok(HardwareInfo {
+                        hw_type: read_u8(rd)?,
+                        hw_id: read_u8(rd)?,
+                        info: read_u8(rd)?
+                    })
+                }).collect::<Result<_>>()?;
+                TzxBlock::Hardware(entries)
+            }
+            TzxId::Custom => {
+                let mut ident = [0u8;16];
+                rd.read_exact(&mut ident)?;
+                let len = read_u32(rd)?;
+                let data = read_data(rd, len)?;
+                TzxBlock::Custom { ident, data }
+            }
+            TzxId::Glue => {
+                let mut glue = [0u8;9];
+                rd.read_exact(&mut glue)?;
+                if glue[..7] != TZX_SIGNATURE[1..] {
+                    return invalid_data("TZX: invalid glue block")
+                }
+                TzxBlock::Glue { major: glue[7], minor: glue[8] }
+            }
+            TzxId::C64RomType|TzxId::C64TurboData => {
+                let len = read_u32(rd)?;
+                let mut data = len.to_le_bytes().to_vec();
+                data.extend_from_slice(&read_data(rd, len)?);
+                TzxBlock::Unsupported { id: id.into(), data: data.into_boxed_slice() }
+            }
+            TzxId::EmulationInfo => {
+                TzxBlock::Unsupported { id: id.into(), data: read_data(rd, 8)? }
+            }
+            TzxId::Snapshot => {
+                let snap_type = read_u8(rd)?;
+                let len = read_u24(rd)?;
+                let mut data = vec![snap_type];
+                data.extend_from_slice(&len.to_le_bytes()[0..3]);
+                data.extend_from_slice(&read_data(rd, len)?);
+                TzxBlock::Unsupported { id: id.into(), data: data.into_boxed_slice() }
+            }
+        };
+        Ok(Some(block))
+    }
+}
+
+impl<R: Read> TzxReadIter<R> {
+    /// Reads and validates the *TZX* header from the given reader.
+    ///
+    /// On success returns an iterator of blocks following the header.
+    pub fn try_new(mut rd: R) -> Result<Self> {
+        let mut header = [0u8;10];
+        rd.read_exact(&mut header)?;
+        if header[..8] != TZX_SIGNATURE[..] {
+            return invalid_data("TZX: invalid signature")
+        }
+        let (major, minor) = (header[8], header[9]);
+        if major != TZX_MAJOR_VERSION {
+            return invalid_data("TZX: unsupported major revision")
+        }
+        Ok(TzxReadIter { major, minor, done: false, rd })
+    }
+}
+
+impl<R> TzxReadIter<R> {
+    /// Returns the major and the minor revision numbers of the file.
+    pub fn version(&self) -> (u8, u8) {
+        (self.major, self.minor)
+    }
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.rd
+    }
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rd
+    }
+    /// Returns a mutable reference to the wrapped reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.rd
+    }
+}
+
+impl<R: Read> Iterator for TzxReadIter<R> {
+    type Item = Result<TzxBlock>;
+
+    /// Returns `None` after the end of the stream or after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        match TzxBlock::read_block(self.rd.by_ref()) {
+            Ok(Some(block)) => Some(Ok(block)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl TzxTape {
+    /// Reads the whole *TZX* file from the given reader.
+    pub fn read_from<R: Read>(rd: R) -> Result<Self> {
+        let mut iter = TzxReadIter::try_new(rd)?;
+        let blocks = iter.by_ref().collect::<Result<Vec<_>>>()?;
+        let (major, minor) = iter.version();
+        Ok(TzxTape { major, minor, blocks })
+    }
+}
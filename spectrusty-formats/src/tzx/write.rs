@@ -0,0 +1,779 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::convert::TryFrom;
+use core::num::NonZeroU32;
+use std::io::{ErrorKind, Error, Write, Seek, SeekFrom, Result};
+
+use crate::tap::pulse::consts::*;
+use super::*;
+
+/// The default number of T-states per sample of the direct recording blocks (~44.1 kHz).
+pub const DEFAULT_TSTATES_PER_SAMPLE: u16 = 79;
+
+/// Pulses longer than this are interpreted as pauses between blocks.
+const MAX_PULSE_LENGTH: u32 = u16::MAX as u32;
+/// The minimum number of similar pulses recognized as a PILOT tone.
+const MIN_PILOT_COUNT: usize = 16;
+/// The minimum number of bits recognized as a pure data block.
+const MIN_PURE_DATA_BITS: usize = 16;
+/// The maximum deviation in T-states from the ROM timings of standard speed blocks.
+const STANDARD_TOLERANCE: u32 = 50;
+/// The number of PILOT pulses of the standard speed blocks may differ by the initial edge.
+const STANDARD_PILOT_TOLERANCE: u16 = 2;
+/// The maximum size of the data in blocks with a 24-bit length.
+const MAX_DATA_SIZE: usize = 0xFF_FFFF;
+
+/// A tool for writing *TZX* files.
+///
+/// Data can be written in one of 2 ways:
+///
+/// * Writing [TzxBlock]s with [TzxWriter::write_block].
+/// * Writing *TAPE* pulse intervals, e.g. from [MicOut::mic_out_pulse_iter], with
+///   [TzxWriter::write_pulses_as_tzx_blocks].
+///
+/// The pulses are grouped into segments separated by pauses. Each segment is encoded as
+/// a standard speed data block if its timings match those of the ROM routines. Otherwise it's
+/// encoded as a turbo speed or a pure data block, with the measured timings. Pulses that do not
+/// encode any data are stored as direct recording blocks.
+///
+/// [MicOut::mic_out_pulse_iter]: spectrusty_core::chip::MicOut::mic_out_pulse_iter
+#[derive(Debug)]
+pub struct TzxWriter<W> {
+    /// The number of T-states per sample of the direct recording blocks.
+    pub tstates_per_sample: u16,
+    pulses: Vec<u32>,
+    /// `true` if the next pulse is the pause between segments
+    ended: bool,
+    /// the level of the signal at the beginning of the buffered pulses
+    level: bool,
+    /// the length of the last pulse of the previous segment that has not been encoded
+    tail: u32,
+    /// the distance from the end of the last written block back to its pause field
+    pause_back: Option<u64>,
+    written: bool,
+    wr: W
+}
+
+fn invalid_input<T>(msg: &'static str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidInput, msg))
+}
+
+fn write_u24<W: Write>(wr: &mut W, value: usize) -> Result<()> {
+    if value > MAX_DATA_SIZE {
+        return invalid_input("TZX: block data too large")
+    }
+    wr.write_all(&(value as u32).to_le_bytes()[0..3])
+}
+
+fn write_u32_len<W: Write>(wr: &mut W, len: usize) -> Result<()> {
+    let len = u32::try_from(len).or_else(|_| invalid_input("TZX: block data too large"))?;
+    wr.write_all(&len.to_le_bytes())
+}
+
+fn write_text<W: Write>(wr: &mut W, text: &[u8]) -> Result<()> {
+    let len = u8::try_from(text.len()).or_else(|_| invalid_input("TZX: text too long"))?;
+    wr.write_all(&[len])?;
+    wr.write_all(text)
+}
+
+fn write_symbols<W: Write>(wr: &mut W, symbols: &[TzxSymbol], max_pulses: usize) -> Result<()> {
+    for symbol in symbols.iter() {
+        wr.write_all(&[symbol.polarity as u8])?;
+        for n in 0..max_pulses {
+            let pulse = symbol.pulses.get(n).copied().unwrap_or_default();
+            wr.write_all(&pulse.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn symbols_header(symbols: &[TzxSymbol]) -> Result<(usize, [u8;2])> {
+    let max_pulses = symbols.iter().map(|sym| sym.pulses.len()).max().unwrap_or_default();
+    let npp = u8::try_from(max_pulses).or_else(|_| invalid_input("TZX: too many pulses in a symbol"))?;
+    if symbols.len() > 256 {
+        return invalid_input("TZX: too many symbols")
+    }
+    Ok((max_pulses, [npp, symbols.len() as u8]))
+}
+
+fn write_generalized<W: Write>(wr: &mut W, gen: &GeneralizedData) -> Result<()> {
+    let pilot_count = u32::try_from(gen.pilot_stream.len())
+                      .or_else(|_| invalid_input("TZX: too many pilot symbols"))?;
+    let (pilot_pulses, pilot_head) = symbols_header(&gen.pilot_symbols)?;
+    let (data_pulses, data_head) = symbols_header(&gen.data_symbols)?;
+    let bits = u64::from(gen.data_count) * u64::from(gen.symbol_bits());
+    let data_len = (bits / 8 + u64::from(bits % 8 != 0)) as usize;
+    if gen.data_count != 0 && gen.data.len() < data_len {
+        return invalid_input("TZX: not enough data in the generalized data stream")
+    }
+    let mut body = Vec::new();
+    body.extend_from_slice(&gen.pause.to_le_bytes());
+    body.extend_from_slice(&pilot_count.to_le_bytes());
+    body.extend_from_slice(&pilot_head);
+    body.extend_from_slice(&gen.data_count.to_le_bytes());
+    body.extend_from_slice(&data_head);
+    if pilot_count != 0 {
+        write_symbols(&mut body, &gen.pilot_symbols, pilot_pulses)?;
+        for (symbol, repeat) in gen.pilot_stream.iter() {
+            body.push(*symbol);
+            body.extend_from_slice(&repeat.to_le_bytes());
+        }
+    }
+    if gen.data_count != 0 {
+        write_symbols(&mut body, &gen.data_symbols, data_pulses)?;
+        body.extend_from_slice(&gen.data[..data_len]);
+    }
+    write_u32_len(wr, body.len())?;
+    wr.write_all(&body)
+}
+
+fn write_entries<W: Write, T, F>(wr: &mut W, entries: &[T], mut entry: F) -> Result<()>
+    where F: FnMut(&mut Vec<u8>, &T) -> Result<()>
+{
+    let count = u8::try_from(entries.len()).or_else(|_| invalid_input("TZX: too many entries"))?;
+    let mut body = vec![count];
+    for item in entries.iter() {
+        entry(&mut body, item)?;
+    }
+    let len = u16::try_from(body.len()).or_else(|_| invalid_input("TZX: block data too large"))?;
+    wr.write_all(&len.to_le_bytes())?;
+    wr.write_all(&body)
+}
+
+impl TzxBlock {
+    /// Writes this block, including its ID, to the given writer.
+    pub fn write_block<W: Write>(&self, mut wr: W) -> Result<()> {
+        let wr = &mut wr;
+        wr.write_all(&[self.id()])?;
+        match self {
+            TzxBlock::StandardSpeed { pause, data } => {
+                let len = u16::try_from(data.len())
+                          .or_else(|_| invalid_input("TZX: standard speed block too large"))?;
+                wr.write_all(&pause.to_le_bytes())?;
+                wr.write_all(&len.to_le_bytes())?;
+                wr.write_all(data)
+            }
+            TzxBlock::TurboSpeed { timings, used_bits, pause, data } => {
+                for word in [timings.pilot, timings.sync1, timings.sync2,
+                             timings.zero, timings.one, timings.pilot_count].iter() {
+                    wr.write_all(&word.to_le_bytes())?;
+                }
+                wr.write_all(&[*used_bits])?;
+                wr.write_all(&pause.to_le_bytes())?;
+                write_u24(wr, data.len())?;
+                wr.write_all(data)
+            }
+            TzxBlock::PureTone { pulse, count } => {
+                wr.write_all(&pulse.to_le_bytes())?;
+                wr.write_all(&count.to_le_bytes())
+            }
+            TzxBlock::SeqOfPulses(pulses) => {
+                let count = u8::try_from(pulses.len())
+                            .or_else(|_| invalid_input("TZX: too many pulses in a sequence"))?;
+                wr.write_all(&[count])?;
+                for pulse in pulses.iter() {
+                    wr.write_all(&pulse.to_le_bytes())?;
+                }
+                Ok(())
+            }
+            TzxBlock::PureData { zero, one, used_bits, pause, data } => {
+                wr.write_all(&zero.to_le_bytes())?;
+                wr.write_all(&one.to_le_bytes())?;
+                wr.write_all(&[*used_bits])?;
+                wr.write_all(&pause.to_le_bytes())?;
+                write_u24(wr, data.len())?;
+                wr.write_all(data)
+            }
+            TzxBlock::DirectRec { tstates_per_sample, pause, used_bits, data } => {
+                wr.write_all(&tstates_per_sample.to_le_bytes())?;
+                wr.write_all(&pause.to_le_bytes())?;
+                wr.write_all(&[*used_bits])?;
+                write_u24(wr, data.len())?;
+                wr.write_all(data)
+            }
+            TzxBlock::CswRecording { pause, sample_rate, compression, pulses, data } => {
+                write_u32_len(wr, data.len() + 10)?;
+                wr.write_all(&pause.to_le_bytes())?;
+                write_u24(wr, *sample_rate as usize)?;
+                wr.write_all(&[*compression as u8])?;
+                wr.write_all(&pulses.to_le_bytes())?;
+                wr.write_all(data)
+            }
+            TzxBlock::Generalized(gen) => write_generalized(wr, gen),
+            TzxBlock::Pause(ms) => wr.write_all(&ms.to_le_bytes()),
+            TzxBlock::GroupStart(name) => write_text(wr, name),
+            TzxBlock::Jump(offset) => wr.write_all(&offset.to_le_bytes()),
+            TzxBlock::LoopStart(count) => wr.write_all(&count.to_le_bytes()),
+            TzxBlock::CallSeq(calls) => {
+                let count = u16::try_from(calls.len())
+                            .or_else(|_| invalid_input("TZX: too many calls"))?;
+                wr.write_all(&count.to_le_bytes())?;
+                for offset in calls.iter() {
+                    wr.write_all(&offset.to_le_bytes())?;
+                }
+                Ok(())
+            }
+            TzxBlock::Select(entries) => {
+                write_entries(wr, entries, |body, (offset, text)| {
+                    body.extend_from_slice(&offset.to_le_bytes());
+                    write_text(body, text)
+                })
+            }
+            TzxBlock::StopIn48k => wr.write_all(&0u32.to_le_bytes()),
+            TzxBlock::SetLevel(level) => {
+                wr.write_all(&1u32.to_le_bytes())?;
+                wr.write_all(&[*level as u8])
+            }
+            TzxBlock::Text(text) => write_text(wr, text),
+            TzxBlock::Message { time, message } => {
+                wr.write_all(&[*time])?;
+                write_text(wr, message)
+            }
+            TzxBlock::Archive(entries) => {
+                write_entries(wr, entries, |body, (text_id, text)| {
+                    body.push(*text_id);
+                    write_text(body, text)
+                })
+            }
+            TzxBlock::Hardware(entries) => {
+                let count = u8::try_from(entries.len())
+                            .or_else(|_| invalid_input("TZX: too many entries"))?;
+                wr.write_all(&[count])?;
+                for info in entries.iter() {
+                    wr.write_all(&[info.hw_type, info.hw_id, info.info])?;
+                }
+                Ok(())
+            }
+            TzxBlock::Custom { ident, data } => {
+                wr.write_all(ident)?;
+                write_u32_len(wr, data.len())?;
+                wr.write_all(data)
+            }
+            TzxBlock::Glue { major, minor } => {
+                wr.write_all(&TZX_SIGNATURE[1..])?;
+                wr.write_all(&[*major, *minor])
+            }
+            TzxBlock::Unsupported { data, .. } => wr.write_all(data),
+            TzxBlock::GroupEnd|TzxBlock::LoopEnd|TzxBlock::Return => Ok(())
+        }
+    }
+    /// Returns the offset of the pause field from the beginning of the block, including its ID.
+    fn pause_offset(&self) -> Option<u64> {
+        match self {
+            TzxBlock::StandardSpeed {..} => Some(1),
+            TzxBlock::TurboSpeed {..} => Some(14),
+            TzxBlock::PureData {..} => Some(6),
+            TzxBlock::DirectRec {..} => Some(3),
+            _ => None
+        }
+    }
+}
+
+impl TzxTape {
+    /// Writes the *TZX* header followed by all the blocks to the given writer.
+    pub fn write_to<W: Write>(&self, mut wr: W) -> Result<()> {
+        wr.write_all(TZX_SIGNATURE)?;
+        wr.write_all(&[self.major, self.minor])?;
+        for block in self.blocks.iter() {
+            block.write_block(wr.by_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> TzxWriter<W> {
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+    /// Returns a mutable reference to the inner writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.wr
+    }
+    /// Returns a shared reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.wr
+    }
+}
+
+impl<W> TzxWriter<W>
+    where W: Write + Seek
+{
+    /// Writes the *TZX* header to the given writer and on success returns a new instance
+    /// of `TzxWriter`.
+    pub fn try_new(mut wr: W) -> Result<Self> {
+        wr.write_all(TZX_SIGNATURE)?;
+        wr.write_all(&[TZX_MAJOR_VERSION, TZX_MINOR_VERSION])?;
+        Ok(TzxWriter {
+            tstates_per_sample: DEFAULT_TSTATES_PER_SAMPLE,
+            pulses: Vec::new(),
+            ended: true,
+            level: false,
+            tail: 0,
+            pause_back: None,
+            written: false,
+            wr
+        })
+    }
+    /// Flushes the underlying writer, ensuring that all intermediately buffered
+    /// contents reach their destination (invokes [Write::flush]).
+    pub fn flush(&mut self) -> Result<()> {
+        self.wr.flush()
+    }
+    /// Writes the given block.
+    ///
+    /// Encodes the buffered pulses with [TzxWriter::end_pulse_block] before proceeding with writing the block.
+    ///
+    /// Returns the number of *TZX* blocks written.
+    pub fn write_block(&mut self, block: &TzxBlock) -> Result<usize> {
+        let nblocks = self.end_pulse_block()?;
+        self.put_block(block)?;
+        Ok(nblocks + 1)
+    }
+    /// Forces the buffered pulses to be encoded and written as *TZX* blocks.
+    ///
+    /// The next pulse will be interpreted as the pause after the last written block.
+    ///
+    /// Returns the number of *TZX* blocks written.
+    pub fn end_pulse_block(&mut self) -> Result<usize> {
+        self.ended = true;
+        if self.pulses.is_empty() {
+            return Ok(0)
+        }
+        let (blocks, tail) = encode_pulses(&self.pulses, self.level, self.tstates_per_sample);
+        if self.pulses.len() & 1 == 1 {
+            self.level = !self.level;
+        }
+        self.pulses.clear();
+        for block in blocks.iter() {
+            self.put_block(block)?;
+        }
+        self.tail = tail;
+        Ok(blocks.len())
+    }
+    /// Interprets pulse intervals from the provided iterator as *TZX* blocks and writes them
+    /// to the underlying writer.
+    ///
+    /// The pulse iterator is expected to provide only a fragment of pulses, such as an iterator
+    /// returned from [MicOut::mic_out_pulse_iter]. Providing an empty iterator is equivalent
+    /// to calling [TzxWriter::end_pulse_block].
+    ///
+    /// Returns the number of *TZX* blocks written.
+    ///
+    /// [MicOut::mic_out_pulse_iter]: spectrusty_core::chip::MicOut::mic_out_pulse_iter
+    pub fn write_pulses_as_tzx_blocks<I>(&mut self, iter: I) -> Result<usize>
+        where I: Iterator<Item=NonZeroU32>
+    {
+        let mut iter = iter.peekable();
+        if iter.peek().is_none() {
+            return self.end_pulse_block()
+        }
+        let mut nblocks = 0;
+        for pulse in iter {
+            let pulse = pulse.get();
+            if pulse > MAX_PULSE_LENGTH || self.ended {
+                nblocks += self.end_pulse_block()?;
+                nblocks += self.write_pause(pulse)?;
+                self.level = !self.level;
+                self.ended = false;
+            }
+            else {
+                self.pulses.push(pulse);
+            }
+        }
+        Ok(nblocks)
+    }
+
+    fn put_block(&mut self, block: &TzxBlock) -> Result<()> {
+        let mut data = Vec::new();
+        block.write_block(&mut data)?;
+        self.wr.write_all(&data)?;
+        self.pause_back = block.pause_offset().map(|offset| data.len() as u64 - offset);
+        self.written = true;
+        self.tail = 0;
+        Ok(())
+    }
+    /// Sets the pause after the last written block. Returns the number of blocks written.
+    fn write_pause(&mut self, pulse: u32) -> Result<usize> {
+        let tstates = pulse.saturating_add(core::mem::take(&mut self.tail));
+        let ms = u16::try_from(tstates / 3_500).unwrap_or(u16::MAX);
+        if ms == 0 || !self.written {
+            return Ok(0)
+        }
+        match self.pause_back.take() {
+            Some(back) => {
+                // nothing has been written after the last block
+                let back = back as i64;
+                self.wr.seek(SeekFrom::Current(-back))?;
+                self.wr.write_all(&ms.to_le_bytes())?;
+                self.wr.seek(SeekFrom::Current(back - 2))?;
+                Ok(0)
+            }
+            None => {
+                self.put_block(&TzxBlock::Pause(ms))?;
+                self.pause_back = None;
+                Ok(1)
+            }
+        }
+    }
+}
+
+fn is_similar(pulse: u32, to: u32) -> bool {
+    let tolerance = to / 8 + 8;
+    pulse.saturating_add(tolerance) >= to && pulse <= to.saturating_add(tolerance)
+}
+
+fn is_standard(pulse: u32, standard: NonZeroU32) -> bool {
+    let standard = standard.get();
+    pulse.saturating_add(STANDARD_TOLERANCE) >= standard && pulse <= standard + STANDARD_TOLERANCE
+}
+
+fn mean(sum: u64, count: u64) -> u16 {
+    u16::try_from((sum + count / 2) / count.max(1)).unwrap_or(u16::MAX)
+}
+
+/// Returns the number of pulses of the PILOT tone at the beginning of `pulses`.
+fn pilot_len(pulses: &[u32]) -> usize {
+    match pulses.first() {
+        Some(&first) => pulses.iter().take_while(|&&p| is_similar(p, first)).count(),
+        None => 0
+    }
+}
+
+/// Decoded data bits with the measured timings.
+struct DataBits {
+    zero: u16,
+    one: u16,
+    bits: usize,
+    data: Vec<u8>
+}
+
+impl DataBits {
+    fn used_bits(&self) -> u8 {
+        match self.bits & 7 {
+            0 => 8,
+            n => n as u8
+        }
+    }
+}
+
+/// Decodes data bits from pairs of pulses of two different lengths.
+///
+/// If `skip_first` is `true` the first pulse of the first pair is assumed to be missing.
+fn decode_bits(pulses: &[u32], skip_first: bool) -> DataBits {
+    let mut centers: [Option<u32>;2] = [None, None];
+    let mut sums = [0u64;2];
+    let mut counts = [0u64;2];
+    let mut bits = Vec::new();
+    let mut index = 0;
+    loop {
+        let pair = if skip_first && index == 0 {
+            pulses.first().map(|&p| (p, p, 1u32))
+        }
+        else {
+            pulses.get(index..index + 2).map(|pair| (pair[0], pair[1], 2u32))
+        };
+        let (a, b, len) = match pair {
+            Some(pair) => pair,
+            None => break
+        };
+        if a > MAX_PULSE_LENGTH || b > MAX_PULSE_LENGTH || !is_similar(a, b) || !is_similar(b, a) {
+            break
+        }
+        let value = (a + b) / 2;
+        let cluster = match centers {
+            [Some(c0), _] if is_similar(value, c0) => 0,
+            [_, Some(c1)] if is_similar(value, c1) => 1,
+            [Some(_), None] => {
+                centers[1] = Some(value);
+                1
+            }
+            [None, _] => {
+                centers[0] = Some(value);
+                0
+            }
+            _ => break
+        };
+        sums[cluster] += u64::from(if len == 1 { a } else { a + b });
+        counts[cluster] += u64::from(len);
+        bits.push(cluster);
+        index += len as usize;
+    }
+    let (zero, one, one_cluster) = match centers {
+        [Some(c0), Some(c1)] if c0 > c1 => (mean(sums[1], counts[1]), mean(sums[0], counts[0]), 0),
+        [Some(_), Some(_)] => (mean(sums[0], counts[0]), mean(sums[1], counts[1]), 1),
+        // pulses of a single length: decide by the ROM timings
+        [Some(c0), None] if is_standard(c0, ONE_PULSE_LENGTH) => {
+            let one = mean(sums[0], counts[0]);
+            ((one / 2).max(1), one, 0)
+        }
+        [Some(_), None] => {
+            let zero = mean(sums[0], counts[0]);
+            (zero, zero.saturating_mul(2), 1)
+        }
+        _ => (0, 0, 1)
+    };
+    let mut data = vec![0u8; bits.len() / 8 + usize::from(bits.len() % 8 != 0)];
+    for (n, &cluster) in bits.iter().enumerate() {
+        if cluster == one_cluster {
+            data[n >> 3] |= 0x80 >> (n & 7);
+        }
+    }
+    DataBits { zero, one, bits: bits.len(), data }
+}
+
+/// Tries to recognize a data block at the beginning of `pulses`.
+///
+/// Returns the block and the number of pulses it consumes.
+fn encode_data_block(pulses: &[u32], pilot: usize, at_start: bool) -> Option<(TzxBlock, usize)> {
+    if pilot >= MIN_PILOT_COUNT {
+        let sync = pulses.get(pilot..pilot + 2)?;
+        let (sync1, sync2) = (sync[0], sync[1]);
+        if sync1 > MAX_PULSE_LENGTH || sync2 > MAX_PULSE_LENGTH {
+            return None
+        }
+        let data = decode_bits(&pulses[pilot + 2..], false);
+        if data.bits < 8 {
+            return None
+        }
+        let pilot_sum: u64 = pulses[..pilot].iter().map(|&p| u64::from(p)).sum();
+        let pilot_mean = mean(pilot_sum, pilot as u64);
+        // the first pulse of the segment is merged with the pause before it
+        let pilot_count = u16::try_from(pilot + at_start as usize).ok()?;
+        let used = pilot + 2 + data.bits * 2;
+        let standard = TurboTimings::standard(data.data[0]);
+        if data.bits & 7 == 0 &&
+           is_standard(pilot_mean.into(), LEAD_PULSE_LENGTH) &&
+           is_standard(sync1, SYNC_PULSE1_LENGTH) &&
+           is_standard(sync2, SYNC_PULSE2_LENGTH) &&
+           is_standard(data.zero.into(), ZERO_PULSE_LENGTH) &&
+           is_standard(data.one.into(), ONE_PULSE_LENGTH) &&
+           pilot_count.saturating_add(STANDARD_PILOT_TOLERANCE) >= standard.pilot_count &&
+           pilot_count <= standard.pilot_count + STANDARD_PILOT_TOLERANCE &&
+           data.data.len() <= u16::MAX as usize
+        {
+            let block = TzxBlock::StandardSpeed { pause: 0, data: data.data.into() };
+            return Some((block, used))
+        }
+        if data.data.len() > MAX_DATA_SIZE {
+            return None
+        }
+        let timings = TurboTimings {
+            pilot: pilot_mean,
+            sync1: sync1 as u16,
+            sync2: sync2 as u16,
+            zero: data.zero,
+            one: data.one,
+            pilot_count
+        };
+        let used_bits = data.used_bits();
+        let block = TzxBlock::TurboSpeed { timings, used_bits, pause: 0, data: data.data.into() };
+        return Some((block, used))
+    }
+    let mut data = decode_bits(pulses, false);
+    let mut used = data.bits * 2;
+    if at_start {
+        // the first pulse of the segment is merged with the pause before it
+        let skipped = decode_bits(pulses, true);
+        if skipped.bits > data.bits {
+            used = skipped.bits * 2 - 1;
+            data = skipped;
+        }
+    }
+    if data.bits < MIN_PURE_DATA_BITS || data.data.len() > MAX_DATA_SIZE {
+        return None
+    }
+    let used_bits = data.used_bits();
+    let block = TzxBlock::PureData { zero: data.zero, one: data.one, used_bits, pause: 0, data: data.data.into() };
+    Some((block, used))
+}
+
+/// Encodes pulses as a direct recording block beginning with the given `level`.
+fn encode_direct_rec(pulses: &[u32], mut level: bool, tstates_per_sample: u16) -> TzxBlock {
+    let tps = u64::from(tstates_per_sample.max(1));
+    let mut data = Vec::new();
+    let mut samples: usize = 0;
+    let mut tstates: u64 = 0;
+    for &pulse in pulses.iter() {
+        tstates += u64::from(pulse);
+        // the samples are rounded to the nearest sample without accumulating an error
+        let end = ((tstates + tps / 2) / tps) as usize;
+        while samples < end {
+            if samples & 7 == 0 {
+                data.push(0);
+            }
+            if level {
+                *data.last_mut().unwrap() |= 0x80 >> (samples & 7);
+            }
+            samples += 1;
+        }
+        level = !level;
+    }
+    let used_bits = match samples & 7 {
+        0 => 8,
+        n => n as u8
+    };
+    TzxBlock::DirectRec { tstates_per_sample, pause: 0, used_bits, data: data.into() }
+}
+
+/// Encodes the segment of pulses as *TZX* blocks.
+///
+/// Returns the blocks and the length of a single trailing pulse following the last data block,
+/// that will be included in the pause after it.
+fn encode_pulses(pulses: &[u32], mut level: bool, tstates_per_sample: u16) -> (Vec<TzxBlock>, u32) {
+    let mut blocks = Vec::new();
+    let mut index = 0;
+    let mut raw_start = 0;
+    while index < pulses.len() {
+        let pilot = pilot_len(&pulses[index..]);
+        match encode_data_block(&pulses[index..], pilot, index == 0) {
+            Some((block, used)) => {
+                if raw_start < index {
+                    let raw = &pulses[raw_start..index];
+                    blocks.push(encode_direct_rec(raw, level, tstates_per_sample));
+                    if raw.len() & 1 == 1 {
+                        level = !level;
+                    }
+                }
+                blocks.push(block);
+                if used & 1 == 1 {
+                    level = !level;
+                }
+                index += used;
+                raw_start = index;
+                if pulses.len() == index + 1 {
+                    return (blocks, pulses[index])
+                }
+            }
+            // a tone without any data is skipped as a whole
+            None if pilot >= MIN_PILOT_COUNT => index += pilot,
+            None => index += 1
+        }
+    }
+    if raw_start < pulses.len() {
+        blocks.push(encode_direct_rec(&pulses[raw_start..], level, tstates_per_sample));
+    }
+    (blocks, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use super::*;
+    use super::super::tests::tap_to_tzx;
+
+    #[test]
+    fn write_tzx_blocks_works() -> Result<()> {
+        let tzx = [
+            b'Z', b'X', b'T', b'a', b'p', b'e', b'!', 0x1A, 1, 20,
+            0x11, 0x78, 0x08, 0x9b, 0x02, 0xdf, 0x02, 0x57, 0x03, 0xae, 0x06, 0x7f, 0x0c, 6, 0xe8, 0x03, 2, 0, 0, 0xff, 0xfc,
+            0x12, 0x78, 0x08, 0x10, 0x00,
+            0x13, 2, 0x9b, 0x02, 0xdf, 0x02,
+            0x14, 0x57, 0x03, 0xae, 0x06, 8, 0, 0, 1, 0, 0, 0x5a,
+            0x15, 0x4f, 0x00, 0, 0, 3, 1, 0, 0, 0xe0,
+            0x18, 12, 0, 0, 0, 0, 0, 0x44, 0xac, 0, 1, 2, 0, 0, 0, 10, 20,
+            0x19, 31, 0, 0, 0, 0xe8, 0x03, 1, 0, 0, 0, 1, 1, 2, 0, 0, 0, 2, 2,
+                  0, 0x78, 0x08,
+                  0, 0x10, 0x00,
+                  0, 0x57, 0x03, 0x57, 0x03, 0, 0xae, 0x06, 0xae, 0x06,
+                  0b1010_0000,
+            0x20, 0xe8, 0x03,
+            0x21, 1, b'G',
+            0x22,
+            0x23, 0xfe, 0xff,
+            0x24, 2, 0,
+            0x25,
+            0x26, 2, 0, 1, 0, 0xff, 0xff,
+            0x27,
+            0x28, 7, 0, 1, 2, 0, 3, b'O', b'n', b'e',
+            0x2A, 0, 0, 0, 0,
+            0x2B, 1, 0, 0, 0, 0,
+            0x30, 1, b'T',
+            0x31, 5, 1, b'M',
+            0x32, 4, 0, 1, 0, 1, b'A',
+            0x33, 2, 0, 0, 0, 0, 1, 3,
+            0x35, b'C', b'u', b's', b't', b'o', b'm', b' ', b' ', b' ', b' ', b' ', b' ', b' ', b' ', b' ', b' ',
+                  2, 0, 0, 0, 1, 2,
+            0x5A, b'X', b'T', b'a', b'p', b'e', b'!', 0x1A, 1, 13,
+            0x34, 1, 2, 3, 4, 5, 6, 7, 8,
+            0x40, 0, 2, 0, 0, 1, 2,
+            0x7f, 1, 0, 0, 0, 9
+        ];
+        let tape = read_tzx(&tzx[..])?;
+        assert_eq!(27, tape.blocks.len());
+        let mut tgt = Vec::new();
+        tape.write_to(&mut tgt)?;
+        assert_eq!(&tzx[..], &tgt[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_standard_pulses_works() -> Result<()> {
+        let mut tap = Vec::new();
+        File::open("../resources/read_tap_test.tap")?.read_to_end(&mut tap)?;
+        let src = read_tzx(Cursor::new(tap_to_tzx(&tap)))?;
+        let mut pulse_iter = TzxPulseIter::new(&src.blocks);
+        let mut writer = TzxWriter::try_new(Cursor::new(Vec::new()))?;
+        // feed pulses in frame sized fragments
+        let mut nblocks = 0;
+        loop {
+            let frame: Vec<_> = pulse_iter.by_ref().take(100).collect();
+            nblocks += writer.write_pulses_as_tzx_blocks(frame.iter().copied())?;
+            if frame.is_empty() {
+                break
+            }
+        }
+        assert_eq!(6, nblocks);
+        assert_eq!(0, writer.end_pulse_block()?);
+        let tgt = read_tzx(Cursor::new(writer.into_inner().into_inner()))?;
+        let mut blocks = src.blocks.clone();
+        // the pause after the last block is unknown
+        if let Some(TzxBlock::StandardSpeed { pause, .. }) = blocks.last_mut() {
+            *pause = 0;
+        }
+        assert_eq!(blocks, tgt.blocks);
+        Ok(())
+    }
+
+    #[test]
+    fn write_custom_pulses_works() -> Result<()> {
+        let timings = TurboTimings { pilot: 1000, sync1: 300, sync2: 400, zero: 500, one: 1000, pilot_count: 100 };
+        let src = vec![
+            TzxBlock::TurboSpeed { timings, used_bits: 5, pause: 100, data: Box::new([0x12, 0x34, 0x50]) },
+            TzxBlock::PureData { zero: 300, one: 600, used_bits: 8, pause: 200, data: Box::new([0xA5, 0x00, 0xFF]) },
+            TzxBlock::SeqOfPulses(vec![1000, 2000, 1500, 1700].into()),
+            TzxBlock::Pause(300),
+            // a standard speed header with a custom number of PILOT pulses
+            TzxBlock::TurboSpeed { timings: TurboTimings { pilot_count: 1000, ..TurboTimings::standard(0) },
+                                   used_bits: 8, pause: 40, data: Box::new([0x00, 0x55]) },
+        ];
+        let mut writer = TzxWriter::try_new(Cursor::new(Vec::new()))?;
+        assert_eq!(3, writer.write_pulses_as_tzx_blocks(TzxPulseIter::new(&src))?);
+        assert_eq!(1, writer.end_pulse_block()?);
+        let tgt = read_tzx(Cursor::new(writer.into_inner().into_inner()))?;
+        assert_eq!(4, tgt.blocks.len());
+        assert_eq!(src[0], tgt.blocks[0]);
+        assert_eq!(src[1], tgt.blocks[1]);
+        match &tgt.blocks[2] {
+            TzxBlock::DirectRec { tstates_per_sample, pause, used_bits, data } => {
+                assert_eq!(DEFAULT_TSTATES_PER_SAMPLE, *tstates_per_sample);
+                assert_eq!(300, *pause);
+                // the first pulse is merged with the pause before it
+                assert_eq!((2000 + 1500 + 1700 + 79 / 2) / 79, (data.len() - 1) * 8 + *used_bits as usize);
+            }
+            block => panic!("unexpected block: {:?}", block)
+        }
+        let mut last = src[4].clone();
+        if let TzxBlock::TurboSpeed { pause, .. } = &mut last {
+            *pause = 0;
+        }
+        assert_eq!(last, tgt.blocks[3]);
+        Ok(())
+    }
+}
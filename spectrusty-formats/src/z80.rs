@@ -15,9 +15,11 @@
 //!
 //! * "Custom" Joystick is always interpreted as Sinclair Left Joystick, regardless of key bindings
 //!   that are being ignored at the moment.
-//! * Handling of MGT +D, DISCiPLE, or Multiface is currently not implemented.
+//! * Handling of Multiface is currently not implemented.
 //! * An `.xzx` extension to version 3 (additional OUT to port 0x1ffd) is being read-only if
 //!   a selected spectrum model would handle it properly.
+//! * From the **SLT** extension only the level data is being read. The levels can be served to the
+//!   running program with [SltLevels].
 //!
 //! When writing to the **Z80** file:
 //!
@@ -27,6 +29,8 @@ mod compress;
 mod decompress;
 mod loader;
 mod saver;
+mod slt;
 
 pub use loader::*;
 pub use saver::*;
+pub use slt::*;
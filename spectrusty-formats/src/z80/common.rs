@@ -122,10 +122,25 @@ pub struct MemoryHeader {
 
 pub const MEMORY_V1_TERM: &[u8] = &[0, 0xED, 0xED, 0];
 
+/// The signature following the empty memory block header which marks the **SLT** extension.
+pub const SLT_SIGNATURE: &[u8;3] = b"SLT";
+/// The **SLT** table entry type of the level data.
+pub const SLT_LEVEL_DATA: u16 = 1;
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+#[repr(packed)]
+pub struct SltEntry {
+    pub data_type: [u8;2],
+    pub id: [u8;2],
+    pub length: [u8;4]
+}
+
 // Structs must be packed and consist of `u8` or/and arrays of `u8` primitives only.
 unsafe impl StructRead for Header {}
 unsafe impl StructRead for HeaderEx {}
 unsafe impl StructRead for MemoryHeader {}
+unsafe impl StructRead for SltEntry {}
 unsafe impl StructWrite for Header {}
 unsafe impl StructWrite for HeaderEx {}
 unsafe impl StructWrite for MemoryHeader {}
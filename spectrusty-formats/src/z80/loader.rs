@@ -41,7 +41,7 @@ fn select_hw_model(version: Z80Version, head_ex: &HeaderEx) -> Option<(ComputerM
         (6, V3) if mgt_type <= 1 => (Spectrum128, Extensions::DISCIPLE),
         (7, _)|(8, _) if flags3.is_alt_hw_mode() => (SpectrumPlus2A, Extensions::empty()),
         (7, _)|(8, _) => (SpectrumPlus3, Extensions::empty()),
-        // (9, _)   => (Pentagon128, Extensions::empty()),
+        (9, V3)  => (Pentagon128, Extensions::empty()),
         // (10, _)  => (Scorpion256, Extensions::empty()),
         // (11, _)  => (DidaktikKompakt, Extensions::empty()),
         (12, _)  => (SpectrumPlus2, Extensions::empty()),
@@ -58,7 +58,7 @@ fn select_ay_model(model: ComputerModel, flags3: Flags3) -> Option<Ay3_891xDevic
     match model {
         Spectrum128|SpectrumPlus2|
         SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
-        SpectrumSE => {
+        SpectrumSE|Pentagon128 => {
             Some(Ay3_891xDevice::Ay128k)
         }
         TimexTC2068|TimexTS2068 => Some(Ay3_891xDevice::Timex),
@@ -91,7 +91,7 @@ fn mem_page_to_range(page: u8, model: ComputerModel, ext: Extensions) -> Option<
         }
         Spectrum128|SpectrumPlus2|
         SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
-        SpectrumSE => {
+        SpectrumSE|Pentagon128 => {
             Some(match page {
                 0 => MemoryRange::Rom(PAGE_SIZE..2*PAGE_SIZE),
                 1 if ext.intersects(Extensions::IF1) => MemoryRange::Interface1Rom,
@@ -140,8 +140,42 @@ fn create_cpu(head: &Header) -> Result<Z80NMOS> {
     Ok(cpu)
 }
 
+fn load_slt<R: Read, S: SnapshotLoader>(mut rd: R, loader: &mut S) -> Result<()> {
+    let mut signature = [0u8;3];
+    rd.read_exact(&mut signature)?;
+    if &signature != SLT_SIGNATURE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid SLT signature"))
+    }
+    let mut entries = Vec::new();
+    loop {
+        let entry = SltEntry::read_new_struct(rd.by_ref())?;
+        if u16::from_le_bytes(entry.data_type) == 0 {
+            break
+        }
+        entries.push(entry);
+    }
+    let mut buf = Vec::new();
+    for entry in entries {
+        let length = u32::from_le_bytes(entry.length);
+        buf.clear();
+        rd.by_ref().take(length.into()).read_to_end(&mut buf)?;
+        if buf.len() != length as usize {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "SLT data too short"))
+        }
+        if u16::from_le_bytes(entry.data_type) == SLT_LEVEL_DATA {
+            let mut data = Vec::new();
+            MemDecompress::new(&buf).read_to_end(&mut data)?;
+            loader.setup_slt_level(u16::from_le_bytes(entry.id), data.into_boxed_slice());
+        }
+    }
+    Ok(())
+}
+
 /// Loads a **Z80** file from `rd` into the provided snapshot `loader` implementing [SnapshotLoader].
 ///
+/// If the file contains the **SLT** extension, the level data blocks are passed to
+/// [SnapshotLoader::setup_slt_level]. Other **SLT** data blocks are being ignored.
+///
 /// # Errors
 /// This function will return an error if the file size is incorrect or there is something wrong
 /// with the format.
@@ -210,6 +244,10 @@ pub fn load_z80<R: Read, S: SnapshotLoader>(
     }
     else {
         while let Some((len, page, is_compressed)) = load_mem_header(rd.by_ref())? {
+            if len == 0 && page == 0 {
+                load_slt(rd.by_ref(), loader)?;
+                break
+            }
             let range = mem_page_to_range(page, model, extensions).ok_or_else(||
                 io::Error::new(io::ErrorKind::InvalidData, "unsupported memory page")
             )?;
@@ -251,7 +289,7 @@ pub fn load_z80<R: Read, S: SnapshotLoader>(
             }
             Spectrum128|SpectrumPlus2|
             SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
-            SpectrumSE => {
+            SpectrumSE|Pentagon128 => {
                 loader.write_port(0x7ffd, head_ex.port1);
             }
             _ => {}
@@ -269,6 +307,14 @@ pub fn load_z80<R: Read, S: SnapshotLoader>(
             }
             _ => {}
         }
+        if head_ex.mgt_rom == 0xff {
+            if extensions.intersects(Extensions::PLUS_D) {
+                loader.plus_d_rom_paged_in();
+            }
+            else if extensions.intersects(Extensions::DISCIPLE) {
+                loader.disciple_rom_paged_in();
+            }
+        }
     }
     Ok(())
 }
@@ -118,6 +118,10 @@ fn select_hw_model_v2<S: SnapshotCreator>(
             return Err(io::Error::new(io::ErrorKind::InvalidInput,
                 "Z80: can't create a snapshot of SpectrumSE"))
         }
+        Pentagon128|Pentagon512|Pentagon1024 => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "Z80: can't create a version 2 snapshot of Pentagon"))
+        }
         TimexTC2048|TimexTC2068|TimexTS2068 if ext.intersects(Extensions::IF1)
                              && snapshot.is_interface1_rom_paged_in() => {
             return Err(io::Error::new(io::ErrorKind::InvalidInput,
@@ -178,6 +182,15 @@ fn select_hw_model_v3<S: SnapshotCreator>(
             return Err(io::Error::new(io::ErrorKind::InvalidInput,
                 "Z80: can't create a snapshot of SpectrumSE"))
         }
+        Pentagon128 if ext.intersects(Extensions::IF1|Extensions::PLUS_D|Extensions::DISCIPLE) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "Z80: can't create a snapshot of Pentagon with IF1 or MGT extensions"))
+        }
+        Pentagon128 => (9, false),
+        Pentagon512|Pentagon1024 => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "Z80: can't create a snapshot of a Pentagon with more than 128k of RAM"))
+        }
         TimexTC2048|TimexTC2068|TimexTS2068 if (ext.intersects(Extensions::IF1) && snapshot.is_interface1_rom_paged_in())
                              || (ext.intersects(Extensions::PLUS_D) && snapshot.is_plus_d_rom_paged_in())
                              || (ext.intersects(Extensions::DISCIPLE) && snapshot.is_disciple_rom_paged_in())
@@ -238,6 +251,7 @@ fn init_z80_header_ex<S: SnapshotCreator, C: Cpu>(
     head_ex.port1 = match model {
         Spectrum128|SpectrumPlus2|SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|
         SpectrumSE => snapshot.ula128_flags().bits(),
+        Pentagon128 => snapshot.pentagon_mem_port(),
         TimexTC2048|TimexTC2068|TimexTS2068 => snapshot.timex_memory_banks(),
         _ => 0
     };
@@ -257,25 +271,36 @@ fn init_z80_header_ex<S: SnapshotCreator, C: Cpu>(
     Ok(())
 }
 
+fn save_mem_page<W: Write>(mut wr: W, ptype: u8, mem_slice: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    buf.clear();
+    compress_write_all(mem_slice, &mut *buf)?;
+    let (mem_head, slice) = match buf.len().try_into() {
+        Ok(core::u16::MAX)|Err(..) => {
+            (MemoryHeader::new(core::u16::MAX, ptype), mem_slice)
+        }
+        Ok(length) => (MemoryHeader::new(length, ptype), &buf[..]),
+    };
+    mem_head.write_struct(wr.by_ref())?;
+    wr.write_all(slice)
+}
+
 fn save_ram_pages<W: Write, S: SnapshotCreator, I: Iterator<Item=(u8, usize)>>(
         mut wr: W,
         snapshot: &S,
+        ex_rom: Option<MemoryRange>,
         pages: I
     ) -> Result<()>
 {
     let mut buf = Vec::with_capacity(0x1000);
+    if let Some(range) = ex_rom {
+        let mem_slice = snapshot.memory_ref(range)?;
+        if mem_slice.len() == PAGE_SIZE {
+            save_mem_page(wr.by_ref(), 1, mem_slice, &mut buf)?;
+        }
+    }
     for (ptype, page) in pages {
-        buf.clear();
         let mem_slice = snapshot.memory_ref(MemoryRange::Ram(page * PAGE_SIZE..(page + 1) * PAGE_SIZE))?;
-        compress_write_all(mem_slice, &mut buf)?;
-        let (mem_head, slice) = match buf.len().try_into() {
-            Ok(core::u16::MAX)|Err(..) => {
-                (MemoryHeader::new(core::u16::MAX, ptype), mem_slice)
-            }
-            Ok(length) => (MemoryHeader::new(length, ptype), &buf[..]),
-        };
-        mem_head.write_struct(wr.by_ref())?;
-        wr.write_all(slice)?;
+        save_mem_page(wr.by_ref(), ptype, mem_slice, &mut buf)?;
     }
     wr.flush()
 }
@@ -300,16 +325,24 @@ fn save_all_v2v3<W: Write, S: SnapshotCreator>(
     wr.write_all(&ex_len.to_le_bytes()[..])?;
     head_ex.write_struct_with_limit(wr.by_ref(), ex_len as usize)?;
 
+    // the M.G.T. memory is saved only if it's paged in
+    let ex_rom = match (version, head_ex.hw_mode) {
+        (Z80Version::V3, 3)|(Z80Version::V3, 6) if head_ex.mgt_rom == 0 => None,
+        (Z80Version::V3, 3)|(Z80Version::V3, 6) if head_ex.mgt_type == 16 => Some(MemoryRange::PlusDRom),
+        (Z80Version::V3, 3)|(Z80Version::V3, 6) => Some(MemoryRange::DiscipleRom),
+        _ => None
+    };
+
     match model {
         Spectrum16 => {
-            save_ram_pages(wr, snapshot, iter::once((8, 0)))
+            save_ram_pages(wr, snapshot, ex_rom, iter::once((8, 0)))
         }
         Spectrum48|SpectrumNTSC|TimexTC2048|TimexTS2068|TimexTC2068 => {
-            save_ram_pages(wr, snapshot,
+            save_ram_pages(wr, snapshot, ex_rom,
                 [(8, 0), (4, 1), (5, 2)].iter().copied())
         }
-        Spectrum128|SpectrumPlus2|SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e => {
-            save_ram_pages(wr, snapshot,
+        Spectrum128|SpectrumPlus2|SpectrumPlus2A|SpectrumPlus3|SpectrumPlus3e|Pentagon128 => {
+            save_ram_pages(wr, snapshot, ex_rom,
                 (0..8).map(|page| (page as u8 + 3, page))
             )
         }
@@ -548,3 +581,52 @@ pub fn save_z80v3<C: SnapshotCreator, W: Write>(
     save_all_v2v3(Z80Version::V3, snapshot, model, &header, &head_ex, wr)?;
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::clock::FTs;
+    use spectrusty_core::memory::ZxMemoryError;
+    use super::*;
+
+    struct TestSnapshot {
+        plus_d_paged_in: bool,
+        mem: Vec<u8>
+    }
+
+    impl SnapshotCreator for TestSnapshot {
+        fn model(&self) -> ComputerModel { ComputerModel::Spectrum48 }
+        fn extensions(&self) -> Extensions { Extensions::PLUS_D }
+        fn cpu(&self) -> CpuModel { CpuModel::NMOS(Z80NMOS::default()) }
+        fn current_clock(&self) -> FTs { 0 }
+        fn border_color(&self) -> BorderColor { BorderColor::BLACK }
+        fn issue(&self) -> ReadEarMode { ReadEarMode::Issue3 }
+        fn memory_ref(&self, range: MemoryRange) -> core::result::Result<&[u8], ZxMemoryError> {
+            match range {
+                MemoryRange::Ram(range) => Ok(&self.mem[range]),
+                _ => Ok(&self.mem[..PAGE_SIZE])
+            }
+        }
+        fn is_plus_d_rom_paged_in(&self) -> bool { self.plus_d_paged_in }
+    }
+
+    fn saved_pages(snapshot: &TestSnapshot) -> Vec<u8> {
+        let mut data = Vec::new();
+        save_z80v3(snapshot, &mut data).unwrap();
+        let mut index = 30 + 2 + u16::from_le_bytes([data[30], data[31]]) as usize;
+        let mut pages = Vec::new();
+        while index < data.len() {
+            let length = u16::from_le_bytes([data[index], data[index + 1]]) as usize;
+            pages.push(data[index + 2]);
+            index += 3 + if length == 0xffff { PAGE_SIZE } else { length };
+        }
+        pages
+    }
+
+    #[test]
+    fn z80_save_mgt_memory_works() {
+        let mut snapshot = TestSnapshot { plus_d_paged_in: false, mem: vec![0; 0xC000] };
+        assert_eq!(saved_pages(&snapshot), [8, 4, 5]);
+        snapshot.plus_d_paged_in = true;
+        assert_eq!(saved_pages(&snapshot), [1, 8, 4, 5]);
+    }
+}
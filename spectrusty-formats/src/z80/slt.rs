@@ -0,0 +1,436 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::cell::Cell;
+use core::num::{NonZeroU8, NonZeroU16};
+use core::mem;
+use std::collections::BTreeMap;
+
+use spectrusty_core::z80emu::{*, host::Result};
+use spectrusty_core::chip::ControlUnit;
+
+/// The level data of the multi-load game from the **SLT** extension of the **Z80** snapshot.
+///
+/// Serves the level loader trap: each time the `ED FB` instruction is being executed, the data
+/// of the level selected by the `A` register is copied into memory at the address given in the
+/// `HL` register. If there is no such level, the instruction does nothing.
+///
+/// The level data can be collected from [SnapshotLoader::setup_slt_level][crate::snapshot::SnapshotLoader::setup_slt_level]
+/// while loading the snapshot with [load_z80][super::load_z80].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SltLevels {
+    levels: BTreeMap<u16, Box<[u8]>>
+}
+
+/// Wraps the [Cpu] intercepting the opcode fetches in search for the level loader trap.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct SltTrapCpu<C> {
+    cpu: C,
+    levels: SltLevels
+}
+
+struct SltTrapIo<'a, M> {
+    control: &'a mut M,
+    ed_pc: Option<u16>,
+    trap: &'a Cell<bool>
+}
+
+struct SltTrapClock<'a, T> {
+    tsc: &'a mut T,
+    trap: &'a Cell<bool>
+}
+
+impl SltLevels {
+    /// Creates an empty level data container.
+    pub fn new() -> Self {
+        SltLevels::default()
+    }
+    /// Inserts the decompressed `data` of the given `level`.
+    ///
+    /// Returns the previous data of this level if there was any.
+    pub fn insert(&mut self, level: u16, data: Box<[u8]>) -> Option<Box<[u8]>> {
+        self.levels.insert(level, data)
+    }
+    /// Returns the data of the given `level`.
+    pub fn get(&self, level: u16) -> Option<&[u8]> {
+        self.levels.get(&level).map(|data| &data[..])
+    }
+    /// Returns the number of levels.
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+    /// Returns `true` if there is no level data.
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+    /// Removes all the level data.
+    pub fn clear(&mut self) {
+        self.levels.clear()
+    }
+    /// Executes instructions on the `cpu` using the `chip` via [ControlUnit::execute_next_frame]
+    /// while serving the level loader traps.
+    pub fn execute_next_frame<U: ControlUnit, C: Cpu>(&mut self, chip: &mut U, cpu: &mut C) {
+        self.with_trap_cpu(cpu, |trap_cpu| chip.execute_next_frame(trap_cpu))
+    }
+    /// Executes a single instruction on the `cpu` using the `chip` via [ControlUnit::execute_single_step]
+    /// while serving the level loader trap.
+    pub fn execute_single_step<U, C, F>(
+            &mut self,
+            chip: &mut U,
+            cpu: &mut C,
+            debug: Option<F>
+        ) -> Result<(), ()>
+        where U: ControlUnit,
+              C: Cpu,
+              F: FnOnce(CpuDebug)
+    {
+        self.with_trap_cpu(cpu, |trap_cpu| chip.execute_single_step(trap_cpu, debug))
+    }
+
+    fn with_trap_cpu<C: Cpu, R, F: FnOnce(&mut SltTrapCpu<C>) -> R>(&mut self, cpu: &mut C, f: F) -> R {
+        let mut trap_cpu = SltTrapCpu { cpu: mem::take(cpu), levels: mem::take(self) };
+        let res = f(&mut trap_cpu);
+        *cpu = trap_cpu.cpu;
+        *self = trap_cpu.levels;
+        res
+    }
+}
+
+impl<M: Memory> Memory for SltTrapIo<'_, M> {
+    type Timestamp = M::Timestamp;
+
+    #[inline]
+    fn read_mem(&self, address: u16, ts: Self::Timestamp) -> u8 {
+        self.control.read_mem(address, ts)
+    }
+    #[inline]
+    fn read_mem16(&self, address: u16, ts: Self::Timestamp) -> u16 {
+        self.control.read_mem16(address, ts)
+    }
+    /// Detects the `FB` opcode fetched right after the `ED` prefix.
+    fn read_opcode(&mut self, pc: u16, ir: u16, ts: Self::Timestamp) -> u8 {
+        let code = self.control.read_opcode(pc, ir, ts);
+        self.ed_pc = match self.ed_pc.take() {
+            Some(ed_pc) if ed_pc == pc.wrapping_sub(1) => {
+                if code == 0xFB {
+                    self.trap.set(true);
+                }
+                None
+            }
+            _ if code == 0xED => Some(pc),
+            _ => None
+        };
+        code
+    }
+    #[inline]
+    fn write_mem(&mut self, address: u16, value: u8, ts: Self::Timestamp) {
+        self.control.write_mem(address, value, ts)
+    }
+    #[inline]
+    fn read_debug(&self, address: u16) -> u8 {
+        self.control.read_debug(address)
+    }
+}
+
+impl<M: Io> Io for SltTrapIo<'_, M> {
+    type Timestamp = M::Timestamp;
+    type WrIoBreak = M::WrIoBreak;
+    type RetiBreak = M::RetiBreak;
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> (u8, Option<NonZeroU16>) {
+        self.control.read_io(port, timestamp)
+    }
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> (Option<Self::WrIoBreak>, Option<NonZeroU16>) {
+        self.control.write_io(port, data, timestamp)
+    }
+    #[inline]
+    fn is_irq(&mut self, timestamp: Self::Timestamp) -> bool {
+        self.control.is_irq(timestamp)
+    }
+    #[inline]
+    fn irq_data(&mut self, pc: u16, timestamp: Self::Timestamp) -> (u8, Option<NonZeroU16>) {
+        self.control.irq_data(pc, timestamp)
+    }
+    #[inline]
+    fn reti(&mut self, address: u16, timestamp: Self::Timestamp) -> Option<Self::RetiBreak> {
+        self.control.reti(address, timestamp)
+    }
+}
+
+impl<T: Clock> Clock for SltTrapClock<'_, T> {
+    type Limit = T::Limit;
+    type Timestamp = T::Timestamp;
+
+    /// Breaks the execution as soon as the trap has been encountered.
+    #[inline]
+    fn is_past_limit(&self, limit: Self::Limit) -> bool {
+        self.trap.get() || self.tsc.is_past_limit(limit)
+    }
+    #[inline]
+    fn add_irq(&mut self, pc: u16) -> Self::Timestamp {
+        self.tsc.add_irq(pc)
+    }
+    #[inline]
+    fn add_no_mreq(&mut self, address: u16, add_ts: NonZeroU8) {
+        self.tsc.add_no_mreq(address, add_ts)
+    }
+    #[inline]
+    fn add_m1(&mut self, address: u16) -> Self::Timestamp {
+        self.tsc.add_m1(address)
+    }
+    #[inline]
+    fn add_mreq(&mut self, address: u16) -> Self::Timestamp {
+        self.tsc.add_mreq(address)
+    }
+    #[inline]
+    fn add_io(&mut self, port: u16) -> Self::Timestamp {
+        self.tsc.add_io(port)
+    }
+    #[inline]
+    fn add_wait_states(&mut self, bus: u16, wait_states: NonZeroU16) {
+        self.tsc.add_wait_states(bus, wait_states)
+    }
+    #[inline]
+    fn as_timestamp(&self) -> Self::Timestamp {
+        self.tsc.as_timestamp()
+    }
+}
+
+impl<C: Cpu> SltTrapCpu<C> {
+    /// Executes `f` with the wrapped `control` and `tsc` and serves the trap if it has been encountered.
+    fn trap<M, T, R, F>(&mut self, control: &mut M, tsc: &mut T, f: F) -> (R, bool)
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(&mut C, &mut SltTrapIo<'_, M>, &mut SltTrapClock<'_, T>) -> R
+    {
+        let trap = Cell::new(false);
+        let res = {
+            let mut control = SltTrapIo { control: &mut *control, ed_pc: None, trap: &trap };
+            let mut tsc = SltTrapClock { tsc: &mut *tsc, trap: &trap };
+            f(&mut self.cpu, &mut control, &mut tsc)
+        };
+        let trapped = trap.get();
+        if trapped {
+            self.load_level(control, tsc);
+        }
+        (res, trapped)
+    }
+
+    fn load_level<M: Memory, T: Clock<Timestamp=M::Timestamp>>(&self, control: &mut M, tsc: &T) {
+        let level = u16::from(self.cpu.get_acc());
+        if let Some(data) = self.levels.get(level) {
+            let mut address = self.cpu.get_reg16(StkReg16::HL);
+            for &byte in data.iter() {
+                control.write_mem(address, byte, tsc.as_timestamp());
+                address = address.wrapping_add(1);
+            }
+        }
+    }
+}
+
+impl<C: Cpu> Cpu for SltTrapCpu<C> {
+    delegate_cpu_state!(cpu);
+
+    #[inline]
+    fn inc_r(&mut self) {
+        self.cpu.inc_r()
+    }
+    #[inline]
+    fn add_r(&mut self, delta: i32) {
+        self.cpu.add_r(delta)
+    }
+
+    fn irq<M, T, F>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            debug: Option<F>
+        ) -> Option<Result<M::WrIoBreak, M::RetiBreak>>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(CpuDebug)
+    {
+        self.trap(control, tsc, |cpu, control, tsc| cpu.irq(control, tsc, debug)).0
+    }
+
+    fn nmi<M, T>(&mut self, control: &mut M, tsc: &mut T) -> bool
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock
+    {
+        self.cpu.nmi(control, tsc)
+    }
+
+    fn execute_instruction<M, T, F>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            debug: Option<F>,
+            code: u8
+        ) -> Result<M::WrIoBreak, M::RetiBreak>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(CpuDebug)
+    {
+        self.trap(control, tsc, |cpu, control, tsc| {
+            cpu.execute_instruction(control, tsc, debug, code)
+        }).0
+    }
+
+    fn execute_next<M, T, F>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            debug: Option<F>
+        ) -> Result<M::WrIoBreak, M::RetiBreak>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock,
+              F: FnOnce(CpuDebug)
+    {
+        self.trap(control, tsc, |cpu, control, tsc| cpu.execute_next(control, tsc, debug)).0
+    }
+
+    /// The execution is interrupted at each trap to serve it and then resumed.
+    fn execute_with_limit<M, T>(
+            &mut self,
+            control: &mut M,
+            tsc: &mut T,
+            limit: T::Limit
+        ) -> Result<M::WrIoBreak, M::RetiBreak>
+        where M: Memory<Timestamp=T::Timestamp> + Io<Timestamp=T::Timestamp>,
+              T: Clock
+    {
+        loop {
+            let (res, trapped) = self.trap(control, tsc, |cpu, control, tsc| {
+                cpu.execute_with_limit(control, tsc, limit)
+            });
+            if !trapped || res.is_err() || tsc.is_past_limit(limit) {
+                break res
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+    use spectrusty::z80emu::{Cpu, Z80NMOS};
+    use spectrusty::chip::{MemoryAccess, ula::UlaPAL};
+    use spectrusty::memory::{Memory48k, ZxMemory, ZxMemoryError};
+    use spectrusty::clock::FTs;
+    use spectrusty::video::BorderColor;
+    use crate::snapshot::*;
+    use crate::z80::load_z80;
+    use super::*;
+
+    type NoDebug = fn(CpuDebug);
+
+    #[derive(Default)]
+    struct TestLoader {
+        model: Option<ComputerModel>,
+        levels: SltLevels
+    }
+
+    impl SnapshotLoader for TestLoader {
+        type Error = &'static str;
+        fn select_model(
+                &mut self,
+                model: ComputerModel,
+                _extensions: Extensions,
+                _border: BorderColor,
+                _issue: spectrusty::chip::ReadEarMode
+            ) -> core::result::Result<(), Self::Error>
+        {
+            self.model = Some(model);
+            Ok(())
+        }
+        fn read_into_memory<R: Read>(&mut self, _range: MemoryRange, mut reader: R) -> core::result::Result<(), ZxMemoryError> {
+            io::copy(&mut reader, &mut io::sink()).map(drop).map_err(ZxMemoryError::Io)
+        }
+        fn assign_cpu(&mut self, _cpu: CpuModel) {}
+        fn set_clock(&mut self, _tstates: FTs) {}
+        fn write_port(&mut self, _port: u16, _data: u8) {}
+        fn setup_slt_level(&mut self, level: u16, data: Box<[u8]>) {
+            self.levels.insert(level, data);
+        }
+    }
+
+    fn z80v2_with_slt(slt: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8;30];
+        buf.extend_from_slice(&[23, 0]);
+        buf.extend_from_slice(&[0u8;23]);
+        for &page in &[4, 5, 8] {
+            buf.extend_from_slice(&[0xff, 0xff, page]);
+            buf.resize(buf.len() + 0x4000, 0);
+        }
+        buf.extend_from_slice(&[0, 0, 0]);
+        buf.extend_from_slice(slt);
+        buf
+    }
+
+    #[test]
+    fn slt_load_works() {
+        let slt = [
+            b'S', b'L', b'T',
+            1, 0, 1, 0, 3, 0, 0, 0,    // level 1
+            3, 0, 0, 0, 2, 0, 0, 0,    // loading screen, ignored
+            1, 0, 2, 0, 4, 0, 0, 0,    // level 2
+            0, 0, 0, 0, 0, 0, 0, 0,
+            1, 2, 3,
+            0xAA, 0xBB,
+            0xED, 0xED, 5, 7
+        ];
+        let mut loader = TestLoader::default();
+        load_z80(&z80v2_with_slt(&slt)[..], &mut loader).unwrap();
+        assert_eq!(loader.model, Some(ComputerModel::Spectrum48));
+        assert_eq!(loader.levels.len(), 2);
+        assert_eq!(loader.levels.get(1), Some(&[1, 2, 3][..]));
+        assert_eq!(loader.levels.get(2), Some(&[7;5][..]));
+        assert_eq!(loader.levels.get(3), None);
+
+        let mut loader = TestLoader::default();
+        let err = load_z80(&z80v2_with_slt(&slt[..slt.len() - 1])[..], &mut loader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = load_z80(&z80v2_with_slt(b"SLX")[..], &mut loader).unwrap_err();
+        assert_eq!(err.to_string(), "invalid SLT signature");
+    }
+
+    #[test]
+    fn slt_trap_works() {
+        let mut ula = UlaPAL::<Memory48k>::default();
+        let mut cpu = Z80NMOS::default();
+        let program = [
+            0x3E, 0x02,       // 0x8000 LD A, 2
+            0x21, 0x00, 0x90, // 0x8002 LD HL, 0x9000
+            0xED, 0xED,       // 0x8005 an invalid ED instruction
+            0xFB,             // 0x8007 EI, not a trap
+            0xF3,             // 0x8008 DI
+            0x21, 0x00, 0xA0, // 0x8009 LD HL, 0xA000
+            0xED, 0xFB,       // 0x800C load level 2 trap
+            0x3C,             // 0x800E INC A
+            0xED, 0xFB,       // 0x800F load level 3 trap, no such level
+            0x18, 0xFE,       // 0x8011 JR 0x8011
+        ];
+        ula.memory_mut().mem_mut()[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+        cpu.reset();
+        cpu.set_pc(0x8000);
+        cpu.set_sp(0xC000);
+        let mut levels = SltLevels::new();
+        levels.insert(2, Box::new([1, 2, 3, 4]));
+        for _ in 0..6 {
+            levels.execute_single_step(&mut ula, &mut cpu, None::<NoDebug>).unwrap();
+        }
+        assert_eq!(cpu.get_pc(), 0x800C);
+        levels.execute_next_frame(&mut ula, &mut cpu);
+        assert_eq!(cpu.get_pc(), 0x8011);
+        assert_eq!(cpu.get_acc(), 3);
+        assert_eq!(&ula.memory_ref().mem_ref()[0x9000..0x9004], &[0;4]);
+        assert_eq!(&ula.memory_ref().mem_ref()[0xA000..0xA005], &[1, 2, 3, 4, 0]);
+        assert_eq!(levels.len(), 1);
+    }
+}
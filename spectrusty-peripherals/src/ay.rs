@@ -19,6 +19,7 @@ use serde::{Serialize, Deserialize};
 
 pub mod audio;
 pub mod serial128;
+pub mod timex;
 
 use spectrusty_core::clock::FTs;
 
@@ -73,6 +73,41 @@ pub const FUSE_AMPS_I16: [i16;16] = [0x0000, 0x01c2, 0x029e, 0x03b8,
                                      0x15a6, 0x21e0, 0x2d25, 0x3997,
                                      0x4902, 0x57f8, 0x6c90, 0x7fff];
 
+/// Amplitude levels for the 32-step DAC of YM2149.
+///
+/// These levels are being used by the [Ayumi] emulator and are based on the measurements of the real chip.
+/// Unlike [AMPS] and [FUSE_AMPS] these levels are indexed by 5-bit values from 0 to 31.
+/// [YmAmps] struct implements `YM_AMPS` for [AmpLevels].
+///
+/// [Ayumi]: https://github.com/true-grue/ayumi
+#[allow(clippy::unreadable_literal,clippy::excessive_precision)]
+pub const YM_AMPS: [f32;32] = [0.000000, 0.000000, 0.004654, 0.007721,
+                               0.010956, 0.013962, 0.016999, 0.020020,
+                               0.024369, 0.029694, 0.035065, 0.040391,
+                               0.048539, 0.058335, 0.068055, 0.077775,
+                               0.092515, 0.111086, 0.129747, 0.148486,
+                               0.176669, 0.211551, 0.246387, 0.281102,
+                               0.333730, 0.400427, 0.467384, 0.534432,
+                               0.635172, 0.758007, 0.879927, 1.000000];
+
+pub const YM_AMPS_I32: [i32;32] = [0x0000_0000, 0x0000_0000, 0x0098_8098, 0x00fd_00fd,
+                                   0x0167_0167, 0x01c9_81c9, 0x022d_022d, 0x0290_0290,
+                                   0x031e_831e, 0x03cd_03cd, 0x047d_047d, 0x052b_852b,
+                                   0x0636_8636, 0x0777_8777, 0x08b6_08b6, 0x09f4_89f4,
+                                   0x0bd7_8bd7, 0x0e38_0e38, 0x109b_909b, 0x1301_9301,
+                                   0x169d_169d, 0x1b14_1b14, 0x1f89_9f89, 0x23fb_23fb,
+                                   0x2ab7_aab7, 0x3341_3341, 0x3bd3_3bd3, 0x4468_4468,
+                                   0x514d_514d, 0x6106_6106, 0x70a1_70a1, 0x7fff_ffff];
+
+pub const YM_AMPS_I16: [i16;32] = [0x0000, 0x0000, 0x0098, 0x00fd,
+                                   0x0167, 0x01c9, 0x022d, 0x0290,
+                                   0x031e, 0x03cd, 0x047d, 0x052b,
+                                   0x0636, 0x0777, 0x08b6, 0x09f4,
+                                   0x0bd7, 0x0e38, 0x109b, 0x1301,
+                                   0x169d, 0x1b14, 0x1f89, 0x23fb,
+                                   0x2ab7, 0x3341, 0x3bd3, 0x4468,
+                                   0x514d, 0x6106, 0x70a1, 0x7fff];
+
 /// This may be used to calculate other levels, but I'd discourage from using it in the player
 /// as it uses expensive float calculations.
 pub struct LogAmpLevels16<T>(PhantomData<T>);
@@ -97,20 +132,25 @@ impl<T: Copy + FromSample<f32>> AmpLevels<T> for LogAmpLevels16<T> {
 pub struct AyAmps<T>(PhantomData<T>);
 /// A struct implementing alternative [AmpLevels] for Ay-3-891x sound chip. See also [FUSE_AMPS].
 pub struct AyFuseAmps<T>(PhantomData<T>);
+/// A struct implementing 5-bit [AmpLevels] for YM2149 sound chip. See also [YM_AMPS].
+///
+/// Use it to render audio when [Ay3_891xAudio] is in the [AyChipModel::Ym2149] mode.
+pub struct YmAmps<T>(PhantomData<T>);
 
 macro_rules! impl_ay_amp_levels {
     ($([$name:ident, $ty:ty, $amps:ident]),*) => { $(
         impl AmpLevels<$ty> for $name<$ty> {
             #[inline(always)]
             fn amp_level(level: u32) -> $ty {
-                $amps[(level & 15) as usize]
+                $amps[level as usize & ($amps.len() - 1)]
             }
         }
     )* };
 }
 impl_ay_amp_levels!(
     [AyAmps, f32, AMPS], [AyAmps, i32, AMPS_I32], [AyAmps, i16, AMPS_I16],
-    [AyFuseAmps, f32, FUSE_AMPS], [AyFuseAmps, i16, FUSE_AMPS_I16]);
+    [AyFuseAmps, f32, FUSE_AMPS], [AyFuseAmps, i16, FUSE_AMPS_I16],
+    [YmAmps, f32, YM_AMPS], [YmAmps, i32, YM_AMPS_I32], [YmAmps, i16, YM_AMPS_I16]);
 
 /// A trait for interfacing controllers to render square-wave audio pulses from an AY-3-891x emulator.
 pub trait AyAudioFrame<B: Blep> {
@@ -118,6 +158,8 @@ pub trait AyAudioFrame<B: Blep> {
     ///
     /// Provide [AmpLevels] that can handle `level` values from 0 to 15 (4-bits).
     /// `channels` - target [Blep] audio channels for `[A, B, C]` AY-3-891x channels.
+    ///
+    /// To pan channels between stereo outputs see [AyStereoPan].
     fn render_ay_audio_frame<V: AmpLevels<B::SampleDelta>>(
         &mut self,
         blep: &mut B,
@@ -125,8 +167,89 @@ pub trait AyAudioFrame<B: Blep> {
     );
 }
 
+/// Stereo panning modes for AY-3-891x channels.
+///
+/// To pan channels, render `[A, B, C]` AY-3-891x channels into channels `[0, 1, 2]` of the
+/// [BlepStereoPan] wrapper, with its [pans][BlepStereoPan::pans] created from [AyStereoPan::weights].
+///
+/// ```text
+///              A          B          C
+///  Mono     center     center     center
+///  Abc       left      center     right
+///  Acb       left      right      center
+///  Bac      center      left      right
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub enum AyStereoPan {
+    Mono,
+    Abc,
+    Acb,
+    Bac,
+    /// Custom positions of `[A, B, C]` channels in percents: 0 - left, 50 - center, 100 - right.
+    Custom([u8; 3])
+}
+
+impl Default for AyStereoPan {
+    fn default() -> Self {
+        AyStereoPan::Abc
+    }
+}
+
+impl AyStereoPan {
+    /// Returns positions of `[A, B, C]` channels in percents: 0 - left, 50 - center, 100 - right.
+    ///
+    /// Custom positions above 100 are limited to 100.
+    pub fn positions(self) -> [u8; 3] {
+        match self {
+            AyStereoPan::Mono => [50, 50, 50],
+            AyStereoPan::Abc  => [0, 50, 100],
+            AyStereoPan::Acb  => [0, 100, 50],
+            AyStereoPan::Bac  => [50, 0, 100],
+            AyStereoPan::Custom(pos) => {
+                let mut res = [0; 3];
+                for (tgt, pos) in res.iter_mut().zip(pos.iter()) {
+                    *tgt = (*pos).min(100);
+                }
+                res
+            }
+        }
+    }
+    /// Returns `(left, right)` amplitude weights of `[A, B, C]` channels.
+    ///
+    /// The weights follow the constant power panning law, so the perceived loudness of a channel
+    /// doesn't depend on its position.
+    pub fn weights<T: FromSample<f32>>(self) -> [(T, T); 3] {
+        let [a, b, c] = self.positions();
+        [pan_weights(a), pan_weights(b), pan_weights(c)]
+    }
+}
+
+fn pan_weights<T: FromSample<f32>>(position: u8) -> (T, T) {
+    let angle = f32::from(position) / 100.0 * core::f32::consts::FRAC_PI_2;
+    (T::from_sample(angle.cos().max(0.0)), T::from_sample(angle.sin().max(0.0)))
+}
+
+/// The sound generator chip being emulated by [Ay3_891xAudio].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub enum AyChipModel {
+    /// AY-3-8910/8912/8913 with the 16-step envelope and the 4-bit DAC.
+    Ay3_891x,
+    /// YM2149 with the 32-step envelope and the 5-bit DAC.
+    Ym2149
+}
+
+impl Default for AyChipModel {
+    fn default() -> Self {
+        AyChipModel::Ay3_891x
+    }
+}
+
 /// Implements AY-3-8910/8912/8913 programmable sound generator.
 ///
+/// Optionally emulates the YM2149 sound generator, see [Ay3_891xAudio::set_chip_model].
+///
 /// For the implementation of I/O ports see [crate::ay].
 #[derive(Default, Clone, Debug)]
 #[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
@@ -139,6 +262,10 @@ pub struct Ay3_891xAudio {
     noise_control: NoiseControl,
     tone_control: [ToneControl; 3],
     mixer: Mixer,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    chip_model: AyChipModel,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    ym_clock_divider: bool,
 }
 
 /// A type for AY-3-891x amplitude level register values.
@@ -185,6 +312,7 @@ pub const ENV_SHAPE_HOLD_MASK:   u8 = 0b0000_0001;
 const ENV_LEVEL_REV_MASK:    u8 = 0b1000_0000;
 const ENV_LEVEL_MOD_MASK:    u8 = 0b0100_0000;
 const ENV_LEVEL_MASK:        u8 = 0x0F;
+const ENV_LEVEL_MASK_YM:     u8 = 0x1F;
 const ENV_CYCLE_MASK:        u8 = 0xF0;
 
 /// A type implementing AY-3-891x volume envelope progression.
@@ -206,15 +334,16 @@ impl Default for EnvelopeControl {
 }
 
 impl EnvelopeControl {
+    /// `mask` determines the number of envelope steps: `ENV_LEVEL_MASK` or `ENV_LEVEL_MASK_YM`.
     #[inline]
-    fn set_shape(&mut self, shape: u8) {
+    fn set_shape(&mut self, shape: u8, mask: u8) {
         self.tick = 0;
         self.cycle = shape & !ENV_CYCLE_MASK;
         self.level = if shape & ENV_SHAPE_ATTACK_MASK != 0 {
             ENV_LEVEL_MOD_MASK
         }
         else {
-            ENV_LEVEL_MOD_MASK|ENV_LEVEL_REV_MASK|ENV_LEVEL_MASK
+            ENV_LEVEL_MOD_MASK|ENV_LEVEL_REV_MASK|mask
         }
     }
     #[inline]
@@ -235,52 +364,64 @@ impl EnvelopeControl {
         }
     }
     #[inline]
-    fn get_level(&self) -> u8 {
-        self.level & ENV_LEVEL_MASK
+    fn get_level(&self, mask: u8) -> u8 {
+        self.level & mask
+    }
+    /// Converts the current level between the 16 and 32-step envelope resolutions.
+    #[inline]
+    fn convert_level(&mut self, from_mask: u8, to_mask: u8) {
+        let level = self.level & from_mask;
+        let level = if from_mask < to_mask {
+            (level << 1) | (level >> 3)
+        }
+        else {
+            level >> 1
+        };
+        self.level = (self.level & !from_mask) | level;
     }
     #[inline]
     fn get_shape(&self) -> u8 {
         self.cycle & !ENV_CYCLE_MASK
     }
+    /// `mask` determines the number of envelope steps: `ENV_LEVEL_MASK` or `ENV_LEVEL_MASK_YM`.
     #[inline]
-    fn update_level(&mut self) -> u8 {
-        let EnvelopeControl { period, mut tick, mut level, .. } = *self;
+    fn update_level(&mut self, mask: u8) -> u8 {
+        let EnvelopeControl { period, mut tick, mut level, cycle } = *self;
         if tick >= period {
             tick -= period;
 
             if level & ENV_LEVEL_MOD_MASK != 0 {
-                level = (level & !ENV_LEVEL_MASK) | (
-                    if level & ENV_LEVEL_REV_MASK == 0 {
-                        level.wrapping_add(1)
-                    }
-                    else {
-                        level.wrapping_sub(1)
-                    }
-                & ENV_LEVEL_MASK);
-
-                let cycle = self.cycle.wrapping_add(0x10); // 16 times
-                if cycle & ENV_CYCLE_MASK == 0 {
+                let (next, cycle_end) = if level & ENV_LEVEL_REV_MASK == 0 {
+                    let next = level.wrapping_add(1) & mask;
+                    (next, next == 0)
+                }
+                else {
+                    let next = level.wrapping_sub(1) & mask;
+                    (next, next == mask)
+                };
+                level = (level & !mask) | next;
+                // the level has wrapped around after 16 or 32 steps
+                if cycle_end {
                     if cycle & ENV_SHAPE_CONT_MASK == 0 {
                         level = 0;
                     }
                     else if cycle & ENV_SHAPE_HOLD_MASK != 0 {
                         if cycle & ENV_SHAPE_ALT_MASK == 0 {
-                            level ^= ENV_LEVEL_MOD_MASK|ENV_LEVEL_MASK;
+                            level ^= ENV_LEVEL_MOD_MASK|mask;
                         }
                         else {
                             level ^= ENV_LEVEL_MOD_MASK;
                         }
                     }
                     else if cycle & ENV_SHAPE_ALT_MASK != 0 {
-                        level ^= ENV_LEVEL_REV_MASK|ENV_LEVEL_MASK;
+                        level ^= ENV_LEVEL_REV_MASK|mask;
                     }
                 }
                 self.level = level;
-                self.cycle = cycle;
             }
         }
         self.tick = tick.wrapping_add(1);
-        level & ENV_LEVEL_MASK
+        level & mask
     }
 }
 
@@ -385,13 +526,14 @@ impl ToneControl {
 #[derive(Clone, Copy, Debug)]
 struct Ticker {
     current: FTs,
-    end_ts: FTs
+    end_ts: FTs,
+    step: FTs
 }
 
 impl Ticker {
     const CLOCK_INCREASE: FTs = HOST_CLOCK_RATIO * INTERNAL_CLOCK_DIVISOR;
-    fn new(current: FTs, end_ts: FTs) -> Self {
-        Ticker { current, end_ts }
+    fn new(current: FTs, end_ts: FTs, clock_divider: FTs) -> Self {
+        Ticker { current, end_ts, step: Self::CLOCK_INCREASE * clock_divider }
     }
 }
 
@@ -400,7 +542,7 @@ impl Iterator for Ticker {
     fn next(&mut self) -> Option<FTs> {
         let res = self.current;
         if res < self.end_ts {
-            self.current = res + Self::CLOCK_INCREASE;
+            self.current = res + self.step;
             Some(res)
         }
         else {
@@ -412,8 +554,61 @@ impl Iterator for Ticker {
 /// Use the [Default] trait to create instances of this struct.
 impl Ay3_891xAudio {
     /// Resets the internal state to the one initialized with.
+    ///
+    /// The chip model and the YM2149 clock divider selection are preserved.
     pub fn reset(&mut self) {
-        *self = Default::default()
+        let Ay3_891xAudio { chip_model, ym_clock_divider, .. } = *self;
+        *self = Ay3_891xAudio { chip_model, ym_clock_divider, ..Default::default() }
+    }
+    /// Returns the currently emulated chip model.
+    #[inline]
+    pub fn chip_model(&self) -> AyChipModel {
+        self.chip_model
+    }
+    /// Changes the emulated chip model.
+    ///
+    /// In the [AyChipModel::Ym2149] mode the envelope generator progresses through 32 steps instead
+    /// of 16 with twice the step frequency, and the amplitude levels passed to [AmpLevels] are in the
+    /// range from 0 to 31, where a fixed channel volume `v` is represented by the level `2 * v + 1`.
+    /// Use [YmAmps] to render audio in this mode.
+    ///
+    /// The current envelope level is converted to the new resolution.
+    pub fn set_chip_model(&mut self, chip_model: AyChipModel) {
+        let from_mask = self.env_level_mask();
+        self.chip_model = chip_model;
+        let to_mask = self.env_level_mask();
+        if from_mask != to_mask {
+            self.env_control.convert_level(from_mask, to_mask);
+            for level in self.last_levels.iter_mut() {
+                *level = if from_mask < to_mask {
+                    (*level << 1) | (*level >> 3)
+                }
+                else {
+                    *level >> 1
+                };
+            }
+        }
+    }
+    /// Returns `true` if the YM2149 clock divider is enabled.
+    #[inline]
+    pub fn ym_clock_divider(&self) -> bool {
+        self.ym_clock_divider
+    }
+    /// Enables or disables the YM2149 clock divider.
+    ///
+    /// Emulates the YM2149 `SEL` pin being pulled low, which makes the chip divide its input clock
+    /// by 2. Has effect only in the [AyChipModel::Ym2149] mode.
+    #[inline]
+    pub fn set_ym_clock_divider(&mut self, enabled: bool) {
+        self.ym_clock_divider = enabled;
+    }
+    #[inline]
+    fn is_ym(&self) -> bool {
+        self.chip_model == AyChipModel::Ym2149
+    }
+    #[inline]
+    fn env_level_mask(&self) -> u8 {
+        if self.is_ym() { ENV_LEVEL_MASK_YM } else { ENV_LEVEL_MASK }
     }
     /// Converts a tone frequency given in Hz to a closest 16-bit tone period register value.
     ///
@@ -473,7 +668,8 @@ impl Ay3_891xAudio {
     /// clock cycles until `end_ts` is reached. The internal cycle counter is then decremented by the
     /// value of `frame_tstates` before returning from this method.
     ///
-    /// Provide [AmpLevels] that can handle `level` values from 0 to 15 (4-bits).
+    /// Provide [AmpLevels] that can handle `level` values from 0 to 15 (4-bits), or from 0 to 31 (5-bits)
+    /// in the [AyChipModel::Ym2149] mode.
     ///
     /// * `changes` should be ordered by `time` and recorded only with `time` < `end_ts`
     ///   otherwise, some register changes may be lost - the iterator will be drained anyway.
@@ -492,7 +688,10 @@ impl Ay3_891xAudio {
               A: Blep
     {
         let mut change_iter = changes.into_iter().peekable();
-        let mut ticker = Ticker::new(self.current_ts, end_ts);
+        let is_ym = self.is_ym();
+        let env_mask = self.env_level_mask();
+        let clock_divider = if is_ym && self.ym_clock_divider { 2 } else { 1 };
+        let mut ticker = Ticker::new(self.current_ts, end_ts, clock_divider);
         let mut tone_levels: [u8; 3] = self.last_levels;
         let mut vol_levels: [A::SampleDelta;3] = Default::default();
 
@@ -512,7 +711,14 @@ impl Ay3_891xAudio {
             }
 
 
-            let env_level = self.env_control.update_level();
+            let env_level = if is_ym {
+                // the YM2149 envelope is clocked twice as fast
+                self.env_control.update_level(env_mask);
+                self.env_control.update_level(env_mask)
+            }
+            else {
+                self.env_control.update_level(env_mask)
+            };
             let noise_low = self.noise_control.update_is_low();
             let mut mixer = self.mixer;
             for ((level, tone_control), tgt_lvl) in self.amp_levels.iter()
@@ -525,6 +731,9 @@ impl Ay3_891xAudio {
                 else if level.is_env_control() {
                     env_level
                 }
+                else if is_ym {
+                    (level.0 << 1) | 1
+                }
                 else {
                     level.0
                 };
@@ -580,7 +789,7 @@ impl Ay3_891xAudio {
                 self.env_control.set_period_coarse(val)
             }
             EnvShape => {
-                self.env_control.set_shape(val)
+                self.env_control.set_shape(val, self.env_level_mask())
             }
             _ => ()
         }
@@ -601,13 +810,13 @@ impl Ay3_891xAudio {
     /// If the channel volume register's envelope bit is set, it returns the current envelope
     /// level for that channel.
     ///
-    /// The levels are in the range: [0, 15].
+    /// The levels are in the range: [0, 15], regardless of the chip model.
     #[inline]
     pub fn get_amp_levels(&self) -> [u8;3] {
         let mut amps = [0;3];
         for (level, tgt) in self.amp_levels.iter().zip(amps.iter_mut()) {
             *tgt = if level.is_env_control() {
-                self.env_control.get_level()
+                self.get_envelope_level()
             }
             else {
                 level.0
@@ -637,9 +846,12 @@ impl Ay3_891xAudio {
         self.mixer.0
     }
     /// Returns the current level of the envelope generator.
+    ///
+    /// The level is in the range: [0, 15], regardless of the chip model.
     #[inline]
     pub fn get_envelope_level(&self) -> u8 {
-        self.env_control.get_level()
+        let level = self.env_control.get_level(self.env_level_mask());
+        if self.is_ym() { level >> 1 } else { level }
     }
     /// Returns the envelope shape.
     #[inline]
@@ -689,19 +901,19 @@ mod tests {
                          ENV_SHAPE_HOLD_MASK,
                          ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK,
                          ENV_SHAPE_CONT_MASK|ENV_SHAPE_HOLD_MASK].iter().copied() {
-            ay.env_control.set_shape(shape);
+            ay.env_control.set_shape(shape, ENV_LEVEL_MASK);
             assert_eq!(ay.env_control.tick, 0);
             assert_eq!(ay.env_control.cycle, shape);
             assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|ENV_LEVEL_MASK);
             ay.env_control.set_period(0);
             assert_eq!(ay.env_control.period, 1);
             for exp_level in (0..=15).rev() {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
             for _ in 0..100 {
                 assert_eq!(ay.env_control.tick, 1);
-                assert_eq!(ay.env_control.update_level(), 0);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), 0);
             }
         }
 
@@ -710,25 +922,25 @@ mod tests {
                          ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK,
                          ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK
                          ].iter().copied() {
-            ay.env_control.set_shape(shape|ENV_SHAPE_ATTACK_MASK);
+            ay.env_control.set_shape(shape|ENV_SHAPE_ATTACK_MASK, ENV_LEVEL_MASK);
             assert_eq!(ay.env_control.tick, 0);
             assert_eq!(ay.env_control.cycle, shape|ENV_SHAPE_ATTACK_MASK);
             assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK);
             ay.env_control.set_period(0);
             assert_eq!(ay.env_control.period, 1);
             for exp_level in 0..=15 {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
             for _ in 0..100 {
                 assert_eq!(ay.env_control.tick, 1);
-                assert_eq!(ay.env_control.update_level(), 0);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), 0);
                 assert_eq!(ay.env_control.level, 0);
             }
         }
 
-        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK);
+        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK, ENV_LEVEL_MASK);
         assert_eq!(ay.env_control.tick, 0);
         assert_eq!(ay.env_control.cycle, ENV_SHAPE_CONT_MASK);
         assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|ENV_LEVEL_MASK);
@@ -736,13 +948,13 @@ mod tests {
         assert_eq!(ay.env_control.period, 1);
         for _ in 0..10 {
             for exp_level in (0..=15).rev() {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
         }
 
-        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK);
+        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK, ENV_LEVEL_MASK);
         assert_eq!(ay.env_control.tick, 0);
         assert_eq!(ay.env_control.cycle, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK);
         assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|ENV_LEVEL_MASK);
@@ -750,35 +962,35 @@ mod tests {
         assert_eq!(ay.env_control.period, 1);
         for _ in 0..10 {
             for exp_level in (0..=15).rev() {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
             for exp_level in 0..=15 {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
         }
 
-        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK);
+        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK, ENV_LEVEL_MASK);
         assert_eq!(ay.env_control.tick, 0);
         assert_eq!(ay.env_control.cycle, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK);
         assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|ENV_LEVEL_MASK);
         ay.env_control.set_period(0);
         assert_eq!(ay.env_control.period, 1);
         for exp_level in (0..=15).rev() {
-            assert_eq!(ay.env_control.update_level(), exp_level);
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
             assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|exp_level);
             assert_eq!(ay.env_control.tick, 1);
         }
         for _ in 0..100 {
             assert_eq!(ay.env_control.tick, 1);
-            assert_eq!(ay.env_control.update_level(), 15);
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), 15);
             assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|15);
         }
 
-        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK);
+        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK, ENV_LEVEL_MASK);
         assert_eq!(ay.env_control.tick, 0);
         assert_eq!(ay.env_control.cycle, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK);
         assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK);
@@ -786,30 +998,30 @@ mod tests {
         assert_eq!(ay.env_control.period, 1);
         for _ in 0..10 {
             for exp_level in 0..=15 {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
         }
 
-        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_HOLD_MASK);
+        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_HOLD_MASK, ENV_LEVEL_MASK);
         assert_eq!(ay.env_control.tick, 0);
         assert_eq!(ay.env_control.cycle, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_HOLD_MASK);
         assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK);
         ay.env_control.set_period(0);
         assert_eq!(ay.env_control.period, 1);
         for exp_level in 0..=15 {
-            assert_eq!(ay.env_control.update_level(), exp_level);
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
             assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK|exp_level);
             assert_eq!(ay.env_control.tick, 1);
         }
         for _ in 0..100 {
             assert_eq!(ay.env_control.tick, 1);
-            assert_eq!(ay.env_control.update_level(), 15);
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), 15);
             assert_eq!(ay.env_control.level, 15);
         }
 
-        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_ALT_MASK);
+        ay.env_control.set_shape(ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_ALT_MASK, ENV_LEVEL_MASK);
         assert_eq!(ay.env_control.tick, 0);
         assert_eq!(ay.env_control.cycle, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ATTACK_MASK|ENV_SHAPE_ALT_MASK);
         assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK);
@@ -817,15 +1029,150 @@ mod tests {
         assert_eq!(ay.env_control.period, 1);
         for _ in 0..10 {
             for exp_level in 0..=15 {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
             for exp_level in (0..=15).rev() {
-                assert_eq!(ay.env_control.update_level(), exp_level);
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), exp_level);
                 assert_eq!(ay.env_control.level, ENV_LEVEL_REV_MASK|ENV_LEVEL_MOD_MASK|exp_level);
                 assert_eq!(ay.env_control.tick, 1);
             }
         }
     }
+
+    #[derive(Default)]
+    struct TestBlep(Vec<(usize, FTs, f32)>);
+
+    impl Blep for TestBlep {
+        type SampleDelta = f32;
+        fn ensure_frame_time(&mut self, _sample_rate: u32, _ts_rate: f64, _frame_ts: FTs, _margin_ts: FTs) {}
+        fn add_step(&mut self, channel: usize, timestamp: FTs, delta: f32) {
+            self.0.push((channel, timestamp, delta));
+        }
+        fn end_frame(&mut self, _timestamp: FTs) -> usize { 0 }
+    }
+
+    struct TestAmps;
+    impl AmpLevels<f32> for TestAmps {
+        fn amp_level(level: u32) -> f32 { level as f32 }
+    }
+
+    #[test]
+    fn ym_2149_env_works() {
+        let mut ay = Ay3_891xAudio::default();
+        assert_eq!(ay.chip_model(), AyChipModel::Ay3_891x);
+        ay.set_chip_model(AyChipModel::Ym2149);
+        assert_eq!(ay.chip_model(), AyChipModel::Ym2149);
+        ay.update_register(AyRegister::EnvPerFine, 0);
+        ay.update_register(AyRegister::EnvShape, ENV_SHAPE_ATTACK_MASK);
+        for exp_level in 0..=31 {
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), exp_level);
+        }
+        for _ in 0..100 {
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), 0);
+        }
+        ay.update_register(AyRegister::EnvShape, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK);
+        for _ in 0..10 {
+            for exp_level in (0..=31).rev() {
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), exp_level);
+            }
+            for exp_level in 0..=31 {
+                assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), exp_level);
+            }
+        }
+        ay.update_register(AyRegister::EnvShape, ENV_SHAPE_CONT_MASK|ENV_SHAPE_ALT_MASK|ENV_SHAPE_HOLD_MASK);
+        for exp_level in (0..=31).rev() {
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), exp_level);
+        }
+        for _ in 0..100 {
+            assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), 31);
+            assert_eq!(ay.get_envelope_level(), 15);
+        }
+        ay.set_chip_model(AyChipModel::Ay3_891x);
+        assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK), 15);
+        ay.set_chip_model(AyChipModel::Ym2149);
+        assert_eq!(ay.env_control.update_level(ENV_LEVEL_MASK_YM), 31);
+        ay.set_ym_clock_divider(true);
+        ay.reset();
+        assert_eq!(ay.chip_model(), AyChipModel::Ym2149);
+        assert!(ay.ym_clock_divider());
+        assert_eq!(ay.get_envelope_level(), 0);
+    }
+
+    #[test]
+    fn ym_2149_render_works() {
+        let changes = [AyRegChange { time: 0, reg: AyRegister::MixerControl, val: 0x3F },
+                       AyRegChange { time: 70, reg: AyRegister::AmpLevelA, val: 15 },
+                       AyRegChange { time: 70, reg: AyRegister::AmpLevelB, val: 7 }];
+        let mut blep = TestBlep::default();
+        let mut ay = Ay3_891xAudio::default();
+        ay.render_audio::<TestAmps,_,_>(changes.iter().copied(), &mut blep, 1000, 1000, [0, 1, 2]);
+        assert_eq!(blep.0, [(0, 96, 15.0), (1, 96, 7.0)]);
+
+        blep.0.clear();
+        let mut ay = Ay3_891xAudio::default();
+        ay.set_chip_model(AyChipModel::Ym2149);
+        ay.render_audio::<YmAmps<f32>,_,_>(changes.iter().copied(), &mut blep, 1000, 1000, [0, 1, 2]);
+        assert_eq!(blep.0, [(0, 96, YM_AMPS[31]), (1, 96, YM_AMPS[15])]);
+        assert_eq!(ay.get_amp_levels(), [15, 7, 0]);
+
+        blep.0.clear();
+        let mut ay = Ay3_891xAudio::default();
+        ay.set_chip_model(AyChipModel::Ym2149);
+        ay.set_ym_clock_divider(true);
+        ay.render_audio::<YmAmps<f32>,_,_>(changes.iter().copied(), &mut blep, 1000, 1000, [0, 1, 2]);
+        assert_eq!(blep.0, [(0, 128, YM_AMPS[31]), (1, 128, YM_AMPS[15])]);
+        assert_eq!(ay.current_ts, 1024 - 1000);
+        assert_eq!(YmAmps::<f32>::amp_level(31), 1.0);
+        assert_eq!(YmAmps::<i16>::amp_level(31), i16::MAX);
+        assert_eq!(YmAmps::<i32>::amp_level(1), 0);
+        assert_eq!(AyAmps::<f32>::amp_level(15), 1.0);
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn ym_2149_serde_works() {
+        let mut ay = Ay3_891xAudio::default();
+        ay.set_chip_model(AyChipModel::Ym2149);
+        ay.set_ym_clock_divider(true);
+        let json = serde_json::to_string(&ay).unwrap();
+        let ay1: Ay3_891xAudio = serde_json::from_str(&json).unwrap();
+        assert_eq!(ay1.chip_model(), AyChipModel::Ym2149);
+        assert!(ay1.ym_clock_divider());
+        let mut value = serde_json::to_value(&ay).unwrap();
+        let map = value.as_object_mut().unwrap();
+        map.remove("chipModel").unwrap();
+        map.remove("ymClockDivider").unwrap();
+        let ay1: Ay3_891xAudio = serde_json::from_value(value).unwrap();
+        assert_eq!(ay1.chip_model(), AyChipModel::Ay3_891x);
+        assert!(!ay1.ym_clock_divider());
+    }
+
+    #[test]
+    fn ay_stereo_pan_works() {
+        assert_eq!(AyStereoPan::default(), AyStereoPan::Abc);
+        assert_eq!(AyStereoPan::Acb.positions(), [0, 100, 50]);
+        assert_eq!(AyStereoPan::Custom([10, 200, 90]).positions(), [10, 100, 90]);
+        let [a, b, c] = AyStereoPan::Abc.weights::<f32>();
+        assert_eq!(a, (1.0, 0.0));
+        assert!((b.0 - core::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((b.1 - core::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert_eq!(c, (0.0, 1.0));
+        assert_eq!(AyStereoPan::Bac.weights::<i16>(), [(23169, 23169), (i16::MAX, 0), (0, i16::MAX)]);
+        for pos in 0..=100 {
+            let [(l, r), ..] = AyStereoPan::Custom([pos, 0, 0]).weights::<f32>();
+            assert!((l*l + r*r - 1.0).abs() < 1e-6);
+        }
+
+        let changes = [AyRegChange { time: 0, reg: AyRegister::MixerControl, val: 0x3F },
+                       AyRegChange { time: 0, reg: AyRegister::AmpLevelA, val: 15 },
+                       AyRegChange { time: 0, reg: AyRegister::AmpLevelB, val: 15 }];
+        let mut blep = BlepStereoPan::new(AyStereoPan::Custom([25, 100, 50]).weights::<f32>().to_vec(),
+                                          TestBlep::default());
+        let mut ay = Ay3_891xAudio::default();
+        ay.render_audio::<TestAmps,_,_>(changes.iter().copied(), &mut blep, 100, 100, [0, 1, 2]);
+        let (l, r) = pan_weights::<f32>(25);
+        assert_eq!(blep.blep.0, [(0, 0, 15.0 * l), (1, 0, 15.0 * r), (0, 0, 0.0), (1, 0, 15.0)]);
+    }
 }
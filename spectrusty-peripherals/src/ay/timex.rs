@@ -0,0 +1,89 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::joystick::{JoystickDevice, fuller::FullerJoystickDevice};
+
+use super::AyIoPort;
+
+const LEFT_JOY_SELECT:  u16 = 0x0100;
+const RIGHT_JOY_SELECT: u16 = 0x0200;
+
+/// The bridge between Timex TC2068 / TS2068 [AY-3-8912][crate::ay::Ay3_891xIo] I/O port `A` and
+/// the two built-in joystick ports.
+///
+/// The joysticks are being read from the AY-3-8912 I/O port `A` register, the joystick is selected
+/// by the address line `A8` for the left and `A9` for the right joystick. If both lines are high
+/// the state of both joysticks is combined.
+///
+/// Bits of an I/O port `A` register have the following meaning (0 - active, 1 - inactive):
+///
+/// ```text
+///     Bit   7   6   5   4   3   2   1   0
+///         +-------------------------------+
+///         | F |   |   |   | R | L | D | U |
+///         +-------------------------------+
+/// ```
+///
+/// This is the same layout as of the Fuller joystick, thus by default the [FullerJoystickDevice]
+/// is being used to emulate both of the joysticks.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct TimexJoystickPorts<T, J1=FullerJoystickDevice, J2=FullerJoystickDevice> {
+    /// The [JoystickDevice] connected to the left joystick port.
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    pub joystick1: J1,
+    /// The [JoystickDevice] connected to the right joystick port.
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    pub joystick2: J2,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    _ts: PhantomData<T>
+}
+
+impl<T, J1, J2> AyIoPort for TimexJoystickPorts<T, J1, J2>
+    where J1: JoystickDevice,
+          J2: JoystickDevice,
+          T: Debug
+{
+    type Timestamp = T;
+
+    #[inline]
+    fn ay_io_read(&mut self, addr: u16, _timestamp: Self::Timestamp) -> u8 {
+        let mut data = u8::MAX;
+        if addr & LEFT_JOY_SELECT != 0 {
+            data &= self.joystick1.port_read(addr);
+        }
+        if addr & RIGHT_JOY_SELECT != 0 {
+            data &= self.joystick2.port_read(addr);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::joystick::JoystickInterface;
+    use super::*;
+
+    #[test]
+    fn timex_joystick_ports_work() {
+        let mut ports = TimexJoystickPorts::<(), FullerJoystickDevice, FullerJoystickDevice>::default();
+        assert_eq!(ports.ay_io_read(0x03f6, ()), 0xff);
+        ports.joystick1.fire(0, true);
+        ports.joystick2.direction(crate::joystick::JoyDirection::UpRight);
+        assert_eq!(ports.ay_io_read(0x00f6, ()), 0xff);
+        assert_eq!(ports.ay_io_read(0x01f6, ()), 0b0111_1111);
+        assert_eq!(ports.ay_io_read(0x02f6, ()), 0b1111_0110);
+        assert_eq!(ports.ay_io_read(0x03f6, ()), 0b0111_0110);
+    }
+}
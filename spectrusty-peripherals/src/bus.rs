@@ -7,9 +7,15 @@
 */
 //! System bus device emulators to be used with [ControlUnit][spectrusty_core::chip::ControlUnit]s.
 pub mod ay;
+pub mod beta128;
+pub mod dac;
 pub mod debug;
 pub mod joystick;
+pub mod mdos;
+pub mod mgt;
 pub mod mouse;
 pub mod parallel;
+pub mod pio8255;
+pub mod plus3disk;
 pub mod zxinterface1;
 pub mod zxprinter;
@@ -11,6 +11,8 @@ use core::num::NonZeroU16;
 use core::marker::PhantomData;
 
 pub mod serial128;
+pub mod timex;
+pub mod turbosound;
 #[cfg(feature = "snapshot")] mod serde;
 #[cfg(feature = "snapshot")] use ::serde::Serialize;
 
@@ -26,9 +28,11 @@ use spectrusty_core::{
 pub use crate::ay::{
     audio::Ay3_891xAudio,
     Ay3_8910Io, Ay3_8912Io, Ay3_8913Io, AyIoPort, AyIoNullPort, AyRegister,
-    AyPortDecode, Ay128kPortDecode, AyFullerBoxPortDecode
+    AyPortDecode, Ay128kPortDecode, AyFullerBoxPortDecode, AyTC2068PortDecode
 };
 
+pub use turbosound::{TurboSoundBusDevice, Ay3_891xTurboSound};
+
 /// Implement this empty trait for [BusDevice] so methods from [AyAudioBusDevice]
 /// will get auto-implemented to pass method calls to the downstream devices.
 pub trait PassByAyAudioBusDevice {}
@@ -134,9 +138,9 @@ impl<T> AyAudioBusDevice for dyn NamedBusDevice<T>
 {
     /// # Note
     /// Because we need to guess the concrete type of the dynamic `BusDevice` we can currently handle
-    /// only the most common cases: [Ay3_891xMelodik] and [Ay3_891xFullerBox]. If you use a customized
-    /// [Ay3_891xBusDevice] for a dynamic `BusDevice` you need to render audio directly on the device
-    /// downcasted to your custom type.
+    /// only the most common cases: [Ay3_891xMelodik], [Ay3_891xFullerBox] and [Ay3_891xTurboSound].
+    /// If you use a customized [Ay3_891xBusDevice] for a dynamic `BusDevice` you need to render audio
+    /// directly on the device downcasted to your custom type.
     #[inline]
     fn render_ay_audio<L, B>(&mut self, blep: &mut B, end_ts: T, frame_tstates: FTs, chans: [usize; 3])
         where L: AmpLevels<B::SampleDelta>,
@@ -148,6 +152,9 @@ impl<T> AyAudioBusDevice for dyn NamedBusDevice<T>
         else if let Some(ay_dev) = self.downcast_mut::<Ay3_891xFullerBox<NullDevice<T>>>() {
             ay_dev.render_ay_audio::<L, B>(blep, end_ts, frame_tstates, chans)
         }
+        else if let Some(ay_dev) = self.downcast_mut::<Ay3_891xTurboSound<NullDevice<T>>>() {
+            ay_dev.render_ay_audio::<L, B>(blep, end_ts, frame_tstates, chans)
+        }
     }
 }
 
@@ -0,0 +1,52 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A custom [Ay3_891xBusDevice] type for Timex TC2068 / TS2068 with [TimexJoystickPorts].
+use core::fmt;
+use spectrusty_core::bus::BusDevice;
+use crate::ay::{AyIoNullPort, AyTC2068PortDecode};
+pub use crate::ay::timex::TimexJoystickPorts;
+use super::Ay3_891xBusDevice;
+
+/// This type implements a [BusDevice][spectrusty_core::bus::BusDevice] emulating the AY-3-8912 built into
+/// Timex TC2068 / TS2068 with two [joystick ports][TimexJoystickPorts].
+pub type Ay3_8912Timex<D> = Ay3_891xBusDevice<
+                                                AyTC2068PortDecode,
+                                                TimexJoystickPorts<<D as BusDevice>::Timestamp>,
+                                                AyIoNullPort<<D as BusDevice>::Timestamp>, D>;
+
+impl<D: BusDevice> fmt::Display for Ay3_8912Timex<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AY-3-8912 (Timex)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::{bus::NullDevice, clock::FTs};
+    use crate::ay::AyRegister;
+    use crate::joystick::{JoystickInterface, JoyDirection};
+    use super::*;
+
+    #[test]
+    fn ay_timex_works() {
+        let mut ay = Ay3_8912Timex::<NullDevice<FTs>>::default();
+        assert_eq!(ay.to_string(), "AY-3-8912 (Timex)");
+        assert_eq!(ay.write_io(0x00F5, AyRegister::IoA as u8, 0), Some(0));
+        assert_eq!(ay.read_io(0x03F6, 10), Some((0xFF, None)));
+        ay.ay_io.port_a.joystick1.direction(JoyDirection::Left);
+        ay.ay_io.port_a.joystick2.fire(0, true);
+        assert_eq!(ay.read_io(0x00F6, 20), Some((0xFF, None)));
+        assert_eq!(ay.read_io(0x01F6, 30), Some((0b1111_1011, None)));
+        assert_eq!(ay.read_io(0x02F6, 40), Some((0b0111_1111, None)));
+        assert_eq!(ay.read_io(0x03F6, 50), Some((0b0111_1011, None)));
+        // the joystick ports are only decoded on the lowest 8 address lines
+        assert_eq!(ay.read_io(0x01FD, 60), None);
+        assert_eq!(ay.write_io(0xFFFD, 0, 70), None);
+        assert_eq!(ay.ay_io.selected_register(), AyRegister::IoA);
+    }
+}
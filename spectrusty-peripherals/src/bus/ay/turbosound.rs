@@ -0,0 +1,257 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A *TurboSound* interface with two `AY-3-8912` programmable sound generators.
+use core::fmt::{self, Debug};
+use core::num::NonZeroU16;
+use core::marker::PhantomData;
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    audio::{Blep, AmpLevels},
+    bus::BusDevice,
+    clock::FTs
+};
+
+use crate::ay::{
+    audio::Ay3_891xAudio,
+    Ay3_8913Io, AyPortDecode, Ay128kPortDecode
+};
+use super::AyAudioBusDevice;
+
+/// The value written to the register selection port which selects the first chip.
+pub const TURBO_SOUND_SELECT_CHIP0: u8 = 0xFF;
+/// The value written to the register selection port which selects the second chip.
+pub const TURBO_SOUND_SELECT_CHIP1: u8 = 0xFE;
+
+/// A convenient [TurboSoundBusDevice] type emulating a *TurboSound* interface with the
+/// `ZX Spectrum 128k` port configuration.
+pub type Ay3_891xTurboSound<D> = TurboSoundBusDevice<Ay128kPortDecode, D>;
+
+/// *TurboSound* interface with two `AY-3-8912` programmable sound generators as a [BusDevice].
+///
+/// Both chips share the same I/O ports, decoded by `P`. Writing `0xFF` to the register selection port
+/// makes the first chip active and writing `0xFE` makes the second chip active. All other values written
+/// to this port and all data port accesses are being directed to the currently active chip.
+/// The first chip is active after reset.
+///
+/// By default, the audio of both chips is rendered into the same [Blep] channels, which are provided to
+/// [AyAudioBusDevice::render_ay_audio]. To render the second chip into separate channels, set
+/// [TurboSoundBusDevice::chip1_channels] to the target channels.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "snapshot", serde(bound(deserialize = "D: Deserialize<'de> + Default, D::Timestamp: Default")))]
+pub struct TurboSoundBusDevice<P, D: BusDevice> {
+    /// Provides direct access to the sound generators.
+    pub ay_sound: [Ay3_891xAudio; 2],
+    /// Provides direct access to the I/O ports of both chips.
+    pub ay_io: [Ay3_8913Io<D::Timestamp>; 2],
+    /// Target [Blep] audio channels for `[A, B, C]` channels of the second chip.
+    ///
+    /// If `None` the second chip is mixed into the same channels as the first one.
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    pub chip1_channels: Option<[usize; 3]>,
+    selected_chip: u8,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    _port_decode: PhantomData<P>
+}
+
+impl<D: BusDevice> fmt::Display for Ay3_891xTurboSound<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("2 x AY-3-8912 (TurboSound)")
+    }
+}
+
+impl<P, D: BusDevice> TurboSoundBusDevice<P, D> {
+    /// Returns the index of the currently active chip: `0` or `1`.
+    #[inline]
+    pub fn selected_chip(&self) -> usize {
+        (self.selected_chip & 1) as usize
+    }
+    /// Makes the chip with the given `index` active: `0` or `1`.
+    ///
+    /// # Panics
+    /// Panics if `index` is larger than 1.
+    #[inline]
+    pub fn select_chip(&mut self, index: usize) {
+        assert!(index < 2, "TurboSound chip index out of range");
+        self.selected_chip = index as u8;
+    }
+}
+
+impl<P, D> AyAudioBusDevice for TurboSoundBusDevice<P, D>
+    where <Self as BusDevice>::Timestamp: Into<FTs>,
+          Self: BusDevice<Timestamp=D::Timestamp>,
+          D: BusDevice
+{
+    /// # Note
+    /// The first chip is rendered into `chans` and the second chip into
+    /// [TurboSoundBusDevice::chip1_channels] or into `chans` if the former is `None`.
+    #[inline]
+    fn render_ay_audio<L, E>(&mut self, blep: &mut E, end_ts: <Self as BusDevice>::Timestamp, frame_tstates: FTs, chans: [usize; 3])
+        where E: Blep,
+              L: AmpLevels<E::SampleDelta>
+    {
+        let end_ts = end_ts.into();
+        let chip1_chans = self.chip1_channels.unwrap_or(chans);
+        for ((ay_sound, ay_io), chans) in self.ay_sound.iter_mut()
+                                          .zip(self.ay_io.iter_mut())
+                                          .zip([chans, chip1_chans].iter().copied())
+        {
+            let changes = ay_io.recorder.drain_ay_reg_changes();
+            ay_sound.render_audio::<L,_,_>(changes, blep, end_ts, frame_tstates, chans)
+        }
+    }
+}
+
+impl<P, D> BusDevice for TurboSoundBusDevice<P, D>
+    where P: AyPortDecode,
+          D: BusDevice,
+          D::Timestamp: Debug + Copy
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        for (ay_sound, ay_io) in self.ay_sound.iter_mut().zip(self.ay_io.iter_mut()) {
+            ay_sound.reset();
+            ay_io.reset(timestamp);
+        }
+        self.selected_chip = 0;
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> Option<(u8, Option<NonZeroU16>)> {
+        if P::is_data_read(port) {
+            let ay_io = &mut self.ay_io[self.selected_chip()];
+            return Some((ay_io.data_port_read(port, timestamp), None))
+        }
+        self.bus.read_io(port, timestamp)
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if P::is_select(port) {
+            match data {
+                TURBO_SOUND_SELECT_CHIP0 => self.selected_chip = 0,
+                TURBO_SOUND_SELECT_CHIP1 => self.selected_chip = 1,
+                data => self.ay_io[self.selected_chip()].select_port_write(data)
+            }
+            return Some(0)
+        }
+        if P::is_data_write(port) {
+            self.ay_io[self.selected_chip()].data_port_write(port, data, timestamp);
+            return Some(0)
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+
+    #[inline]
+    fn next_frame(&mut self, timestamp: Self::Timestamp) {
+        // see Ay3_891xBusDevice::next_frame
+        for (ay_sound, ay_io) in self.ay_sound.iter_mut().zip(self.ay_io.iter_mut()) {
+            if !ay_io.recorder.is_empty() {
+                for (reg, val) in ay_io.iter_sound_gen_regs() {
+                    ay_sound.update_register(reg, val);
+                }
+            }
+            ay_io.next_frame(timestamp);
+        }
+        self.bus.next_frame(timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::bus::NullDevice;
+    use crate::ay::AyRegister;
+    use super::*;
+
+    #[derive(Default)]
+    struct TestBlep(Vec<(usize, FTs, f32)>);
+
+    impl Blep for TestBlep {
+        type SampleDelta = f32;
+        fn ensure_frame_time(&mut self, _sample_rate: u32, _ts_rate: f64, _frame_ts: FTs, _margin_ts: FTs) {}
+        fn add_step(&mut self, channel: usize, timestamp: FTs, delta: f32) {
+            self.0.push((channel, timestamp, delta));
+        }
+        fn end_frame(&mut self, _timestamp: FTs) -> usize { 0 }
+    }
+
+    struct TestAmps;
+    impl AmpLevels<f32> for TestAmps {
+        fn amp_level(level: u32) -> f32 { level as f32 }
+    }
+
+    #[test]
+    fn turbo_sound_works() {
+        let mut ts = Ay3_891xTurboSound::<NullDevice<FTs>>::default();
+        assert_eq!(ts.selected_chip(), 0);
+        for &chip in [TURBO_SOUND_SELECT_CHIP1, TURBO_SOUND_SELECT_CHIP0].iter() {
+            assert_eq!(ts.write_io(0xFFFD, chip, 0), Some(0));
+            assert_eq!(ts.write_io(0xFFFD, AyRegister::MixerControl as u8, 0), Some(0));
+            assert_eq!(ts.write_io(0xBFFD, 0x3F, 0), Some(0));
+        }
+        assert_eq!(ts.selected_chip(), 0);
+        assert_eq!(ts.write_io(0xFFFD, AyRegister::AmpLevelA as u8, 0), Some(0));
+        assert_eq!(ts.write_io(0xBFFD, 12, 10), Some(0));
+        assert_eq!(ts.write_io(0xFFFD, TURBO_SOUND_SELECT_CHIP1, 20), Some(0));
+        assert_eq!(ts.selected_chip(), 1);
+        assert_eq!(ts.ay_io[0].selected_register(), AyRegister::AmpLevelA);
+        assert_eq!(ts.write_io(0xFFFD, AyRegister::AmpLevelB as u8, 30), Some(0));
+        assert_eq!(ts.write_io(0xBFFD, 7, 40), Some(0));
+        assert_eq!(ts.read_io(0xFFFD, 50), Some((7, None)));
+        assert_eq!(ts.write_io(0xFFFD, TURBO_SOUND_SELECT_CHIP0, 60), Some(0));
+        assert_eq!(ts.read_io(0xFFFD, 70), Some((12, None)));
+        assert_eq!(ts.ay_io[0].get(AyRegister::AmpLevelB), 0);
+        assert_eq!(ts.ay_io[1].get(AyRegister::AmpLevelA), 0);
+        assert_eq!(ts.ay_io[1].selected_register(), AyRegister::AmpLevelB);
+        assert_eq!(ts.write_io(0x00FE, 0, 80), None);
+        assert_eq!(ts.read_io(0x00FE, 80), None);
+
+        let mut blep = TestBlep::default();
+        let mut ts1 = ts.clone();
+        ts.render_ay_audio::<TestAmps, _>(&mut blep, 1000, 1000, [0, 1, 2]);
+        let mut steps = blep.0.clone();
+        steps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(steps, [(0, 32, 12.0), (1, 64, 7.0)]);
+        assert!(ts.ay_io.iter().all(|ay_io| ay_io.recorder.is_empty()));
+
+        blep.0.clear();
+        ts1.chip1_channels = Some([3, 4, 5]);
+        ts1.render_ay_audio::<TestAmps, _>(&mut blep, 1000, 1000, [0, 1, 2]);
+        let mut steps = blep.0.clone();
+        steps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(steps, [(0, 32, 12.0), (4, 64, 7.0)]);
+
+        ts.reset(0);
+        assert_eq!(ts.selected_chip(), 0);
+    }
+}
@@ -0,0 +1,272 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A bus device for the **Beta 128** disk interface.
+/*!
+The interface decodes only address lines `A0`, `A1`, `A5`, `A6` and `A7` and its ports are only
+accessible while the TR-DOS ROM is paged in. See [Beta128BusDevice::set_paged_in_flag].
+
+### I/O Port **0x1f**.
+
+Writes the command register and reads the status register of the WD1793 floppy disk controller.
+
+### I/O Port **0x3f**.
+
+Reads and writes the track register of the WD1793 floppy disk controller.
+
+### I/O Port **0x5f**.
+
+Reads and writes the sector register of the WD1793 floppy disk controller.
+
+### I/O Port **0x7f**.
+
+Reads and writes the data register of the WD1793 floppy disk controller.
+
+### I/O Port **0xff**.
+
+The system register of the interface:
+```text
+       Bit    7     6     5   4     3    2     1     0
+            +-------------------------------------------+
+        READ|intrq| drq |   |    |    |     |          |
+            |-----+-----+---+----+----+-----+----------|
+       WRITE|     |dens.|   |side|hlt |reset|drive sel.|
+            +-------------------------------------------+
+```
+*side* `0` selects the second side of the disk (head 1). *reset* `0` resets the controller.
+!*/
+use core::num::NonZeroU16;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    bus::{BusDevice, PortAddress}
+};
+
+use super::ay::PassByAyAudioBusDevice;
+
+pub use crate::storage::wd1793::*;
+pub use crate::memory::{Beta128MemExt, PagedInFlag};
+
+impl<D> fmt::Display for Beta128BusDevice<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Beta 128 Disk Interface")
+    }
+}
+
+/// Connects the [Wd1793] floppy disk controller emulator as a [BusDevice] via Beta 128 interface ports.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Beta128BusDevice<D> {
+    /// Provides direct access to the [Wd1793].
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    pub fdc: Wd1793,
+    sys_reg: Beta128SysReg,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    paged_in: Option<PagedInFlag>,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D
+}
+
+bitflags! {
+    #[derive(Default)]
+    #[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+    struct Beta128SysReg: u8 {
+        const DRIVE_MASK = 0b0000_0011;
+        const NO_RESET   = 0b0000_0100;
+        const HALT       = 0b0000_1000;
+        const SIDE_0     = 0b0001_0000;
+        const DENSITY    = 0b0100_0000;
+    }
+}
+
+const SYS_INTRQ: u8 = 0b1000_0000;
+const SYS_DRQ:   u8 = 0b0100_0000;
+const SYS_MASK:  u8 = 0b0011_1111;
+
+#[derive(Clone, Copy, Default, Debug)]
+struct Beta128FdcPortAddress;
+impl PortAddress for Beta128FdcPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1000_0011;
+    const ADDRESS_BITS: u16 = 0b0000_0000_0001_1111;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct Beta128SysPortAddress;
+impl PortAddress for Beta128SysPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1000_0011;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1111_1111;
+}
+
+impl<D> Beta128BusDevice<D> {
+    /// Shares the TR-DOS ROM paging state with the device.
+    ///
+    /// When the `flag` is provided the interface ports are only accessible while the flag is set,
+    /// otherwise the ports are always accessible.
+    ///
+    /// Pass the flag obtained from [Beta128MemExt::paged_in_flag] of the memory extension that pages
+    /// the TR-DOS ROM in and out. The link is not being serialized, so it should be re-established
+    /// after the device is deserialized.
+    pub fn set_paged_in_flag(&mut self, flag: Option<PagedInFlag>) {
+        self.paged_in = flag;
+    }
+    /// Returns a reference to the TR-DOS ROM paging state flag if the device has one.
+    pub fn paged_in_flag(&self) -> Option<&PagedInFlag> {
+        self.paged_in.as_ref()
+    }
+    /// Returns `true` if the interface ports are accessible.
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.paged_in.as_ref().map(PagedInFlag::get).unwrap_or(true)
+    }
+
+    fn write_sys_reg(&mut self, data: u8) {
+        let sys_reg = Beta128SysReg::from_bits_truncate(data);
+        if !sys_reg.intersects(Beta128SysReg::NO_RESET) {
+            self.fdc.reset();
+        }
+        self.fdc.select_drive((sys_reg & Beta128SysReg::DRIVE_MASK).bits());
+        self.fdc.select_side(!sys_reg.intersects(Beta128SysReg::SIDE_0) as u8);
+        self.sys_reg = sys_reg;
+    }
+}
+
+impl<D> Deref for Beta128BusDevice<D> {
+    type Target = Wd1793;
+    fn deref(&self) -> &Self::Target {
+        &self.fdc
+    }
+}
+
+impl<D> DerefMut for Beta128BusDevice<D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fdc
+    }
+}
+
+impl<D> PassByAyAudioBusDevice for Beta128BusDevice<D> {}
+
+impl<D> BusDevice for Beta128BusDevice<D>
+    where D: BusDevice
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        self.write_sys_reg(0);
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> Option<(u8, Option<NonZeroU16>)> {
+        if self.is_active() {
+            if Beta128SysPortAddress::match_port(port) {
+                let mut data = SYS_MASK;
+                if self.fdc.is_intrq() {
+                    data |= SYS_INTRQ;
+                }
+                if self.fdc.is_drq() {
+                    data |= SYS_DRQ;
+                }
+                return Some((data, None))
+            }
+            else if Beta128FdcPortAddress::match_port(port) {
+                let data = match port & 0x60 {
+                    0x00 => self.fdc.read_status(),
+                    0x20 => self.fdc.read_track_reg(),
+                    0x40 => self.fdc.read_sector_reg(),
+                    _    => self.fdc.read_data()
+                };
+                return Some((data, None))
+            }
+        }
+        self.bus.read_io(port, timestamp)
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if self.is_active() {
+            if Beta128SysPortAddress::match_port(port) {
+                self.write_sys_reg(data);
+                return Some(0)
+            }
+            else if Beta128FdcPortAddress::match_port(port) {
+                match port & 0x60 {
+                    0x00 => self.fdc.write_command(data),
+                    0x20 => self.fdc.write_track_reg(data),
+                    0x40 => self.fdc.write_sector_reg(data),
+                    _    => self.fdc.write_data(data)
+                }
+                return Some(0)
+            }
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::bus::NullDevice;
+    use super::*;
+
+    #[test]
+    fn beta128_bus_device_works() {
+        let mut bus = Beta128BusDevice::<NullDevice<()>>::default();
+        assert!(bus.is_active());
+        bus.insert_disk(1, FloppyDisk::new_formatted(80, 2, 16, 1, 1, 0));
+        bus.reset(());
+        assert_eq!(bus.read_io(0x00ff, ()), Some((0xBF, None)));
+        assert_eq!(bus.read_io(0x001f, ()), Some((STATUS_NOT_READY|STATUS_TRACK0, None)));
+        assert_eq!(bus.write_io(0x00ff, 0x3D, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 1);
+        assert_eq!(bus.side(), 0);
+        assert_eq!(bus.read_io(0x00ff, ()), Some((0x3F, None)));
+        assert_eq!(bus.write_io(0x005f, 9, ()), Some(0));
+        assert_eq!(bus.write_io(0x001f, 0x80, ()), Some(0));
+        assert_eq!(bus.read_io(0x00ff, ()), Some((0x7F, None)));
+        assert_eq!(bus.read_io(0x001f, ()), Some((STATUS_BUSY|STATUS_DRQ, None)));
+        for _ in 0..256 {
+            assert_eq!(bus.read_io(0x007f, ()), Some((0, None)));
+        }
+        assert_eq!(bus.read_io(0x00ff, ()), Some((0xBF, None)));
+        assert_eq!(bus.read_io(0x003f, ()), Some((0, None)));
+        assert_eq!(bus.read_io(0x005f, ()), Some((9, None)));
+        assert_eq!(bus.write_io(0x00ff, 0x2D, ()), Some(0));
+        assert_eq!(bus.side(), 1);
+
+        let memext = Beta128MemExt::default();
+        bus.set_paged_in_flag(Some(memext.paged_in_flag().clone()));
+        assert!(!bus.is_active());
+        assert_eq!(bus.read_io(0x001f, ()), None);
+        assert_eq!(bus.write_io(0x00ff, 0x3C, ()), None);
+        memext.paged_in_flag().set(true);
+        assert!(bus.is_active());
+        assert_eq!(bus.read_io(0x00ff, ()), Some((0xBF, None)));
+        assert_eq!(bus.read_io(0xfe7e, ()), None);
+        assert_eq!(bus.read_io(0x7ffd, ()), None);
+    }
+}
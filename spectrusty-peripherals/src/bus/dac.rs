@@ -0,0 +1,290 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! 8-bit digital to analog converter sound devices: *Covox*, *SpecDrum* and *Soundrive*.
+use core::fmt::{self, Debug};
+use core::marker::PhantomData;
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    audio::{Blep, AmpLevels, SampleDelta},
+    bus::BusDevice,
+    clock::FTs
+};
+
+use super::ay::PassByAyAudioBusDevice;
+
+/// The maximum number of channels supported by [DacBusDevice].
+pub const MAX_DAC_CHANNELS: usize = 4;
+
+/// A convenient *Covox* [DacBusDevice] type with a single channel at port `0xFB`.
+pub type Covox<D> = DacBusDevice<CovoxPortDecode, D>;
+/// A convenient *SpecDrum* [DacBusDevice] type with a single channel at port `0xDF`.
+pub type SpecDrum<D> = DacBusDevice<SpecDrumPortDecode, D>;
+/// A convenient *Soundrive* [DacBusDevice] type with four channels at ports `0x0F`, `0x1F`, `0x4F` and `0x5F`.
+pub type Soundrive<D> = DacBusDevice<SoundrivePortDecode, D>;
+
+impl<D: BusDevice> fmt::Display for Covox<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Covox")
+    }
+}
+
+impl<D: BusDevice> fmt::Display for SpecDrum<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpecDrum")
+    }
+}
+
+impl<D: BusDevice> fmt::Display for Soundrive<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Soundrive")
+    }
+}
+
+/// A trait for interfacing devices to render square-wave audio pulses from the 8-bit DAC outputs.
+pub trait DacAudioFrame<B: Blep> {
+    /// Renders the DAC outputs of the last frame as square-wave pulses via [Blep] interface.
+    ///
+    /// Provide [AmpLevels] that can handle `level` values from 0 to 255 (8-bits).
+    /// `channels` - target [Blep] audio channels for each of the DAC channels. DAC channels without
+    /// the corresponding target channel are not being rendered.
+    fn render_dac_audio_frame<V: AmpLevels<B::SampleDelta>>(&self, blep: &mut B, channels: &[usize]);
+}
+
+/// A helper trait for matching I/O port addresses of the DAC channels.
+pub trait DacPortDecode: Debug {
+    /// The number of DAC channels, should not exceed [MAX_DAC_CHANNELS].
+    const CHANNELS: usize;
+    /// Returns the index of the DAC channel if `port` matches the channel's port address.
+    fn match_channel(port: u16) -> Option<usize>;
+}
+
+/// Matches the I/O port address of the *Covox* DAC, as found in *Pentagon* clones.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CovoxPortDecode;
+impl DacPortDecode for CovoxPortDecode {
+    const CHANNELS: usize = 1;
+    #[inline]
+    fn match_channel(port: u16) -> Option<usize> {
+        if port & 0xff == 0xfb { Some(0) } else { None }
+    }
+}
+
+/// Matches the I/O port address of the *Cheetah SpecDrum* DAC.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SpecDrumPortDecode;
+impl DacPortDecode for SpecDrumPortDecode {
+    const CHANNELS: usize = 1;
+    #[inline]
+    fn match_channel(port: u16) -> Option<usize> {
+        if port & 0xff == 0xdf { Some(0) } else { None }
+    }
+}
+
+/// Matches the I/O port addresses of the *Soundrive* DACs.
+///
+/// The channels `0` and `1` (ports `0x0F` and `0x1F`) belong to the left stereo channel,
+/// the channels `2` and `3` (ports `0x4F` and `0x5F`) belong to the right stereo channel.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SoundrivePortDecode;
+impl DacPortDecode for SoundrivePortDecode {
+    const CHANNELS: usize = 4;
+    #[inline]
+    fn match_channel(port: u16) -> Option<usize> {
+        match port & 0xff {
+            0x0f => Some(0),
+            0x1f => Some(1),
+            0x4f => Some(2),
+            0x5f => Some(3),
+            _ => None
+        }
+    }
+}
+
+/// A linear 8-bit DAC [AmpLevels] implementation.
+pub struct DacAmps<T>(PhantomData<T>);
+
+impl AmpLevels<f32> for DacAmps<f32> {
+    #[inline(always)]
+    fn amp_level(level: u32) -> f32 {
+        (level & 0xff) as f32 / 255.0
+    }
+}
+
+impl AmpLevels<i16> for DacAmps<i16> {
+    #[inline(always)]
+    fn amp_level(level: u32) -> i16 {
+        ((level & 0xff) * i16::MAX as u32 / 255) as i16
+    }
+}
+
+impl AmpLevels<i32> for DacAmps<i32> {
+    #[inline(always)]
+    fn amp_level(level: u32) -> i32 {
+        ((level & 0xff) as u64 * i32::MAX as u64 / 255) as i32
+    }
+}
+
+/// 8-bit digital to analog converters as a [BusDevice].
+///
+/// Records timestamped values written to the DAC ports decoded by `P`, which can be rendered
+/// as audio pulses with [DacAudioFrame].
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "snapshot", serde(bound(deserialize = "D: Deserialize<'de> + Default")))]
+pub struct DacBusDevice<P, D: BusDevice> {
+    /// The current output levels of the DAC channels.
+    levels: [u8; MAX_DAC_CHANNELS],
+    /// The output levels of the DAC channels at the beginning of the current frame.
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    frame_levels: [u8; MAX_DAC_CHANNELS],
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    changes: Vec<(D::Timestamp, u8, u8)>,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    _port_decode: PhantomData<P>
+}
+
+impl<P: DacPortDecode, D: BusDevice> DacBusDevice<P, D> {
+    /// Returns the current output levels of the DAC channels.
+    pub fn levels(&self) -> &[u8] {
+        &self.levels[..P::CHANNELS]
+    }
+}
+
+impl<P, D: BusDevice> PassByAyAudioBusDevice for DacBusDevice<P, D> {}
+
+impl<B, P, D> DacAudioFrame<B> for DacBusDevice<P, D>
+    where B: Blep,
+          P: DacPortDecode,
+          D: BusDevice,
+          D::Timestamp: Into<FTs> + Copy
+{
+    fn render_dac_audio_frame<V: AmpLevels<B::SampleDelta>>(&self, blep: &mut B, channels: &[usize]) {
+        for (index, (&channel, &level)) in channels.iter().zip(self.frame_levels.iter())
+                                                   .enumerate().take(P::CHANNELS) {
+            let mut last_vol = V::amp_level(level.into());
+            for &(ts, _, level) in self.changes.iter().filter(|&&(_, ch, _)| usize::from(ch) == index) {
+                let next_vol = V::amp_level(level.into());
+                if let Some(delta) = last_vol.sample_delta(next_vol) {
+                    blep.add_step(channel, ts.into(), delta);
+                    last_vol = next_vol;
+                }
+            }
+        }
+    }
+}
+
+impl<P, D> BusDevice for DacBusDevice<P, D>
+    where P: DacPortDecode,
+          D: BusDevice,
+          D::Timestamp: Debug + Copy
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        self.levels = Default::default();
+        self.frame_levels = Default::default();
+        self.changes.clear();
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if let Some(channel) = P::match_channel(port) {
+            self.levels[channel] = data;
+            self.changes.push((timestamp, channel as u8, data));
+            return Some(0)
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+
+    #[inline]
+    fn next_frame(&mut self, timestamp: Self::Timestamp) {
+        self.frame_levels = self.levels;
+        self.changes.clear();
+        self.bus.next_frame(timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{TestAmps, TestBlep};
+    use spectrusty_core::bus::NullDevice;
+    use super::*;
+
+    #[test]
+    fn dac_works() {
+        let mut covox = Covox::<NullDevice<FTs>>::default();
+        assert_eq!(covox.write_io(0x00fb, 0x80, 10), Some(0));
+        assert_eq!(covox.write_io(0x12fb, 0x80, 20), Some(0));
+        assert_eq!(covox.write_io(0x00df, 0x40, 30), None);
+        assert_eq!(covox.write_io(0x00fb, 0x40, 40), Some(0));
+        assert_eq!(covox.levels(), [0x40]);
+        let mut blep = TestBlep::default();
+        covox.render_dac_audio_frame::<TestAmps>(&mut blep, &[2]);
+        assert_eq!(blep.0, [(2, 10, 128.0), (2, 40, -64.0)]);
+        covox.next_frame(70000);
+        blep.0.clear();
+        covox.render_dac_audio_frame::<TestAmps>(&mut blep, &[2]);
+        assert!(blep.0.is_empty());
+        assert_eq!(covox.write_io(0x00fb, 0x50, 5), Some(0));
+        covox.render_dac_audio_frame::<TestAmps>(&mut blep, &[]);
+        assert!(blep.0.is_empty());
+        covox.render_dac_audio_frame::<TestAmps>(&mut blep, &[0]);
+        assert_eq!(blep.0, [(0, 5, 16.0)]);
+
+        let mut specdrum = SpecDrum::<NullDevice<FTs>>::default();
+        assert_eq!(specdrum.write_io(0x00fb, 0x80, 10), None);
+        assert_eq!(specdrum.write_io(0xffdf, 0x80, 10), Some(0));
+        assert_eq!(specdrum.levels(), [0x80]);
+
+        let mut soundrive = Soundrive::<NullDevice<FTs>>::default();
+        for (i, &port) in [0x0f, 0x1f, 0x4f, 0x5f].iter().enumerate() {
+            assert_eq!(soundrive.write_io(port, i as u8 + 1, i as FTs), Some(0));
+        }
+        assert_eq!(soundrive.write_io(0x00fb, 0xff, 10), None);
+        assert_eq!(soundrive.levels(), [1, 2, 3, 4]);
+        let mut blep = TestBlep::default();
+        soundrive.render_dac_audio_frame::<TestAmps>(&mut blep, &[0, 0, 1, 1]);
+        assert_eq!(blep.0, [(0, 0, 1.0), (0, 1, 2.0), (1, 2, 3.0), (1, 3, 4.0)]);
+        soundrive.next_frame(70000);
+        soundrive.reset(100);
+        assert_eq!(soundrive.levels(), [0, 0, 0, 0]);
+        assert_eq!(soundrive.write_io(0x1f, 8, 200), Some(0));
+        blep.0.clear();
+        soundrive.render_dac_audio_frame::<TestAmps>(&mut blep, &[0, 0, 1, 1]);
+        assert_eq!(blep.0, [(0, 200, 8.0)]);
+
+        assert_eq!(DacAmps::<f32>::amp_level(255), 1.0);
+        assert_eq!(DacAmps::<i16>::amp_level(255), i16::MAX);
+        assert_eq!(DacAmps::<i32>::amp_level(255), i32::MAX);
+        assert_eq!(DacAmps::<i16>::amp_level(0), 0);
+    }
+}
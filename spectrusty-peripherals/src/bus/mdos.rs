@@ -0,0 +1,247 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A bus device for the **Didaktik MDOS** disk interfaces (D40, D80 and the one built into Didaktik Kompakt).
+/*!
+The interface is built around the WD2797 floppy disk controller, emulated by [Wd1793] in the
+[WD1793][WdModel::Wd1793] mode, and has 14kb of ROM and 2kb of RAM that are paged in by the
+[MdosMemExt] memory extension. The paging flag must be shared between the bus device and the
+memory extension, see [MdosBusDevice::set_paged_in_flag].
+
+The SNAP button of the interface triggers a non-maskable interrupt and can be emulated by
+calling [ControlUnit::nmi][spectrusty_core::chip::ControlUnit::nmi].
+
+The interface's ports are decoded only on the address lines `A0` to `A7`.
+
+### I/O Ports **0x81**, **0x83**, **0x85**, **0x87**.
+
+Read and write the status/command, track, sector and data registers of the floppy disk controller.
+
+### I/O Port **0x89**.
+
+The control register (write only):
+```text
+       Bit   7   6   5   4   3    2      1       0
+            +-----------------------------------------+
+       WRITE|   |   |   |   |   |side|drive B|drive A|
+            +-----------------------------------------+
+```
+
+### I/O Port **0x91**.
+
+Reading from this port pages in the interface memory and writing to it pages the memory out.
+!*/
+use core::num::NonZeroU16;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    bus::{BusDevice, PortAddress}
+};
+
+use super::ay::PassByAyAudioBusDevice;
+
+pub use crate::storage::wd1793::*;
+pub use crate::memory::{MdosMemExt, PagedInFlag};
+
+impl<D> fmt::Display for MdosBusDevice<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Didaktik MDOS Disk Interface")
+    }
+}
+
+/// Connects the [Wd1793] floppy disk controller emulator as a [BusDevice] via the Didaktik MDOS
+/// interface ports.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct MdosBusDevice<D> {
+    /// Provides direct access to the [Wd1793].
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    pub fdc: Wd1793,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    paged_in: PagedInFlag,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D
+}
+
+const MDOS_CTRL_DRIVE_A: u8 = 0b0000_0001;
+const MDOS_CTRL_DRIVE_B: u8 = 0b0000_0010;
+const MDOS_CTRL_SIDE:    u8 = 0b0000_0100;
+
+#[derive(Clone, Copy, Default, Debug)]
+struct MdosFdcPortAddress;
+impl PortAddress for MdosFdcPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1001;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1000_0001;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct MdosCtrlPortAddress;
+impl PortAddress for MdosCtrlPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1000_1001;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct MdosMemPortAddress;
+impl PortAddress for MdosMemPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1001_0001;
+}
+
+impl<D> MdosBusDevice<D> {
+    /// Shares the interface memory paging flag with the device.
+    ///
+    /// Pass the flag obtained from [MdosMemExt::paged_in_flag] of the memory extension. The link is not
+    /// being serialized, so it should be re-established after the device is deserialized.
+    pub fn set_paged_in_flag(&mut self, flag: PagedInFlag) {
+        self.paged_in = flag;
+    }
+    /// Returns a reference to the interface memory paging flag.
+    pub fn paged_in_flag(&self) -> &PagedInFlag {
+        &self.paged_in
+    }
+
+    fn write_control(&mut self, data: u8) {
+        if data & MDOS_CTRL_DRIVE_A != 0 {
+            self.fdc.select_drive(0);
+        }
+        else if data & MDOS_CTRL_DRIVE_B != 0 {
+            self.fdc.select_drive(1);
+        }
+        self.fdc.select_side((data & MDOS_CTRL_SIDE != 0) as u8);
+    }
+}
+
+impl<D> Deref for MdosBusDevice<D> {
+    type Target = Wd1793;
+    fn deref(&self) -> &Self::Target {
+        &self.fdc
+    }
+}
+
+impl<D> DerefMut for MdosBusDevice<D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fdc
+    }
+}
+
+impl<D> PassByAyAudioBusDevice for MdosBusDevice<D> {}
+
+impl<D> BusDevice for MdosBusDevice<D>
+    where D: BusDevice
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        self.fdc.reset();
+        self.paged_in.set(false);
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> Option<(u8, Option<NonZeroU16>)> {
+        if MdosFdcPortAddress::match_port(port) {
+            let data = match (port >> 1) & 3 {
+                0 => self.fdc.read_status(),
+                1 => self.fdc.read_track_reg(),
+                2 => self.fdc.read_sector_reg(),
+                _ => self.fdc.read_data()
+            };
+            return Some((data, None))
+        }
+        else if MdosMemPortAddress::match_port(port) {
+            self.paged_in.set(true);
+            return Some((u8::MAX, None))
+        }
+        self.bus.read_io(port, timestamp)
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if MdosFdcPortAddress::match_port(port) {
+            match (port >> 1) & 3 {
+                0 => self.fdc.write_command(data),
+                1 => self.fdc.write_track_reg(data),
+                2 => self.fdc.write_sector_reg(data),
+                _ => self.fdc.write_data(data)
+            }
+            return Some(0)
+        }
+        else if MdosCtrlPortAddress::match_port(port) {
+            self.write_control(data);
+            return Some(0)
+        }
+        else if MdosMemPortAddress::match_port(port) {
+            self.paged_in.set(false);
+            return Some(0)
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::bus::NullDevice;
+    use super::*;
+
+    #[test]
+    fn mdos_bus_device_works() {
+        let mut bus = MdosBusDevice::<NullDevice<()>>::default();
+        assert_eq!(bus.model(), WdModel::Wd1793);
+        let memext = MdosMemExt::default();
+        bus.set_paged_in_flag(memext.paged_in_flag().clone());
+        assert!(bus.paged_in_flag().is_shared_with(memext.paged_in_flag()));
+        bus.insert_disk(1, FloppyDisk::new_formatted(80, 2, 9, 1, 2, 0));
+        bus.reset(());
+        assert_eq!(bus.write_io(0x0089, 0b0000_0110, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 1);
+        assert_eq!(bus.side(), 1);
+        assert_eq!(bus.write_io(0x0089, 0b0000_0001, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 0);
+        assert_eq!(bus.side(), 0);
+        assert_eq!(bus.write_io(0x0089, 0b0000_0010, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 1);
+        assert_eq!(bus.write_io(0x0085, 9, ()), Some(0));
+        assert_eq!(bus.read_io(0x0085, ()), Some((9, None)));
+        assert_eq!(bus.write_io(0x0083, 5, ()), Some(0));
+        assert_eq!(bus.read_io(0x0083, ()), Some((5, None)));
+        assert_eq!(bus.write_io(0x0087, 0xAA, ()), Some(0));
+        assert_eq!(bus.read_io(0x0087, ()), Some((0xAA, None)));
+
+        assert!(!memext.paged_in_flag().get());
+        assert_eq!(bus.read_io(0x0091, ()), Some((0xff, None)));
+        assert!(memext.paged_in_flag().get());
+        assert_eq!(bus.write_io(0x0091, 0, ()), Some(0));
+        assert!(!memext.paged_in_flag().get());
+        assert_eq!(bus.read_io(0x0089, ()), None);
+        assert_eq!(bus.read_io(0x0093, ()), None);
+        assert_eq!(bus.read_io(0x00fe, ()), None);
+        assert_eq!(bus.to_string(), "Didaktik MDOS Disk Interface");
+    }
+}
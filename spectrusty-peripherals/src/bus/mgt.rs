@@ -0,0 +1,404 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! Bus devices for the **MGT +D** and **DISCiPLE** disk interfaces.
+/*!
+Both interfaces are built around the WD1772 floppy disk controller and have 8kb of ROM and 8kb of RAM
+that are paged in by the [MgtMemExt] memory extension. The paging state must be shared between
+the bus device and the memory extension, see [MgtBusDevice::set_paging_state].
+
+The snapshot button of the interfaces triggers a non-maskable interrupt and can be emulated by
+calling [ControlUnit::nmi][spectrusty_core::chip::ControlUnit::nmi].
+
+The printer, joystick and network ports of the interfaces are not emulated.
+
+## +D
+
+### I/O Ports **0xe3**, **0xeb**, **0xf3**, **0xfb**.
+
+Read and write the status/command, track, sector and data registers of the WD1772 floppy disk controller.
+
+### I/O Port **0xef**.
+
+The control register (write only):
+```text
+       Bit    7     6     5   4   3   2    1      0
+            +-------------------------------------------+
+       WRITE|side|strobe|   |   |   |   |drive 2|drive 1|
+            +-------------------------------------------+
+```
+
+### I/O Port **0xe7**.
+
+Reading from this port pages in the interface memory and writing to it pages the memory out.
+
+## DISCiPLE
+
+### I/O Ports **0x1b**, **0x5b**, **0x9b**, **0xdb**.
+
+Read and write the status/command, track, sector and data registers of the WD1772 floppy disk controller.
+
+### I/O Port **0x1f**.
+
+The control register (write only):
+```text
+       Bit    7     6      5     4    3     2    1     0
+            +------------------------------------------------+
+       WRITE|net.|strobe|exit|inhib.|rom|dens.|side|drive 2|
+            +------------------------------------------------+
+```
+Reading from this port is forwarded to the next device, so it can be handled by a Kempston joystick.
+
+### I/O Port **0x7b**.
+
+Reading from this port maps the interface ROM at `0x0000` and RAM at `0x2000`, writing to it
+swaps the ROM and RAM.
+
+### I/O Port **0xbb**.
+
+Reading from this port pages in the interface memory and writing to it pages the memory out.
+!*/
+use core::num::NonZeroU16;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    bus::{BusDevice, PortAddress}
+};
+
+use super::ay::PassByAyAudioBusDevice;
+
+pub use crate::storage::wd1793::*;
+pub use crate::memory::{
+    MgtMemExt, PlusDMemExt, DiscipleMemExt, MgtInterface, MgtModel, MgtPaging, MgtPagingState,
+    PlusD, Disciple
+};
+
+/// The MGT +D disk interface [BusDevice].
+pub type PlusDBusDevice<D> = MgtBusDevice<PlusD, D>;
+/// The MGT DISCiPLE disk interface [BusDevice].
+pub type DiscipleBusDevice<D> = MgtBusDevice<Disciple, D>;
+
+impl<M: MgtInterface, D> fmt::Display for MgtBusDevice<M, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match M::MODEL {
+            MgtModel::PlusD => "MGT +D Disk Interface",
+            MgtModel::Disciple => "MGT DISCiPLE Disk Interface"
+        })
+    }
+}
+
+/// Connects the [Wd1793] floppy disk controller emulator, in the [WD1772][WdModel::Wd1772] mode, as
+/// a [BusDevice] via the MGT +D or DISCiPLE interface ports.
+///
+/// Use one of the [PlusDBusDevice] or [DiscipleBusDevice] types.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct MgtBusDevice<M, D> {
+    /// Provides direct access to the [Wd1793].
+    #[cfg_attr(feature = "snapshot", serde(default = "wd1772_default"))]
+    pub fdc: Wd1793,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    paging: MgtPagingState,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    _model: PhantomData<M>
+}
+
+const PLUSD_CTRL_DRIVE_MASK: u8 = 0b0000_0011;
+const PLUSD_CTRL_SIDE:       u8 = 0b1000_0000;
+
+const DISCIPLE_CTRL_DRIVE:   u8 = 0b0000_0001;
+const DISCIPLE_CTRL_SIDE:    u8 = 0b0000_0010;
+
+#[derive(Clone, Copy, Default, Debug)]
+struct PlusDFdcPortAddress;
+impl PortAddress for PlusDFdcPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1110_0111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1110_0011;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct PlusDCtrlPortAddress;
+impl PortAddress for PlusDCtrlPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1110_1111;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct PlusDMemPortAddress;
+impl PortAddress for PlusDMemPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1110_0111;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct DiscipleFdcPortAddress;
+impl PortAddress for DiscipleFdcPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_0011_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_0001_1011;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct DiscipleCtrlPortAddress;
+impl PortAddress for DiscipleCtrlPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_0001_1111;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct DiscipleBootPortAddress;
+impl PortAddress for DiscipleBootPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_0111_1011;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct DiscipleMemPortAddress;
+impl PortAddress for DiscipleMemPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1111_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_1011_1011;
+}
+
+impl<M, D: Default> Default for MgtBusDevice<M, D> {
+    fn default() -> Self {
+        MgtBusDevice {
+            fdc: Wd1793::new(WdModel::Wd1772),
+            paging: MgtPagingState::default(),
+            bus: D::default(),
+            _model: PhantomData
+        }
+    }
+}
+
+impl<M, D> MgtBusDevice<M, D> {
+    /// Shares the interface memory paging state with the device.
+    ///
+    /// Pass the state obtained from [MgtMemExt::paging_state] of the memory extension. The link is not
+    /// being serialized, so it should be re-established after the device is deserialized.
+    pub fn set_paging_state(&mut self, paging: MgtPagingState) {
+        self.paging = paging;
+    }
+    /// Returns a reference to the interface memory paging state.
+    pub fn paging_state(&self) -> &MgtPagingState {
+        &self.paging
+    }
+}
+
+impl<M: MgtInterface, D> MgtBusDevice<M, D> {
+    fn fdc_register(port: u16) -> Option<u8> {
+        match M::MODEL {
+            MgtModel::PlusD if PlusDFdcPortAddress::match_port(port) => Some((port >> 3) as u8 & 3),
+            MgtModel::Disciple if DiscipleFdcPortAddress::match_port(port) => Some((port >> 6) as u8 & 3),
+            _ => None
+        }
+    }
+
+    fn is_mem_port(port: u16) -> bool {
+        match M::MODEL {
+            MgtModel::PlusD => PlusDMemPortAddress::match_port(port),
+            MgtModel::Disciple => DiscipleMemPortAddress::match_port(port)
+        }
+    }
+
+    fn write_control(&mut self, port: u16, data: u8) -> bool {
+        match M::MODEL {
+            MgtModel::PlusD if PlusDCtrlPortAddress::match_port(port) => {
+                match data & PLUSD_CTRL_DRIVE_MASK {
+                    1 => self.fdc.select_drive(0),
+                    2 => self.fdc.select_drive(1),
+                    _ => {}
+                }
+                self.fdc.select_side((data & PLUSD_CTRL_SIDE != 0) as u8);
+                true
+            }
+            MgtModel::Disciple if DiscipleCtrlPortAddress::match_port(port) => {
+                self.fdc.select_drive((data & DISCIPLE_CTRL_DRIVE != 0) as u8);
+                self.fdc.select_side((data & DISCIPLE_CTRL_SIDE != 0) as u8);
+                true
+            }
+            _ => false
+        }
+    }
+}
+
+impl<M, D> Deref for MgtBusDevice<M, D> {
+    type Target = Wd1793;
+    fn deref(&self) -> &Self::Target {
+        &self.fdc
+    }
+}
+
+impl<M, D> DerefMut for MgtBusDevice<M, D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fdc
+    }
+}
+
+impl<M, D> PassByAyAudioBusDevice for MgtBusDevice<M, D> {}
+
+impl<M, D> BusDevice for MgtBusDevice<M, D>
+    where M: MgtInterface,
+          D: BusDevice
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        self.fdc.reset();
+        self.paging.set(MgtPaging::empty());
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> Option<(u8, Option<NonZeroU16>)> {
+        if let Some(reg) = Self::fdc_register(port) {
+            let data = match reg {
+                0 => self.fdc.read_status(),
+                1 => self.fdc.read_track_reg(),
+                2 => self.fdc.read_sector_reg(),
+                _ => self.fdc.read_data()
+            };
+            return Some((data, None))
+        }
+        else if Self::is_mem_port(port) {
+            self.paging.set_flags(MgtPaging::PAGED_IN, true);
+            return Some((u8::MAX, None))
+        }
+        else if M::MODEL == MgtModel::Disciple && DiscipleBootPortAddress::match_port(port) {
+            self.paging.set_flags(MgtPaging::SWAPPED, false);
+            return Some((u8::MAX, None))
+        }
+        self.bus.read_io(port, timestamp)
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if let Some(reg) = Self::fdc_register(port) {
+            match reg {
+                0 => self.fdc.write_command(data),
+                1 => self.fdc.write_track_reg(data),
+                2 => self.fdc.write_sector_reg(data),
+                _ => self.fdc.write_data(data)
+            }
+            return Some(0)
+        }
+        else if self.write_control(port, data) {
+            return Some(0)
+        }
+        else if Self::is_mem_port(port) {
+            self.paging.set_flags(MgtPaging::PAGED_IN, false);
+            return Some(0)
+        }
+        else if M::MODEL == MgtModel::Disciple && DiscipleBootPortAddress::match_port(port) {
+            self.paging.set_flags(MgtPaging::SWAPPED, true);
+            return Some(0)
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+}
+
+#[cfg(feature = "snapshot")]
+fn wd1772_default() -> Wd1793 {
+    Wd1793::new(WdModel::Wd1772)
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::bus::NullDevice;
+    use super::*;
+
+    #[test]
+    fn plus_d_bus_device_works() {
+        let mut bus = PlusDBusDevice::<NullDevice<()>>::default();
+        assert_eq!(bus.model(), WdModel::Wd1772);
+        let memext = PlusDMemExt::default();
+        bus.set_paging_state(memext.paging_state().clone());
+        assert!(bus.paging_state().is_shared_with(memext.paging_state()));
+        bus.insert_disk(1, FloppyDisk::new_formatted(80, 2, 10, 1, 2, 0));
+        bus.reset(());
+        assert_eq!(bus.read_io(0x00e3, ()), Some((STATUS_SPIN_UP|STATUS_TRACK0, None)));
+        assert_eq!(bus.write_io(0x00ef, 0x82, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 1);
+        assert_eq!(bus.side(), 1);
+        assert_eq!(bus.write_io(0x00ef, 0x00, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 1);
+        assert_eq!(bus.side(), 0);
+        assert_eq!(bus.write_io(0x00f3, 10, ()), Some(0));
+        assert_eq!(bus.read_io(0x00f3, ()), Some((10, None)));
+        assert_eq!(bus.write_io(0x00e3, 0x80, ()), Some(0));
+        assert_eq!(bus.read_io(0x00e3, ()), Some((STATUS_MOTOR_ON|STATUS_BUSY|STATUS_DRQ, None)));
+        for _ in 0..512 {
+            assert_eq!(bus.read_io(0x00fb, ()), Some((0, None)));
+        }
+        assert!(bus.is_intrq());
+        assert_eq!(bus.read_io(0x00eb, ()), Some((0, None)));
+
+        assert!(!memext.is_paged_in());
+        assert_eq!(bus.read_io(0x00e7, ()), Some((0xff, None)));
+        assert!(memext.is_paged_in());
+        assert_eq!(bus.write_io(0x00e7, 0, ()), Some(0));
+        assert!(!memext.is_paged_in());
+        assert_eq!(bus.read_io(0x001b, ()), None);
+        assert_eq!(bus.read_io(0x00bb, ()), None);
+        assert_eq!(bus.write_io(0x007b, 0, ()), None);
+        assert_eq!(bus.read_io(0x00fe, ()), None);
+        assert_eq!(bus.to_string(), "MGT +D Disk Interface");
+    }
+
+    #[test]
+    fn disciple_bus_device_works() {
+        let mut bus = DiscipleBusDevice::<NullDevice<()>>::default();
+        let memext = DiscipleMemExt::default();
+        bus.set_paging_state(memext.paging_state().clone());
+        bus.reset(());
+        assert_eq!(bus.read_io(0x001b, ()), Some((STATUS_SPIN_UP|STATUS_TRACK0, None)));
+        assert_eq!(bus.write_io(0x001f, 0x03, ()), Some(0));
+        assert_eq!(bus.selected_drive(), 1);
+        assert_eq!(bus.side(), 1);
+        assert_eq!(bus.read_io(0x001f, ()), None);
+        assert_eq!(bus.write_io(0x005b, 7, ()), Some(0));
+        assert_eq!(bus.read_io(0x005b, ()), Some((7, None)));
+        assert_eq!(bus.write_io(0x009b, 3, ()), Some(0));
+        assert_eq!(bus.read_io(0x009b, ()), Some((3, None)));
+        assert_eq!(bus.write_io(0x00db, 5, ()), Some(0));
+        assert_eq!(bus.read_io(0x00db, ()), Some((5, None)));
+
+        assert_eq!(bus.read_io(0x00bb, ()), Some((0xff, None)));
+        assert_eq!(memext.paging_state().get(), MgtPaging::PAGED_IN);
+        assert_eq!(bus.write_io(0x007b, 0, ()), Some(0));
+        assert_eq!(memext.paging_state().get(), MgtPaging::PAGED_IN|MgtPaging::SWAPPED);
+        assert_eq!(bus.read_io(0x007b, ()), Some((0xff, None)));
+        assert_eq!(memext.paging_state().get(), MgtPaging::PAGED_IN);
+        assert_eq!(bus.write_io(0x00bb, 0, ()), Some(0));
+        assert_eq!(memext.paging_state().get(), MgtPaging::empty());
+        assert_eq!(bus.read_io(0x00e7, ()), None);
+        assert_eq!(bus.to_string(), "MGT DISCiPLE Disk Interface");
+    }
+}
@@ -0,0 +1,182 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A bus device for the Intel 8255 parallel I/O interface as found in the Didaktik models.
+/*!
+The device decodes only address lines `A0` to `A4` and `A7`, the address lines `A5` and `A6` select
+the register of the [Pio8255].
+
+### I/O Port **0x1f**.
+
+Reads and writes the port **A** of the [Pio8255].
+
+### I/O Port **0x3f**.
+
+Reads and writes the port **B** of the [Pio8255].
+
+### I/O Port **0x5f**.
+
+Reads and writes the port **C** of the [Pio8255].
+
+### I/O Port **0x7f**.
+
+Writes the control register of the [Pio8255].
+
+Reading the port **A** configured as input is also forwarded to the next device and the data is combined
+with the [Pio8255] input lines with the bitwise AND. This way the Kempston joystick connected to
+the port **A** of the Didaktik models can be emulated by attaching the
+[KempstonJoystick][crate::bus::joystick::KempstonJoystick] as the next device.
+!*/
+use core::num::NonZeroU16;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    bus::{BusDevice, PortAddress}
+};
+
+use super::ay::PassByAyAudioBusDevice;
+
+pub use crate::pio8255::*;
+
+impl<D> fmt::Display for Pio8255BusDevice<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("8255 PIO")
+    }
+}
+
+/// Connects the [Pio8255] emulator as a [BusDevice] via Didaktik's ports.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Pio8255BusDevice<D> {
+    /// Provides direct access to the [Pio8255].
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    pub pio: Pio8255,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D
+}
+
+/// The Didaktik's 8255 PIO [PortAddress].
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DidaktikPioPortAddress;
+impl PortAddress for DidaktikPioPortAddress {
+    const ADDRESS_MASK: u16 = 0b0000_0000_1001_1111;
+    const ADDRESS_BITS: u16 = 0b0000_0000_0001_1111;
+}
+
+impl DidaktikPioPortAddress {
+    /// Returns the index of the [Pio8255] register selected by the `port` address.
+    #[inline]
+    pub fn register(port: u16) -> u8 {
+        (port >> 5) as u8 & 3
+    }
+}
+
+impl<D> Deref for Pio8255BusDevice<D> {
+    type Target = Pio8255;
+    fn deref(&self) -> &Self::Target {
+        &self.pio
+    }
+}
+
+impl<D> DerefMut for Pio8255BusDevice<D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.pio
+    }
+}
+
+impl<D> PassByAyAudioBusDevice for Pio8255BusDevice<D> {}
+
+impl<D> BusDevice for Pio8255BusDevice<D>
+    where D: BusDevice
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        self.pio.reset();
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> Option<(u8, Option<NonZeroU16>)> {
+        if DidaktikPioPortAddress::match_port(port) {
+            let reg = DidaktikPioPortAddress::register(port);
+            let data = self.pio.read(reg);
+            let mask = self.pio.input_mask(PioPort::A);
+            if reg == 0 && mask != 0 {
+                if let Some((bus_data, ws)) = self.bus.read_io(port, timestamp) {
+                    return Some((data & (bus_data | !mask), ws))
+                }
+            }
+            return Some((data, None))
+        }
+        self.bus.read_io(port, timestamp)
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if DidaktikPioPortAddress::match_port(port) {
+            self.pio.write(DidaktikPioPortAddress::register(port), data);
+            return Some(0)
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::bus::NullDevice;
+    use crate::bus::joystick::{KempstonJoystick, JoystickInterface};
+    use super::*;
+
+    #[test]
+    fn pio8255_bus_device_works() {
+        let mut bus = Pio8255BusDevice::<KempstonJoystick<NullDevice<()>>>::default();
+        assert_eq!(bus.to_string(), "8255 PIO");
+        bus.reset(());
+        assert_eq!(bus.read_io(0x001f, ()), Some((0, None)));
+        bus.next_device_mut().fire(0, true);
+        assert_eq!(bus.read_io(0x001f, ()), Some((0b0001_0000, None)));
+        bus.set_input(PioPort::A, 0x0F);
+        assert_eq!(bus.read_io(0x001f, ()), Some((0, None)));
+        bus.set_input(PioPort::B, 0xA5);
+        assert_eq!(bus.read_io(0x003f, ()), Some((0xA5, None)));
+        assert_eq!(bus.write_io(0x007f, 0b1000_1001, ()), Some(0));
+        assert_eq!(bus.write_io(0x001f, 0x12, ()), Some(0));
+        assert_eq!(bus.read_io(0x001f, ()), Some((0x12, None)));
+        assert_eq!(bus.write_io(0x007f, 0b0000_0001, ()), Some(0));
+        assert_eq!(bus.read_io(0x005f, ()), Some((0xFF, None)));
+        assert_eq!(bus.write_io(0x007f, 0b1000_0000, ()), Some(0));
+        assert_eq!(bus.write_io(0x007f, 0b0000_0101, ()), Some(0));
+        assert_eq!(bus.read_io(0x005f, ()), Some((0b0000_0100, None)));
+        assert_eq!(bus.output(PioPort::C), 0b0000_0100);
+        assert_eq!(bus.read_io(0x007f, ()), Some((0xFF, None)));
+        assert_eq!(bus.read_io(0x00ff, ()), None);
+        assert_eq!(bus.write_io(0x00fe, 0, ()), None);
+    }
+}
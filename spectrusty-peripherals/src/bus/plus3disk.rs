@@ -0,0 +1,168 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! ZX Spectrum +3 floppy disk controller bus device.
+/*!
+
+### I/O Port **0x2ffd**.
+
+Reads the main status register of the µPD765 floppy disk controller.
+
+### I/O Port **0x3ffd**.
+
+Reads and writes the data register of the µPD765 floppy disk controller.
+
+### I/O Port **0x1ffd**.
+
+Bit 3 of the value written to this port switches the disk drive motor on or off. Writes are
+forwarded to the next device, so the port can also be handled by the [Ula3][spectrusty_core::chip].
+!*/
+use core::num::NonZeroU16;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use spectrusty_core::{
+    bus::{BusDevice, PortAddress},
+};
+use super::ay::PassByAyAudioBusDevice;
+
+pub use crate::storage::fdc765::*;
+
+impl<D> fmt::Display for Plus3DiskBusDevice<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("+3 Floppy Disk Controller")
+    }
+}
+
+/// Connects the [Fdc765] floppy disk controller emulator as a [BusDevice] via +3 disk ports.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct Plus3DiskBusDevice<D> {
+    /// Provides direct access to the [Fdc765].
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    pub fdc: Fdc765,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    bus: D
+}
+
+const MOTOR_ON: u8 = 0b0000_1000;
+
+#[derive(Clone, Copy, Default, Debug)]
+struct Ula3CtrlPortAddress;
+impl PortAddress for Ula3CtrlPortAddress {
+    const ADDRESS_MASK: u16 = 0b1111_0000_0000_0010;
+    const ADDRESS_BITS: u16 = 0b0001_1111_1111_1101;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct FdcStatusPortAddress;
+impl PortAddress for FdcStatusPortAddress {
+    const ADDRESS_MASK: u16 = 0b1111_0000_0000_0010;
+    const ADDRESS_BITS: u16 = 0b0010_1111_1111_1101;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct FdcDataPortAddress;
+impl PortAddress for FdcDataPortAddress {
+    const ADDRESS_MASK: u16 = 0b1111_0000_0000_0010;
+    const ADDRESS_BITS: u16 = 0b0011_1111_1111_1101;
+}
+
+impl<D> Deref for Plus3DiskBusDevice<D> {
+    type Target = Fdc765;
+    fn deref(&self) -> &Self::Target {
+        &self.fdc
+    }
+}
+
+impl<D> DerefMut for Plus3DiskBusDevice<D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fdc
+    }
+}
+
+impl<D> PassByAyAudioBusDevice for Plus3DiskBusDevice<D> {}
+
+impl<D> BusDevice for Plus3DiskBusDevice<D>
+    where D: BusDevice
+{
+    type Timestamp = D::Timestamp;
+    type NextDevice = D;
+
+    #[inline]
+    fn next_device_mut(&mut self) -> &mut Self::NextDevice {
+        &mut self.bus
+    }
+
+    #[inline]
+    fn next_device_ref(&self) -> &Self::NextDevice {
+        &self.bus
+    }
+
+    #[inline]
+    fn into_next_device(self) -> Self::NextDevice {
+        self.bus
+    }
+
+    #[inline]
+    fn reset(&mut self, timestamp: Self::Timestamp) {
+        self.fdc.reset();
+        self.fdc.set_motor(false);
+        self.bus.reset(timestamp);
+    }
+
+    #[inline]
+    fn read_io(&mut self, port: u16, timestamp: Self::Timestamp) -> Option<(u8, Option<NonZeroU16>)> {
+        if FdcStatusPortAddress::match_port(port) {
+            return Some((self.fdc.read_status(), None))
+        }
+        else if FdcDataPortAddress::match_port(port) {
+            return Some((self.fdc.read_data(), None))
+        }
+        self.bus.read_io(port, timestamp)
+    }
+
+    #[inline]
+    fn write_io(&mut self, port: u16, data: u8, timestamp: Self::Timestamp) -> Option<u16> {
+        if FdcDataPortAddress::match_port(port) {
+            self.fdc.write_data(data);
+            return Some(0)
+        }
+        else if Ula3CtrlPortAddress::match_port(port) {
+            self.fdc.set_motor(data & MOTOR_ON != 0);
+        }
+        self.bus.write_io(port, data, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty_core::bus::NullDevice;
+    use super::*;
+
+    #[test]
+    fn plus3_disk_bus_device_works() {
+        let mut bus = Plus3DiskBusDevice::<NullDevice<()>>::default();
+        bus.insert_disk(0, FloppyDisk::new_formatted(40, 1, 9, 1, 2, 0xE5));
+        assert_eq!(bus.read_io(0x2ffd, ()), Some((MSR_RQM, None)));
+        assert_eq!(bus.write_io(0x1ffd, 0x08, ()), None);
+        assert!(bus.is_motor_on());
+        assert_eq!(bus.write_io(0x3ffd, 0x04, ()), Some(0));
+        assert_eq!(bus.write_io(0x3ffd, 0x00, ()), Some(0));
+        assert_eq!(bus.read_io(0x2ffd, ()), Some((MSR_RQM|MSR_DIO|MSR_CB, None)));
+        assert_eq!(bus.read_io(0x3ffd, ()), Some((0x30, None)));
+        assert_eq!(bus.read_io(0x2ffd, ()), Some((MSR_RQM, None)));
+        assert_eq!(bus.read_io(0x1ffd, ()), None);
+        bus.write_io(0x1ffd, 0x00, ());
+        assert!(!bus.is_motor_on());
+        bus.reset(());
+        assert!(bus.disk_ref(0).is_some());
+    }
+}
@@ -29,6 +29,10 @@ pub mod memory;
 pub mod mouse;
 pub mod network;
 pub mod parallel;
+pub mod pio8255;
 pub mod serial;
 pub mod storage;
 pub mod zxprinter;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -1,11 +1,17 @@
-/*
-    Copyright (C) 2020  Rafal Michalski
-
-    This file is part of SPECTRUSTY, a Rust library for building emulators.
-
-    For the full copyright notice, see the lib.rs file.
-*/
-//! Memory extensions.
-mod zxinterface1;
-
-pub use zxinterface1::*;
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! Memory extensions.
+mod beta128;
+mod mdos;
+mod mgt;
+mod zxinterface1;
+
+pub use beta128::*;
+pub use mdos::*;
+pub use mgt::*;
+pub use zxinterface1::*;
@@ -0,0 +1,219 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::cell::Cell;
+use std::rc::Rc;
+use std::io::{self, Read};
+
+use spectrusty_core::memory::{
+    MemoryExtension, MemoryKind, ExRom, ZxMemory, ZxMemoryError
+};
+#[cfg(feature = "snapshot")]
+use spectrusty_core::memory::serde::{serialize_mem, deserialize_mem};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+/// A flag indicating if the extension ROM is paged in, shared between a memory [extension][MemoryExtension]
+/// and a bus device.
+///
+/// Cloning the flag creates a new handle to the same shared state.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(from = "bool", into = "bool"))]
+pub struct PagedInFlag(Rc<Cell<bool>>);
+
+impl From<bool> for PagedInFlag {
+    fn from(paged_in: bool) -> Self {
+        PagedInFlag(Rc::new(Cell::new(paged_in)))
+    }
+}
+
+impl From<PagedInFlag> for bool {
+    fn from(flag: PagedInFlag) -> Self {
+        flag.get()
+    }
+}
+
+impl PagedInFlag {
+    /// Returns `true` if the extension ROM is paged in.
+    #[inline]
+    pub fn get(&self) -> bool {
+        self.0.get()
+    }
+    /// Changes the state of the flag.
+    #[inline]
+    pub fn set(&self, paged_in: bool) {
+        self.0.set(paged_in)
+    }
+    /// Returns `true` if both handles refer to the same shared state.
+    #[inline]
+    pub fn is_shared_with(&self, other: &PagedInFlag) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The Beta 128 disk interface memory [extension][MemoryExtension].
+///
+/// The TR-DOS ROM is paged in if the processor fetches an instruction from addresses `0x3D00` to `0x3DFF`
+/// while the 48k BASIC ROM (the last ROM bank) is paged in. It is paged out when the processor fetches
+/// an instruction from RAM at address `0x4000` or above.
+///
+/// The I/O ports of the Beta 128 interface are only accessible while the TR-DOS ROM is paged in.
+/// Use [Beta128MemExt::paged_in_flag] to share the paging state with the
+/// [Beta128BusDevice][crate::bus::beta128::Beta128BusDevice].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct Beta128MemExt {
+    #[cfg_attr(feature = "snapshot",
+        serde(serialize_with = "serialize_mem", deserialize_with = "deserialize_mem"))]
+    #[cfg_attr(feature = "snapshot", serde(default = "exrom_default"))]
+    exrom: ExRom,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    paged_in: PagedInFlag
+}
+
+impl Default for Beta128MemExt {
+    fn default() -> Self {
+        let exrom = Rc::new([]);
+        Beta128MemExt { exrom, paged_in: PagedInFlag::default() }
+    }
+}
+
+impl MemoryExtension for Beta128MemExt {
+    #[inline(always)]
+    fn read_opcode<M: ZxMemory>(&mut self, pc: u16, memory: &mut M) -> u8 {
+        if pc & 0xFF00 == 0x3D00 {
+            if !self.paged_in.get() && is_basic_rom_paged_in(memory) {
+                let _ = self.map_exrom(memory);
+            }
+        }
+        else if pc >= 0x4000 && self.paged_in.get() {
+            self.unmap_exrom(memory);
+        }
+        memory.read(pc)
+    }
+}
+
+impl Beta128MemExt {
+    /// Provide a reader with 16kb of TR-DOS ROM program code.
+    pub fn load_trdos_rom<R: Read>(&mut self, mut rd: R) -> io::Result<()> {
+        let mut exrom = Rc::new([!0u8;0x4000]);
+        rd.read_exact(Rc::get_mut(&mut exrom).unwrap())?;
+        self.exrom = exrom;
+        Ok(())
+    }
+    /// Returns a reference to the EX-ROM bank.
+    pub fn exrom(&self) -> &ExRom {
+        &self.exrom
+    }
+    /// Removes data from the EX-ROM bank.
+    ///
+    /// # Note
+    /// If the EX-ROM bank has been paged in, it won't be paged out automatically after the EX-ROM data
+    /// is cleared from the extension.
+    pub fn clear_exrom(&mut self) {
+        self.exrom = Rc::new([]);
+    }
+    /// Returns a handle to the flag indicating if the TR-DOS ROM is paged in.
+    pub fn paged_in_flag(&self) -> &PagedInFlag {
+        &self.paged_in
+    }
+    /// Maps EX-ROM into `memory` page `0`.
+    ///
+    /// # Errors
+    /// Returns an error if the extension's EX-ROM bank is not populated with ROM data.
+    pub fn map_exrom<M: ZxMemory>(&self, memory: &mut M) -> Result<(), ZxMemoryError> {
+        memory.map_exrom(Rc::clone(&self.exrom), 0)?;
+        self.paged_in.set(true);
+        Ok(())
+    }
+    /// Unmaps EX-ROM from `memory`.
+    pub fn unmap_exrom<M: ZxMemory>(&self, memory: &mut M) {
+        memory.unmap_exrom(&self.exrom);
+        self.paged_in.set(false);
+    }
+    /// Returns `true` if EX-ROM is currently paged in.
+    pub fn is_mapped_exrom<M: ZxMemory>(&self, memory: &M) -> bool {
+        memory.has_mapped_exrom(&self.exrom)
+    }
+}
+
+#[inline]
+fn is_basic_rom_paged_in<M: ZxMemory>(memory: &M) -> bool {
+    match memory.page_bank(0) {
+        Ok((MemoryKind::Rom, bank)) => bank + 1 == M::ROM_SIZE / M::PAGE_SIZE,
+        _ => false
+    }
+}
+
+#[cfg(feature = "snapshot")]
+fn exrom_default() -> ExRom {
+    Rc::new([])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::memory::Memory128k;
+    use super::*;
+
+    fn test_memory() -> (Memory128k, Beta128MemExt) {
+        let mut memory = Memory128k::default();
+        memory.rom_bank_mut(0).unwrap()[0x3D00] = 0xB0;
+        memory.rom_bank_mut(1).unwrap()[0x3CFF] = 0xBF;
+        memory.rom_bank_mut(1).unwrap()[0x3D00] = 0xB1;
+        memory.ram_bank_mut(5).unwrap()[0] = 0x5A;
+        let mut ext = Beta128MemExt::default();
+        let mut rom = vec![0u8;0x4000];
+        rom[0x3D00] = 0xD0;
+        rom[0x3D01] = 0xD1;
+        ext.load_trdos_rom(&rom[..]).unwrap();
+        (memory, ext)
+    }
+
+    #[test]
+    fn beta128_pages_in_trdos_rom() {
+        let (mut memory, mut ext) = test_memory();
+        let flag = ext.paged_in_flag().clone();
+        assert!(flag.is_shared_with(ext.paged_in_flag()));
+        // the 128k editor ROM is paged in
+        memory.map_rom_bank(0, 0).unwrap();
+        assert_eq!(ext.read_opcode(0x3D00, &mut memory), 0xB0);
+        assert!(!flag.get());
+        assert!(!ext.is_mapped_exrom(&memory));
+        // the 48k BASIC ROM is paged in
+        memory.map_rom_bank(1, 0).unwrap();
+        assert_eq!(ext.read_opcode(0x3CFF, &mut memory), 0xBF);
+        assert!(!flag.get());
+        assert_eq!(ext.read_opcode(0x3D00, &mut memory), 0xD0);
+        assert!(flag.get());
+        assert!(ext.is_mapped_exrom(&memory));
+        assert_eq!(ext.read_opcode(0x3D01, &mut memory), 0xD1);
+        assert!(flag.get());
+    }
+
+    #[test]
+    fn beta128_pages_out_trdos_rom() {
+        let (mut memory, mut ext) = test_memory();
+        memory.map_rom_bank(1, 0).unwrap();
+        ext.read_opcode(0x3D00, &mut memory);
+        assert!(ext.paged_in_flag().get());
+        // fetching from the ROM area keeps TR-DOS paged in
+        for &pc in [0x0000, 0x3C00, 0x3E00, 0x3FFF].iter() {
+            ext.read_opcode(pc, &mut memory);
+            assert!(ext.paged_in_flag().get());
+        }
+        assert_eq!(ext.read_opcode(0x4000, &mut memory), 0x5A);
+        assert!(!ext.paged_in_flag().get());
+        assert!(!ext.is_mapped_exrom(&memory));
+        assert_eq!(memory.read(0x3D00), 0xB1);
+        // without the TR-DOS ROM loaded nothing is being paged in
+        ext.clear_exrom();
+        assert_eq!(ext.read_opcode(0x3D00, &mut memory), 0xB1);
+        assert!(!ext.paged_in_flag().get());
+    }
+}
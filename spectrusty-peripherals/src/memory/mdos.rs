@@ -0,0 +1,221 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use std::rc::Rc;
+use std::io::{self, Read};
+
+use spectrusty_core::memory::{MemoryExtension, ExRom, ZxMemory};
+#[cfg(feature = "snapshot")]
+use spectrusty_core::memory::serde::{serialize_mem, deserialize_mem};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use super::PagedInFlag;
+
+const MDOS_ROM_SIZE: usize = 0x3800;
+const MDOS_MEM_SIZE: usize = 0x4000;
+
+/// The Didaktik MDOS disk interface (D40/D80) memory [extension][MemoryExtension].
+///
+/// The interface has 14kb of ROM and 2kb of RAM which are paged in as a whole in place of the
+/// Spectrum's ROM, the ROM at addresses `0x0000` to `0x37FF` and the RAM at `0x3800` to `0x3FFF`.
+///
+/// The memory is paged in when the processor fetches an instruction from the address `0x0008`
+/// (the error restart hooked by MDOS) or `0x0066` (the non-maskable interrupt triggered by the
+/// interface's SNAP button). The memory is paged in and out by the interface's I/O ports, so
+/// the [PagedInFlag] should be shared with the [MdosBusDevice][crate::bus::mdos::MdosBusDevice]
+/// with [MdosMemExt::paged_in_flag]. The changes to the shared flag are reflected in the memory
+/// mapping when the next instruction is being fetched.
+///
+/// # Note
+/// The memory must support EX-ROM paging with 16kb pages, e.g. `Memory48kEx` or `Memory128k`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct MdosMemExt {
+    #[cfg_attr(feature = "snapshot",
+        serde(serialize_with = "serialize_mem", deserialize_with = "deserialize_mem"))]
+    #[cfg_attr(feature = "snapshot", serde(default = "exrom_default"))]
+    exrom: ExRom,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    paged_in: PagedInFlag
+}
+
+impl Default for MdosMemExt {
+    fn default() -> Self {
+        let exrom = Rc::new([]);
+        MdosMemExt { exrom, paged_in: PagedInFlag::default() }
+    }
+}
+
+impl MemoryExtension for MdosMemExt {
+    #[inline]
+    fn read_opcode<M: ZxMemory>(&mut self, pc: u16, memory: &mut M) -> u8 {
+        if pc == 0x0008 || pc == 0x0066 {
+            self.paged_in.set(true);
+        }
+        self.update_paging(memory);
+        memory.read(pc)
+    }
+
+    #[inline]
+    fn write_mem<M: ZxMemory>(&mut self, addr: u16, val: u8, memory: &mut M) {
+        if addr < MDOS_MEM_SIZE as u16 && memory.has_mapped_exrom(&self.exrom) {
+            let offset = addr as usize;
+            if offset >= MDOS_ROM_SIZE {
+                memory.unmap_exrom(&self.exrom);
+                self.exrom_mut()[offset] = val;
+                let _ = memory.map_exrom(Rc::clone(&self.exrom), 0);
+            }
+        }
+        else {
+            memory.write(addr, val)
+        }
+    }
+}
+
+impl MdosMemExt {
+    /// Provide a reader with 14kb of the MDOS ROM program code.
+    ///
+    /// The interface RAM is being cleared.
+    pub fn load_mdos_rom<R: Read>(&mut self, mut rd: R) -> io::Result<()> {
+        let mut exrom = Rc::new([0u8;MDOS_MEM_SIZE]);
+        let rom_slice = &mut Rc::get_mut(&mut exrom).unwrap()[..MDOS_ROM_SIZE];
+        rd.read_exact(rom_slice)?;
+        self.exrom = exrom;
+        Ok(())
+    }
+    /// Returns a reference to the EX-ROM bank with the interface memory content.
+    pub fn exrom(&self) -> &ExRom {
+        &self.exrom
+    }
+    /// Removes data from the EX-ROM bank.
+    ///
+    /// # Note
+    /// If the EX-ROM bank has been paged in, it won't be paged out automatically after the EX-ROM data
+    /// is cleared from the extension.
+    pub fn clear_exrom(&mut self) {
+        self.exrom = Rc::new([]);
+    }
+    /// Returns a reference to the interface RAM or an empty slice if the ROM hasn't been loaded.
+    pub fn ram_ref(&self) -> &[u8] {
+        self.exrom.get(MDOS_ROM_SIZE..MDOS_MEM_SIZE).unwrap_or(&[])
+    }
+    /// Returns a handle to the flag indicating if the interface memory should be paged in.
+    pub fn paged_in_flag(&self) -> &PagedInFlag {
+        &self.paged_in
+    }
+    /// Pages the interface memory in immediately.
+    pub fn page_in<M: ZxMemory>(&mut self, memory: &mut M) {
+        self.paged_in.set(true);
+        self.update_paging(memory);
+    }
+    /// Pages the interface memory out immediately.
+    pub fn page_out<M: ZxMemory>(&mut self, memory: &mut M) {
+        self.paged_in.set(false);
+        self.update_paging(memory);
+    }
+    /// Returns `true` if EX-ROM is currently paged in.
+    pub fn is_mapped_exrom<M: ZxMemory>(&self, memory: &M) -> bool {
+        memory.has_mapped_exrom(&self.exrom)
+    }
+    /// Updates the `memory` mapping according to the shared paging flag.
+    ///
+    /// This is being done automatically when an instruction is being fetched.
+    pub fn update_paging<M: ZxMemory>(&mut self, memory: &mut M) {
+        let is_mapped = memory.has_mapped_exrom(&self.exrom);
+        if self.paged_in.get() {
+            if !is_mapped {
+                let _ = memory.map_exrom(Rc::clone(&self.exrom), 0);
+            }
+        }
+        else if is_mapped {
+            memory.unmap_exrom(&self.exrom);
+        }
+    }
+
+    fn exrom_mut(&mut self) -> &mut [u8] {
+        if Rc::get_mut(&mut self.exrom).is_none() {
+            self.exrom = Rc::from(&self.exrom[..]);
+        }
+        Rc::get_mut(&mut self.exrom).unwrap()
+    }
+}
+
+#[cfg(feature = "snapshot")]
+fn exrom_default() -> ExRom {
+    Rc::new([])
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::memory::Memory48kEx;
+    use super::*;
+
+    fn test_ext() -> MdosMemExt {
+        let mut ext = MdosMemExt::default();
+        ext.load_mdos_rom(&[0x55u8;MDOS_ROM_SIZE][..]).unwrap();
+        ext
+    }
+
+    #[test]
+    fn mdos_paging_works() {
+        let mut memory = Memory48kEx::default();
+        let mut ext = test_ext();
+        assert_eq!(ext.ram_ref(), &[0u8;MDOS_MEM_SIZE - MDOS_ROM_SIZE][..]);
+        memory.write(0x0000, 0xAA);
+        assert_eq!(ext.read_opcode(0x0000, &mut memory), 0xFF);
+        assert!(!ext.is_mapped_exrom(&memory));
+        for &pc in &[0x0008, 0x0066] {
+            assert_eq!(ext.read_opcode(pc, &mut memory), 0x55);
+            assert!(ext.paged_in_flag().get() && ext.is_mapped_exrom(&memory));
+            assert_eq!(ext.read_opcode(0x3000, &mut memory), 0x55);
+            assert!(ext.is_mapped_exrom(&memory));
+            // the bus device pages the memory out via the shared flag
+            ext.paged_in_flag().clone().set(false);
+            assert_eq!(ext.read_opcode(0x3800, &mut memory), 0xFF);
+            assert!(!ext.is_mapped_exrom(&memory));
+        }
+        ext.page_in(&mut memory);
+        assert!(ext.is_mapped_exrom(&memory));
+        ext.page_out(&mut memory);
+        assert!(!ext.is_mapped_exrom(&memory));
+        ext.clear_exrom();
+        assert!(ext.ram_ref().is_empty());
+    }
+
+    #[test]
+    fn mdos_write_mem_works() {
+        let mut memory = Memory48kEx::default();
+        let mut ext = test_ext();
+        // writes fall through to the memory while the interface is paged out
+        ext.write_mem(0x3800, 1, &mut memory);
+        ext.write_mem(0x8000, 2, &mut memory);
+        assert_eq!(memory.read(0x8000), 2);
+        assert_eq!(ext.ram_ref()[0], 0);
+
+        ext.page_in(&mut memory);
+        // the interface RAM is writable, the ROM is not
+        ext.write_mem(0x0000, 3, &mut memory);
+        ext.write_mem(0x3800, 4, &mut memory);
+        ext.write_mem(0x3FFF, 5, &mut memory);
+        ext.write_mem(0x4000, 6, &mut memory);
+        assert!(ext.is_mapped_exrom(&memory));
+        assert_eq!(memory.read(0x0000), 0x55);
+        assert_eq!(memory.read(0x3800), 4);
+        assert_eq!(memory.read(0x3FFF), 5);
+        assert_eq!(memory.read(0x4000), 6);
+        assert_eq!(ext.ram_ref()[0], 4);
+        assert_eq!(ext.ram_ref()[MDOS_MEM_SIZE - MDOS_ROM_SIZE - 1], 5);
+        // the interface RAM is preserved while paged out
+        ext.page_out(&mut memory);
+        ext.write_mem(0x3800, 7, &mut memory);
+        assert_eq!(ext.ram_ref()[0], 4);
+        ext.page_in(&mut memory);
+        assert_eq!(memory.read(0x3800), 4);
+    }
+}
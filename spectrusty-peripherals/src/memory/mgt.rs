@@ -0,0 +1,387 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use std::rc::Rc;
+use std::io::{self, Read};
+
+use spectrusty_core::memory::{MemoryExtension, ExRom, ZxMemory};
+#[cfg(feature = "snapshot")]
+use spectrusty_core::memory::serde::{serialize_mem, deserialize_mem};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+const MGT_ROM_SIZE: usize = 0x2000;
+const MGT_MEM_SIZE: usize = 0x4000;
+
+/// The model of the MGT disk interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub enum MgtModel {
+    /// The MGT +D interface.
+    PlusD,
+    /// The MGT DISCiPLE interface.
+    Disciple
+}
+
+/// An interface for the MGT disk interface model markers.
+pub trait MgtInterface: fmt::Debug {
+    /// The model of the interface.
+    const MODEL: MgtModel;
+    /// Returns `true` if fetching an instruction from the given `pc` address pages in the interface memory.
+    fn is_paging_trap(pc: u16) -> bool;
+}
+
+/// The MGT +D model marker.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct PlusD;
+
+/// The MGT DISCiPLE model marker.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Disciple;
+
+impl MgtInterface for PlusD {
+    const MODEL: MgtModel = MgtModel::PlusD;
+    #[inline(always)]
+    fn is_paging_trap(pc: u16) -> bool {
+        matches!(pc, 0x0008|0x003A|0x0066)
+    }
+}
+
+impl MgtInterface for Disciple {
+    const MODEL: MgtModel = MgtModel::Disciple;
+    #[inline(always)]
+    fn is_paging_trap(pc: u16) -> bool {
+        matches!(pc, 0x0001|0x0008|0x0066|0x028E)
+    }
+}
+
+/// The MGT +D memory extension.
+pub type PlusDMemExt = MgtMemExt<PlusD>;
+/// The MGT DISCiPLE memory extension.
+pub type DiscipleMemExt = MgtMemExt<Disciple>;
+
+bitflags! {
+    /// The state of the MGT interface memory paging.
+    #[derive(Default)]
+    #[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+    pub struct MgtPaging: u8 {
+        /// The interface memory is paged in.
+        const PAGED_IN = 0b0000_0001;
+        /// The interface RAM is mapped at `0x0000` and the ROM at `0x2000` (DISCiPLE only).
+        const SWAPPED  = 0b0000_0010;
+    }
+}
+
+/// The state of the MGT interface memory paging, shared between the [MgtMemExt] memory
+/// [extension][MemoryExtension] and a bus device.
+///
+/// Cloning the state creates a new handle to the same shared state.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(from = "MgtPaging", into = "MgtPaging"))]
+pub struct MgtPagingState(Rc<Cell<MgtPaging>>);
+
+impl From<MgtPaging> for MgtPagingState {
+    fn from(paging: MgtPaging) -> Self {
+        MgtPagingState(Rc::new(Cell::new(paging)))
+    }
+}
+
+impl From<MgtPagingState> for MgtPaging {
+    fn from(state: MgtPagingState) -> Self {
+        state.get()
+    }
+}
+
+impl MgtPagingState {
+    /// Returns the current paging state.
+    #[inline]
+    pub fn get(&self) -> MgtPaging {
+        self.0.get()
+    }
+    /// Replaces the paging state.
+    #[inline]
+    pub fn set(&self, paging: MgtPaging) {
+        self.0.set(paging)
+    }
+    /// Sets or clears the specified `flags`.
+    #[inline]
+    pub fn set_flags(&self, flags: MgtPaging, value: bool) {
+        let mut paging = self.0.get();
+        paging.set(flags, value);
+        self.0.set(paging)
+    }
+    /// Returns `true` if both handles refer to the same shared state.
+    #[inline]
+    pub fn is_shared_with(&self, other: &MgtPagingState) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The MGT +D and DISCiPLE disk interfaces memory [extension][MemoryExtension].
+///
+/// Use one of the [PlusDMemExt] or [DiscipleMemExt] types.
+///
+/// Both interfaces have 8kb of ROM and 8kb of RAM which are paged in as a whole in place of the
+/// Spectrum's ROM, at addresses `0x0000` to `0x3FFF`.
+///
+/// The +D memory is paged in when the processor fetches an instruction from the address `0x0008`,
+/// `0x003A` or `0x0066`. The DISCiPLE memory is paged in when the processor fetches an instruction from
+/// the address `0x0001`, `0x0008`, `0x0066` or `0x028E`. The memory is paged in and out by the
+/// interface's I/O ports, so the [MgtPagingState] should be shared with the bus device with
+/// [MgtMemExt::paging_state]. The changes to the shared state are reflected in the memory mapping
+/// when the next instruction is being fetched.
+///
+/// Because the snapshot button triggers the non-maskable interrupt, which executes the code at `0x0066`,
+/// pressing the button is emulated by [ControlUnit::nmi][spectrusty_core::chip::ControlUnit::nmi].
+///
+/// # Note
+/// The memory must support EX-ROM paging with 16kb pages, e.g. `Memory48kEx` or `Memory128k`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct MgtMemExt<M> {
+    #[cfg_attr(feature = "snapshot",
+        serde(serialize_with = "serialize_mem", deserialize_with = "deserialize_mem"))]
+    #[cfg_attr(feature = "snapshot", serde(default = "exrom_default"))]
+    exrom: ExRom,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    swapped: bool,
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    paging: MgtPagingState,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    _model: PhantomData<M>
+}
+
+impl<M> Default for MgtMemExt<M> {
+    fn default() -> Self {
+        let exrom = Rc::new([]);
+        MgtMemExt { exrom, swapped: false, paging: MgtPagingState::default(), _model: PhantomData }
+    }
+}
+
+impl<I: MgtInterface> MemoryExtension for MgtMemExt<I> {
+    #[inline]
+    fn read_opcode<M: ZxMemory>(&mut self, pc: u16, memory: &mut M) -> u8 {
+        if I::is_paging_trap(pc) {
+            self.paging.set_flags(MgtPaging::PAGED_IN, true);
+        }
+        self.update_paging(memory);
+        memory.read(pc)
+    }
+
+    #[inline]
+    fn write_mem<M: ZxMemory>(&mut self, addr: u16, val: u8, memory: &mut M) {
+        if addr < MGT_MEM_SIZE as u16 && memory.has_mapped_exrom(&self.exrom) {
+            let offset = addr as usize;
+            if (offset < MGT_ROM_SIZE) == self.swapped {
+                memory.unmap_exrom(&self.exrom);
+                self.exrom_mut()[offset] = val;
+                let _ = memory.map_exrom(Rc::clone(&self.exrom), 0);
+            }
+        }
+        else {
+            memory.write(addr, val)
+        }
+    }
+}
+
+impl<I: MgtInterface> MgtMemExt<I> {
+    /// Returns the model of the interface.
+    pub fn model(&self) -> MgtModel {
+        I::MODEL
+    }
+}
+
+impl<I> MgtMemExt<I> {
+    /// Provide a reader with 8kb of the interface ROM program code.
+    ///
+    /// The interface RAM is being cleared.
+    pub fn load_mgt_rom<R: Read>(&mut self, mut rd: R) -> io::Result<()> {
+        let mut exrom = Rc::new([0u8;MGT_MEM_SIZE]);
+        let rom_slice = &mut Rc::get_mut(&mut exrom).unwrap()[..MGT_ROM_SIZE];
+        rd.read_exact(rom_slice)?;
+        self.exrom = exrom;
+        self.swapped = false;
+        Ok(())
+    }
+    /// Provide a reader with 16kb of the interface memory content, in the order in which it appears
+    /// in the address space when the memory is paged in.
+    ///
+    /// # Note
+    /// If the memory has been paged in, the new content is being mapped when the next instruction
+    /// is being fetched.
+    pub fn load_mgt_mem<R: Read>(&mut self, mut rd: R) -> io::Result<()> {
+        let mut exrom = Rc::new([0u8;MGT_MEM_SIZE]);
+        rd.read_exact(Rc::get_mut(&mut exrom).unwrap())?;
+        self.exrom = exrom;
+        Ok(())
+    }
+    /// Returns a reference to the EX-ROM bank with the interface memory content, in the order
+    /// in which it appears in the address space when the memory is paged in.
+    pub fn exrom(&self) -> &ExRom {
+        &self.exrom
+    }
+    /// Removes data from the EX-ROM bank.
+    ///
+    /// # Note
+    /// If the EX-ROM bank has been paged in, it won't be paged out automatically after the EX-ROM data
+    /// is cleared from the extension.
+    pub fn clear_exrom(&mut self) {
+        self.exrom = Rc::new([]);
+        self.swapped = false;
+    }
+    /// Returns a reference to the interface ROM or an empty slice if the ROM hasn't been loaded.
+    pub fn rom_ref(&self) -> &[u8] {
+        let offset = if self.swapped { MGT_ROM_SIZE } else { 0 };
+        self.exrom.get(offset..offset + MGT_ROM_SIZE).unwrap_or(&[])
+    }
+    /// Returns a reference to the interface RAM or an empty slice if the ROM hasn't been loaded.
+    pub fn ram_ref(&self) -> &[u8] {
+        let offset = if self.swapped { 0 } else { MGT_ROM_SIZE };
+        self.exrom.get(offset..offset + MGT_ROM_SIZE).unwrap_or(&[])
+    }
+    /// Returns a handle to the shared paging state.
+    pub fn paging_state(&self) -> &MgtPagingState {
+        &self.paging
+    }
+    /// Returns `true` if the interface memory should be paged in.
+    pub fn is_paged_in(&self) -> bool {
+        self.paging.get().intersects(MgtPaging::PAGED_IN)
+    }
+    /// Pages the interface memory in immediately.
+    pub fn page_in<M: ZxMemory>(&mut self, memory: &mut M) {
+        self.paging.set_flags(MgtPaging::PAGED_IN, true);
+        self.update_paging(memory);
+    }
+    /// Pages the interface memory out immediately.
+    pub fn page_out<M: ZxMemory>(&mut self, memory: &mut M) {
+        self.paging.set_flags(MgtPaging::PAGED_IN, false);
+        self.update_paging(memory);
+    }
+    /// Returns `true` if EX-ROM is currently paged in.
+    pub fn is_mapped_exrom<M: ZxMemory>(&self, memory: &M) -> bool {
+        memory.has_mapped_exrom(&self.exrom)
+    }
+    /// Updates the `memory` mapping according to the shared paging state.
+    ///
+    /// This is being done automatically when an instruction is being fetched.
+    pub fn update_paging<M: ZxMemory>(&mut self, memory: &mut M) {
+        let paging = self.paging.get();
+        if paging.intersects(MgtPaging::SWAPPED) != self.swapped && self.exrom.len() == MGT_MEM_SIZE {
+            memory.unmap_exrom(&self.exrom);
+            self.exrom_mut().rotate_left(MGT_ROM_SIZE);
+            self.swapped = !self.swapped;
+        }
+        let is_mapped = memory.has_mapped_exrom(&self.exrom);
+        if paging.intersects(MgtPaging::PAGED_IN) {
+            if !is_mapped {
+                let _ = memory.map_exrom(Rc::clone(&self.exrom), 0);
+            }
+        }
+        else if is_mapped {
+            memory.unmap_exrom(&self.exrom);
+        }
+    }
+
+    fn exrom_mut(&mut self) -> &mut [u8] {
+        if Rc::get_mut(&mut self.exrom).is_none() {
+            self.exrom = Rc::from(&self.exrom[..]);
+        }
+        Rc::get_mut(&mut self.exrom).unwrap()
+    }
+}
+
+#[cfg(feature = "snapshot")]
+fn exrom_default() -> ExRom {
+    Rc::new([])
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::z80emu::Memory;
+    use spectrusty::bus::VFNullDevice;
+    use spectrusty::chip::{MemoryAccess, ula::{UlaPAL, UlaVideoFrame}};
+    use spectrusty::memory::{Memory48kEx, Memory128k};
+    use spectrusty::clock::VideoTs;
+    use super::*;
+
+    fn test_ext<I>() -> MgtMemExt<I> {
+        let mut ext = MgtMemExt::<I>::default();
+        ext.load_mgt_rom(&[0x55u8;MGT_ROM_SIZE][..]).unwrap();
+        ext
+    }
+
+    #[test]
+    fn mgt_write_mem_works() {
+        let mut memory = Memory128k::default();
+        let mut ext = test_ext::<PlusD>();
+        // writes fall through to the memory while the interface is paged out
+        ext.write_mem(0x2000, 1, &mut memory);
+        ext.write_mem(0x8000, 2, &mut memory);
+        assert_eq!(memory.read(0x8000), 2);
+        assert_eq!(ext.ram_ref()[0], 0);
+
+        assert_eq!(ext.read_opcode(0x0008, &mut memory), 0x55);
+        assert!(ext.is_paged_in() && ext.is_mapped_exrom(&memory));
+        // the interface RAM is writable, the ROM is not
+        ext.write_mem(0x0000, 3, &mut memory);
+        ext.write_mem(0x2000, 4, &mut memory);
+        ext.write_mem(0x3FFF, 5, &mut memory);
+        ext.write_mem(0x4000, 6, &mut memory);
+        assert!(ext.is_mapped_exrom(&memory));
+        assert_eq!(memory.read(0x0000), 0x55);
+        assert_eq!(memory.read(0x2000), 4);
+        assert_eq!(memory.read(0x3FFF), 5);
+        assert_eq!(memory.read(0x4000), 6);
+        assert_eq!(ext.rom_ref(), &[0x55;MGT_ROM_SIZE][..]);
+        assert_eq!(ext.ram_ref()[0], 4);
+        assert_eq!(ext.ram_ref()[MGT_ROM_SIZE - 1], 5);
+        // the interface RAM is preserved while paged out
+        ext.page_out(&mut memory);
+        ext.write_mem(0x2000, 7, &mut memory);
+        assert_eq!(ext.ram_ref()[0], 4);
+        ext.page_in(&mut memory);
+        assert_eq!(memory.read(0x2000), 4);
+    }
+
+    #[test]
+    fn mgt_swapped_write_mem_works() {
+        let mut memory = Memory128k::default();
+        let mut ext = test_ext::<Disciple>();
+        ext.paging_state().set(MgtPaging::PAGED_IN|MgtPaging::SWAPPED);
+        assert_eq!(ext.read_opcode(0x2000, &mut memory), 0x55);
+        ext.write_mem(0x0000, 1, &mut memory);
+        ext.write_mem(0x2000, 2, &mut memory);
+        assert_eq!(memory.read(0x0000), 1);
+        assert_eq!(memory.read(0x2000), 0x55);
+        assert_eq!(ext.ram_ref()[0], 1);
+        ext.paging_state().set(MgtPaging::PAGED_IN);
+        ext.update_paging(&mut memory);
+        assert_eq!(memory.read(0x0000), 0x55);
+        assert_eq!(memory.read(0x2000), 1);
+    }
+
+    #[test]
+    fn mgt_chipset_write_mem_works() {
+        let mut ula = UlaPAL::<Memory48kEx, VFNullDevice<UlaVideoFrame>, PlusDMemExt>::default();
+        let ts = VideoTs::default();
+        ula.memory_ext_mut().load_mgt_rom(&[0x55u8;MGT_ROM_SIZE][..]).unwrap();
+        ula.write_mem(0x2000, 1, ts);
+        assert_eq!(ula.memory_ext_ref().ram_ref()[0], 0);
+        let (memory, ext) = ula.memory_with_ext_mut();
+        ext.page_in(memory);
+        ula.write_mem(0x2000, 2, ts);
+        ula.write_mem(0x0000, 3, ts);
+        assert_eq!(ula.memory_ext_ref().ram_ref()[0], 2);
+        assert_eq!(ula.memory_ref().read(0x0000), 0x55);
+        assert_eq!(ula.memory_ref().read(0x2000), 2);
+    }
+}
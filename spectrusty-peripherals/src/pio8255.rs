@@ -0,0 +1,199 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An emulator of the Intel 8255 programmable parallel I/O interface.
+/*!
+The chip has three 8-bit ports: **A**, **B** and **C** and a control register. The port **C** is
+divided into two 4-bit halves, each of them can be programmed as input or output independently.
+
+The control register (write only):
+```text
+       Bit   7      6   5      4      3      2      1      0
+          +--------------------------------------------------+
+  MODE SET|  1 | A mode  | A in |C4-7 in|B mode| B in |C0-3 in|
+          |----+---------+------+-------+------+------+-------|
+   BIT S/R|  0 |         |      |    C bit index      |set/rst|
+          +--------------------------------------------------+
+```
+
+Only the basic *mode 0* I/O is emulated, the strobed modes 1 and 2 of the group **A** and the mode 1 of
+the group **B** are treated as *mode 0*. Writing the mode word resets all the output latches to 0.
+
+The port values of the peripheral side of the chip are provided with [Pio8255::set_input] and may be
+inspected with [Pio8255::output]. Reading the port configured as output returns its output latch.
+!*/
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+/// The control register: the mode set flag.
+pub const CTRL_MODE_SET:   u8 = 0b1000_0000;
+/// The control register: the port **A** is an input.
+pub const CTRL_A_INPUT:    u8 = 0b0001_0000;
+/// The control register: the upper half of the port **C** is an input.
+pub const CTRL_C_HI_INPUT: u8 = 0b0000_1000;
+/// The control register: the port **B** is an input.
+pub const CTRL_B_INPUT:    u8 = 0b0000_0010;
+/// The control register: the lower half of the port **C** is an input.
+pub const CTRL_C_LO_INPUT: u8 = 0b0000_0001;
+/// The control register value after reset: all ports are inputs.
+pub const CTRL_RESET: u8 = CTRL_MODE_SET|CTRL_A_INPUT|CTRL_C_HI_INPUT|CTRL_B_INPUT|CTRL_C_LO_INPUT;
+
+/// The port of the [Pio8255].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PioPort {
+    /// The port **A**.
+    A = 0,
+    /// The port **B**.
+    B = 1,
+    /// The port **C**.
+    C = 2
+}
+
+/// The emulated Intel 8255 programmable parallel I/O interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Pio8255 {
+    ctrl: u8,
+    output: [u8;3],
+    input: [u8;3]
+}
+
+impl Default for Pio8255 {
+    fn default() -> Self {
+        Pio8255 { ctrl: CTRL_RESET, output: [0;3], input: [u8::MAX;3] }
+    }
+}
+
+impl Pio8255 {
+    /// Resets the chip. All the ports become inputs.
+    pub fn reset(&mut self) {
+        self.ctrl = CTRL_RESET;
+        self.output = [0;3];
+    }
+    /// Returns the last mode word written to the control register.
+    pub fn control(&self) -> u8 {
+        self.ctrl
+    }
+    /// Returns the mask of bits of the given `port` that are configured as inputs.
+    pub fn input_mask(&self, port: PioPort) -> u8 {
+        let ctrl = self.ctrl;
+        match port {
+            PioPort::A => if ctrl & CTRL_A_INPUT != 0 { 0xFF } else { 0 },
+            PioPort::B => if ctrl & CTRL_B_INPUT != 0 { 0xFF } else { 0 },
+            PioPort::C => {
+                (if ctrl & CTRL_C_HI_INPUT != 0 { 0xF0 } else { 0 }) |
+                (if ctrl & CTRL_C_LO_INPUT != 0 { 0x0F } else { 0 })
+            }
+        }
+    }
+    /// Returns `true` if all bits of the given `port` are configured as inputs.
+    pub fn is_input(&self, port: PioPort) -> bool {
+        self.input_mask(port) == 0xFF
+    }
+    /// Sets the value of the signals applied to the `port` by the peripheral side.
+    ///
+    /// Only bits configured as inputs can be read by the CPU, by default all the input lines are high.
+    pub fn set_input(&mut self, port: PioPort, data: u8) {
+        self.input[port as usize] = data;
+    }
+    /// Returns the value presented on the peripheral side of the `port`.
+    ///
+    /// Bits configured as inputs are reported as high.
+    pub fn output(&self, port: PioPort) -> u8 {
+        self.output[port as usize] | self.input_mask(port)
+    }
+    /// Returns the value of the `port` read by the CPU.
+    pub fn read_port(&self, port: PioPort) -> u8 {
+        let mask = self.input_mask(port);
+        self.input[port as usize] & mask | self.output[port as usize] & !mask
+    }
+    /// Writes the output latch of the `port` by the CPU.
+    pub fn write_port(&mut self, port: PioPort, data: u8) {
+        self.output[port as usize] = data;
+    }
+    /// Writes the control register by the CPU.
+    pub fn write_control(&mut self, data: u8) {
+        if data & CTRL_MODE_SET != 0 {
+            self.ctrl = data;
+            self.output = [0;3];
+        }
+        else {
+            let mask = 1 << ((data >> 1) & 7);
+            if data & 1 != 0 {
+                self.output[PioPort::C as usize] |= mask;
+            }
+            else {
+                self.output[PioPort::C as usize] &= !mask;
+            }
+        }
+    }
+    /// Reads one of the registers indicated by the lowest 2 bits of `reg` by the CPU.
+    ///
+    /// The control register can't be read and `0xFF` is returned in this instance.
+    pub fn read(&self, reg: u8) -> u8 {
+        match reg & 3 {
+            0 => self.read_port(PioPort::A),
+            1 => self.read_port(PioPort::B),
+            2 => self.read_port(PioPort::C),
+            _ => u8::MAX
+        }
+    }
+    /// Writes one of the registers indicated by the lowest 2 bits of `reg` by the CPU.
+    pub fn write(&mut self, reg: u8, data: u8) {
+        match reg & 3 {
+            0 => self.write_port(PioPort::A, data),
+            1 => self.write_port(PioPort::B, data),
+            2 => self.write_port(PioPort::C, data),
+            _ => self.write_control(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pio8255_works() {
+        let mut pio = Pio8255::default();
+        assert!(pio.is_input(PioPort::A));
+        assert!(pio.is_input(PioPort::B));
+        assert!(pio.is_input(PioPort::C));
+        pio.set_input(PioPort::A, 0x5A);
+        assert_eq!(pio.read(0), 0x5A);
+        assert_eq!(pio.read(3), 0xFF);
+        pio.write(0, 0x12);
+        assert_eq!(pio.read(0), 0x5A);
+        assert_eq!(pio.output(PioPort::A), 0xFF);
+        pio.write(3, 0b1000_1001); // A: out, C hi: in, B: out, C lo: in
+        assert_eq!(pio.control(), 0b1000_1001);
+        assert!(!pio.is_input(PioPort::A));
+        assert!(!pio.is_input(PioPort::B));
+        assert!(pio.is_input(PioPort::C));
+        assert_eq!(pio.read(0), 0);
+        pio.write(0, 0x12);
+        pio.write(1, 0x34);
+        assert_eq!(pio.read(0), 0x12);
+        assert_eq!(pio.output(PioPort::B), 0x34);
+        pio.write(3, 0b1000_0000); // all outputs
+        assert_eq!(pio.output(PioPort::A), 0);
+        pio.write(3, 0b0000_0101); // set C bit 2
+        pio.write(3, 0b0000_1111); // set C bit 7
+        assert_eq!(pio.output(PioPort::C), 0x84);
+        pio.write(3, 0b0000_0100); // reset C bit 2
+        assert_eq!(pio.read(2), 0x80);
+        pio.write(3, 0b1000_0001); // C lo in
+        pio.set_input(PioPort::C, 0x0F);
+        pio.write(2, 0xF0);
+        assert_eq!(pio.read(2), 0xFF);
+        assert_eq!(pio.output(PioPort::C), 0xFF);
+        pio.reset();
+        assert_eq!(pio.control(), CTRL_RESET);
+        assert_eq!(pio.read(2), 0x0F);
+    }
+}
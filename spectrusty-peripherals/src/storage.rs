@@ -6,4 +6,7 @@
     For the full copyright notice, see the lib.rs file.
 */
 //! Data storage related.
+pub mod fdc765;
+pub mod floppy;
 pub mod microdrives;
+pub mod wd1793;
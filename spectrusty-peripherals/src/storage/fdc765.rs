@@ -0,0 +1,751 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! NEC µPD765 floppy disk controller for the ZX Spectrum +3.
+/*!
+The controller is being programmed by writing commands to its data register and reading the results
+back from the same register. The main status register indicates which direction and when the data
+can be transferred.
+
+Main status register:
+```text
+       Bit    7    6    5    4    3    2    1    0
+            +---------------------------------------+
+            |RQM | DIO| EXM| CB | D3B| D2B| D1B| D0B|
+            +---------------------------------------+
+```
+* `RQM` - the data register is ready to send or receive data.
+* `DIO` - the direction of the data transfer: `1` from the controller to the CPU.
+* `EXM` - the controller is in the execution phase of a command.
+* `CB`  - the controller is busy processing a command.
+* `DnB` - drive `n` is in the seek mode.
+
+The following commands are supported: `READ DATA`, `READ DELETED DATA`, `WRITE DATA`, `WRITE DELETED DATA`,
+`READ TRACK`, `READ ID`, `FORMAT TRACK`, `RECALIBRATE`, `SEEK`, `SENSE INTERRUPT STATUS`,
+`SENSE DRIVE STATUS`, `SPECIFY` and `VERSION`. Other commands are being rejected as invalid.
+
+The emulated controller is wired like in the ZX Spectrum +3:
+
+* Only the `US0` unit select line is connected, so there are 2 drives: `A` (0) and `B` (1).
+* The `TC` (terminal count) line is not connected, so the read and write commands are always
+  terminated at the end of the cylinder with the `EN` flag set in the *status register 1*.
+* A single motor line is shared by both drives.
+* Data transfers are not being timed, so the data is available as soon as the controller is ready.
+
+!*/
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+pub use super::floppy::*;
+
+/// The number of drives that can be connected to the controller.
+pub const MAX_DRIVES: usize = 2;
+
+/// Main status register: the data register is ready.
+pub const MSR_RQM: u8 = 0b1000_0000;
+/// Main status register: the data direction is from the controller to the CPU.
+pub const MSR_DIO: u8 = 0b0100_0000;
+/// Main status register: the execution phase.
+pub const MSR_EXM: u8 = 0b0010_0000;
+/// Main status register: the controller is busy.
+pub const MSR_CB:  u8 = 0b0001_0000;
+
+const ST0_ABNORMAL:     u8 = 0b0100_0000;
+const ST0_INVALID:      u8 = 0b1000_0000;
+const ST0_SEEK_END:     u8 = 0b0010_0000;
+const ST0_NOT_READY:    u8 = 0b0000_1000;
+const ST0_HEAD:         u8 = 0b0000_0100;
+
+const ST1_END_OF_CYL:   u8 = 0b1000_0000;
+const ST1_NO_DATA:      u8 = 0b0000_0100;
+const ST1_NOT_WRITABLE: u8 = 0b0000_0010;
+const ST1_MISSING_AM:   u8 = 0b0000_0001;
+
+const ST2_WRONG_CYL:    u8 = 0b0001_0000;
+const ST2_BAD_CYL:      u8 = 0b0000_0010;
+
+const ST3_WRITE_PROT:   u8 = 0b0100_0000;
+const ST3_READY:        u8 = 0b0010_0000;
+const ST3_TRACK0:       u8 = 0b0001_0000;
+const ST3_TWO_SIDE:     u8 = 0b0000_1000;
+
+const CMD_MT: u8 = 0b1000_0000;
+const CMD_SK: u8 = 0b0010_0000;
+
+const CMD_READ_TRACK:         u8 = 0x02;
+const CMD_SPECIFY:            u8 = 0x03;
+const CMD_SENSE_DRIVE_STATUS: u8 = 0x04;
+const CMD_WRITE_DATA:         u8 = 0x05;
+const CMD_READ_DATA:          u8 = 0x06;
+const CMD_RECALIBRATE:        u8 = 0x07;
+const CMD_SENSE_INT_STATUS:   u8 = 0x08;
+const CMD_WRITE_DELETED_DATA: u8 = 0x09;
+const CMD_READ_ID:            u8 = 0x0A;
+const CMD_READ_DELETED_DATA:  u8 = 0x0C;
+const CMD_FORMAT_TRACK:       u8 = 0x0D;
+const CMD_SEEK:               u8 = 0x0F;
+const CMD_VERSION:            u8 = 0x10;
+
+/// The emulated NEC µPD765 floppy disk controller with 2 connected [FloppyDrive]s.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Fdc765 {
+    /// Direct access to the floppy drives.
+    pub drives: [FloppyDrive;MAX_DRIVES],
+    motor: bool,
+    phase: Phase,
+    command: Vec<u8>,
+    data: Vec<u8>,
+    data_index: usize,
+    targets: Vec<Target>,
+    result: Vec<u8>,
+    result_index: usize,
+    seek_status: [Option<u8>;MAX_DRIVES],
+    specify: [u8;2]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+enum Phase {
+    Command,
+    ExecRead,
+    ExecWrite(WriteMode),
+    Result
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+enum WriteMode {
+    Data { deleted: bool },
+    Format
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase::Command
+    }
+}
+
+/// A sector being transferred during the execution phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+struct Target {
+    side: u8,
+    index: usize,
+    len: usize
+}
+
+fn command_len(cmd: u8) -> usize {
+    match cmd & 0x1F {
+        CMD_READ_TRACK|CMD_WRITE_DATA|CMD_READ_DATA|
+        CMD_WRITE_DELETED_DATA|CMD_READ_DELETED_DATA => 9,
+        CMD_FORMAT_TRACK => 6,
+        CMD_SPECIFY|CMD_SEEK => 3,
+        CMD_SENSE_DRIVE_STATUS|CMD_RECALIBRATE|CMD_READ_ID => 2,
+        _ => 1
+    }
+}
+
+/// Returns the drive index from the unit select bits, `US1` is not connected.
+#[inline]
+fn unit_index(hdus: u8) -> usize {
+    (hdus & 1) as usize
+}
+
+impl Fdc765 {
+    /// Inserts a `disk` into the drive `unit` (0 or 1), optionally returning a disk that was previously
+    /// in the same drive.
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 1.
+    pub fn insert_disk(&mut self, unit: usize, disk: FloppyDisk) -> Option<FloppyDisk> {
+        self.drives[unit].insert_disk(disk)
+    }
+    /// Removes and optionally returns a disk from the drive `unit` (0 or 1).
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 1.
+    pub fn eject_disk(&mut self, unit: usize) -> Option<FloppyDisk> {
+        self.drives[unit].eject_disk()
+    }
+    /// Returns a reference to a disk if it's present in the drive `unit` (0 or 1).
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 1.
+    pub fn disk_ref(&self, unit: usize) -> Option<&FloppyDisk> {
+        self.drives[unit].disk.as_ref()
+    }
+    /// Returns a mutable reference to a disk if it's present in the drive `unit` (0 or 1).
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 1.
+    pub fn disk_mut(&mut self, unit: usize) -> Option<&mut FloppyDisk> {
+        self.drives[unit].disk.as_mut()
+    }
+    /// Returns `true` if the drive motor is on.
+    #[inline]
+    pub fn is_motor_on(&self) -> bool {
+        self.motor
+    }
+    /// Turns the drive motor on or off.
+    #[inline]
+    pub fn set_motor(&mut self, on: bool) {
+        self.motor = on;
+    }
+    /// Resets the controller, aborting any command in progress.
+    ///
+    /// Disks remain in the drives.
+    pub fn reset(&mut self) {
+        let drives = core::mem::take(&mut self.drives);
+        *self = Fdc765 { drives, motor: self.motor, ..Fdc765::default() };
+    }
+    /// Returns the value of the main status register.
+    pub fn read_status(&self) -> u8 {
+        let seeking = self.seek_status.iter().enumerate()
+                          .filter(|(_, st0)| st0.is_some())
+                          .fold(0, |acc, (unit, _)| acc | (1 << unit));
+        seeking | match self.phase {
+            Phase::Command if self.command.is_empty() => MSR_RQM,
+            Phase::Command => MSR_RQM|MSR_CB,
+            Phase::ExecRead => MSR_RQM|MSR_DIO|MSR_EXM|MSR_CB,
+            Phase::ExecWrite(..) => MSR_RQM|MSR_EXM|MSR_CB,
+            Phase::Result => MSR_RQM|MSR_DIO|MSR_CB
+        }
+    }
+    /// Reads the data register.
+    pub fn read_data(&mut self) -> u8 {
+        match self.phase {
+            Phase::ExecRead => {
+                let data = self.data.get(self.data_index).copied().unwrap_or(u8::MAX);
+                self.data_index += 1;
+                if self.data_index >= self.data.len() {
+                    self.data.clear();
+                    self.phase = Phase::Result;
+                }
+                data
+            }
+            Phase::Result => {
+                let data = self.result.get(self.result_index).copied().unwrap_or(u8::MAX);
+                self.result_index += 1;
+                if self.result_index >= self.result.len() {
+                    self.end_command();
+                }
+                data
+            }
+            _ => u8::MAX
+        }
+    }
+    /// Writes the data register.
+    pub fn write_data(&mut self, data: u8) {
+        match self.phase {
+            Phase::Command => {
+                self.command.push(data);
+                if self.command.len() >= command_len(self.command[0]) {
+                    self.execute_command();
+                }
+            }
+            Phase::ExecWrite(mode) => {
+                self.data.push(data);
+                if self.data.len() >= self.targets.iter().map(|t| t.len).sum() {
+                    self.commit_write(mode);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_command(&mut self) {
+        self.phase = Phase::Command;
+        self.command.clear();
+        self.data.clear();
+        self.data_index = 0;
+        self.targets.clear();
+        self.result.clear();
+        self.result_index = 0;
+    }
+
+    fn set_result(&mut self, result: &[u8]) {
+        if result.is_empty() {
+            return self.end_command()
+        }
+        self.result.clear();
+        self.result.extend_from_slice(result);
+        self.result_index = 0;
+        self.phase = Phase::Result;
+    }
+
+    fn is_ready(&self, unit: usize) -> bool {
+        self.motor && self.drives[unit].has_disk()
+    }
+
+    fn execute_command(&mut self) {
+        let cmd = self.command[0];
+        match cmd & 0x1F {
+            CMD_READ_DATA => self.read_sectors(false),
+            CMD_READ_DELETED_DATA => self.read_sectors(true),
+            CMD_WRITE_DATA => self.write_sectors(false),
+            CMD_WRITE_DELETED_DATA => self.write_sectors(true),
+            CMD_READ_TRACK => self.read_track(),
+            CMD_READ_ID => self.read_id(),
+            CMD_FORMAT_TRACK => self.format_track(),
+            CMD_SPECIFY => {
+                self.specify = [self.command[1], self.command[2]];
+                self.set_result(&[]);
+            }
+            CMD_SENSE_DRIVE_STATUS => {
+                let hdus = self.command[1] & 7;
+                let unit = unit_index(hdus);
+                let drive = &self.drives[unit];
+                let mut st3 = hdus;
+                if let Some(disk) = drive.disk.as_ref() {
+                    if disk.is_write_protected() {
+                        st3 |= ST3_WRITE_PROT;
+                    }
+                    if disk.sides() == 2 {
+                        st3 |= ST3_TWO_SIDE;
+                    }
+                }
+                if self.is_ready(unit) {
+                    st3 |= ST3_READY;
+                }
+                if drive.cylinder() == 0 {
+                    st3 |= ST3_TRACK0;
+                }
+                self.set_result(&[st3]);
+            }
+            CMD_RECALIBRATE => self.seek(0),
+            CMD_SEEK => self.seek(self.command[2]),
+            CMD_SENSE_INT_STATUS => {
+                match self.seek_status.iter().position(Option::is_some) {
+                    Some(unit) => {
+                        let st0 = self.seek_status[unit].take().unwrap();
+                        let pcn = self.drives[unit].cylinder();
+                        self.set_result(&[st0, pcn]);
+                    }
+                    None => self.set_result(&[ST0_INVALID])
+                }
+            }
+            CMD_VERSION => self.set_result(&[0x80]),
+            _ => self.set_result(&[ST0_INVALID])
+        }
+    }
+
+    fn seek(&mut self, cylinder: u8) {
+        let hdus = self.command[1] & 7;
+        let unit = unit_index(hdus);
+        let mut st0 = ST0_SEEK_END | hdus & 3;
+        if self.is_ready(unit) {
+            self.drives[unit].seek(cylinder);
+        }
+        else {
+            st0 |= ST0_ABNORMAL|ST0_NOT_READY;
+        }
+        self.seek_status[unit] = Some(st0);
+        self.set_result(&[]);
+    }
+
+    fn start_read(&mut self, result: [u8;7]) {
+        let unit = unit_index(self.command[1]);
+        let mut data = core::mem::take(&mut self.data);
+        data.clear();
+        if let Some(disk) = self.drives[unit].disk.as_ref() {
+            let cylinder = self.drives[unit].cylinder();
+            for &Target { side, index, len } in self.targets.iter() {
+                let sector = &disk.track(cylinder, side).unwrap().sectors[index];
+                let end = data.len() + len;
+                data.extend(sector.data.iter().take(len));
+                data.resize(end, 0);
+            }
+        }
+        self.targets.clear();
+        self.data = data;
+        self.data_index = 0;
+        self.set_result(&result);
+        if !self.data.is_empty() {
+            self.phase = Phase::ExecRead;
+        }
+    }
+
+    fn start_write(&mut self, mode: WriteMode, result: [u8;7]) {
+        self.data.clear();
+        self.set_result(&result);
+        if self.targets.iter().any(|t| t.len != 0) {
+            self.phase = Phase::ExecWrite(mode);
+        }
+    }
+
+    fn read_sectors(&mut self, deleted: bool) {
+        let result = self.locate_sectors(false, deleted);
+        self.start_read(result);
+    }
+
+    fn write_sectors(&mut self, deleted: bool) {
+        let result = self.locate_sectors(true, deleted);
+        self.start_write(WriteMode::Data { deleted }, result);
+    }
+
+    /// Finds the sectors to be transferred by the read and write commands and returns the result
+    /// of the command.
+    fn locate_sectors(&mut self, is_write: bool, deleted: bool) -> [u8;7] {
+        let (cmd, hdus) = (self.command[0], self.command[1] & 7);
+        let (mut c, mut h, mut r, n) = (self.command[2], self.command[3], self.command[4], self.command[5]);
+        let (eot, dtl) = (self.command[6], self.command[8]);
+        let is_mt = cmd & CMD_MT != 0;
+        let is_sk = !is_write && cmd & CMD_SK != 0;
+        let unit = unit_index(hdus);
+        let mut side = hdus >> 2;
+        let (mut st0, mut st1, mut st2) = (hdus & 3, 0, 0);
+        self.targets.clear();
+        if !self.is_ready(unit) {
+            return [ST0_ABNORMAL|ST0_NOT_READY|hdus, 0, 0, c, h, r, n]
+        }
+        let drive = &self.drives[unit];
+        let (cylinder, disk) = (drive.cylinder(), drive.disk.as_ref().unwrap());
+        if is_write && disk.is_write_protected() {
+            return [ST0_ABNORMAL|hdus, ST1_NOT_WRITABLE, 0, c, h, r, n]
+        }
+        let len = if n == 0 { dtl as usize } else { sector_size(n) };
+        let mut next_index = None;
+        loop {
+            let sectors = disk.track(cylinder, side).map(|t| &t.sectors[..]).unwrap_or(&[]);
+            let index = match sectors.iter().position(|s|
+                s.cylinder == c && s.head == h && s.id == r && s.size_code == n
+            ) {
+                Some(index) => index,
+                None => {
+                    if sectors.is_empty() {
+                        st1 |= ST1_MISSING_AM;
+                    }
+                    else {
+                        st1 |= ST1_NO_DATA;
+                        if let Some(s) = sectors.iter().find(|s| s.id == r && s.cylinder != c) {
+                            st2 |= ST2_WRONG_CYL;
+                            if s.cylinder == 0xFF {
+                                st2 |= ST2_BAD_CYL;
+                            }
+                        }
+                    }
+                    st0 |= ST0_ABNORMAL;
+                    break
+                }
+            };
+            next_index = Some(index + 1);
+            let sector = &sectors[index];
+            let target = Target { side, index, len };
+            if is_write {
+                self.targets.push(target);
+            }
+            else if sector.is_deleted() != deleted {
+                st2 |= ST2_CONTROL_MARK;
+                if !is_sk {
+                    self.targets.push(target);
+                    break
+                }
+            }
+            else {
+                self.targets.push(target);
+                if sector.has_data_error() {
+                    st1 |= sector.st1 & ST1_DATA_ERROR;
+                    st2 |= sector.st2 & ST2_DATA_ERROR;
+                    st0 |= ST0_ABNORMAL;
+                    break
+                }
+            }
+            if r == eot {
+                if is_mt && side == 0 {
+                    side = 1;
+                    h ^= 1;
+                    r = 1;
+                    continue
+                }
+                st0 |= ST0_ABNORMAL;
+                st1 |= ST1_END_OF_CYL;
+                c = c.wrapping_add(1);
+                if is_mt {
+                    h ^= 1;
+                }
+                r = 1;
+                break
+            }
+            r = r.wrapping_add(1);
+        }
+        if let Some(index) = next_index {
+            self.drives[unit].set_next_sector_index(index);
+        }
+        st0 = (st0 & !ST0_HEAD) | (side << 2);
+        [st0, st1, st2, c, h, r, n]
+    }
+
+    fn read_track(&mut self) {
+        let hdus = self.command[1] & 7;
+        let (c, h, mut r, n) = (self.command[2], self.command[3], self.command[4], self.command[5]);
+        let (eot, dtl) = (self.command[6], self.command[8]);
+        let (unit, side) = (unit_index(hdus), hdus >> 2);
+        self.targets.clear();
+        let result = if !self.is_ready(unit) {
+            [ST0_ABNORMAL|ST0_NOT_READY|hdus, 0, 0, c, h, r, n]
+        }
+        else {
+            let sectors = self.drives[unit].current_track(side).map(|t| &t.sectors[..]).unwrap_or(&[]);
+            if sectors.is_empty() {
+                [ST0_ABNORMAL|hdus, ST1_MISSING_AM, 0, c, h, r, n]
+            }
+            else {
+                let len = if n == 0 { dtl as usize } else { sector_size(n) };
+                let mut st1 = ST1_END_OF_CYL;
+                for (index, sector) in sectors.iter().enumerate().cycle().take(eot as usize) {
+                    if !(sector.cylinder == c && sector.head == h && sector.id == r && sector.size_code == n) {
+                        st1 |= ST1_NO_DATA;
+                    }
+                    self.targets.push(Target { side, index, len });
+                    r = r.wrapping_add(1);
+                }
+                [ST0_ABNORMAL|hdus, st1, 0, c.wrapping_add(1), h, 1, n]
+            }
+        };
+        self.start_read(result);
+    }
+
+    fn read_id(&mut self) {
+        let hdus = self.command[1] & 7;
+        let (unit, side) = (unit_index(hdus), hdus >> 2);
+        if !self.is_ready(unit) {
+            return self.set_result(&[ST0_ABNORMAL|ST0_NOT_READY|hdus, 0, 0, 0, 0, 0, 0])
+        }
+        let drive = &mut self.drives[unit];
+        match drive.next_sector_index(side) {
+            Some(index) => {
+                let s = &drive.current_track(side).unwrap().sectors[index];
+                let result = [hdus, 0, 0, s.cylinder, s.head, s.id, s.size_code];
+                self.set_result(&result)
+            }
+            None => self.set_result(&[ST0_ABNORMAL|hdus, ST1_MISSING_AM, 0, 0, 0, 0, 0])
+        }
+    }
+
+    fn format_track(&mut self) {
+        let hdus = self.command[1] & 7;
+        let (n, sc) = (self.command[2], self.command[3]);
+        let (unit, side) = (unit_index(hdus), hdus >> 2);
+        self.targets.clear();
+        let result = match self.drives[unit].disk.as_ref() {
+            Some(disk) if self.motor && side < disk.sides() => {
+                if disk.is_write_protected() {
+                    [ST0_ABNORMAL|hdus, ST1_NOT_WRITABLE, 0, 0, 0, 0, n]
+                }
+                else {
+                    self.targets.push(Target { side, index: 0, len: 4 * sc as usize });
+                    [hdus, 0, 0, 0, 0, 0, n]
+                }
+            }
+            _ => [ST0_ABNORMAL|ST0_NOT_READY|hdus, 0, 0, 0, 0, 0, n]
+        };
+        self.start_write(WriteMode::Format, result);
+        if self.phase == Phase::Result && sc == 0 && !self.targets.is_empty() {
+            self.commit_write(WriteMode::Format);
+        }
+    }
+
+    fn commit_write(&mut self, mode: WriteMode) {
+        let unit = unit_index(self.command[1]);
+        let drive = &mut self.drives[unit];
+        let cylinder = drive.cylinder();
+        let disk = drive.disk.as_mut().unwrap();
+        disk.set_modified(true);
+        match mode {
+            WriteMode::Data { deleted } => {
+                let mut data = &self.data[..];
+                for &Target { side, index, len } in self.targets.iter() {
+                    let sector = &mut disk.track_mut(cylinder, side).unwrap().sectors[index];
+                    let (chunk, rest) = data.split_at(len);
+                    sector.data = chunk.into();
+                    sector.st1 &= !ST1_DATA_ERROR;
+                    sector.st2 &= !(ST2_DATA_ERROR|ST2_CONTROL_MARK);
+                    if deleted {
+                        sector.st2 |= ST2_CONTROL_MARK;
+                    }
+                    data = rest;
+                }
+            }
+            WriteMode::Format => {
+                let (n, gpl, filler) = (self.command[2], self.command[4], self.command[5]);
+                let side = (self.command[1] & 7) >> 2;
+                let track = disk.track_mut_or_insert(cylinder, side).unwrap();
+                track.gap3 = gpl;
+                track.filler = filler;
+                track.sectors = self.data.chunks_exact(4).map(|id|
+                    DiskSector::new(id[0], id[1], id[2], id[3], filler)
+                ).collect();
+                if let Some(id) = self.data.rchunks_exact(4).next() {
+                    self.result[3..7].copy_from_slice(id);
+                }
+                self.result[6] = n;
+                drive.set_next_sector_index(0);
+            }
+        }
+        self.data.clear();
+        self.targets.clear();
+        self.phase = Phase::Result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(fdc: &mut Fdc765, bytes: &[u8]) {
+        for &byte in bytes {
+            assert_eq!(fdc.read_status() & (MSR_RQM|MSR_DIO), MSR_RQM);
+            fdc.write_data(byte);
+        }
+    }
+
+    fn read_bytes(fdc: &mut Fdc765, count: usize, status: u8) -> Vec<u8> {
+        (0..count).map(|_| {
+            assert_eq!(fdc.read_status() & !3, status);
+            fdc.read_data()
+        }).collect()
+    }
+
+    fn result(fdc: &mut Fdc765) -> Vec<u8> {
+        let mut res = Vec::new();
+        while fdc.read_status() & MSR_CB != 0 {
+            assert_eq!(fdc.read_status() & !3, MSR_RQM|MSR_DIO|MSR_CB);
+            res.push(fdc.read_data());
+        }
+        res
+    }
+
+    fn test_disk() -> FloppyDisk {
+        let mut disk = FloppyDisk::new_formatted(40, 1, 9, 1, 2, 0xE5);
+        for cylinder in 0..40 {
+            for sector in disk.track_mut(cylinder, 0).unwrap().sectors.iter_mut() {
+                for (i, byte) in sector.data.iter_mut().enumerate() {
+                    *byte = (i + cylinder as usize + sector.id as usize) as u8;
+                }
+            }
+        }
+        disk
+    }
+
+    #[test]
+    fn fdc765_seek_sense_works() {
+        let mut fdc = Fdc765::default();
+        assert_eq!(fdc.read_status(), MSR_RQM);
+        command(&mut fdc, &[CMD_VERSION]);
+        assert_eq!(result(&mut fdc), [0x80]);
+        command(&mut fdc, &[CMD_SENSE_INT_STATUS]);
+        assert_eq!(result(&mut fdc), [0x80]);
+        command(&mut fdc, &[CMD_SPECIFY, 0xAF, 0x03]);
+        assert_eq!(fdc.read_status(), MSR_RQM);
+        command(&mut fdc, &[CMD_SENSE_DRIVE_STATUS, 0]);
+        assert_eq!(result(&mut fdc), [ST3_TRACK0]);
+        command(&mut fdc, &[CMD_RECALIBRATE, 0]);
+        assert_eq!(fdc.read_status(), MSR_RQM|1);
+        command(&mut fdc, &[CMD_SENSE_INT_STATUS]);
+        assert_eq!(result(&mut fdc), [ST0_SEEK_END|ST0_ABNORMAL|ST0_NOT_READY, 0]);
+
+        assert!(fdc.insert_disk(1, test_disk()).is_none());
+        fdc.set_motor(true);
+        command(&mut fdc, &[CMD_SEEK, 1, 5]);
+        assert_eq!(fdc.read_status(), MSR_RQM|2);
+        command(&mut fdc, &[CMD_SENSE_INT_STATUS]);
+        assert_eq!(result(&mut fdc), [ST0_SEEK_END|1, 5]);
+        assert_eq!(fdc.read_status(), MSR_RQM);
+        command(&mut fdc, &[CMD_SENSE_DRIVE_STATUS, 1]);
+        assert_eq!(result(&mut fdc), [ST3_READY|1]);
+        command(&mut fdc, &[CMD_READ_ID, 1]);
+        assert_eq!(result(&mut fdc), [1, 0, 0, 5, 0, 1, 2]);
+        command(&mut fdc, &[CMD_READ_ID, 1]);
+        assert_eq!(result(&mut fdc), [1, 0, 0, 5, 0, 2, 2]);
+        command(&mut fdc, &[CMD_READ_ID, 5]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL|5, ST1_MISSING_AM, 0, 0, 0, 0, 0]);
+        command(&mut fdc, &[0x1F]);
+        assert_eq!(result(&mut fdc), [ST0_INVALID]);
+    }
+
+    #[test]
+    fn fdc765_read_write_works() {
+        let mut fdc = Fdc765::default();
+        fdc.insert_disk(0, test_disk());
+        fdc.set_motor(true);
+        command(&mut fdc, &[CMD_SEEK, 0, 2]);
+        command(&mut fdc, &[CMD_SENSE_INT_STATUS]);
+        assert_eq!(result(&mut fdc), [ST0_SEEK_END, 2]);
+        // read 2 sectors
+        command(&mut fdc, &[CMD_READ_DATA|0x40, 0, 2, 0, 3, 2, 4, 0x2A, 0xFF]);
+        let data = read_bytes(&mut fdc, 1024, MSR_RQM|MSR_DIO|MSR_EXM|MSR_CB);
+        assert!(data[..512].iter().enumerate().all(|(i, &b)| b == (i + 2 + 3) as u8));
+        assert!(data[512..].iter().enumerate().all(|(i, &b)| b == (i + 2 + 4) as u8));
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_END_OF_CYL, 0, 3, 0, 1, 2]);
+        // sector not found
+        command(&mut fdc, &[CMD_READ_DATA, 0, 2, 0, 10, 2, 10, 0x2A, 0xFF]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_NO_DATA, 0, 2, 0, 10, 2]);
+        command(&mut fdc, &[CMD_READ_DATA, 0, 1, 0, 1, 2, 1, 0x2A, 0xFF]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_NO_DATA, ST2_WRONG_CYL, 1, 0, 1, 2]);
+        // write deleted data
+        command(&mut fdc, &[CMD_WRITE_DELETED_DATA|0x40, 0, 2, 0, 9, 2, 9, 0x2A, 0xFF]);
+        for i in 0..512 {
+            assert_eq!(fdc.read_status(), MSR_RQM|MSR_EXM|MSR_CB);
+            fdc.write_data(i as u8 ^ 0x55);
+        }
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_END_OF_CYL, 0, 3, 0, 1, 2]);
+        let disk = fdc.disk_ref(0).unwrap();
+        assert!(disk.is_modified());
+        let sector = &disk.track(2, 0).unwrap().sectors[8];
+        assert!(sector.is_deleted());
+        assert!(sector.data.iter().enumerate().all(|(i, &b)| b == i as u8 ^ 0x55));
+        // skip deleted data
+        command(&mut fdc, &[CMD_READ_DATA|CMD_SK, 0, 2, 0, 8, 2, 9, 0x2A, 0xFF]);
+        let data = read_bytes(&mut fdc, 512, MSR_RQM|MSR_DIO|MSR_EXM|MSR_CB);
+        assert_eq!(data[0], 2 + 8);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_END_OF_CYL, ST2_CONTROL_MARK, 3, 0, 1, 2]);
+        command(&mut fdc, &[CMD_READ_DELETED_DATA, 0, 2, 0, 9, 2, 9, 0x2A, 0xFF]);
+        let data = read_bytes(&mut fdc, 512, MSR_RQM|MSR_DIO|MSR_EXM|MSR_CB);
+        assert_eq!(data[1], 1 ^ 0x55);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_END_OF_CYL, 0, 3, 0, 1, 2]);
+        // write protection
+        fdc.disk_mut(0).unwrap().set_write_protected(true);
+        command(&mut fdc, &[CMD_WRITE_DATA, 0, 2, 0, 1, 2, 1, 0x2A, 0xFF]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_NOT_WRITABLE, 0, 2, 0, 1, 2]);
+        // disk swapping
+        let disk = fdc.eject_disk(0).unwrap();
+        command(&mut fdc, &[CMD_READ_DATA, 0, 2, 0, 1, 2, 1, 0x2A, 0xFF]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL|ST0_NOT_READY, 0, 0, 2, 0, 1, 2]);
+        fdc.insert_disk(1, disk);
+        command(&mut fdc, &[CMD_READ_DATA, 1, 0, 0, 1, 2, 1, 0x2A, 0xFF]);
+        assert_eq!(read_bytes(&mut fdc, 512, MSR_RQM|MSR_DIO|MSR_EXM|MSR_CB)[0], 1);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL|1, ST1_END_OF_CYL, 0, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn fdc765_format_works() {
+        let mut fdc = Fdc765::default();
+        fdc.insert_disk(0, FloppyDisk::new(1, 1));
+        fdc.set_motor(true);
+        command(&mut fdc, &[CMD_SEEK, 0, 3]);
+        command(&mut fdc, &[CMD_SENSE_INT_STATUS]);
+        assert_eq!(result(&mut fdc), [ST0_SEEK_END, 3]);
+        command(&mut fdc, &[CMD_READ_ID, 0]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_MISSING_AM, 0, 0, 0, 0, 0]);
+        command(&mut fdc, &[CMD_FORMAT_TRACK|0x40, 0, 1, 3, 0x52, 0xAA]);
+        for id in &[3, 0, 7, 1, 3, 0, 8, 1, 3, 0, 9, 1] {
+            assert_eq!(fdc.read_status(), MSR_RQM|MSR_EXM|MSR_CB);
+            fdc.write_data(*id);
+        }
+        assert_eq!(result(&mut fdc), [0, 0, 0, 3, 0, 9, 1]);
+        let disk = fdc.disk_ref(0).unwrap();
+        assert_eq!(disk.cylinders(), 4);
+        let track = disk.track(3, 0).unwrap();
+        assert_eq!(track.sectors.iter().map(|s| s.id).collect::<Vec<_>>(), [7, 8, 9]);
+        assert!(track.sectors.iter().all(|s| s.data.len() == 256 && s.data.iter().all(|&b| b == 0xAA)));
+        command(&mut fdc, &[CMD_READ_TRACK, 0, 3, 0, 7, 1, 2, 0x2A, 0xFF]);
+        assert_eq!(read_bytes(&mut fdc, 512, MSR_RQM|MSR_DIO|MSR_EXM|MSR_CB), vec![0xAA;512]);
+        assert_eq!(result(&mut fdc), [ST0_ABNORMAL, ST1_END_OF_CYL, 0, 4, 0, 1, 1]);
+    }
+}
@@ -0,0 +1,342 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! Floppy disks and drives used by the floppy disk controller emulators.
+#[cfg(feature = "snapshot")] mod serde;
+#[cfg(feature = "snapshot")]
+use ::serde::{Serialize, Deserialize};
+
+/// The maximum number of cylinders the emulated floppy drive head can reach.
+pub const MAX_CYLINDERS: u8 = 84;
+
+/// The controller's *status register 1* flag stored with a sector: data error (CRC) in the ID or data field.
+pub const ST1_DATA_ERROR: u8 = 0b0010_0000;
+/// The controller's *status register 2* flag stored with a sector: the deleted data address mark.
+pub const ST2_CONTROL_MARK: u8 = 0b0100_0000;
+/// The controller's *status register 2* flag stored with a sector: data error (CRC) in the data field.
+pub const ST2_DATA_ERROR: u8 = 0b0010_0000;
+
+/// This struct represents a single sector of a [DiskTrack].
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct DiskSector {
+    /// The cylinder number from the sector ID field (C).
+    pub cylinder: u8,
+    /// The head number from the sector ID field (H).
+    pub head: u8,
+    /// The sector number from the sector ID field (R).
+    pub id: u8,
+    /// The size code from the sector ID field (N). The nominal sector size is `128 << N`.
+    pub size_code: u8,
+    /// The *status register 1* flags reported by the controller when the sector is being read.
+    pub st1: u8,
+    /// The *status register 2* flags reported by the controller when the sector is being read.
+    pub st2: u8,
+    /// The data of the sector.
+    ///
+    /// The data length may differ from the nominal sector size, e.g. for copy protected sectors.
+    #[cfg_attr(feature = "snapshot", serde(with = "self::serde::sector_data"))]
+    pub data: Box<[u8]>
+}
+
+/// This struct represents a single track of a [FloppyDisk].
+///
+/// A track without sectors is an unformatted track.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct DiskTrack {
+    /// The length of the gap #3 used when formatting the track.
+    pub gap3: u8,
+    /// The filler byte used when formatting the track.
+    pub filler: u8,
+    /// The sectors in the order they appear on the track.
+    pub sectors: Vec<DiskSector>
+}
+
+/// This struct represents an emulated floppy disk.
+///
+/// Instances of this struct can be "inserted" into [FloppyDrive]s.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct FloppyDisk {
+    sides: u8,
+    tracks: Vec<DiskTrack>,
+    write_protected: bool,
+    modified: bool
+}
+
+/// This struct represents an emulated floppy disk drive.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct FloppyDrive {
+    /// The disk in the drive.
+    pub disk: Option<FloppyDisk>,
+    cylinder: u8,
+    rotation: usize
+}
+
+impl DiskSector {
+    /// Creates a new sector with the given ID field values and data filled with `filler`.
+    pub fn new(cylinder: u8, head: u8, id: u8, size_code: u8, filler: u8) -> Self {
+        let data = vec![filler;sector_size(size_code)].into_boxed_slice();
+        DiskSector { cylinder, head, id, size_code, st1: 0, st2: 0, data }
+    }
+    /// Returns `true` if the sector has a deleted data address mark.
+    pub fn is_deleted(&self) -> bool {
+        self.st2 & ST2_CONTROL_MARK != 0
+    }
+    /// Returns `true` if reading the sector data should result in a data error (CRC).
+    pub fn has_data_error(&self) -> bool {
+        self.st1 & ST1_DATA_ERROR != 0 || self.st2 & ST2_DATA_ERROR != 0
+    }
+}
+
+/// Returns the nominal sector size in bytes from the sector size code (N).
+///
+/// Size codes above 7 are being treated as 7.
+#[inline]
+pub fn sector_size(size_code: u8) -> usize {
+    128 << size_code.min(7)
+}
+
+impl FloppyDisk {
+    /// Creates a new disk with the given number of `cylinders` and `sides`, with all tracks unformatted.
+    ///
+    /// # Panics
+    /// Panics if `sides` is not 1 or 2.
+    pub fn new(cylinders: u8, sides: u8) -> Self {
+        assert!(sides == 1 || sides == 2);
+        let tracks = vec![DiskTrack::default();cylinders as usize * sides as usize];
+        FloppyDisk { sides, tracks, write_protected: false, modified: false }
+    }
+    /// Creates a new disk with the given number of `cylinders` and `sides`, with each track formatted
+    /// with `sectors` number of sectors, numbered from `first_id`, each of `128 << size_code` bytes
+    /// filled with `filler`.
+    ///
+    /// # Panics
+    /// Panics if `sides` is not 1 or 2.
+    pub fn new_formatted(
+            cylinders: u8,
+            sides: u8,
+            sectors: u8,
+            first_id: u8,
+            size_code: u8,
+            filler: u8
+        ) -> Self
+    {
+        let mut disk = FloppyDisk::new(cylinders, sides);
+        for cylinder in 0..cylinders {
+            for head in 0..sides {
+                let track = disk.track_mut(cylinder, head).unwrap();
+                track.gap3 = 0x52;
+                track.filler = filler;
+                track.sectors = (0..sectors).map(|n|
+                    DiskSector::new(cylinder, head, first_id.wrapping_add(n), size_code, filler)
+                ).collect();
+            }
+        }
+        disk
+    }
+    /// Creates a new disk from the given tracks ordered by cylinders, then sides.
+    ///
+    /// # Panics
+    /// Panics if `sides` is not 1 or 2.
+    pub fn from_tracks(sides: u8, tracks: Vec<DiskTrack>) -> Self {
+        assert!(sides == 1 || sides == 2);
+        FloppyDisk { sides, tracks, write_protected: false, modified: false }
+    }
+    /// Returns the number of sides of the disk.
+    #[inline]
+    pub fn sides(&self) -> u8 {
+        self.sides
+    }
+    /// Returns the number of cylinders of the disk.
+    #[inline]
+    pub fn cylinders(&self) -> u8 {
+        (self.tracks.len() / self.sides as usize) as u8
+    }
+    /// Returns a slice of all tracks ordered by cylinders, then sides.
+    #[inline]
+    pub fn tracks(&self) -> &[DiskTrack] {
+        &self.tracks
+    }
+    /// Returns a reference to the track at the given `cylinder` and `side`.
+    pub fn track(&self, cylinder: u8, side: u8) -> Option<&DiskTrack> {
+        if side < self.sides {
+            self.tracks.get(cylinder as usize * self.sides as usize + side as usize)
+        }
+        else {
+            None
+        }
+    }
+    /// Returns a mutable reference to the track at the given `cylinder` and `side`.
+    ///
+    /// The disk is not being marked as modified by this method.
+    pub fn track_mut(&mut self, cylinder: u8, side: u8) -> Option<&mut DiskTrack> {
+        if side < self.sides {
+            self.tracks.get_mut(cylinder as usize * self.sides as usize + side as usize)
+        }
+        else {
+            None
+        }
+    }
+    /// Returns a mutable reference to the track at the given `cylinder` and `side`, adding unformatted
+    /// cylinders if necessary.
+    ///
+    /// Returns `None` if `side` exceeds the number of sides or `cylinder` is not below [MAX_CYLINDERS].
+    pub fn track_mut_or_insert(&mut self, cylinder: u8, side: u8) -> Option<&mut DiskTrack> {
+        if side >= self.sides || cylinder >= MAX_CYLINDERS {
+            return None
+        }
+        let index = cylinder as usize * self.sides as usize + side as usize;
+        if index >= self.tracks.len() {
+            self.tracks.resize((cylinder as usize + 1) * self.sides as usize, DiskTrack::default());
+        }
+        self.tracks.get_mut(index)
+    }
+    /// Returns `true` if the disk is write protected.
+    #[inline]
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protected
+    }
+    /// Changes the write protected flag of the disk.
+    #[inline]
+    pub fn set_write_protected(&mut self, protect: bool) {
+        self.write_protected = protect;
+    }
+    /// Returns `true` if the disk has been written to by the controller since the last
+    /// [FloppyDisk::set_modified] call with `false`.
+    #[inline]
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+    /// Changes the modified flag of the disk.
+    #[inline]
+    pub fn set_modified(&mut self, modified: bool) {
+        self.modified = modified;
+    }
+}
+
+impl FloppyDrive {
+    /// Returns the cylinder number at which the drive head is positioned.
+    #[inline]
+    pub fn cylinder(&self) -> u8 {
+        self.cylinder
+    }
+    /// Moves the drive head to the given `cylinder`, limited by [MAX_CYLINDERS].
+    #[inline]
+    pub fn seek(&mut self, cylinder: u8) {
+        self.cylinder = cylinder.min(MAX_CYLINDERS - 1);
+    }
+    /// Returns `true` if there is a disk in the drive.
+    #[inline]
+    pub fn has_disk(&self) -> bool {
+        self.disk.is_some()
+    }
+    /// Inserts a `disk` into the drive optionally returning a disk that was previously in the drive.
+    pub fn insert_disk(&mut self, disk: FloppyDisk) -> Option<FloppyDisk> {
+        self.rotation = 0;
+        self.disk.replace(disk)
+    }
+    /// Removes and optionally returns a disk from the drive.
+    pub fn eject_disk(&mut self) -> Option<FloppyDisk> {
+        self.disk.take()
+    }
+    /// Returns a reference to the track under the drive head on the given `side`.
+    pub fn current_track(&self, side: u8) -> Option<&DiskTrack> {
+        self.disk.as_ref().and_then(|disk| disk.track(self.cylinder, side))
+    }
+    /// Returns the index of the sector which is going to pass under the drive head next, advancing
+    /// the rotation of the disk to the following sector.
+    ///
+    /// Returns `None` if the track under the head on the given `side` is unformatted or doesn't exist.
+    pub fn next_sector_index(&mut self, side: u8) -> Option<usize> {
+        let count = self.current_track(side).map(|track| track.sectors.len()).unwrap_or(0);
+        if count == 0 {
+            return None
+        }
+        let index = self.rotation % count;
+        self.rotation = index + 1;
+        Some(index)
+    }
+    /// Sets the rotation of the disk, so the sector with the given `index` is going to be the next one.
+    #[inline]
+    pub fn set_next_sector_index(&mut self, index: usize) {
+        self.rotation = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floppy_disk_works() {
+        let mut disk = FloppyDisk::new_formatted(40, 2, 9, 0xC1, 2, 0xE5);
+        assert_eq!(disk.sides(), 2);
+        assert_eq!(disk.cylinders(), 40);
+        assert_eq!(disk.tracks().len(), 80);
+        let track = disk.track(39, 1).unwrap();
+        assert_eq!(track.sectors.len(), 9);
+        assert_eq!(track.sectors[8], DiskSector::new(39, 1, 0xC9, 2, 0xE5));
+        assert_eq!(track.sectors[0].data.len(), 512);
+        assert!(disk.track(40, 0).is_none());
+        assert!(disk.track(0, 2).is_none());
+        assert!(disk.track_mut_or_insert(0, 2).is_none());
+        assert!(disk.track_mut_or_insert(MAX_CYLINDERS, 0).is_none());
+        // new cylinders are unformatted
+        assert_eq!(disk.track_mut_or_insert(41, 1), Some(&mut DiskTrack::default()));
+        assert_eq!(disk.cylinders(), 42);
+        assert!(!disk.is_modified());
+        assert_eq!(sector_size(9), 16384);
+
+        let mut sector = DiskSector::new(0, 0, 1, 1, 0);
+        assert!(!sector.is_deleted() && !sector.has_data_error());
+        sector.st2 = ST2_CONTROL_MARK;
+        assert!(sector.is_deleted() && !sector.has_data_error());
+        sector.st1 = ST1_DATA_ERROR;
+        assert!(sector.has_data_error());
+        sector.st1 = 0;
+        sector.st2 = ST2_DATA_ERROR;
+        assert!(!sector.is_deleted() && sector.has_data_error());
+    }
+
+    #[test]
+    fn floppy_drive_works() {
+        let mut drive = FloppyDrive::default();
+        assert!(!drive.has_disk());
+        assert_eq!(drive.next_sector_index(0), None);
+        let mut disk = FloppyDisk::new_formatted(2, 1, 3, 1, 1, 0);
+        disk.track_mut(1, 0).unwrap().sectors.clear();
+        assert_eq!(drive.insert_disk(disk.clone()), None);
+        assert!(drive.has_disk());
+        // the sectors are passing under the head in a loop
+        let indexes: Vec<_> = (0..5).map(|_| drive.next_sector_index(0).unwrap()).collect();
+        assert_eq!(indexes, [0, 1, 2, 0, 1]);
+        assert_eq!(drive.next_sector_index(1), None);
+        drive.set_next_sector_index(1);
+        assert_eq!(drive.next_sector_index(0), Some(1));
+        // an unformatted track
+        drive.seek(1);
+        assert!(drive.current_track(0).unwrap().sectors.is_empty());
+        assert_eq!(drive.next_sector_index(0), None);
+        drive.seek(u8::MAX);
+        assert_eq!(drive.cylinder(), MAX_CYLINDERS - 1);
+        assert_eq!(drive.current_track(0), None);
+        // inserting a disk rewinds it
+        drive.seek(0);
+        drive.next_sector_index(0);
+        assert_eq!(drive.insert_disk(disk.clone()), Some(disk.clone()));
+        assert_eq!(drive.next_sector_index(0), Some(0));
+        assert_eq!(drive.eject_disk(), Some(disk));
+        assert!(!drive.has_disk());
+    }
+}
@@ -0,0 +1,34 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+pub mod sector_data {
+    use serde::{Serializer, Deserialize, Deserializer, de};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::encode(data))
+        }
+        else {
+            serializer.serialize_bytes(data)
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Box<[u8]>, D::Error>
+        where D: Deserializer<'de>
+    {
+        if deserializer.is_human_readable() {
+            Deserialize::deserialize(deserializer).and_then(|string: &str|
+                base64::decode(string).map_err(|err| de::Error::custom(err.to_string()))
+            )
+            .map(Vec::into_boxed_slice)
+        }
+        else {
+            let buf: Vec<u8> = Deserialize::deserialize(deserializer)?;
+            Ok(buf.into_boxed_slice())
+        }
+    }
+}
@@ -0,0 +1,868 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An emulator of the Western Digital WD1793 and WD1772 floppy disk controllers.
+/*!
+The controller is accessed via 4 registers:
+
+* *command* (write) and *status* (read) registers,
+* the *track* register,
+* the *sector* register,
+* the *data* register.
+
+The meaning of the status register bits depends on the type of the last command:
+```text
+          Bit    7     6      5      4      3     2     1     0
+               +------------------------------------------------+
+         Type I|not |write|head  |seek  |CRC  |track|index|busy|
+               |ready|prot.|loaded|error |error|  0  |     |    |
+               |-----+-----+------+------+-----+-----+-----+----|
+Type II and III|not |write|record|record|CRC  |lost |DRQ  |busy|
+               |ready|prot.|type  |not f.|error|data |     |    |
+               +------------------------------------------------+
+```
+
+The [WD1772][WdModel::Wd1772] variant reports the *motor on* state in bit 7 instead of *not ready*
+and the *spin-up* completion in bit 5 of the type I status instead of *head loaded*.
+
+All commands are supported: `RESTORE`, `SEEK`, `STEP`, `STEP IN`, `STEP OUT`, `READ SECTOR`, `WRITE SECTOR`,
+`READ ADDRESS`, `READ TRACK`, `WRITE TRACK` and `FORCE INTERRUPT`.
+
+The emulation is not timed:
+
+* Commands are executed instantly and the data is available as soon as the command is issued.
+* The index pulse is being emulated by toggling the *index* status bit while the type I status is being polled.
+* `WRITE TRACK` ends after [RAW_TRACK_SIZE] bytes has been written and `READ TRACK` produces a synthesized
+  double density track image from the sectors found on the track.
+
+The drive and the disk side are selected externally, and the drive is *ready* when there is a disk
+inserted into it.
+
+The WD1772 has no *ready* input, so the type II and III commands end with the *record not found* status
+if there is no disk in the selected drive. The *side compare* flag of the WD1793 commands is not
+recognized by the WD1772 and the *motor on* status bit is set only while a command is in progress.
+
+!*/
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+pub use super::floppy::*;
+
+/// The number of drives that can be connected to the controller.
+pub const MAX_DRIVES: usize = 4;
+/// The number of bytes of a raw double density track.
+pub const RAW_TRACK_SIZE: usize = 6250;
+
+/// Status register: the drive is not ready.
+pub const STATUS_NOT_READY:    u8 = 0b1000_0000;
+/// Status register (WD1772): the motor is on.
+pub const STATUS_MOTOR_ON:     u8 = 0b1000_0000;
+/// Status register: the disk is write protected.
+pub const STATUS_WRITE_PROT:   u8 = 0b0100_0000;
+/// Status register (type I): the head is loaded.
+pub const STATUS_HEAD_LOADED:  u8 = 0b0010_0000;
+/// Status register (type I, WD1772): the motor spin-up sequence has been completed.
+pub const STATUS_SPIN_UP:      u8 = 0b0010_0000;
+/// Status register (type II and III): the deleted data address mark has been found.
+pub const STATUS_RECORD_TYPE:  u8 = 0b0010_0000;
+/// Status register (type I): the desired track has not been verified.
+pub const STATUS_SEEK_ERROR:   u8 = 0b0001_0000;
+/// Status register (type II and III): the desired sector has not been found.
+pub const STATUS_RNF:          u8 = 0b0001_0000;
+/// Status register: CRC error.
+pub const STATUS_CRC_ERROR:    u8 = 0b0000_1000;
+/// Status register (type I): the head is positioned at track 0.
+pub const STATUS_TRACK0:       u8 = 0b0000_0100;
+/// Status register (type II and III): the data has been lost.
+pub const STATUS_LOST_DATA:    u8 = 0b0000_0100;
+/// Status register (type I): the index pulse.
+pub const STATUS_INDEX:        u8 = 0b0000_0010;
+/// Status register (type II and III): the data register is ready.
+pub const STATUS_DRQ:          u8 = 0b0000_0010;
+/// Status register: the controller is busy.
+pub const STATUS_BUSY:         u8 = 0b0000_0001;
+
+const CMD_SEEK:         u8 = 0x10;
+const CMD_STEP_IN:      u8 = 0x40;
+const CMD_STEP_OUT:     u8 = 0x60;
+const CMD_READ_SECTOR:  u8 = 0x80;
+const CMD_WRITE_SECTOR: u8 = 0xA0;
+const CMD_READ_ADDRESS: u8 = 0xC0;
+const CMD_FORCE_INT:    u8 = 0xD0;
+const CMD_READ_TRACK:   u8 = 0xE0;
+const CMD_WRITE_TRACK:  u8 = 0xF0;
+
+const FLAG_UPDATE:       u8 = 0b0001_0000;
+const FLAG_HEAD_LOAD:    u8 = 0b0000_1000;
+const FLAG_SPIN_UP_OFF:  u8 = 0b0000_1000;
+const FLAG_VERIFY:       u8 = 0b0000_0100;
+const FLAG_MULTIPLE:     u8 = 0b0001_0000;
+const FLAG_SIDE:         u8 = 0b0000_1000;
+const FLAG_SIDE_COMPARE: u8 = 0b0000_0010;
+const FLAG_DELETED_MARK: u8 = 0b0000_0001;
+const FLAG_INT_MASK:     u8 = 0b0000_1111;
+
+const INDEX_PERIOD: u8 = 64;
+const INDEX_WIDTH:  u8 = 4;
+
+/// The model of the emulated controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub enum WdModel {
+    /// The WD1793 (and compatible) controller used by the Beta 128 interface.
+    Wd1793,
+    /// The WD1772 controller used by the MGT +D and DISCiPLE interfaces.
+    Wd1772
+}
+
+impl Default for WdModel {
+    fn default() -> Self {
+        WdModel::Wd1793
+    }
+}
+
+/// The emulated Western Digital WD1793 or WD1772 floppy disk controller with 4 connected [FloppyDrive]s.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Wd1793 {
+    /// Direct access to the floppy drives.
+    pub drives: [FloppyDrive;MAX_DRIVES],
+    #[cfg_attr(feature = "snapshot", serde(default))]
+    model: WdModel,
+    unit: u8,
+    side: u8,
+    command: u8,
+    status: u8,
+    track: u8,
+    sector: u8,
+    data: u8,
+    step_in: bool,
+    intrq: bool,
+    index_counter: u8,
+    phase: Phase,
+    buffer: Vec<u8>,
+    buffer_index: usize,
+    end_status: u8
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+enum Phase {
+    Idle,
+    Read,
+    WriteSector { index: usize, deleted: bool },
+    WriteTrack
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase::Idle
+    }
+}
+
+impl Wd1793 {
+    /// Creates a new controller of the given `model`.
+    pub fn new(model: WdModel) -> Self {
+        Wd1793 { model, sector: 1, ..Wd1793::default() }
+    }
+    /// Returns the model of the controller.
+    #[inline]
+    pub fn model(&self) -> WdModel {
+        self.model
+    }
+    /// Inserts a `disk` into the drive `unit` (0 to 3), optionally returning a disk that was previously
+    /// in the same drive.
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 3.
+    pub fn insert_disk(&mut self, unit: usize, disk: FloppyDisk) -> Option<FloppyDisk> {
+        self.drives[unit].insert_disk(disk)
+    }
+    /// Removes and optionally returns a disk from the drive `unit` (0 to 3).
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 3.
+    pub fn eject_disk(&mut self, unit: usize) -> Option<FloppyDisk> {
+        self.drives[unit].eject_disk()
+    }
+    /// Returns a reference to a disk if it's present in the drive `unit` (0 to 3).
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 3.
+    pub fn disk_ref(&self, unit: usize) -> Option<&FloppyDisk> {
+        self.drives[unit].disk.as_ref()
+    }
+    /// Returns a mutable reference to a disk if it's present in the drive `unit` (0 to 3).
+    ///
+    /// # Panics
+    /// Panics if `unit` is above 3.
+    pub fn disk_mut(&mut self, unit: usize) -> Option<&mut FloppyDisk> {
+        self.drives[unit].disk.as_mut()
+    }
+    /// Returns the index of the selected drive.
+    #[inline]
+    pub fn selected_drive(&self) -> usize {
+        self.unit as usize
+    }
+    /// Selects the drive `unit`. Only the lowest 2 bits of `unit` are being used.
+    #[inline]
+    pub fn select_drive(&mut self, unit: u8) {
+        self.unit = unit & 3;
+    }
+    /// Returns the selected disk side.
+    #[inline]
+    pub fn side(&self) -> u8 {
+        self.side
+    }
+    /// Selects the disk side. Only the lowest bit of `side` is being used.
+    #[inline]
+    pub fn select_side(&mut self, side: u8) {
+        self.side = side & 1;
+    }
+    /// Returns the state of the interrupt request (`INTRQ`) output.
+    #[inline]
+    pub fn is_intrq(&self) -> bool {
+        self.intrq
+    }
+    /// Returns the state of the data request (`DRQ`) output.
+    #[inline]
+    pub fn is_drq(&self) -> bool {
+        self.phase != Phase::Idle
+    }
+    /// Resets the controller, aborting any command in progress and executing the `RESTORE` command.
+    ///
+    /// Disks remain in the drives.
+    pub fn reset(&mut self) {
+        let drives = core::mem::take(&mut self.drives);
+        *self = Wd1793 { drives, model: self.model, unit: self.unit, side: self.side, sector: 1,
+                         ..Wd1793::default() };
+        self.step(0x03);
+    }
+    /// Reads the status register. Clears the interrupt request.
+    pub fn read_status(&mut self) -> u8 {
+        self.intrq = false;
+        let ready = self.is_ready();
+        let mut status = self.status;
+        if self.is_type1_status() {
+            let drive = &self.drives[self.unit as usize];
+            if ready {
+                if drive.disk.as_ref().unwrap().is_write_protected() {
+                    status |= STATUS_WRITE_PROT;
+                }
+                self.index_counter = self.index_counter.wrapping_add(1);
+                if self.index_counter % INDEX_PERIOD < INDEX_WIDTH {
+                    status |= STATUS_INDEX;
+                }
+            }
+            if drive.cylinder() == 0 {
+                status |= STATUS_TRACK0;
+            }
+        }
+        match self.model {
+            WdModel::Wd1793 if !ready => status |= STATUS_NOT_READY,
+            WdModel::Wd1772 if status & STATUS_BUSY != 0 => status |= STATUS_MOTOR_ON,
+            _ => {}
+        }
+        status
+    }
+    /// Writes the command register. Clears the interrupt request.
+    ///
+    /// Commands other than `FORCE INTERRUPT` are being ignored while the controller is busy.
+    pub fn write_command(&mut self, cmd: u8) {
+        self.intrq = false;
+        if cmd & 0xF0 == CMD_FORCE_INT {
+            self.command = cmd;
+            self.phase = Phase::Idle;
+            self.buffer.clear();
+            self.status &= !(STATUS_BUSY|STATUS_DRQ);
+            self.intrq = cmd & FLAG_INT_MASK != 0;
+            return
+        }
+        if self.status & STATUS_BUSY != 0 {
+            return
+        }
+        self.command = cmd;
+        match cmd & 0xE0 {
+            CMD_READ_SECTOR => self.read_sector(),
+            CMD_WRITE_SECTOR => self.write_sector(),
+            CMD_READ_ADDRESS => self.read_address(),
+            CMD_READ_TRACK => if cmd & 0xF0 == CMD_WRITE_TRACK {
+                self.write_track()
+            }
+            else {
+                self.read_track()
+            }
+            _ => self.step(cmd)
+        }
+    }
+    /// Reads the track register.
+    #[inline]
+    pub fn read_track_reg(&self) -> u8 {
+        self.track
+    }
+    /// Writes the track register.
+    #[inline]
+    pub fn write_track_reg(&mut self, track: u8) {
+        self.track = track;
+    }
+    /// Reads the sector register.
+    #[inline]
+    pub fn read_sector_reg(&self) -> u8 {
+        self.sector
+    }
+    /// Writes the sector register.
+    #[inline]
+    pub fn write_sector_reg(&mut self, sector: u8) {
+        self.sector = sector;
+    }
+    /// Reads the data register.
+    pub fn read_data(&mut self) -> u8 {
+        if self.phase == Phase::Read {
+            self.data = self.buffer.get(self.buffer_index).copied().unwrap_or(u8::MAX);
+            self.buffer_index += 1;
+            if self.buffer_index >= self.buffer.len() {
+                if self.command & 0xE0 == CMD_READ_SECTOR && self.command & FLAG_MULTIPLE != 0
+                   && self.end_status & STATUS_CRC_ERROR == 0
+                {
+                    self.sector = self.sector.wrapping_add(1);
+                    self.read_sector();
+                }
+                else {
+                    self.end_command(self.end_status);
+                }
+            }
+        }
+        self.data
+    }
+    /// Writes the data register.
+    pub fn write_data(&mut self, data: u8) {
+        self.data = data;
+        match self.phase {
+            Phase::WriteSector { index, deleted } => {
+                self.buffer.push(data);
+                if self.buffer.len() >= self.buffer_index {
+                    self.commit_sector(index, deleted);
+                }
+            }
+            Phase::WriteTrack => {
+                self.buffer.push(data);
+                if self.buffer.len() >= RAW_TRACK_SIZE {
+                    self.commit_track();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_type1_status(&self) -> bool {
+        self.command & 0x80 == 0 || self.command & 0xF0 == CMD_FORCE_INT
+    }
+
+    fn is_ready(&self) -> bool {
+        self.drives[self.unit as usize].has_disk()
+    }
+
+    fn end_not_ready(&mut self) {
+        let status = match self.model {
+            WdModel::Wd1793 => STATUS_NOT_READY,
+            WdModel::Wd1772 => STATUS_RNF
+        };
+        self.end_command(status)
+    }
+
+    fn end_command(&mut self, status: u8) {
+        self.phase = Phase::Idle;
+        self.buffer.clear();
+        self.buffer_index = 0;
+        self.status = status;
+        self.intrq = true;
+    }
+
+    fn start_read(&mut self, data: Vec<u8>, status: u8, end_status: u8) {
+        self.buffer = data;
+        self.buffer_index = 0;
+        self.end_status = end_status;
+        self.status = status|STATUS_BUSY|STATUS_DRQ;
+        self.phase = Phase::Read;
+    }
+
+    fn step(&mut self, cmd: u8) {
+        let drive = &mut self.drives[self.unit as usize];
+        let cylinder = drive.cylinder();
+        match cmd & 0xF0 {
+            0x00 => {
+                drive.seek(0);
+                self.track = 0;
+            }
+            CMD_SEEK => {
+                let steps = i16::from(self.data) - i16::from(self.track);
+                drive.seek(if steps > 0 {
+                    cylinder.saturating_add(steps as u8)
+                }
+                else {
+                    cylinder.saturating_sub(-steps as u8)
+                });
+                self.step_in = steps > 0;
+                self.track = self.data;
+            }
+            _ => {
+                match cmd & 0xE0 {
+                    CMD_STEP_IN => self.step_in = true,
+                    CMD_STEP_OUT => self.step_in = false,
+                    _ => {}
+                }
+                if self.step_in {
+                    drive.seek(cylinder.saturating_add(1));
+                    if cmd & FLAG_UPDATE != 0 {
+                        self.track = self.track.wrapping_add(1);
+                    }
+                }
+                else {
+                    drive.seek(cylinder.saturating_sub(1));
+                    if cmd & FLAG_UPDATE != 0 {
+                        self.track = self.track.wrapping_sub(1);
+                    }
+                }
+            }
+        }
+        let mut status = 0;
+        match self.model {
+            WdModel::Wd1793 if cmd & FLAG_HEAD_LOAD != 0 => status |= STATUS_HEAD_LOADED,
+            WdModel::Wd1772 if cmd & FLAG_SPIN_UP_OFF == 0 => status |= STATUS_SPIN_UP,
+            _ => {}
+        }
+        if cmd & FLAG_VERIFY != 0 {
+            let track = self.track;
+            let verified = self.drives[self.unit as usize].current_track(self.side)
+                               .map(|t| t.sectors.iter().any(|s| s.cylinder == track))
+                               .unwrap_or(false);
+            if !verified {
+                status |= STATUS_SEEK_ERROR;
+            }
+        }
+        self.end_command(status);
+    }
+
+    /// Finds the sector matching the track and sector registers on the current track.
+    fn find_sector(&mut self) -> Option<usize> {
+        let (track, sector, side, cmd) = (self.track, self.sector, self.side, self.command);
+        let side_compare = self.model == WdModel::Wd1793 && cmd & FLAG_SIDE_COMPARE != 0;
+        let drive = &mut self.drives[self.unit as usize];
+        let count = drive.current_track(side).map(|t| t.sectors.len()).unwrap_or(0);
+        for _ in 0..count {
+            let index = drive.next_sector_index(side)?;
+            let s = &drive.current_track(side).unwrap().sectors[index];
+            if s.cylinder == track && s.id == sector &&
+               (!side_compare || s.head == (cmd & FLAG_SIDE) >> 3)
+            {
+                return Some(index)
+            }
+        }
+        None
+    }
+
+    fn current_sector(&self, index: usize) -> &DiskSector {
+        &self.drives[self.unit as usize].current_track(self.side).unwrap().sectors[index]
+    }
+
+    fn read_sector(&mut self) {
+        if !self.is_ready() {
+            return self.end_not_ready()
+        }
+        match self.find_sector() {
+            Some(index) => {
+                let sector = self.current_sector(index);
+                let mut data = sector.data.to_vec();
+                data.resize(sector_size(sector.size_code & 3), 0);
+                let status = if sector.is_deleted() { STATUS_RECORD_TYPE } else { 0 };
+                let end_status = status | if sector.has_data_error() { STATUS_CRC_ERROR } else { 0 };
+                self.start_read(data, status, end_status);
+            }
+            None => self.end_command(STATUS_RNF)
+        }
+    }
+
+    fn is_write_protected(&self) -> bool {
+        self.drives[self.unit as usize].disk.as_ref().unwrap().is_write_protected()
+    }
+
+    fn write_sector(&mut self) {
+        if !self.is_ready() {
+            return self.end_not_ready()
+        }
+        if self.is_write_protected() {
+            return self.end_command(STATUS_WRITE_PROT)
+        }
+        match self.find_sector() {
+            Some(index) => {
+                let deleted = self.command & FLAG_DELETED_MARK != 0;
+                self.buffer.clear();
+                self.buffer_index = sector_size(self.current_sector(index).size_code & 3);
+                self.status = STATUS_BUSY|STATUS_DRQ;
+                self.phase = Phase::WriteSector { index, deleted };
+            }
+            None => self.end_command(STATUS_RNF)
+        }
+    }
+
+    fn commit_sector(&mut self, index: usize, deleted: bool) {
+        let (cylinder, side) = (self.drives[self.unit as usize].cylinder(), self.side);
+        let data = core::mem::take(&mut self.buffer).into_boxed_slice();
+        let disk = self.drives[self.unit as usize].disk.as_mut().unwrap();
+        disk.set_modified(true);
+        let sector = &mut disk.track_mut(cylinder, side).unwrap().sectors[index];
+        sector.data = data;
+        sector.st1 &= !ST1_DATA_ERROR;
+        sector.st2 &= !(ST2_DATA_ERROR|ST2_CONTROL_MARK);
+        if deleted {
+            sector.st2 |= ST2_CONTROL_MARK;
+        }
+        if self.command & FLAG_MULTIPLE != 0 {
+            self.sector = self.sector.wrapping_add(1);
+            self.write_sector();
+        }
+        else {
+            self.end_command(0);
+        }
+    }
+
+    fn read_address(&mut self) {
+        if !self.is_ready() {
+            return self.end_not_ready()
+        }
+        let side = self.side;
+        match self.drives[self.unit as usize].next_sector_index(side) {
+            Some(index) => {
+                let sector = self.current_sector(index);
+                let mut data = vec![sector.cylinder, sector.head, sector.id, sector.size_code];
+                let crc = crc16(&[0xA1, 0xA1, 0xA1, 0xFE]).update(&data);
+                data.extend_from_slice(&crc.to_be_bytes());
+                self.sector = sector.cylinder;
+                self.start_read(data, 0, 0);
+            }
+            None => self.end_command(STATUS_RNF)
+        }
+    }
+
+    fn read_track(&mut self) {
+        if !self.is_ready() {
+            return self.end_not_ready()
+        }
+        let data = self.drives[self.unit as usize].current_track(self.side)
+                       .map(raw_track)
+                       .unwrap_or_else(|| vec![0x4E;RAW_TRACK_SIZE]);
+        self.drives[self.unit as usize].set_next_sector_index(0);
+        self.start_read(data, 0, 0);
+    }
+
+    fn write_track(&mut self) {
+        if !self.is_ready() {
+            return self.end_not_ready()
+        }
+        if self.is_write_protected() {
+            return self.end_command(STATUS_WRITE_PROT)
+        }
+        self.buffer.clear();
+        self.status = STATUS_BUSY|STATUS_DRQ;
+        self.phase = Phase::WriteTrack;
+    }
+
+    fn commit_track(&mut self) {
+        let side = self.side;
+        let drive = &mut self.drives[self.unit as usize];
+        let cylinder = drive.cylinder();
+        let disk = drive.disk.as_mut().unwrap();
+        if let Some(track) = disk.track_mut_or_insert(cylinder, side) {
+            *track = parse_raw_track(&self.buffer);
+            disk.set_modified(true);
+        }
+        drive.set_next_sector_index(0);
+        self.end_command(0);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Crc16(u16);
+
+fn crc16(data: &[u8]) -> Crc16 {
+    Crc16(0xFFFF).update(data)
+}
+
+impl Crc16 {
+    fn update(self, data: &[u8]) -> Self {
+        Crc16(data.iter().fold(self.0, |mut crc, &byte| {
+            crc ^= u16::from(byte) << 8;
+            for _ in 0..8 {
+                crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+            }
+            crc
+        }))
+    }
+
+    fn to_be_bytes(self) -> [u8;2] {
+        self.0.to_be_bytes()
+    }
+}
+
+/// Synthesizes a raw double density track from the sectors of the given `track`.
+fn raw_track(track: &DiskTrack) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(RAW_TRACK_SIZE);
+    raw.resize(80, 0x4E);
+    raw.resize(raw.len() + 12, 0x00);
+    raw.extend_from_slice(&[0xC2, 0xC2, 0xC2, 0xFC]);
+    raw.resize(raw.len() + 50, 0x4E);
+    for sector in track.sectors.iter() {
+        raw.resize(raw.len() + 12, 0x00);
+        let id = [0xA1, 0xA1, 0xA1, 0xFE, sector.cylinder, sector.head, sector.id, sector.size_code];
+        raw.extend_from_slice(&id);
+        raw.extend_from_slice(&crc16(&id).to_be_bytes());
+        raw.resize(raw.len() + 22, 0x4E);
+        raw.resize(raw.len() + 12, 0x00);
+        let mark = [0xA1, 0xA1, 0xA1, if sector.is_deleted() { 0xF8 } else { 0xFB }];
+        raw.extend_from_slice(&mark);
+        raw.extend_from_slice(&sector.data);
+        raw.extend_from_slice(&crc16(&mark).update(&sector.data).to_be_bytes());
+        raw.resize(raw.len() + track.gap3 as usize, 0x4E);
+    }
+    if raw.len() < RAW_TRACK_SIZE {
+        raw.resize(RAW_TRACK_SIZE, 0x4E);
+    }
+    raw
+}
+
+/// Creates a track from the data written by the `WRITE TRACK` command.
+///
+/// Bytes `0xF5` are written as sync marks and the ID and data fields are being recognized after them.
+fn parse_raw_track(raw: &[u8]) -> DiskTrack {
+    let mut track = DiskTrack::default();
+    let mut gap3 = None;
+    let mut id = None;
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] != 0xF5 {
+            i += 1;
+            continue
+        }
+        while raw.get(i) == Some(&0xF5) {
+            i += 1;
+        }
+        match raw.get(i) {
+            Some(0xFE) => {
+                if let Some(&[c, h, r, n]) = raw.get(i + 1..i + 5) {
+                    id = Some((c, h, r, n));
+                }
+                i += 5;
+            }
+            Some(&mark) if (mark == 0xFB || mark == 0xF8) && id.is_some() => {
+                let (cylinder, head, sector_id, size_code) = id.take().unwrap();
+                let start = i + 1;
+                let end = (start + sector_size(size_code & 3)).min(raw.len());
+                let mut sector = DiskSector::new(cylinder, head, sector_id, size_code, 0);
+                sector.data[..end - start].copy_from_slice(&raw[start..end]);
+                if mark == 0xF8 {
+                    sector.st2 |= ST2_CONTROL_MARK;
+                }
+                track.sectors.push(sector);
+                i = end;
+                if raw.get(i) == Some(&0xF7) {
+                    i += 1;
+                }
+                if gap3.is_none() {
+                    gap3 = Some(raw[i.min(raw.len())..].iter().take_while(|&&b| b == 0x4E).count());
+                }
+            }
+            _ => {}
+        }
+    }
+    track.gap3 = gap3.unwrap_or(0).min(u8::MAX as usize) as u8;
+    track.filler = track.sectors.first().map(|s| s.data[0]).unwrap_or(0);
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fdc_with_disk() -> Wd1793 {
+        let mut fdc = Wd1793::default();
+        let mut disk = FloppyDisk::new_formatted(80, 2, 16, 1, 1, 0);
+        for (i, track) in (0..80).flat_map(|c| (0..2).map(move |h| (c, h))).enumerate() {
+            disk.track_mut(track.0, track.1).unwrap().sectors[0].data[0] = i as u8;
+        }
+        fdc.insert_disk(0, disk);
+        fdc.reset();
+        fdc
+    }
+
+    #[test]
+    fn wd1793_step_works() {
+        let mut fdc = fdc_with_disk();
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status() & !STATUS_INDEX, STATUS_TRACK0);
+        assert!(!fdc.is_intrq());
+        fdc.write_data(5);
+        fdc.write_command(0x1C);
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_track_reg(), 5);
+        assert_eq!(fdc.drives[0].cylinder(), 5);
+        assert_eq!(fdc.read_status() & !STATUS_INDEX, STATUS_HEAD_LOADED);
+        fdc.write_command(0x58);
+        assert_eq!(fdc.read_track_reg(), 6);
+        assert_eq!(fdc.drives[0].cylinder(), 6);
+        fdc.write_command(0x20);
+        assert_eq!(fdc.read_track_reg(), 6);
+        assert_eq!(fdc.drives[0].cylinder(), 7);
+        fdc.write_command(0x74);
+        assert_eq!(fdc.read_track_reg(), 5);
+        assert_eq!(fdc.drives[0].cylinder(), 6);
+        assert_eq!(fdc.read_status() & !STATUS_INDEX, STATUS_SEEK_ERROR);
+        fdc.write_command(0x08);
+        assert_eq!(fdc.read_track_reg(), 0);
+        assert_eq!(fdc.read_status() & !STATUS_INDEX, STATUS_HEAD_LOADED|STATUS_TRACK0);
+        let index_count = (0..INDEX_PERIOD).filter(|_| fdc.read_status() & STATUS_INDEX != 0).count();
+        assert_eq!(index_count, INDEX_WIDTH as usize);
+        fdc.select_drive(1);
+        assert_eq!(fdc.read_status(), STATUS_NOT_READY|STATUS_HEAD_LOADED|STATUS_TRACK0);
+    }
+
+    #[test]
+    fn wd1793_read_write_works() {
+        let mut fdc = fdc_with_disk();
+        fdc.write_data(3);
+        fdc.write_command(0x18);
+        fdc.select_side(1);
+        fdc.write_sector_reg(1);
+        fdc.write_command(0x80);
+        assert_eq!(fdc.read_status(), STATUS_BUSY|STATUS_DRQ);
+        assert!(fdc.is_drq());
+        let data: Vec<u8> = (0..256).map(|_| fdc.read_data()).collect();
+        assert_eq!(data[0], 7);
+        assert!(!fdc.is_drq());
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status(), 0);
+        fdc.write_sector_reg(17);
+        fdc.write_command(0x80);
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status(), STATUS_RNF);
+
+        fdc.write_sector_reg(16);
+        fdc.write_command(0xA1);
+        assert_eq!(fdc.read_status(), STATUS_BUSY|STATUS_DRQ);
+        for i in 0..256 {
+            fdc.write_data(i as u8);
+        }
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status(), 0);
+        assert!(fdc.disk_ref(0).unwrap().is_modified());
+        let sector = &fdc.disk_ref(0).unwrap().track(3, 1).unwrap().sectors[15];
+        assert!(sector.is_deleted());
+        assert_eq!(sector.data[255], 255);
+        fdc.write_command(0x80);
+        assert_eq!(fdc.read_status(), STATUS_BUSY|STATUS_DRQ|STATUS_RECORD_TYPE);
+        fdc.write_command(0xD0);
+        assert!(!fdc.is_intrq());
+        assert!(!fdc.is_drq());
+
+        fdc.write_sector_reg(15);
+        fdc.write_command(0x90);
+        let data: Vec<u8> = (0..512).map(|_| fdc.read_data()).collect();
+        assert_eq!(data[511], 255);
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status(), STATUS_RNF);
+        assert_eq!(fdc.read_sector_reg(), 17);
+
+        fdc.disk_mut(0).unwrap().set_write_protected(true);
+        fdc.write_sector_reg(1);
+        fdc.write_command(0xA0);
+        assert_eq!(fdc.read_status(), STATUS_WRITE_PROT);
+        fdc.write_command(0x00);
+        assert_eq!(fdc.read_status() & !STATUS_INDEX, STATUS_WRITE_PROT|STATUS_TRACK0);
+    }
+
+    #[test]
+    fn wd1772_works() {
+        let mut fdc = Wd1793::new(WdModel::Wd1772);
+        assert_eq!(fdc.model(), WdModel::Wd1772);
+        fdc.reset();
+        assert_eq!(fdc.model(), WdModel::Wd1772);
+        assert_eq!(fdc.read_status(), STATUS_SPIN_UP|STATUS_TRACK0);
+        fdc.write_command(0x88);
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status(), STATUS_RNF);
+        fdc.insert_disk(0, FloppyDisk::new_formatted(80, 2, 10, 1, 2, 0xE5));
+        fdc.write_command(0x08);
+        assert_eq!(fdc.read_status() & !STATUS_INDEX, STATUS_TRACK0);
+        fdc.select_side(1);
+        fdc.write_sector_reg(10);
+        fdc.write_command(0x8A);
+        assert_eq!(fdc.read_status(), STATUS_MOTOR_ON|STATUS_BUSY|STATUS_DRQ);
+        let data: Vec<u8> = (0..512).map(|_| fdc.read_data()).collect();
+        assert_eq!(data, [0xE5;512].to_vec());
+        assert_eq!(fdc.read_status(), 0);
+    }
+
+    #[test]
+    fn wd1793_read_address_works() {
+        let mut fdc = fdc_with_disk();
+        fdc.write_command(0xC0);
+        assert_eq!(fdc.read_status(), STATUS_BUSY|STATUS_DRQ);
+        let data: Vec<u8> = (0..6).map(|_| fdc.read_data()).collect();
+        let crc = crc16(&[0xA1, 0xA1, 0xA1, 0xFE, 0, 0, 1, 1]).to_be_bytes();
+        assert_eq!(data, [0, 0, 1, 1, crc[0], crc[1]]);
+        assert_eq!(fdc.read_sector_reg(), 0);
+        fdc.write_command(0xC0);
+        let data: Vec<u8> = (0..6).map(|_| fdc.read_data()).collect();
+        assert_eq!(data[..4], [0, 0, 2, 1]);
+        assert_eq!(fdc.read_status(), 0);
+    }
+
+    #[test]
+    fn wd1793_format_works() {
+        let mut fdc = fdc_with_disk();
+        fdc.write_data(2);
+        fdc.write_command(0x10);
+        fdc.write_command(0xE0);
+        let mut raw = Vec::new();
+        while fdc.is_drq() {
+            raw.push(fdc.read_data());
+        }
+        assert_eq!(fdc.read_status(), 0);
+        assert_eq!(raw.len(), 146 + 16 * (62 + 256 + 0x52));
+        let id = [0xA1, 0xA1, 0xA1, 0xFE, 2, 0, 1, 1];
+        assert_eq!(raw[158..166], id);
+        assert_eq!(raw[166..168], crc16(&id).to_be_bytes());
+        assert_eq!(raw[168 + 34..][..5], [0xA1, 0xA1, 0xA1, 0xFB, 4]);
+
+        let mut raw = vec![0x4E;80];
+        for id in (1..=5).rev() {
+            raw.extend_from_slice(&[0, 0, 0, 0xF5, 0xF5, 0xF5, 0xFE, 2, 0, id, 2, 0xF7]);
+            raw.extend_from_slice(&[0x4E;22]);
+            raw.extend_from_slice(&[0, 0, 0, 0xF5, 0xF5, 0xF5, 0xFB]);
+            raw.extend_from_slice(&[id;512]);
+            raw.extend_from_slice(&[0xF7]);
+            raw.extend_from_slice(&[0x4E;40]);
+        }
+        fdc.write_command(0xF0);
+        for &byte in raw.iter() {
+            fdc.write_data(byte);
+        }
+        assert!(fdc.is_drq());
+        while fdc.is_drq() {
+            fdc.write_data(0x4E);
+        }
+        assert!(fdc.is_intrq());
+        assert_eq!(fdc.read_status(), 0);
+        let track = fdc.disk_ref(0).unwrap().track(2, 0).unwrap();
+        assert_eq!(track.gap3, 40);
+        assert_eq!(track.filler, 5);
+        assert_eq!(track.sectors.len(), 5);
+        for (sector, id) in track.sectors.iter().zip((1..=5).rev()) {
+            assert_eq!(*sector, DiskSector::new(2, 0, id, 2, id));
+        }
+    }
+}
@@ -0,0 +1,30 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! Fixtures shared by the unit tests of the audio rendering devices.
+use spectrusty_core::audio::{AmpLevels, Blep};
+use spectrusty_core::clock::FTs;
+
+/// Records the steps added by the tested devices as `(channel, timestamp, delta)`.
+#[derive(Default)]
+pub(crate) struct TestBlep(pub Vec<(usize, FTs, f32)>);
+
+impl Blep for TestBlep {
+    type SampleDelta = f32;
+    fn ensure_frame_time(&mut self, _sample_rate: u32, _ts_rate: f64, _frame_ts: FTs, _margin_ts: FTs) {}
+    fn add_step(&mut self, channel: usize, timestamp: FTs, delta: f32) {
+        self.0.push((channel, timestamp, delta));
+    }
+    fn end_frame(&mut self, _timestamp: FTs) -> usize { 0 }
+}
+
+/// Converts the levels directly to the amplitudes.
+pub(crate) struct TestAmps;
+
+impl AmpLevels<f32> for TestAmps {
+    fn amp_level(level: u32) -> f32 { level as f32 }
+}
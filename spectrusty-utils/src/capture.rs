@@ -0,0 +1,375 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! Utilities for capturing the emulated screen as animated images.
+
+[ScreenCapture] takes successive video frames rendered with [Video::render_video_frame] via [PixelBufP8]
+and passes them to an [AnimationEncoder]:
+
+* `GifEncoder` writes an animated **GIF**, requires the `gif` feature.
+* `ApngEncoder` writes an animated **PNG**, requires the `apng` feature.
+
+Both encoders are implemented in pure Rust.
+
+Identical consecutive frames are being merged into a single animation frame which is displayed
+for their total duration. Only the area that has changed since the previous animation frame is
+being encoded. The timing of the frames follows [HostConfig::frame_duration] of the emulated chipset.
+
+```text
+let colors = palette_colors::<SpectrumPalGRB8>();
+let (width, height) = UlaPAL::<Memory48k>::render_size_pixels(border_size);
+let encoder = GifEncoder::new(File::create("capture.gif")?, width, height, &colors)?;
+let mut capture = ScreenCapture::new::<UlaPAL<Memory48k>>(encoder, border_size)?;
+for _ in 0..frames {
+    spectrum.execute_next_frame(&mut cpu);
+    capture.render_video_frame::<SpectrumPalGRB8, _>(&mut spectrum)?;
+}
+capture.finish()?;
+```
+!*/
+use core::mem;
+use core::time::Duration;
+use std::io;
+
+use spectrusty::chip::HostConfig;
+use spectrusty::video::{
+    BorderSize, Palette, RgbPalette, Video,
+    pixel::PixelBufP8
+};
+
+#[cfg(feature = "gif")]
+pub mod animated_gif;
+#[cfg(feature = "apng")]
+pub mod apng;
+
+#[cfg(feature = "gif")]
+pub use animated_gif::GifEncoder;
+#[cfg(feature = "apng")]
+pub use apng::ApngEncoder;
+
+/// A rectangular area of the animation canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameRect {
+    /// The horizontal offset of the area from the left edge of the canvas in pixels.
+    pub left: u32,
+    /// The vertical offset of the area from the top edge of the canvas in pixels.
+    pub top: u32,
+    /// The width of the area in pixels.
+    pub width: u32,
+    /// The height of the area in pixels.
+    pub height: u32
+}
+
+/// A trait implemented by the animated image encoders.
+pub trait AnimationEncoder {
+    /// Should return the size of the animation canvas in pixels: `(width, height)`.
+    fn canvas_size(&self) -> (u32, u32);
+    /// Writes the next animation frame.
+    ///
+    /// `pixels` contains palette indexes of the `rect` area of the canvas, line after line.
+    /// The rest of the canvas should remain unchanged since the previous frame.
+    /// `delay` is the duration of displaying the frame.
+    fn write_frame(&mut self, pixels: &[u8], rect: FrameRect, delay: Duration) -> io::Result<()>;
+    /// Finalizes the animation.
+    ///
+    /// Should be called once, after all the frames have been written.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Captures successive video frames as an animation via an [AnimationEncoder].
+#[derive(Debug)]
+pub struct ScreenCapture<E> {
+    encoder: E,
+    border_size: BorderSize,
+    frame_duration: Duration,
+    width: usize,
+    frame: Vec<u8>,
+    pending: Vec<u8>,
+    pending_frames: u32,
+    canvas: Option<Vec<u8>>,
+    area: Vec<u8>
+}
+
+/// Returns the colors of all the 256 pixel values of the palette `P`, e.g. to be used as
+/// a color table of the indexed image formats.
+pub fn palette_colors<P: RgbPalette<Pixel=u8>>() -> Vec<[u8;3]> {
+    (0..=255u8).map(P::pixel_to_rgb).collect()
+}
+
+impl FrameRect {
+    /// Returns `true` if the area has no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+impl<E: AnimationEncoder> ScreenCapture<E> {
+    /// Creates a new screen capture of the video frames of the chipset `V` rendered with the given
+    /// `border_size`.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the canvas size of the `encoder` doesn't
+    /// match the rendered frame size.
+    pub fn new<V: Video + HostConfig>(encoder: E, border_size: BorderSize) -> io::Result<Self> {
+        let (width, height) = V::render_size_pixels(border_size);
+        if encoder.canvas_size() != (width, height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                       "the canvas size doesn't match the size of the rendered frame"))
+        }
+        let size = width as usize * height as usize;
+        Ok(ScreenCapture {
+            encoder, border_size,
+            frame_duration: V::frame_duration(),
+            width: width as usize,
+            frame: vec![0; size],
+            pending: vec![0; size],
+            pending_frames: 0,
+            canvas: None,
+            area: Vec::new()
+        })
+    }
+    /// Returns the border size of the captured frames.
+    pub fn border_size(&self) -> BorderSize {
+        self.border_size
+    }
+    /// Returns the number of the captured video frames not yet passed to the encoder.
+    pub fn pending_frames(&self) -> u32 {
+        self.pending_frames
+    }
+    /// Renders the video frame of the last emulated frame and adds it to the animation.
+    ///
+    /// `P` should produce pixels matching the color table of the encoder, e.g. see [palette_colors].
+    pub fn render_video_frame<P, V>(&mut self, video: &mut V) -> io::Result<()>
+        where P: Palette<Pixel=u8>,
+              V: Video
+    {
+        video.render_video_frame::<PixelBufP8, P>(&mut self.frame, self.width, self.border_size);
+        self.push_frame()
+    }
+    /// Adds a video frame already rendered via [PixelBufP8] to the animation.
+    ///
+    /// The `frame` should contain lines of pixels with no padding between them.
+    ///
+    /// # Panics
+    /// Panics if the size of the `frame` doesn't match the capture's frame size.
+    pub fn add_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.frame.copy_from_slice(frame);
+        self.push_frame()
+    }
+    /// Writes the pending frames and finalizes the animation.
+    ///
+    /// Returns the encoder.
+    pub fn finish(mut self) -> io::Result<E> {
+        self.flush_pending()?;
+        self.encoder.finish()?;
+        Ok(self.encoder)
+    }
+    /// Returns a reference to the encoder.
+    pub fn encoder_ref(&self) -> &E {
+        &self.encoder
+    }
+
+    fn push_frame(&mut self) -> io::Result<()> {
+        if self.pending_frames != 0 && self.frame == self.pending {
+            self.pending_frames += 1;
+            return Ok(())
+        }
+        self.flush_pending()?;
+        mem::swap(&mut self.frame, &mut self.pending);
+        self.pending_frames = 1;
+        Ok(())
+    }
+
+    fn flush_pending(&mut self) -> io::Result<()> {
+        if self.pending_frames == 0 {
+            return Ok(())
+        }
+        let delay = self.frame_duration * self.pending_frames;
+        self.pending_frames = 0;
+        let width = self.width;
+        let height = self.pending.len() / width;
+        let rect = match self.canvas.as_ref() {
+            Some(canvas) => changed_rect(canvas, &self.pending, width),
+            None => FrameRect { left: 0, top: 0, width: width as u32, height: height as u32 }
+        };
+        // a frame must have at least a single pixel
+        let rect = if rect.is_empty() { FrameRect { width: 1, height: 1, ..rect } } else { rect };
+        let area = &mut self.area;
+        area.clear();
+        let (left, right) = (rect.left as usize, (rect.left + rect.width) as usize);
+        for line in self.pending.chunks_exact(width).skip(rect.top as usize).take(rect.height as usize) {
+            area.extend_from_slice(&line[left..right]);
+        }
+        self.encoder.write_frame(area, rect, delay)?;
+        match self.canvas.as_mut() {
+            Some(canvas) => canvas.copy_from_slice(&self.pending),
+            None => self.canvas = Some(self.pending.clone())
+        }
+        Ok(())
+    }
+}
+
+/// Returns the smallest area containing all the pixels that differ between the frames.
+fn changed_rect(prev: &[u8], next: &[u8], width: usize) -> FrameRect {
+    let mut top = None;
+    let mut bottom = 0;
+    let mut left = width;
+    let mut right = 0;
+    for (y, (prev_line, next_line)) in prev.chunks_exact(width).zip(next.chunks_exact(width)).enumerate() {
+        if let Some(x0) = prev_line.iter().zip(next_line.iter()).position(|(a, b)| a != b) {
+            let x1 = width - prev_line.iter().rev().zip(next_line.iter().rev())
+                                      .position(|(a, b)| a != b).unwrap();
+            top.get_or_insert(y);
+            bottom = y + 1;
+            left = left.min(x0);
+            right = right.max(x1);
+        }
+    }
+    match top {
+        Some(top) => FrameRect {
+            left: left as u32,
+            top: top as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32
+        },
+        None => FrameRect::default()
+    }
+}
+
+/// Converts frame delays to the integer units of the animation formats, so the rounding errors
+/// don't accumulate over time.
+#[cfg(any(feature = "gif", feature = "apng"))]
+#[derive(Debug)]
+pub(crate) struct DelayCounter {
+    units_per_sec: u32,
+    elapsed: Duration,
+    units: u64
+}
+
+#[cfg(any(feature = "gif", feature = "apng"))]
+impl DelayCounter {
+    pub fn new(units_per_sec: u32) -> Self {
+        DelayCounter { units_per_sec, elapsed: Duration::default(), units: 0 }
+    }
+    /// Returns the number of units of the next frame `delay`.
+    pub fn next_delay(&mut self, delay: Duration) -> u64 {
+        self.elapsed += delay;
+        let total = (self.elapsed.as_nanos() * u128::from(self.units_per_sec) + 500_000_000)
+                    / 1_000_000_000;
+        let units = total as u64 - self.units;
+        self.units = total as u64;
+        units
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrusty::chip::ula::UlaPAL;
+    use spectrusty::memory::Memory48k;
+    use spectrusty::video::{BorderColor, pixel::SpectrumPalGRB8};
+    use super::*;
+
+    type TestUla = UlaPAL<Memory48k>;
+
+    #[derive(Default, Debug)]
+    struct TestEncoder {
+        canvas_size: (u32, u32),
+        frames: Vec<(Vec<u8>, FrameRect, Duration)>,
+        finished: bool
+    }
+
+    impl AnimationEncoder for TestEncoder {
+        fn canvas_size(&self) -> (u32, u32) {
+            self.canvas_size
+        }
+
+        fn write_frame(&mut self, pixels: &[u8], rect: FrameRect, delay: Duration) -> io::Result<()> {
+            self.frames.push((pixels.to_vec(), rect, delay));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    fn rect(left: u32, top: u32, width: u32, height: u32) -> FrameRect {
+        FrameRect { left, top, width, height }
+    }
+
+    #[test]
+    fn screen_capture_works() {
+        let border_size = BorderSize::Minimal;
+        let (width, height) = TestUla::render_size_pixels(border_size);
+        let encoder = TestEncoder { canvas_size: (width, height + 1), ..TestEncoder::default() };
+        assert_eq!(ScreenCapture::new::<TestUla>(encoder, border_size).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        let encoder = TestEncoder { canvas_size: (width, height), ..TestEncoder::default() };
+        let mut capture = ScreenCapture::new::<TestUla>(encoder, border_size).unwrap();
+        assert_eq!(capture.border_size(), border_size);
+        let frame_duration = TestUla::frame_duration();
+
+        let mut ula = TestUla::default();
+        ula.set_border_color(BorderColor::BLUE);
+        capture.render_video_frame::<SpectrumPalGRB8, _>(&mut ula).unwrap();
+        let mut frame = capture.pending.clone();
+        assert_eq!(frame[0], SpectrumPalGRB8::get_pixel(BorderColor::BLUE.into()));
+        capture.add_frame(&frame).unwrap();
+        capture.add_frame(&frame).unwrap();
+        assert_eq!(capture.pending_frames(), 3);
+        assert!(capture.encoder_ref().frames.is_empty());
+        capture.add_frame(&frame).unwrap();
+        assert_eq!(capture.pending_frames(), 4);
+        frame[width as usize * 7 + 5] = 0;
+        capture.add_frame(&frame).unwrap();
+        capture.add_frame(&frame).unwrap();
+        assert_eq!(capture.pending_frames(), 2);
+        assert_eq!(capture.encoder_ref().frames.len(), 1);
+        let encoder = capture.finish().unwrap();
+        assert!(encoder.finished);
+        assert_eq!(encoder.frames.len(), 2);
+        let (pixels, frame_rect, delay) = &encoder.frames[0];
+        assert_eq!(*frame_rect, rect(0, 0, width, height));
+        assert_eq!(pixels.len(), (width * height) as usize);
+        assert_eq!(*delay, frame_duration * 4);
+        assert_eq!(encoder.frames[1], (vec![0], rect(5, 7, 1, 1), frame_duration * 2));
+    }
+
+    #[test]
+    fn changed_rect_works() {
+        let prev = [0u8; 12];
+        assert_eq!(changed_rect(&prev, &prev, 4), FrameRect::default());
+        assert!(changed_rect(&prev, &prev, 4).is_empty());
+        let mut next = prev;
+        next[6] = 1;
+        assert_eq!(changed_rect(&prev, &next, 4), rect(2, 1, 1, 1));
+        next[1] = 1;
+        next[9] = 1;
+        assert_eq!(changed_rect(&prev, &next, 4), rect(1, 0, 2, 3));
+        assert_eq!(changed_rect(&prev, &[1u8; 12], 4), rect(0, 0, 4, 3));
+    }
+
+    #[cfg(any(feature = "gif", feature = "apng"))]
+    #[test]
+    fn delay_counter_works() {
+        // 69888 T-states at 3.5 MHz: 50.08 frames per second
+        let frame_duration = TestUla::frame_duration();
+        assert_eq!(frame_duration, Duration::from_nanos(19_968_000));
+        let mut centis = DelayCounter::new(100);
+        let delays: Vec<u64> = (0..250).map(|_| centis.next_delay(frame_duration)).collect();
+        assert!(delays.iter().all(|&delay| delay == 1 || delay == 2));
+        assert_eq!(delays[..4], [2, 2, 2, 2]);
+        assert_eq!(delays.iter().sum::<u64>(), 499);
+        let mut millis = DelayCounter::new(1000);
+        let delays: Vec<u64> = (0..250).map(|_| millis.next_delay(frame_duration)).collect();
+        assert!(delays.iter().all(|&delay| delay == 19 || delay == 20));
+        assert_eq!(delays.iter().sum::<u64>(), 4992);
+        let mut centis = DelayCounter::new(100);
+        let delays: Vec<u64> = (0..4).map(|_| centis.next_delay(Duration::from_millis(15))).collect();
+        assert_eq!(delays, [2, 1, 2, 1]);
+    }
+}
@@ -0,0 +1,160 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An animated **GIF** encoder.
+use core::convert::TryFrom;
+use core::time::Duration;
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use gif::{Encoder, EncodingError, Frame, Repeat};
+
+use super::{AnimationEncoder, DelayCounter, FrameRect};
+
+/// The number of GIF delay units per second.
+const GIF_DELAY_UNITS: u32 = 100;
+
+/// Writes animation frames to an animated **GIF** file.
+///
+/// The frame delays are being expressed in 1/100 of a second, with the rounding errors carried over
+/// to the subsequent frames.
+pub struct GifEncoder<W: Write> {
+    encoder: Option<Encoder<W>>,
+    wr: Option<W>,
+    width: u16,
+    height: u16,
+    delays: DelayCounter
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// Creates a new encoder and writes the **GIF** header with the global color table
+    /// made of `colors`. The animation is set to loop forever.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the canvas size exceeds the format limits
+    /// or if there are more than 256 `colors`.
+    pub fn new(wr: W, width: u32, height: u32, colors: &[[u8;3]]) -> io::Result<Self> {
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "the canvas is too large"))
+        };
+        if colors.len() > 256 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many colors"))
+        }
+        let palette: Vec<u8> = colors.iter().flat_map(|rgb| rgb.iter().copied()).collect();
+        let mut encoder = Encoder::new(wr, width, height, &palette).map_err(encoding_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(encoding_error)?;
+        Ok(GifEncoder {
+            encoder: Some(encoder),
+            wr: None,
+            width, height,
+            delays: DelayCounter::new(GIF_DELAY_UNITS)
+        })
+    }
+    /// Writes the **GIF** trailer if not already written by [AnimationEncoder::finish]
+    /// and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.into_inner(),
+            None => Ok(self.wr.take().unwrap())
+        }
+    }
+
+    fn encoder_mut(&mut self) -> io::Result<&mut Encoder<W>> {
+        self.encoder.as_mut().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the animation has been already finished")
+        })
+    }
+}
+
+impl<W: Write> AnimationEncoder for GifEncoder<W> {
+    fn canvas_size(&self) -> (u32, u32) {
+        (self.width.into(), self.height.into())
+    }
+
+    /// # Note
+    /// Delays longer than `65535` units are being split between the frame and its repeated
+    /// single pixel.
+    fn write_frame(&mut self, pixels: &[u8], rect: FrameRect, delay: Duration) -> io::Result<()> {
+        let mut delay = self.delays.next_delay(delay);
+        let mut frame = Frame {
+            left: rect.left as u16,
+            top: rect.top as u16,
+            width: rect.width as u16,
+            height: rect.height as u16,
+            buffer: Cow::Borrowed(pixels),
+            ..Frame::default()
+        };
+        let encoder = self.encoder_mut()?;
+        loop {
+            frame.delay = u16::try_from(delay).unwrap_or(u16::MAX);
+            encoder.write_frame(&frame).map_err(encoding_error)?;
+            delay -= u64::from(frame.delay);
+            if delay == 0 {
+                break Ok(())
+            }
+            frame.width = 1;
+            frame.height = 1;
+            frame.buffer = Cow::Borrowed(&pixels[..1]);
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let mut wr = encoder.into_inner()?;
+            wr.flush()?;
+            self.wr = Some(wr);
+        }
+        Ok(())
+    }
+}
+
+fn encoding_error(err: EncodingError) -> io::Error {
+    match err {
+        EncodingError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [[u8;3]; 2] = [[0, 0, 0], [255, 255, 255]];
+
+    fn gif_delays(data: &[u8]) -> Vec<u16> {
+        data.windows(4).enumerate()
+            .filter(|(_, w)| w[..3] == [0x21, 0xF9, 0x04])
+            .map(|(i, _)| u16::from_le_bytes([data[i + 4], data[i + 5]]))
+            .collect()
+    }
+
+    #[test]
+    fn gif_encoder_works() {
+        assert_eq!(GifEncoder::new(Vec::new(), 0x10000, 1, &COLORS).err().unwrap().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(GifEncoder::new(Vec::new(), 4, 2, &[[0, 0, 0]; 257]).err().unwrap().kind(),
+                   io::ErrorKind::InvalidInput);
+        let mut gif = GifEncoder::new(Vec::new(), 4, 2, &COLORS).unwrap();
+        assert_eq!(gif.canvas_size(), (4, 2));
+        let full = FrameRect { left: 0, top: 0, width: 4, height: 2 };
+        gif.write_frame(&[0, 1, 0, 1, 1, 0, 1, 0], full, Duration::from_millis(15)).unwrap();
+        gif.write_frame(&[1], FrameRect { left: 2, top: 1, width: 1, height: 1 },
+                        Duration::from_millis(15)).unwrap();
+        // 700 seconds don't fit in a single frame delay
+        gif.write_frame(&[0, 0], FrameRect { left: 0, top: 0, width: 2, height: 1 },
+                        Duration::from_secs(700)).unwrap();
+        gif.finish().unwrap();
+        assert_eq!(gif.write_frame(&[0], full, Duration::from_millis(20)).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        let data = gif.into_inner().unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+        assert_eq!(data[6..10], [4, 0, 2, 0]);
+        assert!(data.windows(11).any(|w| w == b"NETSCAPE2.0"));
+        assert_eq!(data.last(), Some(&0x3B));
+        assert_eq!(gif_delays(&data), [2, 1, 65535, 4465]);
+    }
+}
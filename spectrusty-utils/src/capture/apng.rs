@@ -0,0 +1,276 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An animated **PNG** (APNG) encoder.
+use core::convert::TryFrom;
+use core::time::Duration;
+use std::io::{self, Write, Seek, SeekFrom};
+
+use super::{AnimationEncoder, DelayCounter, FrameRect};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const BIT_DEPTH: u8 = 8;
+const COLOR_TYPE_INDEXED: u8 = 3;
+const FILTER_NONE: u8 = 0;
+const DISPOSE_OP_NONE: u8 = 0;
+const BLEND_OP_SOURCE: u8 = 0;
+/// The denominator of the frame delays in fractions of a second.
+const DELAY_DEN: u16 = 1000;
+
+/// Writes animation frames to an animated **PNG** file.
+///
+/// The frame delays are being expressed in milliseconds, with the rounding errors carried over
+/// to the subsequent frames. The number of frames is being updated by [AnimationEncoder::finish].
+#[derive(Debug)]
+pub struct ApngEncoder<W> {
+    wr: W,
+    width: u32,
+    height: u32,
+    anim_size: u64,
+    frames: u32,
+    sequence: u32,
+    finished: bool,
+    delays: DelayCounter,
+    buf: Vec<u8>
+}
+
+impl<W: Write + Seek> ApngEncoder<W> {
+    /// Creates a new encoder and writes the **PNG** header with the palette made of `colors`.
+    /// The animation is set to loop forever.
+    ///
+    /// Returns an error of [io::ErrorKind::InvalidInput] kind if the canvas is empty or if there
+    /// are no `colors` or more than 256 of them.
+    pub fn new(mut wr: W, width: u32, height: u32, colors: &[[u8;3]]) -> io::Result<Self> {
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid canvas size"))
+        }
+        if colors.is_empty() || colors.len() > 256 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid number of colors"))
+        }
+        wr.write_all(PNG_SIGNATURE)?;
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        // compression method, filter method, interlace method
+        ihdr.extend_from_slice(&[BIT_DEPTH, COLOR_TYPE_INDEXED, 0, 0, 0]);
+        write_chunk(&mut wr, b"IHDR", &ihdr)?;
+        let palette: Vec<u8> = colors.iter().flat_map(|rgb| rgb.iter().copied()).collect();
+        write_chunk(&mut wr, b"PLTE", &palette)?;
+        let anim_size = write_actl(&mut wr, 0)?;
+        Ok(ApngEncoder {
+            wr, width, height, anim_size,
+            frames: 0,
+            sequence: 0,
+            finished: false,
+            delays: DelayCounter::new(DELAY_DEN.into()),
+            buf: Vec::new()
+        })
+    }
+    /// Returns the number of written animation frames.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+
+    fn next_sequence(&mut self) -> u32 {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        sequence
+    }
+
+    fn write_fctl(&mut self, rect: FrameRect, delay: u16) -> io::Result<()> {
+        let mut fctl = Vec::with_capacity(26);
+        fctl.extend_from_slice(&self.next_sequence().to_be_bytes());
+        for value in [rect.width, rect.height, rect.left, rect.top].iter() {
+            fctl.extend_from_slice(&value.to_be_bytes());
+        }
+        fctl.extend_from_slice(&delay.to_be_bytes());
+        fctl.extend_from_slice(&DELAY_DEN.to_be_bytes());
+        fctl.extend_from_slice(&[DISPOSE_OP_NONE, BLEND_OP_SOURCE]);
+        self.anim_size += write_chunk(&mut self.wr, b"fcTL", &fctl)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn write_image_data(&mut self, pixels: &[u8], width: usize) -> io::Result<()> {
+        let buf = &mut self.buf;
+        buf.clear();
+        for line in pixels.chunks_exact(width) {
+            buf.push(FILTER_NONE);
+            buf.extend_from_slice(line);
+        }
+        let data = deflate::deflate_bytes_zlib(buf);
+        self.anim_size += if self.frames == 1 {
+            write_chunk(&mut self.wr, b"IDAT", &data)?
+        }
+        else {
+            let mut fdat = Vec::with_capacity(4 + data.len());
+            fdat.extend_from_slice(&self.next_sequence().to_be_bytes());
+            fdat.extend_from_slice(&data);
+            write_chunk(&mut self.wr, b"fdAT", &fdat)?
+        };
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> AnimationEncoder for ApngEncoder<W> {
+    fn canvas_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// # Note
+    /// The first frame must cover the whole canvas.
+    ///
+    /// Delays longer than `65535` milliseconds are being split between the frame and its repeated
+    /// single pixel.
+    fn write_frame(&mut self, pixels: &[u8], rect: FrameRect, delay: Duration) -> io::Result<()> {
+        if self.finished {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the animation has been already finished"))
+        }
+        if rect.is_empty() || rect.left + rect.width > self.width || rect.top + rect.height > self.height
+           || pixels.len() != rect.width as usize * rect.height as usize
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid frame area"))
+        }
+        if self.frames == 0 && (rect.left, rect.top, rect.width, rect.height) != (0, 0, self.width, self.height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the first frame must cover the whole canvas"))
+        }
+        let mut delay = self.delays.next_delay(delay);
+        let mut rect = rect;
+        let mut pixels = pixels;
+        loop {
+            let frame_delay = u16::try_from(delay).unwrap_or(u16::MAX);
+            self.write_fctl(rect, frame_delay)?;
+            self.write_image_data(pixels, rect.width as usize)?;
+            delay -= u64::from(frame_delay);
+            if delay == 0 {
+                break Ok(())
+            }
+            rect.width = 1;
+            rect.height = 1;
+            pixels = &pixels[..1];
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(())
+        }
+        self.anim_size += write_chunk(&mut self.wr, b"IEND", &[])?;
+        // go back to the animation control chunk and update the number of frames
+        let back = i64::try_from(self.anim_size)
+                   .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the animation is too large"))?;
+        self.wr.seek(SeekFrom::Current(-back))?;
+        let actl_size = write_actl(&mut self.wr, self.frames)?;
+        self.wr.seek(SeekFrom::Current(back - actl_size as i64))?;
+        self.finished = true;
+        self.wr.flush()
+    }
+}
+
+/// Writes the animation control chunk of an infinitely looped animation.
+///
+/// Returns the number of bytes written.
+fn write_actl<W: Write>(wr: W, num_frames: u32) -> io::Result<u64> {
+    let mut actl = [0u8; 8];
+    actl[0..4].copy_from_slice(&num_frames.to_be_bytes());
+    write_chunk(wr, b"acTL", &actl)
+}
+
+/// Writes a PNG chunk and returns the number of bytes written.
+fn write_chunk<W: Write>(mut wr: W, kind: &[u8;4], data: &[u8]) -> io::Result<u64> {
+    let len = u32::try_from(data.len()).ok()
+              .filter(|&len| len <= i32::MAX as u32)
+              .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the PNG chunk is too large"))?;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    wr.write_all(&len.to_be_bytes())?;
+    wr.write_all(kind)?;
+    wr.write_all(data)?;
+    wr.write_all(&hasher.finalize().to_be_bytes())?;
+    Ok(12 + u64::from(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    const COLORS: [[u8;3]; 2] = [[0, 0, 0], [255, 255, 255]];
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+    }
+
+    /// Returns the chunks as `(type, data)` verifying their CRC.
+    fn png_chunks(data: &[u8]) -> Vec<([u8;4], &[u8])> {
+        assert_eq!(&data[..8], PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut pos = 8;
+        while pos < data.len() {
+            let len = u32_at(data, pos) as usize;
+            let kind = &data[pos + 4..pos + 8];
+            let chunk = &data[pos + 8..pos + 8 + len];
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(kind);
+            hasher.update(chunk);
+            assert_eq!(u32_at(data, pos + 8 + len), hasher.finalize());
+            chunks.push(([kind[0], kind[1], kind[2], kind[3]], chunk));
+            pos += 12 + len;
+        }
+        chunks
+    }
+
+    #[test]
+    fn apng_encoder_works() {
+        assert_eq!(ApngEncoder::new(Cursor::new(Vec::new()), 0, 2, &COLORS).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(ApngEncoder::new(Cursor::new(Vec::new()), 4, 2, &[]).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        let mut apng = ApngEncoder::new(Cursor::new(Vec::new()), 4, 2, &COLORS).unwrap();
+        assert_eq!(apng.canvas_size(), (4, 2));
+        let full = FrameRect { left: 0, top: 0, width: 4, height: 2 };
+        let pixel = FrameRect { left: 2, top: 1, width: 1, height: 1 };
+        assert_eq!(apng.write_frame(&[1], pixel, Duration::from_millis(20)).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        apng.write_frame(&[0, 1, 0, 1, 1, 0, 1, 0], full, Duration::from_micros(19_968)).unwrap();
+        apng.write_frame(&[1], pixel, Duration::from_micros(19_968)).unwrap();
+        assert_eq!(apng.write_frame(&[1, 1], pixel, Duration::from_millis(20)).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        apng.write_frame(&[0], pixel, Duration::from_micros(19_968 * 48)).unwrap();
+        apng.finish().unwrap();
+        assert_eq!(apng.frames(), 3);
+        assert_eq!(apng.write_frame(&[1], pixel, Duration::from_millis(20)).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        let cursor = apng.into_inner();
+        assert_eq!(cursor.position(), cursor.get_ref().len() as u64);
+        let data = cursor.into_inner();
+        let chunks = png_chunks(&data);
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+        assert_eq!(kinds, [&b"IHDR"[..], b"PLTE", b"acTL", b"fcTL", b"IDAT",
+                           b"fcTL", b"fdAT", b"fcTL", b"fdAT", b"IEND"]);
+        assert_eq!(chunks[0].1[..8], [0, 0, 0, 4, 0, 0, 0, 2]);
+        assert_eq!(chunks[1].1, [0, 0, 0, 255, 255, 255]);
+        // the number of frames and infinite looping
+        assert_eq!(u32_at(chunks[2].1, 0), 3);
+        assert_eq!(u32_at(chunks[2].1, 4), 0);
+        let fctls: Vec<&[u8]> = chunks.iter().filter(|(kind, _)| kind == b"fcTL").map(|(_, c)| *c).collect();
+        let sequences: Vec<u32> = fctls.iter().map(|c| u32_at(c, 0)).collect();
+        assert_eq!(sequences, [0, 1, 3]);
+        assert_eq!(u32_at(chunks[6].1, 0), 2);
+        // (width, height, left, top) and (delay numerator, denominator)
+        let frame = |c: &[u8]| ((u32_at(c, 4), u32_at(c, 8), u32_at(c, 12), u32_at(c, 16)),
+                                 (u16::from_be_bytes([c[20], c[21]]), u16::from_be_bytes([c[22], c[23]])));
+        assert_eq!(frame(fctls[0]), ((4, 2, 0, 0), (20, 1000)));
+        assert_eq!(frame(fctls[1]), ((1, 1, 2, 1), (20, 1000)));
+        assert_eq!(frame(fctls[2]), ((1, 1, 2, 1), (958, 1000)));
+    }
+}
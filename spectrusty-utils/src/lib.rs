@@ -20,7 +20,9 @@
 */
 //! Additional utilities for the emulators, based on the SPECTRUSTY library.
 // pub mod dynamic;
+pub mod capture;
 pub mod keyboard;
 pub mod io;
 pub mod printer;
+pub mod recording;
 pub mod tap;
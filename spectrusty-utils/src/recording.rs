@@ -0,0 +1,296 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! Utilities for recording emulated sessions as uncompressed video and audio streams.
+
+A [Recorder] renders each emulated frame with [Video::render_video_frame] into its own frame buffer and
+collects audio samples from the `Blep` implementation's sum iterators, e.g. `BandLimited::sum_iter` of
+the `spectrusty-audio` crate. On [Recorder::end_frame] both are being passed to the [FrameWriter]:
+
+* [AviWriter] writes an uncompressed `AVI` file with 24-bit RGB video and 16-bit PCM audio streams.
+* [Y4mWavWriter] writes a `YUV4MPEG2` video stream and a separate `WAV` audio file.
+
+```text
+let format = StreamFormat::new::<UlaPAL<Memory48k>>(border_size, sample_rate, 1);
+let writer = AviWriter::new(File::create("session.avi")?, format)?;
+let mut recorder = Recorder::new(writer, format, border_size);
+loop {
+    spectrum.execute_next_frame(&mut cpu);
+    spectrum.render_audio(&mut bandlim);
+    recorder.render_video_frame::<SpectrumPalRGB24, _>(&mut spectrum);
+    recorder.add_audio_samples((0..1).map(|channel| bandlim.sum_iter::<i16>(channel)));
+    recorder.end_frame()?;
+    bandlim.next_frame();
+}
+recorder.finish()?;
+```
+!*/
+use std::io;
+
+use spectrusty::chip::HostConfig;
+use spectrusty::video::{
+    BorderSize, Palette, Video,
+    pixel::PixelBufA24
+};
+
+pub mod avi;
+pub mod wav;
+pub mod y4m;
+
+pub use avi::AviWriter;
+pub use wav::WavWriter;
+pub use y4m::{Y4mWriter, Y4mWavWriter};
+
+/// The number of bytes of a single pixel of recorded video frames.
+pub const RGB24_PIXEL_SIZE: usize = 3;
+
+/// Parameters of the recorded video and audio streams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamFormat {
+    /// The width of the video frames in pixels.
+    pub width: u32,
+    /// The height of the video frames in pixels.
+    pub height: u32,
+    /// The video frame rate as a fraction: `(numerator, denominator)` frames per second.
+    pub frame_rate: (u32, u32),
+    /// The pixel aspect ratio as a fraction: `(width, height)`.
+    pub pixel_aspect: (u32, u32),
+    /// The audio sample rate in samples per second.
+    pub sample_rate: u32,
+    /// The number of audio channels, `0` for a video only recording.
+    pub channels: u16
+}
+
+/// A trait implemented by the writers of the recorded streams.
+pub trait FrameWriter {
+    /// Writes a single video `frame` and the audio `samples` belonging to it.
+    ///
+    /// The `frame` pixels are `[red, green, blue]` triples, line after line, starting from the top
+    /// line. The audio `samples` are interleaved by channels.
+    fn write_frame(&mut self, frame: &[u8], samples: &[i16]) -> io::Result<()>;
+    /// Finalizes the streams, e.g. updates the headers with the stream lengths.
+    ///
+    /// Should be called once, after all the frames have been written.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Records video frames and audio samples of the emulated session via a [FrameWriter].
+#[derive(Debug)]
+pub struct Recorder<F> {
+    writer: F,
+    format: StreamFormat,
+    border_size: BorderSize,
+    frame: Vec<u8>,
+    samples: Vec<i16>
+}
+
+impl StreamFormat {
+    /// Returns the stream format of recordings of the chipset `V` rendered with the given `border_size`.
+    ///
+    /// The frame rate is derived from the [HostConfig] of the chipset and the pixel aspect ratio
+    /// from the [Video::pixel_density].
+    pub fn new<V: Video + HostConfig>(border_size: BorderSize, sample_rate: u32, channels: u16) -> Self {
+        let (width, height) = V::render_size_pixels(border_size);
+        let frame_rate = (V::CPU_HZ, V::FRAME_TSTATES as u32);
+        let pixel_aspect = (1, V::pixel_density());
+        StreamFormat { width, height, frame_rate, pixel_aspect, sample_rate, channels }
+    }
+    /// Returns the number of bytes of a single video frame.
+    pub fn frame_size(&self) -> usize {
+        self.width as usize * self.height as usize * RGB24_PIXEL_SIZE
+    }
+    /// Returns the number of bytes of a single line of a video frame.
+    pub fn frame_pitch(&self) -> usize {
+        self.width as usize * RGB24_PIXEL_SIZE
+    }
+}
+
+impl<F: FrameWriter> Recorder<F> {
+    /// Creates a new recorder, writing streams of the given `format` to the `writer`.
+    ///
+    /// `border_size` is used for rendering video frames, it should match the frame size of the `format`.
+    pub fn new(writer: F, format: StreamFormat, border_size: BorderSize) -> Self {
+        let frame = vec![0; format.frame_size()];
+        let samples = Vec::new();
+        Recorder { writer, format, border_size, frame, samples }
+    }
+    /// Returns the format of the recorded streams.
+    pub fn format(&self) -> &StreamFormat {
+        &self.format
+    }
+    /// Returns a reference to the frame buffer with the last rendered video frame.
+    ///
+    /// The frame can be also presented by the host as its pixel format is `RGB24` with the pitch
+    /// of [StreamFormat::frame_pitch].
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame
+    }
+    /// Renders the video frame of the last emulated frame into the frame buffer.
+    ///
+    /// Instead of rendering frames on the host side, this method should be called in place
+    /// of [Video::render_video_frame]. The rendered frame is available via [Recorder::frame_buffer].
+    pub fn render_video_frame<P, V>(&mut self, video: &mut V)
+        where P: Palette<Pixel=[u8;RGB24_PIXEL_SIZE]>,
+              V: Video
+    {
+        let pitch = self.format.frame_pitch();
+        video.render_video_frame::<PixelBufA24, P>(&mut self.frame, pitch, self.border_size);
+    }
+    /// Adds audio samples of the current frame from the sum iterators of the audio `channels`.
+    ///
+    /// The samples are being interleaved until any of the iterators ends. Provide one iterator for each
+    /// of the [StreamFormat::channels].
+    ///
+    /// # Panics
+    /// Panics if the number of `channels` doesn't match the stream format.
+    pub fn add_audio_samples<I, S>(&mut self, channels: I)
+        where I: IntoIterator<Item=S>,
+              S: Iterator<Item=i16>
+    {
+        let mut channels: Vec<S> = channels.into_iter().collect();
+        assert_eq!(channels.len(), self.format.channels as usize, "invalid number of audio channels");
+        if channels.is_empty() {
+            return
+        }
+        'samples: loop {
+            for channel in channels.iter_mut() {
+                match channel.next() {
+                    Some(sample) => self.samples.push(sample),
+                    None => break 'samples
+                }
+            }
+        }
+        let len = self.samples.len();
+        self.samples.truncate(len - len % channels.len());
+    }
+    /// Writes the last rendered video frame and the audio samples added since the previous call
+    /// via the [FrameWriter].
+    pub fn end_frame(&mut self) -> io::Result<()> {
+        let res = self.writer.write_frame(&self.frame, &self.samples);
+        self.samples.clear();
+        res
+    }
+    /// Finalizes the recording and returns the [FrameWriter].
+    pub fn finish(mut self) -> io::Result<F> {
+        self.writer.finish()?;
+        Ok(self.writer)
+    }
+    /// Returns a reference to the [FrameWriter].
+    pub fn writer_ref(&self) -> &F {
+        &self.writer
+    }
+}
+
+#[inline]
+pub(crate) fn write_u16_le<W: io::Write>(mut wr: W, value: u16) -> io::Result<()> {
+    wr.write_all(&value.to_le_bytes())
+}
+
+#[inline]
+pub(crate) fn write_u32_le<W: io::Write>(mut wr: W, value: u32) -> io::Result<()> {
+    wr.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn stream_too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "the recorded stream is too large")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+    use spectrusty::chip::ula::UlaPAL;
+    use spectrusty::memory::Memory48k;
+    use spectrusty::video::{BorderColor, VideoFrame, pixel::SpectrumPalRGB24};
+    use super::*;
+
+    pub(crate) const TEST_FORMAT: StreamFormat = StreamFormat {
+        width: 3,
+        height: 2,
+        frame_rate: (50, 1),
+        pixel_aspect: (1, 1),
+        sample_rate: 100,
+        channels: 2
+    };
+
+    pub(crate) fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+    }
+
+    #[derive(Default)]
+    struct TestWriter {
+        frames: Vec<(Vec<u8>, Vec<i16>)>,
+        finished: bool
+    }
+
+    impl FrameWriter for TestWriter {
+        fn write_frame(&mut self, frame: &[u8], samples: &[i16]) -> io::Result<()> {
+            self.frames.push((frame.to_vec(), samples.to_vec()));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn recorder_works() {
+        let border_size = BorderSize::Full;
+        let format = StreamFormat::new::<UlaPAL<Memory48k>>(border_size, 44100, 2);
+        let (width, height) = <UlaPAL<Memory48k> as Video>::VideoFrame::screen_size_pixels(border_size);
+        assert_eq!((format.width, format.height), (width, height));
+        assert_eq!(format.frame_rate, (3_500_000, 69888));
+        assert_eq!(format.pixel_aspect, (1, 1));
+        assert_eq!(format.frame_size(), width as usize * height as usize * 3);
+        assert_eq!(format.frame_pitch(), width as usize * 3);
+
+        let mut recorder = Recorder::new(TestWriter::default(), format, border_size);
+        assert_eq!(recorder.format(), &format);
+        let mut ula = UlaPAL::<Memory48k>::default();
+        ula.set_border_color(BorderColor::BLUE);
+        recorder.render_video_frame::<SpectrumPalRGB24, _>(&mut ula);
+        assert_eq!(recorder.frame_buffer()[..3], [0, 0, 0xB6]);
+        recorder.add_audio_samples(vec![[1, 2, 3].iter().copied(), [-1, -2].iter().copied()]);
+        recorder.end_frame().unwrap();
+        recorder.add_audio_samples(vec![[4].iter().copied(), [-4].iter().copied()]);
+        recorder.add_audio_samples(vec![[5].iter().copied(), [].iter().copied()]);
+        recorder.add_audio_samples(vec![[6, 7].iter().copied(), [-6, -7].iter().copied()]);
+        recorder.end_frame().unwrap();
+        recorder.end_frame().unwrap();
+        let writer = recorder.finish().unwrap();
+        assert!(writer.finished);
+        assert_eq!(writer.frames.len(), 3);
+        assert_eq!(writer.frames[0].1, [1, -1, 2, -2]);
+        assert_eq!(writer.frames[1].1, [4, -4, 6, -6, 7, -7]);
+        assert!(writer.frames[2].1.is_empty());
+        assert!(writer.frames.iter().all(|(frame, _)| frame.len() == format.frame_size()));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid number of audio channels")]
+    fn recorder_panics_on_invalid_channels() {
+        let mut recorder = Recorder::new(TestWriter::default(), TEST_FORMAT, BorderSize::Full);
+        recorder.add_audio_samples(vec![[1].iter().copied()]);
+    }
+
+    #[test]
+    fn recorder_writes_avi() {
+        let mut recorder = Recorder::new(AviWriter::new(Cursor::new(Vec::new()), TEST_FORMAT).unwrap(),
+                                         TEST_FORMAT, BorderSize::Full);
+        for frame in 0..3 {
+            recorder.add_audio_samples(vec![[frame].iter().copied(), [-frame].iter().copied()]);
+            recorder.end_frame().unwrap();
+        }
+        let writer = recorder.finish().unwrap();
+        assert_eq!(writer.frames(), 3);
+        assert_eq!(writer.sample_frames(), 3);
+        let data = writer.into_inner().into_inner();
+        assert_eq!(u32_at(&data, 4) as usize, data.len() - 8);
+        assert_eq!(u32_at(&data, 48), 3);
+    }
+}
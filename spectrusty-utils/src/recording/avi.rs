@@ -0,0 +1,339 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An uncompressed **AVI** file writer.
+use core::convert::TryFrom;
+use std::io::{self, Write, Seek, SeekFrom};
+
+use super::{FrameWriter, StreamFormat, RGB24_PIXEL_SIZE, write_u16_le, write_u32_le, stream_too_large};
+use super::wav::write_pcm_format;
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIF_ISINTERLEAVED: u32 = 0x100;
+const AVIIF_KEYFRAME: u32 = 0x10;
+const VIDEO_CHUNK_ID: &[u8;4] = b"00db";
+const AUDIO_CHUNK_ID: &[u8;4] = b"01wb";
+// positions of the header fields updated by AviWriter::finish
+const RIFF_SIZE_POS: u64 = 4;
+const AVIH_TOTAL_FRAMES_POS: u64 = 48;
+const VIDEO_STRH_LENGTH_POS: u64 = 140;
+
+/// Writes video frames and audio samples to an uncompressed **AVI** file.
+///
+/// The video stream consists of 24-bit RGB frames and the audio stream of 16-bit PCM samples. The audio
+/// stream is not present if the [StreamFormat::channels] is `0`. The audio chunks are interleaved with
+/// the video chunks, so each video frame is followed by its audio samples.
+///
+/// The file is limited to the `RIFF` size of 4 GB (the `OpenDML` extensions are not supported),
+/// which is about 3 hours of a full border PAL video.
+#[derive(Debug)]
+pub struct AviWriter<W> {
+    wr: W,
+    format: StreamFormat,
+    audio_length_pos: u64,
+    movi_pos: u64,
+    movi_size: u32,
+    index: Vec<(&'static [u8;4], u32, u32)>,
+    frames: u32,
+    sample_frames: u32,
+    line: Vec<u8>
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    /// Creates a new writer and writes the file headers with the given `format`.
+    pub fn new(mut wr: W, format: StreamFormat) -> io::Result<Self> {
+        let mut header = Vec::new();
+        let audio_length_pos = write_headers(&mut header, &format)?;
+        let movi_pos = header.len() as u64 - 4;
+        wr.write_all(&header)?;
+        let line = Vec::with_capacity(dib_pitch(format.width));
+        Ok(AviWriter {
+            wr, format, audio_length_pos, movi_pos,
+            movi_size: 4,
+            index: Vec::new(),
+            frames: 0,
+            sample_frames: 0,
+            line
+        })
+    }
+    /// Returns the number of written video frames.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+    /// Returns the number of written audio sample frames (samples per channel).
+    pub fn sample_frames(&self) -> u32 {
+        self.sample_frames
+    }
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+
+    fn write_chunk_header(&mut self, id: &'static [u8;4], size: usize) -> io::Result<()> {
+        let size = u32::try_from(size).map_err(|_| stream_too_large())?;
+        let chunk_size = size.checked_add(8 + (size & 1))
+                         .filter(|&n| n <= u32::MAX / 2)
+                         .ok_or_else(stream_too_large)?;
+        let movi_size = self.movi_size.checked_add(chunk_size)
+                        // leave a room for the headers and the index
+                        .filter(|&n| n < u32::MAX - (1 << 24) - self.index.len() as u32 * 16)
+                        .ok_or_else(stream_too_large)?;
+        self.wr.write_all(id)?;
+        write_u32_le(&mut self.wr, size)?;
+        self.index.push((id, self.movi_size, size));
+        self.movi_size = movi_size;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> FrameWriter for AviWriter<W> {
+    fn write_frame(&mut self, frame: &[u8], samples: &[i16]) -> io::Result<()> {
+        let (width, height) = (self.format.width as usize, self.format.height as usize);
+        let pitch = width * RGB24_PIXEL_SIZE;
+        if frame.len() < pitch * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the video frame is too small"))
+        }
+        let dib_pitch = dib_pitch(self.format.width);
+        self.write_chunk_header(VIDEO_CHUNK_ID, dib_pitch * height)?;
+        // DIB lines are stored bottom-up with BGR pixels
+        for rgb_line in frame[..pitch * height].chunks_exact(pitch).rev() {
+            let line = &mut self.line;
+            line.clear();
+            for rgb in rgb_line.chunks_exact(RGB24_PIXEL_SIZE) {
+                line.extend_from_slice(&[rgb[2], rgb[1], rgb[0]]);
+            }
+            line.resize(dib_pitch, 0);
+            self.wr.write_all(line)?;
+        }
+        self.frames += 1;
+        let channels = usize::from(self.format.channels);
+        if channels != 0 && !samples.is_empty() {
+            let samples = &samples[..samples.len() - samples.len() % channels];
+            let size = samples.len() * 2;
+            self.write_chunk_header(AUDIO_CHUNK_ID, size)?;
+            let mut buf = Vec::with_capacity(size + 1);
+            for sample in samples.iter() {
+                buf.extend_from_slice(&sample.to_le_bytes());
+            }
+            if size & 1 == 1 {
+                buf.push(0);
+            }
+            self.wr.write_all(&buf)?;
+            self.sample_frames += (samples.len() / channels) as u32;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut idx1 = Vec::with_capacity(8 + self.index.len() * 16);
+        idx1.extend_from_slice(b"idx1");
+        write_u32_le(&mut idx1, self.index.len() as u32 * 16)?;
+        for &(id, offset, size) in self.index.iter() {
+            idx1.extend_from_slice(id);
+            write_u32_le(&mut idx1, AVIIF_KEYFRAME)?;
+            write_u32_le(&mut idx1, offset)?;
+            write_u32_le(&mut idx1, size)?;
+        }
+        self.wr.write_all(&idx1)?;
+        // the index follows the movi list
+        let end = self.movi_pos + u64::from(self.movi_size) + idx1.len() as u64;
+        let riff_size = u32::try_from(end - 8).map_err(|_| stream_too_large())?;
+        for &(pos, value) in [(RIFF_SIZE_POS, riff_size),
+                              (AVIH_TOTAL_FRAMES_POS, self.frames),
+                              (VIDEO_STRH_LENGTH_POS, self.frames),
+                              (self.movi_pos - 4, self.movi_size)].iter() {
+            self.wr.seek(SeekFrom::Start(pos))?;
+            write_u32_le(&mut self.wr, value)?;
+        }
+        if self.format.channels != 0 {
+            self.wr.seek(SeekFrom::Start(self.audio_length_pos))?;
+            write_u32_le(&mut self.wr, self.sample_frames)?;
+        }
+        self.wr.seek(SeekFrom::Start(end))?;
+        self.wr.flush()
+    }
+}
+
+/// Returns the number of bytes of a single DIB line, aligned to 4 bytes.
+fn dib_pitch(width: u32) -> usize {
+    (width as usize * RGB24_PIXEL_SIZE + 3) & !3
+}
+
+/// Writes the `RIFF` header up to the `movi` list identifier and returns the position of
+/// the audio stream length field.
+fn write_headers(buf: &mut Vec<u8>, format: &StreamFormat) -> io::Result<u64> {
+    let (rate, scale) = format.frame_rate;
+    let frame_size = u32::try_from(dib_pitch(format.width) * format.height as usize)
+                     .map_err(|_| stream_too_large())?;
+    let block_align = u32::from(format.channels) * 2;
+    let audio_bytes_per_sec = format.sample_rate * block_align;
+    let audio_buffer_size = (u64::from(audio_bytes_per_sec) * u64::from(scale) / u64::from(rate.max(1))) as u32
+                            + block_align;
+    let streams = if format.channels == 0 { 1 } else { 2 };
+    let video_strl_size = 4 + 8 + 56 + 8 + 40;
+    let audio_strl_size = 4 + 8 + 56 + 8 + 16;
+    let mut hdrl_size = 4 + 8 + 56 + 8 + video_strl_size;
+    if format.channels != 0 {
+        hdrl_size += 8 + audio_strl_size;
+    }
+
+    buf.extend_from_slice(b"RIFF");
+    write_u32_le(&mut *buf, 0)?;
+    buf.extend_from_slice(b"AVI LIST");
+    write_u32_le(&mut *buf, hdrl_size)?;
+    buf.extend_from_slice(b"hdrlavih");
+    write_u32_le(&mut *buf, 56)?;
+    // MainAVIHeader
+    let micro_sec_per_frame = (1_000_000u64 * u64::from(scale) / u64::from(rate.max(1))) as u32;
+    write_u32_le(&mut *buf, micro_sec_per_frame)?;
+    let frame_bytes_per_sec = u64::from(frame_size) * u64::from(rate) / u64::from(scale.max(1));
+    write_u32_le(&mut *buf, (frame_bytes_per_sec + u64::from(audio_bytes_per_sec)).min(u32::MAX.into()) as u32)?;
+    write_u32_le(&mut *buf, 0)?; // padding granularity
+    write_u32_le(&mut *buf, AVIF_HASINDEX | AVIF_ISINTERLEAVED)?;
+    debug_assert_eq!(buf.len() as u64, AVIH_TOTAL_FRAMES_POS);
+    write_u32_le(&mut *buf, 0)?; // total frames
+    write_u32_le(&mut *buf, 0)?; // initial frames
+    write_u32_le(&mut *buf, streams)?;
+    write_u32_le(&mut *buf, frame_size)?; // suggested buffer size
+    write_u32_le(&mut *buf, format.width)?;
+    write_u32_le(&mut *buf, format.height)?;
+    buf.extend_from_slice(&[0; 16]); // reserved
+    // video stream
+    buf.extend_from_slice(b"LIST");
+    write_u32_le(&mut *buf, video_strl_size)?;
+    buf.extend_from_slice(b"strlstrh");
+    write_u32_le(&mut *buf, 56)?;
+    buf.extend_from_slice(b"vidsDIB ");
+    write_stream_header(buf, scale, rate, frame_size, 0, (format.width, format.height))?;
+    buf.extend_from_slice(b"strf");
+    write_u32_le(&mut *buf, 40)?;
+    // BITMAPINFOHEADER
+    write_u32_le(&mut *buf, 40)?;
+    write_u32_le(&mut *buf, format.width)?;
+    write_u32_le(&mut *buf, format.height)?; // positive height: bottom-up DIB
+    write_u16_le(&mut *buf, 1)?; // planes
+    write_u16_le(&mut *buf, 24)?; // bits per pixel
+    write_u32_le(&mut *buf, 0)?; // BI_RGB
+    write_u32_le(&mut *buf, frame_size)?;
+    buf.extend_from_slice(&[0; 16]);
+    // audio stream
+    let mut audio_length_pos = 0;
+    if format.channels != 0 {
+        buf.extend_from_slice(b"LIST");
+        write_u32_le(&mut *buf, audio_strl_size)?;
+        buf.extend_from_slice(b"strlstrh");
+        write_u32_le(&mut *buf, 56)?;
+        buf.extend_from_slice(b"auds\0\0\0\0");
+        audio_length_pos = buf.len() as u64 + 24;
+        write_stream_header(buf, block_align, audio_bytes_per_sec, audio_buffer_size, block_align, (0, 0))?;
+        buf.extend_from_slice(b"strf");
+        write_u32_le(&mut *buf, 16)?;
+        write_pcm_format(&mut *buf, format.sample_rate, format.channels)?;
+    }
+    buf.extend_from_slice(b"LIST");
+    write_u32_le(&mut *buf, 0)?;
+    buf.extend_from_slice(b"movi");
+    Ok(audio_length_pos)
+}
+
+/// Writes the `AVIStreamHeader` fields following `fccType` and `fccHandler`.
+fn write_stream_header(
+        buf: &mut Vec<u8>,
+        scale: u32,
+        rate: u32,
+        buffer_size: u32,
+        sample_size: u32,
+        (width, height): (u32, u32)
+    ) -> io::Result<()>
+{
+    write_u32_le(&mut *buf, 0)?; // flags
+    write_u16_le(&mut *buf, 0)?; // priority
+    write_u16_le(&mut *buf, 0)?; // language
+    write_u32_le(&mut *buf, 0)?; // initial frames
+    write_u32_le(&mut *buf, scale)?;
+    write_u32_le(&mut *buf, rate)?;
+    write_u32_le(&mut *buf, 0)?; // start
+    write_u32_le(&mut *buf, 0)?; // length
+    write_u32_le(&mut *buf, buffer_size)?;
+    write_u32_le(&mut *buf, u32::MAX)?; // quality
+    write_u32_le(&mut *buf, sample_size)?;
+    write_u16_le(&mut *buf, 0)?;
+    write_u16_le(&mut *buf, 0)?;
+    write_u16_le(&mut *buf, width as u16)?;
+    write_u16_le(&mut *buf, height as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::recording::tests::{TEST_FORMAT, u32_at};
+    use super::*;
+
+    #[test]
+    fn avi_works() {
+        let mut avi = AviWriter::new(Cursor::new(Vec::new()), TEST_FORMAT).unwrap();
+        let audio_length_pos = avi.audio_length_pos as usize;
+        let movi_pos = avi.movi_pos as usize;
+        let frame: Vec<u8> = (0..18).collect();
+        avi.write_frame(&frame, &[1, -1, 2, -2]).unwrap();
+        avi.write_frame(&frame, &[]).unwrap();
+        avi.write_frame(&frame, &[3, -3, 4]).unwrap();
+        assert_eq!(avi.write_frame(&frame[1..], &[]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        avi.finish().unwrap();
+        assert_eq!(avi.frames(), 3);
+        assert_eq!(avi.sample_frames(), 3);
+        let data = avi.into_inner().into_inner();
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(&data, RIFF_SIZE_POS as usize) as usize, data.len() - 8);
+        assert_eq!(&data[8..12], b"AVI ");
+        assert_eq!(u32_at(&data, AVIH_TOTAL_FRAMES_POS as usize), 3);
+        assert_eq!(&data[VIDEO_STRH_LENGTH_POS as usize - 32..][..8], b"vidsDIB ");
+        assert_eq!(u32_at(&data, VIDEO_STRH_LENGTH_POS as usize), 3);
+        assert_eq!(u32_at(&data, audio_length_pos), 3);
+        assert_eq!(&data[movi_pos..movi_pos + 4], b"movi");
+        let movi_size = u32_at(&data, movi_pos - 4) as usize;
+        // the video frame: 2 lines of 3 BGR pixels padded to 12 bytes, bottom-up
+        let video_chunk = &data[movi_pos + 4..][..8 + 24];
+        assert_eq!(&video_chunk[..4], VIDEO_CHUNK_ID);
+        assert_eq!(u32_at(video_chunk, 4), 24);
+        assert_eq!(video_chunk[8..20], [11, 10, 9, 14, 13, 12, 17, 16, 15, 0, 0, 0]);
+        assert_eq!(video_chunk[20..32], [2, 1, 0, 5, 4, 3, 8, 7, 6, 0, 0, 0]);
+
+        let idx1_pos = movi_pos + movi_size;
+        assert_eq!(&data[idx1_pos..idx1_pos + 4], b"idx1");
+        assert_eq!(u32_at(&data, idx1_pos + 4), 5 * 16);
+        assert_eq!(data.len(), idx1_pos + 8 + 5 * 16);
+        let expected: [(&[u8;4], u32); 5] = [(VIDEO_CHUNK_ID, 24), (AUDIO_CHUNK_ID, 8), (VIDEO_CHUNK_ID, 24),
+                                             (VIDEO_CHUNK_ID, 24), (AUDIO_CHUNK_ID, 4)];
+        for (entry, &(id, size)) in data[idx1_pos + 8..].chunks_exact(16).zip(expected.iter()) {
+            assert_eq!(&entry[..4], id);
+            assert_eq!(u32_at(entry, 4), AVIIF_KEYFRAME);
+            let offset = movi_pos + u32_at(entry, 8) as usize;
+            assert_eq!(&data[offset..offset + 4], id);
+            assert_eq!(u32_at(&data, offset + 4), size);
+            assert_eq!(u32_at(entry, 12), size);
+        }
+        let last_audio = movi_pos + u32_at(&data[idx1_pos + 8 + 4 * 16..], 8) as usize + 8;
+        assert_eq!(data[last_audio..last_audio + 4], [3, 0, 0xFD, 0xFF]);
+    }
+
+    #[test]
+    fn avi_video_only_works() {
+        let format = StreamFormat { channels: 0, ..TEST_FORMAT };
+        let mut avi = AviWriter::new(Cursor::new(Vec::new()), format).unwrap();
+        avi.write_frame(&[0; 18], &[1, 2]).unwrap();
+        avi.write_frame(&[0; 18], &[]).unwrap();
+        avi.finish().unwrap();
+        assert_eq!(avi.sample_frames(), 0);
+        let data = avi.into_inner().into_inner();
+        assert_eq!(u32_at(&data, 4) as usize, data.len() - 8);
+        assert_eq!(u32_at(&data, AVIH_TOTAL_FRAMES_POS as usize), 2);
+        assert_eq!(u32_at(&data, AVIH_TOTAL_FRAMES_POS as usize + 8), 1);
+        assert_eq!(u32_at(&data, VIDEO_STRH_LENGTH_POS as usize), 2);
+        assert_eq!(u32_at(&data, data.len() - 2 * 16 - 4), 2 * 16);
+    }
+}
@@ -0,0 +1,120 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A **WAV** file writer with 16-bit PCM samples.
+use core::convert::TryFrom;
+use std::io::{self, Write, Seek, SeekFrom};
+
+use super::{write_u16_le, write_u32_le, stream_too_large};
+
+const WAV_HEADER_SIZE: u32 = 44;
+const RIFF_SIZE_POS: u64 = 4;
+const DATA_SIZE_POS: u64 = 40;
+const WAVE_FORMAT_PCM: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writes 16-bit PCM audio samples to a **WAV** file.
+///
+/// The header is written on creation and updated with the data size by [WavWriter::finish].
+#[derive(Debug)]
+pub struct WavWriter<W> {
+    wr: W,
+    data_size: u32
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Creates a new writer and writes the **WAV** header with the given parameters.
+    pub fn new(mut wr: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        write_pcm_header(&mut wr, sample_rate, channels, 0)?;
+        Ok(WavWriter { wr, data_size: 0 })
+    }
+    /// Writes audio `samples`, interleaved by channels.
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let size = u32::try_from(samples.len() * 2).ok()
+                   .and_then(|size| self.data_size.checked_add(size))
+                   .filter(|&size| size <= u32::MAX - WAV_HEADER_SIZE)
+                   .ok_or_else(stream_too_large)?;
+        let mut buf = Vec::with_capacity(samples.len() * 2);
+        for sample in samples.iter() {
+            buf.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.wr.write_all(&buf)?;
+        self.data_size = size;
+        Ok(())
+    }
+    /// Updates the header with the size of the written data and flushes the writer.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let end = u64::from(WAV_HEADER_SIZE + self.data_size);
+        self.wr.seek(SeekFrom::Start(RIFF_SIZE_POS))?;
+        write_u32_le(&mut self.wr, WAV_HEADER_SIZE - 8 + self.data_size)?;
+        self.wr.seek(SeekFrom::Start(DATA_SIZE_POS))?;
+        write_u32_le(&mut self.wr, self.data_size)?;
+        self.wr.seek(SeekFrom::Start(end))?;
+        self.wr.flush()
+    }
+    /// Returns the number of bytes of the written audio data.
+    pub fn data_size(&self) -> u32 {
+        self.data_size
+    }
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+}
+
+/// Writes the `PCMWAVEFORMAT` structure of 16-bit samples.
+pub(crate) fn write_pcm_format<W: Write>(mut wr: W, sample_rate: u32, channels: u16) -> io::Result<()> {
+    let block_align = channels * (BITS_PER_SAMPLE / 8);
+    write_u16_le(&mut wr, WAVE_FORMAT_PCM)?;
+    write_u16_le(&mut wr, channels)?;
+    write_u32_le(&mut wr, sample_rate)?;
+    write_u32_le(&mut wr, sample_rate * u32::from(block_align))?;
+    write_u16_le(&mut wr, block_align)?;
+    write_u16_le(&mut wr, BITS_PER_SAMPLE)
+}
+
+fn write_pcm_header<W: Write>(mut wr: W, sample_rate: u32, channels: u16, data_size: u32) -> io::Result<()> {
+    wr.write_all(b"RIFF")?;
+    write_u32_le(&mut wr, WAV_HEADER_SIZE - 8 + data_size)?;
+    wr.write_all(b"WAVEfmt ")?;
+    write_u32_le(&mut wr, 16)?;
+    write_pcm_format(&mut wr, sample_rate, channels)?;
+    wr.write_all(b"data")?;
+    write_u32_le(&mut wr, data_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::recording::tests::u32_at;
+    use super::*;
+
+    #[test]
+    fn wav_works() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 100, 2).unwrap();
+        wav.write_samples(&[1, -1, 2, -2]).unwrap();
+        wav.write_samples(&[]).unwrap();
+        wav.write_samples(&[3, -3]).unwrap();
+        assert_eq!(wav.data_size(), 12);
+        wav.finish().unwrap();
+        let data = wav.into_inner().into_inner();
+        assert_eq!(data.len(), 44 + 12);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(&data, 4), 36 + 12);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&data, 16), 16);
+        assert_eq!(data[20..24], [1, 0, 2, 0]);
+        assert_eq!(u32_at(&data, 24), 100);
+        assert_eq!(u32_at(&data, 28), 400);
+        assert_eq!(data[32..36], [4, 0, 16, 0]);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32_at(&data, 40), 12);
+        let samples: Vec<i16> = data[44..].chunks_exact(2)
+                                .map(|s| i16::from_le_bytes([s[0], s[1]])).collect();
+        assert_eq!(samples, [1, -1, 2, -2, 3, -3]);
+    }
+}
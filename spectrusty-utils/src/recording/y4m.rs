@@ -0,0 +1,169 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! A **YUV4MPEG2** video stream writer.
+use std::io::{self, Write, Seek};
+
+use super::{FrameWriter, StreamFormat, RGB24_PIXEL_SIZE};
+use super::wav::WavWriter;
+
+/// Writes video frames to a **YUV4MPEG2** (`.y4m`) stream.
+///
+/// The frames are being converted from RGB to the `Y'CbCr` color space with the ITU-R BT.601 coefficients
+/// (limited range) without chroma subsampling (`C444`).
+#[derive(Debug)]
+pub struct Y4mWriter<W> {
+    wr: W,
+    format: StreamFormat,
+    planes: Vec<u8>,
+    frames: u32
+}
+
+/// Writes video frames to a **YUV4MPEG2** stream and audio samples to a separate **WAV** file.
+#[derive(Debug)]
+pub struct Y4mWavWriter<V, A> {
+    /// The video stream writer.
+    pub video: Y4mWriter<V>,
+    /// The audio stream writer.
+    pub audio: WavWriter<A>
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Creates a new writer and writes the stream header with the given `format`.
+    ///
+    /// The audio parameters of the `format` are ignored.
+    pub fn new(mut wr: W, format: StreamFormat) -> io::Result<Self> {
+        let (num, den) = format.frame_rate;
+        let (aspect_w, aspect_h) = format.pixel_aspect;
+        writeln!(wr, "YUV4MPEG2 W{} H{} F{}:{} Ip A{}:{} C444",
+                    format.width, format.height, num, den, aspect_w, aspect_h)?;
+        let planes = Vec::with_capacity(format.frame_size());
+        Ok(Y4mWriter { wr, format, planes, frames: 0 })
+    }
+    /// Writes a single video `frame` of `RGB24` pixels.
+    pub fn write_video_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        let pixels = self.format.width as usize * self.format.height as usize;
+        if frame.len() < pixels * RGB24_PIXEL_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the video frame is too small"))
+        }
+        let planes = &mut self.planes;
+        planes.clear();
+        planes.resize(pixels * 3, 0);
+        let (y_plane, cbcr) = planes.split_at_mut(pixels);
+        let (cb_plane, cr_plane) = cbcr.split_at_mut(pixels);
+        for (((rgb, y), cb), cr) in frame.chunks_exact(RGB24_PIXEL_SIZE)
+                                         .zip(y_plane.iter_mut())
+                                         .zip(cb_plane.iter_mut())
+                                         .zip(cr_plane.iter_mut())
+        {
+            let [vy, vcb, vcr] = rgb_to_ycbcr(rgb[0], rgb[1], rgb[2]);
+            *y = vy;
+            *cb = vcb;
+            *cr = vcr;
+        }
+        self.wr.write_all(b"FRAME\n")?;
+        self.wr.write_all(planes)?;
+        self.frames = self.frames.wrapping_add(1);
+        Ok(())
+    }
+    /// Returns the number of written video frames.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+    /// Flushes the writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wr.flush()
+    }
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wr
+    }
+}
+
+impl<V: Write, A: Write + Seek> Y4mWavWriter<V, A> {
+    /// Creates new video and audio stream writers with the given `format`.
+    pub fn new(video: V, audio: A, format: StreamFormat) -> io::Result<Self> {
+        let video = Y4mWriter::new(video, format)?;
+        let audio = WavWriter::new(audio, format.sample_rate, format.channels)?;
+        Ok(Y4mWavWriter { video, audio })
+    }
+}
+
+impl<V: Write, A: Write + Seek> FrameWriter for Y4mWavWriter<V, A> {
+    fn write_frame(&mut self, frame: &[u8], samples: &[i16]) -> io::Result<()> {
+        self.video.write_video_frame(frame)?;
+        self.audio.write_samples(samples)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.video.flush()?;
+        self.audio.finish()
+    }
+}
+
+impl<W: Write> FrameWriter for Y4mWriter<W> {
+    /// # Note
+    /// The audio `samples` are ignored.
+    fn write_frame(&mut self, frame: &[u8], _samples: &[i16]) -> io::Result<()> {
+        self.write_video_frame(frame)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+#[inline]
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (i32::from(r), i32::from(g), i32::from(b));
+    let y  = ( 16829 * r + 33039 * g +  6416 * b + (16 << 16) + 32768) >> 16;
+    let cb = ( -9714 * r - 19070 * g + 28784 * b + (128 << 16) + 32768) >> 16;
+    let cr = ( 28784 * r - 24103 * g -  4681 * b + (128 << 16) + 32768) >> 16;
+    [y as u8, cb as u8, cr as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::recording::tests::{TEST_FORMAT, u32_at};
+    use super::*;
+
+    #[test]
+    fn y4m_works() {
+        let mut y4m = Y4mWriter::new(Vec::new(), TEST_FORMAT).unwrap();
+        let black = [0u8; 18];
+        let white = [255u8; 18];
+        for frame in [&black, &white, &black].iter() {
+            y4m.write_video_frame(&frame[..]).unwrap();
+        }
+        assert_eq!(y4m.write_video_frame(&black[1..]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(y4m.frames(), 3);
+        let data = y4m.into_inner();
+        let header = b"YUV4MPEG2 W3 H2 F50:1 Ip A1:1 C444\n";
+        assert_eq!(&data[..header.len()], &header[..]);
+        let frames: Vec<&[u8]> = data[header.len()..].chunks(6 + 18).collect();
+        assert_eq!(frames.len(), 3);
+        for (frame, &y) in frames.iter().zip([16u8, 235, 16].iter()) {
+            assert_eq!(&frame[..6], b"FRAME\n");
+            assert!(frame[6..12].iter().all(|&v| v == y));
+            assert!(frame[12..].iter().all(|&v| v == 128));
+        }
+    }
+
+    #[test]
+    fn y4m_wav_works() {
+        let mut writer = Y4mWavWriter::new(Vec::new(), Cursor::new(Vec::new()), TEST_FORMAT).unwrap();
+        writer.write_frame(&[0; 18], &[1, -1]).unwrap();
+        writer.write_frame(&[0; 18], &[2, -2, 3, -3]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(writer.video.frames(), 2);
+        assert_eq!(writer.audio.data_size(), 12);
+        let audio = writer.audio.into_inner().into_inner();
+        assert_eq!(u32_at(&audio, 40), 12);
+        assert_eq!(audio.len(), 44 + 12);
+    }
+}
@@ -11,6 +11,11 @@ pub mod ula128;
 pub mod ula3;
 pub mod scld;
 pub mod plus;
+pub mod pentagon;
+pub mod scorpion;
+pub mod chloe;
+#[cfg(feature = "peripherals")]
+pub mod didaktik;
 #[cfg(feature = "peripherals")]
 pub mod ay_player;
 use crate::memory::{ZxMemory, PagedMemory8k};
@@ -20,8 +25,13 @@ use crate::peripherals::KeyboardInterface;
 use ula::{Ula, UlaVideoFrame, UlaNTSC, UlaNTSCVidFrame};
 use ula128::{Ula128, Ula128VidFrame};
 use ula3::Ula3;
-use scld::Scld;
+use scld::{Scld, ScldVidFrame, ScldNTSCVidFrame};
 use plus::UlaPlus;
+use pentagon::{Pentagon, PentagonVidFrame};
+use scorpion::{Scorpion, ScorpionVidFrame};
+use chloe::Chloe;
+#[cfg(feature = "peripherals")]
+use didaktik::DidaktikGama;
 pub use spectrusty_core::chip::*;
 
 /// ZX Spectrum PAL configuration parameters.
@@ -45,6 +55,34 @@ impl HostConfig for ZxSpectrum128Config {
     const FRAME_TSTATES: FTs = Ula128VidFrame::FRAME_TSTATES_COUNT;
 }
 
+/// Timex TC2048/TC2068 configuration parameters.
+pub struct TimexPALConfig;
+impl HostConfig for TimexPALConfig {
+    const CPU_HZ: u32 = 3_500_000;
+    const FRAME_TSTATES: FTs = ScldVidFrame::FRAME_TSTATES_COUNT;
+}
+
+/// Timex TS2068 configuration parameters.
+pub struct TimexNTSCConfig;
+impl HostConfig for TimexNTSCConfig {
+    const CPU_HZ: u32 = 3_528_000;
+    const FRAME_TSTATES: FTs = ScldNTSCVidFrame::FRAME_TSTATES_COUNT;
+}
+
+/// Pentagon 128k/512k/1024k configuration parameters.
+pub struct PentagonConfig;
+impl HostConfig for PentagonConfig {
+    const CPU_HZ: u32 = 3_500_000;
+    const FRAME_TSTATES: FTs = PentagonVidFrame::FRAME_TSTATES_COUNT;
+}
+
+/// Scorpion ZS-256 configuration parameters.
+pub struct ScorpionConfig;
+impl HostConfig for ScorpionConfig {
+    const CPU_HZ: u32 = 3_500_000;
+    const FRAME_TSTATES: FTs = ScorpionVidFrame::FRAME_TSTATES_COUNT;
+}
+
 /// A grouping trait of all common control traits for all emulated `Ula` chipsets except audio rendering.
 ///
 /// For audio rendering see [crate::audio::UlaAudioFrame].
@@ -76,6 +114,11 @@ pub trait UlaControl {
     /// Sets the current value of the memory port `0x1FFD`. Returns `true` if supported.
     /// Otherwise, returns `false` and no writing is performed.
     fn set_ula3_ctrl_port_value(&mut self, _value: Ula3CtrlFlags) -> bool { false }
+    /// Returns the last value sent to the Scorpion's memory port `0x1FFD` if supported.
+    fn scorpion_mem_port_value(&self) -> Option<ScorpionMemFlags> { None }
+    /// Sets the current value of the Scorpion's memory port `0x1FFD`. Returns `true` if supported.
+    /// Otherwise, returns `false` and no writing is performed.
+    fn set_scorpion_mem_port_value(&mut self, _value: ScorpionMemFlags) -> bool { false }
     /// Returns the last value sent to the memory port `0xFF`.
     fn scld_ctrl_port_value(&self) -> Option<ScldCtrlFlags> { None }
     /// Sets the current value of the memory port `0xFF`. Returns `true` if supported.
@@ -108,6 +151,16 @@ impl<M: PagedMemory8k, B, X> HostConfig for Scld<M, B, X, UlaVideoFrame> {
     const FRAME_TSTATES: FTs = <Self as Video>::VideoFrame::FRAME_TSTATES_COUNT;
 }
 
+impl<M: PagedMemory8k, B, X> HostConfig for Scld<M, B, X, ScldVidFrame> {
+    const CPU_HZ: u32 = TimexPALConfig::CPU_HZ;
+    const FRAME_TSTATES: FTs = TimexPALConfig::FRAME_TSTATES;
+}
+
+impl<M: PagedMemory8k, B, X> HostConfig for Scld<M, B, X, ScldNTSCVidFrame> {
+    const CPU_HZ: u32 = TimexNTSCConfig::CPU_HZ;
+    const FRAME_TSTATES: FTs = TimexNTSCConfig::FRAME_TSTATES;
+}
+
 impl<M: ZxMemory, B, X> HostConfig for UlaNTSC<M, B, X> {
     const CPU_HZ: u32 = ZxSpectrumNTSCConfig::CPU_HZ;
     const FRAME_TSTATES: FTs = <Self as Video>::VideoFrame::FRAME_TSTATES_COUNT;
@@ -123,6 +176,27 @@ impl<B, X> HostConfig for Ula3<B, X> {
     const FRAME_TSTATES: FTs = <Self as Video>::VideoFrame::FRAME_TSTATES_COUNT;
 }
 
+impl<M, B, X> HostConfig for Pentagon<M, B, X> {
+    const CPU_HZ: u32 = PentagonConfig::CPU_HZ;
+    const FRAME_TSTATES: FTs = PentagonConfig::FRAME_TSTATES;
+}
+
+impl<B, X> HostConfig for Scorpion<B, X> {
+    const CPU_HZ: u32 = ScorpionConfig::CPU_HZ;
+    const FRAME_TSTATES: FTs = ScorpionConfig::FRAME_TSTATES;
+}
+
+impl<B, X> HostConfig for Chloe<B, X> {
+    const CPU_HZ: u32 = ZxSpectrumPALConfig::CPU_HZ;
+    const FRAME_TSTATES: FTs = ZxSpectrumPALConfig::FRAME_TSTATES;
+}
+
+#[cfg(feature = "peripherals")]
+impl<B, X> HostConfig for DidaktikGama<B, X> {
+    const CPU_HZ: u32 = ZxSpectrumPALConfig::CPU_HZ;
+    const FRAME_TSTATES: FTs = ZxSpectrumPALConfig::FRAME_TSTATES;
+}
+
 impl<U: HostConfig + Video> HostConfig for UlaPlus<U> {
     const CPU_HZ: u32 = U::CPU_HZ;
     const FRAME_TSTATES: FTs = U::FRAME_TSTATES;
@@ -0,0 +1,482 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! An emulator of the Chloe chipset used in the ZX Spectrum SE (Chloe 280SE) model.
+
+The ZX Spectrum SE combines the memory paging of the 128k model with the Timex's DOCK and EX-ROM
+bank switching and screen modes of the SCLD chip.
+
+Implementation specifics:
+
+* The video frame and the memory contention are the same as of the original 48k model.
+* SCLD ports: `0xFF` and `0xF4` as well as ULA `0xFE` port are decoded on all 8 lowest address bits.
+* The 128k memory port `0x7FFD` is decoded on address bits: `A15` and `A1` (both must be 0).
+* The hard reset defaults the screen mode and memory paging but leaves the border-color unmodified.
+* The 272kb RAM is organized in 34 8k banks as follows:
+
+```text
+  128k RAM: [0, 15]  - 16k RAM page N consists of 8k banks: [2*N, 2*N + 1]
+  HOME RAM: [16, 17] - mapped at 0x8000-0xBFFF in the HOME memory
+      DOCK: [18, 25] - see DOCK_BANK
+    EX-ROM: [26, 33] - see EX_ROM_BANK
+```
+
+* The HOME memory is mapped from:
+
+```text
+0x0000-0x3FFF: ROM 0 or ROM 1 (selected by 0x7FFD) - 8k ROM banks: [2*N, 2*N + 1]
+0x4000-0x7FFF: 16k RAM page 5
+0x8000-0xBFFF: HOME RAM
+0xC000-0xFFFF: 16k RAM page 0-7 (selected by 0x7FFD)
+```
+
+* The primary screens are located at the 16k RAM pages 5 and 7 (shadow screen) and the secondary
+  screens at the second half of the same pages.
+
+The 32k ROM should be loaded to ROM banks `[0, 3]` with the 128k editor ROM first and the 48k BASIC ROM next.
+*/
+mod audio_earmic;
+mod io;
+mod video;
+#[cfg(feature = "formats")]
+mod screen;
+
+use core::fmt;
+
+use crate::z80emu::{*, host::Result};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::bus::{BusDevice, VFNullDevice};
+use crate::clock::{
+    VFrameTs, VideoTs,
+    VideoTsData2, VideoTsData6,
+    VFrameTsCounter, MemoryContention
+};
+use crate::chip::{
+    InnerAccess, ControlUnit, MemoryAccess, Ula128MemFlags, ScldCtrlFlags, UlaControl,
+    ula::{
+        Ula, UlaVideoFrame, UlaControlExt, UlaCpuExt,
+        frame_cache::UlaFrameCache
+    },
+    scld::frame_cache::SourceMode
+};
+use crate::memory::{ZxMemory, MemoryExtension, NoMemoryExtension, Memory272k};
+use crate::video::{Video, BorderColor, RenderMode};
+
+/// The first 8k RAM bank of the HOME RAM mapped at `0x8000`.
+pub const HOME_RAM_BANK: usize = 16;
+/// The first 8k RAM bank of the DOCK memory.
+pub const DOCK_BANK: usize = 18;
+/// The first 8k RAM bank of the EX-ROM memory.
+pub const EX_ROM_BANK: usize = 26;
+
+type InnerUla<B, X> = Ula<Memory272k, B, X, UlaVideoFrame>;
+
+/// ZX Spectrum SE (Chloe 280SE) chipset.
+///
+/// See [Ula] for description of generic parameters.
+#[derive(Clone)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Chloe<B=VFNullDevice<UlaVideoFrame>, X=NoMemoryExtension> {
+    ula: InnerUla<B, X>,
+    mem_page3_bank: u8,         // 16k RAM page selected by 0x7FFD
+    rom_bank: u8,               // 16k ROM page selected by 0x7FFD
+    beg_screen_shadow: bool,    // shadow screen when a frame began
+    cur_screen_shadow: bool,    // current shadow screen
+    mem_locked: bool,
+    beg_ctrl_flags: ScldCtrlFlags,
+    cur_ctrl_flags: ScldCtrlFlags,
+    mem_paged: u8,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    sec_frame_cache: UlaFrameCache<UlaVideoFrame>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    shadow_frame_cache: UlaFrameCache<UlaVideoFrame>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    shadow_sec_frame_cache: UlaFrameCache<UlaVideoFrame>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    screen_changes: Vec<VideoTs>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    mode_changes: Vec<VideoTsData6>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    source_changes: Vec<VideoTsData2>,
+}
+
+impl<B: Default, X: Default> Default for Chloe<B, X> {
+    fn default() -> Self {
+        Chloe {
+            ula: Default::default(),
+            mem_page3_bank: 0,
+            rom_bank: 0,
+            beg_screen_shadow: false,
+            cur_screen_shadow: false,
+            mem_locked: false,
+            beg_ctrl_flags: ScldCtrlFlags::empty(),
+            cur_ctrl_flags: ScldCtrlFlags::empty(),
+            mem_paged: 0,
+            sec_frame_cache: Default::default(),
+            shadow_frame_cache: Default::default(),
+            shadow_sec_frame_cache: Default::default(),
+            screen_changes: Vec::new(),
+            mode_changes: Vec::new(),
+            source_changes: Vec::new(),
+        }
+    }
+}
+
+impl<B, X> fmt::Debug for Chloe<B, X>
+    where B: BusDevice, X: MemoryExtension
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chloe")
+            .field("ula", &self.ula)
+            .field("mem_page3_bank", &self.mem_page3_bank)
+            .field("rom_bank", &self.rom_bank)
+            .field("beg_screen_shadow", &self.beg_screen_shadow)
+            .field("cur_screen_shadow", &self.cur_screen_shadow)
+            .field("mem_locked", &self.mem_locked)
+            .field("beg_ctrl_flags", &self.beg_ctrl_flags)
+            .field("cur_ctrl_flags", &self.cur_ctrl_flags)
+            .field("mem_paged", &self.mem_paged)
+            .field("sec_frame_cache", &self.sec_frame_cache)
+            .field("shadow_frame_cache", &self.shadow_frame_cache)
+            .field("shadow_sec_frame_cache", &self.shadow_sec_frame_cache)
+            .field("screen_changes", &self.screen_changes.len())
+            .field("mode_changes", &self.mode_changes.len())
+            .field("source_changes", &self.source_changes.len())
+            .finish()
+    }
+}
+
+impl<B, X> InnerAccess for Chloe<B, X> {
+    type Inner = InnerUla<B, X>;
+
+    fn inner_ref(&self) -> &Self::Inner {
+        &self.ula
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Inner {
+        &mut self.ula
+    }
+
+    fn into_inner(self) -> Self::Inner {
+        self.ula
+    }
+}
+
+impl<B, X> UlaControl for Chloe<B, X> {
+    fn has_late_timings(&self) -> bool {
+        self.ula.has_late_timings()
+    }
+
+    fn set_late_timings(&mut self, late_timings: bool) {
+        self.ula.set_late_timings(late_timings)
+    }
+
+    fn ula128_mem_port_value(&self) -> Option<Ula128MemFlags> {
+        let mut flags = Ula128MemFlags::empty()
+                        .with_last_ram_page_bank(self.mem_page3_bank.into());
+        flags.set(Ula128MemFlags::SCREEN_BANK, self.cur_screen_shadow);
+        flags.set(Ula128MemFlags::ROM_BANK, self.rom_bank != 0);
+        flags.set(Ula128MemFlags::LOCK_MMU, self.mem_locked);
+        Some(flags)
+    }
+
+    fn set_ula128_mem_port_value(&mut self, value: Ula128MemFlags) -> bool {
+        self.set_mem_port_value(value, self.ula.current_video_ts());
+        true
+    }
+
+    fn scld_ctrl_port_value(&self) -> Option<ScldCtrlFlags> {
+        Some(self.cur_ctrl_flags)
+    }
+
+    fn set_scld_ctrl_port_value(&mut self, value: ScldCtrlFlags) -> bool {
+        self.set_ctrl_flags_value(value, self.ula.current_video_ts());
+        true
+    }
+
+    fn scld_mmu_port_value(&self) -> Option<u8> {
+        Some(self.mem_paged)
+    }
+
+    fn set_scld_mmu_port_value(&mut self, value: u8) -> bool {
+        self.set_mmu_flags_value(value);
+        true
+    }
+}
+
+impl<B, X> Chloe<B, X> {
+    #[inline]
+    fn push_mode_change(&mut self, ts: VideoTs, render_mode: RenderMode) {
+        self.mode_changes.push((ts, render_mode.bits()).into())
+    }
+
+    #[inline]
+    fn change_border_color(&mut self, border: BorderColor, ts: VideoTs) {
+        if self.ula.last_border != border {
+            if !self.cur_ctrl_flags.is_screen_hi_res() {
+                self.push_mode_change(ts,
+                        RenderMode::with_color(RenderMode::empty(), border.into()));
+            }
+            self.ula.last_border = border;
+        }
+    }
+
+    fn beg_render_mode(&self) -> RenderMode {
+        let (flags, color) = if self.beg_ctrl_flags.is_screen_hi_res() {
+            (RenderMode::HI_RESOLUTION, self.beg_ctrl_flags.hires_color_index())
+        }
+        else {
+            (RenderMode::empty(), self.ula.border.into())
+        };
+        RenderMode::with_color(flags, color)
+    }
+
+    fn map_page(&mut self, page: u8) {
+        let memory = &mut self.ula.memory;
+        let offset = page as usize & 1;
+        if self.mem_paged & (1 << page) != 0 {
+            if self.cur_ctrl_flags.is_map_ex_rom() {
+                memory.map_ram_bank(EX_ROM_BANK + page as usize, page)
+            }
+            else {
+                memory.map_ram_bank(DOCK_BANK + page as usize, page)
+            }
+        }
+        else {
+            match page {
+                0|1 => memory.map_rom_bank(2 * self.rom_bank as usize + offset, page),
+                2|3 => memory.map_ram_bank(2 * 5 + offset, page),
+                4|5 => memory.map_ram_bank(HOME_RAM_BANK + offset, page),
+                _   => memory.map_ram_bank(2 * self.mem_page3_bank as usize + offset, page)
+            }
+        }.unwrap()
+    }
+
+    fn set_mem_port_value(&mut self, flags: Ula128MemFlags, ts: VideoTs) {
+        self.mem_locked = flags.is_mmu_locked();
+        let cur_screen_shadow = flags.is_shadow_screen();
+        if self.cur_screen_shadow != cur_screen_shadow {
+            self.cur_screen_shadow = cur_screen_shadow;
+            self.screen_changes.push(ts);
+        }
+        self.rom_bank = flags.rom_page_bank() as u8;
+        self.mem_page3_bank = flags.last_ram_page_bank() as u8;
+        for &page in &[0, 1, 6, 7] {
+            self.map_page(page);
+        }
+    }
+
+    fn set_ctrl_flags_value(&mut self, flags: ScldCtrlFlags, ts: VideoTs) {
+        let diff_flags = self.cur_ctrl_flags ^ flags;
+        // source mode
+        if diff_flags.intersects(ScldCtrlFlags::SCREEN_SOURCE_MASK) {
+            let source_mode = SourceMode::from_scld_flags(flags);
+            self.source_changes.push((ts, source_mode.bits()).into());
+        }
+        // render mode
+        if flags.is_screen_hi_res() {
+            if diff_flags.intersects(ScldCtrlFlags::SCREEN_HI_RES|ScldCtrlFlags::HIRES_COLOR_MASK) {
+                self.push_mode_change(ts,
+                    RenderMode::with_color(
+                        RenderMode::HI_RESOLUTION, flags.hires_color_index()));
+            }
+        }
+        else if diff_flags.is_screen_hi_res() {
+            self.push_mode_change(ts,
+                RenderMode::with_color(
+                    RenderMode::empty(), self.ula.last_border.into()));
+        }
+
+        self.cur_ctrl_flags = flags;
+        // mmu
+        if self.mem_paged != 0 && diff_flags.is_map_ex_rom() {
+            for page in 0..8u8 {
+                if self.mem_paged & (1 << page) != 0 {
+                    self.map_page(page);
+                }
+            }
+        }
+    }
+
+    fn set_mmu_flags_value(&mut self, paged: u8) {
+        let diff_pages = self.mem_paged ^ paged;
+        self.mem_paged = paged;
+        for page in 0..8u8 {
+            if diff_pages & (1 << page) != 0 {
+                self.map_page(page);
+            }
+        }
+    }
+}
+
+impl<B, X> MemoryAccess for Chloe<B, X>
+    where X: MemoryExtension
+{
+    type Memory = Memory272k;
+    type MemoryExt = X;
+
+    #[inline(always)]
+    fn memory_ext_ref(&self) -> &Self::MemoryExt {
+        &self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_ext_mut(&mut self) -> &mut Self::MemoryExt {
+        &mut self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_mut(&mut self) -> &mut Self::Memory {
+        &mut self.ula.memory
+    }
+    #[inline(always)]
+    fn memory_ref(&self) -> &Self::Memory {
+        &self.ula.memory
+    }
+
+    fn memory_with_ext_mut(&mut self) -> (&mut Self::Memory, &mut Self::MemoryExt) {
+        (&mut self.ula.memory, &mut self.ula.memext)
+    }
+}
+
+impl<B, X> ControlUnit for Chloe<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<UlaVideoFrame>>,
+          X: MemoryExtension
+{
+    type BusDevice = B;
+
+    #[inline]
+    fn bus_device_mut(&mut self) -> &mut Self::BusDevice {
+        self.ula.bus_device_mut()
+    }
+    #[inline]
+    fn bus_device_ref(&self) -> &Self::BusDevice {
+        self.ula.bus_device_ref()
+    }
+    #[inline]
+    fn into_bus_device(self) -> Self::BusDevice {
+        self.ula.into_bus_device()
+    }
+
+    fn reset<C: Cpu>(&mut self, cpu: &mut C, hard: bool) {
+        self.ula.reset(cpu, hard);
+        if hard {
+            let ts = self.current_video_ts();
+            self.set_mem_port_value(Ula128MemFlags::empty(), ts);
+            self.set_mmu_flags_value(0);
+            self.set_ctrl_flags_value(ScldCtrlFlags::empty(), ts);
+        }
+    }
+
+    fn nmi<C: Cpu>(&mut self, cpu: &mut C) -> bool {
+        self.ula_nmi(cpu)
+    }
+
+    fn execute_next_frame<C: Cpu>(&mut self, cpu: &mut C) {
+        while !self.ula_execute_next_frame_with_breaks(cpu) {}
+    }
+
+    fn ensure_next_frame(&mut self) {
+        self.ensure_next_frame_vtsc();
+    }
+
+    fn execute_single_step<C: Cpu, F: FnOnce(CpuDebug)>(
+            &mut self,
+            cpu: &mut C,
+            debug: Option<F>
+        ) -> Result<(),()>
+    {
+        self.ula_execute_single_step(cpu, debug)
+    }
+}
+
+impl<B, X> UlaControlExt for Chloe<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<UlaVideoFrame>>,
+{
+    fn prepare_next_frame<C: MemoryContention>(
+            &mut self,
+            vtsc: VFrameTsCounter<UlaVideoFrame, C>
+        ) -> VFrameTsCounter<UlaVideoFrame, C>
+    {
+        self.beg_screen_shadow = self.cur_screen_shadow;
+        self.beg_ctrl_flags = self.cur_ctrl_flags;
+        self.sec_frame_cache.clear();
+        self.shadow_frame_cache.clear();
+        self.shadow_sec_frame_cache.clear();
+        self.screen_changes.clear();
+        self.mode_changes.clear();
+        self.source_changes.clear();
+        self.ula.prepare_next_frame(vtsc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::MemoryKind;
+    use crate::video::VideoFrame;
+    use super::*;
+
+    fn page_banks(ula: &Chloe) -> Vec<(MemoryKind, usize)> {
+        (0..8).map(|page| ula.memory_ref().page_bank(page).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_chloe() {
+        use MemoryKind::*;
+        assert_eq!(<Chloe as Video>::VideoFrame::FRAME_TSTATES_COUNT, 69888);
+        let mut ula: Chloe = Default::default();
+        let ts = VideoTs::default();
+        assert_eq!(page_banks(&ula), [(Rom, 0), (Rom, 1), (Ram, 10), (Ram, 11),
+                                      (Ram, 16), (Ram, 17), (Ram, 0), (Ram, 1)]);
+        assert_eq!(ula.write_io(0x7FFD, 0x1F, ts), (None, None));
+        assert_eq!(ula.ula128_mem_port_value().unwrap().bits(), 0x1F);
+        assert_eq!(ula.visible_screen_bank(), 1);
+        assert_eq!(page_banks(&ula), [(Rom, 2), (Rom, 3), (Ram, 10), (Ram, 11),
+                                      (Ram, 16), (Ram, 17), (Ram, 14), (Ram, 15)]);
+        assert_eq!(ula.write_io(0x00F4, 0b1100_0011, ts), (None, None));
+        assert_eq!(ula.read_io(0x00F4, ts), (0b1100_0011, None));
+        assert_eq!(page_banks(&ula), [(Ram, 18), (Ram, 19), (Ram, 10), (Ram, 11),
+                                      (Ram, 16), (Ram, 17), (Ram, 24), (Ram, 25)]);
+        assert_eq!(ula.write_io(0x00FF, 0x80, ts), (None, None));
+        assert_eq!(ula.read_io(0x00FF, ts), (0x80, None));
+        assert_eq!(page_banks(&ula), [(Ram, 26), (Ram, 27), (Ram, 10), (Ram, 11),
+                                      (Ram, 16), (Ram, 17), (Ram, 32), (Ram, 33)]);
+        assert_eq!(ula.write_io(0x7FFD, 0x00, ts), (None, None));
+        assert_eq!(page_banks(&ula), [(Ram, 26), (Ram, 27), (Ram, 10), (Ram, 11),
+                                      (Ram, 16), (Ram, 17), (Ram, 32), (Ram, 33)]);
+        assert_eq!(ula.write_io(0x00F4, 0b0011_1100, ts), (None, None));
+        assert_eq!(page_banks(&ula), [(Rom, 0), (Rom, 1), (Ram, 28), (Ram, 29),
+                                      (Ram, 30), (Ram, 31), (Ram, 0), (Ram, 1)]);
+        assert_eq!(ula.write_io(0x00FF, 0x00, ts), (None, None));
+        assert_eq!(page_banks(&ula), [(Rom, 0), (Rom, 1), (Ram, 20), (Ram, 21),
+                                      (Ram, 22), (Ram, 23), (Ram, 0), (Ram, 1)]);
+        assert_eq!(ula.write_io(0x00F4, 0, ts), (None, None));
+        assert_eq!(ula.write_io(0x7FFD, 0x27, ts), (None, None));
+        assert!(ula.mem_locked);
+        assert_eq!(ula.write_io(0x7FFD, 0x10, ts), (None, None));
+        assert_eq!(ula.ula128_mem_port_value().unwrap().bits(), 0x27);
+        assert_eq!(page_banks(&ula), [(Rom, 0), (Rom, 1), (Ram, 10), (Ram, 11),
+                                      (Ram, 16), (Ram, 17), (Ram, 14), (Ram, 15)]);
+        // the screen modes
+        for &flags in &[0x00u8, 0x01, 0x02, 0x06, 0x3E] {
+            assert_eq!(ula.write_io(0x00FF, flags, ts), (None, None));
+            assert_eq!(ula.scld_ctrl_port_value().unwrap().bits(), flags);
+        }
+        assert_eq!(ula.write_io(0x00FF, 0x40, ts), (None, None));
+        assert!(!ula.is_irq(VideoTs::new(0, 0)));
+        assert_eq!(ula.write_io(0x00FF, 0x00, ts), (None, None));
+        assert!(ula.is_irq(VideoTs::new(0, 0)));
+        // the memory contention
+        let clock = ula.current_video_clock();
+        for addr in (0x0000..=0xFFFF).step_by(0x100) {
+            assert_eq!(clock.is_contended_address(addr), addr & 0xC000 == 0x4000);
+        }
+    }
+}
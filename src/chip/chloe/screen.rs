@@ -0,0 +1,69 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use crate::chip::{ScldCtrlFlags, MemoryAccess};
+use crate::memory::{MemoryExtension, ZxMemory};
+use crate::formats::scr::*;
+use crate::video::Video;
+use super::Chloe;
+
+impl<B, X: MemoryExtension> ScreenDataProvider for Chloe<B, X> {
+    fn get_screen_mode(&self) -> ScrMode {
+        let flags = self.cur_ctrl_flags;
+        if flags.is_screen_hi_res() {
+            ScrMode::HighRes(flags.bits(), false)
+        }
+        else if flags.is_screen_hi_attrs() {
+            ScrMode::HighColor(false)
+        }
+        else {
+            ScrMode::Classic(false)
+        }
+    }
+
+    fn set_screen_mode(&mut self, mode: ScrMode) -> bool {
+        let mut flags = self.cur_ctrl_flags;
+        match mode {
+            ScrMode::Classic(false) =>  {
+                flags.remove(ScldCtrlFlags::SCREEN_MODE_MASK);
+            }
+            ScrMode::HighColor(false) => {
+                flags.remove(ScldCtrlFlags::SCREEN_MODE_MASK);
+                flags.insert(ScldCtrlFlags::SCREEN_HI_ATTRS);
+            }
+            ScrMode::HighRes(mode, false) => {
+                flags.remove(ScldCtrlFlags::SCREEN_MODE_MASK|ScldCtrlFlags::HIRES_COLOR_MASK);
+                flags.insert(ScldCtrlFlags::SCREEN_HI_ATTRS|ScldCtrlFlags::SCREEN_HI_RES|
+                             (ScldCtrlFlags::from(mode) & ScldCtrlFlags::HIRES_COLOR_MASK));
+            }
+            _ => return false
+        }
+        self.set_ctrl_flags_value(flags, self.ula.current_video_ts());
+        true
+    }
+
+    fn screen_primary_ref(&self) -> &ScreenArray {
+        let secondary = if self.cur_ctrl_flags.is_screen_secondary() { 2 } else { 0 };
+        let screen_bank = self.visible_screen_bank() + secondary;
+        self.memory_ref().screen_ref(screen_bank).unwrap()
+    }
+
+    fn screen_primary_mut(&mut self) -> &mut ScreenArray {
+        let screen_bank = self.visible_screen_bank();
+        self.memory_mut().screen_mut(screen_bank).unwrap()
+    }
+
+    fn screen_secondary_ref(&self) -> &ScreenArray {
+        let screen_bank = self.visible_screen_bank() + 2;
+        self.memory_ref().screen_ref(screen_bank).unwrap()
+    }
+
+    fn screen_secondary_mut(&mut self) -> &mut ScreenArray {
+        let screen_bank = self.visible_screen_bank() + 2;
+        self.memory_mut().screen_mut(screen_bank).unwrap()
+    }
+}
@@ -0,0 +1,130 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use std::vec::Drain;
+
+use crate::memory::{ZxMemory, MemoryKind};
+use crate::clock::{VideoTs, VideoTsData2, VideoTsData6, VFrameTsCounter};
+use crate::video::{
+    RendererPlus, UlaPlusPalette, PaletteChange, BorderSize, BorderColor, PixelBuffer, Palette,
+    Video,
+    frame_cache::{
+        pixel_address_coords, color_address_coords
+    }
+};
+use crate::chip::{
+    ula::{UlaVideoFrame, UlaMemoryContention},
+    plus::frame_cache::PlusFrameProducer,
+    scld::frame_cache::SourceMode
+};
+use super::Chloe;
+
+impl<D, X> Video for Chloe<D, X> {
+    const PIXEL_DENSITY: u32 = 2;
+    type VideoFrame = UlaVideoFrame;
+    type Contention = UlaMemoryContention;
+
+    #[inline]
+    fn border_color(&self) -> BorderColor {
+        self.ula.border_color()
+    }
+
+    fn set_border_color(&mut self, border: BorderColor) {
+        self.change_border_color(border, self.ula.current_video_ts())
+    }
+
+    fn render_video_frame<'a, B: PixelBuffer<'a>, P: Palette<Pixel=B::Pixel>>(
+            &mut self,
+            buffer: &'a mut [u8],
+            pitch: usize,
+            border_size: BorderSize
+        )
+    {
+        let mut palette = UlaPlusPalette::default();
+        self.create_renderer(border_size, &mut palette)
+            .render_pixels::<B, P, UlaVideoFrame>(buffer, pitch)
+    }
+
+    fn visible_screen_bank(&self) -> usize {
+        self.cur_screen_shadow.into()
+    }
+
+    fn current_video_ts(&self) -> VideoTs {
+        self.ula.current_video_ts()
+    }
+
+    fn current_video_clock(&self) -> VFrameTsCounter<Self::VideoFrame, Self::Contention> {
+        self.ula.current_video_clock()
+    }
+
+    fn set_video_ts(&mut self, vts: VideoTs) {
+        self.ula.set_video_ts(vts);
+    }
+
+    fn flash_state(&self) -> bool {
+        self.ula.flash_state()
+    }
+}
+
+impl<D, X> Chloe<D, X> {
+    #[inline]
+    pub(super) fn update_frame_cache(&mut self, addr: u16, ts: VideoTs) {
+        if addr & 0x1FFF > 0x1AFF {
+            return
+        }
+        let frame_cache = match self.ula.memory.page_bank((addr >> 13) as u8) {
+            Ok((MemoryKind::Ram, 10)) => &mut self.ula.frame_cache,
+            Ok((MemoryKind::Ram, 11)) => &mut self.sec_frame_cache,
+            Ok((MemoryKind::Ram, 14)) => &mut self.shadow_frame_cache,
+            Ok((MemoryKind::Ram, 15)) => &mut self.shadow_sec_frame_cache,
+            _ => return
+        };
+
+        if addr & 0x1800 != 0x1800 {
+            let coords = pixel_address_coords(addr);
+            frame_cache.update_frame_pixels(&self.ula.memory, coords, addr, ts);
+        }
+        else {
+            let coords = color_address_coords(addr);
+            frame_cache.update_frame_colors(&self.ula.memory, coords, addr, ts);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn create_renderer<'a, 'r>(
+            &'a mut self,
+            border_size: BorderSize,
+            palette: &'r mut UlaPlusPalette
+        ) -> RendererPlus<'r,
+                PlusFrameProducer<'a, UlaVideoFrame, Drain<'a, VideoTsData2>, Drain<'a, VideoTs>>,
+                Drain<'a, VideoTsData6>,
+                core::iter::Empty<PaletteChange>>
+    {
+        let render_mode = self.beg_render_mode();
+        let invert_flash = self.flash_state();
+        let memory = &self.ula.memory;
+        let frame_image_producer = PlusFrameProducer::new(
+            self.beg_screen_shadow,
+            SourceMode::from_scld_flags(self.beg_ctrl_flags),
+            memory.screen_ref(0).unwrap(), &self.ula.frame_cache,
+            memory.screen_ref(2).unwrap(), &self.sec_frame_cache,
+            memory.screen_ref(1).unwrap(), &self.shadow_frame_cache,
+            memory.screen_ref(3).unwrap(), &self.shadow_sec_frame_cache,
+            self.screen_changes.drain(..),
+            self.source_changes.drain(..));
+
+        RendererPlus {
+            render_mode,
+            palette,
+            frame_image_producer,
+            mode_changes: self.mode_changes.drain(..),
+            palette_changes: core::iter::empty(),
+            border_size,
+            invert_flash
+        }
+    }
+}
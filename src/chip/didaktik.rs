@@ -0,0 +1,310 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+/*! Emulators of the Slovak Didaktik series of ZX Spectrum 48k clones.
+
+The Didaktik Gama model has 80kb of RAM: 48kb as the original 48k model and an additional 32kb bank
+which can be switched with the upper 32kb of RAM. Both Gama and Kompakt models have a built-in
+Intel 8255 programmable parallel I/O interface, see [Pio8255][crate::peripherals::pio8255::Pio8255].
+The Kompakt model also has a built-in MDOS floppy disk interface.
+
+Implementation specifics:
+
+* The video frame and the memory contention are the same as of the original 48k model.
+* The 8255 PIO ports `0x1F`, `0x3F`, `0x5F` and `0x7F` are decoded on address bits: `A0` to `A4`
+  and `A7`, see [DidaktikPioPortAddress].
+* Reading the port **A** of the 8255 PIO, configured as input, is also forwarded to the bus device
+  and the data is combined with the bitwise AND, so the Kempston joystick can be attached as a bus device.
+* The Gama's 80kb RAM is organized in 5 16k banks:
+
+```text
+0x4000-0x7FFF: RAM 0
+0x8000-0xFFFF: RAM 1 and RAM 2 (32k bank 0) or RAM 3 and RAM 4 (32k bank 1)
+```
+
+* The Gama's 32k bank 1 is selected when the bit 2 of the 8255 PIO port **C** is configured as output
+  and is set to 1, otherwise the 32k bank 0 is selected.
+* The hard reset resets the 8255 PIO, selecting the 32k bank 0.
+
+The Didaktik Kompakt is defined as the [DidaktikKompakt] type alias of the [Ula] chipset with
+the [MdosBusDevice] and the [Pio8255BusDevice] bus devices and the [MdosMemExt] memory extension.
+The paging flag of the [MdosMemExt] must be shared with the [MdosBusDevice] with
+[MdosBusDevice::set_paged_in_flag] after the chipset is created.
+*/
+mod audio_earmic;
+mod io;
+mod video;
+#[cfg(feature = "formats")]
+mod screen;
+
+use core::fmt;
+
+use crate::z80emu::{*, host::Result};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::bus::{BusDevice, VFNullDevice};
+use crate::clock::{VFrameTs, VFrameTsCounter, MemoryContention};
+use crate::chip::{
+    InnerAccess, ControlUnit, MemoryAccess, UlaControl,
+    ula::{Ula, UlaVideoFrame, UlaControlExt, UlaCpuExt}
+};
+use crate::memory::{ZxMemory, MemoryExtension, NoMemoryExtension, Memory48kEx, Memory80k};
+use crate::peripherals::pio8255::{Pio8255, PioPort};
+
+pub use crate::peripherals::bus::pio8255::{Pio8255BusDevice, DidaktikPioPortAddress};
+pub use crate::peripherals::bus::mdos::{MdosBusDevice, MdosMemExt};
+
+/// The bit of the 8255 PIO port **C** selecting the Gama's 32k bank at `0x8000`.
+pub const GAMA_BANK_SELECT: u8 = 0b0000_0100;
+
+/// The Didaktik Kompakt chipset with the built-in 8255 PIO and the MDOS floppy disk interface.
+///
+/// `D` is the next bus device, see [Ula] for the description of the chipset.
+pub type DidaktikKompakt<D=VFNullDevice<UlaVideoFrame>> =
+    Ula<Memory48kEx, MdosBusDevice<Pio8255BusDevice<D>>, MdosMemExt, UlaVideoFrame>;
+
+type InnerUla<B, X> = Ula<Memory80k, B, X, UlaVideoFrame>;
+
+/// Didaktik Gama chipset.
+///
+/// See [Ula] for description of generic parameters.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct DidaktikGama<B=VFNullDevice<UlaVideoFrame>, X=NoMemoryExtension> {
+    ula: InnerUla<B, X>,
+    pio: Pio8255,
+    mem_bank: bool // the 32k bank 1 is paged in
+}
+
+impl<B, X> fmt::Debug for DidaktikGama<B, X>
+    where B: BusDevice, X: MemoryExtension
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DidaktikGama")
+            .field("ula", &self.ula)
+            .field("pio", &self.pio)
+            .field("mem_bank", &self.mem_bank)
+            .finish()
+    }
+}
+
+impl<B, X> InnerAccess for DidaktikGama<B, X> {
+    type Inner = InnerUla<B, X>;
+
+    fn inner_ref(&self) -> &Self::Inner {
+        &self.ula
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Inner {
+        &mut self.ula
+    }
+
+    fn into_inner(self) -> Self::Inner {
+        self.ula
+    }
+}
+
+impl<B, X> UlaControl for DidaktikGama<B, X> {
+    fn has_late_timings(&self) -> bool {
+        self.ula.has_late_timings()
+    }
+
+    fn set_late_timings(&mut self, late_timings: bool) {
+        self.ula.set_late_timings(late_timings)
+    }
+}
+
+impl<B, X> DidaktikGama<B, X> {
+    /// Returns a reference to the built-in 8255 PIO.
+    pub fn pio_ref(&self) -> &Pio8255 {
+        &self.pio
+    }
+    /// Returns a mutable reference to the built-in 8255 PIO.
+    ///
+    /// # Note
+    /// Changes to the PIO output are reflected in the memory paging on the next PIO write by the CPU.
+    pub fn pio_mut(&mut self) -> &mut Pio8255 {
+        &mut self.pio
+    }
+    /// Returns the index of the currently paged in 32k bank: 0 or 1.
+    pub fn mem_bank(&self) -> usize {
+        self.mem_bank.into()
+    }
+
+    fn update_mem_bank(&mut self) {
+        let port_c = self.pio.output(PioPort::C) & !self.pio.input_mask(PioPort::C);
+        let mem_bank = port_c & GAMA_BANK_SELECT != 0;
+        if self.mem_bank != mem_bank {
+            self.mem_bank = mem_bank;
+            let bank = if mem_bank { 3 } else { 1 };
+            let memory = &mut self.ula.memory;
+            memory.map_ram_bank(bank, 2).unwrap();
+            memory.map_ram_bank(bank + 1, 3).unwrap();
+        }
+    }
+}
+
+impl<B, X> MemoryAccess for DidaktikGama<B, X>
+    where X: MemoryExtension
+{
+    type Memory = Memory80k;
+    type MemoryExt = X;
+
+    #[inline(always)]
+    fn memory_ext_ref(&self) -> &Self::MemoryExt {
+        &self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_ext_mut(&mut self) -> &mut Self::MemoryExt {
+        &mut self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_mut(&mut self) -> &mut Self::Memory {
+        &mut self.ula.memory
+    }
+    #[inline(always)]
+    fn memory_ref(&self) -> &Self::Memory {
+        &self.ula.memory
+    }
+
+    fn memory_with_ext_mut(&mut self) -> (&mut Self::Memory, &mut Self::MemoryExt) {
+        (&mut self.ula.memory, &mut self.ula.memext)
+    }
+}
+
+impl<B, X> ControlUnit for DidaktikGama<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<UlaVideoFrame>>,
+          X: MemoryExtension
+{
+    type BusDevice = B;
+
+    #[inline]
+    fn bus_device_mut(&mut self) -> &mut Self::BusDevice {
+        self.ula.bus_device_mut()
+    }
+    #[inline]
+    fn bus_device_ref(&self) -> &Self::BusDevice {
+        self.ula.bus_device_ref()
+    }
+    #[inline]
+    fn into_bus_device(self) -> Self::BusDevice {
+        self.ula.into_bus_device()
+    }
+
+    fn reset<C: Cpu>(&mut self, cpu: &mut C, hard: bool) {
+        self.ula.reset(cpu, hard);
+        if hard {
+            self.pio.reset();
+            self.mem_bank = false;
+        }
+    }
+
+    fn nmi<C: Cpu>(&mut self, cpu: &mut C) -> bool {
+        self.ula_nmi(cpu)
+    }
+
+    fn execute_next_frame<C: Cpu>(&mut self, cpu: &mut C) {
+        while !self.ula_execute_next_frame_with_breaks(cpu) {}
+    }
+
+    fn ensure_next_frame(&mut self) {
+        self.ensure_next_frame_vtsc();
+    }
+
+    fn execute_single_step<C: Cpu, F: FnOnce(CpuDebug)>(
+            &mut self,
+            cpu: &mut C,
+            debug: Option<F>
+        ) -> Result<(),()>
+    {
+        self.ula_execute_single_step(cpu, debug)
+    }
+}
+
+impl<B, X> UlaControlExt for DidaktikGama<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<UlaVideoFrame>>
+{
+    fn prepare_next_frame<C: MemoryContention>(
+            &mut self,
+            vtsc: VFrameTsCounter<UlaVideoFrame, C>
+        ) -> VFrameTsCounter<UlaVideoFrame, C>
+    {
+        self.ula.prepare_next_frame(vtsc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::VideoTs;
+    use crate::memory::MemoryKind;
+    use crate::peripherals::bus::joystick::{KempstonJoystick, JoystickInterface};
+    use crate::video::{Video, VideoFrame};
+    use super::*;
+
+    #[test]
+    fn test_didaktik_gama() {
+        assert_eq!(<DidaktikGama as Video>::VideoFrame::FRAME_TSTATES_COUNT, 69888);
+        let mut ula = DidaktikGama::<KempstonJoystick<VFNullDevice<UlaVideoFrame>>>::default();
+        let ts = VideoTs::default();
+        assert_eq!(ula.mem_bank(), 0);
+        assert_eq!(ula.memory_ref().page_bank(2).unwrap(), (MemoryKind::Ram, 1));
+        assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, 2));
+        ula.memory_mut().write(0x8000, 1);
+        assert_eq!(ula.write_io(0x005F, GAMA_BANK_SELECT, ts), (None, None));
+        assert_eq!(ula.mem_bank(), 0);
+        assert_eq!(ula.write_io(0x007F, 0b1001_0000, ts), (None, None));
+        assert_eq!(ula.write_io(0x005F, GAMA_BANK_SELECT, ts), (None, None));
+        assert_eq!(ula.read_io(0x005F, ts), (GAMA_BANK_SELECT, None));
+        assert_eq!(ula.mem_bank(), 1);
+        assert_eq!(ula.memory_ref().page_bank(2).unwrap(), (MemoryKind::Ram, 3));
+        assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, 4));
+        assert_ne!(ula.memory_ref().read(0x8000), 1);
+        ula.memory_mut().write(0x8000, 2);
+        assert_eq!(ula.write_io(0x007F, 0b0000_0100, ts), (None, None));
+        assert_eq!(ula.mem_bank(), 0);
+        assert_eq!(ula.memory_ref().read(0x8000), 1);
+        assert_eq!(ula.write_io(0x007F, 0b0000_0101, ts), (None, None));
+        assert_eq!(ula.mem_bank(), 1);
+        assert_eq!(ula.memory_ref().read(0x8000), 2);
+        assert_eq!(ula.write_io(0x007F, 0b1001_1011, ts), (None, None));
+        assert_eq!(ula.mem_bank(), 0);
+        assert_eq!(ula.pio_ref().control(), 0b1001_1011);
+
+        assert_eq!(ula.read_io(0x001F, ts), (0, None));
+        ula.bus_device_mut().fire(0, true);
+        assert_eq!(ula.read_io(0x001F, ts), (0b0001_0000, None));
+        ula.pio_mut().set_input(PioPort::B, 0x5A);
+        assert_eq!(ula.read_io(0x003F, ts), (0x5A, None));
+
+        let clock = ula.current_video_clock();
+        for addr in (0x0000..=0xFFFF).step_by(0x100) {
+            assert_eq!(clock.is_contended_address(addr), (0x4000..0x8000).contains(&addr));
+        }
+    }
+
+    #[test]
+    fn test_didaktik_kompakt() {
+        let mut ula: DidaktikKompakt = Default::default();
+        let flag = ula.memory_ext_ref().paged_in_flag().clone();
+        ula.bus_device_mut().set_paged_in_flag(flag);
+        assert!(!ula.memory_ext_ref().paged_in_flag().get());
+        let ts = VideoTs::default();
+        assert_eq!(ula.read_io(0x0091, ts).0, 0xFF);
+        assert!(ula.memory_ext_ref().paged_in_flag().get());
+        assert_eq!(ula.write_io(0x0091, 0, ts), (None, None));
+        assert!(!ula.memory_ext_ref().paged_in_flag().get());
+        assert_eq!(ula.write_io(0x003F, 0xA5, ts), (None, None));
+        assert_eq!(ula.bus_device_ref().next_device_ref().output(PioPort::B), 0xFF);
+        assert_eq!(ula.write_io(0x007F, 0b1000_0000, ts), (None, None));
+        assert_eq!(ula.write_io(0x003F, 0xA5, ts), (None, None));
+        assert_eq!(ula.bus_device_ref().next_device_ref().output(PioPort::B), 0xA5);
+    }
+}
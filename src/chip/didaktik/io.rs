@@ -0,0 +1,102 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::num::NonZeroU16;
+
+use crate::z80emu::{Io, Memory};
+use crate::bus::{BusDevice, PortAddress};
+use crate::clock::{VideoTs, VFrameTs};
+use crate::chip::ula::UlaVideoFrame;
+use crate::peripherals::{KeyboardInterface, ZXKeyboardMap};
+use crate::peripherals::pio8255::PioPort;
+use crate::memory::MemoryExtension;
+use super::{DidaktikGama, DidaktikPioPortAddress};
+
+impl<B, X> Io for DidaktikGama<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<UlaVideoFrame>>
+{
+    type Timestamp = VideoTs;
+    type WrIoBreak = ();
+    type RetiBreak = ();
+
+    #[inline(always)]
+    fn is_irq(&mut self, ts: VideoTs) -> bool {
+        self.ula.is_irq(ts)
+    }
+
+    fn read_io(&mut self, port: u16, ts: VideoTs) -> (u8, Option<NonZeroU16>) {
+        if DidaktikPioPortAddress::match_port(port) {
+            let reg = DidaktikPioPortAddress::register(port);
+            let data = self.pio.read(reg);
+            let mask = self.pio.input_mask(PioPort::A);
+            if reg == 0 && mask != 0 {
+                if let Some((bus_data, ws)) = self.ula.bus.read_io(port, VFrameTs::from(ts).into()) {
+                    return (data & (bus_data | !mask), ws)
+                }
+            }
+            (data, None)
+        }
+        else {
+            self.ula.read_io(port, ts)
+        }
+    }
+
+    fn write_io(&mut self, port: u16, data: u8, ts: VideoTs) -> (Option<()>, Option<NonZeroU16>) {
+        if DidaktikPioPortAddress::match_port(port) {
+            // memory contention is not affected by paging, so no need to break the execution
+            self.pio.write(DidaktikPioPortAddress::register(port), data);
+            self.update_mem_bank();
+            (None, None)
+        }
+        else {
+            self.ula.write_io(port, data, ts)
+        }
+    }
+}
+
+impl<B, X> Memory for DidaktikGama<B, X>
+    where X: MemoryExtension
+{
+    type Timestamp = VideoTs;
+
+    #[inline(always)]
+    fn read_debug(&self, addr: u16) -> u8 {
+        self.ula.read_debug(addr)
+    }
+
+    #[inline(always)]
+    fn read_mem(&self, addr: u16, ts: VideoTs) -> u8 {
+        self.ula.read_mem(addr, ts)
+    }
+
+    #[inline(always)]
+    fn read_mem16(&self, addr: u16, ts: VideoTs) -> u16 {
+        self.ula.read_mem16(addr, ts)
+    }
+
+    #[inline(always)]
+    fn read_opcode(&mut self, pc: u16, ir: u16, ts: VideoTs) -> u8 {
+        self.ula.read_opcode(pc, ir, ts)
+    }
+
+    #[inline(always)]
+    fn write_mem(&mut self, addr: u16, val: u8, ts: VideoTs) {
+        self.ula.write_mem(addr, val, ts)
+    }
+}
+
+impl<B, X> KeyboardInterface for DidaktikGama<B, X> {
+    #[inline(always)]
+    fn get_key_state(&self) -> ZXKeyboardMap {
+        self.ula.get_key_state()
+    }
+    #[inline(always)]
+    fn set_key_state(&mut self, keymap: ZXKeyboardMap)  {
+        self.ula.set_key_state(keymap);
+    }
+}
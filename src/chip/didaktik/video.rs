@@ -0,0 +1,51 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use crate::clock::{VideoTs, VFrameTsCounter};
+use crate::chip::ula::{UlaVideoFrame, UlaMemoryContention};
+use crate::video::{BorderSize, BorderColor, PixelBuffer, Palette, Video};
+use super::DidaktikGama;
+
+impl<B, X> Video for DidaktikGama<B, X> {
+    type VideoFrame = UlaVideoFrame;
+    type Contention = UlaMemoryContention;
+
+    #[inline]
+    fn border_color(&self) -> BorderColor {
+        self.ula.border_color()
+    }
+
+    fn set_border_color(&mut self, border: BorderColor) {
+        self.ula.set_border_color(border)
+    }
+
+    fn render_video_frame<'a, P: PixelBuffer<'a>, L: Palette<Pixel=P::Pixel>>(
+            &mut self,
+            buffer: &'a mut [u8],
+            pitch: usize,
+            border_size: BorderSize
+        )
+    {
+        self.ula.render_video_frame::<P, L>(buffer, pitch, border_size)
+    }
+
+    fn current_video_ts(&self) -> VideoTs {
+        self.ula.current_video_ts()
+    }
+
+    fn current_video_clock(&self) -> VFrameTsCounter<Self::VideoFrame, Self::Contention> {
+        self.ula.current_video_clock()
+    }
+
+    fn set_video_ts(&mut self, vts: VideoTs) {
+        self.ula.set_video_ts(vts);
+    }
+
+    fn flash_state(&self) -> bool {
+        self.ula.flash_state()
+    }
+}
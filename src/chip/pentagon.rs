@@ -0,0 +1,392 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An emulator of the Pentagon 128k/512k/1024k chipset.
+//!
+//! Pentagon is a family of Russian ZX Spectrum 128k clones. The chipset has no memory or I/O contention,
+//! no floating bus and its video frame consists of 320 scan lines, 224 T-states each.
+//!
+//! The memory is being paged via the `0x7FFD` port, in the same way as on the original 128k models.
+//! Models with more than 128kb of RAM use additional port bits to select the RAM bank being paged in
+//! at `0xC000`:
+//!
+//! * Pentagon 512: bits 6 and 7 are being used as the RAM bank bits 3 and 4.
+//! * Pentagon 1024: additionally bit 5 is being used as the RAM bank bit 5, thus the memory paging can't be
+//!   locked on this model.
+mod audio_earmic;
+mod io;
+mod video;
+#[cfg(feature = "formats")]
+mod screen;
+
+use core::fmt;
+
+use crate::z80emu::{*, host::Result};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::bus::{BusDevice, VFNullDevice};
+use crate::clock::{VFrameTs, VideoTs, VFrameTsCounter, MemoryContention};
+use crate::chip::{
+    InnerAccess, ControlUnit, MemoryAccess, Ula128MemFlags, UlaControl,
+    ula::{
+        Ula, UlaControlExt, UlaCpuExt,
+        frame_cache::UlaFrameCache
+    }
+};
+use crate::memory::{
+    ZxMemory, PagedMemory16k, MemoryExtension, NoMemoryExtension, MemoryKind,
+    Memory128k, Memory512k, Memory1024k
+};
+use crate::video::Video;
+pub use video::PentagonVidFrame;
+
+/// Pentagon 128k chipset.
+pub type Pentagon128<B=VFNullDevice<PentagonVidFrame>, X=NoMemoryExtension> = Pentagon<Memory128k, B, X>;
+/// Pentagon 512k chipset.
+pub type Pentagon512<B=VFNullDevice<PentagonVidFrame>, X=NoMemoryExtension> = Pentagon<Memory512k, B, X>;
+/// Pentagon 1024k chipset.
+pub type Pentagon1024<B=VFNullDevice<PentagonVidFrame>, X=NoMemoryExtension> = Pentagon<Memory1024k, B, X>;
+
+/// A struct implementing [MemoryContention] with no contended addresses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PentagonMemContention;
+
+type InnerUla<M, B, X> = Ula<M, B, X, PentagonVidFrame>;
+
+/// Pentagon chipset.
+///
+/// * `M` - [ZxMemory] with 16k pages and the RAM size determining the Pentagon model,
+///   see [Pentagon128], [Pentagon512] and [Pentagon1024].
+///
+/// See [Ula] for description of other generic parameters.
+#[derive(Clone)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Pentagon<M=Memory128k, B=VFNullDevice<PentagonVidFrame>, X=NoMemoryExtension> {
+    ula: InnerUla<M, B, X>,
+    mem_page3_bank: u8,
+    beg_screen_shadow: bool, // shadow screen when a frame began
+    cur_screen_shadow: bool, // current shadow screen
+    mem_locked: bool,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    shadow_frame_cache: UlaFrameCache<PentagonVidFrame>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    screen_changes: Vec<VideoTs>,
+}
+
+impl MemoryContention for PentagonMemContention {
+    #[inline(always)]
+    fn is_contended_address(self, _address: u16) -> bool {
+        false
+    }
+}
+
+impl<M: Default, B: Default, X: Default> Default for Pentagon<M, B, X> {
+    fn default() -> Self {
+        Pentagon {
+            ula: Default::default(),
+            mem_page3_bank: 0,
+            beg_screen_shadow: false,
+            cur_screen_shadow: false,
+            mem_locked: false,
+            shadow_frame_cache: Default::default(),
+            screen_changes: Vec::new()
+        }
+    }
+}
+
+impl<M, B, X> fmt::Debug for Pentagon<M, B, X>
+    where M: ZxMemory, B: BusDevice, X: MemoryExtension
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pentagon")
+            .field("ula", &self.ula)
+            .field("mem_page3_bank", &self.mem_page3_bank)
+            .field("beg_screen_shadow", &self.beg_screen_shadow)
+            .field("cur_screen_shadow", &self.cur_screen_shadow)
+            .field("mem_locked", &self.mem_locked)
+            .field("shadow_frame_cache", &self.shadow_frame_cache)
+            .field("screen_changes", &self.screen_changes.len())
+            .finish()
+    }
+}
+
+impl<M, B, X> InnerAccess for Pentagon<M, B, X> {
+    type Inner = InnerUla<M, B, X>;
+
+    fn inner_ref(&self) -> &Self::Inner {
+        &self.ula
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Inner {
+        &mut self.ula
+    }
+
+    fn into_inner(self) -> Self::Inner {
+        self.ula
+    }
+}
+
+impl<M: PagedMemory16k, B, X> UlaControl for Pentagon<M, B, X> {
+    fn has_late_timings(&self) -> bool {
+        self.ula.has_late_timings()
+    }
+
+    fn set_late_timings(&mut self, late_timings: bool) {
+        self.ula.set_late_timings(late_timings)
+    }
+
+    fn ula128_mem_port_value(&self) -> Option<Ula128MemFlags> {
+        Some(Ula128MemFlags::from_bits_truncate(self.mem_port_value()))
+    }
+
+    fn set_ula128_mem_port_value(&mut self, value: Ula128MemFlags) -> bool {
+        self.set_mem_port_value(value.bits(), self.ula.current_video_ts());
+        true
+    }
+}
+
+impl<M: PagedMemory16k, B, X> Pentagon<M, B, X> {
+    /// Returns the last value sent to the memory port `0x7FFD`, including the extended RAM bank bits.
+    pub fn mem_port_value(&self) -> u8 {
+        let bank = self.mem_page3_bank;
+        let mut data = bank & 7 | (bank & 0x18) << 3;
+        if Self::has_bank_bit5() {
+            data |= bank & 0x20;
+        }
+        else if self.mem_locked {
+            data |= Ula128MemFlags::LOCK_MMU.bits();
+        }
+        if self.cur_screen_shadow {
+            data |= Ula128MemFlags::SCREEN_BANK.bits();
+        }
+        if let Ok((MemoryKind::Rom, rom_bank)) = self.ula.memory.page_bank(0) {
+            if rom_bank != 0 {
+                data |= Ula128MemFlags::ROM_BANK.bits();
+            }
+        }
+        data
+    }
+
+    /// Sets the current value of the memory port `0x7FFD`, including the extended RAM bank bits.
+    ///
+    /// The bits not supported by the RAM size of the model are being ignored.
+    pub fn set_mem_port_value(&mut self, data: u8, ts: VideoTs) {
+        let flags = Ula128MemFlags::from_bits_truncate(data);
+        self.mem_locked = !Self::has_bank_bit5() && flags.is_mmu_locked();
+        let cur_screen_shadow = flags.is_shadow_screen();
+        if self.cur_screen_shadow != cur_screen_shadow {
+            self.cur_screen_shadow = cur_screen_shadow;
+            self.screen_changes.push(ts);
+        }
+        let rom_bank = flags.rom_page_bank();
+        self.ula.memory.map_rom_bank(rom_bank, 0).unwrap();
+        let bank = Self::ram_bank_from_port(data);
+        if self.mem_page3_bank != bank {
+            self.mem_page3_bank = bank;
+            self.ula.memory.map_ram_bank(bank.into(), 3).unwrap();
+        }
+    }
+
+    /// Returns `true` if the bit 5 of the memory port is a RAM bank bit instead of the paging lock.
+    #[inline(always)]
+    fn has_bank_bit5() -> bool {
+        M::RAM_BANKS_MAX >= 0x20
+    }
+
+    #[inline]
+    fn ram_bank_from_port(data: u8) -> u8 {
+        let mut bank = data & 7 | (data & 0xC0) >> 3;
+        if Self::has_bank_bit5() {
+            bank |= data & 0x20;
+        }
+        bank & M::RAM_BANKS_MAX as u8
+    }
+}
+
+impl<M, B, X> Pentagon<M, B, X> {
+    #[inline(always)]
+    fn page3_screen_shadow_bank(&self) -> Option<bool> {
+        match self.mem_page3_bank {
+            5 => Some(false),
+            7 => Some(true),
+            _ => None
+        }
+    }
+}
+
+impl<M, B, X> MemoryAccess for Pentagon<M, B, X>
+    where M: ZxMemory, X: MemoryExtension
+{
+    type Memory = M;
+    type MemoryExt = X;
+
+    #[inline(always)]
+    fn memory_ext_ref(&self) -> &Self::MemoryExt {
+        &self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_ext_mut(&mut self) -> &mut Self::MemoryExt {
+        &mut self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_mut(&mut self) -> &mut Self::Memory {
+        &mut self.ula.memory
+    }
+    #[inline(always)]
+    fn memory_ref(&self) -> &Self::Memory {
+        &self.ula.memory
+    }
+
+    fn memory_with_ext_mut(&mut self) -> (&mut Self::Memory, &mut Self::MemoryExt) {
+        (&mut self.ula.memory, &mut self.ula.memext)
+    }
+}
+
+impl<M, B, X> ControlUnit for Pentagon<M, B, X>
+    where M: PagedMemory16k,
+          B: BusDevice,
+          B::Timestamp: From<VFrameTs<PentagonVidFrame>>,
+          X: MemoryExtension
+{
+    type BusDevice = B;
+
+    #[inline]
+    fn bus_device_mut(&mut self) -> &mut Self::BusDevice {
+        self.ula.bus_device_mut()
+    }
+    #[inline]
+    fn bus_device_ref(&self) -> &Self::BusDevice {
+        self.ula.bus_device_ref()
+    }
+    #[inline]
+    fn into_bus_device(self) -> Self::BusDevice {
+        self.ula.into_bus_device()
+    }
+
+    fn reset<C: Cpu>(&mut self, cpu: &mut C, hard: bool) {
+        self.ula.reset(cpu, hard);
+        if hard {
+            self.mem_page3_bank = 0;
+            if self.cur_screen_shadow {
+                self.screen_changes.push(self.current_video_ts());
+            }
+            self.cur_screen_shadow = false;
+            self.mem_locked = false;
+        }
+    }
+
+    fn nmi<C: Cpu>(&mut self, cpu: &mut C) -> bool {
+        self.ula_nmi(cpu)
+    }
+
+    fn execute_next_frame<C: Cpu>(&mut self, cpu: &mut C) {
+        while !self.ula_execute_next_frame_with_breaks(cpu) {}
+    }
+
+    fn ensure_next_frame(&mut self) {
+        self.ensure_next_frame_vtsc();
+    }
+
+    fn execute_single_step<C: Cpu, F: FnOnce(CpuDebug)>(
+            &mut self,
+            cpu: &mut C,
+            debug: Option<F>
+        ) -> Result<(),()>
+    {
+        self.ula_execute_single_step(cpu, debug)
+    }
+}
+
+impl<M, B, X> UlaControlExt for Pentagon<M, B, X>
+    where M: ZxMemory,
+          B: BusDevice,
+          B::Timestamp: From<VFrameTs<PentagonVidFrame>>,
+{
+    fn prepare_next_frame<C: MemoryContention>(
+            &mut self,
+            vtsc: VFrameTsCounter<PentagonVidFrame, C>
+        ) -> VFrameTsCounter<PentagonVidFrame, C>
+    {
+        self.beg_screen_shadow = self.cur_screen_shadow;
+        self.shadow_frame_cache.clear();
+        self.screen_changes.clear();
+        self.ula.prepare_next_frame(vtsc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip::FrameState;
+    use crate::video::{Video, VideoFrame};
+    use super::*;
+
+    #[test]
+    fn test_pentagon() {
+        assert_eq!(<Pentagon128 as Video>::VideoFrame::FRAME_TSTATES_COUNT, 71680);
+        let mut ula: Pentagon128 = Default::default();
+        for data in 0..=255u8 {
+            ula.mem_locked = false;
+            ula.set_mem_port_value(data, VideoTs::default());
+            let bank = data as usize & 7;
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank));
+            assert_eq!(ula.mem_port_value(), data & 0x3F);
+            assert_eq!(ula.mem_locked, data & 0x20 != 0);
+            let clock = ula.current_video_clock();
+            for addr in 0x0000..=0xFFFF {
+                assert!(!clock.is_contended_address(addr));
+            }
+        }
+        assert_eq!(ula.write_io(0x7FFD, 0, VideoTs::default()), (None, None));
+        assert_eq!(ula.mem_port_value(), 0x3F);
+        assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, 7));
+
+        let mut ula: Pentagon512 = Default::default();
+        for data in 0..=255u8 {
+            ula.mem_locked = false;
+            ula.set_mem_port_value(data, VideoTs::default());
+            let bank = data as usize & 7 | (data as usize & 0xC0) >> 3;
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank));
+            assert_eq!(ula.mem_port_value(), data);
+        }
+
+        let mut ula: Pentagon1024 = Default::default();
+        for data in 0..=255u8 {
+            ula.set_mem_port_value(data, VideoTs::default());
+            let bank = data as usize & 0x27 | (data as usize & 0xC0) >> 3;
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank));
+            assert_eq!(ula.mem_port_value(), data);
+            assert!(!ula.mem_locked);
+        }
+        let flags = ula.ula128_mem_port_value().unwrap();
+        assert_eq!(flags, Ula128MemFlags::all());
+    }
+
+    #[test]
+    fn test_pentagon_interrupt() {
+        let mut ula: Pentagon128 = Default::default();
+        let rom = ula.memory_mut().page_mut(0).unwrap();
+        // EI; HALT; JR -3
+        rom[0..4].copy_from_slice(&[0xFB, 0x76, 0x18, 0xFD]);
+        // INC (HL); EI; RET
+        rom[0x38..0x3B].copy_from_slice(&[0x34, 0xFB, 0xC9]);
+        ula.memory_mut().write(0x8000, 0);
+        let mut cpu = Z80NMOS::default();
+        cpu.reset();
+        cpu.set_im(InterruptMode::Mode1);
+        cpu.set_sp(0xFFFE);
+        cpu.set_reg16(StkReg16::HL, 0x8000);
+        ula.set_video_ts(VideoTs::new(0, PentagonVidFrame::HTS_RANGE.start));
+        for frame in 1..=3 {
+            ula.execute_next_frame(&mut cpu);
+            assert_eq!(ula.memory_ref().read(0x8000), frame);
+            assert!(cpu.is_halt());
+        }
+        assert_eq!(ula.current_frame(), 2);
+    }
+}
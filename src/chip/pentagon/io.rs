@@ -0,0 +1,100 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::num::NonZeroU16;
+
+use crate::z80emu::{Io, Memory};
+use crate::bus::{BusDevice, PortAddress};
+use crate::clock::{VideoTs, VFrameTs};
+use crate::peripherals::{KeyboardInterface, ZXKeyboardMap};
+use crate::memory::{ZxMemory, PagedMemory16k, MemoryExtension};
+use super::{Pentagon, PentagonVidFrame};
+
+#[derive(Clone, Copy, Default, Debug)]
+struct PentagonMemPortAddress;
+impl PortAddress for PentagonMemPortAddress {
+    const ADDRESS_MASK: u16 = 0b1000_0000_0000_0010;
+    const ADDRESS_BITS: u16 = 0b0111_1111_1111_1101;
+}
+
+impl<M, B, X> Io for Pentagon<M, B, X>
+    where M: PagedMemory16k,
+          B: BusDevice,
+          B::Timestamp: From<VFrameTs<PentagonVidFrame>>,
+{
+    type Timestamp = VideoTs;
+    type WrIoBreak = ();
+    type RetiBreak = ();
+
+    #[inline(always)]
+    fn is_irq(&mut self, ts: VideoTs) -> bool {
+        self.ula.is_irq(ts)
+    }
+
+    fn read_io(&mut self, port: u16, ts: VideoTs) -> (u8, Option<NonZeroU16>) {
+        // there is no floating bus
+        self.ula.ula_read_io(port, ts)
+                .unwrap_or((u8::MAX, None))
+    }
+
+    fn write_io(&mut self, port: u16, data: u8, ts: VideoTs) -> (Option<()>, Option<NonZeroU16>) {
+        if PentagonMemPortAddress::match_port(port) {
+            // memory contention is not affected by paging, so no need to break the execution
+            if !self.mem_locked {
+                self.set_mem_port_value(data, ts);
+            }
+            (None, None)
+        }
+        else {
+            self.ula.write_io(port, data, ts)
+        }
+    }
+}
+
+impl<M, B, X> Memory for Pentagon<M, B, X>
+    where M: ZxMemory,
+          X: MemoryExtension
+{
+    type Timestamp = VideoTs;
+
+    #[inline(always)]
+    fn read_debug(&self, addr: u16) -> u8 {
+        self.ula.memory.read(addr)
+    }
+
+    #[inline(always)]
+    fn read_mem(&self, addr: u16, _ts: VideoTs) -> u8 {
+        self.ula.memory.read(addr)
+    }
+
+    #[inline(always)]
+    fn read_mem16(&self, addr: u16, _ts: VideoTs) -> u16 {
+        self.ula.memory.read16(addr)
+    }
+
+    #[inline]
+    fn read_opcode(&mut self, pc: u16, _ir: u16, _ts: VideoTs) -> u8 {
+        self.ula.memext.read_opcode(pc, &mut self.ula.memory)
+    }
+
+    #[inline]
+    fn write_mem(&mut self, addr: u16, val: u8, ts: VideoTs) {
+        self.update_frame_cache(addr, ts);
+        self.ula.memext.write_mem(addr, val, &mut self.ula.memory);
+    }
+}
+
+impl<M, B, X> KeyboardInterface for Pentagon<M, B, X> {
+    #[inline(always)]
+    fn get_key_state(&self) -> ZXKeyboardMap {
+        self.ula.get_key_state()
+    }
+    #[inline(always)]
+    fn set_key_state(&mut self, keymap: ZXKeyboardMap)  {
+        self.ula.set_key_state(keymap);
+    }
+}
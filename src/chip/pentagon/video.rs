@@ -0,0 +1,188 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::iter::StepBy;
+use core::ops::Range;
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::memory::ZxMemory;
+use crate::clock::{VideoTs, Ts, VFrameTsCounter};
+use crate::chip::ula128::video::create_ula128_renderer;
+use crate::video::{
+    BorderSize, BorderColor, PixelBuffer, Palette,
+    VideoFrame, Video, MAX_BORDER_SIZE,
+    frame_cache::{pixel_address_coords, color_address_coords}
+};
+use super::{Pentagon, PentagonMemContention};
+
+/// Implements [VideoFrame] for the Pentagon chipset.
+///
+/// The maskable interrupt is being requested 4 T-states after the frame begins. The first pixel of
+/// the screen is being drawn 17988 T-states after the beginning of the interrupt request.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct PentagonVidFrame;
+
+impl VideoFrame for PentagonVidFrame {
+    /// A range of horizontal T-states, 0 should be where the frame starts.
+    const HTS_RANGE: Range<Ts> = -72..152;
+    /// The horizontal T-state of the first video scan line when the frame interrupt is being requested.
+    const HTS_IRQ: Ts = -68;
+    /// The first video scan line index of the top border.
+    const VSL_BORDER_TOP: Ts = 16;
+    /// A range of video scan line indexes for the pixel area.
+    const VSL_PIXELS: Range<Ts> = 80..272;
+    /// The last video scan line index of the bottom border.
+    const VSL_BORDER_BOT: Ts = 320;
+    /// A total number of video scan lines.
+    const VSL_COUNT: Ts = 320;
+
+    type BorderHtsIter = StepBy<Range<Ts>>;
+
+    fn border_whole_line_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        let invborder = ((MAX_BORDER_SIZE - Self::border_size_pixels(border_size))/2) as Ts;
+        (-24+invborder..152-invborder).step_by(4)
+    }
+
+    fn border_left_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        let invborder = ((MAX_BORDER_SIZE - Self::border_size_pixels(border_size))/2) as Ts;
+        (-24+invborder..0).step_by(4)
+    }
+
+    fn border_right_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        let invborder = ((MAX_BORDER_SIZE - Self::border_size_pixels(border_size))/2) as Ts;
+        (128..152-invborder).step_by(4)
+    }
+
+    #[inline(always)]
+    fn contention(hc: Ts) -> Ts {
+        hc
+    }
+
+    #[inline(always)]
+    fn is_contended_line_mreq(_vsl: Ts) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn is_contended_line_no_mreq(_vsl: Ts) -> bool {
+        false
+    }
+}
+
+impl<M: ZxMemory, D, X> Video for Pentagon<M, D, X> {
+    type VideoFrame = PentagonVidFrame;
+    type Contention = PentagonMemContention;
+
+    #[inline]
+    fn border_color(&self) -> BorderColor {
+        self.ula.border_color()
+    }
+
+    fn set_border_color(&mut self, border: BorderColor) {
+        self.ula.set_border_color(border)
+    }
+
+    fn render_video_frame<'a, B: PixelBuffer<'a>, P: Palette<Pixel=B::Pixel>>(
+            &mut self,
+            buffer: &'a mut [u8],
+            pitch: usize,
+            border_size: BorderSize
+        )
+    {
+        create_ula128_renderer(border_size,
+                               &mut self.ula,
+                               self.beg_screen_shadow,
+                               &self.shadow_frame_cache,
+                               &mut self.screen_changes)
+        .render_pixels::<B, P, Self::VideoFrame>(buffer, pitch)
+    }
+
+    fn visible_screen_bank(&self) -> usize {
+        self.cur_screen_shadow.into()
+    }
+
+    fn current_video_ts(&self) -> VideoTs {
+        self.ula.current_video_ts()
+    }
+
+    fn current_video_clock(&self) -> VFrameTsCounter<Self::VideoFrame, Self::Contention> {
+        VFrameTsCounter::from_video_ts(self.ula.current_video_ts(), PentagonMemContention)
+    }
+
+    fn set_video_ts(&mut self, vts: VideoTs) {
+        self.ula.set_video_ts(vts);
+    }
+
+    fn flash_state(&self) -> bool {
+        self.ula.flash_state()
+    }
+}
+
+impl<M: ZxMemory, B, X> Pentagon<M, B, X> {
+    #[inline]
+    pub(super) fn update_frame_cache(&mut self, addr: u16, ts: VideoTs) {
+        let frame_cache = match addr {
+            0x4000..=0x5AFF => &mut self.ula.frame_cache,
+            0xC000..=0xDAFF => match self.page3_screen_shadow_bank() {
+                Some(false) => &mut self.ula.frame_cache,
+                Some(true)  => &mut self.shadow_frame_cache,
+                None => return
+            }
+            _ => return
+        };
+        if addr & 0x1800 != 0x1800 {
+            let coords = pixel_address_coords(addr);
+            frame_cache.update_frame_pixels(&self.ula.memory, coords, addr, ts);
+        }
+        else {
+            let coords = color_address_coords(addr);
+            frame_cache.update_frame_colors(&self.ula.memory, coords, addr, ts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{TimestampOps, VFrameTs};
+    use super::*;
+    type TestVideoFrame = PentagonVidFrame;
+    type TestVFTs = VFrameTs<TestVideoFrame>;
+
+    #[test]
+    fn test_video_frame_vts_utils() {
+        assert_eq!(TestVideoFrame::HTS_COUNT, 224);
+        assert_eq!(TestVideoFrame::FRAME_TSTATES_COUNT, 71680);
+        assert_eq!(TestVFTs::EOF, TestVFTs::from_tstates(TestVideoFrame::FRAME_TSTATES_COUNT));
+        let first_pixel = TestVFTs::new(TestVideoFrame::VSL_PIXELS.start, 0);
+        assert_eq!(first_pixel.diff_from(TestVFTs::new(0, TestVideoFrame::HTS_IRQ)), 17988);
+        let items = [((  0, -72),   -72, ( 0, 71608), false, true , (  0, -72)),
+                     ((  0,   0),     0, ( 1,     0), false, true , (  0,   0)),
+                     ((  0,  -1),    -1, ( 0, 71679), false, true , (  0,  -1)),
+                     ((  1,   0),   224, ( 1,   224), false, true , (  1,   0)),
+                     ((320,  -1), 71679, ( 1, 71679), true , true , (320,  -1)),
+                     ((320,   0), 71680, ( 2,     0), true , true , (320,   0)),
+                     ((  0, 224),   224, ( 1,   224), false, false, (  1,   0))];
+        for ((vc, hc), fts, (nfr, nfts), eof, is_norm, (nvc, nhc)) in items.iter().copied() {
+            let vts = TestVFTs::new(vc, hc);
+            let nvts = TestVFTs::new(nvc, nhc);
+            assert_eq!(TestVideoFrame::vc_hc_to_tstates(vc, hc), fts);
+            assert_eq!(vts.into_tstates(), fts);
+            assert_eq!(TestVFTs::from_tstates(fts), nvts);
+            assert_eq!(vts.into_frame_tstates(1), (nfr, nfts));
+            assert_eq!(vts.is_eof(), eof);
+            assert_eq!(vts.is_normalized(), is_norm);
+            assert_eq!(vts.normalized(), nvts);
+        }
+        for hc in TestVideoFrame::HTS_RANGE {
+            assert_eq!(TestVideoFrame::contention(hc), hc);
+            assert_eq!(TestVideoFrame::floating_bus_offset(hc), None);
+        }
+    }
+}
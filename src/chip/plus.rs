@@ -85,7 +85,7 @@ use crate::clock::{
 use crate::chip::{
     ControlUnit, MemoryAccess,
     UlaPortFlags, ScldCtrlFlags, UlaPlusRegFlags, ColorMode, Ula128MemFlags, Ula3CtrlFlags,
-    UlaControl,
+    ScorpionMemFlags, UlaControl,
     InnerAccess,
     scld::frame_cache::SourceMode,
     ula::{
@@ -285,6 +285,14 @@ impl<'a, U> UlaControl for UlaPlus<U>
         self.ula.set_ula3_ctrl_port_value(value)
     }
 
+    fn scorpion_mem_port_value(&self) -> Option<ScorpionMemFlags> {
+        self.ula.scorpion_mem_port_value()
+    }
+
+    fn set_scorpion_mem_port_value(&mut self, value: ScorpionMemFlags) -> bool {
+        self.ula.set_scorpion_mem_port_value(value)
+    }
+
     fn scld_ctrl_port_value(&self) -> Option<ScldCtrlFlags> {
         Some(self.scld_mode)
     }
@@ -15,7 +15,7 @@ use crate::chip::{
     scld::io::ScldCtrlPortAddress
 };
 use crate::peripherals::{KeyboardInterface, ZXKeyboardMap};
-use crate::memory::ZxMemory;
+use crate::memory::MemoryExtension;
 use crate::video::{Video, BorderColor};
 use super::{UlaPlus, UlaPlusInner};
 
@@ -110,7 +110,8 @@ impl<'a, U> Memory for UlaPlus<U>
     #[inline]
     fn write_mem(&mut self, addr: u16, val: u8, ts: VideoTs) {
         self.update_frame_cache(addr, ts);
-        self.ula.memory_mut().write(addr, val);
+        let (memory, memext) = self.ula.memory_with_ext_mut();
+        memext.write_mem(addr, val, memory);
     }
 }
 
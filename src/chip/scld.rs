@@ -9,8 +9,13 @@
 
 Implementation specifics:
 
-* Currently, there are no dedicated [VideoFrame] implementations for TC2048 / TC2068 / TS2068 models,
-  in the meantime, you may use the [UlaVideoFrame] and [UlaNTSCVidFrame] with [Scld].
+* Use [ScldVidFrame] with TC2048 / TC2068 and [ScldNTSCVidFrame] with TS2068 models.
+  The maskable interrupt is being requested 15 T-states later than on ZX Spectrum 48k.
+* The Timex specific memory contention pattern is not emulated, the pattern of the Ferranti ULA
+  is being used instead.
+* Only the HOME bank RAM pages at addresses `0x4000` to `0x7FFF` are contended,
+  the DOCK and EX-ROM pages mapped there instead are not, see [ScldMemContention].
+* There is no floating bus, reading from unattached ports always returns `0xFF`.
 * SCLD ports: `0xFF` and `0xF4` as well as ULA `0xFE` port are decoded on all 8 lowest address bits as per original machines.
 * The hard reset defaults the screen mode and memory paging but leaves the border-color unmodified.
 * The DOCK and EX-ROM memory pages are mapped from ROM banks as follows and depend on [ZxMemory::ROM_BANKS_MAX]:
@@ -24,8 +29,6 @@ Implementation specifics:
 In case of TC2048 the 16k ROM should be loaded to ROM banks: `[ROM_BANKS_MAX - 1, ROM_BANKS_MAX]`.
 In case of Tx2068 the 24k ROM should be loaded to ROM banks: `[ROM_BANKS_MAX - 1, ROM_BANKS_MAX, 8]`.
 
-[UlaVideoFrame]: crate::chip::ula::UlaVideoFrame
-[UlaNTSCVidFrame]: crate::chip::ula::UlaNTSCVidFrame
 [ZxMemory::ROM_BANKS_MAX]: crate::memory::ZxMemory::ROM_BANKS_MAX
 */
 use crate::z80emu::{*, host::Result};
@@ -56,10 +59,37 @@ pub mod frame_cache;
 mod audio_earmic;
 pub(crate) mod io;
 mod video;
+mod video_ntsc;
 #[cfg(feature = "formats")]
 mod screen;
 
 use frame_cache::SourceMode;
+pub use video::ScldVidFrame;
+pub use video_ntsc::ScldNTSCVidFrame;
+
+/// A struct implementing [MemoryContention] for the SCLD chipset.
+///
+/// Only the 8k pages at addresses [0x4000, 0x5FFF] and [0x6000, 0x7FFF] are being contended,
+/// and only while the HOME bank RAM is mapped there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScldMemContention { pages: u8 }
+
+impl ScldMemContention {
+    const HOME_PAGES: u8 = 0b0000_1100;
+}
+
+impl Default for ScldMemContention {
+    fn default() -> Self {
+        ScldMemContention { pages: ScldMemContention::HOME_PAGES }
+    }
+}
+
+impl MemoryContention for ScldMemContention {
+    #[inline(always)]
+    fn is_contended_address(self, address: u16) -> bool {
+        self.pages & (1u8 << (address >> 13)) != 0
+    }
+}
 
 /// This is the emulator of SCLD chip used with Timex's TC2048 / TC2068 / TS2068 models.
 ///
@@ -180,6 +210,11 @@ impl<M, B, X, V> UlaControl for Scld<M, B, X, V>
 impl<M, B, X, V> Scld<M, B, X, V>
     where M: PagedMemory8k,
 {
+    #[inline(always)]
+    pub(crate) fn memory_contention(&self) -> ScldMemContention {
+        ScldMemContention { pages: !self.mem_paged & ScldMemContention::HOME_PAGES }
+    }
+
     #[inline]
     fn push_mode_change(&mut self, ts: VideoTs, render_mode: RenderMode) {
         self.mode_changes.push((ts, render_mode.bits()).into())
@@ -246,10 +281,11 @@ impl<M, B, X, V> Scld<M, B, X, V>
         self.cur_ctrl_flags = flags;
     }
 
-    fn set_mmu_flags_value(&mut self, paged: u8) {
+    /// Returns `true` if the memory contention has changed.
+    fn set_mmu_flags_value(&mut self, paged: u8) -> bool {
         let diff_pages = self.mem_paged ^ paged;
         if diff_pages == 0 {
-            return;
+            return false;
         }
         let map_ex_rom = self.cur_ctrl_flags.is_map_ex_rom();
         for page in 0..8u8 {
@@ -272,6 +308,7 @@ impl<M, B, X, V> Scld<M, B, X, V>
             }
         }
         self.mem_paged = paged;
+        diff_pages & ScldMemContention::HOME_PAGES != 0
     }
 }
 
@@ -69,8 +69,8 @@ impl<M, B, X, V> Io for Scld<M, B, X, V>
                 (ula_data, None)
             }
             else {
-                self.ula.bus.read_io(port, VFrameTs::from(ts).into())
-                            .unwrap_or_else(|| (!0, None) )
+                // no floating bus on Timex machines
+                bus_data.unwrap_or((!0, None))
             }
         }
     }
@@ -87,7 +87,10 @@ impl<M, B, X, V> Io for Scld<M, B, X, V>
             self.set_ctrl_flags_value(flags, ts);
         }
         else if ScldMmuPortAddress::match_port(port) {
-            self.set_mmu_flags_value(data);
+            if self.set_mmu_flags_value(data) {
+                // the memory contention has changed
+                return (Some(()), None)
+            }
         }
         else if let Some(ws) = self.ula.bus.write_io(port, data, VFrameTs::from(ts).into()) {
             return (None, NonZeroU16::new(ws))
@@ -126,7 +129,7 @@ impl<M, B, X, V> Memory for Scld<M, B, X, V>
     #[inline]
     fn write_mem(&mut self, addr: u16, val: u8, ts: VideoTs) {
         self.update_frame_cache(addr, ts);
-        self.ula.memory.write(addr, val);
+        self.ula.memext.write_mem(addr, val, &mut self.ula.memory);
     }
 }
 
@@ -5,11 +5,15 @@
 
     For the full copyright notice, see the lib.rs file.
 */
-use core::iter::{self, Empty};
+use core::iter::{self, Empty, StepBy};
+use core::ops::Range;
 use std::vec::Drain;
 
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
 use crate::memory::PagedMemory8k;
-use crate::clock::{VideoTs, VideoTsData2, VideoTsData6, VFrameTsCounter};
+use crate::clock::{VideoTs, Ts, VideoTsData2, VideoTsData6, VFrameTsCounter};
 use crate::video::{
     RendererPlus, UlaPlusPalette, PaletteChange, BorderSize, BorderColor, PixelBuffer, Palette,
     VideoFrame, Video,
@@ -17,11 +21,58 @@ use crate::video::{
         pixel_address_coords, color_address_coords
     }
 };
-use crate::chip::ula::UlaMemoryContention;
+use crate::chip::ula::UlaVideoFrame;
 use super::frame_cache::{
     SourceMode, ScldFrameProducer
 };
-use super::Scld;
+use super::{Scld, ScldMemContention};
+
+/// Implements [VideoFrame] for the PAL SCLD chipset of TC2048 and TC2068.
+///
+/// The maskable interrupt is being requested 15 T-states after the frame begins. The first pixel of
+/// the screen is being drawn 14321 T-states after the beginning of the interrupt request.
+///
+/// The memory contention pattern is borrowed from [UlaVideoFrame], the Timex specific pattern
+/// is not emulated.
+///
+/// There is no floating bus nor the snow effect.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct ScldVidFrame;
+
+impl VideoFrame for ScldVidFrame {
+    /// A range of horizontal T-states, 0 should be where the frame starts.
+    const HTS_RANGE: Range<Ts> = UlaVideoFrame::HTS_RANGE;
+    /// The horizontal T-state of the first video scan line when the frame interrupt is being requested.
+    const HTS_IRQ: Ts = 15;
+    /// The first video scan line index of the top border.
+    const VSL_BORDER_TOP: Ts = UlaVideoFrame::VSL_BORDER_TOP;
+    /// A range of video scan line indexes for the pixel area.
+    const VSL_PIXELS: Range<Ts> = UlaVideoFrame::VSL_PIXELS;
+    /// The last video scan line index of the bottom border.
+    const VSL_BORDER_BOT: Ts = UlaVideoFrame::VSL_BORDER_BOT;
+    /// A total number of video scan lines.
+    const VSL_COUNT: Ts = UlaVideoFrame::VSL_COUNT;
+
+    type BorderHtsIter = StepBy<Range<Ts>>;
+
+    fn border_whole_line_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        UlaVideoFrame::border_whole_line_hts_iter(border_size)
+    }
+
+    fn border_left_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        UlaVideoFrame::border_left_hts_iter(border_size)
+    }
+
+    fn border_right_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        UlaVideoFrame::border_right_hts_iter(border_size)
+    }
+
+    #[inline]
+    fn contention(hc: Ts) -> Ts {
+        UlaVideoFrame::contention(hc)
+    }
+}
 
 impl<M, D, X, V> Video for Scld<M, D, X, V>
     where M: PagedMemory8k,
@@ -29,7 +80,7 @@ impl<M, D, X, V> Video for Scld<M, D, X, V>
 {
     const PIXEL_DENSITY: u32 = 2;
     type VideoFrame = V;
-    type Contention = UlaMemoryContention;
+    type Contention = ScldMemContention;
 
     #[inline]
     fn border_color(&self) -> BorderColor {
@@ -57,7 +108,8 @@ impl<M, D, X, V> Video for Scld<M, D, X, V>
     }
 
     fn current_video_clock(&self) -> VFrameTsCounter<Self::VideoFrame, Self::Contention> {
-        self.ula.current_video_clock()
+        let contention = self.memory_contention();
+        VFrameTsCounter::from_video_ts(self.ula.current_video_ts(), contention)
     }
 
     fn set_video_ts(&mut self, vts: VideoTs) {
@@ -121,3 +173,53 @@ impl<M, D, X, V> Scld<M, D, X, V>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{TimestampOps, VFrameTs};
+    use super::*;
+    type TestVideoFrame = ScldVidFrame;
+    type TestVFTs = VFrameTs<TestVideoFrame>;
+
+    #[test]
+    fn test_contention() {
+        let vts0 = TestVFTs::new(0, 0);
+        let tstates = [(14335, 14341),
+                       (14336, 14341),
+                       (14337, 14341),
+                       (14338, 14341),
+                       (14339, 14341),
+                       (14340, 14341),
+                       (14341, 14341),
+                       (14342, 14342)];
+        for offset in (0..16).map(|x| x * 8i32) {
+            for (testing, target) in tstates.iter().copied() {
+                let mut vts: TestVFTs = vts0 + testing + offset as u32;
+                vts.hc = TestVideoFrame::contention(vts.hc);
+                assert_eq!(vts.normalized(),
+                           TestVFTs::from_tstates(target + offset));
+            }
+        }
+        let refts = tstates[0].0;
+        for ts in (refts - 96..refts)
+            .chain(refts + 128..refts+TestVideoFrame::HTS_COUNT as i32) {
+            let vts = TestVFTs::from_tstates(ts);
+            assert_eq!(TestVideoFrame::contention(vts.hc), vts.hc);
+        }
+    }
+
+    #[test]
+    fn test_video_frame_vts_utils() {
+        assert_eq!(TestVideoFrame::HTS_COUNT, 224);
+        assert_eq!(TestVideoFrame::FRAME_TSTATES_COUNT, 69888);
+        assert_eq!(TestVFTs::EOF, TestVFTs::from_tstates(TestVideoFrame::FRAME_TSTATES_COUNT));
+        let first_pixel = TestVFTs::new(TestVideoFrame::VSL_PIXELS.start, 0);
+        assert_eq!(first_pixel.diff_from(TestVFTs::new(0, TestVideoFrame::HTS_IRQ)), 14321);
+        for hc in TestVideoFrame::HTS_RANGE {
+            assert_eq!(TestVideoFrame::floating_bus_offset(hc), None);
+        }
+        for vc in 0..TestVideoFrame::VSL_COUNT {
+            assert_eq!(TestVideoFrame::snow_interference_coords(VideoTs::new(vc, 2)), None);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::iter::StepBy;
+use core::ops::Range;
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::clock::Ts;
+use crate::video::{BorderSize, VideoFrame};
+use crate::chip::ula128::Ula128VidFrame;
+use super::ScldVidFrame;
+
+/// Implements [VideoFrame] for the NTSC SCLD chipset of TS2068.
+///
+/// There are 228 T-states per video scan line (24 for the left border, 128 for the pixel area,
+/// 24 for the right border and 52 for the horizontal retrace) and 262 lines per frame (24 for the top
+/// border, 192 for the pixel area, 25 for the bottom border and 21 for the vertical retrace), which
+/// gives 59736 T-states per frame at 3.528 MHz.
+///
+/// The maskable interrupt is being requested 15 T-states after the frame begins, as with the PAL
+/// SCLD. The first pixel of the screen is being drawn 9105 T-states after the beginning of the
+/// interrupt request. The memory contention pattern is borrowed from [ScldVidFrame].
+///
+/// There is no floating bus nor the snow effect.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct ScldNTSCVidFrame;
+
+impl VideoFrame for ScldNTSCVidFrame {
+    /// A range of horizontal T-states, 0 should be where the frame starts.
+    const HTS_RANGE: Range<Ts> = Ula128VidFrame::HTS_RANGE;
+    /// The horizontal T-state of the first video scan line when the frame interrupt is being requested.
+    const HTS_IRQ: Ts = ScldVidFrame::HTS_IRQ;
+    /// The first video scan line index of the top border.
+    const VSL_BORDER_TOP: Ts = 16;
+    /// A range of video scan line indexes for the pixel area.
+    const VSL_PIXELS: Range<Ts> = 40..232;
+    /// The last video scan line index of the bottom border.
+    const VSL_BORDER_BOT: Ts = 257;
+    /// A total number of video scan lines.
+    const VSL_COUNT: Ts = 262;
+
+    type BorderHtsIter = StepBy<Range<Ts>>;
+
+    fn border_whole_line_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        Ula128VidFrame::border_whole_line_hts_iter(border_size)
+    }
+
+    fn border_left_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        Ula128VidFrame::border_left_hts_iter(border_size)
+    }
+
+    fn border_right_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        Ula128VidFrame::border_right_hts_iter(border_size)
+    }
+
+    #[inline]
+    fn contention(hc: Ts) -> Ts {
+        ScldVidFrame::contention(hc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{TimestampOps, VFrameTs};
+    use super::*;
+    type TestVideoFrame = ScldNTSCVidFrame;
+    type TestVFTs = VFrameTs<TestVideoFrame>;
+
+    #[test]
+    fn test_contention() {
+        let vts0 = TestVFTs::new(0, 0);
+        let tstates = [(9119, 9125),
+                       (9120, 9125),
+                       (9121, 9125),
+                       (9122, 9125),
+                       (9123, 9125),
+                       (9124, 9125),
+                       (9125, 9125),
+                       (9126, 9126)];
+        for offset in (0..16).map(|x| x * 8i32) {
+            for (testing, target) in tstates.iter().copied() {
+                let mut vts: TestVFTs = vts0 + testing + offset as u32;
+                vts.hc = TestVideoFrame::contention(vts.hc);
+                assert_eq!(vts.normalized(),
+                           TestVFTs::from_tstates(target + offset));
+            }
+        }
+    }
+
+    #[test]
+    fn test_video_frame_vts_utils() {
+        assert_eq!(TestVideoFrame::HTS_COUNT, 228);
+        assert_eq!(TestVideoFrame::FRAME_TSTATES_COUNT, 59736);
+        assert_eq!(TestVFTs::EOF, TestVFTs::from_tstates(TestVideoFrame::FRAME_TSTATES_COUNT));
+        let first_pixel = TestVFTs::new(TestVideoFrame::VSL_PIXELS.start, 0);
+        assert_eq!(first_pixel.diff_from(TestVFTs::new(0, TestVideoFrame::HTS_IRQ)), 9105);
+        for hc in TestVideoFrame::HTS_RANGE {
+            assert_eq!(TestVideoFrame::floating_bus_offset(hc), None);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+//! An emulator of the Scorpion ZS-256 chipset.
+//!
+//! Scorpion ZS-256 is a Russian ZX Spectrum 128k clone with 256kb of RAM and 64kb of ROM.
+//! The chipset has no memory or I/O contention and no floating bus. Its video frame has the same
+//! geometry as the one of the original 48k model.
+//!
+//! The memory is being paged via two ports:
+//!
+//! * `0x7FFD` - the same as on the 128k model: the RAM bank bits 0-2, the shadow screen, the ROM bank
+//!   and the paging lock.
+//! * `0x1FFD` - described by [ScorpionMemFlags]: the RAM bank bit 3, the service ROM and the RAM bank 0
+//!   paged in at `0x0000` instead of ROM.
+//!
+//! Setting the paging lock prevents writes to both ports.
+//!
+//! The ROM banks are expected to be loaded in the following order:
+//!
+//! 0. The 128k ROM.
+//! 1. The 48k BASIC ROM.
+//! 2. The service ROM (the shadow monitor).
+//! 3. The TR-DOS ROM.
+mod audio_earmic;
+mod io;
+mod video;
+#[cfg(feature = "formats")]
+mod screen;
+
+use core::fmt;
+
+use crate::z80emu::{*, host::Result};
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::bus::{BusDevice, VFNullDevice};
+use crate::clock::{VFrameTs, VideoTs, VFrameTsCounter, MemoryContention};
+use crate::chip::{
+    InnerAccess, ControlUnit, MemoryAccess, Ula128MemFlags, ScorpionMemFlags, UlaControl,
+    ula::{
+        Ula, UlaControlExt, UlaCpuExt,
+        frame_cache::UlaFrameCache
+    }
+};
+use crate::memory::{ZxMemory, MemoryExtension, NoMemoryExtension, Memory256kPlus};
+use crate::video::Video;
+pub use video::ScorpionVidFrame;
+
+/// The index of the service ROM bank.
+pub const SERVICE_ROM_BANK: usize = 2;
+
+/// A struct implementing [MemoryContention] with no contended addresses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScorpionMemContention;
+
+type InnerUla<B, X> = Ula<Memory256kPlus, B, X, ScorpionVidFrame>;
+
+/// Scorpion ZS-256 chipset.
+///
+/// See [Ula] for description of generic parameters.
+#[derive(Clone)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "snapshot", serde(rename_all = "camelCase"))]
+pub struct Scorpion<B=VFNullDevice<ScorpionVidFrame>, X=NoMemoryExtension> {
+    ula: InnerUla<B, X>,
+    mem_page3_bank: u8,
+    rom_bank: u8,                   // selected by 0x7FFD
+    mem2_flags: ScorpionMemFlags,   // last value written to 0x1FFD
+    beg_screen_shadow: bool,        // shadow screen when a frame began
+    cur_screen_shadow: bool,        // current shadow screen
+    mem_locked: bool,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    shadow_frame_cache: UlaFrameCache<ScorpionVidFrame>,
+    #[cfg_attr(feature = "snapshot", serde(skip))]
+    screen_changes: Vec<VideoTs>,
+}
+
+impl MemoryContention for ScorpionMemContention {
+    #[inline(always)]
+    fn is_contended_address(self, _address: u16) -> bool {
+        false
+    }
+}
+
+impl<B: Default, X: Default> Default for Scorpion<B, X> {
+    fn default() -> Self {
+        Scorpion {
+            ula: Default::default(),
+            mem_page3_bank: 0,
+            rom_bank: 0,
+            mem2_flags: ScorpionMemFlags::empty(),
+            beg_screen_shadow: false,
+            cur_screen_shadow: false,
+            mem_locked: false,
+            shadow_frame_cache: Default::default(),
+            screen_changes: Vec::new()
+        }
+    }
+}
+
+impl<B, X> fmt::Debug for Scorpion<B, X>
+    where B: BusDevice, X: MemoryExtension
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scorpion")
+            .field("ula", &self.ula)
+            .field("mem_page3_bank", &self.mem_page3_bank)
+            .field("rom_bank", &self.rom_bank)
+            .field("mem2_flags", &self.mem2_flags)
+            .field("beg_screen_shadow", &self.beg_screen_shadow)
+            .field("cur_screen_shadow", &self.cur_screen_shadow)
+            .field("mem_locked", &self.mem_locked)
+            .field("shadow_frame_cache", &self.shadow_frame_cache)
+            .field("screen_changes", &self.screen_changes.len())
+            .finish()
+    }
+}
+
+impl<B, X> InnerAccess for Scorpion<B, X> {
+    type Inner = InnerUla<B, X>;
+
+    fn inner_ref(&self) -> &Self::Inner {
+        &self.ula
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Inner {
+        &mut self.ula
+    }
+
+    fn into_inner(self) -> Self::Inner {
+        self.ula
+    }
+}
+
+impl<B, X> UlaControl for Scorpion<B, X> {
+    fn has_late_timings(&self) -> bool {
+        self.ula.has_late_timings()
+    }
+
+    fn set_late_timings(&mut self, late_timings: bool) {
+        self.ula.set_late_timings(late_timings)
+    }
+
+    fn ula128_mem_port_value(&self) -> Option<Ula128MemFlags> {
+        let mut flags = Ula128MemFlags::empty()
+                        .with_last_ram_page_bank(self.mem_page3_bank.into());
+        flags.set(Ula128MemFlags::SCREEN_BANK, self.cur_screen_shadow);
+        flags.set(Ula128MemFlags::ROM_BANK, self.rom_bank != 0);
+        flags.set(Ula128MemFlags::LOCK_MMU, self.mem_locked);
+        Some(flags)
+    }
+
+    fn set_ula128_mem_port_value(&mut self, value: Ula128MemFlags) -> bool {
+        self.set_mem1_port_value(value, self.ula.current_video_ts());
+        true
+    }
+
+    fn scorpion_mem_port_value(&self) -> Option<ScorpionMemFlags> {
+        Some(self.mem2_flags)
+    }
+
+    fn set_scorpion_mem_port_value(&mut self, value: ScorpionMemFlags) -> bool {
+        self.set_mem2_port_value(value);
+        true
+    }
+}
+
+impl<B, X> Scorpion<B, X> {
+    #[inline(always)]
+    fn page3_screen_shadow_bank(&self) -> Option<bool> {
+        match self.mem_page3_bank {
+            5 => Some(false),
+            7 => Some(true),
+            _ => None
+        }
+    }
+
+    fn update_page0(&mut self) {
+        let memory = &mut self.ula.memory;
+        if self.mem2_flags.is_ram_at_rom() {
+            memory.map_ram_bank(0, 0).unwrap();
+        }
+        else if self.mem2_flags.is_service_rom() {
+            memory.map_rom_bank(SERVICE_ROM_BANK, 0).unwrap();
+        }
+        else {
+            memory.map_rom_bank(self.rom_bank.into(), 0).unwrap();
+        }
+    }
+
+    fn update_page3(&mut self) {
+        let bank = Ula128MemFlags::from_bits_truncate(self.mem_page3_bank).last_ram_page_bank()
+                   | self.mem2_flags.last_ram_page_bank_hi();
+        self.mem_page3_bank = bank as u8;
+        self.ula.memory.map_ram_bank(bank, 3).unwrap();
+    }
+
+    fn set_mem1_port_value(&mut self, flags: Ula128MemFlags, ts: VideoTs) {
+        self.mem_locked = flags.is_mmu_locked();
+        let cur_screen_shadow = flags.is_shadow_screen();
+        if self.cur_screen_shadow != cur_screen_shadow {
+            self.cur_screen_shadow = cur_screen_shadow;
+            self.screen_changes.push(ts);
+        }
+        self.rom_bank = flags.rom_page_bank() as u8;
+        self.mem_page3_bank = flags.last_ram_page_bank() as u8;
+        self.update_page0();
+        self.update_page3();
+    }
+
+    fn set_mem2_port_value(&mut self, flags: ScorpionMemFlags) {
+        self.mem2_flags = flags;
+        self.update_page0();
+        self.update_page3();
+    }
+}
+
+impl<B, X> MemoryAccess for Scorpion<B, X>
+    where X: MemoryExtension
+{
+    type Memory = Memory256kPlus;
+    type MemoryExt = X;
+
+    #[inline(always)]
+    fn memory_ext_ref(&self) -> &Self::MemoryExt {
+        &self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_ext_mut(&mut self) -> &mut Self::MemoryExt {
+        &mut self.ula.memext
+    }
+    #[inline(always)]
+    fn memory_mut(&mut self) -> &mut Self::Memory {
+        &mut self.ula.memory
+    }
+    #[inline(always)]
+    fn memory_ref(&self) -> &Self::Memory {
+        &self.ula.memory
+    }
+
+    fn memory_with_ext_mut(&mut self) -> (&mut Self::Memory, &mut Self::MemoryExt) {
+        (&mut self.ula.memory, &mut self.ula.memext)
+    }
+}
+
+impl<B, X> ControlUnit for Scorpion<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<ScorpionVidFrame>>,
+          X: MemoryExtension
+{
+    type BusDevice = B;
+
+    #[inline]
+    fn bus_device_mut(&mut self) -> &mut Self::BusDevice {
+        self.ula.bus_device_mut()
+    }
+    #[inline]
+    fn bus_device_ref(&self) -> &Self::BusDevice {
+        self.ula.bus_device_ref()
+    }
+    #[inline]
+    fn into_bus_device(self) -> Self::BusDevice {
+        self.ula.into_bus_device()
+    }
+
+    fn reset<C: Cpu>(&mut self, cpu: &mut C, hard: bool) {
+        self.ula.reset(cpu, hard);
+        if hard {
+            self.mem_page3_bank = 0;
+            self.rom_bank = 0;
+            self.mem2_flags = ScorpionMemFlags::empty();
+            if self.cur_screen_shadow {
+                self.screen_changes.push(self.current_video_ts());
+            }
+            self.cur_screen_shadow = false;
+            self.mem_locked = false;
+            self.update_page0();
+        }
+    }
+
+    fn nmi<C: Cpu>(&mut self, cpu: &mut C) -> bool {
+        self.ula_nmi(cpu)
+    }
+
+    fn execute_next_frame<C: Cpu>(&mut self, cpu: &mut C) {
+        while !self.ula_execute_next_frame_with_breaks(cpu) {}
+    }
+
+    fn ensure_next_frame(&mut self) {
+        self.ensure_next_frame_vtsc();
+    }
+
+    fn execute_single_step<C: Cpu, F: FnOnce(CpuDebug)>(
+            &mut self,
+            cpu: &mut C,
+            debug: Option<F>
+        ) -> Result<(),()>
+    {
+        self.ula_execute_single_step(cpu, debug)
+    }
+}
+
+impl<B, X> UlaControlExt for Scorpion<B, X>
+    where B: BusDevice,
+          B::Timestamp: From<VFrameTs<ScorpionVidFrame>>,
+{
+    fn prepare_next_frame<C: MemoryContention>(
+            &mut self,
+            vtsc: VFrameTsCounter<ScorpionVidFrame, C>
+        ) -> VFrameTsCounter<ScorpionVidFrame, C>
+    {
+        self.beg_screen_shadow = self.cur_screen_shadow;
+        self.shadow_frame_cache.clear();
+        self.screen_changes.clear();
+        self.ula.prepare_next_frame(vtsc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::MemoryKind;
+    use crate::video::{Video, VideoFrame};
+    use super::*;
+
+    #[test]
+    fn test_scorpion() {
+        assert_eq!(<Scorpion as Video>::VideoFrame::FRAME_TSTATES_COUNT, 69888);
+        let mut ula: Scorpion = Default::default();
+        let ts = VideoTs::default();
+        for data in 0..=0x1Fu8 {
+            assert_eq!(ula.write_io(0x7FFD, data, ts), (None, None));
+            let bank = data as usize & 7;
+            assert_eq!(ula.memory_ref().page_bank(0).unwrap(), (MemoryKind::Rom, (data >> 4).into()));
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank));
+            assert_eq!(ula.ula128_mem_port_value().unwrap().bits(), data);
+            assert_eq!(ula.write_io(0x1FFD, 0x10, ts), (None, None));
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank | 8));
+            assert_eq!(ula.ula128_mem_port_value().unwrap().bits(), data);
+            assert_eq!(ula.write_io(0x1FFD, 0x02, ts), (None, None));
+            assert_eq!(ula.memory_ref().page_bank(0).unwrap(), (MemoryKind::Rom, SERVICE_ROM_BANK));
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank));
+            assert_eq!(ula.write_io(0x1FFD, 0x13, ts), (None, None));
+            assert_eq!(ula.memory_ref().page_bank(0).unwrap(), (MemoryKind::Ram, 0));
+            assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, bank | 8));
+            assert_eq!(ula.scorpion_mem_port_value().unwrap().bits(), 0x13);
+            ula.memory_mut().write(0x0000, data);
+            assert_eq!(ula.memory_ref().read(0x0000), data);
+            assert_eq!(ula.write_io(0x1FFD, 0, ts), (None, None));
+            let clock = ula.current_video_clock();
+            for addr in (0x0000..=0xFFFF).step_by(0x100) {
+                assert!(!clock.is_contended_address(addr));
+            }
+        }
+        assert_eq!(ula.write_io(0x7FFD, 0x21, ts), (None, None));
+        assert!(ula.mem_locked);
+        assert_eq!(ula.write_io(0x7FFD, 0x07, ts), (None, None));
+        assert_eq!(ula.write_io(0x1FFD, 0x11, ts), (None, None));
+        assert_eq!(ula.ula128_mem_port_value().unwrap().bits(), 0x21);
+        assert_eq!(ula.scorpion_mem_port_value().unwrap(), ScorpionMemFlags::empty());
+        assert_eq!(ula.memory_ref().page_bank(0).unwrap(), (MemoryKind::Rom, 0));
+        assert_eq!(ula.memory_ref().page_bank(3).unwrap(), (MemoryKind::Ram, 1));
+    }
+}
@@ -0,0 +1,167 @@
+/*
+    Copyright (C) 2020  Rafal Michalski
+
+    This file is part of SPECTRUSTY, a Rust library for building emulators.
+
+    For the full copyright notice, see the lib.rs file.
+*/
+use core::iter::StepBy;
+use core::ops::Range;
+
+#[cfg(feature = "snapshot")]
+use serde::{Serialize, Deserialize};
+
+use crate::clock::{VideoTs, Ts, VFrameTsCounter};
+use crate::chip::ula128::video::create_ula128_renderer;
+use crate::video::{
+    BorderSize, BorderColor, PixelBuffer, Palette,
+    VideoFrame, Video, MAX_BORDER_SIZE,
+    frame_cache::{pixel_address_coords, color_address_coords}
+};
+use super::{Scorpion, ScorpionMemContention};
+
+/// Implements [VideoFrame] for the Scorpion ZS-256 chipset.
+///
+/// The frame has the same geometry as [UlaVideoFrame][crate::chip::ula::UlaVideoFrame] but
+/// without the memory contention.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "snapshot", derive(Serialize, Deserialize))]
+pub struct ScorpionVidFrame;
+
+impl VideoFrame for ScorpionVidFrame {
+    /// A range of horizontal T-states, 0 should be where the frame starts.
+    const HTS_RANGE: Range<Ts> = -69..155;
+    /// The first video scan line index of the top border.
+    const VSL_BORDER_TOP: Ts = 16;
+    /// A range of video scan line indexes for the pixel area.
+    const VSL_PIXELS: Range<Ts> = 64..256;
+    /// The last video scan line index of the bottom border.
+    const VSL_BORDER_BOT: Ts = 304;
+    /// A total number of video scan lines.
+    const VSL_COUNT: Ts = 312;
+
+    type BorderHtsIter = StepBy<Range<Ts>>;
+
+    fn border_whole_line_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        let invborder = ((MAX_BORDER_SIZE - Self::border_size_pixels(border_size))/2) as Ts;
+        (-20+invborder..156-invborder).step_by(4)
+    }
+
+    fn border_left_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        let invborder = ((MAX_BORDER_SIZE - Self::border_size_pixels(border_size))/2) as Ts;
+        (-20+invborder..4).step_by(4)
+    }
+
+    fn border_right_hts_iter(border_size: BorderSize) -> Self::BorderHtsIter {
+        let invborder = ((MAX_BORDER_SIZE - Self::border_size_pixels(border_size))/2) as Ts;
+        (132..156-invborder).step_by(4)
+    }
+
+    #[inline(always)]
+    fn contention(hc: Ts) -> Ts {
+        hc
+    }
+
+    #[inline(always)]
+    fn is_contended_line_mreq(_vsl: Ts) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn is_contended_line_no_mreq(_vsl: Ts) -> bool {
+        false
+    }
+}
+
+impl<D, X> Video for Scorpion<D, X> {
+    type VideoFrame = ScorpionVidFrame;
+    type Contention = ScorpionMemContention;
+
+    #[inline]
+    fn border_color(&self) -> BorderColor {
+        self.ula.border_color()
+    }
+
+    fn set_border_color(&mut self, border: BorderColor) {
+        self.ula.set_border_color(border)
+    }
+
+    fn render_video_frame<'a, B: PixelBuffer<'a>, P: Palette<Pixel=B::Pixel>>(
+            &mut self,
+            buffer: &'a mut [u8],
+            pitch: usize,
+            border_size: BorderSize
+        )
+    {
+        create_ula128_renderer(border_size,
+                               &mut self.ula,
+                               self.beg_screen_shadow,
+                               &self.shadow_frame_cache,
+                               &mut self.screen_changes)
+        .render_pixels::<B, P, Self::VideoFrame>(buffer, pitch)
+    }
+
+    fn visible_screen_bank(&self) -> usize {
+        self.cur_screen_shadow.into()
+    }
+
+    fn current_video_ts(&self) -> VideoTs {
+        self.ula.current_video_ts()
+    }
+
+    fn current_video_clock(&self) -> VFrameTsCounter<Self::VideoFrame, Self::Contention> {
+        VFrameTsCounter::from_video_ts(self.ula.current_video_ts(), ScorpionMemContention)
+    }
+
+    fn set_video_ts(&mut self, vts: VideoTs) {
+        self.ula.set_video_ts(vts);
+    }
+
+    fn flash_state(&self) -> bool {
+        self.ula.flash_state()
+    }
+}
+
+impl<B, X> Scorpion<B, X> {
+    #[inline]
+    pub(super) fn update_frame_cache(&mut self, addr: u16, ts: VideoTs) {
+        let frame_cache = match addr {
+            0x4000..=0x5AFF => &mut self.ula.frame_cache,
+            0xC000..=0xDAFF => match self.page3_screen_shadow_bank() {
+                Some(false) => &mut self.ula.frame_cache,
+                Some(true)  => &mut self.shadow_frame_cache,
+                None => return
+            }
+            _ => return
+        };
+        if addr & 0x1800 != 0x1800 {
+            let coords = pixel_address_coords(addr);
+            frame_cache.update_frame_pixels(&self.ula.memory, coords, addr, ts);
+        }
+        else {
+            let coords = color_address_coords(addr);
+            frame_cache.update_frame_colors(&self.ula.memory, coords, addr, ts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type TestVideoFrame = ScorpionVidFrame;
+
+    #[test]
+    fn test_video_frame_vts_utils() {
+        assert_eq!(TestVideoFrame::HTS_COUNT, 224);
+        assert_eq!(TestVideoFrame::FRAME_TSTATES_COUNT, 69888);
+        assert_eq!(TestVideoFrame::vc_hc_to_tstates(TestVideoFrame::VSL_PIXELS.start, 0), 14336);
+        for hc in TestVideoFrame::HTS_RANGE {
+            assert_eq!(TestVideoFrame::contention(hc), hc);
+            assert_eq!(TestVideoFrame::floating_bus_offset(hc), None);
+        }
+        for vsl in 0..TestVideoFrame::VSL_COUNT {
+            assert!(!TestVideoFrame::is_contended_line_mreq(vsl));
+            assert!(!TestVideoFrame::is_contended_line_no_mreq(vsl));
+        }
+    }
+}
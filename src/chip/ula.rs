@@ -316,8 +316,13 @@ impl<M, B, X, V> UlaControlExt for Ula<M, B, X, V>
 
 #[cfg(test)]
 mod tests {
+    use crate::chip::FrameState;
+    use crate::clock::{Ts, VideoTs};
     use crate::memory::Memory64k;
-    use crate::video::Video;
+    use crate::video::{
+        Video, VideoField, BorderSize, BorderColor,
+        pixel::{PixelBufA24, SpectrumPalRGB24}
+    };
     use super::*;
     type TestUla = UlaPAL::<Memory64k>;
 
@@ -333,4 +338,90 @@ mod tests {
             assert_eq!(clock.is_contended_address(addr), false);
         }
     }
+    #[test]
+    fn test_ula_interlaced() {
+        let border_size = BorderSize::Minimal;
+        let (width, height) = TestUla::render_size_pixels(border_size);
+        assert_eq!(TestUla::render_size_pixels_interlaced(border_size), (width, 2 * height));
+        let pitch = width as usize * 3;
+        let mut buffer = vec![0x55u8; pitch * 2 * height as usize];
+        for (frame, &border) in [BorderColor::RED, BorderColor::BLUE].iter().enumerate() {
+            let mut ula = TestUla::default();
+            ula.set_border_color(border);
+            let field = VideoField::from_frame(frame as u64);
+            ula.render_video_frame_interlaced::<PixelBufA24, SpectrumPalRGB24>(
+                &mut buffer, pitch, border_size, field);
+        }
+        for (index, line) in buffer.chunks(pitch).enumerate() {
+            let border = if index & 1 == 0 { [0xB6, 0, 0] } else { [0, 0, 0xB6] };
+            assert_eq!(line[..3], border);
+            assert_eq!(line[pitch - 3..], border);
+        }
+        // the odd field has no lines to be rendered into a single line buffer
+        let mut ula = TestUla::default();
+        let mut line = vec![0x55u8; pitch];
+        ula.render_video_frame_interlaced::<PixelBufA24, SpectrumPalRGB24>(
+            &mut line, pitch, border_size, VideoField::Odd);
+        assert!(line.iter().all(|&b| b == 0x55));
+        assert_eq!(VideoField::Odd.toggled(), VideoField::Even);
+        assert_eq!(VideoField::default().toggled(), VideoField::Odd);
+    }
+
+    #[test]
+    fn test_ula_video_field() {
+        let border_size = BorderSize::Minimal;
+        let (width, height) = TestUla::render_size_pixels_interlaced(border_size);
+        let pitch = width as usize * 3;
+        let mut buffer = vec![0x55u8; pitch * height as usize];
+        let mut ula = TestUla::default();
+        let mut cpu = Z80NMOS::default();
+        let mut fields = Vec::new();
+        for &border in [BorderColor::RED, BorderColor::BLUE, BorderColor::RED].iter() {
+            ula.set_border_color(border);
+            ula.execute_next_frame(&mut cpu);
+            fields.push(ula.current_video_field());
+            ula.render_video_field::<PixelBufA24, SpectrumPalRGB24>(&mut buffer, pitch, border_size);
+        }
+        assert_eq!(fields, [VideoField::Even, VideoField::Odd, VideoField::Even]);
+        for (index, line) in buffer.chunks(pitch).enumerate() {
+            let border = if index & 1 == 0 { [0xB6, 0, 0] } else { [0, 0, 0xB6] };
+            assert_eq!(line[..3], border);
+            assert_eq!(line[pitch - 3..], border);
+        }
+    }
+    #[test]
+    fn test_ula_interrupt() {
+        let mut ula = TestUla::default();
+        for &late_timings in &[false, true] {
+            ula.set_late_timings(late_timings);
+            let start = -Ts::from(late_timings);
+            for hc in start..start + 32 {
+                assert!(ula.is_irq(VideoTs::new(0, hc)));
+            }
+            assert!(!ula.is_irq(VideoTs::new(0, start - 1)));
+            assert!(!ula.is_irq(VideoTs::new(0, start + 32)));
+            assert!(!ula.is_irq(VideoTs::new(1, start)));
+        }
+        ula.set_late_timings(false);
+        let rom = ula.memory_mut().page_mut(0).unwrap();
+        // EI; HALT; JR -3
+        rom[0..4].copy_from_slice(&[0xFB, 0x76, 0x18, 0xFD]);
+        // INC (HL); EI; RET
+        rom[0x38..0x3B].copy_from_slice(&[0x34, 0xFB, 0xC9]);
+        ula.memory_mut().write(0x8000, 0);
+        let mut cpu = Z80NMOS::default();
+        cpu.reset();
+        cpu.set_im(InterruptMode::Mode1);
+        cpu.set_sp(0xFFFE);
+        cpu.set_reg16(StkReg16::HL, 0x8000);
+        for frame in 1..=3 {
+            ula.execute_next_frame(&mut cpu);
+            assert_eq!(ula.memory_ref().read(0x8000), frame);
+            assert!(cpu.is_halt());
+            let VideoTs { vc, hc } = ula.current_video_ts();
+            assert_eq!(vc, UlaVideoFrame::VSL_COUNT);
+            assert!((UlaVideoFrame::HTS_IRQ - 4..UlaVideoFrame::HTS_IRQ).contains(&hc));
+        }
+        assert_eq!(ula.current_frame(), 2);
+    }
 }
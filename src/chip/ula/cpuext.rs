@@ -226,7 +226,7 @@ pub fn execute_halted_state_until_eof<V, T, C>(
     }
 
     let vc = V::VSL_COUNT;
-    let hc = vtsc.hc.rem_euclid(M1_CYCLE_TS as Ts) - M1_CYCLE_TS as Ts;
+    let hc = V::HTS_IRQ + (vtsc.hc - V::HTS_IRQ).rem_euclid(M1_CYCLE_TS as Ts) - M1_CYCLE_TS as Ts;
     r_incr += (
                 (i32::from(vc) - i32::from(vtsc.vc)) * V::HTS_COUNT as i32 +
                 (i32::from(hc) - i32::from(vtsc.hc))
@@ -28,7 +28,7 @@ impl<M, B, X, V> Io for Ula<M, B, X, V>
 
     #[inline(always)]
     fn is_irq(&mut self, VideoTs{ vc, hc }: VideoTs) -> bool {
-        vc == 0 && (hc + Ts::from(self.late_timings)) & !31 == 0
+        vc == 0 && (hc - V::HTS_IRQ + Ts::from(self.late_timings)) & !31 == 0
     }
 
     fn read_io(&mut self, port: u16, ts: VideoTs) -> (u8, Option<NonZeroU16>) {
@@ -84,7 +84,7 @@ impl<M, B, X, V> Memory for Ula<M, B, X, V>
     #[inline(always)]
     fn write_mem(&mut self, addr: u16, val: u8, ts: VideoTs) {
         self.update_frame_cache(addr, ts);
-        self.memory.write(addr, val);
+        self.memext.write_mem(addr, val, &mut self.memory);
     }
 }
 
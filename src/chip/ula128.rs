@@ -364,6 +364,8 @@ impl<B, X> UlaControlExt for Ula128<B, X>
 
 #[cfg(test)]
 mod tests {
+    use crate::chip::FrameState;
+    use crate::clock::Ts;
     use crate::video::{Video, VideoFrame};
     use super::*;
 
@@ -386,4 +388,39 @@ mod tests {
             }
         }
     }
+    #[test]
+    fn test_ula128_interrupt() {
+        let mut ula: Ula128 = Default::default();
+        for &late_timings in &[false, true] {
+            ula.set_late_timings(late_timings);
+            let start = -Ts::from(late_timings);
+            for hc in start..start + 32 {
+                assert!(ula.is_irq(VideoTs::new(0, hc)));
+            }
+            assert!(!ula.is_irq(VideoTs::new(0, start - 1)));
+            assert!(!ula.is_irq(VideoTs::new(0, start + 32)));
+            assert!(!ula.is_irq(VideoTs::new(1, start)));
+        }
+        ula.set_late_timings(false);
+        let rom = ula.memory_mut().page_mut(0).unwrap();
+        // EI; HALT; JR -3
+        rom[0..4].copy_from_slice(&[0xFB, 0x76, 0x18, 0xFD]);
+        // INC (HL); EI; RET
+        rom[0x38..0x3B].copy_from_slice(&[0x34, 0xFB, 0xC9]);
+        ula.memory_mut().write(0x8000, 0);
+        let mut cpu = Z80NMOS::default();
+        cpu.reset();
+        cpu.set_im(InterruptMode::Mode1);
+        cpu.set_sp(0xFFFE);
+        cpu.set_reg16(StkReg16::HL, 0x8000);
+        for frame in 1..=3 {
+            ula.execute_next_frame(&mut cpu);
+            assert_eq!(ula.memory_ref().read(0x8000), frame);
+            assert!(cpu.is_halt());
+            let VideoTs { vc, hc } = ula.current_video_ts();
+            assert_eq!(vc, Ula128VidFrame::VSL_COUNT);
+            assert!((Ula128VidFrame::HTS_IRQ - 4..Ula128VidFrame::HTS_IRQ).contains(&hc));
+        }
+        assert_eq!(ula.current_frame(), 2);
+    }
 }
@@ -121,7 +121,7 @@ impl<B, X> Memory for Ula3<B, X>
     #[inline]
     fn write_mem(&mut self, addr: u16, val: u8, ts: VideoTs) {
         self.update_frame_cache(addr, ts);
-        self.ula.memory.write(addr, val);
+        self.ula.memext.write_mem(addr, val, &mut self.ula.memory);
     }
 }
 
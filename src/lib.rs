@@ -131,6 +131,10 @@ These are the most commonly used:
 | [Ula128][chip::ula128::Ula128]`<B, X>` | A chipset for emulating ZX Spectrum 128k/+2 |
 | [Ula3][chip::ula3::Ula3]`<B, X>` | A chipset for emulating ZX Spectrum +2A/+3 |
 | [Scld][chip::scld::Scld]`<M, B, X, V>` | A chipset for emulating TC2048 / TC2068 / TS2068 |
+| [Pentagon][chip::pentagon::Pentagon]`<M, B, X>` | A chipset for emulating Pentagon 128k/512k/1024k |
+| [Scorpion][chip::scorpion::Scorpion]`<B, X>` | A chipset for emulating Scorpion ZS-256 |
+| [Chloe][chip::chloe::Chloe]`<B, X>` | A chipset for emulating ZX Spectrum SE |
+| [DidaktikGama][chip::didaktik::DidaktikGama]`<B, X>` | A chipset for emulating Didaktik Gama |
 | [UlaPlus][chip::plus::UlaPlus]`<U>` | A wrapper chipset enhancer for emulating ULAplus graphic modes |
 
 ### Generic parameters
@@ -31,10 +31,18 @@ use super::{
 pub type Memory16kEx = MemPageableRomRamExRom<[u8; MEM48K_SIZE]>;
 /// An EX-ROM attachable, paged (16k) memory type with 48kb RAM and 16kb ROM.
 pub type Memory48kEx = MemPageableRomRamExRom<[u8; MEM64K_SIZE]>;
+/// An EX-ROM attachable, paged (16k) memory type with 80kb RAM and 16kb ROM.
+pub type Memory80k = MemPageableRomRamExRom<[u8; MEM16K_SIZE + MEM64K_SIZE + MEM16K_SIZE]>;
 /// An EX-ROM attachable, paged (16k) memory type with 128kb RAM and 32kb ROM.
 pub type Memory128k = MemPageableRomRamExRom<[u8; MEM32K_SIZE + MEM128K_SIZE]>;
 /// An EX-ROM attachable, paged (16k) memory type with 128kb RAM and 64kb ROM.
 pub type Memory128kPlus = MemPageableRomRamExRom<[u8; MEM64K_SIZE + MEM128K_SIZE]>;
+/// An EX-ROM attachable, paged (16k) memory type with 256kb RAM and 64kb ROM.
+pub type Memory256kPlus = MemPageableRomRamExRom<[u8; MEM64K_SIZE + 2 * MEM128K_SIZE]>;
+/// An EX-ROM attachable, paged (16k) memory type with 512kb RAM and 32kb ROM.
+pub type Memory512k = MemPageableRomRamExRom<[u8; MEM32K_SIZE + 4 * MEM128K_SIZE]>;
+/// An EX-ROM attachable, paged (16k) memory type with 1024kb RAM and 32kb ROM.
+pub type Memory1024k = MemPageableRomRamExRom<[u8; MEM32K_SIZE + 8 * MEM128K_SIZE]>;
 /// An EX-ROM attachable, paged (8k) memory type with 48kb RAM and 96kb ROM (64kb DOCK, 8kB EX-ROM, 16kB ROM).
 pub type Memory48kDock64kEx = MemPageableRomRamExRom<[u8; MEM8K_SIZE + MEM128K_SIZE]>;
 /// An EX-ROM attachable, paged (8k) memory type with 272kb RAM and 32kb ROM.
@@ -45,8 +53,12 @@ pub trait PagedMemory8k: ZxMemory {}
 
 impl PagedMemory16k for Memory16kEx {}
 impl PagedMemory16k for Memory48kEx {}
+impl PagedMemory16k for Memory80k {}
 impl PagedMemory16k for Memory128k {}
 impl PagedMemory16k for Memory128kPlus {}
+impl PagedMemory16k for Memory256kPlus {}
+impl PagedMemory16k for Memory512k {}
+impl PagedMemory16k for Memory1024k {}
 impl PagedMemory8k for Memory48kDock64kEx {}
 impl PagedMemory8k for Memory272k {}
 
@@ -154,8 +166,12 @@ macro_rules! impl_memory_block {
 
 impl_memory_block!(MEM16K_SIZE, MEM48K_SIZE, 2, 1, [ROM 0, RAM 0, ROM 1, ROM 1], [0]);
 impl_memory_block!(MEM16K_SIZE, MEM64K_SIZE, 1, 3, [ROM 0, RAM 0, RAM 1, RAM 2], [0]);
+impl_memory_block!(MEM16K_SIZE, MEM16K_SIZE + MEM64K_SIZE + MEM16K_SIZE, 1, 5, [ROM 0, RAM 0, RAM 1, RAM 2], [0]);
 impl_memory_block!(MEM16K_SIZE, MEM32K_SIZE + MEM128K_SIZE, 2, 8, [ROM 0, RAM 5, RAM 2, RAM 0], [5, 7]);
 impl_memory_block!(MEM16K_SIZE, MEM64K_SIZE + MEM128K_SIZE, 4, 8, [ROM 0, RAM 5, RAM 2, RAM 0], [5, 7]);
+impl_memory_block!(MEM16K_SIZE, MEM64K_SIZE + 2 * MEM128K_SIZE, 4, 16, [ROM 0, RAM 5, RAM 2, RAM 0], [5, 7]);
+impl_memory_block!(MEM16K_SIZE, MEM32K_SIZE + 4 * MEM128K_SIZE, 2, 32, [ROM 0, RAM 5, RAM 2, RAM 0], [5, 7]);
+impl_memory_block!(MEM16K_SIZE, MEM32K_SIZE + 8 * MEM128K_SIZE, 2, 64, [ROM 0, RAM 5, RAM 2, RAM 0], [5, 7]);
 impl_memory_block!(MEM8K_SIZE, MEM8K_SIZE + MEM128K_SIZE, 11, 6,
                                         [ROM 9, ROM 10, RAM 0, RAM 1, RAM 2, RAM 3, RAM 4, RAM 5], [0]);
 impl_memory_block!(MEM8K_SIZE, MEM48K_SIZE + MEM128K_SIZE + MEM128K_SIZE, 4, 34,
@@ -671,6 +687,12 @@ mod tests {
 
     #[test]
     fn memory_banks_work() {
+        assert_eq!(Memory80k::ROM_SIZE, 0x4000);
+        assert_eq!(Memory80k::PAGE_SIZE, 0x4000);
+        assert_eq!(Memory80k::PAGES_MAX, 3);
+        assert_eq!(Memory80k::SCR_BANKS_MAX, 1);
+        assert_eq!(Memory80k::RAM_BANKS_MAX, 4);
+        assert_eq!(Memory80k::ROM_BANKS_MAX, 0);
         assert_eq!(Memory128k::ROM_SIZE, 0x8000);
         assert_eq!(Memory128k::PAGE_SIZE, 0x4000);
         assert_eq!(Memory128k::PAGES_MAX, 3);
@@ -683,6 +705,12 @@ mod tests {
         assert_eq!(Memory128kPlus::SCR_BANKS_MAX, 3);
         assert_eq!(Memory128kPlus::RAM_BANKS_MAX, 7);
         assert_eq!(Memory128kPlus::ROM_BANKS_MAX, 3);
+        assert_eq!(Memory256kPlus::ROM_SIZE, 0x10000);
+        assert_eq!(Memory256kPlus::PAGE_SIZE, 0x4000);
+        assert_eq!(Memory256kPlus::PAGES_MAX, 3);
+        assert_eq!(Memory256kPlus::SCR_BANKS_MAX, 3);
+        assert_eq!(Memory256kPlus::RAM_BANKS_MAX, 15);
+        assert_eq!(Memory256kPlus::ROM_BANKS_MAX, 3);
         assert_eq!(Memory48kDock64kEx::ROM_SIZE, 0x16000);
         assert_eq!(Memory48kDock64kEx::PAGE_SIZE, 0x2000);
         assert_eq!(Memory48kDock64kEx::PAGES_MAX, 7);
@@ -293,6 +293,18 @@ mod tests {
        memory_single_page_rom::<Memory64k>(false);
     }
 
+    #[test]
+    fn test_memory_extension_write_through() {
+        let mut mem = Memory48k::default();
+        let mut ext = NoMemoryExtension;
+        ext.write_mem(0x0000, 201, &mut mem);
+        ext.write_mem(0x4000, 202, &mut mem);
+        ext.write_mem(0xFFFF, 203, &mut mem);
+        assert_eq!(mem.read(0x0000), 0);
+        assert_eq!(mem.read(0x4000), 202);
+        assert_eq!(mem.read(0xFFFF), 203);
+    }
+
     fn memory_single_page_rom<M: ZxMemory + Default>(is_ro: bool) {
         let mut mem = M::default();
         for addr in 0..=ROM_TOP {
@@ -0,0 +1,118 @@
+/*
+    common: shared helpers for the SPECTRUSTY library tests.
+    Copyright (C) 2020  Rafal Michalski
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+    Author contact information: see Cargo.toml file, section [package.authors].
+*/
+//! Helpers for testing the emulation of the Z80 halted state and the maskable interrupt timings.
+use spectrusty::z80emu::{*, opconsts::HALT_OPCODE, host::cycles::M1_CYCLE_TS};
+use spectrusty::memory::ZxMemory;
+use spectrusty::chip::*;
+use spectrusty::clock::*;
+use spectrusty::video::*;
+
+/// Executes the next frame without the custom halted state emulation, stopping just before
+/// the frame interrupt is being requested.
+pub fn execute_next_frame_no_halt_emu<U, C>(ula: &mut U, cpu: &mut C, mut halt_limit: u32) -> VideoTs
+    where C: Cpu,
+          U: UlaCommon +
+             Memory<Timestamp=VideoTs> +
+             Io<Timestamp=VideoTs>
+{
+    assert!(!cpu.is_halt());
+    ula.ensure_next_frame();
+    let mut tsc = ula.current_video_clock();
+    loop {
+        match cpu.execute_with_limit(ula, &mut tsc, U::VideoFrame::VSL_COUNT) {
+            Ok(()) => break,
+            Err(BreakCause::Halt) => {
+                // println!("HALT {:04x} {:?}", cpu.get_pc(), tsc.tsc);
+                assert_ne!(halt_limit, 0);
+                halt_limit -= 1;
+                continue
+            },
+            Err(_) => unreachable!()
+        }
+    }
+    assert!(cpu.is_halt());
+    assert_eq!(halt_limit, 0);
+    while tsc.hc < U::VideoFrame::HTS_IRQ - M1_CYCLE_TS as Ts {
+        match cpu.execute_next::<_,_,CpuDebugFn>(ula, &mut tsc, None) {
+            Ok(()) => (),
+            Err(_) => unreachable!()
+        }
+    }
+
+    tsc.into()
+}
+
+/// Returns the number of interrupts accepted by a halted CPU while the frame begins at the given
+/// video timestamp.
+pub fn halt_irq<U>(vc: Ts, hc: Ts, late_timings: bool, halt_limit: u32) -> u16
+    where U: UlaCommon + Default + Clone +
+             Memory<Timestamp=VideoTs> +
+             Io<Timestamp=VideoTs>
+{
+    let mut ula = U::default();
+    ula.set_late_timings(late_timings);
+    ula.set_video_ts(VideoTs::new(vc, hc));
+    ula.memory_mut().fill_mem(.., || HALT_OPCODE).unwrap();
+    ula.memory_mut().page_mut(0).unwrap()[0x38..0x3B].copy_from_slice(&[
+              // +13       IRQ
+        0x03, // + 6 0038  INC  BC
+        0xFB, // + 4 0039  EI
+        0xC9, // +10 003A  RET
+    ]);
+    let mut cpu = Z80NMOS::default();
+    cpu.reset();
+    cpu.set_sp(0x0000);
+    cpu.set_pc(0x8000);
+    cpu.set_reg16(StkReg16::BC, 0);
+    cpu.enable_interrupts();
+    let mut cpu1 = cpu.clone();
+    let mut ula1 = ula.clone();
+
+    assert!(!cpu.is_halt());
+    ula.execute_next_frame(&mut cpu);
+    assert!(cpu.is_halt());
+
+    let tsc1 = execute_next_frame_no_halt_emu(&mut ula1, &mut cpu1, halt_limit);
+
+    assert_eq!(tsc1, ula.current_video_ts());
+    assert_eq!(cpu1, cpu);
+
+    cpu.get_reg16(StkReg16::BC)
+}
+
+/// Checks that the interrupt is being accepted only if the frame begins before `hc_stop`.
+pub fn halt_irq_tstates<U>(late_timings: bool, hc_stop: Ts)
+    where U: UlaCommon + Default + Clone +
+             Memory<Timestamp=VideoTs> +
+             Io<Timestamp=VideoTs>
+{
+    for hc in U::VideoFrame::HTS_RANGE.start..hc_stop {
+        // println!("  hc: {}", hc);
+        assert_eq!(1, halt_irq::<U>(0, hc, late_timings, 2));
+    }
+    for hc in hc_stop..U::VideoFrame::HTS_RANGE.end {
+        // println!("  hc: {}", hc);
+        assert_eq!(0, halt_irq::<U>(0, hc, late_timings, 1));
+    }
+    for hc in U::VideoFrame::HTS_RANGE {
+        // println!("  hc: {}", hc);
+        assert_eq!(0, halt_irq::<U>(1, hc, late_timings, 1));
+    }
+}
@@ -0,0 +1,102 @@
+/*
+    test_interlaced: tests for the SPECTRUSTY library.
+    Copyright (C) 2020  Rafal Michalski
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+    Author contact information: see Cargo.toml file, section [package.authors].
+*/
+//! Tests the interlaced video rendering of the chipsets with the double horizontal pixel density.
+use spectrusty::memory::{Memory64k, Memory48kDock64kEx, NoMemoryExtension, ZxMemory};
+use spectrusty::chip::{*, ula::*, scld::*, plus::*};
+use spectrusty::bus::VFNullDevice;
+use spectrusty::video::{*, pixel::{PixelBufA24, SpectrumPalRGB24}};
+
+type TestUlaPlus = UlaPlus<UlaPAL<Memory64k>>;
+type TestScld = Scld<Memory48kDock64kEx, VFNullDevice<ScldVidFrame>, NoMemoryExtension, ScldVidFrame>;
+
+const BORDER_SIZE: BorderSize = BorderSize::Minimal;
+
+fn render_progressive<U>(setup: &dyn Fn(&mut U, VideoField)) -> [Vec<u8>; 2]
+    where U: Video + Default
+{
+    let (width, height) = U::render_size_pixels(BORDER_SIZE);
+    let pitch = width as usize * 3;
+    let mut frames = [vec![0u8; pitch * height as usize], vec![0u8; pitch * height as usize]];
+    for (field, buffer) in [VideoField::Even, VideoField::Odd].iter().zip(frames.iter_mut()) {
+        let mut ula = U::default();
+        setup(&mut ula, *field);
+        ula.render_video_frame::<PixelBufA24, SpectrumPalRGB24>(buffer, pitch, BORDER_SIZE);
+    }
+    assert_ne!(frames[0], frames[1]);
+    frames
+}
+
+// Renders both fields interlaced and compares them with the progressive frames set up the same way.
+fn interlaced_fields<U>(setup: &dyn Fn(&mut U, VideoField)) -> [Vec<u8>; 2]
+    where U: Video + Default
+{
+    let (width, height) = U::render_size_pixels(BORDER_SIZE);
+    assert_eq!(U::render_size_pixels_interlaced(BORDER_SIZE), (width, 2 * height));
+    let pitch = width as usize * 3;
+    let frames = render_progressive(setup);
+    let mut buffer = vec![0x55u8; pitch * 2 * height as usize];
+    for &field in [VideoField::Even, VideoField::Odd].iter() {
+        let mut ula = U::default();
+        setup(&mut ula, field);
+        ula.render_video_frame_interlaced::<PixelBufA24, SpectrumPalRGB24>(
+            &mut buffer, pitch, BORDER_SIZE, field);
+    }
+    for (index, line) in buffer.chunks(pitch).enumerate() {
+        let frame_line = &frames[index & 1][(index >> 1) * pitch..][..pitch];
+        assert_eq!(line, frame_line);
+    }
+    frames
+}
+
+#[test]
+fn test_ulaplus_hires_interlaced() {
+    assert_eq!(TestUlaPlus::pixel_density(), 2);
+    let frames = interlaced_fields::<TestUlaPlus>(&|ula, field| {
+        ula.memory_mut().fill_mem(0x4000..0x8000, || 0xF0).unwrap();
+        let color = match field {
+            VideoField::Even => 0b0000_0000,
+            VideoField::Odd => 0b0000_1000
+        };
+        let flags = ScldCtrlFlags::SCREEN_HI_RES|ScldCtrlFlags::from_bits_truncate(color);
+        assert!(ula.set_scld_ctrl_port_value(flags));
+    });
+    // the 512 pixels wide hi-res line of 0xF0 bytes: 4 INK pixels followed by 4 PAPER pixels
+    let (width, height) = TestUlaPlus::render_size_pixels(BORDER_SIZE);
+    let left = (width as usize - 512) / 2;
+    let top = (height as usize - 192) / 2;
+    for frame in frames.iter() {
+        let line = &frame[top * width as usize * 3..][..width as usize * 3];
+        let pixels: Vec<&[u8]> = line.chunks(3).skip(left).take(16).collect();
+        for (x, pixel) in pixels.iter().enumerate() {
+            assert_eq!(pixel == &pixels[0], x & 7 < 4);
+        }
+    }
+}
+
+#[test]
+fn test_scld_interlaced() {
+    assert_eq!(TestScld::pixel_density(), 2);
+    interlaced_fields::<TestScld>(&|ula, field| {
+        ula.set_border_color(match field {
+            VideoField::Even => BorderColor::RED,
+            VideoField::Odd => BorderColor::BLUE
+        });
+    });
+}
@@ -0,0 +1,124 @@
+/*
+    test_scld_timings: tests for the SPECTRUSTY library.
+    Copyright (C) 2020  Rafal Michalski
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+    Author contact information: see Cargo.toml file, section [package.authors].
+*/
+//! Tests the Timex SCLD video frame timings, the memory contention and the floating bus.
+mod common;
+
+use spectrusty::z80emu::*;
+use spectrusty::memory::{Memory48kDock64kEx, NoMemoryExtension, ZxMemory};
+use spectrusty::chip::{*, scld::*};
+use spectrusty::bus::VFNullDevice;
+use spectrusty::clock::*;
+use spectrusty::video::*;
+use common::*;
+
+type TC2048 = Scld<Memory48kDock64kEx, VFNullDevice<ScldVidFrame>, NoMemoryExtension, ScldVidFrame>;
+type TS2068 = Scld<Memory48kDock64kEx, VFNullDevice<ScldNTSCVidFrame>, NoMemoryExtension, ScldNTSCVidFrame>;
+
+#[test]
+fn test_scld_halt_irq() {
+    halt_irq_tstates::<TC2048>(false, 43);
+    halt_irq_tstates::<TC2048>(true, 42);
+    halt_irq_tstates::<TS2068>(false, 43);
+    halt_irq_tstates::<TS2068>(true, 42);
+}
+
+// Executes LD A,(0x4000) at 0x8000 so the memory read occurs at the given video timestamp.
+fn scld_mem_read_tstates<U>(ula: &mut U, vts: VideoTs) -> i32
+    where U: UlaCommon + Memory<Timestamp=VideoTs> + Io<Timestamp=VideoTs>
+{
+    let mut cpu = Z80NMOS::default();
+    cpu.reset();
+    cpu.set_pc(0x8000);
+    for (addr, code) in (0x8000..).zip([0x3A, 0x00, 0x40].iter().copied()) {
+        ula.memory_mut().write(addr, code);
+    }
+    let start = VideoTs::new(vts.vc, vts.hc - 10);
+    ula.set_video_ts(start);
+    ula.execute_single_step::<_,CpuDebugFn>(&mut cpu, None).unwrap();
+    assert_eq!(cpu.get_pc(), 0x8003);
+    VFrameTs::<U::VideoFrame>::from(ula.current_video_ts())
+        .diff_from(VFrameTs::from(start))
+}
+
+fn scld_contention<U>()
+    where U: UlaCommon + Default +
+             Memory<Timestamp=VideoTs> +
+             Io<Timestamp=VideoTs, WrIoBreak=()>
+{
+    let first_pixel = VideoTs::new(U::VideoFrame::VSL_PIXELS.start, 0);
+    let border = VideoTs::new(U::VideoFrame::VSL_PIXELS.start - 1, 0);
+    let mut ula = U::default();
+    assert!(ula.current_video_clock().is_contended_address(0x4000));
+    assert!(ula.current_video_clock().is_contended_address(0x7FFF));
+    assert!(!ula.current_video_clock().is_contended_address(0x3FFF));
+    assert!(!ula.current_video_clock().is_contended_address(0x8000));
+    assert_eq!(scld_mem_read_tstates(&mut ula, first_pixel), 13 + 5);
+    assert_eq!(scld_mem_read_tstates(&mut ula, border), 13);
+    // DOCK paged in at 0x4000
+    assert_eq!(ula.write_io(0x00F4, 0b0000_0100, VideoTs::default()), (Some(()), None));
+    assert!(!ula.current_video_clock().is_contended_address(0x4000));
+    assert!(ula.current_video_clock().is_contended_address(0x6000));
+    assert_eq!(scld_mem_read_tstates(&mut ula, first_pixel), 13);
+    // DOCK paged in at 0x0000, no contention change
+    assert_eq!(ula.write_io(0x00F4, 0b0000_0101, VideoTs::default()), (None, None));
+    // DOCK paged in at 0x6000
+    assert_eq!(ula.write_io(0x00F4, 0b0000_1000, VideoTs::default()), (Some(()), None));
+    assert!(ula.current_video_clock().is_contended_address(0x5FFF));
+    assert!(!ula.current_video_clock().is_contended_address(0x6000));
+    assert_eq!(scld_mem_read_tstates(&mut ula, first_pixel), 13 + 5);
+    // EX-ROM paged in at 0x4000 and 0x6000
+    ula.set_scld_ctrl_port_value(ScldCtrlFlags::MAP_EX_ROM);
+    ula.set_scld_mmu_port_value(0b0000_1100);
+    assert!(!ula.current_video_clock().is_contended_address(0x4000));
+    assert!(!ula.current_video_clock().is_contended_address(0x6000));
+    assert_eq!(scld_mem_read_tstates(&mut ula, first_pixel), 13);
+}
+
+#[test]
+fn test_scld_contention() {
+    scld_contention::<TC2048>();
+    scld_contention::<TS2068>();
+}
+
+fn scld_no_floating_bus<U>()
+    where U: UlaCommon + Default + Io<Timestamp=VideoTs>
+{
+    let mut ula = U::default();
+    ula.memory_mut().fill_mem(0x4000..0x5B00, || 0x55).unwrap();
+    for vc in U::VideoFrame::VSL_PIXELS {
+        for hc in U::VideoFrame::HTS_RANGE {
+            assert_eq!(ula.read_io(0x0001, VideoTs::new(vc, hc)), (u8::MAX, None));
+        }
+    }
+}
+
+#[test]
+fn test_scld_no_floating_bus() {
+    scld_no_floating_bus::<TC2048>();
+    scld_no_floating_bus::<TS2068>();
+}
+
+#[test]
+fn test_scld_host_config() {
+    assert_eq!(<TC2048 as HostConfig>::CPU_HZ, 3_500_000);
+    assert_eq!(<TC2048 as HostConfig>::FRAME_TSTATES, 69888);
+    assert_eq!(<TS2068 as HostConfig>::CPU_HZ, 3_528_000);
+    assert_eq!(<TS2068 as HostConfig>::FRAME_TSTATES, 59736);
+}